@@ -0,0 +1,883 @@
+//! Procedural macros of [shades](https://crates.io/crates/shades).
+//!
+//! This crate is re-exported by shades behind the `edsl` feature gate: use [`shades!`] from there.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, ToTokens as _};
+use std::collections::HashSet;
+use syn::{
+  parse_macro_input,
+  visit_mut::{self, VisitMut},
+  BinOp, Block, Error, Expr, ExprClosure, ExprForLoop, ExprIf, ExprMatch, Ident, Local, LocalInit,
+  Pat, RangeLimits, Stmt,
+};
+
+/// Write the body of a shader function with ordinary Rust statements.
+///
+/// `shades!(|s: &mut Scope<R>, args…| { … })` takes the closure you would pass to
+/// [`ShaderBuilder::fun`](https://docs.rs/shades/latest/shades/struct.ShaderBuilder.html#method.fun) or
+/// [`ShaderBuilder::main_fun`](https://docs.rs/shades/latest/shades/struct.ShaderBuilder.html#method.main_fun) and
+/// lowers the statements of its body to calls on the scope — its first argument:
+///
+/// | Statement                       | Lowered to                                       |
+/// |---------------------------------|--------------------------------------------------|
+/// | `let x = e;`, `let x: T = e;`   | `let x = s.var(e);`, `let x = s.var::<T>(e);`    |
+/// | `x = e;`                        | `s.set(&x, e);`                                  |
+/// | `x += e;` and other compounds   | `s.set(&x, &x + e);`                             |
+/// | `x[i] = e;`                     | `s.set(&x.at(i), e);`                            |
+/// | `if c { … } else if … else { … }` | `s.when(c, …).or_else(…).or(…);`               |
+/// | `while c { … }`                 | `s.loop_while(c, …);`                            |
+/// | `loop { … }`                    | `s.loop_forever(…);`                             |
+/// | `for i in a..b { … }`           | `s.loop_range(a, b, …);`                         |
+/// | `for i in a..=b { … }`          | `s.loop_for(a, \|i\| i.lte(b), \|i\| i + 1, …);` |
+/// | `for x in array { … }`          | `s.for_each(array, …);`                          |
+/// | `match e { 1 => …, _ => … }`    | `s.switch_on(e).case(1, …).default(…);`          |
+/// | `break;`, `continue;`           | `s.loop_break();`, `s.loop_continue();`          |
+/// | `return e;`, `return;`          | `s.leave(e);`, `s.abort();`                      |
+///
+/// In expressions:
+///
+/// - Comparisons and boolean operators are lowered to their methods: `a < b` is `a.lt(b)`, `a == b` is `a.eq(b)`,
+///   `a && b` is `a.and(b)`, etc.
+/// - `a[i]` is `a.at(i)`.
+/// - `if c { a } else { b }` is `s.if_else(c, |s| a, |s| b)`, and blocks are lowered as bodies of their own.
+/// - The variables declared in the macro and the arguments of the closure can be used as many times as needed: they
+///   are turned into expressions, so there’s no need to borrow them. Variables declared out of the macro — inputs,
+///   outputs, uniforms, etc. — are used as they are, so they have to be borrowed as with the builder API.
+///
+/// The value of the function is the trailing expression of the body, if any. Anything else — items, macros, method
+/// calls, etc. — is left untouched, so all of the builder API remains available.
+///
+/// Because the lowering is syntactic, it has the restrictions of the builder API: loop bodies can only `break` and
+/// `continue` from their `if` branches, not from their `else` branches, nor from `match` arms, and `return` is only
+/// available in nested bodies, the top-level body returning its trailing expression. `match` arms are only matched
+/// against literals and paths, and `_` — the default arm — must come last. Closures are left untouched as well, so
+/// statements in closures are not lowered.
+///
+/// # Examples
+///
+/// ```
+/// use shades::{shades, EscapeScope, Expr, Scope, ShaderBuilder, V4, outputs, uniforms};
+///
+/// let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+///   uniforms!(s, count: i32);
+///   outputs!(s, color: V4<f32>);
+///
+///   let brightness = s.fun(shades!(|s: &mut Scope<Expr<f32>>, n: Expr<i32>| {
+///     let sum = 0.;
+///
+///     for i in 0..n {
+///       if i < 3 {
+///         sum += 1.;
+///       } else if i > 10 {
+///         return sum;
+///       }
+///     }
+///
+///     sum / 10.
+///   }));
+///
+///   s.main_fun(shades!(|s: &mut Scope<()>| {
+///     let b = brightness.call(count.to_expr());
+///     let c = if b < 0.5 { 0. } else { b };
+///     color = shades::vec4!(c, c, c, 1.);
+///   }))
+/// });
+///
+/// let code = shades::writer::glsl::write_shader_to_str(&shader).unwrap();
+/// assert!(code.contains("for (int var_1_0 = 0; (var_1_0 < arg_0); var_1_0 = (var_1_0 + 1)) {"));
+/// ```
+///
+/// Other loops, switches and array updates:
+///
+/// ```
+/// use shades::{shades, Scope, ShaderBuilder, inputs, lit, outputs};
+/// use shades::eval::{Eval, Value};
+///
+/// let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+///   inputs!(s, n: i32);
+///   outputs!(s, x: i32, y: f32);
+///
+///   s.main_fun(shades!(|s: &mut Scope<()>| {
+///     let a = lit!([1., 2., 3.]);
+///     let i = 0;
+///
+///     while i < n {
+///       match i {
+///         0 | 1 => a[i] *= 10.,
+///         2 => {
+///           a[i] = -a[i];
+///         }
+///         _ => {}
+///       }
+///
+///       i += 1;
+///     }
+///
+///     for v in a {
+///       y += v;
+///     }
+///
+///     loop {
+///       if i >= 10 {
+///         break;
+///       }
+///
+///       i += 3;
+///     }
+///
+///     for j in 1..=2 {
+///       i *= j;
+///     }
+///
+///     x = i;
+///   }))
+/// });
+///
+/// let outputs = Eval::new(&shader).input("n", 2).run().unwrap();
+/// assert_eq!(outputs.get("x"), Some(&Value::Int(vec![22])));
+/// assert_eq!(outputs.get("y"), Some(&Value::Float(vec![33.])));
+/// ```
+#[proc_macro]
+pub fn shades(input: TokenStream) -> TokenStream {
+  let closure = parse_macro_input!(input as ExprClosure);
+
+  match lower_closure(closure) {
+    Ok(tokens) => tokens.into(),
+    Err(error) => error.to_compile_error().into(),
+  }
+}
+
+fn lower_closure(mut closure: ExprClosure) -> syn::Result<TokenStream2> {
+  let mut inputs = closure.inputs.iter();
+  let scope = match inputs.next().and_then(pat_ident) {
+    Some(scope) => scope.clone(),
+    None => {
+      return Err(Error::new_spanned(
+        &closure,
+        "the first argument of the closure must be the scope",
+      ))
+    }
+  };
+  let args = inputs
+    .filter_map(pat_ident)
+    .map(|ident| ident.to_string())
+    .collect();
+
+  let mut lowerer = Lowerer {
+    scope,
+    bindings: vec![args],
+    hoist: true,
+    error: None,
+  };
+  let body = match &*closure.body {
+    Expr::Block(body) if body.label.is_none() => {
+      let stmts = lowerer.block(&body.block, true)?;
+      quote!({ #stmts })
+    }
+    body => lowerer.value(body)?,
+  };
+
+  closure.body = Box::new(Expr::Verbatim(body));
+  Ok(closure.into_token_stream())
+}
+
+/// Identifier bound by a pattern, if it binds a single one.
+fn pat_ident(pat: &Pat) -> Option<&Ident> {
+  match pat {
+    Pat::Ident(pat) if pat.by_ref.is_none() && pat.subpat.is_none() => Some(&pat.ident),
+    Pat::Type(pat) => pat_ident(&pat.pat),
+    _ => None,
+  }
+}
+
+/// Identifier of a value generated by the macro, which cannot clash with the identifiers of the user.
+fn hygienic(name: &str) -> Ident {
+  Ident::new(name, Span::mixed_site())
+}
+
+/// Whether an expression is a statement of the EDSL, which has no value.
+fn is_stmt(expr: &Expr) -> bool {
+  match expr {
+    Expr::If(expr) => !has_value(expr),
+    Expr::Binary(expr) => compound_op(&expr.op).is_some(),
+    Expr::Assign(_)
+    | Expr::While(_)
+    | Expr::Loop(_)
+    | Expr::ForLoop(_)
+    | Expr::Match(_)
+    | Expr::Break(_)
+    | Expr::Continue(_)
+    | Expr::Return(_) => true,
+    _ => false,
+  }
+}
+
+/// Whether an `if` expression has a value, i.e. an `else` branch and values in all of its branches.
+fn has_value(expr: &ExprIf) -> bool {
+  let has_tail = |block: &Block| match block.stmts.last() {
+    Some(Stmt::Expr(expr, None)) => !is_stmt(expr),
+    _ => false,
+  };
+
+  match &expr.else_branch {
+    Some((_, else_branch)) if has_tail(&expr.then_branch) => match &**else_branch {
+      Expr::If(else_branch) => has_value(else_branch),
+      Expr::Block(else_branch) => has_tail(&else_branch.block),
+      _ => false,
+    },
+    _ => false,
+  }
+}
+
+/// Binary operator of a compound assignment.
+fn compound_op(op: &BinOp) -> Option<TokenStream2> {
+  let op = match op {
+    BinOp::AddAssign(_) => quote!(+),
+    BinOp::SubAssign(_) => quote!(-),
+    BinOp::MulAssign(_) => quote!(*),
+    BinOp::DivAssign(_) => quote!(/),
+    BinOp::RemAssign(_) => quote!(%),
+    BinOp::BitXorAssign(_) => quote!(^),
+    BinOp::BitAndAssign(_) => quote!(&),
+    BinOp::BitOrAssign(_) => quote!(|),
+    BinOp::ShlAssign(_) => quote!(<<),
+    BinOp::ShrAssign(_) => quote!(>>),
+    _ => return None,
+  };
+
+  Some(op)
+}
+
+/// Method of a comparison or boolean operator.
+fn op_method(op: &BinOp) -> Option<&'static str> {
+  let method = match op {
+    BinOp::Lt(_) => "lt",
+    BinOp::Le(_) => "lte",
+    BinOp::Gt(_) => "gt",
+    BinOp::Ge(_) => "gte",
+    BinOp::Eq(_) => "eq",
+    BinOp::Ne(_) => "neq",
+    BinOp::And(_) => "and",
+    BinOp::Or(_) => "or",
+    _ => return None,
+  };
+
+  Some(method)
+}
+
+/// Lowering of statements and expressions to calls on the scope.
+struct Lowerer {
+  // identifier of the current scope, which all the nested scopes are named after
+  scope: Ident,
+  // names of the variables declared in the macro, by block
+  bindings: Vec<HashSet<String>>,
+  // whether statements can be added to the current scope while lowering an expression
+  hoist: bool,
+  // first error met while visiting an expression
+  error: Option<Error>,
+}
+
+impl Lowerer {
+  fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+    self.bindings.push(HashSet::new());
+    let result = f(self);
+    self.bindings.pop();
+    result
+  }
+
+  fn bind(&mut self, ident: &Ident) {
+    if let Some(bindings) = self.bindings.last_mut() {
+      bindings.insert(ident.to_string());
+    }
+  }
+
+  fn is_bound(&self, ident: &Ident) -> bool {
+    let name = ident.to_string();
+    self
+      .bindings
+      .iter()
+      .any(|bindings| bindings.contains(&name))
+  }
+
+  /// Lower the statements of a block, without its braces, in a nested block of bindings.
+  ///
+  /// If `value` is `true`, the trailing expression of the block, if any, is kept as the value of the block.
+  fn block(&mut self, block: &Block, value: bool) -> syn::Result<TokenStream2> {
+    self.nested(|lowerer| {
+      let mut tokens = TokenStream2::new();
+
+      for (i, stmt) in block.stmts.iter().enumerate() {
+        match stmt {
+          Stmt::Expr(expr, None) if value && i + 1 == block.stmts.len() && !is_stmt(expr) => {
+            tokens.extend(lowerer.value(expr)?)
+          }
+          _ => tokens.extend(lowerer.stmt(stmt)?),
+        }
+      }
+
+      Ok(tokens)
+    })
+  }
+
+  fn stmt(&mut self, stmt: &Stmt) -> syn::Result<TokenStream2> {
+    match stmt {
+      Stmt::Local(local) => self.local(local),
+      Stmt::Expr(expr, _) => self.expr_stmt(expr),
+      Stmt::Item(_) | Stmt::Macro(_) => Ok(stmt.to_token_stream()),
+    }
+  }
+
+  fn local(&mut self, local: &Local) -> syn::Result<TokenStream2> {
+    let ident = match pat_ident(&local.pat) {
+      Some(ident) => ident,
+      None => {
+        return Err(Error::new_spanned(
+          &local.pat,
+          "variables must be bound to identifiers",
+        ))
+      }
+    };
+
+    let init = match &local.init {
+      Some(LocalInit {
+        expr,
+        diverge: None,
+        ..
+      }) => self.value(expr)?,
+      Some(LocalInit {
+        diverge: Some(_), ..
+      }) => return Err(Error::new_spanned(local, "let-else is not supported")),
+      None => return Err(Error::new_spanned(local, "variables must be initialized")),
+    };
+
+    self.bind(ident);
+
+    let s = &self.scope;
+    let value = hygienic("value");
+    let var = match &local.pat {
+      Pat::Type(pat) => {
+        let ty = &pat.ty;
+        quote!(#s.var::<#ty>(#value))
+      }
+      _ => quote!(#s.var(#value)),
+    };
+
+    Ok(quote!(let #value = #init; let #ident = #var;))
+  }
+
+  fn expr_stmt(&mut self, expr: &Expr) -> syn::Result<TokenStream2> {
+    let s = self.scope.clone();
+    let value = hygienic("value");
+
+    let tokens = match expr {
+      Expr::If(expr) => self.when(expr)?,
+
+      Expr::While(expr) => {
+        no_label(expr.label.is_some(), expr)?;
+
+        // the condition is evaluated at each iteration, so nothing can be hoisted before the loop
+        let hoist = std::mem::replace(&mut self.hoist, false);
+        let condition = self.value(&expr.cond);
+        self.hoist = hoist;
+
+        let condition = condition?;
+        let body = self.block(&expr.body, false)?;
+        quote!(#s.loop_while(#condition, |#s| { #body });)
+      }
+
+      Expr::Loop(expr) => {
+        no_label(expr.label.is_some(), expr)?;
+
+        let body = self.block(&expr.body, false)?;
+        quote!(#s.loop_forever(|#s| { #body });)
+      }
+
+      Expr::ForLoop(expr) => self.for_loop(expr)?,
+
+      Expr::Match(expr) => self.switch(expr)?,
+
+      Expr::Break(expr) => {
+        if expr.label.is_some() || expr.expr.is_some() {
+          return Err(Error::new_spanned(
+            expr,
+            "breaking with labels or values is not supported",
+          ));
+        }
+
+        quote!(#s.loop_break();)
+      }
+
+      Expr::Continue(expr) => {
+        no_label(expr.label.is_some(), expr)?;
+        quote!(#s.loop_continue();)
+      }
+
+      Expr::Return(expr) => match &expr.expr {
+        Some(ret) => {
+          let ret = self.value(ret)?;
+          quote!(let #value = #ret; #s.leave(#value);)
+        }
+        None => quote!(#s.abort();),
+      },
+
+      Expr::Assign(expr) => {
+        let place = self.place(&expr.left)?;
+        let new_value = self.value(&expr.right)?;
+        quote!(let #value = #new_value; #s.set(&#place, #value);)
+      }
+
+      Expr::Binary(binary) if compound_op(&binary.op).is_some() => {
+        let op = compound_op(&binary.op);
+        let place = self.place(&binary.left)?;
+        let rhs = self.value(&binary.right)?;
+        quote!(let #value = ::shades::Expr::from(&#place) #op #rhs; #s.set(&#place, #value);)
+      }
+
+      Expr::Block(block) if block.label.is_none() => {
+        let body = self.block(&block.block, false)?;
+        quote!({ #body })
+      }
+
+      _ => {
+        let expr = self.value(expr)?;
+        quote!(#expr;)
+      }
+    };
+
+    Ok(tokens)
+  }
+
+  /// Lower an `if` statement, along with its `else if` and `else` branches.
+  fn when(&mut self, mut expr: &ExprIf) -> syn::Result<TokenStream2> {
+    let s = self.scope.clone();
+
+    // conditions are lowered first, so that the scope is not borrowed twice
+    let mut conditions = TokenStream2::new();
+    let mut chain = TokenStream2::new();
+
+    for i in 0.. {
+      let condition = hygienic(&format!("condition{}", i));
+      let value = self.value(&expr.cond)?;
+      conditions.extend(quote!(let #condition = #value;));
+
+      let body = self.block(&expr.then_branch, false)?;
+      chain.extend(if i == 0 {
+        quote!(#s.when(#condition, |#s| { #body }))
+      } else {
+        quote!(.or_else(#condition, |#s| { #body }))
+      });
+
+      match expr
+        .else_branch
+        .as_ref()
+        .map(|(_, else_branch)| &**else_branch)
+      {
+        Some(Expr::If(else_if)) => expr = else_if,
+
+        Some(Expr::Block(else_branch)) => {
+          let body = self.block(&else_branch.block, false)?;
+          chain.extend(quote!(.or(|#s| { #body })));
+          break;
+        }
+
+        _ => break,
+      }
+    }
+
+    Ok(quote!({
+      use ::shades::CanEscape as _;
+      #conditions
+      #chain;
+    }))
+  }
+
+  fn for_loop(&mut self, expr: &ExprForLoop) -> syn::Result<TokenStream2> {
+    no_label(expr.label.is_some(), expr)?;
+
+    let s = self.scope.clone();
+    let ident = match &*expr.pat {
+      Pat::Wild(_) => None,
+      pat => match pat_ident(pat) {
+        Some(ident) => Some(ident),
+        None => {
+          return Err(Error::new_spanned(
+            pat,
+            "loop variables must be identifiers",
+          ))
+        }
+      },
+    };
+
+    let body = self.nested(|lowerer| {
+      if let Some(ident) = ident {
+        lowerer.bind(ident);
+      }
+
+      lowerer.block(&expr.body, false)
+    })?;
+
+    // the loop variable is passed by reference
+    let (param, body) = match ident {
+      Some(ident) => (quote!(#ident), quote!(let #ident = #ident.clone(); #body)),
+      None => (quote!(_), body),
+    };
+
+    let start = hygienic("start");
+    let end = hygienic("end");
+
+    let tokens = match &*expr.expr {
+      Expr::Range(range) => {
+        let (range_start, range_end) = match (&range.start, &range.end) {
+          (Some(start), Some(end)) => (self.value(start)?, self.value(end)?),
+          _ => {
+            return Err(Error::new_spanned(
+              range,
+              "ranges must have a start and an end",
+            ))
+          }
+        };
+
+        let looping = match range.limits {
+          RangeLimits::HalfOpen(_) => quote!(#s.loop_range(#start, #end, |#s, #param| { #body })),
+          RangeLimits::Closed(_) => quote!(#s.loop_for(
+            #start,
+            |i| i.lte(&#end),
+            |i| i + 1,
+            |#s, #param| { #body },
+          )),
+        };
+
+        quote!({
+          let #start = #range_start;
+          let #end = #range_end;
+          #looping;
+        })
+      }
+
+      array => {
+        let array = self.value(array)?;
+        let value = hygienic("value");
+        quote!({
+          let #value = #array;
+          #s.for_each(#value, |#s, #param| { #body });
+        })
+      }
+    };
+
+    Ok(tokens)
+  }
+
+  /// Lower a `match` statement to a `switch` statement.
+  fn switch(&mut self, expr: &ExprMatch) -> syn::Result<TokenStream2> {
+    let s = self.scope.clone();
+    let value = hygienic("value");
+    let switched = self.value(&expr.expr)?;
+    let mut chain = TokenStream2::new();
+
+    for (i, arm) in expr.arms.iter().enumerate() {
+      if let Some((if_token, _)) = &arm.guard {
+        return Err(Error::new_spanned(
+          if_token,
+          "match guards are not supported",
+        ));
+      }
+
+      let body = match &*arm.body {
+        Expr::Block(block) if block.label.is_none() => self.block(&block.block, false)?,
+        body => self.nested(|lowerer| lowerer.expr_stmt(body))?,
+      };
+
+      let pats: Vec<_> = match &arm.pat {
+        Pat::Or(pat) => pat.cases.iter().collect(),
+        pat => vec![pat],
+      };
+
+      for (j, pat) in pats.iter().enumerate() {
+        let last = j + 1 == pats.len();
+
+        chain.extend(match pat {
+          Pat::Wild(_) if last && i + 1 == expr.arms.len() => quote!(.default(|#s| { #body })),
+          Pat::Wild(_) => return Err(Error::new_spanned(pat, "the `_` arm must come last")),
+
+          // alternatives fall through to the last one, which holds the body
+          Pat::Lit(_) | Pat::Path(_) if last => quote!(.case(#pat, |#s| { #body })),
+          Pat::Lit(_) | Pat::Path(_) => quote!(.case_fallthrough(#pat, |_| {})),
+
+          _ => {
+            return Err(Error::new_spanned(
+              pat,
+              "only literals, paths and `_` can be matched",
+            ))
+          }
+        });
+      }
+    }
+
+    Ok(quote!({
+      let #value = #switched;
+      #s.switch_on(#value) #chain;
+    }))
+  }
+
+  /// Lower the target of an assignment, which must be a variable.
+  fn place(&mut self, expr: &Expr) -> syn::Result<TokenStream2> {
+    match expr {
+      Expr::Path(_) | Expr::Field(_) | Expr::Macro(_) => Ok(expr.to_token_stream()),
+
+      Expr::Paren(expr) => self.place(&expr.expr),
+
+      Expr::Index(expr) => {
+        let object = self.place(&expr.expr)?;
+        let index = self.value(&expr.index)?;
+        Ok(quote!(#object.at(#index)))
+      }
+
+      Expr::MethodCall(expr) => {
+        let receiver = self.place(&expr.receiver)?;
+        let method = &expr.method;
+        let turbofish = &expr.turbofish;
+        let args = expr
+          .args
+          .iter()
+          .map(|arg| self.value(arg))
+          .collect::<syn::Result<Vec<_>>>()?;
+        Ok(quote!(#receiver.#method #turbofish(#(#args),*)))
+      }
+
+      _ => Err(Error::new_spanned(expr, "cannot assign to this expression")),
+    }
+  }
+
+  /// Lower an expression.
+  fn value(&mut self, expr: &Expr) -> syn::Result<TokenStream2> {
+    let mut expr = expr.clone();
+    self.visit_expr_mut(&mut expr);
+
+    match self.error.take() {
+      Some(error) => Err(error),
+      None => Ok(expr.into_token_stream()),
+    }
+  }
+
+  /// Lower an `if` expression to a conditional expression.
+  fn if_else(&mut self, expr: &ExprIf) -> syn::Result<TokenStream2> {
+    let s = self.scope.clone();
+    let else_branch = match &expr.else_branch {
+      Some((_, else_branch)) => else_branch,
+      None => {
+        return Err(Error::new_spanned(
+          expr,
+          "if expressions must have an else branch",
+        ))
+      }
+    };
+
+    let condition = self.value(&expr.cond)?;
+    let then_branch = self.block(&expr.then_branch, true)?;
+    let else_branch = match &**else_branch {
+      Expr::Block(block) => self.block(&block.block, true)?,
+      else_branch => self.value(else_branch)?,
+    };
+
+    // branches can be literals, which must be lifted to expressions
+    Ok(quote!(#s.if_else(
+      #condition,
+      |#s| ::shades::Expr::from({ #then_branch }),
+      |#s| ::shades::Expr::from({ #else_branch }),
+    )))
+  }
+}
+
+impl VisitMut for Lowerer {
+  fn visit_expr_mut(&mut self, expr: &mut Expr) {
+    if self.error.is_some() {
+      return;
+    }
+
+    let lowered = match expr {
+      // variables of the macro are used by reference, so that they can be used several times
+      Expr::Path(path) => match path.path.get_ident() {
+        Some(ident) if path.qself.is_none() && self.is_bound(ident) => {
+          Ok(quote!(::shades::Expr::from(&#ident)))
+        }
+        _ => return,
+      },
+
+      // host code
+      Expr::Closure(_) | Expr::Macro(_) => return,
+
+      // functions are called with lowered arguments, but the functions themselves are host code
+      Expr::Call(call) => {
+        call
+          .args
+          .iter_mut()
+          .for_each(|arg| self.visit_expr_mut(arg));
+        return;
+      }
+
+      Expr::If(_) | Expr::Block(_) if !self.hoist => Err(Error::new_spanned(
+        &*expr,
+        "if expressions and blocks are not supported in loop conditions",
+      )),
+
+      Expr::If(if_else) => self.if_else(if_else),
+
+      Expr::Block(block) if block.label.is_none() => {
+        self.block(&block.block, true).map(|body| quote!({ #body }))
+      }
+
+      Expr::Index(index) => {
+        self.visit_expr_mut(&mut index.expr);
+        self.visit_expr_mut(&mut index.index);
+
+        let object = parenthesized(&index.expr);
+        let index = &index.index;
+        Ok(quote!(#object.at(#index)))
+      }
+
+      Expr::Binary(binary) if compound_op(&binary.op).is_some() => Err(Error::new_spanned(
+        &*binary,
+        "assignments cannot be used as values",
+      )),
+
+      Expr::Binary(binary) => match op_method(&binary.op) {
+        Some(method) => {
+          self.visit_expr_mut(&mut binary.left);
+          self.visit_expr_mut(&mut binary.right);
+
+          let method = Ident::new(method, Span::call_site());
+          let lhs = match &*binary.left {
+            // literals don’t have the methods of expressions
+            lit @ Expr::Lit(_) => quote!(::shades::Expr::from(#lit)),
+            lhs => parenthesized(lhs),
+          };
+          let rhs = &binary.right;
+          Ok(quote!(#lhs.#method(#rhs)))
+        }
+
+        None => {
+          visit_mut::visit_expr_mut(self, expr);
+          return;
+        }
+      },
+
+      Expr::Assign(_)
+      | Expr::While(_)
+      | Expr::Loop(_)
+      | Expr::ForLoop(_)
+      | Expr::Match(_)
+      | Expr::Break(_)
+      | Expr::Continue(_)
+      | Expr::Return(_)
+      | Expr::Let(_) => Err(Error::new_spanned(
+        &*expr,
+        "this statement cannot be used as a value",
+      )),
+
+      _ => {
+        visit_mut::visit_expr_mut(self, expr);
+        return;
+      }
+    };
+
+    match lowered {
+      Ok(tokens) => *expr = Expr::Verbatim(tokens),
+      Err(error) => self.error = Some(error),
+    }
+  }
+}
+
+/// Tokens of `expr`, parenthesized if it is not a primary expression, so that a method can be called on it.
+fn parenthesized(expr: &Expr) -> TokenStream2 {
+  match expr {
+    Expr::Path(_)
+    | Expr::Lit(_)
+    | Expr::Call(_)
+    | Expr::MethodCall(_)
+    | Expr::Field(_)
+    | Expr::Index(_)
+    | Expr::Paren(_)
+    | Expr::Macro(_)
+    | Expr::Verbatim(_) => expr.to_token_stream(),
+    _ => quote!((#expr)),
+  }
+}
+
+fn no_label(has_label: bool, expr: &impl quote::ToTokens) -> syn::Result<()> {
+  if has_label {
+    Err(Error::new_spanned(expr, "loop labels are not supported"))
+  } else {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use syn::parse_quote;
+
+  fn lower(closure: ExprClosure) -> String {
+    match lower_closure(closure) {
+      Ok(tokens) => tokens.to_string(),
+      Err(error) => error.to_string(),
+    }
+  }
+
+  #[test]
+  fn statements() {
+    let lowered = lower(parse_quote!(|s: &mut Scope<()>| {
+      let x: f32 = 1.;
+      x *= 2.;
+    }));
+
+    assert_eq!(
+      lowered,
+      quote!(|s: &mut Scope<()>| {
+        let value = 1.;
+        let x = s.var::<f32>(value);
+        let value = ::shades::Expr::from(&x) * 2.;
+        s.set(&x, value);
+      })
+      .to_string()
+    );
+  }
+
+  #[test]
+  fn expressions() {
+    let lowered = lower(parse_quote!(|s, a: Expr<i32>| a[0] + 1 < 3 && b));
+
+    assert_eq!(
+      lowered,
+      quote!(|s, a: Expr<i32>| (::shades::Expr::from(&a).at(0) + 1).lt(3).and(b)).to_string()
+    );
+  }
+
+  #[test]
+  fn errors() {
+    assert_eq!(
+      lower(parse_quote!(|| {})),
+      "the first argument of the closure must be the scope"
+    );
+    assert_eq!(
+      lower(parse_quote!(|s| {
+        let x;
+      })),
+      "variables must be initialized"
+    );
+    assert_eq!(
+      lower(parse_quote!(|s| {
+        match x {
+          _ => {}
+          1 => {}
+        }
+      })),
+      "the `_` arm must come last"
+    );
+    assert_eq!(
+      lower(parse_quote!(|s| { while if c { a } else { b } {} })),
+      "if expressions and blocks are not supported in loop conditions"
+    );
+  }
+}
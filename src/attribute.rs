@@ -0,0 +1,187 @@
+//! Vertex attributes.
+//!
+//! Vertex shaders read their inputs from vertex buffers, whose layout the engine describes when creating a pipeline:
+//! which buffer bytes feed which input, and in which format. Plain [inputs](crate::ShaderBuilder::input) only carry a
+//! name and a type, which is not enough to bind buffers generically: the engine must also know what each input means
+//! and how it is stored.
+//!
+//! [`ShaderBuilder::attribute`] declares an input along with a [`VertexFormat`] — how the attribute is stored in the
+//! buffer — and a [`Semantic`] — what the attribute is. [`Shader::attributes`] and [`Shader::attribute`] surface them
+//! back, so that an engine can match the attributes of its meshes against any vertex shader built with shades.
+//!
+//! [`Shader::check`](crate::Shader::check) reports attributes whose type doesn’t match their format, and semantics
+//! used several times.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{Scope, ShaderBuilder, V3, V4, vec4};
+//! use shades::attribute::{Semantic, VertexFormat};
+//!
+//! let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+//!   let position = unsafe {
+//!     s.attribute::<V3<f32>>("position", VertexFormat::Float32x3, Semantic::Position)
+//!   };
+//!   let color = unsafe { s.attribute::<V4<f32>>("color", VertexFormat::Unorm8x4, Semantic::Color) };
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     s.set(vertex.position, vec4!(position, 1.));
+//!   })
+//! });
+//!
+//! let color = shader.attribute(&Semantic::Color).unwrap();
+//! assert_eq!(color.var().name(), "color");
+//! assert_eq!(color.var().index(), 1);
+//! assert_eq!(color.format().size(), 4);
+//! ```
+//!
+//! [`ShaderBuilder::attribute`]: crate::ShaderBuilder::attribute
+//! [`Shader::attributes`]: crate::Shader::attributes
+//! [`Shader::attribute`]: crate::Shader::attribute
+
+use crate::{Dim, PrimType, Type};
+
+/// What a vertex attribute is.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Semantic {
+  /// Position of the vertex.
+  Position,
+
+  /// Normal of the surface at the vertex.
+  Normal,
+
+  /// Tangent of the surface at the vertex.
+  Tangent,
+
+  /// Color of the vertex.
+  Color,
+
+  /// Texture coordinates of the given set, starting at `0`.
+  Uv(u32),
+
+  /// Engine-defined semantic, identified by name.
+  Custom(String),
+}
+
+/// How a vertex attribute is stored in a vertex buffer.
+///
+/// Normalized formats — `Unorm` and `Snorm` — are read as floating-point values in `[0; 1]` and `[-1; 1]`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum VertexFormat {
+  /// One 32-bit float, read as `float`.
+  Float32,
+  /// Two 32-bit floats, read as `vec2`.
+  Float32x2,
+  /// Three 32-bit floats, read as `vec3`.
+  Float32x3,
+  /// Four 32-bit floats, read as `vec4`.
+  Float32x4,
+  /// Two 16-bit floats, read as `vec2`.
+  Float16x2,
+  /// Four 16-bit floats, read as `vec4`.
+  Float16x4,
+  /// One 32-bit signed integer, read as `int`.
+  Sint32,
+  /// Two 32-bit signed integers, read as `ivec2`.
+  Sint32x2,
+  /// Three 32-bit signed integers, read as `ivec3`.
+  Sint32x3,
+  /// Four 32-bit signed integers, read as `ivec4`.
+  Sint32x4,
+  /// One 32-bit unsigned integer, read as `uint`.
+  Uint32,
+  /// Two 32-bit unsigned integers, read as `uvec2`.
+  Uint32x2,
+  /// Three 32-bit unsigned integers, read as `uvec3`.
+  Uint32x3,
+  /// Four 32-bit unsigned integers, read as `uvec4`.
+  Uint32x4,
+  /// Four 8-bit unsigned integers, read as `uvec4`.
+  Uint8x4,
+  /// Four 8-bit signed integers, read as `ivec4`.
+  Sint8x4,
+  /// Four normalized 8-bit unsigned integers, read as `vec4`.
+  Unorm8x4,
+  /// Four normalized 8-bit signed integers, read as `vec4`.
+  Snorm8x4,
+  /// Two normalized 16-bit unsigned integers, read as `vec2`.
+  Unorm16x2,
+  /// Four normalized 16-bit unsigned integers, read as `vec4`.
+  Unorm16x4,
+  /// Two normalized 16-bit signed integers, read as `vec2`.
+  Snorm16x2,
+  /// Four normalized 16-bit signed integers, read as `vec4`.
+  Snorm16x4,
+}
+
+impl VertexFormat {
+  /// Size of an attribute of this format, in bytes.
+  pub fn size(self) -> usize {
+    match self {
+      VertexFormat::Uint8x4
+      | VertexFormat::Sint8x4
+      | VertexFormat::Unorm8x4
+      | VertexFormat::Snorm8x4 => 4,
+      VertexFormat::Float16x2 | VertexFormat::Unorm16x2 | VertexFormat::Snorm16x2 => 4,
+      VertexFormat::Float16x4 | VertexFormat::Unorm16x4 | VertexFormat::Snorm16x4 => 8,
+      VertexFormat::Float32 | VertexFormat::Sint32 | VertexFormat::Uint32 => 4,
+      VertexFormat::Float32x2 | VertexFormat::Sint32x2 | VertexFormat::Uint32x2 => 8,
+      VertexFormat::Float32x3 | VertexFormat::Sint32x3 | VertexFormat::Uint32x3 => 12,
+      VertexFormat::Float32x4 | VertexFormat::Sint32x4 | VertexFormat::Uint32x4 => 16,
+    }
+  }
+
+  /// Type the attribute is read as in the shader.
+  pub fn ty(self) -> Type {
+    let prim_ty = match self {
+      VertexFormat::Float32 => PrimType::Float(Dim::Scalar),
+      VertexFormat::Float32x2
+      | VertexFormat::Float16x2
+      | VertexFormat::Unorm16x2
+      | VertexFormat::Snorm16x2 => PrimType::Float(Dim::D2),
+      VertexFormat::Float32x3 => PrimType::Float(Dim::D3),
+      VertexFormat::Float32x4
+      | VertexFormat::Float16x4
+      | VertexFormat::Unorm8x4
+      | VertexFormat::Snorm8x4
+      | VertexFormat::Unorm16x4
+      | VertexFormat::Snorm16x4 => PrimType::Float(Dim::D4),
+      VertexFormat::Sint32 => PrimType::Int(Dim::Scalar),
+      VertexFormat::Sint32x2 => PrimType::Int(Dim::D2),
+      VertexFormat::Sint32x3 => PrimType::Int(Dim::D3),
+      VertexFormat::Sint32x4 | VertexFormat::Sint8x4 => PrimType::Int(Dim::D4),
+      VertexFormat::Uint32 => PrimType::UInt(Dim::Scalar),
+      VertexFormat::Uint32x2 => PrimType::UInt(Dim::D2),
+      VertexFormat::Uint32x3 => PrimType::UInt(Dim::D3),
+      VertexFormat::Uint32x4 | VertexFormat::Uint8x4 => PrimType::UInt(Dim::D4),
+    };
+
+    Type {
+      prim_ty,
+      array_dims: Vec::new(),
+    }
+  }
+}
+
+/// Format and semantic of an input declared with [`ShaderBuilder::attribute`](crate::ShaderBuilder::attribute).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct VertexAttribute {
+  pub(crate) name: String,
+  pub(crate) format: VertexFormat,
+  pub(crate) semantic: Semantic,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{ToType as _, V2, V4};
+
+  #[test]
+  fn formats() {
+    assert_eq!(VertexFormat::Float32x3.size(), 12);
+    assert_eq!(VertexFormat::Snorm16x4.size(), 8);
+    assert_eq!(VertexFormat::Unorm8x4.ty(), V4::<f32>::ty());
+    assert_eq!(VertexFormat::Uint8x4.ty(), V4::<u32>::ty());
+    assert_eq!(VertexFormat::Float16x2.ty(), V2::<f32>::ty());
+  }
+}
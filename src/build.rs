@@ -0,0 +1,343 @@
+//! Build script helpers.
+//!
+//! Shaders that don’t depend on runtime values can be written once, at build time, instead of every time the
+//! application starts. [`Build`] is meant to be used from a build script: it writes shaders to `OUT_DIR`, one file per
+//! shader and [`Target`], and the application embeds them with [`include_shader!`].
+//!
+//! Files are named `{name}.{stage}.{target}`, where `{stage}` is the usual extension of the stage — `vert`, `tesc`,
+//! `tese`, `geom`, `frag` or `comp` — and `{target}` is given by [`Target::extension`]: a fragment shader emitted as `sky` is
+//! written to `sky.frag.glsl`.
+//!
+//! Files are only written when their content changes, so that crates including them are not rebuilt needlessly.
+//! Cargo reruns build scripts when any file of the package changes, unless told otherwise; [`Build::rerun_if_changed`]
+//! restricts reruns to the files the shaders are actually built from.
+//!
+//! # Examples
+//!
+//! In `build.rs`:
+//!
+//! ```no_run
+//! use shades::{Scope, ShaderBuilder, V4, outputs, vec4};
+//! use shades::build::Build;
+//!
+//! fn main() -> Result<(), shades::build::BuildError> {
+//!   let sky = ShaderBuilder::new_fragment_shader(|mut s, _| {
+//!     outputs!(s, color: V4<f32>);
+//!     s.main_fun(|s: &mut Scope<()>| s.set(color, vec4!(0.2, 0.4, 0.8, 1.)))
+//!   });
+//!
+//!   Build::new()?.rerun_if_changed("build.rs").emit("sky", &sky)?;
+//!   Ok(())
+//! }
+//! ```
+//!
+//! In the application:
+//!
+//! ```ignore
+//! const SKY: &str = shades::include_shader!("sky.frag.glsl");
+//! ```
+
+use crate::{program::Program, stage::Stage, stage::StageKind, writer, Shader};
+use std::{
+  env,
+  error::Error,
+  fmt, fs, io,
+  path::{Path, PathBuf},
+};
+
+/// Include a shader emitted by [`Build`] as a `&'static str`.
+///
+/// The argument is the name of the file, relative to `OUT_DIR`, such as `"sky.frag.glsl"`.
+#[macro_export]
+macro_rules! include_shader {
+  ($file:expr) => {
+    include_str!(concat!(env!("OUT_DIR"), "/", $file))
+  };
+}
+
+/// Language shaders are written in.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Target {
+  /// GLSL, as written by [`writer::glsl`].
+  Glsl,
+
+  /// Vulkan-flavored GLSL, as written by [`crate::wgpu`].
+  #[cfg(feature = "wgpu")]
+  Wgpu,
+}
+
+impl Target {
+  /// Extension of the files written for this target.
+  pub fn extension(self) -> &'static str {
+    match self {
+      Target::Glsl => "glsl",
+      #[cfg(feature = "wgpu")]
+      Target::Wgpu => "wgpu.glsl",
+    }
+  }
+
+  fn write_shader_to_str<S>(self, shader: &Shader<S>) -> Result<String, fmt::Error> {
+    match self {
+      Target::Glsl => writer::glsl::write_shader_to_str(shader),
+      #[cfg(feature = "wgpu")]
+      Target::Wgpu => crate::wgpu::write_shader_to_str(shader),
+    }
+  }
+}
+
+/// Errors that can happen while emitting shaders.
+#[derive(Debug)]
+pub enum BuildError {
+  /// `OUT_DIR` is not set; [`Build::new`] was not called from a build script.
+  MissingOutDir,
+
+  /// A shader couldn’t be written.
+  Fmt(fmt::Error),
+
+  /// A file couldn’t be read or written.
+  Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for BuildError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      BuildError::MissingOutDir => f.write_str("OUT_DIR is not set"),
+      BuildError::Fmt(_) => f.write_str("cannot write shader"),
+      BuildError::Io(path, e) => write!(f, "cannot write {}: {}", path.display(), e),
+    }
+  }
+}
+
+impl Error for BuildError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      BuildError::MissingOutDir => None,
+      BuildError::Fmt(e) => Some(e),
+      BuildError::Io(_, e) => Some(e),
+    }
+  }
+}
+
+impl From<fmt::Error> for BuildError {
+  fn from(e: fmt::Error) -> Self {
+    BuildError::Fmt(e)
+  }
+}
+
+/// Shader emitter, writing shaders to a directory.
+#[derive(Debug)]
+pub struct Build {
+  out_dir: PathBuf,
+  targets: Vec<Target>,
+}
+
+impl Build {
+  /// Create an emitter writing to `OUT_DIR`, for the [`Target::Glsl`] target.
+  pub fn new() -> Result<Self, BuildError> {
+    let out_dir = env::var_os("OUT_DIR").ok_or(BuildError::MissingOutDir)?;
+    Ok(Self::with_out_dir(out_dir))
+  }
+
+  /// Create an emitter writing to `out_dir`, for the [`Target::Glsl`] target.
+  pub fn with_out_dir(out_dir: impl Into<PathBuf>) -> Self {
+    Self {
+      out_dir: out_dir.into(),
+      targets: vec![Target::Glsl],
+    }
+  }
+
+  /// Directory files are written to.
+  pub fn out_dir(&self) -> &Path {
+    &self.out_dir
+  }
+
+  /// Emit shaders for `target` as well.
+  pub fn target(mut self, target: Target) -> Self {
+    if !self.targets.contains(&target) {
+      self.targets.push(target);
+    }
+
+    self
+  }
+
+  /// Emit shaders for `targets` only.
+  pub fn targets(mut self, targets: impl IntoIterator<Item = Target>) -> Self {
+    self.targets.clear();
+
+    for target in targets {
+      self = self.target(target);
+    }
+
+    self
+  }
+
+  /// Tell Cargo to rerun the build script only when `path` — or any other path passed here — changes.
+  ///
+  /// Paths are relative to the root of the package; a directory is scanned for any change of the files it contains.
+  pub fn rerun_if_changed(self, path: impl AsRef<Path>) -> Self {
+    println!("cargo:rerun-if-changed={}", path.as_ref().display());
+    self
+  }
+
+  /// Emit `shader` as `name`, once per target.
+  ///
+  /// The paths of the written files are returned in the order of the targets.
+  pub fn emit<S>(
+    &self,
+    name: &str,
+    shader: impl AsRef<Shader<S>>,
+  ) -> Result<Vec<PathBuf>, BuildError>
+  where
+    S: Stage,
+  {
+    let shader = shader.as_ref();
+
+    self
+      .targets
+      .iter()
+      .map(|&target| {
+        let path = self.out_dir.join(file_name(name, S::KIND, target));
+        let code = target.write_shader_to_str(shader)?;
+        write_if_changed(&path, &code)?;
+        Ok(path)
+      })
+      .collect()
+  }
+
+  /// Emit all the stages of `program` as `name`, once per target.
+  pub fn emit_program(&self, name: &str, program: &Program) -> Result<Vec<PathBuf>, BuildError> {
+    let mut paths = self.emit(name, program.vertex())?;
+
+    if let Some(tess_ctrl) = program.tess_ctrl() {
+      paths.extend(self.emit(name, tess_ctrl)?);
+    }
+
+    if let Some(tess_eval) = program.tess_eval() {
+      paths.extend(self.emit(name, tess_eval)?);
+    }
+
+    if let Some(geometry) = program.geometry() {
+      paths.extend(self.emit(name, geometry)?);
+    }
+
+    paths.extend(self.emit(name, program.fragment())?);
+    Ok(paths)
+  }
+}
+
+/// Name of the file `name` is emitted to for `stage` and `target`.
+pub fn file_name(name: &str, stage: StageKind, target: Target) -> String {
+  let stage = match stage {
+    StageKind::Vertex => "vert",
+    StageKind::TessCtrl => "tesc",
+    StageKind::TessEval => "tese",
+    StageKind::Geometry => "geom",
+    StageKind::Fragment => "frag",
+    StageKind::Compute => "comp",
+  };
+
+  format!("{}.{}.{}", name, stage, target.extension())
+}
+
+/// Write `content` to `path`, unless it already holds it, to keep the modification time of unchanged files.
+fn write_if_changed(path: &Path, content: &str) -> Result<(), BuildError> {
+  match fs::read_to_string(path) {
+    Ok(existing) if existing == content => return Ok(()),
+    _ => (),
+  }
+
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|e| BuildError::Io(parent.to_owned(), e))?;
+  }
+
+  fs::write(path, content).map_err(|e| BuildError::Io(path.to_owned(), e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{outputs, program::ProgramBuilder, Scope, ShaderBuilder, V4};
+
+  fn out_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("shades-build-{}-{}", std::process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+  }
+
+  fn shaders() -> (Shader<crate::stage::Vertex>, Shader<crate::stage::Fragment>) {
+    let vertex = ShaderBuilder::new_vertex_shader(|s, vertex| {
+      s.main_fun(|s: &mut Scope<()>| s.set(vertex.position, V4::from([0., 0., 0., 1.])))
+    });
+    let fragment = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      outputs!(s, color: V4<f32>);
+      s.main_fun(|s: &mut Scope<()>| s.set(color, V4::from([1., 1., 1., 1.])))
+    });
+
+    (vertex, fragment)
+  }
+
+  #[test]
+  fn emit() {
+    let dir = out_dir("emit");
+    let (_, fragment) = shaders();
+    let build = Build::with_out_dir(&dir);
+
+    let paths = build.emit("sky", &fragment).unwrap();
+    assert_eq!(paths, [dir.join("sky.frag.glsl")]);
+    assert_eq!(
+      fs::read_to_string(&paths[0]).unwrap(),
+      writer::glsl::write_shader_to_str(&fragment).unwrap()
+    );
+
+    // unchanged files are not written again
+    let modified = fs::metadata(&paths[0]).unwrap().modified().unwrap();
+    build.emit("sky", &fragment).unwrap();
+    assert_eq!(
+      fs::metadata(&paths[0]).unwrap().modified().unwrap(),
+      modified
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn emit_program() {
+    let dir = out_dir("emit_program");
+    let (vertex, fragment) = shaders();
+    let program = ProgramBuilder::new(vertex, fragment).link().unwrap();
+
+    let paths = Build::with_out_dir(&dir)
+      .emit_program("quad", &program)
+      .unwrap();
+    assert_eq!(
+      paths,
+      [dir.join("quad.vert.glsl"), dir.join("quad.frag.glsl")]
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[cfg(feature = "wgpu")]
+  #[test]
+  fn targets() {
+    let dir = out_dir("targets");
+    let (vertex, _) = shaders();
+
+    let paths = Build::with_out_dir(&dir)
+      .target(Target::Wgpu)
+      .emit("quad", &vertex)
+      .unwrap();
+    assert_eq!(
+      paths,
+      [dir.join("quad.vert.glsl"), dir.join("quad.vert.wgpu.glsl")]
+    );
+
+    let paths = Build::with_out_dir(&dir)
+      .targets([Target::Wgpu])
+      .emit("quad", &vertex)
+      .unwrap();
+    assert_eq!(paths, [dir.join("quad.vert.wgpu.glsl")]);
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
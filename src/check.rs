@@ -0,0 +1,1835 @@
+//! Validation of shaders.
+//!
+//! The typed API catches most mistakes at compile-time, but not all of them: variables can escape the scope they were
+//! declared in, environments of a stage can be passed to another one, and erased expressions can be built by hand.
+//! Such shaders are written just fine, and then rejected by the driver with a cryptic log. [`Shader::check`] looks for
+//! those problems beforehand and reports all of them as [`Diagnostic`]s, along with the [`Location`] where they appear.
+//! Scopes record where their instructions are added, so that locations also point at the
+//! [source](Location::source) of the instruction in the Rust code.
+//!
+//! Types are inferred from the leaves of expressions, and the operands of every operator and function call are checked
+//! against what GLSL accepts. Expressions whose type cannot be inferred — e.g. unsized built-in arrays — are assumed to
+//! be well-typed.
+//!
+//! Inputs which are never read and outputs which are never written — including `gl_Position` in vertex shaders — are
+//! valid, but are frequent silent bugs of generated shaders; they are reported as [warnings](Severity::Warning), which
+//! don’t fail the check on their own.
+//!
+//! The names of inputs, outputs and uniforms must be valid GLSL identifiers which are neither reserved nor clash with
+//! generated names; [`pass::rename_identifiers`](crate::pass::rename_identifiers) fixes the offending ones.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{CanEscape as _, Scope, ShaderBuilder, V4, lit, outputs};
+//! use shades::check::Severity;
+//!
+//! let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+//!   outputs!(s, color: V4<f32>);
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     let mut escaped = None;
+//!
+//!     s.when(lit!(true), |s| {
+//!       escaped = Some(s.var(lit!(1., 0., 0., 1.)));
+//!     });
+//!
+//!     // the variable doesn’t exist anymore here
+//!     s.set(color, escaped.unwrap());
+//!   })
+//! });
+//!
+//! let diagnostics = shader.check().unwrap_err();
+//! assert_eq!(diagnostics.len(), 1);
+//! assert_eq!(diagnostics[0].severity(), Severity::Error);
+//! assert_eq!(diagnostics[0].location().decl(), "main");
+//! assert_eq!(diagnostics[0].location().instruction(), &[1]);
+//! println!("{}", diagnostics[0]);
+//! ```
+//!
+//! [`Shader::check`]: crate::Shader::check
+
+use crate::{
+  attribute::VertexAttribute,
+  infer::{component_wise_ty, matrix_size, mul_ty, prim_dim, with_dim, TypeEnv, Types},
+  stage::StageKind,
+  writer::glsl,
+  BuiltIn, Dim, ErasedExpr, ErasedFun, ErasedFunHandle, ErasedReturn, ErasedScope, GeometryBuiltIn,
+  OutputLayout, PrimType, ScopeInstr, ScopedHandle, ShaderDecl, StageLayout, Swizzle,
+  SwizzleSelector, Type, VertexBuiltIn,
+};
+use std::{
+  collections::{HashMap, HashSet},
+  fmt, panic,
+};
+
+/// How bad a [`Diagnostic`] is.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+  /// The shader is valid, but probably doesn’t do what was intended.
+  Warning,
+
+  /// The shader is invalid and will be rejected by the driver.
+  Error,
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Severity::Warning => f.write_str("warning"),
+      Severity::Error => f.write_str("error"),
+    }
+  }
+}
+
+/// Where a [`Diagnostic`] was found.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Location {
+  decl: String,
+  instruction: Vec<usize>,
+  source: Option<&'static panic::Location<'static>>,
+}
+
+impl Location {
+  /// Name of the declaration, such as `main`, `uniform time` or `function fun_0`.
+  pub fn decl(&self) -> &str {
+    &self.decl
+  }
+
+  /// Path to the instruction in the declaration.
+  ///
+  /// The first index is the one of the instruction in the body of the function, the next one the index of the
+  /// instruction in the scope nested in that instruction, and so on. The path is empty if the problem is about the
+  /// declaration itself, or the expression returned at the end of a function.
+  pub fn instruction(&self) -> &[usize] {
+    &self.instruction
+  }
+
+  /// Location in the Rust code where the instruction was added, if known.
+  pub fn source(&self) -> Option<&'static panic::Location<'static>> {
+    self.source
+  }
+}
+
+impl fmt::Display for Location {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&self.decl)?;
+
+    if let Some((first, rest)) = self.instruction.split_first() {
+      write!(f, ", instruction {}", first)?;
+
+      for index in rest {
+        write!(f, ".{}", index)?;
+      }
+    }
+
+    if let Some(source) = self.source {
+      write!(f, ", at {}", source)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// A problem found in a shader by [`Shader::check`](crate::Shader::check).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Diagnostic {
+  severity: Severity,
+  location: Location,
+  message: String,
+}
+
+impl Diagnostic {
+  /// How bad the problem is.
+  pub fn severity(&self) -> Severity {
+    self.severity
+  }
+
+  /// Where the problem is.
+  pub fn location(&self) -> &Location {
+    &self.location
+  }
+
+  /// Description of the problem.
+  pub fn message(&self) -> &str {
+    &self.message
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{}: {} (in {})",
+      self.severity, self.message, self.location
+    )
+  }
+}
+
+/// Check the layout qualifiers, vertex attributes and declarations of a shader of the given stage.
+pub(crate) fn check(
+  stage: StageKind,
+  layouts: &[StageLayout],
+  attributes: &[VertexAttribute],
+  decls: &[ShaderDecl],
+) -> Vec<Diagnostic> {
+  let mut checker = Checker {
+    stage,
+    globals: TypeEnv::default(),
+    funs: HashMap::new(),
+    ret: None,
+    location: Location::default(),
+    read: HashSet::new(),
+    written: HashSet::new(),
+    output_locations: HashSet::new(),
+    input_vertices: None,
+    xfb_outputs: HashMap::new(),
+    xfb_strides: HashMap::new(),
+    tys: Types::new(),
+    diagnostics: Vec::new(),
+  };
+
+  checker.layouts(layouts);
+  checker.attributes(attributes, decls);
+
+  for decl in decls {
+    checker.decl(decl);
+  }
+
+  checker.unused(decls);
+  checker.diagnostics
+}
+
+struct Checker<'a> {
+  stage: StageKind,
+  // top-level declarations visible so far
+  globals: TypeEnv,
+  // functions defined so far
+  funs: HashMap<u16, &'a ErasedFun>,
+  // return type of the function being checked; None for void
+  ret: Option<Type>,
+  location: Location,
+  // inputs read and outputs written anywhere in the shader
+  read: HashSet<ScopedHandle>,
+  written: HashSet<ScopedHandle>,
+  // explicit locations and indices of the outputs
+  output_locations: HashSet<(u32, u32)>,
+  // size of gl_in in geometry shaders, given by the input primitive
+  input_vertices: Option<usize>,
+  // bytes occupied by the outputs captured by transform feedback, per buffer
+  xfb_outputs: HashMap<u32, Vec<(u32, u32, String)>>,
+  // strides of the transform feedback buffers which have one
+  xfb_strides: HashMap<u32, u32>,
+  // types of the expressions of the instruction being checked, which share their sub-expressions
+  tys: Types,
+  diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+  fn infer(&mut self, env: &TypeEnv, expr: &ErasedExpr) -> Option<Type> {
+    env.infer_shared(expr, &mut self.tys)
+  }
+
+  fn error(&mut self, message: String) {
+    self.diagnostic(Severity::Error, message);
+  }
+
+  fn warning(&mut self, message: String) {
+    self.diagnostic(Severity::Warning, message);
+  }
+
+  fn diagnostic(&mut self, severity: Severity, message: String) {
+    let diagnostic = Diagnostic {
+      severity,
+      location: self.location.clone(),
+      message,
+    };
+
+    // shared or repeated expressions would otherwise report the same problem several times
+    if !self.diagnostics.contains(&diagnostic) {
+      self.diagnostics.push(diagnostic);
+    }
+  }
+
+  /// Check that the vertex attributes are read as their declared type and have distinct semantics.
+  fn attributes(&mut self, attributes: &[VertexAttribute], decls: &[ShaderDecl]) {
+    for (i, attribute) in attributes.iter().enumerate() {
+      self.location = Location {
+        decl: format!("input {}", attribute.name),
+        instruction: Vec::new(),
+        source: None,
+      };
+
+      let ty = decls.iter().find_map(|decl| match decl {
+        ShaderDecl::In(name, ty) if *name == attribute.name => Some(ty),
+        _ => None,
+      });
+
+      match ty {
+        Some(ty) if *ty != attribute.format.ty() => self.error(format!(
+          "`{}` has type {}, but {:?} attributes are read as {}",
+          attribute.name,
+          type_name(ty),
+          attribute.format,
+          type_name(&attribute.format.ty())
+        )),
+        _ => (),
+      }
+
+      if let Some(previous) = attributes[..i]
+        .iter()
+        .find(|previous| previous.semantic == attribute.semantic)
+      {
+        self.error(format!(
+          "`{}` has the same semantic as `{}`",
+          attribute.name, previous.name
+        ));
+      }
+    }
+  }
+
+  fn layouts(&mut self, layouts: &[StageLayout]) {
+    for layout in layouts {
+      self.location = Location {
+        decl: layout.name(),
+        instruction: Vec::new(),
+        source: None,
+      };
+
+      match *layout {
+        StageLayout::Invocations(0) => {
+          self.error("the number of invocations must be at least 1".to_owned())
+        }
+        StageLayout::Vertices(0) => {
+          self.error("the number of output vertices must be at least 1".to_owned())
+        }
+        StageLayout::LocalSize(sizes) if sizes.iter().any(|size| size.size() == 0) => {
+          self.error("the local size must be at least 1 along every axis".to_owned())
+        }
+        StageLayout::ClipDistances(0) | StageLayout::CullDistances(0) => {
+          self.error(format!("`{}` must have at least 1 element", layout.name()))
+        }
+        StageLayout::InputPrimitive(primitive) => self.input_vertices = Some(primitive.vertices()),
+        StageLayout::XfbStride(buffer, stride) => {
+          if !stride.is_multiple_of(4) {
+            self.error(format!(
+              "the stride of {} bytes is not a multiple of 4",
+              stride
+            ));
+          }
+
+          self.xfb_strides.insert(buffer, stride);
+        }
+        _ => (),
+      }
+    }
+
+    // layout qualifiers without which the shader doesn’t compile
+    let required = match self.stage {
+      StageKind::TessCtrl => Some((
+        "layout vertices",
+        "the number of output vertices is not set; see `ShaderBuilder::vertices`",
+      )),
+      StageKind::TessEval => Some((
+        "layout tessellation",
+        "the primitive mode is not set; see `TessEvalShaderEnv::tessellation`",
+      )),
+      StageKind::Geometry => Some((
+        "layout input primitive",
+        "the input primitive is not set; see `ShaderBuilder::input_primitive`",
+      )),
+      StageKind::Compute => Some((
+        "layout local_size",
+        "the local size is not set; see `ShaderBuilder::local_size`",
+      )),
+      _ => None,
+    };
+
+    if let Some((decl, message)) = required {
+      if layouts.iter().all(|layout| layout.name() != decl) {
+        self.location = Location {
+          decl: decl.to_owned(),
+          instruction: Vec::new(),
+          source: None,
+        };
+        self.error(message.to_owned());
+      }
+    }
+  }
+
+  fn decl(&mut self, decl: &'a ShaderDecl) {
+    self.location = Location {
+      decl: decl.name(),
+      instruction: Vec::new(),
+      source: None,
+    };
+
+    match decl {
+      ShaderDecl::Main(fun) => self.fun(fun),
+
+      ShaderDecl::FunDef(handle, fun) => {
+        // recursion is not allowed, so the function is only visible after its definition
+        self.fun(fun);
+        self.funs.insert(*handle, fun);
+      }
+
+      ShaderDecl::Const(handle, ty, expr) => {
+        let env = self.globals.clone();
+        if self.expr(expr, &env) {
+          self.expect_ty(&env, expr, ty, || format!("`glob_{}`", handle));
+        }
+
+        self
+          .globals
+          .insert(ScopedHandle::global(*handle), ty.clone());
+      }
+
+      ShaderDecl::In(name, ty) => self.global(ScopedHandle::Input(name.clone()), ty),
+      ShaderDecl::Out(name, ty) => self.global(ScopedHandle::Output(name.clone()), ty),
+
+      ShaderDecl::LayoutOut(name, ty, layout) => {
+        self.output_layout(name, ty, layout);
+        self.global(ScopedHandle::Output(name.clone()), ty)
+      }
+
+      ShaderDecl::PatchIn(name, ty) => {
+        if self.stage != StageKind::TessEval {
+          self.error(format!(
+            "per-patch inputs are only available in tessellation evaluation shaders, not in a {} shader",
+            self.stage
+          ));
+        }
+
+        self.global(ScopedHandle::Input(name.clone()), ty)
+      }
+
+      ShaderDecl::PatchOut(name, ty) => {
+        if self.stage != StageKind::TessCtrl {
+          self.error(format!(
+            "per-patch outputs are only available in tessellation control shaders, not in a {} shader",
+            self.stage
+          ));
+        }
+
+        self.global(ScopedHandle::Output(name.clone()), ty)
+      }
+      ShaderDecl::Uniform(name, ty) => self.global(ScopedHandle::uniform(name.clone()), ty),
+
+      ShaderDecl::Shared(handle, ty) => {
+        if self.stage != StageKind::Compute {
+          self.error(format!(
+            "shared variables are only available in compute shaders, not in a {} shader",
+            self.stage
+          ));
+        }
+
+        self
+          .globals
+          .insert(ScopedHandle::global(*handle), ty.clone());
+      }
+
+      ShaderDecl::Comment(_) => (),
+    }
+  }
+
+  /// Declare an input, output or uniform, whose name is chosen by the user and hence might not be valid in GLSL.
+  fn global(&mut self, handle: ScopedHandle, ty: &Type) {
+    if let ScopedHandle::Input(ref name)
+    | ScopedHandle::Output(ref name)
+    | ScopedHandle::Uniform(ref name) = handle
+    {
+      if let Some(problem) = glsl::identifier_problem(name) {
+        self.error(format!(
+          "`{}` {}; see `pass::rename_identifiers`",
+          name, problem
+        ));
+      }
+    }
+
+    self.globals.insert(handle, ty.clone());
+  }
+
+  /// Check the location, index and transform feedback capture of an output.
+  fn output_layout(&mut self, name: &str, ty: &Type, layout: &OutputLayout) {
+    let index = layout.index.unwrap_or(0);
+
+    if index > 1 {
+      self.error(format!(
+        "`{}` has index {}, but only indices 0 and 1 are available for dual-source blending",
+        name, index
+      ));
+    } else if index == 1 && self.stage != StageKind::Fragment {
+      self.error(format!(
+        "`{}` has an index, which is only available in fragment shaders",
+        name
+      ));
+    }
+
+    match layout.location {
+      Some(location) if !self.output_locations.insert((location, index)) => self.error(format!(
+        "`{}` has the same location and index as another output",
+        name
+      )),
+
+      None if layout.index.is_some() => {
+        self.error(format!("`{}` has an index but no location", name))
+      }
+
+      _ => (),
+    }
+
+    if let Some(offset) = layout.xfb_offset {
+      self.xfb_output(name, ty, layout.xfb_buffer.unwrap_or(0), offset);
+    }
+  }
+
+  /// Check the bytes an output captured by transform feedback occupies in its buffer.
+  fn xfb_output(&mut self, name: &str, ty: &Type, buffer: u32, offset: u32) {
+    if !offset.is_multiple_of(4) {
+      self.error(format!(
+        "`{}` has an xfb_offset of {} bytes, which is not a multiple of 4",
+        name, offset
+      ));
+    }
+
+    let end = offset.saturating_add(xfb_size(ty));
+    let captured = self.xfb_outputs.entry(buffer).or_default();
+    let overlapped = captured
+      .iter()
+      .find(|(start, other_end, _)| offset < *other_end && *start < end)
+      .map(|(_, _, other)| other.clone());
+    captured.push((offset, end, name.to_owned()));
+
+    if let Some(other) = overlapped {
+      self.error(format!(
+        "`{}` overlaps `{}` in the transform feedback buffer {}",
+        name, other, buffer
+      ));
+    }
+
+    match self.xfb_strides.get(&buffer) {
+      Some(&stride) if end > stride => self.error(format!(
+        "`{}` ends at byte {}, past the stride of {} bytes of the transform feedback buffer {}",
+        name, end, stride, buffer
+      )),
+      _ => (),
+    }
+  }
+
+  /// Report the inputs which are never read and the outputs which are never written.
+  fn unused(&mut self, decls: &[ShaderDecl]) {
+    for decl in decls {
+      self.location = Location {
+        decl: decl.name(),
+        instruction: Vec::new(),
+        source: None,
+      };
+
+      match decl {
+        ShaderDecl::In(name, _) | ShaderDecl::PatchIn(name, _)
+          if !self.read.contains(&ScopedHandle::Input(name.clone())) =>
+        {
+          self.warning(format!("input `{}` is never read", name))
+        }
+
+        ShaderDecl::Out(name, _) | ShaderDecl::PatchOut(name, _)
+          if !self.written.contains(&ScopedHandle::Output(name.clone())) =>
+        {
+          self.warning(format!("output `{}` is never written", name))
+        }
+
+        _ => (),
+      }
+    }
+
+    let position = ScopedHandle::BuiltIn(BuiltIn::Vertex(VertexBuiltIn::Position));
+
+    if self.stage == StageKind::Vertex && !self.written.contains(&position) {
+      self.location = Location {
+        decl: "main".to_owned(),
+        instruction: Vec::new(),
+        source: None,
+      };
+      self.warning(format!("{} is never written", var_name(&position)));
+    }
+  }
+
+  fn fun(&mut self, fun: &ErasedFun) {
+    self.ret = match fun.ret {
+      ErasedReturn::Void => None,
+      ErasedReturn::Expr(ref ty, _) => Some(ty.clone()),
+    };
+
+    let env = self.scope(&fun.scope, self.globals.with_fun_args(fun));
+    self.tys.clear();
+
+    if let ErasedReturn::Expr(ref ty, ref expr) = fun.ret {
+      if self.expr(expr, &env) {
+        self.expect_ty(&env, expr, ty, || "the returned value".to_owned());
+      }
+    }
+  }
+
+  /// Check the instructions of `scope` and return the environment at its end.
+  fn scope(&mut self, scope: &ErasedScope, mut env: TypeEnv) -> TypeEnv {
+    let source = self.location.source;
+
+    for (i, instr) in scope.instructions.iter().enumerate() {
+      self.location.instruction.push(i);
+      self.location.source = scope.location(i);
+      self.instr(instr, &mut env);
+      self.location.instruction.pop();
+    }
+
+    self.location.source = source;
+
+    env
+  }
+
+  fn instr(&mut self, instr: &ScopeInstr, env: &mut TypeEnv) {
+    // types are only reused in the same environment
+    self.tys.clear();
+
+    if let ScopeInstr::For {
+      init_ty,
+      init_handle,
+      init_expr,
+      condition,
+      post_expr,
+      scope,
+    } = instr
+    {
+      // the loop variable is only visible in the loop
+      if self.expr(init_expr, env) {
+        self.expect_ty(env, init_expr, init_ty, || var_name(init_handle));
+      }
+
+      let mut env = env.clone();
+      env.declare(instr);
+      self.tys.clear();
+
+      if self.expr(condition, &env) {
+        self.expect_bool(&env, condition);
+      }
+
+      self.expr(post_expr, &env);
+      self.scope(scope, env);
+
+      return;
+    }
+
+    if let ScopeInstr::Call(fun, _) = instr {
+      self.fun_stage(fun);
+    }
+
+    if let Some(handle) = match instr {
+      ScopeInstr::MutateVar { var, .. } => assigned_var(var),
+      _ => None,
+    } {
+      self.written.insert(handle.clone());
+    }
+
+    let mut well_typed = true;
+
+    for expr in instr.exprs() {
+      well_typed &= self.expr(expr, env);
+    }
+
+    if well_typed {
+      self.instr_types(instr, env);
+    }
+
+    for scope in instr.scopes() {
+      self.scope(scope, env.clone());
+    }
+
+    env.declare(instr);
+  }
+
+  /// Check the types of the well-typed expressions of `instr` against what the instruction expects.
+  fn instr_types(&mut self, instr: &ScopeInstr, env: &TypeEnv) {
+    match instr {
+      ScopeInstr::VarDecl {
+        ty,
+        handle,
+        init_value,
+      } => {
+        self.expect_ty(env, init_value, ty, || var_name(handle));
+      }
+
+      ScopeInstr::Return(ret) => match (ret, self.ret.clone()) {
+        (ErasedReturn::Void, Some(ty)) => self.error(format!(
+          "returning nothing from a function returning {}",
+          type_name(&ty)
+        )),
+
+        (ErasedReturn::Expr(_, expr), None) => self.error(format!(
+          "returning {} from a void function",
+          expr_name(expr)
+        )),
+
+        (ErasedReturn::Expr(_, expr), Some(ty)) => {
+          self.expect_ty(env, expr, &ty, || "the returned value".to_owned());
+        }
+
+        _ => (),
+      },
+
+      ScopeInstr::If { condition, .. }
+      | ScopeInstr::ElseIf { condition, .. }
+      | ScopeInstr::ForMulti { condition, .. }
+      | ScopeInstr::While { condition, .. }
+      | ScopeInstr::DoWhile { condition, .. } => self.expect_bool(env, condition),
+
+      ScopeInstr::MutateVar { var, expr } => {
+        if let Some(ty) = self.infer(env, var) {
+          self.expect_ty(env, expr, &ty, || expr_name(var));
+        }
+      }
+
+      ScopeInstr::Call(fun, args) => {
+        self.fun_call(fun, args.iter().collect(), env);
+      }
+
+      ScopeInstr::Switch { expr, cases } => match self.infer(env, expr) {
+        Some(ty) if ty == int_ty() || ty == uint_ty() => {
+          for case in cases.iter().filter_map(|(case, _)| case.as_ref()) {
+            self.expect_ty(env, case, &ty, || "the case label".to_owned());
+          }
+        }
+
+        Some(ty) => self.error(format!(
+          "switching on {} of type {}, which is neither int nor uint",
+          expr_name(expr),
+          type_name(&ty)
+        )),
+
+        None => (),
+      },
+
+      _ => (),
+    }
+  }
+
+  /// Check `expr` and return whether it is well-typed.
+  ///
+  /// Expressions with ill-typed sub-expressions are not considered well-typed, so that a single mistake is not reported
+  /// again by all the expressions using it.
+  fn expr(&mut self, expr: &ErasedExpr, env: &TypeEnv) -> bool {
+    self.sub_expr(expr, env, &mut HashMap::new())
+  }
+
+  /// Check `expr`, skipping the sub-expressions already checked in the same environment.
+  fn sub_expr(
+    &mut self,
+    expr: &ErasedExpr,
+    env: &TypeEnv,
+    seen: &mut HashMap<*const ErasedExpr, bool>,
+  ) -> bool {
+    if let Some(&well_typed) = seen.get(&(expr as *const _)) {
+      return well_typed;
+    }
+
+    let mut well_typed = true;
+
+    for sub_expr in expr.sub_exprs() {
+      well_typed &= self.sub_expr(sub_expr, env, seen);
+    }
+
+    if let ErasedExpr::FunCall(fun, _) = expr {
+      self.fun_stage(fun);
+    }
+
+    match expr {
+      ErasedExpr::Var(handle) => self.var(handle, env),
+
+      ErasedExpr::FunCall(ErasedFunHandle::UserDefined(_), _) if !well_typed => (),
+
+      ErasedExpr::FunCall(fun @ ErasedFunHandle::UserDefined(_), args) => {
+        well_typed = self.fun_call(fun, args.iter().map(|arg| &**arg).collect(), env)
+      }
+
+      _ if well_typed => well_typed = self.operands(expr, env),
+
+      _ => (),
+    }
+
+    if let (true, ErasedExpr::ArrayLookup { object, index }) = (well_typed, expr) {
+      self.bounds(object, index, env);
+    }
+
+    seen.insert(expr, well_typed);
+    well_typed
+  }
+
+  /// Report an error if the operands of the operator or function call `expr` have invalid types.
+  ///
+  /// Operands whose type cannot be inferred are assumed to be valid.
+  fn operands(&mut self, expr: &ErasedExpr, env: &TypeEnv) -> bool {
+    // fields are represented by the built-in they refer to, which is not an operand
+    if let ErasedExpr::Field { .. } = expr {
+      return true;
+    }
+
+    let operands = expr.sub_exprs();
+
+    if operands.is_empty() {
+      return true;
+    }
+
+    let tys = match operands
+      .into_iter()
+      .map(|operand| self.infer(env, operand))
+      .collect::<Option<Vec<_>>>()
+    {
+      Some(tys) => tys,
+      None => return true,
+    };
+
+    if well_typed(expr, &tys) {
+      return true;
+    }
+
+    let what = match expr {
+      ErasedExpr::FunCall(..) => "arguments",
+      _ => "operands",
+    };
+
+    self.error(format!(
+      "{} has {} of invalid types {}",
+      expr_name(expr),
+      what,
+      tys.iter().map(type_name).collect::<Vec<_>>().join(", ")
+    ));
+
+    false
+  }
+
+  /// Report an error if the literal `index` is out of the bounds of `object`.
+  fn bounds(&mut self, object: &ErasedExpr, index: &ErasedExpr, env: &TypeEnv) {
+    let index = match *index {
+      ErasedExpr::LitInt(index) => i64::from(index),
+      ErasedExpr::LitUInt(index) => i64::from(index),
+      _ => return,
+    };
+
+    let len = match self.infer(env, object) {
+      Some(ty) => match (ty.array_dims.first(), ty.prim_ty) {
+        (Some(&len), _) => len,
+        (None, PrimType::Matrix(dim)) => dim_len(&matrix_size(&dim).0),
+        (None, ref prim_ty) => prim_dim(prim_ty).map_or(1, dim_len),
+      },
+
+      // gl_in has no type, but its size is known from the input primitive
+      None => match (object, self.input_vertices) {
+        (
+          ErasedExpr::Var(ScopedHandle::BuiltIn(BuiltIn::Geometry(GeometryBuiltIn::In))),
+          Some(len),
+        ) => len,
+        _ => return,
+      },
+    };
+
+    if index < 0 || index >= len as i64 {
+      self.error(format!(
+        "index {} is out of the bounds of {}, which has {} item(s)",
+        index,
+        expr_name(object),
+        len
+      ));
+    }
+  }
+
+  fn var(&mut self, handle: &ScopedHandle, env: &TypeEnv) {
+    if let ScopedHandle::Input(_) = handle {
+      self.read.insert(handle.clone());
+    }
+
+    match handle {
+      ScopedHandle::BuiltIn(builtin) => match builtin_stage(builtin) {
+        Some(stage) if stage != self.stage => self.error(format!(
+          "{} is a {} built-in, which is not available in a {} shader",
+          var_name(handle),
+          stage,
+          self.stage
+        )),
+
+        _ => (),
+      },
+
+      // scopes have unique IDs, so a variable of a function not found in the environment has escaped its scope
+      ScopedHandle::FunVar { .. } if !env.contains(handle) => self.error(format!(
+        "{} is used outside of the scope it is declared in",
+        var_name(handle)
+      )),
+
+      _ if !env.contains(handle) => self.error(format!(
+        "{} is not declared in this scope",
+        var_name(handle)
+      )),
+
+      _ => (),
+    }
+  }
+
+  fn fun_stage(&mut self, fun: &ErasedFunHandle) {
+    match fun_stage(fun) {
+      // invocations of compute work groups can be synchronized as well
+      Some(StageKind::TessCtrl)
+        if *fun == ErasedFunHandle::Barrier && self.stage == StageKind::Compute => {}
+
+      Some(stage) if stage != self.stage => self.error(format!(
+        "{} is a {} function, which is not available in a {} shader",
+        fun_name(fun),
+        stage,
+        self.stage
+      )),
+
+      _ => (),
+    }
+  }
+
+  /// Check a call to a user-defined function and return whether it is well-typed.
+  fn fun_call(&mut self, fun: &ErasedFunHandle, args: Vec<&ErasedExpr>, env: &TypeEnv) -> bool {
+    let handle = match fun {
+      ErasedFunHandle::UserDefined(handle) => *handle,
+      _ => return true,
+    };
+
+    let fun = match self.funs.get(&handle) {
+      Some(fun) => *fun,
+      None => {
+        self.error(format!("`fun_{}` is called but not defined before", handle));
+        return false;
+      }
+    };
+
+    if fun.args.len() != args.len() {
+      self.error(format!(
+        "`fun_{}` expects {} argument(s), but is called with {}",
+        handle,
+        fun.args.len(),
+        args.len()
+      ));
+      return false;
+    }
+
+    let mut well_typed = true;
+
+    for (i, (ty, arg)) in fun.args.iter().zip(args).enumerate() {
+      well_typed &= self.expect_ty(env, arg, ty, || {
+        format!("argument {} of `fun_{}`", i, handle)
+      });
+    }
+
+    well_typed
+  }
+
+  /// Report an error if `expr` is known not to have the type `expected`, and return whether it has.
+  fn expect_ty(
+    &mut self,
+    env: &TypeEnv,
+    expr: &ErasedExpr,
+    expected: &Type,
+    what: impl FnOnce() -> String,
+  ) -> bool {
+    match self.infer(env, expr) {
+      Some(ty) if ty != *expected => {
+        self.error(format!(
+          "{} has type {}, but is given {} of type {}",
+          what(),
+          type_name(expected),
+          expr_name(expr),
+          type_name(&ty)
+        ));
+        false
+      }
+
+      _ => true,
+    }
+  }
+
+  fn expect_bool(&mut self, env: &TypeEnv, condition: &ErasedExpr) {
+    match self.infer(env, condition) {
+      Some(ty) if ty != bool_ty() => self.error(format!(
+        "condition {} has type {}, but bool is expected",
+        expr_name(condition),
+        type_name(&ty)
+      )),
+
+      _ => (),
+    }
+  }
+}
+
+/// Variable assigned when assigning to `var`, which might be a component or a field of the variable.
+fn assigned_var(var: &ErasedExpr) -> Option<&ScopedHandle> {
+  match var {
+    ErasedExpr::Var(handle) => Some(handle),
+    ErasedExpr::Swizzle(object, _)
+    | ErasedExpr::Field { object, .. }
+    | ErasedExpr::ArrayLookup { object, .. } => assigned_var(object),
+    _ => None,
+  }
+}
+
+/// Stage a built-in belongs to; [`None`] for built-ins available in all stages.
+fn builtin_stage(builtin: &BuiltIn) -> Option<StageKind> {
+  match builtin {
+    BuiltIn::Vertex(_) => Some(StageKind::Vertex),
+    BuiltIn::TessCtrl(_) => Some(StageKind::TessCtrl),
+    BuiltIn::TessEval(_) => Some(StageKind::TessEval),
+    BuiltIn::Geometry(_) => Some(StageKind::Geometry),
+    BuiltIn::Fragment(_) => Some(StageKind::Fragment),
+    BuiltIn::Compute(_) => Some(StageKind::Compute),
+    BuiltIn::Subgroup(_) => None,
+  }
+}
+
+/// Whether `expr` is well-typed, given the types of its operands.
+fn well_typed(expr: &ErasedExpr, tys: &[Type]) -> bool {
+  match expr {
+    ErasedExpr::Array(ty, _) => match ty.array_dims.split_first() {
+      Some((&len, dims)) => {
+        tys.len() == len
+          && tys
+            .iter()
+            .all(|item| item.prim_ty == ty.prim_ty && item.array_dims == dims)
+      }
+      None => false,
+    },
+
+    ErasedExpr::ArrayLookup { .. } => {
+      let indexable = !tys[0].array_dims.is_empty()
+        || match tys[0].prim_ty {
+          PrimType::Matrix(_) => true,
+          ref prim_ty => prim_dim(prim_ty).is_some_and(|dim| *dim != Dim::Scalar),
+        };
+
+      indexable && (tys[1] == int_ty() || tys[1] == uint_ty())
+    }
+
+    ErasedExpr::ArrayLength(_) => !tys[0].array_dims.is_empty(),
+
+    ErasedExpr::Eq(..) | ErasedExpr::Neq(..) => tys[0] == tys[1],
+
+    _ => {
+      // other operators and functions don’t accept arrays
+      let prim_tys: Option<Vec<_>> = tys
+        .iter()
+        .map(|ty| ty.array_dims.is_empty().then(|| ty.prim_ty.clone()))
+        .collect();
+
+      prim_tys.is_some_and(|prim_tys| prim_well_typed(expr, &prim_tys))
+    }
+  }
+}
+
+/// Whether `expr` is well-typed, given the types of its operands, none of which is an array.
+fn prim_well_typed(expr: &ErasedExpr, tys: &[PrimType]) -> bool {
+  match expr {
+    ErasedExpr::Not(_) => is_bool(&tys[0]),
+
+    ErasedExpr::Neg(_) => is_numeric(&tys[0]),
+
+    ErasedExpr::And(..) | ErasedExpr::Or(..) | ErasedExpr::Xor(..) => {
+      is_bool(&tys[0]) && is_bool(&tys[1])
+    }
+
+    ErasedExpr::Lt(..) | ErasedExpr::Lte(..) | ErasedExpr::Gt(..) | ErasedExpr::Gte(..) => {
+      tys[0] == tys[1] && is_numeric(&tys[0]) && is_scalar(&tys[0])
+    }
+
+    ErasedExpr::Shl(..) | ErasedExpr::Shr(..) => {
+      is_integer(&tys[0])
+        && is_integer(&tys[1])
+        && (is_scalar(&tys[1]) || same_dim(&tys[0], &tys[1]))
+    }
+
+    ErasedExpr::BitOr(..) | ErasedExpr::BitAnd(..) | ErasedExpr::BitXor(..) => {
+      is_integer(&tys[0]) && component_wise_ty(tys[0].clone(), tys[1].clone()).is_some()
+    }
+
+    ErasedExpr::Add(..) | ErasedExpr::Sub(..) | ErasedExpr::Div(..) => {
+      is_numeric(&tys[0]) && component_wise_ty(tys[0].clone(), tys[1].clone()).is_some()
+    }
+
+    ErasedExpr::Mul(..) => is_numeric(&tys[0]) && mul_ty(tys[0].clone(), tys[1].clone()).is_some(),
+
+    // written as mod()
+    ErasedExpr::Rem(..) => is_float(&tys[0]) && (tys[1] == tys[0] || is_float_scalar(&tys[1])),
+
+    ErasedExpr::Cast(ty, _) => match (&ty.prim_ty, &tys[0]) {
+      (PrimType::Matrix(_), PrimType::Matrix(_)) => true,
+      (PrimType::Matrix(_), from) => is_float_scalar(from),
+      (to, from) => ty.array_dims.is_empty() && (is_scalar(from) || same_dim(to, from)),
+    },
+
+    ErasedExpr::Swizzle(_, sw) => {
+      let len = match prim_dim(&tys[0]) {
+        Some(dim) => dim_len(dim),
+        None => return false,
+      };
+
+      let in_range = |sel: &SwizzleSelector| (*sel as usize) < len;
+
+      match sw {
+        Swizzle::D1(a) => in_range(a),
+        Swizzle::D2(a, b) => in_range(a) && in_range(b),
+        Swizzle::D3(a, b, c) => in_range(a) && in_range(b) && in_range(c),
+        Swizzle::D4(a, b, c, d) => in_range(a) && in_range(b) && in_range(c) && in_range(d),
+      }
+    }
+
+    ErasedExpr::FunCall(fun, _) => fun_well_typed(fun, tys),
+
+    _ => true,
+  }
+}
+
+/// Whether a call to the built-in function `fun` is well-typed, given the types of its arguments.
+fn fun_well_typed(fun: &ErasedFunHandle, tys: &[PrimType]) -> bool {
+  let arity = |n: usize| tys.len() == n;
+  let all_same = tys.windows(2).all(|pair| pair[0] == pair[1]);
+
+  match fun {
+    ErasedFunHandle::Vec2 | ErasedFunHandle::Vec3 | ErasedFunHandle::Vec4 => {
+      let len = match fun {
+        ErasedFunHandle::Vec2 => 2,
+        ErasedFunHandle::Vec3 => 3,
+        _ => 4,
+      };
+      let same_kind = tys.iter().all(|ty| {
+        is_scalar_or_vec(ty) && with_dim(ty, Dim::Scalar) == with_dim(&tys[0], Dim::Scalar)
+      });
+      let total: usize = tys.iter().filter_map(prim_dim).map(dim_len).sum();
+
+      !tys.is_empty() && same_kind && (total == len || (arity(1) && is_scalar(&tys[0])))
+    }
+
+    ErasedFunHandle::Radians
+    | ErasedFunHandle::Degrees
+    | ErasedFunHandle::Sin
+    | ErasedFunHandle::Cos
+    | ErasedFunHandle::Tan
+    | ErasedFunHandle::ASin
+    | ErasedFunHandle::ACos
+    | ErasedFunHandle::SinH
+    | ErasedFunHandle::CosH
+    | ErasedFunHandle::TanH
+    | ErasedFunHandle::ASinH
+    | ErasedFunHandle::ACosH
+    | ErasedFunHandle::ATanH
+    | ErasedFunHandle::Exp
+    | ErasedFunHandle::Exp2
+    | ErasedFunHandle::Log
+    | ErasedFunHandle::Log2
+    | ErasedFunHandle::Sqrt
+    | ErasedFunHandle::InverseSqrt
+    | ErasedFunHandle::Floor
+    | ErasedFunHandle::Trunc
+    | ErasedFunHandle::Round
+    | ErasedFunHandle::RoundEven
+    | ErasedFunHandle::Ceil
+    | ErasedFunHandle::Fract
+    | ErasedFunHandle::IsNan
+    | ErasedFunHandle::IsInf
+    | ErasedFunHandle::Length
+    | ErasedFunHandle::Normalize
+    | ErasedFunHandle::DfDx
+    | ErasedFunHandle::DfDy
+    | ErasedFunHandle::DfDxFine
+    | ErasedFunHandle::DfDyFine
+    | ErasedFunHandle::DfDxCoarse
+    | ErasedFunHandle::DfDyCoarse
+    | ErasedFunHandle::FWidth
+    | ErasedFunHandle::FWidthFine
+    | ErasedFunHandle::FWidthCoarse
+    | ErasedFunHandle::InterpolateAtCentroid => arity(1) && is_float(&tys[0]),
+
+    ErasedFunHandle::ATan => (arity(1) || arity(2)) && all_same && is_float(&tys[0]),
+
+    ErasedFunHandle::Pow
+    | ErasedFunHandle::Distance
+    | ErasedFunHandle::Dot
+    | ErasedFunHandle::Reflect => arity(2) && all_same && is_float(&tys[0]),
+
+    ErasedFunHandle::Fma | ErasedFunHandle::FaceForward => {
+      arity(3) && all_same && is_float(&tys[0])
+    }
+
+    ErasedFunHandle::Cross => arity(2) && all_same && tys[0] == PrimType::Float(Dim::D3),
+
+    ErasedFunHandle::Refract => {
+      arity(3) && tys[0] == tys[1] && is_float(&tys[0]) && is_float_scalar(&tys[2])
+    }
+
+    ErasedFunHandle::Abs | ErasedFunHandle::Sign => {
+      arity(1) && matches!(tys[0], PrimType::Int(_) | PrimType::Float(_))
+    }
+
+    ErasedFunHandle::Mod => {
+      arity(2) && is_float(&tys[0]) && (tys[1] == tys[0] || is_float_scalar(&tys[1]))
+    }
+
+    ErasedFunHandle::Min | ErasedFunHandle::Max => {
+      arity(2) && is_numeric_vec(&tys[0]) && same_or_scalar(&tys[1], &tys[0])
+    }
+
+    ErasedFunHandle::Clamp => {
+      arity(3) && is_numeric_vec(&tys[0]) && tys[1] == tys[2] && same_or_scalar(&tys[1], &tys[0])
+    }
+
+    ErasedFunHandle::Mix => {
+      arity(3)
+        && tys[0] == tys[1]
+        && match tys[2] {
+          // component-wise selection
+          PrimType::Bool(_) => is_scalar_or_vec(&tys[0]) && same_dim(&tys[0], &tys[2]),
+          _ => is_float(&tys[0]) && same_or_scalar(&tys[2], &tys[0]),
+        }
+    }
+
+    ErasedFunHandle::Step => arity(2) && is_float(&tys[1]) && same_or_scalar(&tys[0], &tys[1]),
+
+    ErasedFunHandle::SmoothStep => {
+      arity(3) && is_float(&tys[2]) && tys[0] == tys[1] && same_or_scalar(&tys[0], &tys[2])
+    }
+
+    ErasedFunHandle::VLt | ErasedFunHandle::VLte | ErasedFunHandle::VGt | ErasedFunHandle::VGte => {
+      arity(2) && all_same && is_numeric_vec(&tys[0]) && !is_scalar(&tys[0])
+    }
+
+    ErasedFunHandle::VEq | ErasedFunHandle::VNeq => {
+      arity(2) && all_same && is_scalar_or_vec(&tys[0]) && !is_scalar(&tys[0])
+    }
+
+    ErasedFunHandle::VAny | ErasedFunHandle::VAll | ErasedFunHandle::VNot => {
+      arity(1) && matches!(tys[0], PrimType::Bool(_)) && !is_scalar(&tys[0])
+    }
+
+    ErasedFunHandle::BitfieldExtract => {
+      arity(3) && is_integer(&tys[0]) && tys[1] == int() && tys[2] == int()
+    }
+
+    ErasedFunHandle::BitfieldInsert => {
+      arity(4) && tys[0] == tys[1] && is_integer(&tys[0]) && tys[2] == int() && tys[3] == int()
+    }
+
+    ErasedFunHandle::BitfieldReverse
+    | ErasedFunHandle::BitCount
+    | ErasedFunHandle::FindLSB
+    | ErasedFunHandle::FindMSB => arity(1) && is_integer(&tys[0]),
+
+    ErasedFunHandle::InterpolateAtSample => arity(2) && is_float(&tys[0]) && tys[1] == int(),
+
+    ErasedFunHandle::InterpolateAtOffset => {
+      arity(2) && is_float(&tys[0]) && tys[1] == PrimType::Float(Dim::D2)
+    }
+
+    _ => true,
+  }
+}
+
+fn int() -> PrimType {
+  PrimType::Int(Dim::Scalar)
+}
+
+fn dim_len(dim: &Dim) -> usize {
+  match dim {
+    Dim::Scalar => 1,
+    Dim::D2 => 2,
+    Dim::D3 => 3,
+    Dim::D4 => 4,
+  }
+}
+
+/// Size of a value of type `ty` captured by transform feedback, in bytes.
+fn xfb_size(ty: &Type) -> u32 {
+  let components = match &ty.prim_ty {
+    PrimType::Matrix(dim) => {
+      let (cols, rows) = matrix_size(dim);
+      dim_len(&cols) * dim_len(&rows)
+    }
+    prim_ty => prim_dim(prim_ty).map_or(1, dim_len),
+  };
+  let len: usize = ty.array_dims.iter().product();
+
+  (components * len * 4) as u32
+}
+
+fn is_scalar(ty: &PrimType) -> bool {
+  prim_dim(ty) == Some(&Dim::Scalar)
+}
+
+fn is_scalar_or_vec(ty: &PrimType) -> bool {
+  prim_dim(ty).is_some()
+}
+
+fn same_dim(a: &PrimType, b: &PrimType) -> bool {
+  prim_dim(a).is_some() && prim_dim(a) == prim_dim(b)
+}
+
+/// Whether `ty` is either `other` or its scalar type.
+fn same_or_scalar(ty: &PrimType, other: &PrimType) -> bool {
+  ty == other || with_dim(other, Dim::Scalar).as_ref() == Some(ty)
+}
+
+fn is_bool(ty: &PrimType) -> bool {
+  *ty == PrimType::Bool(Dim::Scalar)
+}
+
+fn is_float(ty: &PrimType) -> bool {
+  matches!(ty, PrimType::Float(_))
+}
+
+fn is_float_scalar(ty: &PrimType) -> bool {
+  *ty == PrimType::Float(Dim::Scalar)
+}
+
+fn is_integer(ty: &PrimType) -> bool {
+  matches!(ty, PrimType::Int(_) | PrimType::UInt(_))
+}
+
+/// Whether `ty` is a scalar or a vector of numbers.
+fn is_numeric_vec(ty: &PrimType) -> bool {
+  matches!(
+    ty,
+    PrimType::Int(_) | PrimType::UInt(_) | PrimType::Float(_)
+  )
+}
+
+/// Whether `ty` is a scalar, a vector or a matrix of numbers.
+fn is_numeric(ty: &PrimType) -> bool {
+  is_numeric_vec(ty) || matches!(ty, PrimType::Matrix(_))
+}
+
+/// Stage a built-in function belongs to; [`None`] for functions available in all stages.
+fn fun_stage(fun: &ErasedFunHandle) -> Option<StageKind> {
+  match fun {
+    ErasedFunHandle::EmitStreamVertex
+    | ErasedFunHandle::EndStreamPrimitive
+    | ErasedFunHandle::EmitVertex
+    | ErasedFunHandle::EndPrimitive => Some(StageKind::Geometry),
+
+    ErasedFunHandle::DfDx
+    | ErasedFunHandle::DfDy
+    | ErasedFunHandle::DfDxFine
+    | ErasedFunHandle::DfDyFine
+    | ErasedFunHandle::DfDxCoarse
+    | ErasedFunHandle::DfDyCoarse
+    | ErasedFunHandle::FWidth
+    | ErasedFunHandle::FWidthFine
+    | ErasedFunHandle::FWidthCoarse
+    | ErasedFunHandle::InterpolateAtCentroid
+    | ErasedFunHandle::InterpolateAtSample
+    | ErasedFunHandle::InterpolateAtOffset => Some(StageKind::Fragment),
+
+    // also available in compute shaders, see `Checker::fun_stage`
+    ErasedFunHandle::Barrier => Some(StageKind::TessCtrl),
+
+    _ => None,
+  }
+}
+
+fn scalar_ty(prim_ty: PrimType) -> Type {
+  Type {
+    prim_ty,
+    array_dims: Vec::new(),
+  }
+}
+
+fn int_ty() -> Type {
+  scalar_ty(PrimType::Int(Dim::Scalar))
+}
+
+fn uint_ty() -> Type {
+  scalar_ty(PrimType::UInt(Dim::Scalar))
+}
+
+fn bool_ty() -> Type {
+  scalar_ty(PrimType::Bool(Dim::Scalar))
+}
+
+// writing to a String never fails in the functions below; functions and constants keep their generated names, which
+// don’t depend on the other declarations
+
+fn var_name(handle: &ScopedHandle) -> String {
+  let mut name = String::new();
+  let _ = glsl::write_scoped_handle(&mut name, &glsl::Identifiers::default(), handle);
+  format!("`{}`", name)
+}
+
+fn fun_name(fun: &ErasedFunHandle) -> String {
+  let mut name = String::new();
+  let _ = glsl::write_fun_handle(&mut name, &glsl::Identifiers::default(), fun);
+  format!("`{}`", name)
+}
+
+fn type_name(ty: &Type) -> String {
+  let mut name = String::new();
+  let _ = glsl::write_type(&mut name, ty);
+  name
+}
+
+fn expr_name(expr: &ErasedExpr) -> String {
+  let mut name = String::new();
+  let _ = glsl::write_expr(&mut name, &glsl::Identifiers::default(), expr);
+  format!("`{}`", name)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    inputs, lit, outputs, primitive, sw, vec4, Barriers as _, CanEscape as _, Expr,
+    FragmentBuiltIn, FragmentShaderEnv, GeometryShaderEnv, Scope, ShaderBuilder, Swizzlable as _,
+    V3, V4,
+  };
+
+  // the source locations depend on the layout of this file, so they are left out
+  fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+    diagnostics
+      .iter()
+      .cloned()
+      .map(|mut diagnostic| {
+        diagnostic.location.source = None;
+        diagnostic.to_string()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn valid_shaders() {
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      let square = s.fun(|_: &mut Scope<Expr<f32>>, a: Expr<f32>| &a * &a);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let x = s.var(square.call(lit!(2.)));
+
+        s.loop_for(
+          0,
+          |i| i.lt(4),
+          |i| i + 1,
+          |s, i| {
+            s.when(i.eq(2), |s| s.set(&x, &x * 2.));
+          },
+        );
+
+        s.set(vertex.position, lit!(1., 2., 3., 4.) * x.clone());
+      })
+    });
+
+    assert_eq!(shader.check(), Ok(vec![]));
+  }
+
+  #[test]
+  fn invalid_shaders() {
+    let shader = ShaderBuilder::new_vertex_shader(|s, vertex| {
+      s.main_fun(|s: &mut Scope<()>| {
+        let frag_coord = Expr::<V4<f32>>::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+          FragmentBuiltIn::FragCoord,
+        )));
+        let x = s.var(frag_coord);
+
+        let mut escaped = None;
+        s.when(lit!(true), |s| {
+          escaped = Some(s.var(lit!(1., 2., 3., 4.)));
+        });
+        s.set(vertex.position, escaped.unwrap());
+
+        // a float disguised as a vec4
+        s.set(&x, Expr::new(ErasedExpr::LitFloat(1.)));
+      })
+    });
+
+    let diagnostics = shader.check().unwrap_err();
+    let messages = messages(&diagnostics);
+
+    assert_eq!(
+      messages,
+      [
+        "error: `gl_FragCoord` is a fragment built-in, which is not available in a vertex shader (in main, instruction 0)",
+        "error: `var_1_0` is used outside of the scope it is declared in (in main, instruction 2)",
+        "error: `var_0_0` has type vec4, but is given `1.` of type float (in main, instruction 3)",
+      ]
+    );
+  }
+
+  #[test]
+  fn ill_typed_operands() {
+    let shader = ShaderBuilder::new_fragment_shader(|s, _| {
+      s.main_fun(|s: &mut Scope<()>| {
+        let v = s.var(lit!(1., 2.));
+
+        // vec2 + vec3
+        s.set(
+          &v,
+          Expr::new(ErasedExpr::Add(
+            lit!(1., 2.).erased.intern(),
+            lit!(1., 2., 3.).erased.intern(),
+          )),
+        );
+
+        // sin(int), hidden in a well-typed expression
+        let sin = Expr::<f32>::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Sin,
+          vec![lit!(1i32).erased],
+        ));
+        s.set(&v, &v * sin);
+
+        // vec2 with three components
+        s.set(
+          &v,
+          Expr::new(ErasedExpr::fun_call(
+            ErasedFunHandle::Vec2,
+            vec![lit!(1., 2.).erased, ErasedExpr::LitFloat(3.)],
+          )),
+        );
+      })
+    });
+
+    let diagnostics = shader.check().unwrap_err();
+    let messages: Vec<_> = diagnostics.iter().map(Diagnostic::message).collect();
+
+    assert_eq!(
+      messages,
+      [
+        "`(vec2(1., 2.) + vec3(1., 2., 3.))` has operands of invalid types vec2, vec3",
+        "`sin(1)` has arguments of invalid types int",
+        "`vec2(vec2(1., 2.), 3.)` has arguments of invalid types vec2, float",
+      ]
+    );
+  }
+
+  #[test]
+  fn stage_misuse() {
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      let fragment = FragmentShaderEnv::new();
+      let geometry = GeometryShaderEnv::new();
+
+      let slope = s.fun(move |_: &mut Scope<Expr<f32>>, a: Expr<f32>| fragment.fwidth(a));
+
+      s.main_fun(|s: &mut Scope<()>| {
+        s.set(vertex.point_size, slope.call(lit!(1.)));
+        geometry.emit_vertex(s);
+      })
+    });
+
+    let diagnostics = shader.check().unwrap_err();
+    let messages = messages(&diagnostics);
+
+    assert_eq!(
+      messages,
+      [
+        "error: `fwidth` is a fragment function, which is not available in a vertex shader (in function fun_0)",
+        "error: `EmitVertex` is a geometry function, which is not available in a vertex shader (in main, instruction 1)",
+        "warning: `gl_Position` is never written (in main)",
+      ]
+    );
+  }
+
+  #[test]
+  fn layouts() {
+    let shader = ShaderBuilder::new_tess_ctrl_shader(|s, _| s.main_fun(|_: &mut Scope<()>| {}));
+    let diagnostics = shader.check().unwrap_err();
+
+    assert_eq!(
+      messages(&diagnostics),
+      ["error: the number of output vertices is not set; see `ShaderBuilder::vertices` (in layout vertices)"]
+    );
+
+    let shader = ShaderBuilder::new_tess_ctrl_shader(|mut s, _| {
+      s.vertices::<0>();
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
+    let diagnostics = shader.check().unwrap_err();
+
+    assert_eq!(
+      messages(&diagnostics),
+      ["error: the number of output vertices must be at least 1 (in layout vertices)"]
+    );
+
+    let shader = ShaderBuilder::new_tess_ctrl_shader(|mut s, _| {
+      s.vertices::<4>();
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
+    assert_eq!(shader.check(), Ok(vec![]));
+
+    let shader = ShaderBuilder::new_compute_shader(|mut s, compute| {
+      s.local_size(16, 16, 0);
+      s.main_fun(move |s: &mut Scope<()>| compute.barrier(s))
+    });
+    let diagnostics = shader.check().unwrap_err();
+
+    assert_eq!(
+      messages(&diagnostics),
+      ["error: the local size must be at least 1 along every axis (in layout local_size)"]
+    );
+
+    let shader = ShaderBuilder::new_tess_eval_shader(|s, _| s.main_fun(|_: &mut Scope<()>| {}));
+    let diagnostics = shader.check().unwrap_err();
+
+    assert_eq!(
+      messages(&diagnostics),
+      ["error: the primitive mode is not set; see `TessEvalShaderEnv::tessellation` (in layout tessellation)"]
+    );
+
+    let shader = ShaderBuilder::new_geometry_shader(|mut s, _| {
+      s.clip_distances(2);
+      s.cull_distances(0);
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
+    let diagnostics = shader.check().unwrap_err();
+
+    assert_eq!(
+      messages(&diagnostics),
+      [
+        "error: `gl_CullDistance` must have at least 1 element (in gl_CullDistance)",
+        "error: the input primitive is not set; see `ShaderBuilder::input_primitive` (in layout input primitive)",
+      ]
+    );
+
+    let shader = ShaderBuilder::new_geometry_shader(|mut s, geometry| {
+      let input = s.input_primitive(primitive::Triangles);
+      s.main_fun(move |s: &mut Scope<()>| {
+        s.set(&geometry.position, input.at(2).position());
+        s.set(&geometry.position, input.at(3).position());
+      })
+    });
+    let diagnostics = shader.check().unwrap_err();
+
+    assert_eq!(
+      messages(&diagnostics),
+      ["error: index 3 is out of the bounds of `gl_in`, which has 3 item(s) (in main, instruction 1)"]
+    );
+  }
+
+  #[test]
+  fn output_layouts() {
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      let a = unsafe { s.output_at::<V4<f32>>("a", 0, 0) };
+      let b = unsafe { s.output_at::<V4<f32>>("b", 0, 1) };
+      let c = unsafe { s.output_at::<V4<f32>>("c", 0, 2) };
+      let d = unsafe { s.output_at::<V4<f32>>("d", 0, 1) };
+
+      s.main_fun(move |s: &mut Scope<()>| {
+        for output in [a, b, c, d] {
+          s.set(output, lit!(0., 0., 0., 0.));
+        }
+      })
+    });
+
+    let diagnostics = shader.check().unwrap_err();
+
+    assert_eq!(
+      messages(&diagnostics),
+      [
+        "error: `c` has index 2, but only indices 0 and 1 are available for dual-source blending (in output c)",
+        "error: `d` has the same location and index as another output (in output d)",
+      ]
+    );
+  }
+
+  #[test]
+  fn transform_feedback() {
+    let shader = ShaderBuilder::new_geometry_shader(|mut s, geometry| {
+      s.input_primitive(primitive::Points);
+      s.xfb_stride(0, 30);
+      s.xfb_stride(1, 16);
+      let a = unsafe { s.xfb_output::<V3<f32>>("a", 0, 0) };
+      let b = unsafe { s.xfb_output::<V3<f32>>("b", 0, 8) };
+      let c = unsafe { s.xfb_output::<[f32; 2]>("c", 0, 14) };
+      let d = unsafe { s.xfb_output::<V4<f32>>("d", 1, 4) };
+      let e = unsafe { s.xfb_output::<V4<f32>>("e", 2, 0) };
+
+      s.main_fun(move |s: &mut Scope<()>| {
+        s.set(&geometry.position, lit!(0., 0., 0., 1.));
+        s.set(a, lit!(0., 0., 0.));
+        s.set(b, lit!(0., 0., 0.));
+        s.set(c, [0., 0.]);
+        s.set(d, lit!(0., 0., 0., 0.));
+        s.set(e, lit!(0., 0., 0., 0.));
+      })
+    });
+
+    let diagnostics = shader.check().unwrap_err();
+
+    assert_eq!(
+      messages(&diagnostics),
+      [
+        "error: the stride of 30 bytes is not a multiple of 4 (in layout xfb_stride of buffer 0)",
+        "error: `b` overlaps `a` in the transform feedback buffer 0 (in output b)",
+        "error: `c` has an xfb_offset of 14 bytes, which is not a multiple of 4 (in output c)",
+        "error: `c` overlaps `b` in the transform feedback buffer 0 (in output c)",
+        "error: `d` ends at byte 20, past the stride of 16 bytes of the transform feedback buffer 1 (in output d)",
+      ]
+    );
+  }
+
+  #[test]
+  fn attributes() {
+    use crate::attribute::{Semantic, VertexFormat};
+
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      let position =
+        unsafe { s.attribute::<V4<f32>>("position", VertexFormat::Float32x4, Semantic::Position) };
+      let normal =
+        unsafe { s.attribute::<V3<f32>>("normal", VertexFormat::Unorm8x4, Semantic::Normal) };
+      let other =
+        unsafe { s.attribute::<V4<f32>>("other", VertexFormat::Float32x4, Semantic::Position) };
+
+      s.main_fun(move |s: &mut Scope<()>| {
+        s.set(vertex.position, position + other + vec4!(normal, 0.));
+      })
+    });
+
+    let diagnostics = shader.check().unwrap_err();
+
+    assert_eq!(
+      messages(&diagnostics),
+      [
+        "error: `normal` has type vec3, but Unorm8x4 attributes are read as vec4 (in input normal)",
+        "error: `other` has the same semantic as `position` (in input other)",
+      ]
+    );
+  }
+
+  #[test]
+  fn escaping_vars() {
+    let shader = ShaderBuilder::new_fragment_shader(|s, _| {
+      s.main_fun(|s: &mut Scope<()>| {
+        let x = s.var(lit!(0.));
+        let mut escaped = None;
+
+        s.when(x.gt(0.), |s| {
+          escaped = Some(s.var(lit!(1.)));
+        })
+        .or_else(x.lt(0.), |s| {
+          // a variable of the sibling scope
+          let y = s.var(lit!(2.));
+          s.set(&y, escaped.as_ref().unwrap());
+        });
+      })
+    });
+
+    let diagnostics = shader.check().unwrap_err();
+
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+      diagnostics[0].message(),
+      "`var_1_0` is used outside of the scope it is declared in"
+    );
+    assert_eq!(diagnostics[0].location().instruction(), &[2, 1]);
+  }
+
+  #[test]
+  fn unused_inputs_outputs() {
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      inputs!(s, position: V3<f32>, normal: V3<f32>);
+      outputs!(s, v_position: V3<f32>, v_color: V4<f32>);
+      let _ = (normal, v_color);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        s.set(sw!(vertex.position, .x.y), sw!(position, .x.y));
+        s.set(v_position, position);
+      })
+    });
+
+    // warnings alone don’t fail the check
+    let diagnostics = shader.check().unwrap();
+    let messages = messages(&diagnostics);
+
+    assert_eq!(
+      messages,
+      [
+        "warning: input `normal` is never read (in input normal)",
+        "warning: output `v_color` is never written (in output v_color)",
+      ]
+    );
+    assert!(diagnostics
+      .iter()
+      .all(|diagnostic| diagnostic.severity() == Severity::Warning));
+  }
+
+  #[test]
+  fn literal_indices() {
+    let shader = ShaderBuilder::new_fragment_shader(|s, _| {
+      s.main_fun(|s: &mut Scope<()>| {
+        let a = s.var(lit!([1, 2, 3]));
+        let _ = s.var(a.at(2));
+        let _ = s.var(a.at(3));
+        let _ = s.var(a.at(-1));
+        let _ = s.var(a.at(a.at(0)));
+      })
+    });
+
+    let diagnostics = shader.check().unwrap_err();
+    let messages = messages(&diagnostics);
+
+    assert_eq!(
+      messages,
+      [
+        "error: index 3 is out of the bounds of `var_0_0`, which has 3 item(s) (in main, instruction 2)",
+        "error: index -1 is out of the bounds of `var_0_0`, which has 3 item(s) (in main, instruction 3)",
+      ]
+    );
+  }
+
+  #[test]
+  fn reserved_names() {
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, sample: f32);
+      let color = unsafe { s.input::<V4<f32>>("gl_color") };
+      let out = unsafe { s.output::<V4<f32>>("var_0") };
+      let time = unsafe { s.uniform::<f32>("my__time") };
+      let scale = unsafe { s.uniform::<f32>("2d") };
+
+      s.main_fun(|s: &mut Scope<()>| {
+        s.set(out, color * sample * time * scale);
+      })
+    });
+
+    let diagnostics = shader.check().unwrap_err();
+    let messages = messages(&diagnostics);
+
+    assert_eq!(
+      messages,
+      [
+        "error: `sample` is a reserved keyword; see `pass::rename_identifiers` (in input sample)",
+        "error: `gl_color` starts with the reserved prefix `gl_`; see `pass::rename_identifiers` (in input gl_color)",
+        "error: `var_0` clashes with a generated name; see `pass::rename_identifiers` (in output var_0)",
+        "error: `my__time` contains the reserved sequence `__`; see `pass::rename_identifiers` (in uniform my__time)",
+        "error: `2d` is not a valid identifier; see `pass::rename_identifiers` (in uniform 2d)",
+      ]
+    );
+  }
+
+  #[test]
+  fn source_locations() {
+    let mut line = 0;
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      outputs!(s, color: V4<f32>);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let mut escaped = None;
+
+        s.when(lit!(true), |s| {
+          escaped = Some(s.var(lit!(1., 0., 0., 1.)));
+        });
+
+        line = line!() + 1;
+        s.set(color, escaped.unwrap());
+      })
+    });
+
+    let diagnostics = shader.check().unwrap_err();
+    let source = diagnostics[0].location().source().unwrap();
+
+    assert_eq!(source.file(), file!());
+    assert_eq!(source.line(), line);
+    assert!(diagnostics[0]
+      .to_string()
+      .ends_with(&format!("(in main, instruction 1, at {})", source)));
+  }
+}
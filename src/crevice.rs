@@ -0,0 +1,289 @@
+//! [crevice] integration, enabled with the `crevice` feature gate.
+//!
+//! crevice derives the std140 byte representation of Rust structs, so that they can be uploaded to uniform buffers as
+//! they are. This module implements [`AsStd140`] for the vector and square matrix types of shades, and [`uniform_block!`]
+//! declares a struct once for both sides: the host side gets a struct deriving [`AsStd140`], and the shader side gets
+//! a struct of the matching uniforms, declared by [`UniformBlock::uniforms`].
+//!
+//! The uniforms are declared in the order of the fields, which is also the order in which crevice lays them out, so the
+//! bytes of the std140 struct can be uploaded directly to a buffer bound to a block of those uniforms, such as the
+//! block written by the [`wgpu`](crate::wgpu) writer.
+//!
+//! crevice only supports scalars, vectors and square matrices, so the fields of a uniform block are restricted to
+//! those.
+//!
+//! # Examples
+//!
+//! ```
+//! use crevice::std140::{AsStd140, Std140 as _};
+//! use shades::{M44, Scope, ShaderBuilder, V3, inputs, uniform_block, vec4};
+//! use shades::crevice::UniformBlock as _;
+//!
+//! uniform_block! {
+//!   pub struct Camera => CameraUniforms {
+//!     pub projection: M44,
+//!     pub offset: V3<f32>,
+//!     pub scale: f32,
+//!   }
+//! }
+//!
+//! let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+//!   inputs!(s, position: V3<f32>);
+//!   let camera = Camera::uniforms(&mut s);
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     s.set(
+//!       vertex.position,
+//!       camera.projection * vec4!(position * camera.scale + camera.offset, 1.),
+//!     );
+//!   })
+//! });
+//!
+//! let camera = Camera {
+//!   projection: [[1., 0., 0., 0.], [0., 1., 0., 0.], [0., 0., 1., 0.], [0., 0., 0., 1.]].into(),
+//!   offset: [0., 0., -1.].into(),
+//!   scale: 2.,
+//! };
+//! let bytes = camera.as_std140().as_bytes().to_owned();
+//! assert_eq!(bytes.len(), shader.uniform_block_layout(shades::layout::Layout::Std140).size());
+//! ```
+//!
+//! [crevice]: https://crates.io/crates/crevice
+
+use crate::{ShaderBuilder, M22, M33, M44, V2, V3, V4};
+use ::crevice::std140::{self, AsStd140};
+
+/// Structs declaring their fields as uniforms.
+///
+/// This trait is implemented by [`uniform_block!`].
+pub trait UniformBlock: AsStd140 {
+  /// Struct holding one uniform per field.
+  type Uniforms;
+
+  /// Declare the fields of the struct as uniforms of `builder`, named after the fields, in order.
+  fn uniforms<S>(builder: &mut ShaderBuilder<S>) -> Self::Uniforms;
+}
+
+/// Declare a struct whose fields are uniforms.
+///
+/// `uniform_block! { pub struct Name => NameUniforms { … } }` declares the struct `Name`, deriving crevice’s
+/// [`AsStd140`], and the struct `NameUniforms`, with the same fields wrapped in [`Var`](crate::Var)s, and implements
+/// [`UniformBlock`] for `Name`. Attributes are applied to `Name` only.
+///
+/// The derive emits paths to `::crevice`, so the calling crate must depend on crevice as well.
+///
+/// See the [module documentation](crate::crevice) for an example.
+#[macro_export]
+macro_rules! uniform_block {
+  (
+    $(#[$attr:meta])*
+    $vis:vis struct $name:ident => $uniforms:ident {
+      $( $field_vis:vis $field:ident : $t:ty ),* $(,)?
+    }
+  ) => {
+    $(#[$attr])*
+    #[derive(::crevice::std140::AsStd140)]
+    $vis struct $name {
+      $( $field_vis $field: $t ),*
+    }
+
+    $vis struct $uniforms {
+      $( $field_vis $field: $crate::Var<$t> ),*
+    }
+
+    impl $crate::crevice::UniformBlock for $name {
+      type Uniforms = $uniforms;
+
+      fn uniforms<S>(builder: &mut $crate::ShaderBuilder<S>) -> $uniforms {
+        $uniforms {
+          $( $field: unsafe { builder.uniform::<$t>(stringify!($field)) } ),*
+        }
+      }
+    }
+  };
+}
+
+macro_rules! impl_AsStd140_vn {
+  ($t:ident, $c:ty, $crevice:ident, $($field:ident: $i:tt),+) => {
+    impl AsStd140 for $t<$c> {
+      type Output = std140::$crevice;
+
+      fn as_std140(&self) -> Self::Output {
+        std140::$crevice {
+          $( $field: component(self.0[$i]) ),+
+        }
+      }
+
+      fn from_std140(v: Self::Output) -> Self {
+        Self([$( component(v.$field) ),+])
+      }
+    }
+  };
+}
+
+impl_AsStd140_vn!(V2, f32, Vec2, x: 0, y: 1);
+impl_AsStd140_vn!(V3, f32, Vec3, x: 0, y: 1, z: 2);
+impl_AsStd140_vn!(V4, f32, Vec4, x: 0, y: 1, z: 2, w: 3);
+impl_AsStd140_vn!(V2, i32, IVec2, x: 0, y: 1);
+impl_AsStd140_vn!(V3, i32, IVec3, x: 0, y: 1, z: 2);
+impl_AsStd140_vn!(V4, i32, IVec4, x: 0, y: 1, z: 2, w: 3);
+impl_AsStd140_vn!(V2, u32, UVec2, x: 0, y: 1);
+impl_AsStd140_vn!(V3, u32, UVec3, x: 0, y: 1, z: 2);
+impl_AsStd140_vn!(V4, u32, UVec4, x: 0, y: 1, z: 2, w: 3);
+impl_AsStd140_vn!(V2, bool, BVec2, x: 0, y: 1);
+impl_AsStd140_vn!(V3, bool, BVec3, x: 0, y: 1, z: 2);
+impl_AsStd140_vn!(V4, bool, BVec4, x: 0, y: 1, z: 2, w: 3);
+
+/// Convert a vector component to or from its std140 representation.
+///
+/// Only booleans have a different representation, but going through [`Into`] for all components keeps
+/// [`impl_AsStd140_vn`] uniform.
+fn component<A, B>(a: A) -> B
+where
+  A: Into<B>,
+{
+  a.into()
+}
+
+impl AsStd140 for M22 {
+  type Output = std140::Mat2;
+
+  fn as_std140(&self) -> Self::Output {
+    let [x, y] = self.0;
+
+    std140::Mat2 {
+      x: V2(x).as_std140(),
+      _pad_x: [0.; 2],
+      y: V2(y).as_std140(),
+      _pad_y: [0.; 2],
+    }
+  }
+
+  fn from_std140(m: Self::Output) -> Self {
+    Self([V2::from_std140(m.x).0, V2::from_std140(m.y).0])
+  }
+}
+
+impl AsStd140 for M33 {
+  type Output = std140::Mat3;
+
+  fn as_std140(&self) -> Self::Output {
+    let [x, y, z] = self.0;
+
+    std140::Mat3 {
+      x: V3(x).as_std140(),
+      _pad_x: 0.,
+      y: V3(y).as_std140(),
+      _pad_y: 0.,
+      z: V3(z).as_std140(),
+      _pad_z: 0.,
+    }
+  }
+
+  fn from_std140(m: Self::Output) -> Self {
+    Self([
+      V3::from_std140(m.x).0,
+      V3::from_std140(m.y).0,
+      V3::from_std140(m.z).0,
+    ])
+  }
+}
+
+impl AsStd140 for M44 {
+  type Output = std140::Mat4;
+
+  fn as_std140(&self) -> Self::Output {
+    let [x, y, z, w] = self.0;
+
+    std140::Mat4 {
+      x: V4(x).as_std140(),
+      y: V4(y).as_std140(),
+      z: V4(z).as_std140(),
+      w: V4(w).as_std140(),
+    }
+  }
+
+  fn from_std140(m: Self::Output) -> Self {
+    Self([
+      V4::from_std140(m.x).0,
+      V4::from_std140(m.y).0,
+      V4::from_std140(m.z).0,
+      V4::from_std140(m.w).0,
+    ])
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{layout::Layout, Scope, ShaderBuilder};
+  use std::convert::TryInto as _;
+
+  uniform_block! {
+    struct Block => BlockUniforms {
+      scale: f32,
+      offset: V3<f32>,
+      flags: V2<bool>,
+      transform: M33,
+      count: u32,
+    }
+  }
+
+  #[test]
+  fn round_trip() {
+    let v = V3::from([1., 2., 3.]);
+    assert_eq!(V3::from_std140(v.as_std140()), v);
+
+    let b = V4::from([true, false, false, true]);
+    assert_eq!(V4::from_std140(b.as_std140()), b);
+
+    let m = M22::from([[1., 2.], [3., 4.]]);
+    assert_eq!(M22::from_std140(m.as_std140()), m);
+  }
+
+  #[test]
+  fn uniform_block() {
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      let block = Block::uniforms(&mut s);
+      let _ = (
+        block.scale,
+        block.offset,
+        block.flags,
+        block.transform,
+        block.count,
+      );
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
+
+    let names: Vec<_> = shader.uniforms().map(|uniform| uniform.name()).collect();
+    assert_eq!(names, ["scale", "offset", "flags", "transform", "count"]);
+
+    let block = Block {
+      scale: 2.,
+      offset: [3., 4., 5.].into(),
+      flags: [true, false].into(),
+      transform: [[6., 7., 8.], [9., 10., 11.], [12., 13., 14.]].into(),
+      count: 15,
+    };
+    let std140 = block.as_std140();
+    let bytes = std140.as_bytes();
+    let layout = shader.uniform_block_layout(Layout::Std140);
+
+    assert_eq!(bytes.len(), layout.size());
+
+    // every member is found at the offset computed by shades
+    let word = |offset: usize| u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    let floats = |offset: usize| f32::from_bits(word(offset));
+    let offsets: Vec<_> = layout
+      .members()
+      .iter()
+      .map(|member| member.offset())
+      .collect();
+
+    assert_eq!(floats(offsets[0]), 2.);
+    assert_eq!(floats(offsets[1] + 8), 5.);
+    assert_eq!(word(offsets[2] + 4), 0);
+    assert_eq!(floats(offsets[3] + 16 + 4), 10.);
+    assert_eq!(word(offsets[4]), 15);
+  }
+}
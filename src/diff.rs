@@ -0,0 +1,286 @@
+//! Structural diff of shaders.
+//!
+//! When a regenerated shader doesn’t have the same [digest](crate::Shader::digest) as before, [`diff`] tells which
+//! declarations were added, removed or changed, and how. Declarations are matched by identity — the name of inputs,
+//! outputs and uniforms and the handle of functions and constants —, and changed ones come with a line diff of their
+//! GLSL code.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{Scope, ShaderBuilder, V4, lit, outputs, uniforms};
+//!
+//! let a = ShaderBuilder::new_fragment_shader(|mut s, _| {
+//!   outputs!(s, color: V4<f32>);
+//!   s.main_fun(|s: &mut Scope<()>| s.set(color, lit!(1., 0., 0., 1.)))
+//! });
+//!
+//! let b = ShaderBuilder::new_fragment_shader(|mut s, _| {
+//!   uniforms!(s, time: f32);
+//!   outputs!(s, color: V4<f32>);
+//!   s.main_fun(|s: &mut Scope<()>| s.set(color, lit!(1., 0., 0., 1.) * time))
+//! });
+//!
+//! let diff = shades::diff(&a, &b);
+//! assert_eq!(diff.changes().len(), 2);
+//! println!("{}", diff);
+//! ```
+
+use crate::{writer::glsl, Shader};
+use std::fmt;
+
+/// Compute the structural diff between the shaders `a` and `b`.
+pub fn diff<S>(a: &Shader<S>, b: &Shader<S>) -> ShaderDiff {
+  let a = decls(a);
+  let b = decls(b);
+  let mut changes = Vec::new();
+
+  for (name, old) in &a {
+    match b.iter().find(|(b_name, _)| b_name == name) {
+      None => changes.push(Change::Removed {
+        name: name.clone(),
+        code: old.clone(),
+      }),
+
+      Some((_, new)) if new != old => changes.push(Change::Changed {
+        name: name.clone(),
+        old: old.clone(),
+        new: new.clone(),
+      }),
+
+      _ => (),
+    }
+  }
+
+  for (name, new) in &b {
+    if a.iter().all(|(a_name, _)| a_name != name) {
+      changes.push(Change::Added {
+        name: name.clone(),
+        code: new.clone(),
+      });
+    }
+  }
+
+  ShaderDiff { changes }
+}
+
+/// Name and GLSL code of the directives, capabilities, extensions, layout qualifiers and declarations of a shader.
+fn decls<S>(shader: &Shader<S>) -> Vec<(String, String)> {
+  let directives = shader.builder.directives.iter().map(|directive| {
+    let mut code = String::new();
+    // writing to a String never fails
+    let _ = glsl::write_directive(&mut code, directive);
+    (directive.name(), code.trim().to_owned())
+  });
+
+  let mut capabilities = shader.builder.capabilities.clone();
+  capabilities.sort();
+
+  let capabilities = capabilities.into_iter().map(|capability| {
+    let mut code = String::new();
+    let _ = glsl::write_capability(&mut code, &capability);
+    (format!("capability {:?}", capability), code)
+  });
+
+  let mut extensions = shader.builder.extensions.clone();
+  extensions.sort();
+
+  let extensions = extensions.into_iter().map(|(name, behavior)| {
+    let mut code = String::new();
+    let _ = glsl::write_extension(&mut code, &name, behavior);
+    (format!("extension {}", name), code.trim().to_owned())
+  });
+
+  let layouts = shader.builder.layouts.iter().map(|layout| {
+    let mut code = String::new();
+    let _ = glsl::write_stage_layout(&mut code, layout);
+    (layout.name(), code.trim().to_owned())
+  });
+
+  let names = glsl::Identifiers::new(&shader.builder);
+  let decls = shader.builder.decls.iter().map(move |decl| {
+    let name = decl.name();
+
+    let mut code = String::new();
+    let _ = glsl::write_decl(&mut code, &names, decl, false);
+    (name, code.trim().to_owned())
+  });
+
+  directives
+    .chain(capabilities)
+    .chain(extensions)
+    .chain(layouts)
+    .chain(decls)
+    .collect()
+}
+
+/// Differences between two shaders, computed by [`diff`].
+///
+/// Its [`Display`](fmt::Display) implementation lists the changes, one declaration after the other, prefixing removed
+/// lines with `-` and added lines with `+`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ShaderDiff {
+  changes: Vec<Change>,
+}
+
+impl ShaderDiff {
+  /// Changed declarations. Removed and changed declarations come first, in the order of the first shader, followed by
+  /// added declarations, in the order of the second shader.
+  pub fn changes(&self) -> &[Change] {
+    &self.changes
+  }
+
+  /// Whether both shaders have the same declarations.
+  pub fn is_empty(&self) -> bool {
+    self.changes.is_empty()
+  }
+}
+
+impl fmt::Display for ShaderDiff {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for change in &self.changes {
+      match change {
+        Change::Added { name, code } => {
+          writeln!(f, "added {}:", name)?;
+
+          for line in code.lines() {
+            writeln!(f, "+ {}", line)?;
+          }
+        }
+
+        Change::Removed { name, code } => {
+          writeln!(f, "removed {}:", name)?;
+
+          for line in code.lines() {
+            writeln!(f, "- {}", line)?;
+          }
+        }
+
+        Change::Changed { name, old, new } => {
+          writeln!(f, "changed {}:", name)?;
+
+          for (prefix, line) in diff_lines(old, new) {
+            writeln!(f, "{} {}", prefix, line)?;
+          }
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// Change of a single declaration.
+///
+/// Declarations are named after what they declare; e.g. `main`, `uniform time` or `function fun_0`. Their code is
+/// written in GLSL.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change {
+  /// A declaration only present in the second shader.
+  Added { name: String, code: String },
+
+  /// A declaration only present in the first shader.
+  Removed { name: String, code: String },
+
+  /// A declaration present in both shaders, with different code.
+  Changed {
+    name: String,
+    old: String,
+    new: String,
+  },
+}
+
+/// Line diff of `old` and `new`, based on their longest common subsequence of lines.
+///
+/// Each line is prefixed with `-` if it was removed, `+` if it was added and a blank otherwise.
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<(char, &'a str)> {
+  let old: Vec<_> = old.lines().collect();
+  let new: Vec<_> = new.lines().collect();
+
+  // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+  let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+
+  for i in (0..old.len()).rev() {
+    for j in (0..new.len()).rev() {
+      lcs[i][j] = if old[i] == new[j] {
+        lcs[i + 1][j + 1] + 1
+      } else {
+        lcs[i + 1][j].max(lcs[i][j + 1])
+      };
+    }
+  }
+
+  let mut lines = Vec::new();
+  let (mut i, mut j) = (0, 0);
+
+  while i < old.len() || j < new.len() {
+    if i < old.len() && j < new.len() && old[i] == new[j] {
+      lines.push((' ', old[i]));
+      i += 1;
+      j += 1;
+    } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+      lines.push(('-', old[i]));
+      i += 1;
+    } else {
+      lines.push(('+', new[j]));
+      j += 1;
+    }
+  }
+
+  lines
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{lit, outputs, uniforms, Scope, ShaderBuilder, V4};
+
+  #[test]
+  fn diff_shaders() {
+    let a = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      uniforms!(s, scale: f32);
+      outputs!(s, color: V4<f32>);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let x = s.var(lit!(1.) * scale);
+        s.set(color, lit!(1., 0., 0., 1.) * x);
+      })
+    });
+
+    let b = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      uniforms!(s, time: f32);
+      outputs!(s, color: V4<f32>);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let x = s.var(lit!(1.) * time);
+        s.set(color, lit!(1., 0., 0., 1.) * x);
+      })
+    });
+
+    assert!(diff(&a, &a).is_empty());
+
+    let diff = diff(&a, &b);
+
+    assert_eq!(
+      diff.changes()[0],
+      Change::Removed {
+        name: "uniform scale".to_owned(),
+        code: "uniform float scale;".to_owned()
+      }
+    );
+    assert_eq!(
+      diff.to_string(),
+      "removed uniform scale:
+- uniform float scale;
+changed main:
+  void main() {
+-   float var_0_0 = (1. * scale);
++   float var_0_0 = (1. * time);
+    color = (vec4(1., 0., 0., 1.) * var_0_0);
+  }
+added uniform time:
++ uniform float time;
+"
+    );
+  }
+}
@@ -0,0 +1,1603 @@
+//! CPU interpreter.
+//!
+//! [`Eval`] runs the `main` function of a shader on the CPU, given the values of its inputs, uniforms and built-ins,
+//! and returns the values of its outputs. This allows to unit-test the maths of a shader with `cargo test`, without a
+//! GPU.
+//!
+//! The interpreter runs a single invocation of the shader. Built-ins which are not set read as zero, barriers do
+//! nothing and subgroup operations behave as if the subgroup only contained the current invocation. Functions
+//! depending on other invocations or on the pipeline — derivatives, interpolation, geometry primitives, etc. — are not
+//! supported and make [`Eval::run`] fail with [`EvalError::Unsupported`].
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{BuiltIn, Scope, ShaderBuilder, V3, VertexBuiltIn, inputs, uniforms, vec4};
+//! use shades::eval::{Eval, Value};
+//!
+//! let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+//!   inputs!(s, position: V3<f32>);
+//!   uniforms!(s, scale: f32);
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     s.set(vertex.position, vec4!(position * scale, 1.));
+//!   })
+//! });
+//!
+//! let outputs = Eval::new(&shader)
+//!   .input("position", V3::from([1., 2., 3.]))
+//!   .uniform("scale", 2.)
+//!   .run()
+//!   .unwrap();
+//!
+//! assert_eq!(
+//!   outputs.builtin(BuiltIn::Vertex(VertexBuiltIn::Position)),
+//!   Some(&Value::Float(vec![2., 4., 6., 1.]))
+//! );
+//! ```
+
+use crate::{
+  infer::{self, TypeEnv},
+  BuiltIn, Dim, ErasedExpr, ErasedFun, ErasedFunHandle, ErasedReturn, ErasedScope, Matrix,
+  PrimType, ScopeInstr, ScopedHandle, Shader, ShaderDecl, Swizzle, SwizzleSelector, Type, V2, V3,
+  V4,
+};
+use std::{cmp::Ordering, collections::HashMap, convert::TryFrom, error::Error, fmt};
+
+/// Value of an expression.
+///
+/// Scalars are represented as vectors with a single component.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+  /// Integral scalar or vector.
+  Int(Vec<i32>),
+
+  /// Unsigned integral scalar or vector.
+  UInt(Vec<u32>),
+
+  /// Floating scalar or vector.
+  Float(Vec<f32>),
+
+  /// Boolean scalar or vector.
+  Bool(Vec<bool>),
+
+  /// Floating matrix, as a list of columns.
+  Matrix(Vec<Vec<f32>>),
+
+  /// Array.
+  Array(Vec<Value>),
+}
+
+impl Value {
+  /// Zero value of type `ty`, used for outputs and variables which are not initialized.
+  fn zero(ty: &Type) -> Self {
+    let value = match ty.prim_ty {
+      PrimType::Int(ref dim) => Value::Int(vec![0; dim_len(dim)]),
+      PrimType::UInt(ref dim) => Value::UInt(vec![0; dim_len(dim)]),
+      PrimType::Float(ref dim) => Value::Float(vec![0.; dim_len(dim)]),
+      PrimType::Bool(ref dim) => Value::Bool(vec![false; dim_len(dim)]),
+      PrimType::Matrix(ref dim) => {
+        let (cols, rows) = infer::matrix_size(dim);
+        Value::Matrix(vec![vec![0.; dim_len(&rows)]; dim_len(&cols)])
+      }
+    };
+
+    ty.array_dims
+      .iter()
+      .rev()
+      .fold(value, |value, &len| Value::Array(vec![value; len]))
+  }
+
+  fn to_bool(&self) -> Result<bool, EvalError> {
+    match self {
+      Value::Bool(a) if a.len() == 1 => Ok(a[0]),
+      _ => Err(mismatch()),
+    }
+  }
+
+  fn to_index(&self) -> Result<i64, EvalError> {
+    match self {
+      Value::Int(a) if a.len() == 1 => Ok(a[0].into()),
+      Value::UInt(a) if a.len() == 1 => Ok(a[0].into()),
+      _ => Err(mismatch()),
+    }
+  }
+
+  fn to_floats(&self) -> Result<Vec<f32>, EvalError> {
+    match self {
+      Value::Float(a) => Ok(a.clone()),
+      _ => Err(mismatch()),
+    }
+  }
+
+  /// Primitive type of the same kind as this value, with the dimension `dim`.
+  fn prim_ty(&self, dim: Dim) -> Result<PrimType, EvalError> {
+    match self {
+      Value::Int(_) => Ok(PrimType::Int(dim)),
+      Value::UInt(_) => Ok(PrimType::UInt(dim)),
+      Value::Float(_) => Ok(PrimType::Float(dim)),
+      Value::Bool(_) => Ok(PrimType::Bool(dim)),
+      _ => Err(mismatch()),
+    }
+  }
+
+  /// Element `index` of an array, column `index` of a matrix or component `index` of a vector.
+  fn get(&self, index: i64) -> Result<Value, EvalError> {
+    let len = self.len();
+    let i = usize::try_from(index)
+      .ok()
+      .filter(|&i| i < len)
+      .ok_or(EvalError::OutOfBounds { index, len })?;
+
+    Ok(match self {
+      Value::Int(a) => Value::Int(vec![a[i]]),
+      Value::UInt(a) => Value::UInt(vec![a[i]]),
+      Value::Float(a) => Value::Float(vec![a[i]]),
+      Value::Bool(a) => Value::Bool(vec![a[i]]),
+      Value::Matrix(a) => Value::Float(a[i].clone()),
+      Value::Array(a) => a[i].clone(),
+    })
+  }
+
+  fn set(&mut self, index: i64, value: Value) -> Result<(), EvalError> {
+    let len = self.len();
+    let i = usize::try_from(index)
+      .ok()
+      .filter(|&i| i < len)
+      .ok_or(EvalError::OutOfBounds { index, len })?;
+
+    match (self, value) {
+      (Value::Int(a), Value::Int(b)) if b.len() == 1 => a[i] = b[0],
+      (Value::UInt(a), Value::UInt(b)) if b.len() == 1 => a[i] = b[0],
+      (Value::Float(a), Value::Float(b)) if b.len() == 1 => a[i] = b[0],
+      (Value::Bool(a), Value::Bool(b)) if b.len() == 1 => a[i] = b[0],
+      (Value::Matrix(a), Value::Float(b)) if b.len() == a[i].len() => a[i] = b,
+      (Value::Array(a), b) => a[i] = b,
+      _ => return Err(mismatch()),
+    }
+
+    Ok(())
+  }
+
+  fn len(&self) -> usize {
+    match self {
+      Value::Int(a) => a.len(),
+      Value::UInt(a) => a.len(),
+      Value::Float(a) => a.len(),
+      Value::Bool(a) => a.len(),
+      Value::Matrix(a) => a.len(),
+      Value::Array(a) => a.len(),
+    }
+  }
+
+  fn swizzle(&self, sw: &Swizzle) -> Result<Value, EvalError> {
+    let indices = swizzle_indices(sw);
+    let len = self.len();
+
+    if let Some(&i) = indices.iter().find(|&&i| i >= len) {
+      return Err(EvalError::OutOfBounds {
+        index: i as i64,
+        len,
+      });
+    }
+
+    match self {
+      Value::Int(a) => Ok(Value::Int(indices.iter().map(|&i| a[i]).collect())),
+      Value::UInt(a) => Ok(Value::UInt(indices.iter().map(|&i| a[i]).collect())),
+      Value::Float(a) => Ok(Value::Float(indices.iter().map(|&i| a[i]).collect())),
+      Value::Bool(a) => Ok(Value::Bool(indices.iter().map(|&i| a[i]).collect())),
+      _ => Err(mismatch()),
+    }
+  }
+
+  fn set_swizzle(&mut self, sw: &Swizzle, value: Value) -> Result<(), EvalError> {
+    let indices = swizzle_indices(sw);
+
+    if value.len() != indices.len() {
+      return Err(mismatch());
+    }
+
+    for (j, &i) in indices.iter().enumerate() {
+      self.set(i as i64, value.get(j as i64)?)?;
+    }
+
+    Ok(())
+  }
+}
+
+macro_rules! impl_from_value {
+  ($t:ty, $variant:ident) => {
+    impl From<$t> for Value {
+      fn from(a: $t) -> Self {
+        Value::$variant(vec![a])
+      }
+    }
+
+    impl From<V2<$t>> for Value {
+      fn from(a: V2<$t>) -> Self {
+        Value::$variant(a.0.to_vec())
+      }
+    }
+
+    impl From<V3<$t>> for Value {
+      fn from(a: V3<$t>) -> Self {
+        Value::$variant(a.0.to_vec())
+      }
+    }
+
+    impl From<V4<$t>> for Value {
+      fn from(a: V4<$t>) -> Self {
+        Value::$variant(a.0.to_vec())
+      }
+    }
+  };
+}
+
+impl_from_value!(i32, Int);
+impl_from_value!(u32, UInt);
+impl_from_value!(f32, Float);
+impl_from_value!(bool, Bool);
+
+impl<const M: usize, const N: usize> From<Matrix<[[f32; N]; M]>> for Value {
+  fn from(a: Matrix<[[f32; N]; M]>) -> Self {
+    Value::Matrix(a.0.iter().map(|col| col.to_vec()).collect())
+  }
+}
+
+impl<T, const N: usize> From<[T; N]> for Value
+where
+  T: Into<Value>,
+{
+  fn from(a: [T; N]) -> Self {
+    Value::Array(Vec::from(a).into_iter().map(Into::into).collect())
+  }
+}
+
+/// Errors that can occur while interpreting a shader.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EvalError {
+  /// The shader reads an input which was not set.
+  MissingInput(String),
+
+  /// The shader reads a uniform which was not set.
+  MissingUniform(String),
+
+  /// An integral division or remainder by zero.
+  DivisionByZero,
+
+  /// An array, matrix or vector lookup out of bounds.
+  OutOfBounds { index: i64, len: usize },
+
+  /// The shader uses something the interpreter doesn’t support, or values of unexpected types.
+  Unsupported(String),
+}
+
+impl fmt::Display for EvalError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      EvalError::MissingInput(name) => write!(f, "input {} is not set", name),
+      EvalError::MissingUniform(name) => write!(f, "uniform {} is not set", name),
+      EvalError::DivisionByZero => f.write_str("integral division by zero"),
+      EvalError::OutOfBounds { index, len } => {
+        write!(f, "index {} is out of bounds (length is {})", index, len)
+      }
+      EvalError::Unsupported(what) => write!(f, "unsupported: {}", what),
+    }
+  }
+}
+
+impl Error for EvalError {}
+
+fn mismatch() -> EvalError {
+  EvalError::Unsupported("operands of unexpected types".to_owned())
+}
+
+/// Interpreter of a [`Shader`].
+#[derive(Debug)]
+pub struct Eval<'a, S> {
+  shader: &'a Shader<S>,
+  inputs: HashMap<String, Value>,
+  uniforms: HashMap<String, Value>,
+  builtins: HashMap<BuiltIn, Value>,
+}
+
+impl<'a, S> Eval<'a, S> {
+  /// Create an interpreter for `shader`, with no inputs, uniforms or built-ins set.
+  pub fn new(shader: &'a Shader<S>) -> Self {
+    Self {
+      shader,
+      inputs: HashMap::new(),
+      uniforms: HashMap::new(),
+      builtins: HashMap::new(),
+    }
+  }
+
+  /// Set the value of the input `name`.
+  pub fn input(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+    self.inputs.insert(name.into(), value.into());
+    self
+  }
+
+  /// Set the value of the uniform `name`.
+  pub fn uniform(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+    self.uniforms.insert(name.into(), value.into());
+    self
+  }
+
+  /// Set the value of a built-in, such as [`VertexBuiltIn::VertexID`](crate::VertexBuiltIn::VertexID).
+  pub fn builtin(mut self, builtin: BuiltIn, value: impl Into<Value>) -> Self {
+    self.builtins.insert(builtin, value.into());
+    self
+  }
+
+  /// Run the `main` function of the shader.
+  pub fn run(&self) -> Result<Outputs, EvalError> {
+    let mut machine = Machine::default();
+
+    let globals = self
+      .inputs
+      .iter()
+      .map(|(name, value)| (ScopedHandle::Input(name.clone()), value))
+      .chain(
+        self
+          .uniforms
+          .iter()
+          .map(|(name, value)| (ScopedHandle::uniform(name.clone()), value)),
+      )
+      .chain(
+        self
+          .builtins
+          .iter()
+          .map(|(&builtin, value)| (ScopedHandle::builtin(builtin), value)),
+      );
+
+    for (handle, value) in globals {
+      machine.globals.insert(handle, value.clone());
+    }
+
+    let mut main = None;
+
+    for decl in &self.shader.builder.decls {
+      match decl {
+        ShaderDecl::Main(fun) => main = Some(fun),
+
+        ShaderDecl::FunDef(handle, fun) => {
+          machine.funs.insert(*handle, fun);
+        }
+
+        ShaderDecl::Const(handle, _, expr) => {
+          let value = machine.eval(expr)?;
+          machine.globals.insert(ScopedHandle::global(*handle), value);
+        }
+
+        ShaderDecl::Out(name, ty)
+        | ShaderDecl::PatchOut(name, ty)
+        | ShaderDecl::LayoutOut(name, ty, _) => {
+          machine
+            .globals
+            .insert(ScopedHandle::Output(name.clone()), Value::zero(ty));
+        }
+
+        ShaderDecl::Shared(handle, ty) => {
+          machine
+            .globals
+            .insert(ScopedHandle::global(*handle), Value::zero(ty));
+        }
+
+        ShaderDecl::In(..)
+        | ShaderDecl::PatchIn(..)
+        | ShaderDecl::Uniform(..)
+        | ShaderDecl::Comment(_) => (),
+      }
+    }
+
+    if let Some(main) = main {
+      machine.call_fun(main, Vec::new())?;
+    }
+
+    let mut outputs = Outputs::default();
+
+    for (handle, value) in machine.globals {
+      match handle {
+        ScopedHandle::Output(name) => {
+          outputs.outputs.insert(name, value);
+        }
+
+        ScopedHandle::BuiltIn(builtin) => {
+          outputs.builtins.insert(builtin, value);
+        }
+
+        _ => (),
+      }
+    }
+
+    Ok(outputs)
+  }
+}
+
+/// Values of the outputs of a shader, returned by [`Eval::run`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Outputs {
+  outputs: HashMap<String, Value>,
+  builtins: HashMap<BuiltIn, Value>,
+}
+
+impl Outputs {
+  /// Value of the output `name`.
+  pub fn get(&self, name: &str) -> Option<&Value> {
+    self.outputs.get(name)
+  }
+
+  /// Value of a built-in at the end of `main`, such as
+  /// [`VertexBuiltIn::Position`](crate::VertexBuiltIn::Position).
+  ///
+  /// Only the built-ins which were set or used by the shader have a value.
+  pub fn builtin(&self, builtin: BuiltIn) -> Option<&Value> {
+    self.builtins.get(&builtin)
+  }
+}
+
+/// What to do after running an instruction.
+enum Flow {
+  Next,
+  Break,
+  Continue,
+  Return(Option<Value>),
+}
+
+#[derive(Default)]
+struct Machine<'a> {
+  funs: HashMap<u16, &'a ErasedFun>,
+  /// Constants, inputs, outputs, uniforms and built-ins.
+  globals: HashMap<ScopedHandle, Value>,
+  /// Arguments and variables of the functions being called.
+  frames: Vec<HashMap<ScopedHandle, Value>>,
+}
+
+impl<'a> Machine<'a> {
+  fn call_fun(&mut self, fun: &ErasedFun, args: Vec<Value>) -> Result<Option<Value>, EvalError> {
+    let frame = args
+      .into_iter()
+      .enumerate()
+      .map(|(i, arg)| (ScopedHandle::fun_arg(i as u16), arg))
+      .collect();
+
+    self.frames.push(frame);
+    let ret = self.run_fun(fun);
+    self.frames.pop();
+
+    ret
+  }
+
+  fn run_fun(&mut self, fun: &ErasedFun) -> Result<Option<Value>, EvalError> {
+    if let Flow::Return(ret) = self.exec_scope(&fun.scope)? {
+      return Ok(ret);
+    }
+
+    match fun.ret {
+      ErasedReturn::Void => Ok(None),
+      ErasedReturn::Expr(_, ref expr) => self.eval(expr).map(Some),
+    }
+  }
+
+  fn exec_scope(&mut self, scope: &ErasedScope) -> Result<Flow, EvalError> {
+    // whether a branch of the current if / else if / else chain was taken
+    let mut taken = false;
+
+    for instr in &scope.instructions {
+      let flow = match instr {
+        ScopeInstr::VarDecl {
+          handle, init_value, ..
+        } => {
+          let value = self.eval(init_value)?;
+          self.set_var(handle, value);
+          Flow::Next
+        }
+
+        ScopeInstr::VarDeclUninit { ty, handle } => {
+          self.set_var(handle, Value::zero(ty));
+          Flow::Next
+        }
+
+        ScopeInstr::Return(ErasedReturn::Void) => Flow::Return(None),
+        ScopeInstr::Return(ErasedReturn::Expr(_, expr)) => Flow::Return(Some(self.eval(expr)?)),
+        ScopeInstr::Continue => Flow::Continue,
+        ScopeInstr::Break => Flow::Break,
+        ScopeInstr::Comment(_) => Flow::Next,
+
+        ScopeInstr::If { condition, scope } => {
+          taken = self.eval(condition)?.to_bool()?;
+          self.exec_if(taken, scope)?
+        }
+
+        ScopeInstr::ElseIf { condition, scope } => {
+          if taken {
+            Flow::Next
+          } else {
+            taken = self.eval(condition)?.to_bool()?;
+            self.exec_if(taken, scope)?
+          }
+        }
+
+        ScopeInstr::Else { scope } => self.exec_if(!taken, scope)?,
+        ScopeInstr::Block { scope } => self.exec_scope(scope)?,
+
+        ScopeInstr::For {
+          init_handle,
+          init_expr,
+          condition,
+          post_expr,
+          scope,
+          ..
+        } => {
+          let value = self.eval(init_expr)?;
+          self.set_var(init_handle, value);
+
+          loop {
+            if !self.eval(condition)?.to_bool()? {
+              break Flow::Next;
+            }
+
+            match self.exec_scope(scope)? {
+              Flow::Next | Flow::Continue => (),
+              Flow::Break => break Flow::Next,
+              flow => break flow,
+            }
+
+            let value = self.eval(post_expr)?;
+            self.set_var(init_handle, value);
+          }
+        }
+
+        ScopeInstr::ForMulti {
+          condition,
+          post_exprs,
+          scope,
+        } => loop {
+          if !self.eval(condition)?.to_bool()? {
+            break Flow::Next;
+          }
+
+          match self.exec_scope(scope)? {
+            Flow::Next | Flow::Continue => (),
+            Flow::Break => break Flow::Next,
+            flow => break flow,
+          }
+
+          for (var, expr) in post_exprs {
+            let value = self.eval(expr)?;
+            self.assign(var, value)?;
+          }
+        },
+
+        ScopeInstr::While { condition, scope } => loop {
+          if !self.eval(condition)?.to_bool()? {
+            break Flow::Next;
+          }
+
+          match self.exec_scope(scope)? {
+            Flow::Next | Flow::Continue => (),
+            Flow::Break => break Flow::Next,
+            flow => break flow,
+          }
+        },
+
+        ScopeInstr::DoWhile { condition, scope } => loop {
+          match self.exec_scope(scope)? {
+            Flow::Next | Flow::Continue => (),
+            Flow::Break => break Flow::Next,
+            flow => break flow,
+          }
+
+          if !self.eval(condition)?.to_bool()? {
+            break Flow::Next;
+          }
+        },
+
+        ScopeInstr::Loop { scope } => loop {
+          match self.exec_scope(scope)? {
+            Flow::Next | Flow::Continue => (),
+            Flow::Break => break Flow::Next,
+            flow => break flow,
+          }
+        },
+
+        ScopeInstr::MutateVar { var, expr } => {
+          let value = self.eval(expr)?;
+          self.assign(var, value)?;
+          Flow::Next
+        }
+
+        ScopeInstr::Call(fun, args) => {
+          let args = args
+            .iter()
+            .map(|arg| self.eval(arg))
+            .collect::<Result<_, _>>()?;
+          self.call(fun, args)?;
+          Flow::Next
+        }
+
+        ScopeInstr::Switch { expr, cases } => {
+          let value = self.eval(expr)?;
+          let mut start = None;
+
+          for (i, (label, _)) in cases.iter().enumerate() {
+            match label {
+              Some(label) if self.eval(label)? == value => {
+                start = Some(i);
+                break;
+              }
+              None if start.is_none() => start = Some(i),
+              _ => (),
+            }
+          }
+
+          let mut flow = Flow::Next;
+
+          if let Some(start) = start {
+            for (_, scope) in &cases[start..] {
+              match self.exec_scope(scope)? {
+                Flow::Next => (),
+                Flow::Break => break,
+                f => {
+                  flow = f;
+                  break;
+                }
+              }
+            }
+          }
+
+          flow
+        }
+      };
+
+      if let Flow::Next = flow {
+        continue;
+      }
+
+      return Ok(flow);
+    }
+
+    Ok(Flow::Next)
+  }
+
+  fn exec_if(&mut self, cond: bool, scope: &ErasedScope) -> Result<Flow, EvalError> {
+    if cond {
+      self.exec_scope(scope)
+    } else {
+      Ok(Flow::Next)
+    }
+  }
+
+  fn var(&mut self, handle: &ScopedHandle) -> Result<Value, EvalError> {
+    let value = match handle {
+      ScopedHandle::FunArg(_) | ScopedHandle::FunVar { .. } => {
+        self.frames.last().and_then(|frame| frame.get(handle))
+      }
+      _ => self.globals.get(handle),
+    };
+
+    if let Some(value) = value {
+      return Ok(value.clone());
+    }
+
+    match handle {
+      ScopedHandle::Input(name) => Err(EvalError::MissingInput(name.clone())),
+      ScopedHandle::Uniform(name) => Err(EvalError::MissingUniform(name.clone())),
+
+      ScopedHandle::BuiltIn(builtin) => {
+        let ty = TypeEnv::default()
+          .infer(&ErasedExpr::new_builtin(*builtin))
+          .ok_or_else(|| EvalError::Unsupported(format!("built-in {:?}", builtin)))?;
+        let value = Value::zero(&ty);
+        self.globals.insert(handle.clone(), value.clone());
+        Ok(value)
+      }
+
+      _ => Err(EvalError::Unsupported(format!(
+        "unknown variable {:?}",
+        handle
+      ))),
+    }
+  }
+
+  fn set_var(&mut self, handle: &ScopedHandle, value: Value) {
+    let vars = match handle {
+      ScopedHandle::FunArg(_) | ScopedHandle::FunVar { .. } => self.frames.last_mut(),
+      _ => None,
+    };
+
+    vars
+      .unwrap_or(&mut self.globals)
+      .insert(handle.clone(), value);
+  }
+
+  /// Assign `value` to the l-value `var`.
+  fn assign(&mut self, var: &ErasedExpr, value: Value) -> Result<(), EvalError> {
+    match var {
+      ErasedExpr::Var(handle) => {
+        self.set_var(handle, value);
+        Ok(())
+      }
+
+      ErasedExpr::Swizzle(object, sw) => {
+        let mut object_value = self.eval(object)?;
+        object_value.set_swizzle(sw, value)?;
+        self.assign(object, object_value)
+      }
+
+      ErasedExpr::ArrayLookup { object, index } => {
+        let index = self.eval(index)?.to_index()?;
+        let mut object_value = self.eval(object)?;
+        object_value.set(index, value)?;
+        self.assign(object, object_value)
+      }
+
+      _ => Err(EvalError::Unsupported(format!("assignment to {:?}", var))),
+    }
+  }
+
+  fn eval(&mut self, expr: &ErasedExpr) -> Result<Value, EvalError> {
+    let value = match expr {
+      ErasedExpr::LitInt(a) => Value::Int(vec![*a]),
+      ErasedExpr::LitUInt(a) => Value::UInt(vec![*a]),
+      ErasedExpr::LitFloat(a) => Value::Float(vec![*a]),
+      ErasedExpr::LitBool(a) => Value::Bool(vec![*a]),
+      ErasedExpr::LitInt2(a) => Value::Int(a.to_vec()),
+      ErasedExpr::LitUInt2(a) => Value::UInt(a.to_vec()),
+      ErasedExpr::LitFloat2(a) => Value::Float(a.to_vec()),
+      ErasedExpr::LitBool2(a) => Value::Bool(a.to_vec()),
+      ErasedExpr::LitInt3(a) => Value::Int(a.to_vec()),
+      ErasedExpr::LitUInt3(a) => Value::UInt(a.to_vec()),
+      ErasedExpr::LitFloat3(a) => Value::Float(a.to_vec()),
+      ErasedExpr::LitBool3(a) => Value::Bool(a.to_vec()),
+      ErasedExpr::LitInt4(a) => Value::Int(a.to_vec()),
+      ErasedExpr::LitUInt4(a) => Value::UInt(a.to_vec()),
+      ErasedExpr::LitFloat4(a) => Value::Float(a.to_vec()),
+      ErasedExpr::LitBool4(a) => Value::Bool(a.to_vec()),
+      ErasedExpr::LitM22(a) => a.clone().into(),
+      ErasedExpr::LitM23(a) => a.clone().into(),
+      ErasedExpr::LitM24(a) => a.clone().into(),
+      ErasedExpr::LitM32(a) => a.clone().into(),
+      ErasedExpr::LitM33(a) => a.clone().into(),
+      ErasedExpr::LitM34(a) => a.clone().into(),
+      ErasedExpr::LitM42(a) => a.clone().into(),
+      ErasedExpr::LitM43(a) => a.clone().into(),
+      ErasedExpr::LitM44(a) => a.clone().into(),
+
+      ErasedExpr::Array(_, items) => Value::Array(
+        items
+          .iter()
+          .map(|item| self.eval(item))
+          .collect::<Result<_, _>>()?,
+      ),
+
+      ErasedExpr::Var(handle) => self.var(handle)?,
+
+      ErasedExpr::Not(a) => match self.eval(a)? {
+        Value::Bool(a) => Value::Bool(a.iter().map(|a| !a).collect()),
+        _ => return Err(mismatch()),
+      },
+
+      ErasedExpr::And(a, b) => {
+        let a = self.eval(a)?.to_bool()?;
+        Value::Bool(vec![a && self.eval(b)?.to_bool()?])
+      }
+
+      ErasedExpr::Or(a, b) => {
+        let a = self.eval(a)?.to_bool()?;
+        Value::Bool(vec![a || self.eval(b)?.to_bool()?])
+      }
+
+      ErasedExpr::Xor(a, b) => {
+        let a = self.eval(a)?.to_bool()?;
+        Value::Bool(vec![a != self.eval(b)?.to_bool()?])
+      }
+
+      ErasedExpr::BitOr(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        bitwise(a, b, |a, b| a | b, |a, b| a | b)?
+      }
+
+      ErasedExpr::BitAnd(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        bitwise(a, b, |a, b| a & b, |a, b| a & b)?
+      }
+
+      ErasedExpr::BitXor(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        bitwise(a, b, |a, b| a ^ b, |a, b| a ^ b)?
+      }
+
+      ErasedExpr::Neg(a) => match self.eval(a)? {
+        Value::Int(a) => Value::Int(a.iter().map(|a| a.wrapping_neg()).collect()),
+        Value::Float(a) => Value::Float(a.iter().map(|a| -a).collect()),
+        Value::Matrix(a) => Value::Matrix(
+          a.iter()
+            .map(|col| col.iter().map(|a| -a).collect())
+            .collect(),
+        ),
+        _ => return Err(mismatch()),
+      },
+
+      ErasedExpr::Add(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        arith(
+          a,
+          b,
+          |a, b| Ok(a.wrapping_add(b)),
+          |a, b| Ok(a.wrapping_add(b)),
+          |a, b| a + b,
+        )?
+      }
+
+      ErasedExpr::Sub(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        arith(
+          a,
+          b,
+          |a, b| Ok(a.wrapping_sub(b)),
+          |a, b| Ok(a.wrapping_sub(b)),
+          |a, b| a - b,
+        )?
+      }
+
+      ErasedExpr::Mul(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        mul(a, b)?
+      }
+
+      ErasedExpr::Div(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        arith(
+          a,
+          b,
+          |a, b| a.checked_div(b).ok_or(EvalError::DivisionByZero),
+          |a, b| a.checked_div(b).ok_or(EvalError::DivisionByZero),
+          |a, b| a / b,
+        )?
+      }
+
+      ErasedExpr::Rem(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        arith(
+          a,
+          b,
+          |a, b| a.checked_rem(b).ok_or(EvalError::DivisionByZero),
+          |a, b| a.checked_rem(b).ok_or(EvalError::DivisionByZero),
+          |a, b| a - b * (a / b).floor(),
+        )?
+      }
+
+      ErasedExpr::Shl(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        shift(a, b, i32::wrapping_shl, u32::wrapping_shl)?
+      }
+
+      ErasedExpr::Shr(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        shift(a, b, i32::wrapping_shr, u32::wrapping_shr)?
+      }
+
+      ErasedExpr::Eq(a, b) => Value::Bool(vec![self.eval(a)? == self.eval(b)?]),
+      ErasedExpr::Neq(a, b) => Value::Bool(vec![self.eval(a)? != self.eval(b)?]),
+
+      ErasedExpr::Lt(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        relational(a, b, |ord| ord == Some(Ordering::Less))?
+      }
+
+      ErasedExpr::Lte(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        relational(a, b, |ord| {
+          matches!(ord, Some(Ordering::Less | Ordering::Equal))
+        })?
+      }
+
+      ErasedExpr::Gt(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        relational(a, b, |ord| ord == Some(Ordering::Greater))?
+      }
+
+      ErasedExpr::Gte(a, b) => {
+        let (a, b) = (self.eval(a)?, self.eval(b)?);
+        relational(a, b, |ord| {
+          matches!(ord, Some(Ordering::Greater | Ordering::Equal))
+        })?
+      }
+
+      ErasedExpr::FunCall(fun, args) => {
+        let args = args
+          .iter()
+          .map(|arg| self.eval(arg))
+          .collect::<Result<_, _>>()?;
+
+        self
+          .call(fun, args)?
+          .ok_or_else(|| EvalError::Unsupported(format!("{:?} doesn’t return a value", fun)))?
+      }
+
+      ErasedExpr::Swizzle(a, sw) => self.eval(a)?.swizzle(sw)?,
+
+      ErasedExpr::ArrayLookup { object, index } => {
+        let object = self.eval(object)?;
+        object.get(self.eval(index)?.to_index()?)?
+      }
+
+      ErasedExpr::ArrayLength(a) => Value::Int(vec![self.eval(a)?.len() as i32]),
+
+      ErasedExpr::Cast(ty, a) => {
+        if !ty.array_dims.is_empty() {
+          return Err(EvalError::Unsupported("array casts".to_owned()));
+        }
+
+        construct(&[self.eval(a)?], &ty.prim_ty)?
+      }
+
+      ErasedExpr::Field { .. } => {
+        return Err(EvalError::Unsupported("field expressions".to_owned()))
+      }
+    };
+
+    Ok(value)
+  }
+
+  /// Call a function.
+  ///
+  /// Return [`None`] if the function doesn’t return anything.
+  fn call(&mut self, fun: &ErasedFunHandle, args: Vec<Value>) -> Result<Option<Value>, EvalError> {
+    let arg = |i: usize| args.get(i).cloned().ok_or_else(mismatch);
+    let floats = |i: usize| arg(i)?.to_floats();
+
+    let value = match fun {
+      ErasedFunHandle::UserDefined(handle) => {
+        let fun = *self
+          .funs
+          .get(handle)
+          .ok_or_else(|| EvalError::Unsupported(format!("unknown function {}", handle)))?;
+        return self.call_fun(fun, args);
+      }
+
+      ErasedFunHandle::Vec2 | ErasedFunHandle::Vec3 | ErasedFunHandle::Vec4 => {
+        let dim = match fun {
+          ErasedFunHandle::Vec2 => Dim::D2,
+          ErasedFunHandle::Vec3 => Dim::D3,
+          _ => Dim::D4,
+        };
+
+        construct(&args, &arg(0)?.prim_ty(dim)?)?
+      }
+
+      ErasedFunHandle::Radians => map_float(arg(0)?, f32::to_radians)?,
+      ErasedFunHandle::Degrees => map_float(arg(0)?, f32::to_degrees)?,
+      ErasedFunHandle::Sin => map_float(arg(0)?, f32::sin)?,
+      ErasedFunHandle::Cos => map_float(arg(0)?, f32::cos)?,
+      ErasedFunHandle::Tan => map_float(arg(0)?, f32::tan)?,
+      ErasedFunHandle::ASin => map_float(arg(0)?, f32::asin)?,
+      ErasedFunHandle::ACos => map_float(arg(0)?, f32::acos)?,
+      ErasedFunHandle::ATan if args.len() == 2 => zip_float(arg(0)?, arg(1)?, f32::atan2)?,
+      ErasedFunHandle::ATan => map_float(arg(0)?, f32::atan)?,
+      ErasedFunHandle::SinH => map_float(arg(0)?, f32::sinh)?,
+      ErasedFunHandle::CosH => map_float(arg(0)?, f32::cosh)?,
+      ErasedFunHandle::TanH => map_float(arg(0)?, f32::tanh)?,
+      ErasedFunHandle::ASinH => map_float(arg(0)?, f32::asinh)?,
+      ErasedFunHandle::ACosH => map_float(arg(0)?, f32::acosh)?,
+      ErasedFunHandle::ATanH => map_float(arg(0)?, f32::atanh)?,
+
+      ErasedFunHandle::Pow => zip_float(arg(0)?, arg(1)?, f32::powf)?,
+      ErasedFunHandle::Exp => map_float(arg(0)?, f32::exp)?,
+      ErasedFunHandle::Exp2 => map_float(arg(0)?, f32::exp2)?,
+      ErasedFunHandle::Log => map_float(arg(0)?, f32::ln)?,
+      ErasedFunHandle::Log2 => map_float(arg(0)?, f32::log2)?,
+      ErasedFunHandle::Sqrt => map_float(arg(0)?, f32::sqrt)?,
+      ErasedFunHandle::InverseSqrt => map_float(arg(0)?, |a| a.sqrt().recip())?,
+
+      ErasedFunHandle::Abs => match arg(0)? {
+        Value::Int(a) => Value::Int(a.iter().map(|a| a.wrapping_abs()).collect()),
+        a => map_float(a, f32::abs)?,
+      },
+
+      ErasedFunHandle::Sign => match arg(0)? {
+        Value::Int(a) => Value::Int(a.iter().map(|a| a.signum()).collect()),
+        a => map_float(a, |a| if a == 0. { 0. } else { a.signum() })?,
+      },
+
+      ErasedFunHandle::Floor => map_float(arg(0)?, f32::floor)?,
+      ErasedFunHandle::Trunc => map_float(arg(0)?, f32::trunc)?,
+      ErasedFunHandle::Round => map_float(arg(0)?, f32::round)?,
+      ErasedFunHandle::RoundEven => map_float(arg(0)?, f32::round_ties_even)?,
+      ErasedFunHandle::Ceil => map_float(arg(0)?, f32::ceil)?,
+      ErasedFunHandle::Fract => map_float(arg(0)?, |a| a - a.floor())?,
+      ErasedFunHandle::Mod => zip_float(arg(0)?, arg(1)?, |a, b| a - b * (a / b).floor())?,
+      ErasedFunHandle::Min => min(arg(0)?, arg(1)?)?,
+      ErasedFunHandle::Max => max(arg(0)?, arg(1)?)?,
+      ErasedFunHandle::Clamp => min(max(arg(0)?, arg(1)?)?, arg(2)?)?,
+
+      ErasedFunHandle::Mix => match arg(2)? {
+        // component-wise selection
+        Value::Bool(selector) => {
+          let (mut x, y) = (arg(0)?, arg(1)?);
+
+          if x.len() != selector.len() || y.len() != selector.len() {
+            return Err(mismatch());
+          }
+
+          for (i, &pick_y) in selector.iter().enumerate() {
+            if pick_y {
+              x.set(i as i64, y.get(i as i64)?)?;
+            }
+          }
+
+          x
+        }
+
+        a => Value::Float(zip3(
+          &floats(0)?,
+          &floats(1)?,
+          &a.to_floats()?,
+          |x, y, a| x * (1. - a) + y * a,
+        )?),
+      },
+
+      ErasedFunHandle::Step => {
+        zip_float(arg(0)?, arg(1)?, |edge, x| if x < edge { 0. } else { 1. })?
+      }
+
+      ErasedFunHandle::SmoothStep => Value::Float(zip3(
+        &floats(0)?,
+        &floats(1)?,
+        &floats(2)?,
+        |edge0, edge1, x| {
+          let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+          t * t * (3. - 2. * t)
+        },
+      )?),
+
+      ErasedFunHandle::IsNan => Value::Bool(floats(0)?.iter().map(|a| a.is_nan()).collect()),
+      ErasedFunHandle::IsInf => Value::Bool(floats(0)?.iter().map(|a| a.is_infinite()).collect()),
+
+      ErasedFunHandle::FloatBitsToInt => {
+        Value::Int(floats(0)?.iter().map(|a| a.to_bits() as i32).collect())
+      }
+
+      ErasedFunHandle::IntBitsToFloat => match arg(0)? {
+        Value::Int(a) => Value::Float(a.iter().map(|&a| f32::from_bits(a as u32)).collect()),
+        _ => return Err(mismatch()),
+      },
+
+      ErasedFunHandle::UIntBitsToFloat => match arg(0)? {
+        Value::UInt(a) => Value::Float(a.iter().map(|&a| f32::from_bits(a)).collect()),
+        _ => return Err(mismatch()),
+      },
+
+      ErasedFunHandle::Fma => {
+        Value::Float(zip3(&floats(0)?, &floats(1)?, &floats(2)?, |a, b, c| {
+          a.mul_add(b, c)
+        })?)
+      }
+
+      ErasedFunHandle::Length => Value::Float(vec![length(&floats(0)?)]),
+      ErasedFunHandle::Distance => {
+        let d = zip(&floats(0)?, &floats(1)?, |a, b| Ok(a - b))?;
+        Value::Float(vec![length(&d)])
+      }
+      ErasedFunHandle::Dot => Value::Float(vec![dot(&floats(0)?, &floats(1)?)?]),
+
+      ErasedFunHandle::Cross => match (&floats(0)?[..], &floats(1)?[..]) {
+        (&[ax, ay, az], &[bx, by, bz]) => Value::Float(vec![
+          ay * bz - az * by,
+          az * bx - ax * bz,
+          ax * by - ay * bx,
+        ]),
+        _ => return Err(mismatch()),
+      },
+
+      ErasedFunHandle::Normalize => {
+        let a = floats(0)?;
+        let len = length(&a);
+        Value::Float(a.iter().map(|a| a / len).collect())
+      }
+
+      ErasedFunHandle::FaceForward => {
+        let (n, i, n_ref) = (floats(0)?, floats(1)?, floats(2)?);
+
+        if dot(&n_ref, &i)? < 0. {
+          Value::Float(n)
+        } else {
+          Value::Float(n.iter().map(|a| -a).collect())
+        }
+      }
+
+      ErasedFunHandle::Reflect => {
+        let (i, n) = (floats(0)?, floats(1)?);
+        let d = 2. * dot(&n, &i)?;
+        Value::Float(zip(&i, &n, |i, n| Ok(i - d * n))?)
+      }
+
+      ErasedFunHandle::Refract => {
+        let (i, n, eta) = (floats(0)?, floats(1)?, floats(2)?);
+        let eta = *eta.first().ok_or_else(mismatch)?;
+        let d = dot(&n, &i)?;
+        let k = 1. - eta * eta * (1. - d * d);
+
+        if k < 0. {
+          Value::Float(vec![0.; i.len()])
+        } else {
+          Value::Float(zip(&i, &n, |i, n| Ok(eta * i - (eta * d + k.sqrt()) * n))?)
+        }
+      }
+
+      ErasedFunHandle::VLt => relational(arg(0)?, arg(1)?, |ord| ord == Some(Ordering::Less))?,
+      ErasedFunHandle::VLte => relational(arg(0)?, arg(1)?, |ord| {
+        matches!(ord, Some(Ordering::Less | Ordering::Equal))
+      })?,
+      ErasedFunHandle::VGt => relational(arg(0)?, arg(1)?, |ord| ord == Some(Ordering::Greater))?,
+      ErasedFunHandle::VGte => relational(arg(0)?, arg(1)?, |ord| {
+        matches!(ord, Some(Ordering::Greater | Ordering::Equal))
+      })?,
+      ErasedFunHandle::VEq => relational(arg(0)?, arg(1)?, |ord| ord == Some(Ordering::Equal))?,
+      ErasedFunHandle::VNeq => relational(arg(0)?, arg(1)?, |ord| ord != Some(Ordering::Equal))?,
+
+      ErasedFunHandle::VAny | ErasedFunHandle::VAll | ErasedFunHandle::VNot => match arg(0)? {
+        Value::Bool(a) => match fun {
+          ErasedFunHandle::VAny => Value::Bool(vec![a.iter().any(|&a| a)]),
+          ErasedFunHandle::VAll => Value::Bool(vec![a.iter().all(|&a| a)]),
+          _ => Value::Bool(a.iter().map(|a| !a).collect()),
+        },
+        _ => return Err(mismatch()),
+      },
+
+      ErasedFunHandle::BitCount => match arg(0)? {
+        Value::Int(a) => Value::Int(a.iter().map(|a| a.count_ones() as i32).collect()),
+        Value::UInt(a) => Value::Int(a.iter().map(|a| a.count_ones() as i32).collect()),
+        _ => return Err(mismatch()),
+      },
+
+      ErasedFunHandle::BitfieldReverse => match arg(0)? {
+        Value::Int(a) => Value::Int(a.iter().map(|a| a.reverse_bits()).collect()),
+        Value::UInt(a) => Value::UInt(a.iter().map(|a| a.reverse_bits()).collect()),
+        _ => return Err(mismatch()),
+      },
+
+      ErasedFunHandle::Barrier
+      | ErasedFunHandle::MemoryBarrier
+      | ErasedFunHandle::MemoryBarrierAtomic
+      | ErasedFunHandle::MemoryBarrierBuffer
+      | ErasedFunHandle::MemoryBarrierShared
+      | ErasedFunHandle::MemoryBarrierImage
+      | ErasedFunHandle::GroupMemoryBarrier
+      | ErasedFunHandle::SubgroupBarrier => return Ok(None),
+
+      // there is only one invocation
+      ErasedFunHandle::SubgroupElect | ErasedFunHandle::AllInvocationsEqual => {
+        Value::Bool(vec![true])
+      }
+
+      ErasedFunHandle::AnyInvocation
+      | ErasedFunHandle::AllInvocations
+      | ErasedFunHandle::SubgroupAll
+      | ErasedFunHandle::SubgroupAny
+      | ErasedFunHandle::SubgroupBroadcastFirst
+      | ErasedFunHandle::SubgroupShuffle
+      | ErasedFunHandle::SubgroupAdd
+      | ErasedFunHandle::SubgroupMul
+      | ErasedFunHandle::SubgroupMin
+      | ErasedFunHandle::SubgroupMax => arg(0)?,
+
+      _ => return Err(EvalError::Unsupported(format!("function {:?}", fun))),
+    };
+
+    Ok(Some(value))
+  }
+}
+
+fn dim_len(dim: &Dim) -> usize {
+  match dim {
+    Dim::Scalar => 1,
+    Dim::D2 => 2,
+    Dim::D3 => 3,
+    Dim::D4 => 4,
+  }
+}
+
+fn swizzle_indices(sw: &Swizzle) -> Vec<usize> {
+  let index = |sel: &SwizzleSelector| match sel {
+    SwizzleSelector::X => 0,
+    SwizzleSelector::Y => 1,
+    SwizzleSelector::Z => 2,
+    SwizzleSelector::W => 3,
+  };
+
+  match sw {
+    Swizzle::D1(x) => vec![index(x)],
+    Swizzle::D2(x, y) => vec![index(x), index(y)],
+    Swizzle::D3(x, y, z) => vec![index(x), index(y), index(z)],
+    Swizzle::D4(x, y, z, w) => vec![index(x), index(y), index(z), index(w)],
+  }
+}
+
+/// Component `i` of `a`, broadcasting scalars.
+fn component<T: Copy>(a: &[T], i: usize) -> T {
+  a[if a.len() == 1 { 0 } else { i }]
+}
+
+/// Number of components of the result of a component-wise operation, where scalar operands are broadcast.
+fn broadcast_len(lens: &[usize]) -> Result<usize, EvalError> {
+  let len = lens.iter().copied().max().unwrap_or(0);
+
+  if lens.iter().all(|&l| l == len || l == 1) {
+    Ok(len)
+  } else {
+    Err(mismatch())
+  }
+}
+
+fn zip<T: Copy, U>(
+  a: &[T],
+  b: &[T],
+  f: impl Fn(T, T) -> Result<U, EvalError>,
+) -> Result<Vec<U>, EvalError> {
+  let len = broadcast_len(&[a.len(), b.len()])?;
+  (0..len)
+    .map(|i| f(component(a, i), component(b, i)))
+    .collect()
+}
+
+fn zip3(
+  a: &[f32],
+  b: &[f32],
+  c: &[f32],
+  f: impl Fn(f32, f32, f32) -> f32,
+) -> Result<Vec<f32>, EvalError> {
+  let len = broadcast_len(&[a.len(), b.len(), c.len()])?;
+  Ok(
+    (0..len)
+      .map(|i| f(component(a, i), component(b, i), component(c, i)))
+      .collect(),
+  )
+}
+
+fn map_float(a: Value, f: impl Fn(f32) -> f32) -> Result<Value, EvalError> {
+  Ok(Value::Float(a.to_floats()?.into_iter().map(f).collect()))
+}
+
+fn zip_float(a: Value, b: Value, f: impl Fn(f32, f32) -> f32) -> Result<Value, EvalError> {
+  zip(&a.to_floats()?, &b.to_floats()?, |a, b| Ok(f(a, b))).map(Value::Float)
+}
+
+fn dot(a: &[f32], b: &[f32]) -> Result<f32, EvalError> {
+  if a.len() != b.len() {
+    return Err(mismatch());
+  }
+
+  Ok(a.iter().zip(b).map(|(a, b)| a * b).sum())
+}
+
+fn length(a: &[f32]) -> f32 {
+  a.iter().map(|a| a * a).sum::<f32>().sqrt()
+}
+
+/// Component-wise arithmetic operator, where matrices are treated component-wise as well.
+fn arith(
+  a: Value,
+  b: Value,
+  int: impl Fn(i32, i32) -> Result<i32, EvalError>,
+  uint: impl Fn(u32, u32) -> Result<u32, EvalError>,
+  float: impl Fn(f32, f32) -> f32,
+) -> Result<Value, EvalError> {
+  let float = |a, b| Ok(float(a, b));
+
+  match (a, b) {
+    (Value::Int(a), Value::Int(b)) => zip(&a, &b, int).map(Value::Int),
+    (Value::UInt(a), Value::UInt(b)) => zip(&a, &b, uint).map(Value::UInt),
+    (Value::Float(a), Value::Float(b)) => zip(&a, &b, float).map(Value::Float),
+
+    (Value::Matrix(a), Value::Matrix(b)) if a.len() == b.len() => a
+      .iter()
+      .zip(&b)
+      .map(|(a, b)| zip(a, b, float))
+      .collect::<Result<_, _>>()
+      .map(Value::Matrix),
+
+    (Value::Matrix(a), Value::Float(b)) if b.len() == 1 => a
+      .iter()
+      .map(|a| zip(a, &b, float))
+      .collect::<Result<_, _>>()
+      .map(Value::Matrix),
+
+    (Value::Float(a), Value::Matrix(b)) if a.len() == 1 => b
+      .iter()
+      .map(|b| zip(&a, b, float))
+      .collect::<Result<_, _>>()
+      .map(Value::Matrix),
+
+    _ => Err(mismatch()),
+  }
+}
+
+/// Multiplication, which is the linear algebraic product when matrices are involved.
+fn mul(a: Value, b: Value) -> Result<Value, EvalError> {
+  let mat_vec = |m: &[Vec<f32>], v: &[f32]| -> Result<Vec<f32>, EvalError> {
+    if m.len() != v.len() {
+      return Err(mismatch());
+    }
+
+    let rows = m.first().map_or(0, Vec::len);
+    Ok(
+      (0..rows)
+        .map(|r| m.iter().zip(v).map(|(col, v)| col[r] * v).sum())
+        .collect(),
+    )
+  };
+
+  match (a, b) {
+    (Value::Matrix(a), Value::Matrix(b)) => b
+      .iter()
+      .map(|col| mat_vec(&a, col))
+      .collect::<Result<_, _>>()
+      .map(Value::Matrix),
+
+    (Value::Matrix(m), Value::Float(v)) if v.len() > 1 => mat_vec(&m, &v).map(Value::Float),
+
+    (Value::Float(v), Value::Matrix(m)) if v.len() > 1 => m
+      .iter()
+      .map(|col| dot(&v, col))
+      .collect::<Result<_, _>>()
+      .map(Value::Float),
+
+    (a, b) => arith(
+      a,
+      b,
+      |a, b| Ok(a.wrapping_mul(b)),
+      |a, b| Ok(a.wrapping_mul(b)),
+      |a, b| a * b,
+    ),
+  }
+}
+
+fn bitwise(
+  a: Value,
+  b: Value,
+  int: impl Fn(i32, i32) -> i32,
+  uint: impl Fn(u32, u32) -> u32,
+) -> Result<Value, EvalError> {
+  match (a, b) {
+    (Value::Int(a), Value::Int(b)) => zip(&a, &b, |a, b| Ok(int(a, b))).map(Value::Int),
+    (Value::UInt(a), Value::UInt(b)) => zip(&a, &b, |a, b| Ok(uint(a, b))).map(Value::UInt),
+    _ => Err(mismatch()),
+  }
+}
+
+fn shift(
+  a: Value,
+  b: Value,
+  int: impl Fn(i32, u32) -> i32,
+  uint: impl Fn(u32, u32) -> u32,
+) -> Result<Value, EvalError> {
+  let amounts = match b {
+    Value::Int(b) => b.into_iter().map(|b| b as u32).collect(),
+    Value::UInt(b) => b,
+    _ => return Err(mismatch()),
+  };
+
+  match a {
+    Value::Int(a) => {
+      let a: Vec<_> = a.into_iter().map(|a| a as u32).collect();
+      zip(&a, &amounts, |a, b| Ok(int(a as i32, b))).map(Value::Int)
+    }
+    Value::UInt(a) => zip(&a, &amounts, |a, b| Ok(uint(a, b))).map(Value::UInt),
+    _ => Err(mismatch()),
+  }
+}
+
+/// Component-wise comparison.
+fn relational(
+  a: Value,
+  b: Value,
+  f: impl Fn(Option<Ordering>) -> bool,
+) -> Result<Value, EvalError> {
+  let bools = match (a, b) {
+    (Value::Int(a), Value::Int(b)) => zip(&a, &b, |a, b| Ok(f(a.partial_cmp(&b)))),
+    (Value::UInt(a), Value::UInt(b)) => zip(&a, &b, |a, b| Ok(f(a.partial_cmp(&b)))),
+    (Value::Float(a), Value::Float(b)) => zip(&a, &b, |a, b| Ok(f(a.partial_cmp(&b)))),
+    (Value::Bool(a), Value::Bool(b)) => zip(&a, &b, |a, b| Ok(f(a.partial_cmp(&b)))),
+    _ => Err(mismatch()),
+  }?;
+
+  Ok(Value::Bool(bools))
+}
+
+fn min(a: Value, b: Value) -> Result<Value, EvalError> {
+  arith(a, b, |a, b| Ok(a.min(b)), |a, b| Ok(a.min(b)), f32::min)
+}
+
+fn max(a: Value, b: Value) -> Result<Value, EvalError> {
+  arith(a, b, |a, b| Ok(a.max(b)), |a, b| Ok(a.max(b)), f32::max)
+}
+
+/// Construct a value of type `prim_ty` out of the components of `args`, converting them if needed.
+///
+/// This is used by casts and vector constructors; a single scalar is broadcast to all the components, and extra
+/// components are dropped.
+fn construct(args: &[Value], prim_ty: &PrimType) -> Result<Value, EvalError> {
+  fn components<T>(
+    args: &[Value],
+    len: usize,
+    f: impl Fn(&Value) -> Result<Vec<T>, EvalError>,
+  ) -> Result<Vec<T>, EvalError>
+  where
+    T: Clone,
+  {
+    let mut components = Vec::new();
+
+    for arg in args {
+      components.extend(f(arg)?);
+    }
+
+    match components.len() {
+      1 => Ok(vec![components[0].clone(); len]),
+      n if n >= len => {
+        components.truncate(len);
+        Ok(components)
+      }
+      _ => Err(mismatch()),
+    }
+  }
+
+  let value = match prim_ty {
+    PrimType::Int(dim) => Value::Int(components(args, dim_len(dim), |arg| match arg {
+      Value::Int(a) => Ok(a.clone()),
+      Value::UInt(a) => Ok(a.iter().map(|&a| a as i32).collect()),
+      Value::Float(a) => Ok(a.iter().map(|&a| a as i32).collect()),
+      Value::Bool(a) => Ok(a.iter().map(|&a| a as i32).collect()),
+      _ => Err(mismatch()),
+    })?),
+
+    PrimType::UInt(dim) => Value::UInt(components(args, dim_len(dim), |arg| match arg {
+      Value::Int(a) => Ok(a.iter().map(|&a| a as u32).collect()),
+      Value::UInt(a) => Ok(a.clone()),
+      Value::Float(a) => Ok(a.iter().map(|&a| a as u32).collect()),
+      Value::Bool(a) => Ok(a.iter().map(|&a| a as u32).collect()),
+      _ => Err(mismatch()),
+    })?),
+
+    PrimType::Float(dim) => Value::Float(components(args, dim_len(dim), |arg| match arg {
+      Value::Int(a) => Ok(a.iter().map(|&a| a as f32).collect()),
+      Value::UInt(a) => Ok(a.iter().map(|&a| a as f32).collect()),
+      Value::Float(a) => Ok(a.clone()),
+      Value::Bool(a) => Ok(a.iter().map(|&a| a as u8 as f32).collect()),
+      _ => Err(mismatch()),
+    })?),
+
+    PrimType::Bool(dim) => Value::Bool(components(args, dim_len(dim), |arg| match arg {
+      Value::Int(a) => Ok(a.iter().map(|&a| a != 0).collect()),
+      Value::UInt(a) => Ok(a.iter().map(|&a| a != 0).collect()),
+      Value::Float(a) => Ok(a.iter().map(|&a| a != 0.).collect()),
+      Value::Bool(a) => Ok(a.clone()),
+      _ => Err(mismatch()),
+    })?),
+
+    PrimType::Matrix(_) => match args {
+      [a @ Value::Matrix(_)] => a.clone(),
+      _ => return Err(EvalError::Unsupported("matrix constructors".to_owned())),
+    },
+  };
+
+  Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    inputs, lit, outputs, sw, CanEscape as _, Expr, LoopScope, Scope, ShaderBuilder,
+    Swizzlable as _, VectorRelational as _, M22,
+  };
+
+  #[test]
+  fn control_flow() {
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, n: i32);
+      outputs!(s, sum: i32, color: V4<f32>);
+
+      let square = s.fun(|_: &mut Scope<Expr<i32>>, a: Expr<i32>| &a * &a);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        // sum of the squares below n, except 2²
+        s.loop_for(
+          0,
+          |i| i.lt(&n),
+          |i| i + 1,
+          |s: &mut LoopScope<()>, i| {
+            s.when(i.eq(2), |s| s.loop_continue());
+            s.set(&sum, &sum + square.call(i.clone()));
+          },
+        );
+
+        let m: Expr<M22> = Matrix::from([[0., 1.], [1., 0.]]).into();
+        let v = s.var(m * lit!(1., 2.));
+
+        s.switch_on(&n)
+          .case(3, |s| s.set(sw!(color, .x.y), &v))
+          .default(|s| s.set(sw!(color, .z.w), &v));
+      })
+    });
+
+    let outputs = Eval::new(&shader).input("n", 4).run().unwrap();
+    assert_eq!(outputs.get("sum"), Some(&Value::Int(vec![10])));
+    assert_eq!(
+      outputs.get("color"),
+      Some(&Value::Float(vec![0., 0., 2., 1.]))
+    );
+
+    let outputs = Eval::new(&shader).input("n", 3).run().unwrap();
+    assert_eq!(outputs.get("sum"), Some(&Value::Int(vec![1])));
+    assert_eq!(
+      outputs.get("color"),
+      Some(&Value::Float(vec![2., 1., 0., 0.]))
+    );
+  }
+
+  #[test]
+  fn select() {
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, x: V3<i32>);
+      outputs!(s, clamped: V3<i32>);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        s.set(
+          &clamped,
+          x.to_expr()
+            .less_than(lit!(0, 0, 0))
+            .select(lit!(0, 0, 0), &x),
+        );
+      })
+    });
+
+    let outputs = Eval::new(&shader)
+      .input("x", V3::from([-1, 2, -3]))
+      .run()
+      .unwrap();
+    assert_eq!(outputs.get("clamped"), Some(&Value::Int(vec![0, 2, 0])));
+  }
+
+  #[test]
+  fn float_remainder() {
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, x: f32);
+      outputs!(s, rem: f32);
+
+      s.main_fun(|s: &mut Scope<()>| s.set(&rem, &x % 3.))
+    });
+
+    // same as GLSL’s mod, which takes the sign of the divisor
+    let outputs = Eval::new(&shader).input("x", -1.).run().unwrap();
+    assert_eq!(outputs.get("rem"), Some(&Value::Float(vec![2.])));
+  }
+
+  #[test]
+  fn errors() {
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, n: i32);
+      outputs!(s, x: i32);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        s.set(&x, lit!(1) / &n);
+        s.set(&x, lit!([1, 2, 3]).at(&n));
+      })
+    });
+
+    assert_eq!(
+      Eval::new(&shader).run(),
+      Err(EvalError::MissingInput("n".to_owned()))
+    );
+    assert_eq!(
+      Eval::new(&shader).input("n", 0).run(),
+      Err(EvalError::DivisionByZero)
+    );
+    assert_eq!(
+      Eval::new(&shader).input("n", 3).run(),
+      Err(EvalError::OutOfBounds { index: 3, len: 3 })
+    );
+  }
+}
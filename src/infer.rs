@@ -0,0 +1,522 @@
+//! Type inference of erased expressions.
+//!
+//! Typed expressions carry their type at the Rust level only; once erased, the type of an expression has to be
+//! recomputed from its leaves. This is needed by passes generating new declarations, such as
+//! [`pass::eliminate_common_subexpressions`](crate::pass::eliminate_common_subexpressions).
+
+use crate::{
+  BuiltIn, ComputeBuiltIn, Dim, ErasedExpr, ErasedFun, ErasedFunHandle, ErasedReturn,
+  FragmentBuiltIn, GeometryBuiltIn, MatrixDim, PrimType, ScopeInstr, ScopedHandle, ShaderDecl,
+  SubgroupBuiltIn, Swizzle, TessCtrlBuiltIn, TessEvalBuiltIn, Type, VertexBuiltIn,
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// Types inferred for expression nodes, by address.
+pub(crate) type Types = HashMap<*const ErasedExpr, Option<Type>>;
+
+/// Types of the variables visible at a given point of a shader.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TypeEnv {
+  vars: HashMap<ScopedHandle, Type>,
+  // return types of the user-defined functions returning something
+  funs: HashMap<u16, Type>,
+}
+
+impl TypeEnv {
+  /// Environment made of the top-level declarations of a shader: constants, inputs, outputs, uniforms and functions.
+  pub(crate) fn new(decls: &[ShaderDecl]) -> Self {
+    let mut env = Self::default();
+
+    for decl in decls {
+      match decl {
+        ShaderDecl::Const(handle, ty, _) | ShaderDecl::Shared(handle, ty) => {
+          env.insert(ScopedHandle::global(*handle), ty.clone())
+        }
+        ShaderDecl::In(name, ty) | ShaderDecl::PatchIn(name, ty) => {
+          env.insert(ScopedHandle::Input(name.clone()), ty.clone())
+        }
+        ShaderDecl::Out(name, ty)
+        | ShaderDecl::PatchOut(name, ty)
+        | ShaderDecl::LayoutOut(name, ty, _) => {
+          env.insert(ScopedHandle::Output(name.clone()), ty.clone())
+        }
+        ShaderDecl::Uniform(name, ty) => {
+          env.insert(ScopedHandle::uniform(name.clone()), ty.clone())
+        }
+        ShaderDecl::FunDef(handle, fun) => {
+          if let ErasedReturn::Expr(ref ty, _) = fun.ret {
+            env.funs.insert(*handle, ty.clone());
+          }
+        }
+        ShaderDecl::Main(_) | ShaderDecl::Comment(_) => (),
+      }
+    }
+
+    env
+  }
+
+  /// Environment of the body of `fun`, which sees its arguments.
+  pub(crate) fn with_fun_args(&self, fun: &ErasedFun) -> Self {
+    let mut env = self.clone();
+
+    for (i, ty) in fun.args.iter().enumerate() {
+      env.insert(ScopedHandle::fun_arg(i as u16), ty.clone());
+    }
+
+    env
+  }
+
+  /// Whether `handle` is visible in this environment.
+  pub(crate) fn contains(&self, handle: &ScopedHandle) -> bool {
+    self.vars.contains_key(handle)
+  }
+
+  pub(crate) fn insert(&mut self, handle: ScopedHandle, ty: Type) {
+    self.vars.insert(handle, ty);
+  }
+
+  /// Add the variables declared by `instr`, if any.
+  pub(crate) fn declare(&mut self, instr: &ScopeInstr) {
+    match instr {
+      ScopeInstr::VarDecl { ty, handle, .. } | ScopeInstr::VarDeclUninit { ty, handle } => {
+        self.insert(handle.clone(), ty.clone())
+      }
+
+      ScopeInstr::For {
+        init_ty,
+        init_handle,
+        ..
+      } => self.insert(init_handle.clone(), init_ty.clone()),
+
+      _ => (),
+    }
+  }
+
+  /// Infer the type of `expr`.
+  ///
+  /// [`None`] is returned if the type cannot be computed, because the expression is ill-typed or refers to something
+  /// the inference doesn’t know about — e.g. unsized built-in arrays.
+  pub(crate) fn infer(&self, expr: &ErasedExpr) -> Option<Type> {
+    self.infer_shared(expr, &mut HashMap::new())
+  }
+
+  /// Infer the type of `expr`, reusing the types in `tys` of the nodes already inferred in this environment, and
+  /// recording the ones of the nodes of `expr`.
+  ///
+  /// Shared sub-expressions are then inferred once, instead of once per path leading to them.
+  pub(crate) fn infer_shared(&self, expr: &ErasedExpr, tys: &mut Types) -> Option<Type> {
+    if let Some(ty) = tys.get(&(expr as *const _)) {
+      return ty.clone();
+    }
+
+    let ty = self.infer_node(expr, tys);
+    tys.insert(expr, ty.clone());
+    ty
+  }
+
+  fn infer_node(&self, expr: &ErasedExpr, tys: &mut Types) -> Option<Type> {
+    let prim_ty = match expr {
+      ErasedExpr::LitInt(_) => PrimType::Int(Dim::Scalar),
+      ErasedExpr::LitUInt(_) => PrimType::UInt(Dim::Scalar),
+      ErasedExpr::LitFloat(_) => PrimType::Float(Dim::Scalar),
+      ErasedExpr::LitBool(_) => PrimType::Bool(Dim::Scalar),
+      ErasedExpr::LitInt2(_) => PrimType::Int(Dim::D2),
+      ErasedExpr::LitUInt2(_) => PrimType::UInt(Dim::D2),
+      ErasedExpr::LitFloat2(_) => PrimType::Float(Dim::D2),
+      ErasedExpr::LitBool2(_) => PrimType::Bool(Dim::D2),
+      ErasedExpr::LitInt3(_) => PrimType::Int(Dim::D3),
+      ErasedExpr::LitUInt3(_) => PrimType::UInt(Dim::D3),
+      ErasedExpr::LitFloat3(_) => PrimType::Float(Dim::D3),
+      ErasedExpr::LitBool3(_) => PrimType::Bool(Dim::D3),
+      ErasedExpr::LitInt4(_) => PrimType::Int(Dim::D4),
+      ErasedExpr::LitUInt4(_) => PrimType::UInt(Dim::D4),
+      ErasedExpr::LitFloat4(_) => PrimType::Float(Dim::D4),
+      ErasedExpr::LitBool4(_) => PrimType::Bool(Dim::D4),
+      ErasedExpr::LitM22(_) => PrimType::Matrix(MatrixDim::D22),
+      ErasedExpr::LitM23(_) => PrimType::Matrix(MatrixDim::D23),
+      ErasedExpr::LitM24(_) => PrimType::Matrix(MatrixDim::D24),
+      ErasedExpr::LitM32(_) => PrimType::Matrix(MatrixDim::D32),
+      ErasedExpr::LitM33(_) => PrimType::Matrix(MatrixDim::D33),
+      ErasedExpr::LitM34(_) => PrimType::Matrix(MatrixDim::D34),
+      ErasedExpr::LitM42(_) => PrimType::Matrix(MatrixDim::D42),
+      ErasedExpr::LitM43(_) => PrimType::Matrix(MatrixDim::D43),
+      ErasedExpr::LitM44(_) => PrimType::Matrix(MatrixDim::D44),
+
+      ErasedExpr::Array(ty, _) | ErasedExpr::Cast(ty, _) => return Some(ty.clone()),
+
+      ErasedExpr::Var(ScopedHandle::BuiltIn(builtin)) => return builtin_ty(builtin),
+      ErasedExpr::Var(handle) => return self.vars.get(handle).cloned(),
+
+      ErasedExpr::Not(a) | ErasedExpr::Neg(a) => return self.infer_shared(a, tys),
+
+      ErasedExpr::And(..)
+      | ErasedExpr::Or(..)
+      | ErasedExpr::Xor(..)
+      | ErasedExpr::Eq(..)
+      | ErasedExpr::Neq(..)
+      | ErasedExpr::Lt(..)
+      | ErasedExpr::Lte(..)
+      | ErasedExpr::Gt(..)
+      | ErasedExpr::Gte(..) => PrimType::Bool(Dim::Scalar),
+
+      ErasedExpr::Shl(a, _) | ErasedExpr::Shr(a, _) => return self.infer_shared(a, tys),
+
+      ErasedExpr::Mul(a, b) => mul_ty(self.infer_prim(a, tys)?, self.infer_prim(b, tys)?)?,
+
+      ErasedExpr::BitOr(a, b)
+      | ErasedExpr::BitAnd(a, b)
+      | ErasedExpr::BitXor(a, b)
+      | ErasedExpr::Add(a, b)
+      | ErasedExpr::Sub(a, b)
+      | ErasedExpr::Div(a, b)
+      | ErasedExpr::Rem(a, b) => {
+        component_wise_ty(self.infer_prim(a, tys)?, self.infer_prim(b, tys)?)?
+      }
+
+      ErasedExpr::FunCall(fun, args) => return self.infer_fun_call(fun, args, tys),
+
+      ErasedExpr::Swizzle(a, sw) => {
+        let dim = match sw {
+          Swizzle::D1(..) => Dim::Scalar,
+          Swizzle::D2(..) => Dim::D2,
+          Swizzle::D3(..) => Dim::D3,
+          Swizzle::D4(..) => Dim::D4,
+        };
+
+        with_dim(&self.infer_prim(a, tys)?, dim)?
+      }
+
+      // fields are represented by the built-in they refer to
+      ErasedExpr::Field { field, .. } => return self.infer_shared(field, tys),
+
+      ErasedExpr::ArrayLookup { object, .. } => {
+        let ty = self.infer_shared(object, tys)?;
+
+        if let Some((_, array_dims)) = ty.array_dims.split_first() {
+          return Some(Type {
+            prim_ty: ty.prim_ty,
+            array_dims: array_dims.to_vec(),
+          });
+        }
+
+        match ty.prim_ty {
+          PrimType::Matrix(dim) => PrimType::Float(matrix_size(&dim).1),
+          ref prim_ty => with_dim(prim_ty, Dim::Scalar)?,
+        }
+      }
+
+      ErasedExpr::ArrayLength(_) => PrimType::Int(Dim::Scalar),
+    };
+
+    Some(Type {
+      prim_ty,
+      array_dims: Vec::new(),
+    })
+  }
+
+  /// Infer the type of `expr`, which must not be an array.
+  fn infer_prim(&self, expr: &ErasedExpr, tys: &mut Types) -> Option<PrimType> {
+    self
+      .infer_shared(expr, tys)
+      .filter(|ty| ty.array_dims.is_empty())
+      .map(|ty| ty.prim_ty)
+  }
+
+  fn infer_fun_call(
+    &self,
+    fun: &ErasedFunHandle,
+    args: &[Arc<ErasedExpr>],
+    tys: &mut Types,
+  ) -> Option<Type> {
+    let mut arg = |i: usize| args.get(i).and_then(|arg| self.infer_prim(arg, tys));
+
+    let prim_ty = match fun {
+      ErasedFunHandle::UserDefined(handle) => return self.funs.get(handle).cloned(),
+
+      ErasedFunHandle::Vec2 => with_dim(&arg(0)?, Dim::D2)?,
+      ErasedFunHandle::Vec3 => with_dim(&arg(0)?, Dim::D3)?,
+      ErasedFunHandle::Vec4 => with_dim(&arg(0)?, Dim::D4)?,
+
+      ErasedFunHandle::Length | ErasedFunHandle::Distance | ErasedFunHandle::Dot => {
+        with_dim(&arg(0)?, Dim::Scalar)?
+      }
+
+      ErasedFunHandle::Step => arg(1)?,
+      ErasedFunHandle::SmoothStep => arg(2)?,
+
+      ErasedFunHandle::IsNan | ErasedFunHandle::IsInf => {
+        PrimType::Bool(prim_dim(&arg(0)?)?.clone())
+      }
+
+      ErasedFunHandle::VLt
+      | ErasedFunHandle::VLte
+      | ErasedFunHandle::VGt
+      | ErasedFunHandle::VGte
+      | ErasedFunHandle::VEq
+      | ErasedFunHandle::VNeq => PrimType::Bool(prim_dim(&arg(0)?)?.clone()),
+
+      ErasedFunHandle::VAny | ErasedFunHandle::VAll => PrimType::Bool(Dim::Scalar),
+
+      ErasedFunHandle::Radians
+      | ErasedFunHandle::Degrees
+      | ErasedFunHandle::Sin
+      | ErasedFunHandle::Cos
+      | ErasedFunHandle::Tan
+      | ErasedFunHandle::ASin
+      | ErasedFunHandle::ACos
+      | ErasedFunHandle::ATan
+      | ErasedFunHandle::SinH
+      | ErasedFunHandle::CosH
+      | ErasedFunHandle::TanH
+      | ErasedFunHandle::ASinH
+      | ErasedFunHandle::ACosH
+      | ErasedFunHandle::ATanH
+      | ErasedFunHandle::Pow
+      | ErasedFunHandle::Exp
+      | ErasedFunHandle::Exp2
+      | ErasedFunHandle::Log
+      | ErasedFunHandle::Log2
+      | ErasedFunHandle::Sqrt
+      | ErasedFunHandle::InverseSqrt
+      | ErasedFunHandle::Abs
+      | ErasedFunHandle::Sign
+      | ErasedFunHandle::Floor
+      | ErasedFunHandle::Trunc
+      | ErasedFunHandle::Round
+      | ErasedFunHandle::RoundEven
+      | ErasedFunHandle::Ceil
+      | ErasedFunHandle::Fract
+      | ErasedFunHandle::Mod
+      | ErasedFunHandle::Min
+      | ErasedFunHandle::Max
+      | ErasedFunHandle::Clamp
+      | ErasedFunHandle::Mix
+      | ErasedFunHandle::Fma
+      | ErasedFunHandle::Cross
+      | ErasedFunHandle::Normalize
+      | ErasedFunHandle::FaceForward
+      | ErasedFunHandle::Reflect
+      | ErasedFunHandle::Refract
+      | ErasedFunHandle::VNot
+      | ErasedFunHandle::BitfieldExtract
+      | ErasedFunHandle::BitfieldInsert
+      | ErasedFunHandle::BitfieldReverse
+      | ErasedFunHandle::DfDx
+      | ErasedFunHandle::DfDy
+      | ErasedFunHandle::DfDxFine
+      | ErasedFunHandle::DfDyFine
+      | ErasedFunHandle::DfDxCoarse
+      | ErasedFunHandle::DfDyCoarse
+      | ErasedFunHandle::FWidth
+      | ErasedFunHandle::FWidthFine
+      | ErasedFunHandle::FWidthCoarse
+      | ErasedFunHandle::InterpolateAtCentroid
+      | ErasedFunHandle::InterpolateAtSample
+      | ErasedFunHandle::InterpolateAtOffset
+      | ErasedFunHandle::SubgroupBroadcastFirst
+      | ErasedFunHandle::SubgroupShuffle
+      | ErasedFunHandle::SubgroupAdd
+      | ErasedFunHandle::SubgroupMul
+      | ErasedFunHandle::SubgroupMin
+      | ErasedFunHandle::SubgroupMax => arg(0)?,
+
+      _ => return None,
+    };
+
+    Some(Type {
+      prim_ty,
+      array_dims: Vec::new(),
+    })
+  }
+}
+
+/// Vector dimension of a non-matrix primitive type.
+pub(crate) fn prim_dim(prim_ty: &PrimType) -> Option<&Dim> {
+  match prim_ty {
+    PrimType::Int(dim) | PrimType::UInt(dim) | PrimType::Float(dim) | PrimType::Bool(dim) => {
+      Some(dim)
+    }
+    _ => None,
+  }
+}
+
+/// Same primitive type as `prim_ty`, with another vector dimension.
+pub(crate) fn with_dim(prim_ty: &PrimType, dim: Dim) -> Option<PrimType> {
+  match prim_ty {
+    PrimType::Int(_) => Some(PrimType::Int(dim)),
+    PrimType::UInt(_) => Some(PrimType::UInt(dim)),
+    PrimType::Float(_) => Some(PrimType::Float(dim)),
+    PrimType::Bool(_) => Some(PrimType::Bool(dim)),
+    _ => None,
+  }
+}
+
+/// Number of columns and rows of a matrix.
+pub(crate) fn matrix_size(dim: &MatrixDim) -> (Dim, Dim) {
+  match dim {
+    MatrixDim::D22 => (Dim::D2, Dim::D2),
+    MatrixDim::D23 => (Dim::D2, Dim::D3),
+    MatrixDim::D24 => (Dim::D2, Dim::D4),
+    MatrixDim::D32 => (Dim::D3, Dim::D2),
+    MatrixDim::D33 => (Dim::D3, Dim::D3),
+    MatrixDim::D34 => (Dim::D3, Dim::D4),
+    MatrixDim::D42 => (Dim::D4, Dim::D2),
+    MatrixDim::D43 => (Dim::D4, Dim::D3),
+    MatrixDim::D44 => (Dim::D4, Dim::D4),
+  }
+}
+
+fn matrix_dim(cols: Dim, rows: Dim) -> Option<MatrixDim> {
+  match (cols, rows) {
+    (Dim::D2, Dim::D2) => Some(MatrixDim::D22),
+    (Dim::D2, Dim::D3) => Some(MatrixDim::D23),
+    (Dim::D2, Dim::D4) => Some(MatrixDim::D24),
+    (Dim::D3, Dim::D2) => Some(MatrixDim::D32),
+    (Dim::D3, Dim::D3) => Some(MatrixDim::D33),
+    (Dim::D3, Dim::D4) => Some(MatrixDim::D34),
+    (Dim::D4, Dim::D2) => Some(MatrixDim::D42),
+    (Dim::D4, Dim::D3) => Some(MatrixDim::D43),
+    (Dim::D4, Dim::D4) => Some(MatrixDim::D44),
+    _ => None,
+  }
+}
+
+/// Type of a component-wise binary operator, where a scalar operand is broadcast to the dimension of the other one.
+pub(crate) fn component_wise_ty(a: PrimType, b: PrimType) -> Option<PrimType> {
+  if a == b {
+    return Some(a);
+  }
+
+  match (&a, &b) {
+    (PrimType::Matrix(_), PrimType::Float(Dim::Scalar)) => Some(a),
+    (PrimType::Float(Dim::Scalar), PrimType::Matrix(_)) => Some(b),
+    _ if with_dim(&a, Dim::Scalar) != with_dim(&b, Dim::Scalar) => None,
+    _ if prim_dim(&a) == Some(&Dim::Scalar) => Some(b),
+    _ if prim_dim(&b) == Some(&Dim::Scalar) => Some(a),
+    _ => None,
+  }
+}
+
+/// Type of a multiplication, which is the linear algebraic product when matrices are involved.
+pub(crate) fn mul_ty(a: PrimType, b: PrimType) -> Option<PrimType> {
+  match (&a, &b) {
+    (PrimType::Matrix(a_dim), PrimType::Matrix(b_dim)) => {
+      let (a_cols, a_rows) = matrix_size(a_dim);
+      let (b_cols, b_rows) = matrix_size(b_dim);
+      (a_cols == b_rows)
+        .then(|| matrix_dim(b_cols, a_rows))
+        .flatten()
+        .map(PrimType::Matrix)
+    }
+
+    (PrimType::Matrix(dim), PrimType::Float(vec_dim)) if *vec_dim != Dim::Scalar => {
+      let (cols, rows) = matrix_size(dim);
+      (cols == *vec_dim).then_some(PrimType::Float(rows))
+    }
+
+    (PrimType::Float(vec_dim), PrimType::Matrix(dim)) if *vec_dim != Dim::Scalar => {
+      let (cols, rows) = matrix_size(dim);
+      (rows == *vec_dim).then_some(PrimType::Float(cols))
+    }
+
+    _ => component_wise_ty(a, b),
+  }
+}
+
+/// Type of the built-ins which are neither structures nor unsized arrays.
+fn builtin_ty(builtin: &BuiltIn) -> Option<Type> {
+  let int = PrimType::Int(Dim::Scalar);
+  let float = PrimType::Float(Dim::Scalar);
+
+  let (prim_ty, array_dims) = match builtin {
+    BuiltIn::Vertex(builtin) => match builtin {
+      VertexBuiltIn::VertexID
+      | VertexBuiltIn::InstanceID
+      | VertexBuiltIn::BaseVertex
+      | VertexBuiltIn::BaseInstance => (int, vec![]),
+      VertexBuiltIn::Position => (PrimType::Float(Dim::D4), vec![]),
+      VertexBuiltIn::PointSize => (float, vec![]),
+      VertexBuiltIn::ClipDistance => return None,
+    },
+
+    BuiltIn::TessCtrl(builtin) => match builtin {
+      TessCtrlBuiltIn::MaxPatchVerticesIn
+      | TessCtrlBuiltIn::PatchVerticesIn
+      | TessCtrlBuiltIn::PrimitiveID
+      | TessCtrlBuiltIn::InvocationID => (int, vec![]),
+      TessCtrlBuiltIn::TessellationLevelOuter => (float, vec![4]),
+      TessCtrlBuiltIn::TessellationLevelInner => (float, vec![2]),
+      TessCtrlBuiltIn::Position => (PrimType::Float(Dim::D4), vec![]),
+      TessCtrlBuiltIn::PointSize => (float, vec![]),
+      TessCtrlBuiltIn::In
+      | TessCtrlBuiltIn::Out
+      | TessCtrlBuiltIn::ClipDistance
+      | TessCtrlBuiltIn::CullDistance => return None,
+    },
+
+    BuiltIn::TessEval(builtin) => match builtin {
+      TessEvalBuiltIn::TessCoord => (PrimType::Float(Dim::D3), vec![]),
+      TessEvalBuiltIn::MaxPatchVerticesIn
+      | TessEvalBuiltIn::PatchVerticesIn
+      | TessEvalBuiltIn::PrimitiveID => (int, vec![]),
+      TessEvalBuiltIn::TessellationLevelOuter => (float, vec![4]),
+      TessEvalBuiltIn::TessellationLevelInner => (float, vec![2]),
+      TessEvalBuiltIn::Position => (PrimType::Float(Dim::D4), vec![]),
+      TessEvalBuiltIn::PointSize => (float, vec![]),
+      TessEvalBuiltIn::In
+      | TessEvalBuiltIn::Out
+      | TessEvalBuiltIn::ClipDistance
+      | TessEvalBuiltIn::CullDistance => return None,
+    },
+
+    BuiltIn::Geometry(builtin) => match builtin {
+      GeometryBuiltIn::Position => (PrimType::Float(Dim::D4), vec![]),
+      GeometryBuiltIn::PointSize => (float, vec![]),
+      GeometryBuiltIn::PrimitiveID
+      | GeometryBuiltIn::PrimitiveIDIn
+      | GeometryBuiltIn::InvocationID
+      | GeometryBuiltIn::Layer
+      | GeometryBuiltIn::ViewportIndex => (int, vec![]),
+      GeometryBuiltIn::In
+      | GeometryBuiltIn::Out
+      | GeometryBuiltIn::ClipDistance
+      | GeometryBuiltIn::CullDistance => return None,
+    },
+
+    BuiltIn::Fragment(builtin) => match builtin {
+      FragmentBuiltIn::FragCoord => (PrimType::Float(Dim::D4), vec![]),
+      FragmentBuiltIn::FrontFacing | FragmentBuiltIn::HelperInvocation => {
+        (PrimType::Bool(Dim::Scalar), vec![])
+      }
+      FragmentBuiltIn::PointCoord | FragmentBuiltIn::SamplePosition => {
+        (PrimType::Float(Dim::D2), vec![])
+      }
+      FragmentBuiltIn::SampleID
+      | FragmentBuiltIn::SampleMaskIn
+      | FragmentBuiltIn::PrimitiveID
+      | FragmentBuiltIn::Layer
+      | FragmentBuiltIn::ViewportIndex => (int, vec![]),
+      FragmentBuiltIn::FragDepth => (float, vec![]),
+      FragmentBuiltIn::ClipDistance
+      | FragmentBuiltIn::CullDistance
+      | FragmentBuiltIn::SampleMask => return None,
+    },
+
+    BuiltIn::Compute(builtin) => match builtin {
+      ComputeBuiltIn::NumWorkGroups
+      | ComputeBuiltIn::WorkGroupID
+      | ComputeBuiltIn::LocalInvocationID
+      | ComputeBuiltIn::GlobalInvocationID => (PrimType::UInt(Dim::D3), vec![]),
+      ComputeBuiltIn::LocalInvocationIndex => (PrimType::UInt(Dim::Scalar), vec![]),
+    },
+
+    BuiltIn::Subgroup(SubgroupBuiltIn::SubgroupSize | SubgroupBuiltIn::SubgroupInvocationID) => {
+      (PrimType::UInt(Dim::Scalar), vec![])
+    }
+  };
+
+  Some(Type {
+    prim_ty,
+    array_dims,
+  })
+}
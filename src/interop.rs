@@ -0,0 +1,10 @@
+//! Conversions from the types of other math crates, behind feature gates.
+
+#[cfg(feature = "cgmath")]
+mod cgmath;
+
+#[cfg(feature = "mint")]
+mod mint;
+
+#[cfg(feature = "nalgebra")]
+mod nalgebra;
@@ -0,0 +1,82 @@
+//! [cgmath] conversions, enabled with the `cgmath` feature gate.
+//!
+//! Vectors and points convert to [`V2`], [`V3`] and [`V4`], and matrices convert to [`M22`](crate::M22),
+//! [`M33`](crate::M33) and [`M44`](crate::M44). All of them convert to literal [`Expr`]s as well.
+//!
+//! [cgmath]: https://crates.io/crates/cgmath
+
+use crate::{Expr, Matrix, V2, V3, V4};
+
+macro_rules! impl_From_cgmath_vn {
+  ($cgmath:ident, $t:ident) => {
+    impl<T> From<::cgmath::$cgmath<T>> for $t<T> {
+      fn from(v: ::cgmath::$cgmath<T>) -> Self {
+        Self(v.into())
+      }
+    }
+
+    impl<T> From<::cgmath::$cgmath<T>> for Expr<$t<T>>
+    where
+      Expr<$t<T>>: From<$t<T>>,
+    {
+      fn from(v: ::cgmath::$cgmath<T>) -> Self {
+        $t::from(v).into()
+      }
+    }
+  };
+}
+
+impl_From_cgmath_vn!(Vector2, V2);
+impl_From_cgmath_vn!(Vector3, V3);
+impl_From_cgmath_vn!(Vector4, V4);
+impl_From_cgmath_vn!(Point2, V2);
+impl_From_cgmath_vn!(Point3, V3);
+
+macro_rules! impl_From_cgmath_mat {
+  ($cgmath:ident, $dim:expr) => {
+    impl From<::cgmath::$cgmath<f32>> for Matrix<[[f32; $dim]; $dim]> {
+      fn from(m: ::cgmath::$cgmath<f32>) -> Self {
+        Matrix(m.into())
+      }
+    }
+
+    impl From<::cgmath::$cgmath<f32>> for Expr<Matrix<[[f32; $dim]; $dim]>> {
+      fn from(m: ::cgmath::$cgmath<f32>) -> Self {
+        Matrix::from(m).into()
+      }
+    }
+  };
+}
+
+impl_From_cgmath_mat!(Matrix2, 2);
+impl_From_cgmath_mat!(Matrix3, 3);
+impl_From_cgmath_mat!(Matrix4, 4);
+
+#[cfg(test)]
+mod tests {
+  use crate::{writer::glsl, Expr, M22, V3};
+
+  #[test]
+  fn vectors() {
+    let v = ::cgmath::Vector3::new(1., 2., 3.);
+
+    assert_eq!(V3::from(v), V3::from([1., 2., 3.]));
+    assert_eq!(V3::from(::cgmath::Point3::new(1., 2., 3.)), V3::from(v));
+    assert_eq!(
+      glsl::write_expr_to_str(&Expr::<V3<f32>>::from(v)).unwrap(),
+      "vec3(1., 2., 3.)"
+    );
+  }
+
+  #[test]
+  fn matrices() {
+    // new takes the components in column-major order
+    let m = ::cgmath::Matrix2::new(1., 2., 3., 4.);
+
+    assert_eq!(M22::from(m), M22::from([[1., 2.], [3., 4.]]));
+    assert_eq!(
+      glsl::write_expr_to_str(&Expr::<M22>::from(m)).unwrap(),
+      "mat2(1., 2., 3., 4.)"
+    );
+  }
+}
@@ -0,0 +1,121 @@
+//! [mint] conversions, enabled with the `mint` feature gate.
+//!
+//! Vectors and points convert to [`V2`], [`V3`] and [`V4`], and column and row matrices convert to the matrix types
+//! with the same number of columns and rows — e.g. a [`ColumnMatrix2x3`](::mint::ColumnMatrix2x3), which has 2 rows
+//! and 3 columns, converts to [`M32`]. All of them convert to literal [`Expr`]s as well.
+//!
+//! [mint]: https://crates.io/crates/mint
+
+use crate::{Expr, Matrix, V2, V3, V4};
+
+macro_rules! impl_From_mint_vn {
+  ($mint:ident, $t:ident) => {
+    impl<T> From<::mint::$mint<T>> for $t<T> {
+      fn from(v: ::mint::$mint<T>) -> Self {
+        Self(v.into())
+      }
+    }
+
+    impl<T> From<::mint::$mint<T>> for Expr<$t<T>>
+    where
+      Expr<$t<T>>: From<$t<T>>,
+    {
+      fn from(v: ::mint::$mint<T>) -> Self {
+        $t::from(v).into()
+      }
+    }
+  };
+}
+
+impl_From_mint_vn!(Vector2, V2);
+impl_From_mint_vn!(Vector3, V3);
+impl_From_mint_vn!(Vector4, V4);
+impl_From_mint_vn!(Point2, V2);
+impl_From_mint_vn!(Point3, V3);
+
+macro_rules! impl_From_mint_mat {
+  ($column:ident, $row:ident, $rows:expr, $columns:expr) => {
+    impl From<::mint::$column<f32>> for Matrix<[[f32; $rows]; $columns]> {
+      fn from(m: ::mint::$column<f32>) -> Self {
+        Matrix(m.into())
+      }
+    }
+
+    impl From<::mint::$column<f32>> for Expr<Matrix<[[f32; $rows]; $columns]>> {
+      fn from(m: ::mint::$column<f32>) -> Self {
+        Matrix::from(m).into()
+      }
+    }
+
+    impl From<::mint::$row<f32>> for Matrix<[[f32; $rows]; $columns]> {
+      fn from(m: ::mint::$row<f32>) -> Self {
+        ::mint::$column::from(m).into()
+      }
+    }
+
+    impl From<::mint::$row<f32>> for Expr<Matrix<[[f32; $rows]; $columns]>> {
+      fn from(m: ::mint::$row<f32>) -> Self {
+        Matrix::from(m).into()
+      }
+    }
+  };
+}
+
+impl_From_mint_mat!(ColumnMatrix2, RowMatrix2, 2, 2);
+impl_From_mint_mat!(ColumnMatrix3x2, RowMatrix3x2, 3, 2);
+impl_From_mint_mat!(ColumnMatrix4x2, RowMatrix4x2, 4, 2);
+impl_From_mint_mat!(ColumnMatrix2x3, RowMatrix2x3, 2, 3);
+impl_From_mint_mat!(ColumnMatrix3, RowMatrix3, 3, 3);
+impl_From_mint_mat!(ColumnMatrix4x3, RowMatrix4x3, 4, 3);
+impl_From_mint_mat!(ColumnMatrix2x4, RowMatrix2x4, 2, 4);
+impl_From_mint_mat!(ColumnMatrix3x4, RowMatrix3x4, 3, 4);
+impl_From_mint_mat!(ColumnMatrix4, RowMatrix4, 4, 4);
+
+#[cfg(test)]
+mod tests {
+  use crate::{writer::glsl, Expr, M32, V3};
+
+  #[test]
+  fn vectors() {
+    let v = ::mint::Vector3 {
+      x: 1.,
+      y: 2.,
+      z: 3.,
+    };
+
+    assert_eq!(V3::from(v), V3::from([1., 2., 3.]));
+    assert_eq!(
+      glsl::write_expr_to_str(&Expr::<V3<f32>>::from(v)).unwrap(),
+      "vec3(1., 2., 3.)"
+    );
+  }
+
+  #[test]
+  fn matrices() {
+    // 2 rows, 3 columns
+    let column = ::mint::ColumnMatrix2x3 {
+      x: ::mint::Vector2 { x: 1., y: 2. },
+      y: ::mint::Vector2 { x: 3., y: 4. },
+      z: ::mint::Vector2 { x: 5., y: 6. },
+    };
+    let row = ::mint::RowMatrix2x3 {
+      x: ::mint::Vector3 {
+        x: 1.,
+        y: 3.,
+        z: 5.,
+      },
+      y: ::mint::Vector3 {
+        x: 2.,
+        y: 4.,
+        z: 6.,
+      },
+    };
+
+    assert_eq!(M32::from(column), M32::from([[1., 2.], [3., 4.], [5., 6.]]));
+    assert_eq!(M32::from(row), M32::from(column));
+    assert_eq!(
+      glsl::write_expr_to_str(&Expr::<M32>::from(row)).unwrap(),
+      "mat3x2(1., 2., 3., 4., 5., 6.)"
+    );
+  }
+}
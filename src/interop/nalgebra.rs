@@ -0,0 +1,111 @@
+//! [nalgebra] conversions, enabled with the `nalgebra` feature gate.
+//!
+//! Vectors and points convert to [`V2`], [`V3`] and [`V4`], and matrices convert to the matrix types with the same
+//! number of columns and rows — e.g. a [`Matrix2x3`](::nalgebra::Matrix2x3), which has 2 rows and 3 columns, converts
+//! to [`M32`]. All of them convert to literal [`Expr`]s as well.
+//!
+//! [nalgebra]: https://crates.io/crates/nalgebra
+
+use crate::{Expr, Matrix, V2, V3, V4};
+use ::nalgebra::{Point, SMatrix, SVector, Scalar};
+
+macro_rules! impl_From_nalgebra_vn {
+  ($t:ident, $dim:expr) => {
+    impl<T> From<SVector<T, $dim>> for $t<T>
+    where
+      T: Scalar,
+    {
+      fn from(v: SVector<T, $dim>) -> Self {
+        Self(v.into())
+      }
+    }
+
+    impl<T> From<SVector<T, $dim>> for Expr<$t<T>>
+    where
+      T: Scalar,
+      Expr<$t<T>>: From<$t<T>>,
+    {
+      fn from(v: SVector<T, $dim>) -> Self {
+        $t::from(v).into()
+      }
+    }
+
+    impl<T> From<Point<T, $dim>> for $t<T>
+    where
+      T: Scalar,
+    {
+      fn from(p: Point<T, $dim>) -> Self {
+        Self(p.into())
+      }
+    }
+
+    impl<T> From<Point<T, $dim>> for Expr<$t<T>>
+    where
+      T: Scalar,
+      Expr<$t<T>>: From<$t<T>>,
+    {
+      fn from(p: Point<T, $dim>) -> Self {
+        $t::from(p).into()
+      }
+    }
+  };
+}
+
+impl_From_nalgebra_vn!(V2, 2);
+impl_From_nalgebra_vn!(V3, 3);
+impl_From_nalgebra_vn!(V4, 4);
+
+macro_rules! impl_From_nalgebra_mat {
+  ($rows:expr, $columns:expr) => {
+    impl From<SMatrix<f32, $rows, $columns>> for Matrix<[[f32; $rows]; $columns]> {
+      fn from(m: SMatrix<f32, $rows, $columns>) -> Self {
+        Matrix(m.into())
+      }
+    }
+
+    impl From<SMatrix<f32, $rows, $columns>> for Expr<Matrix<[[f32; $rows]; $columns]>> {
+      fn from(m: SMatrix<f32, $rows, $columns>) -> Self {
+        Matrix::from(m).into()
+      }
+    }
+  };
+}
+
+impl_From_nalgebra_mat!(2, 2);
+impl_From_nalgebra_mat!(3, 2);
+impl_From_nalgebra_mat!(4, 2);
+impl_From_nalgebra_mat!(2, 3);
+impl_From_nalgebra_mat!(3, 3);
+impl_From_nalgebra_mat!(4, 3);
+impl_From_nalgebra_mat!(2, 4);
+impl_From_nalgebra_mat!(3, 4);
+impl_From_nalgebra_mat!(4, 4);
+
+#[cfg(test)]
+mod tests {
+  use crate::{writer::glsl, Expr, M32, V3};
+
+  #[test]
+  fn vectors() {
+    let v = ::nalgebra::Vector3::new(1., 2., 3.);
+
+    assert_eq!(V3::from(v), V3::from([1., 2., 3.]));
+    assert_eq!(V3::from(::nalgebra::Point3::from(v)), V3::from(v));
+    assert_eq!(
+      glsl::write_expr_to_str(&Expr::<V3<f32>>::from(v)).unwrap(),
+      "vec3(1., 2., 3.)"
+    );
+  }
+
+  #[test]
+  fn matrices() {
+    // 2 rows, 3 columns; new takes the components in row-major order
+    let m = ::nalgebra::Matrix2x3::new(1., 3., 5., 2., 4., 6.);
+
+    assert_eq!(M32::from(m), M32::from([[1., 2.], [3., 4.], [5., 6.]]));
+    assert_eq!(
+      glsl::write_expr_to_str(&Expr::<M32>::from(m)).unwrap(),
+      "mat3x2(1., 2., 3., 4., 5., 6.)"
+    );
+  }
+}
@@ -0,0 +1,368 @@
+//! Memory layouts of uniform and storage blocks.
+//!
+//! The GPU reads the members of a uniform or storage block at offsets and strides given by the layout of the block —
+//! usually std140 for uniform blocks and std430 for storage blocks. Host code filling the buffer bound to a block must
+//! follow the very same rules, which are easy to get wrong by hand: `vec3`s are aligned like `vec4`s, std140 arrays of
+//! scalars have a 16-byte stride, matrices are stored as arrays of columns, etc.
+//!
+//! [`Layout::type_layout`] computes the size, alignment and strides of a single [`Type`], and [`Layout::block`]
+//! computes the offset and trailing padding of every member of a block. [`Shader::uniform_block_layout`] computes the
+//! layout of a block holding all the uniforms of a shader, in declaration order, as written by the
+//! [`wgpu`](crate::wgpu) writer.
+//!
+//! All sizes, alignments, strides, offsets and paddings are expressed in bytes. Booleans are stored as 32-bit integers.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{M44, Scope, ShaderBuilder, V3, inputs, uniforms, vec4};
+//! use shades::layout::Layout;
+//!
+//! let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+//!   inputs!(s, position: V3<f32>);
+//!   uniforms!(s, scale: f32, offset: V3<f32>, projection: M44);
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     s.set(vertex.position, projection * vec4!(position * scale + offset, 1.));
+//!   })
+//! });
+//!
+//! let block = shader.uniform_block_layout(Layout::Std140);
+//! let offsets: Vec<_> = block.members().iter().map(|member| member.offset()).collect();
+//! assert_eq!(offsets, [0, 16, 32]);
+//! assert_eq!(block.members()[0].padding(), 12);
+//! assert_eq!(block.size(), 96);
+//! ```
+//!
+//! [`Shader::uniform_block_layout`]: crate::Shader::uniform_block_layout
+
+use crate::{infer::matrix_size, Dim, PrimType, Type};
+
+/// Standard layout of a block.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Layout {
+  /// std140 layout, the default layout of uniform blocks.
+  ///
+  /// Arrays elements and matrix columns are aligned to 16 bytes.
+  Std140,
+
+  /// std430 layout, the default layout of storage blocks.
+  ///
+  /// Arrays elements and matrix columns are aligned to their own alignment.
+  Std430,
+}
+
+impl Layout {
+  /// Compute the layout of a value of type `ty`.
+  pub fn type_layout(self, ty: &Type) -> TypeLayout {
+    let (size, align, matrix_stride) = match ty.prim_ty {
+      PrimType::Int(ref dim)
+      | PrimType::UInt(ref dim)
+      | PrimType::Float(ref dim)
+      | PrimType::Bool(ref dim) => {
+        let (size, align) = vector_layout(dim);
+        (size, align, None)
+      }
+
+      PrimType::Matrix(ref dim) => {
+        // column-major matrices are stored as arrays of columns
+        let (columns, rows) = matrix_size(dim);
+        let (column_size, column_align) = vector_layout(&rows);
+        let stride = self.array_stride(column_size, column_align);
+        (
+          dim_len(&columns) * stride,
+          self.array_align(column_align),
+          Some(stride),
+        )
+      }
+    };
+
+    if ty.array_dims.is_empty() {
+      return TypeLayout {
+        size,
+        align,
+        array_stride: None,
+        matrix_stride,
+      };
+    }
+
+    // arrays of arrays are laid out as flat arrays of their innermost elements, since the size of an array is always a
+    // multiple of its alignment
+    let stride = self.array_stride(size, align);
+
+    TypeLayout {
+      size: ty.array_dims.iter().product::<usize>() * stride,
+      align: self.array_align(align),
+      array_stride: Some(stride),
+      matrix_stride,
+    }
+  }
+
+  /// Compute the layout of a block made of `members`, given by name and type, in order.
+  pub fn block<'a>(self, members: impl IntoIterator<Item = (&'a str, &'a Type)>) -> BlockLayout {
+    let mut layouts: Vec<MemberLayout> = Vec::new();
+    let mut block_align = match self {
+      Layout::Std140 => 16,
+      Layout::Std430 => 1,
+    };
+    let mut end = 0;
+
+    for (name, ty) in members {
+      let layout = self.type_layout(ty);
+      let offset = round_up(end, layout.align);
+
+      if let Some(previous) = layouts.last_mut() {
+        previous.padding = offset - end;
+      }
+
+      block_align = block_align.max(layout.align);
+      end = offset + layout.size;
+      layouts.push(MemberLayout {
+        name: name.to_owned(),
+        layout,
+        offset,
+        padding: 0,
+      });
+    }
+
+    let size = if layouts.is_empty() {
+      0
+    } else {
+      round_up(end, block_align)
+    };
+
+    if let Some(last) = layouts.last_mut() {
+      last.padding = size - end;
+    }
+
+    BlockLayout {
+      members: layouts,
+      size,
+    }
+  }
+
+  /// Stride of the elements of an array, given the size and alignment of an element.
+  fn array_stride(self, size: usize, align: usize) -> usize {
+    match self {
+      Layout::Std140 => round_up(round_up(size, align), 16),
+      Layout::Std430 => round_up(size, align),
+    }
+  }
+
+  /// Alignment of an array, given the alignment of an element.
+  fn array_align(self, align: usize) -> usize {
+    match self {
+      Layout::Std140 => round_up(align, 16),
+      Layout::Std430 => align,
+    }
+  }
+}
+
+/// Layout of a value of a given [`Type`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TypeLayout {
+  size: usize,
+  align: usize,
+  array_stride: Option<usize>,
+  matrix_stride: Option<usize>,
+}
+
+impl TypeLayout {
+  /// Size of the value, without trailing padding.
+  pub fn size(&self) -> usize {
+    self.size
+  }
+
+  /// Alignment of the value; its offset in a block is always a multiple of it.
+  pub fn align(&self) -> usize {
+    self.align
+  }
+
+  /// Stride between two consecutive innermost elements, if the value is an array.
+  ///
+  /// The stride between two consecutive elements of an outer dimension is this stride multiplied by the product of
+  /// the inner dimensions.
+  pub fn array_stride(&self) -> Option<usize> {
+    self.array_stride
+  }
+
+  /// Stride between two consecutive columns, if the value is a matrix or an array of matrices.
+  pub fn matrix_stride(&self) -> Option<usize> {
+    self.matrix_stride
+  }
+}
+
+/// Layout of a block, computed by [`Layout::block`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BlockLayout {
+  members: Vec<MemberLayout>,
+  size: usize,
+}
+
+impl BlockLayout {
+  /// Layouts of the members of the block, in order.
+  pub fn members(&self) -> &[MemberLayout] {
+    &self.members
+  }
+
+  /// Size of the block, including its trailing padding; the minimum size of the buffer bound to it.
+  pub fn size(&self) -> usize {
+    self.size
+  }
+}
+
+/// Layout of a member of a block.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct MemberLayout {
+  name: String,
+  layout: TypeLayout,
+  offset: usize,
+  padding: usize,
+}
+
+impl MemberLayout {
+  /// Name of the member.
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Layout of the type of the member.
+  pub fn layout(&self) -> &TypeLayout {
+    &self.layout
+  }
+
+  /// Offset of the member from the start of the block.
+  pub fn offset(&self) -> usize {
+    self.offset
+  }
+
+  /// Padding between the end of the member and the start of the next one — or the end of the block, for the last
+  /// member.
+  pub fn padding(&self) -> usize {
+    self.padding
+  }
+}
+
+/// Size and alignment of a scalar or vector of 32-bit components.
+fn vector_layout(dim: &Dim) -> (usize, usize) {
+  let len = dim_len(dim);
+  let align = if len == 3 { 16 } else { len * 4 };
+  (len * 4, align)
+}
+
+fn dim_len(dim: &Dim) -> usize {
+  match dim {
+    Dim::Scalar => 1,
+    Dim::D2 => 2,
+    Dim::D3 => 3,
+    Dim::D4 => 4,
+  }
+}
+
+fn round_up(x: usize, align: usize) -> usize {
+  x.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{ToType as _, M23, M32, M44, V2, V3, V4};
+
+  fn layout(layout: Layout, ty: Type) -> (usize, usize, Option<usize>, Option<usize>) {
+    let layout = layout.type_layout(&ty);
+    (
+      layout.size(),
+      layout.align(),
+      layout.array_stride(),
+      layout.matrix_stride(),
+    )
+  }
+
+  #[test]
+  fn type_layouts() {
+    assert_eq!(layout(Layout::Std140, f32::ty()), (4, 4, None, None));
+    assert_eq!(layout(Layout::Std140, V2::<i32>::ty()), (8, 8, None, None));
+    assert_eq!(
+      layout(Layout::Std140, V3::<f32>::ty()),
+      (12, 16, None, None)
+    );
+    assert_eq!(
+      layout(Layout::Std140, V4::<bool>::ty()),
+      (16, 16, None, None)
+    );
+
+    assert_eq!(layout(Layout::Std140, M44::ty()), (64, 16, None, Some(16)));
+    assert_eq!(layout(Layout::Std430, M44::ty()), (64, 16, None, Some(16)));
+    assert_eq!(layout(Layout::Std140, M32::ty()), (48, 16, None, Some(16)));
+    assert_eq!(layout(Layout::Std430, M32::ty()), (24, 8, None, Some(8)));
+    assert_eq!(layout(Layout::Std430, M23::ty()), (32, 16, None, Some(16)));
+
+    assert_eq!(
+      layout(Layout::Std140, <[f32; 3]>::ty()),
+      (48, 16, Some(16), None)
+    );
+    assert_eq!(
+      layout(Layout::Std430, <[f32; 3]>::ty()),
+      (12, 4, Some(4), None)
+    );
+    assert_eq!(
+      layout(Layout::Std430, <[V2<f32>; 3]>::ty()),
+      (24, 8, Some(8), None)
+    );
+    assert_eq!(
+      layout(Layout::Std430, <[V3<f32>; 2]>::ty()),
+      (32, 16, Some(16), None)
+    );
+    assert_eq!(
+      layout(Layout::Std140, <[[f32; 2]; 3]>::ty()),
+      (96, 16, Some(16), None)
+    );
+    assert_eq!(
+      layout(Layout::Std430, <[M32; 2]>::ty()),
+      (48, 8, Some(24), Some(8))
+    );
+  }
+
+  #[test]
+  fn block_layouts() {
+    let ty_a = V3::<f32>::ty();
+    let ty_b = f32::ty();
+    let ty_c = <[f32; 2]>::ty();
+    let ty_d = V2::<f32>::ty();
+    let members = [("a", &ty_a), ("b", &ty_b), ("c", &ty_c), ("d", &ty_d)];
+
+    let offsets = |block: &BlockLayout| {
+      block
+        .members()
+        .iter()
+        .map(|member| (member.name().to_owned(), member.offset(), member.padding()))
+        .collect::<Vec<_>>()
+    };
+
+    let std140 = Layout::Std140.block(members.iter().copied());
+    assert_eq!(
+      offsets(&std140),
+      [
+        ("a".to_owned(), 0, 0),
+        ("b".to_owned(), 12, 0),
+        ("c".to_owned(), 16, 0),
+        ("d".to_owned(), 48, 8)
+      ]
+    );
+    assert_eq!(std140.size(), 64);
+
+    let std430 = Layout::Std430.block(members.iter().copied());
+    assert_eq!(
+      offsets(&std430),
+      [
+        ("a".to_owned(), 0, 0),
+        ("b".to_owned(), 12, 0),
+        ("c".to_owned(), 16, 0),
+        ("d".to_owned(), 24, 0)
+      ]
+    );
+    assert_eq!(std430.size(), 32);
+
+    assert_eq!(Layout::Std140.block(None).size(), 0);
+  }
+}
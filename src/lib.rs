@@ -76,6 +76,8 @@
 //! - An experimental _monadic_ experience behind a _feature-gate_. This allows to write shaders by using the [do-notation]
 //!   crate and remove a lot of boilerplate for you, making _scopes_ and _shader scopes_ hidden for you, making it feel
 //!   like writing magic shading code.
+//! - Ordinary Rust statements — `let`, `if`, `for`, `match`, etc. — behind the `edsl` feature gate, with the `shades!`
+//!   procedural macro lowering them to the builder API.
 //!
 //! # Why you wouldn’t love this
 //!
@@ -94,6 +96,26 @@
 //!   with a much more convoluted way with this crate. The generated code is the same, but it is correctly more verbose via
 //!   this crate.
 //!
+//! # Interoperability
+//!
+//! Vectors and matrices of other math crates can be converted into shades types and literal expressions by enabling
+//! the feature gate named after the crate:
+//!
+//! - `cgmath`: [cgmath](https://crates.io/crates/cgmath) vectors, points and square matrices.
+//! - `mint`: [mint](https://crates.io/crates/mint) vectors, points and matrices.
+//! - `nalgebra`: [nalgebra](https://crates.io/crates/nalgebra) vectors, points and matrices.
+//!
+//! Graphics crates get integration modules, behind feature gates as well:
+//!
+//! - `crevice`: the [`crevice`](crate::crevice) module shares uniform blocks between shaders and
+//!   [crevice](https://crates.io/crates/crevice) std140 structs.
+//! - `glsl`: the [`reader::glsl`](crate::reader::glsl) module reads GLSL sources into [`Shader`]s with the
+//!   [glsl](https://crates.io/crates/glsl) crate.
+//! - `luminance`: the [`luminance`](crate::luminance) module creates [luminance](https://crates.io/crates/luminance)
+//!   programs out of [`Program`](program::Program)s.
+//! - `wgpu`: the [`wgpu`](crate::wgpu) module creates [wgpu](https://crates.io/crates/wgpu) shader modules out of
+//!   [`Shader`]s.
+//!
 //! [@phaazon]: https://github.com/phaazon
 //! [EDSL]: https://en.wikipedia.org/wiki/Domain-specific_language#External_and_Embedded_Domain_Specific_Languages
 //! [shaders]: https://en.wikipedia.org/wiki/Shader
@@ -107,23 +129,472 @@
 
 #![cfg_attr(feature = "fun-call", feature(unboxed_closures), feature(fn_traits))]
 
+pub mod attribute;
+pub mod build;
+pub mod check;
+#[cfg(feature = "crevice")]
+pub mod crevice;
+pub mod diff;
+pub mod eval;
+mod infer;
+mod interop;
+pub mod layout;
+#[cfg(feature = "luminance")]
+pub mod luminance;
+pub mod module;
+pub mod pass;
+pub mod primitive;
+pub mod program;
+#[cfg(feature = "glsl")]
+pub mod reader;
+pub mod reflect;
+pub mod stage;
+pub mod stats;
+pub mod subgroup;
+pub mod swizzle;
+pub mod variant;
+pub mod visit;
+#[cfg(feature = "wgpu")]
+pub mod wgpu;
+pub mod workgroup;
 pub mod writer;
 
+pub use diff::diff;
+#[cfg(feature = "edsl")]
+pub use shades_edsl::shades;
 use std::{
-  iter::once,
+  cell::{Cell, RefCell},
+  cmp,
+  collections::{BTreeMap, HashMap},
+  convert::TryFrom,
+  error::Error,
+  fmt,
+  iter::{once, repeat},
   marker::PhantomData,
+  mem,
   ops::{self, Deref, DerefMut},
+  panic,
+  rc::Rc,
+  sync::{Arc, Weak},
 };
 
 /// A fully built shader stage as represented in Rust, obtained by adding the `main` function to a [`ShaderBuilder`].
+///
+/// The `S` type parameter is the [stage](crate::stage) the shader was built for, such as [`stage::Vertex`] or
+/// [`stage::Fragment`]. This prevents from mixing up stages, as in passing a fragment shader where a vertex shader is
+/// expected:
+///
+/// ```compile_fail
+/// use shades::{Scope, Shader, ShaderBuilder, stage};
+///
+/// fn use_vertex_shader(_: Shader<stage::Vertex>) {}
+///
+/// let fragment_shader = ShaderBuilder::new_fragment_shader(|s, _| s.main_fun(|_: &mut Scope<()>| {}));
+/// use_vertex_shader(fragment_shader);
+/// ```
 #[derive(Debug)]
-pub struct Shader {
-  pub(crate) builder: ShaderBuilder,
+pub struct Shader<S> {
+  pub(crate) builder: ShaderBuilder<S>,
+}
+
+impl<S> AsRef<Shader<S>> for Shader<S> {
+  fn as_ref(&self) -> &Shader<S> {
+    self
+  }
+}
+
+/// Arbitrary shaders, made of arbitrary declarations, to fuzz the writers and the [passes](pass).
+///
+/// The generated shaders are usually ill-typed.
+#[cfg(feature = "arbitrary")]
+impl<'a, S> arbitrary::Arbitrary<'a> for Shader<S> {
+  fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+    let mut builder = ShaderBuilder::new();
+    builder.decls = u.arbitrary()?;
+
+    Ok(Shader { builder })
+  }
+}
+
+impl<S> Shader<S> {
+  /// Iterate over the inputs of the shader, in declaration order.
+  pub fn inputs(&self) -> impl Iterator<Item = reflect::VarInfo<'_>> {
+    reflect::inputs(&self.builder.decls)
+  }
+
+  /// Iterate over the outputs of the shader, in declaration order.
+  pub fn outputs(&self) -> impl Iterator<Item = reflect::VarInfo<'_>> {
+    reflect::outputs(&self.builder.decls)
+  }
+
+  /// Iterate over the uniforms of the shader, in declaration order.
+  pub fn uniforms(&self) -> impl Iterator<Item = reflect::VarInfo<'_>> {
+    reflect::uniforms(&self.builder.decls)
+  }
+
+  /// Compute the `layout` of a block holding all the uniforms of the shader, in declaration order.
+  pub fn uniform_block_layout(&self, layout: layout::Layout) -> layout::BlockLayout {
+    layout.block(
+      self
+        .uniforms()
+        .map(|uniform| (uniform.name(), uniform.ty())),
+    )
+  }
+
+  /// Iterate over the functions defined in the shader, in declaration order.
+  ///
+  /// The `main` function is not part of the iterated functions.
+  pub fn functions(&self) -> impl Iterator<Item = reflect::FunInfo<'_>> {
+    reflect::functions(&self.builder.decls)
+  }
+
+  /// Compute statistics about the complexity of the shader.
+  pub fn stats(&self) -> stats::ShaderStats {
+    stats::ShaderStats::new(self)
+  }
+
+  /// Walk the declarations of the shader with a [`visit::Visit`].
+  pub fn visit(&self, visitor: &mut impl visit::Visit) {
+    for decl in &self.builder.decls {
+      visitor.visit_decl(decl);
+    }
+  }
+
+  /// Transform the declarations of the shader with a [`visit::Fold`].
+  pub fn fold(&mut self, folder: &mut impl visit::Fold) {
+    self.builder.decls = mem::take(&mut self.builder.decls)
+      .into_iter()
+      .map(|decl| folder.fold_decl(decl))
+      .collect();
+  }
+
+  /// Merge `other` into this shader.
+  ///
+  /// The declarations of `other` are added before the `main` function of this shader, with their handles remapped so
+  /// that they don’t clash with the ones already present. The `main` function of `other` becomes a regular function,
+  /// called at the end of the `main` function of this shader, so that both run.
+  ///
+  /// Inputs, outputs and uniforms are identified by name: the ones declared by both shaders are kept once, with the
+  /// declaration of this shader. They must have the same type in both, otherwise nothing is merged and
+  /// [`MergeError::TypeMismatch`] is returned.
+  ///
+  /// Stage-wide layouts, vertex attributes, directives and extensions are identified by name as well, and the ones of
+  /// this shader win whenever both shaders have one with the same name, without any error. Capabilities and imported
+  /// modules of `other` are merged as well.
+  ///
+  /// The returned [`Relocation`](module::Relocation) translates the handles of `other` — such as [`FunHandle`]s
+  /// obtained while building it — to the handles of the merged shader with [`Exports::relocate`](module::Exports).
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{Scope, ShaderBuilder, V4, outputs, vec4};
+  ///
+  /// let mut color = ShaderBuilder::new_fragment_shader(|mut s, _| {
+  ///   outputs!(s, color: V4<f32>);
+  ///   s.main_fun(|s: &mut Scope<()>| s.set(color, vec4!(1., 0., 0., 1.)))
+  /// });
+  ///
+  /// let normal = ShaderBuilder::new_fragment_shader(|mut s, _| {
+  ///   outputs!(s, normal: V4<f32>);
+  ///   s.main_fun(|s: &mut Scope<()>| s.set(normal, vec4!(0., 0., 1., 0.)))
+  /// });
+  ///
+  /// // writes both color and normal
+  /// color.merge(&normal).unwrap();
+  /// assert_eq!(color.outputs().count(), 2);
+  /// ```
+  pub fn merge(&mut self, other: &Shader<S>) -> Result<module::Relocation, MergeError> {
+    let builder = &mut self.builder;
+    let other = &other.builder;
+
+    for decl in &other.decls {
+      if let Some(other_ty) = decl.interface_ty() {
+        let name = decl.name();
+        let ty = builder
+          .decls
+          .iter()
+          .filter(|decl| decl.name() == name)
+          .find_map(ShaderDecl::interface_ty);
+
+        match ty {
+          Some(ty) if ty != other_ty => {
+            return Err(MergeError::TypeMismatch {
+              name,
+              ty: ty.clone(),
+              other_ty: other_ty.clone(),
+            })
+          }
+          _ => (),
+        }
+      }
+    }
+
+    let mut relocation =
+      module::Relocation::new(builder.next_fun_handle, builder.next_global_handle);
+    let main_handle = builder.next_fun_handle + other.next_fun_handle;
+
+    // modules imported by both shaders are only kept once
+    for import in &other.imports {
+      if let Some(existing) = builder.imports.iter().find(|i| i.id == import.id) {
+        relocation.redirect(import, existing);
+      }
+    }
+
+    let mut decls = Vec::new();
+    // comments annotate the declaration following them, and are dropped along with it
+    let mut comments = Vec::new();
+
+    for decl in &other.decls {
+      let keep = match decl {
+        ShaderDecl::Comment(_) => {
+          comments.push(relocation.decl(decl));
+          continue;
+        }
+
+        ShaderDecl::In(..)
+        | ShaderDecl::Out(..)
+        | ShaderDecl::LayoutOut(..)
+        | ShaderDecl::Uniform(..)
+        | ShaderDecl::PatchIn(..)
+        | ShaderDecl::PatchOut(..) => {
+          let name = decl.name();
+          builder.decls.iter().all(|decl| decl.name() != name)
+        }
+
+        _ => !relocation.is_redirected(decl),
+      };
+
+      if keep {
+        decls.append(&mut comments);
+        decls.push(match relocation.decl(decl) {
+          ShaderDecl::Main(fun) => ShaderDecl::FunDef(main_handle, fun),
+          decl => decl,
+        });
+      } else {
+        comments.clear();
+      }
+    }
+
+    let main_index = builder
+      .decls
+      .iter()
+      .position(|decl| matches!(decl, ShaderDecl::Main(_)))
+      .unwrap_or(builder.decls.len());
+
+    if let Some(ShaderDecl::Main(main)) = builder.decls.get_mut(main_index) {
+      main.scope.instructions.push(ScopeInstr::Call(
+        ErasedFunHandle::UserDefined(main_handle),
+        Vec::new(),
+      ));
+    }
+
+    builder.decls.splice(main_index..main_index, decls);
+    builder.next_fun_handle = main_handle + 1;
+    builder.next_global_handle += other.next_global_handle;
+
+    for (&handle, name) in &other.fun_names {
+      builder
+        .fun_names
+        .insert(relocation.relocated_fun(handle), name.clone());
+    }
+
+    for (&handle, name) in &other.global_names {
+      builder
+        .global_names
+        .insert(relocation.relocated_global(handle), name.clone());
+    }
+
+    for &capability in &other.capabilities {
+      builder.require(capability);
+    }
+
+    for extension in &other.extensions {
+      if builder
+        .extensions
+        .iter()
+        .all(|(name, _)| *name != extension.0)
+      {
+        builder.extensions.push(extension.clone());
+      }
+    }
+
+    for directive in &other.directives {
+      if builder
+        .directives
+        .iter()
+        .all(|d| d.name() != directive.name())
+      {
+        builder.directives.push(directive.clone());
+      }
+    }
+
+    for layout in &other.layouts {
+      if builder.layouts.iter().all(|l| l.name() != layout.name()) {
+        builder.layouts.push(*layout);
+      }
+    }
+
+    for attribute in &other.attributes {
+      if builder.attributes.iter().all(|a| a.name != attribute.name) {
+        builder.attributes.push(attribute.clone());
+      }
+    }
+
+    for import in &other.imports {
+      if builder.imports.iter().all(|i| i.id != import.id) {
+        builder.imports.push(import.then(&relocation));
+      }
+    }
+
+    Ok(relocation)
+  }
+}
+
+/// Errors that can occur while [merging](Shader::merge) shaders.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MergeError {
+  /// An input, output or uniform declared by both shaders doesn’t have the same type in each.
+  TypeMismatch {
+    /// Human-readable name of the declaration, such as `uniform time`.
+    name: String,
+
+    /// Type of the declaration in the shader [`Shader::merge`] is called on.
+    ty: Type,
+
+    /// Type of the declaration in the shader passed to [`Shader::merge`].
+    other_ty: Type,
+  },
+}
+
+impl fmt::Display for MergeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      MergeError::TypeMismatch { name, ty, other_ty } => write!(
+        f,
+        "{} has type {:?}, but the merged shader declares it with type {:?}",
+        name, ty, other_ty
+      ),
+    }
+  }
+}
+
+impl Error for MergeError {}
+
+impl Shader<stage::Compute> {
+  /// Size of the work groups along the X, Y and Z axes, if set with [`ShaderBuilder::local_size`].
+  ///
+  /// This is the size to dispatch the shader with, such as the number of invocations to divide the problem size by.
+  pub fn local_size(&self) -> Option<[LocalSize; 3]> {
+    self
+      .builder
+      .layouts
+      .iter()
+      .find_map(|layout| match *layout {
+        StageLayout::LocalSize(sizes) => Some(sizes),
+        _ => None,
+      })
+  }
 }
 
-impl AsRef<Shader> for Shader {
-  fn as_ref(&self) -> &Shader {
+impl Shader<stage::Vertex> {
+  /// Iterate over the inputs declared with [`ShaderBuilder::attribute`], in declaration order.
+  pub fn attributes(&self) -> impl Iterator<Item = reflect::AttributeInfo<'_>> {
+    reflect::attributes(&self.builder.decls, &self.builder.attributes)
+  }
+
+  /// Attribute of the given `semantic`, if any.
+  pub fn attribute(&self, semantic: &attribute::Semantic) -> Option<reflect::AttributeInfo<'_>> {
     self
+      .attributes()
+      .find(|attribute| attribute.semantic() == semantic)
+  }
+}
+
+impl<S> Shader<S>
+where
+  S: stage::Stage,
+{
+  /// Compute a digest of the shader.
+  ///
+  /// The digest only depends on the stage and the content of the shader — and not on the order in which capabilities
+  /// and extensions were required —, so two shaders built the same way have the same digest, across runs and across
+  /// platforms. This is useful to key pipeline caches and skip recompiling unchanged shaders.
+  ///
+  /// The digest is not guaranteed to be the same across versions of this crate.
+  pub fn digest(&self) -> u64 {
+    use std::fmt::Write as _;
+
+    let mut capabilities = self.builder.capabilities.clone();
+    capabilities.sort();
+    let mut extensions = self.builder.extensions.clone();
+    extensions.sort();
+
+    let mut digest = Digest::new();
+
+    // writing to a Digest never fails
+    let _ = write!(
+      digest,
+      "{:?}{:?}{:?}{:?}{:?}{:?}{:?}{:?}{:?}",
+      S::KIND,
+      self.builder.directives,
+      capabilities,
+      extensions,
+      self.builder.layouts,
+      self.builder.attributes,
+      self.builder.fun_names,
+      self.builder.global_names,
+      self.builder.decls
+    );
+
+    digest.0
+  }
+
+  /// Check the shader for problems the typed API cannot catch, such as variables used outside of their scope,
+  /// built-ins and functions of another stage or ill-typed erased expressions.
+  ///
+  /// Only [errors](check::Severity::Error) fail the check: a shader with warnings only is valid, and its warnings are
+  /// returned in [`Ok`]. Otherwise, all the problems found are returned, warnings included; see the [`check`] module.
+  pub fn check(&self) -> Result<Vec<check::Diagnostic>, Vec<check::Diagnostic>> {
+    let diagnostics = check::check(
+      S::KIND,
+      &self.builder.layouts,
+      &self.builder.attributes,
+      &self.builder.decls,
+    );
+
+    if diagnostics
+      .iter()
+      .any(|diagnostic| diagnostic.severity() == check::Severity::Error)
+    {
+      Err(diagnostics)
+    } else {
+      Ok(diagnostics)
+    }
+  }
+}
+
+/// 64-bit FNV-1a hash, fed with the textual representation of the AST.
+///
+/// [`std::collections::hash_map::DefaultHasher`] is not used as its algorithm is not guaranteed to be stable.
+struct Digest(u64);
+
+impl Digest {
+  const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+  const PRIME: u64 = 0x0100_0000_01b3;
+
+  fn new() -> Self {
+    Digest(Self::OFFSET_BASIS)
+  }
+}
+
+impl std::fmt::Write for Digest {
+  fn write_str(&mut self, s: &str) -> std::fmt::Result {
+    for byte in s.bytes() {
+      self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(Self::PRIME);
+    }
+
+    Ok(())
   }
 }
 
@@ -132,14 +603,27 @@ impl AsRef<Shader> for Shader {
 /// This opaque type is the representation of a shader stage in Rust. It contains constants, uniforms, inputs, outputs and
 /// functions declarations. Such a type is used to build a shader stage and is fully built when the `main` function is
 /// present in its code. See [`ShaderBuilder::main_fun`] for further details.
+///
+/// The `S` type parameter is the [stage](crate::stage) being built. It is set by the method used to create the builder,
+/// such as [`ShaderBuilder::new_vertex_shader`], and allows to restrict some declarations to some stages only.
 #[derive(Debug)]
-pub struct ShaderBuilder {
+pub struct ShaderBuilder<S> {
   pub(crate) decls: Vec<ShaderDecl>,
+  pub(crate) capabilities: Vec<Capability>,
+  pub(crate) extensions: Vec<(String, Behavior)>,
+  pub(crate) directives: Vec<Directive>,
+  pub(crate) layouts: Vec<StageLayout>,
+  pub(crate) attributes: Vec<attribute::VertexAttribute>,
+  // names given to functions and constants, by handle
+  pub(crate) fun_names: BTreeMap<u16, String>,
+  pub(crate) global_names: BTreeMap<u16, String>,
+  pub(crate) imports: Vec<module::Import>,
   next_fun_handle: u16,
   next_global_handle: u16,
+  _phantom: PhantomData<S>,
 }
 
-impl ShaderBuilder {
+impl ShaderBuilder<stage::Vertex> {
   /// Create a new _vertex shader_.
   ///
   /// This method creates a [`Shader`] that can be used as _vertex shader_. This is enforced by the fact only this
@@ -168,10 +652,77 @@ impl ShaderBuilder {
   ///   })
   /// });
   /// ```
-  pub fn new_vertex_shader(f: impl FnOnce(Self, VertexShaderEnv) -> Shader) -> Shader {
+  pub fn new_vertex_shader(
+    f: impl FnOnce(Self, VertexShaderEnv) -> Shader<stage::Vertex>,
+  ) -> Shader<stage::Vertex> {
     f(Self::new(), VertexShaderEnv::new())
   }
 
+  /// Declare a new input read from vertex buffers as `format`, with the given `semantic`.
+  ///
+  /// The input is declared as with [`ShaderBuilder::input`]; the format and semantic are surfaced with
+  /// [`Shader::attributes`], for engines to bind vertex buffers. See the [`attribute`] module.
+  ///
+  /// # Safety
+  ///
+  /// The `name` is used verbatim in the generated code, so it must be a valid identifier in the target language and
+  /// must not clash with another declaration or built-in.
+  pub unsafe fn attribute<T>(
+    &mut self,
+    name: &str,
+    format: attribute::VertexFormat,
+    semantic: attribute::Semantic,
+  ) -> Var<T>
+  where
+    T: ToType,
+  {
+    self.attributes.push(attribute::VertexAttribute {
+      name: name.to_owned(),
+      format,
+      semantic,
+    });
+
+    self.input(name)
+  }
+
+  /// Redeclare [`VertexShaderEnv::clip_distance`] with `size` elements — `out float gl_ClipDistance[size]`.
+  ///
+  /// GLSL requires the size of the array to be known, either from this redeclaration or from indexing it with constants
+  /// only. `size` must be at least `1`; setting it again replaces the previous size.
+  pub fn clip_distances(&mut self, size: u32) {
+    self.set_layout(StageLayout::ClipDistances(size));
+  }
+
+  /// Declare a new output captured by transform feedback to `buffer`, `offset` bytes into the captured vertices —
+  /// `layout(xfb_buffer = …, xfb_offset = …) out`.
+  ///
+  /// Captured outputs are tightly packed, 4 bytes per component. Offsets which are not a multiple of 4, as well as
+  /// outputs overlapping in the same buffer, are reported by [`Shader::check`]. See [`ShaderBuilder::xfb_stride`] to
+  /// leave room between the captured vertices.
+  ///
+  /// # Safety
+  ///
+  /// The `name` is used verbatim in the generated code, so it must be a valid identifier in the target language and
+  /// must not clash with another declaration or built-in.
+  pub unsafe fn xfb_output<T>(&mut self, name: &str, buffer: u32, offset: u32) -> Var<T>
+  where
+    T: ToType,
+  {
+    self.layout_output(name.to_owned(), OutputLayout::xfb(buffer, offset))
+  }
+
+  /// Set the stride of the vertices captured by transform feedback to `buffer`, in bytes — `layout(xfb_buffer = …,
+  /// xfb_stride = …) out`.
+  ///
+  /// Without it, the stride is the end of the last output captured to the buffer. Strides which are not a multiple of
+  /// 4, or which are shorter than the captured outputs, are reported by [`Shader::check`]; setting it again replaces
+  /// the previous stride of the buffer.
+  pub fn xfb_stride(&mut self, buffer: u32, stride: u32) {
+    self.set_layout(StageLayout::XfbStride(buffer, stride));
+  }
+}
+
+impl ShaderBuilder<stage::TessCtrl> {
   /// Create a new _tessellation control shader_.
   ///
   /// This method creates a [`Shader`] that can be used as _tessellation control shader_. This is enforced by the
@@ -194,15 +745,52 @@ impl ShaderBuilder {
   /// use shades::{Scope, ShaderBuilder, V3, vec4};
   ///
   /// let tess_ctrl_shader = ShaderBuilder::new_tess_ctrl_shader(|mut s, patch| {
+  ///   let output = s.vertices::<3>();
+  ///
   ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     let id = patch.invocation_id.clone();
+  ///     s.set(output.at(id.clone()).position(), patch.input.at(id).position());
   ///     s.set(patch.tess_level_outer.at(0), 0.1);
   ///   })
   /// });
   /// ```
-  pub fn new_tess_ctrl_shader(f: impl FnOnce(Self, TessCtrlShaderEnv) -> Shader) -> Shader {
+  pub fn new_tess_ctrl_shader(
+    f: impl FnOnce(Self, TessCtrlShaderEnv) -> Shader<stage::TessCtrl>,
+  ) -> Shader<stage::TessCtrl> {
     f(Self::new(), TessCtrlShaderEnv::new())
   }
 
+  /// Set the number of vertices of the output patch to `N` — `layout(vertices = N) out` — and get the per-vertex
+  /// outputs, typed with that size.
+  ///
+  /// The shader runs once per output vertex, and [`TessCtrlShaderEnv::invocation_id`] is the index of the vertex to
+  /// write in the returned array. Setting the number of vertices is mandatory; [`Shader::check`] reports shaders
+  /// lacking it. `N` must be at least `1`; setting it again replaces the previous number.
+  pub fn vertices<const N: usize>(&mut self) -> Var<[TessControlPerVertexOut; N]> {
+    self.set_layout(StageLayout::Vertices(N as u32));
+    Var::new(ScopedHandle::BuiltIn(BuiltIn::TessCtrl(
+      TessCtrlBuiltIn::Out,
+    )))
+  }
+
+  /// Declare a new per-patch output — `patch out` —, shared between all the invocations of the patch and read by the
+  /// tessellation evaluation shader as a per-patch input of the same name.
+  ///
+  /// # Safety
+  ///
+  /// The `name` is used verbatim in the generated code, so it must be a valid identifier in the target language and
+  /// must not clash with another declaration or built-in.
+  pub unsafe fn patch_output<T>(&mut self, name: &str) -> Var<T>
+  where
+    T: ToType,
+  {
+    let name = name.to_owned();
+    self.decls.push(ShaderDecl::PatchOut(name.clone(), T::ty()));
+    Var::new(ScopedHandle::Output(name))
+  }
+}
+
+impl ShaderBuilder<stage::TessEval> {
   /// Create a new _tessellation evaluation shader_.
   ///
   /// This method creates a [`Shader`] that can be used as _tessellation evaluation shader_. This is enforced by the
@@ -222,20 +810,80 @@ impl ShaderBuilder {
   /// # Examples
   ///
   /// ```
-  /// use shades::{Scope, ShaderBuilder, V3, inputs, vec4};
+  /// use shades::{Scope, ShaderBuilder, TessPrimitive, TessSpacing, TessWinding, V3, inputs, vec4};
   ///
   /// let tess_eval_shader = ShaderBuilder::new_tess_eval_shader(|mut s, patch| {
   ///   inputs!(s, position: V3<f32>);
+  ///   patch.tessellation(&mut s, TessPrimitive::Triangles, TessSpacing::Equal, TessWinding::Ccw);
   ///
   ///   s.main_fun(|s: &mut Scope<()>| {
   ///     s.set(patch.position, vec4!(position, 1.));
   ///   })
   /// });
   /// ```
-  pub fn new_tess_eval_shader(f: impl FnOnce(Self, TessEvalShaderEnv) -> Shader) -> Shader {
+  pub fn new_tess_eval_shader(
+    f: impl FnOnce(Self, TessEvalShaderEnv) -> Shader<stage::TessEval>,
+  ) -> Shader<stage::TessEval> {
     f(Self::new(), TessEvalShaderEnv::new())
   }
 
+  /// Declare a new per-patch input — `patch in` —, written by the tessellation control shader as a per-patch output of
+  /// the same name.
+  ///
+  /// # Safety
+  ///
+  /// The `name` is used verbatim in the generated code, so it must be a valid identifier in the target language and
+  /// must not clash with another declaration or built-in.
+  pub unsafe fn patch_input<T>(&mut self, name: &str) -> Var<T>
+  where
+    T: ToType,
+  {
+    let name = name.to_owned();
+    self.decls.push(ShaderDecl::PatchIn(name.clone(), T::ty()));
+    Var::new(ScopedHandle::Input(name))
+  }
+
+  /// Redeclare [`TessEvalShaderEnv::clip_distance`] with `size` elements — `out float gl_ClipDistance[size]`.
+  ///
+  /// GLSL requires the size of the array to be known, either from this redeclaration or from indexing it with constants
+  /// only. `size` must be at least `1`; setting it again replaces the previous size.
+  pub fn clip_distances(&mut self, size: u32) {
+    self.set_layout(StageLayout::ClipDistances(size));
+  }
+
+  /// Redeclare [`TessEvalShaderEnv::cull_distance`] with `size` elements — `out float gl_CullDistance[size]`.
+  ///
+  /// See [`ShaderBuilder::clip_distances`].
+  pub fn cull_distances(&mut self, size: u32) {
+    self.set_layout(StageLayout::CullDistances(size));
+  }
+
+  /// Declare a new output captured by transform feedback to `buffer`, `offset` bytes into the captured vertices —
+  /// `layout(xfb_buffer = …, xfb_offset = …) out`.
+  ///
+  /// See [`ShaderBuilder::xfb_output`].
+  ///
+  /// # Safety
+  ///
+  /// The `name` is used verbatim in the generated code, so it must be a valid identifier in the target language and
+  /// must not clash with another declaration or built-in.
+  pub unsafe fn xfb_output<T>(&mut self, name: &str, buffer: u32, offset: u32) -> Var<T>
+  where
+    T: ToType,
+  {
+    self.layout_output(name.to_owned(), OutputLayout::xfb(buffer, offset))
+  }
+
+  /// Set the stride of the vertices captured by transform feedback to `buffer`, in bytes — `layout(xfb_buffer = …,
+  /// xfb_stride = …) out`.
+  ///
+  /// See [`ShaderBuilder::xfb_stride`].
+  pub fn xfb_stride(&mut self, buffer: u32, stride: u32) {
+    self.set_layout(StageLayout::XfbStride(buffer, stride));
+  }
+}
+
+impl ShaderBuilder<stage::Geometry> {
   /// Create a new _geometry shader_.
   ///
   /// This method creates a [`Shader`] that can be used as _geometry shader_. This is enforced by the fact only this
@@ -255,19 +903,94 @@ impl ShaderBuilder {
   ///
   /// ```
   /// use shades::{LoopScope, Scope, ShaderBuilder, V3, vec4};
+  /// use shades::primitive::Triangles;
   ///
   /// let geo_shader = ShaderBuilder::new_geometry_shader(|mut s, vertex| {
+  ///   let input = s.input_primitive(Triangles);
+  ///
   ///   s.main_fun(|s: &mut Scope<()>| {
-  ///     s.loop_for(0, |i| i.lt(3), |i| i + 1, |s: &mut LoopScope<()>, i| {
-  ///       s.set(vertex.position, vertex.input.at(i).position());
+  ///     s.for_each(input, |s: &mut LoopScope<()>, v| {
+  ///       s.set(&vertex.position, v.position());
+  ///       vertex.emit_vertex(s);
   ///     });
+  ///
+  ///     vertex.end_primitive(s);
   ///   })
   /// });
   /// ```
-  pub fn new_geometry_shader(f: impl FnOnce(Self, GeometryShaderEnv) -> Shader) -> Shader {
+  pub fn new_geometry_shader(
+    f: impl FnOnce(Self, GeometryShaderEnv) -> Shader<stage::Geometry>,
+  ) -> Shader<stage::Geometry> {
     f(Self::new(), GeometryShaderEnv::new())
   }
 
+  /// Run `count` invocations of the shader per input primitive — `layout(invocations = count) in`.
+  ///
+  /// Each invocation gets its own [`GeometryShaderEnv::invocation_id`], from `0` to `count - 1`, which is typically
+  /// used to select the layer to render to, such as a face of a cube map or a cascade of a shadow map. `count` must be
+  /// at least `1`; setting it again replaces the previous count.
+  pub fn invocations(&mut self, count: u32) {
+    self.set_layout(StageLayout::Invocations(count));
+  }
+
+  /// Set the primitives read by the shader to `primitive` — `layout(triangles) in` — and get the per-vertex inputs,
+  /// typed with the number of vertices of those primitives.
+  ///
+  /// `primitive` is one of the types of the [`primitive`] module, which gives the number of vertices. The returned
+  /// array is [`GeometryShaderEnv::input`], sized, so that it can be iterated over with [`Scope::for_each`] and indexed
+  /// with bounds checked by [`Shader::check`]. Setting the input primitive is mandatory; [`Shader::check`] reports
+  /// shaders lacking it. Setting it again replaces the previous primitive.
+  pub fn input_primitive<P>(&mut self, _primitive: P) -> Expr<P::Vertices>
+  where
+    P: primitive::InputPrimitive,
+  {
+    self.set_layout(StageLayout::InputPrimitive(P::PRIMITIVE));
+    Expr::new(ErasedExpr::new_builtin(BuiltIn::Geometry(
+      GeometryBuiltIn::In,
+    )))
+  }
+
+  /// Redeclare [`GeometryShaderEnv::clip_distance`] with `size` elements — `out float gl_ClipDistance[size]`.
+  ///
+  /// GLSL requires the size of the array to be known, either from this redeclaration or from indexing it with constants
+  /// only. `size` must be at least `1`; setting it again replaces the previous size.
+  pub fn clip_distances(&mut self, size: u32) {
+    self.set_layout(StageLayout::ClipDistances(size));
+  }
+
+  /// Redeclare [`GeometryShaderEnv::cull_distance`] with `size` elements — `out float gl_CullDistance[size]`.
+  ///
+  /// See [`ShaderBuilder::clip_distances`].
+  pub fn cull_distances(&mut self, size: u32) {
+    self.set_layout(StageLayout::CullDistances(size));
+  }
+
+  /// Declare a new output captured by transform feedback to `buffer`, `offset` bytes into the captured vertices —
+  /// `layout(xfb_buffer = …, xfb_offset = …) out`.
+  ///
+  /// See [`ShaderBuilder::xfb_output`].
+  ///
+  /// # Safety
+  ///
+  /// The `name` is used verbatim in the generated code, so it must be a valid identifier in the target language and
+  /// must not clash with another declaration or built-in.
+  pub unsafe fn xfb_output<T>(&mut self, name: &str, buffer: u32, offset: u32) -> Var<T>
+  where
+    T: ToType,
+  {
+    self.layout_output(name.to_owned(), OutputLayout::xfb(buffer, offset))
+  }
+
+  /// Set the stride of the vertices captured by transform feedback to `buffer`, in bytes — `layout(xfb_buffer = …,
+  /// xfb_stride = …) out`.
+  ///
+  /// See [`ShaderBuilder::xfb_stride`].
+  pub fn xfb_stride(&mut self, buffer: u32, stride: u32) {
+    self.set_layout(StageLayout::XfbStride(buffer, stride));
+  }
+}
+
+impl ShaderBuilder<stage::Fragment> {
   /// Create a new _fragment shader_.
   ///
   /// This method creates a [`Shader`] that can be used as _fragment shader_. This is enforced by the fact only this
@@ -296,24 +1019,167 @@ impl ShaderBuilder {
   ///   })
   /// });
   /// ```
-  pub fn new_fragment_shader(f: impl FnOnce(Self, FragmentShaderEnv) -> Shader) -> Shader {
+  pub fn new_fragment_shader(
+    f: impl FnOnce(Self, FragmentShaderEnv) -> Shader<stage::Fragment>,
+  ) -> Shader<stage::Fragment> {
     f(Self::new(), FragmentShaderEnv::new())
   }
 
-  /// Create a new empty shader.
-  fn new() -> Self {
-    Self {
-      decls: Vec::new(),
-      next_fun_handle: 0,
-      next_global_handle: 0,
-    }
+  /// Declare a new output at `location`, as the blending source `index` — `layout(location = …, index = …) out`.
+  ///
+  /// Dual-source blending uses two outputs at the location `0`, of indices `0` and `1`, the second one being read by
+  /// the blend factors such as `SRC1_COLOR`. Indices other than `0` and `1`, as well as several outputs at the same
+  /// location and index, are reported by [`Shader::check`].
+  ///
+  /// # Safety
+  ///
+  /// The `name` is used verbatim in the generated code, so it must be a valid identifier in the target language and
+  /// must not clash with another declaration or built-in.
+  pub unsafe fn output_at<T>(&mut self, name: &str, location: u32, index: u32) -> Var<T>
+  where
+    T: ToType,
+  {
+    let layout = OutputLayout {
+      location: Some(location),
+      index: Some(index),
+      ..OutputLayout::default()
+    };
+    self.layout_output(name.to_owned(), layout)
   }
 
-  /// Create a new function in the shader and get its handle for future use.
+  /// Declare the output written to the color attachment `location` — `layout(location = …) out`.
   ///
-  /// This method requires to pass a closure encoding the argument(s) and return type of the function to create. The
-  /// closure’s body encodes the body of the function to create. The number of arguments will directly impact the
-  /// number of arguments the created function will have. The return type can be [`()`](unit) if the function doesn’t
+  /// This is the building block of multiple render targets: each attachment gets its own typed output, named
+  /// `frag_color_{location}`. Several outputs at the same location are reported by [`Shader::check`].
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{Scope, ShaderBuilder, V2, V4, vec2, vec4};
+  ///
+  /// let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+  ///   let albedo = s.color_output::<V4<f32>>(0);
+  ///   let normal = s.color_output::<V2<f32>>(1);
+  ///
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     s.set(albedo, vec4!(1., 1., 1., 1.));
+  ///     s.set(normal, vec2!(0., 1.));
+  ///   })
+  /// });
+  ///
+  /// assert!(shader.check().is_ok());
+  ///
+  /// let code = shades::writer::glsl::write_shader_to_str(&shader).unwrap();
+  /// assert!(code.starts_with("layout(location = 0) out vec4 frag_color_0;\nlayout(location = 1) out vec2 frag_color_1;\n"));
+  /// ```
+  pub fn color_output<T>(&mut self, location: u32) -> Var<T>
+  where
+    T: ToType,
+  {
+    let layout = OutputLayout {
+      location: Some(location),
+      ..OutputLayout::default()
+    };
+    self.layout_output(format!("frag_color_{}", location), layout)
+  }
+
+  /// Run the depth and stencil tests before the shader instead of after it — `layout(early_fragment_tests) in`.
+  ///
+  /// Drivers usually do so already, unless the shader has side effects, such as image stores, which must then only
+  /// happen for fragments passing the tests. Writes to [`FragmentShaderEnv::frag_depth`] are ignored.
+  pub fn early_fragment_tests(&mut self) {
+    self.set_layout(StageLayout::EarlyFragmentTests);
+  }
+}
+
+impl ShaderBuilder<stage::Compute> {
+  /// Create a new _compute shader_.
+  ///
+  /// This method creates a [`Shader`] that can be used as _compute shader_. This is enforced by the fact only this
+  /// method authorized to build a compute [`Shader`] by using the [`ComputeShaderEnv`] argument passed to the input
+  /// closure.
+  ///
+  /// Compute shaders run in work groups, whose size must be set with [`ShaderBuilder::local_size`].
+  ///
+  /// # Return
+  ///
+  /// This method returns the fully built [`Shader`], which cannot be mutated anymore once it has been built,
+  /// and can be passed to various [`writers`](crate::writer) to generate actual code for target shading languages.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{Barriers as _, Scope, ShaderBuilder};
+  ///
+  /// let compute_shader = ShaderBuilder::new_compute_shader(|mut s, compute| {
+  ///   s.local_size(64, 1, 1);
+  ///
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     let index = s.var(compute.local_invocation_index.clone());
+  ///     compute.barrier(s);
+  ///   })
+  /// });
+  /// ```
+  pub fn new_compute_shader(
+    f: impl FnOnce(Self, ComputeShaderEnv) -> Shader<stage::Compute>,
+  ) -> Shader<stage::Compute> {
+    f(Self::new(), ComputeShaderEnv::new())
+  }
+
+  /// Set the size of the work groups — `layout(local_size_x = x, local_size_y = y, local_size_z = z) in`.
+  ///
+  /// Each dimension is either a constant, given as a [`u32`], or a [`LocalSize::Spec`]ialization constant. Setting the
+  /// local size is mandatory; [`Shader::check`] reports shaders lacking it. Setting it again replaces the previous size.
+  pub fn local_size(
+    &mut self,
+    x: impl Into<LocalSize>,
+    y: impl Into<LocalSize>,
+    z: impl Into<LocalSize>,
+  ) {
+    self.set_layout(StageLayout::LocalSize([x.into(), y.into(), z.into()]));
+  }
+
+  /// Declare a new variable in shared memory — `shared`.
+  ///
+  /// Shared variables are visible by all the invocations of a work group, which must synchronize their accesses with
+  /// [`Barriers::barrier`]. They cannot be initialized and hold undefined values until written.
+  pub fn shared<T>(&mut self) -> Var<T>
+  where
+    T: ToType,
+  {
+    let handle = self.next_global_handle;
+    self.next_global_handle += 1;
+
+    self.decls.push(ShaderDecl::Shared(handle, T::ty()));
+
+    Var::new(ScopedHandle::global(handle))
+  }
+}
+
+impl<S> ShaderBuilder<S> {
+  /// Create a new empty shader.
+  fn new() -> Self {
+    Self {
+      decls: Vec::new(),
+      capabilities: Vec::new(),
+      extensions: Vec::new(),
+      directives: Vec::new(),
+      layouts: Vec::new(),
+      attributes: Vec::new(),
+      fun_names: BTreeMap::new(),
+      global_names: BTreeMap::new(),
+      imports: Vec::new(),
+      next_fun_handle: 0,
+      next_global_handle: 0,
+      _phantom: PhantomData,
+    }
+  }
+
+  /// Create a new function in the shader and get its handle for future use.
+  ///
+  /// This method requires to pass a closure encoding the argument(s) and return type of the function to create. The
+  /// closure’s body encodes the body of the function to create. The number of arguments will directly impact the
+  /// number of arguments the created function will have. The return type can be [`()`](unit) if the function doesn’t
   /// return anything or [`Expr<T>`] if it does return something.
   ///
   /// The first argument of the closure is a mutable reference on a [`Scope`]. Its type parameter must be set to the
@@ -444,6 +1310,44 @@ impl ShaderBuilder {
     }
   }
 
+  /// Create a new function named `name` in the generated code, instead of a generated name.
+  ///
+  /// This is otherwise the same as [`ShaderBuilder::fun`]. Names only make the generated code easier to read, so they
+  /// don’t have to be valid identifiers nor unique: names which are not are fixed when writing the shader, as with
+  /// [`pass::rename_identifiers`] — a second `luminance` function is written `luminance0`, for instance.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{Exponential as _, Expr, Scope, ShaderBuilder, lit, writer::glsl};
+  ///
+  /// let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+  ///   let gamma = s.named_constant("gamma", 2.2);
+  ///   let decode = s.named_fun("decode", move |_: &mut Scope<Expr<f32>>, c: Expr<f32>| {
+  ///     c.pow(gamma.clone())
+  ///   });
+  ///
+  ///   s.main_fun(move |s: &mut Scope<()>| {
+  ///     s.var(decode.call(lit!(0.5)));
+  ///   })
+  /// });
+  ///
+  /// let code = glsl::write_shader_to_str(&shader).unwrap();
+  /// assert!(code.contains("const float gamma = 2.2;"));
+  /// assert!(code.contains("float decode(float arg_0) {"));
+  /// assert!(code.contains("float var_0_0 = decode(.5);"));
+  /// ```
+  pub fn named_fun<F, R, A>(&mut self, name: &str, f: F) -> FunHandle<R, A>
+  where
+    F: ToFun<R, A>,
+  {
+    let fun = self.fun(f);
+    self
+      .fun_names
+      .insert(self.next_fun_handle - 1, name.to_owned());
+    fun
+  }
+
   /// Declare the `main` function of the shader stage.
   ///
   /// This method is very similar to [`ShaderBuilder::fun`] in the sense it declares a function. However, it declares the special
@@ -468,7 +1372,7 @@ impl ShaderBuilder {
   ///   })
   /// });
   /// ```
-  pub fn main_fun<F, R>(mut self, f: F) -> Shader
+  pub fn main_fun<F, R>(mut self, f: F) -> Shader<S>
   where
     F: ToFun<R, ()>,
   {
@@ -512,9 +1416,67 @@ impl ShaderBuilder {
     Expr::new(ErasedExpr::Var(ScopedHandle::global(handle)))
   }
 
+  /// Declare a new constant array holding `items`, shared between all functions and constants that come next.
+  ///
+  /// This embeds tables computed on the host — kernels, lookup tables, sample offsets, etc. — in the shader. As the
+  /// number of items is only known when the shader is built, the constant is an unsized [`Expr<[T]>`](Expr), which
+  /// [length](Expr::length) is still known by the target language.
+  ///
+  /// [`EmptyArray`] is returned if `items` is empty, as arrays cannot be empty.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{Scope, ShaderBuilder};
+  ///
+  /// let weights: Vec<f32> = (0..9).map(|i| (-((i as f32 - 4.) / 2.).powi(2)).exp()).collect();
+  ///
+  /// let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+  ///   let weights = s.constant_array(&weights).unwrap();
+  ///
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     let _center = s.var(weights.at(4));
+  ///   })
+  /// });
+  /// ```
+  pub fn constant_array<T>(&mut self, items: &[T]) -> Result<Expr<[T]>, EmptyArray>
+  where
+    T: Clone + ToType,
+    Expr<T>: From<T>,
+  {
+    let array = Expr::<[T]>::try_from(items)?;
+    let ty = array_ty::<T>(items.len());
+
+    let handle = self.next_global_handle;
+    self.next_global_handle += 1;
+
+    self.decls.push(ShaderDecl::Const(handle, ty, array.erased));
+
+    Ok(Expr::new(ErasedExpr::Var(ScopedHandle::global(handle))))
+  }
+
+  /// Declare a new constant named `name` in the generated code, instead of a generated name.
+  ///
+  /// See [`ShaderBuilder::named_fun`] for further details.
+  pub fn named_constant<T>(&mut self, name: &str, expr: impl Into<Expr<T>>) -> Expr<T>
+  where
+    T: ToType,
+  {
+    let constant = self.constant(expr);
+    self
+      .global_names
+      .insert(self.next_global_handle - 1, name.to_owned());
+    constant
+  }
+
   /// Declare a new input, shared between all functions and constants that come next.
   ///
-  /// TODO
+  /// You are advised to use the [`inputs!`](inputs) macro instead.
+  ///
+  /// # Safety
+  ///
+  /// The `name` is used verbatim in the generated code, so it must be a valid identifier in the target language and
+  /// must not clash with another declaration or built-in.
   pub unsafe fn input<T>(&mut self, name: &str) -> Var<T>
   where
     T: ToType,
@@ -524,7 +1486,14 @@ impl ShaderBuilder {
     Var::new(ScopedHandle::Input(name))
   }
 
-  /// TODO
+  /// Declare a new output, shared between all functions and constants that come next.
+  ///
+  /// You are advised to use the [`outputs!`](outputs) macro instead.
+  ///
+  /// # Safety
+  ///
+  /// The `name` is used verbatim in the generated code, so it must be a valid identifier in the target language and
+  /// must not clash with another declaration or built-in.
   pub unsafe fn output<T>(&mut self, name: &str) -> Var<T>
   where
     T: ToType,
@@ -534,6 +1503,14 @@ impl ShaderBuilder {
     Var::new(ScopedHandle::Output(name))
   }
 
+  /// Declare a new uniform, shared between all functions and constants that come next.
+  ///
+  /// You are advised to use the [`uniforms!`](uniforms) macro instead.
+  ///
+  /// # Safety
+  ///
+  /// The `name` is used verbatim in the generated code, so it must be a valid identifier in the target language and
+  /// must not clash with another declaration or built-in.
   pub unsafe fn uniform<T>(&mut self, name: &str) -> Var<T>
   where
     T: ToType,
@@ -542,4958 +1519,9550 @@ impl ShaderBuilder {
     self.decls.push(ShaderDecl::Uniform(name.clone(), T::ty()));
     Var::new(ScopedHandle::uniform(name))
   }
-}
 
-/// Shader declaration.
-///
-/// This contain everything that can be declared at top-level of a shader.
-#[derive(Debug)]
-pub(crate) enum ShaderDecl {
-  /// The `main` function declaration. The [`ErasedFun`] is a function that returns nothing and has no argument.
-  Main(ErasedFun),
+  /// Annotate the next declaration with a comment — `// text`.
+  ///
+  /// See [`Scope::comment`] for further details, and to comment the code of functions.
+  pub fn comment(&mut self, text: &str) {
+    self.decls.push(ShaderDecl::Comment(text.to_owned()));
+  }
 
-  /// A function definition.
+  /// Require a [`Capability`] for this shader.
   ///
-  /// The [`u16`] represents the _handle_ of the function, and is unique for each shader stage. The [`ErasedFun`] is
-  /// the representation of the function definition.
-  FunDef(u16, ErasedFun),
+  /// Requiring the same capability several times has no additional effect.
+  pub fn require(&mut self, capability: Capability) {
+    if !self.capabilities.contains(&capability) {
+      self.capabilities.push(capability);
+    }
+  }
 
-  /// A constant definition.
+  /// Declare the `behavior` of the compiler towards the extension `name` — `#extension name : behavior`.
   ///
-  /// The [`u16`] represents the _handle_ of the constant, and is unique for each shader stage. The [`Type`] is the
-  /// the type of the constant expression. [`ErasedExpr`] is the representation of the constant.
-  Const(u16, Type, ErasedExpr),
+  /// The extensions of the [capabilities](Capability) of the shader are required automatically; this is meant for the
+  /// other ones, such as vendor extensions. Declarations are written after the extensions of the capabilities, so they
+  /// take precedence over them, and declaring the same extension again replaces its behavior.
+  pub fn require_extension(&mut self, name: &str, behavior: Behavior) {
+    match self.extensions.iter_mut().find(|(n, _)| n == name) {
+      Some(previous) => previous.1 = behavior,
+      None => self.extensions.push((name.to_owned(), behavior)),
+    }
+  }
 
-  /// An input definition.
+  /// Define the preprocessor macro `name` as `value` — `#define name value`.
   ///
-  /// The [`u16`] represents the _handle_ of the input, and is unique for each shader stage. The [`Type`] is the
-  /// the type of the input.
-  In(String, Type),
+  /// The generated code doesn’t use macros itself: this is meant for engines preprocessing the code or injecting code
+  /// of their own. Defines and [pragmas](ShaderBuilder::pragma) are written first — right after `#version` for the
+  /// writers emitting it —, in declaration order; `value` can be empty, and defining the same macro again replaces its
+  /// value.
+  pub fn define(&mut self, name: &str, value: &str) {
+    let define = Directive::Define(name.to_owned(), value.to_owned());
 
-  /// An output definition.
+    match self
+      .directives
+      .iter_mut()
+      .find(|d| matches!(d, Directive::Define(n, _) if n == name))
+    {
+      Some(previous) => *previous = define,
+      None => self.directives.push(define),
+    }
+  }
+
+  /// Add the pragma `pragma` — `#pragma pragma`, such as `#pragma optimize(off)`.
   ///
-  /// The [`u16`] represents the _handle_ of the output, and is unique for each shader stage. The [`Type`] is the
-  /// the type of the output.
-  Out(String, Type),
+  /// See [`ShaderBuilder::define`].
+  pub fn pragma(&mut self, pragma: &str) {
+    self.directives.push(Directive::Pragma(pragma.to_owned()));
+  }
 
-  /// A uniform definition.
-  Uniform(String, Type),
-}
+  /// Declare a new output with layout qualifiers.
+  fn layout_output<T>(&mut self, name: String, layout: OutputLayout) -> Var<T>
+  where
+    T: ToType,
+  {
+    self
+      .decls
+      .push(ShaderDecl::LayoutOut(name.clone(), T::ty(), layout));
+    Var::new(ScopedHandle::Output(name))
+  }
 
-macro_rules! make_vn {
-  ($t:ident, $dim:expr) => {
-    /// Scalar vectors.
-    ///
-    /// Scalar vectors come into three flavors, based on the dimension used:
-    ///
-    /// - Two dimensions (2D): [`V2<T>`].
-    /// - Three dimensions (3D): [`V3<T>`].
-    /// - Four dimensions (4D): [`V4<T>`].
-    ///
-    /// Each type implements the [`From`] trait for sized array. For instance, if you want to make a `V3<f32>` from
-    /// constants / literals, you can simply use the implementor `From<[f32; 3]> for V3<f32>`.
-    ///
-    /// A builder macro version exists for each flavor:
-    ///
-    /// - [`vec2`]: build [`V2<T>`].
-    /// - [`vec3`]: build [`V3<T>`].
-    /// - [`vec4`]: build [`V4<T>`].
-    ///
-    /// Those three macros can also be used with literals.
-    #[derive(Clone, Debug, PartialEq)]
-    pub struct $t<T>([T; $dim]);
+  /// Set a stage-wide layout qualifier, replacing the previous one of the same kind.
+  fn set_layout(&mut self, layout: StageLayout) {
+    let kind = layout.name();
 
-    impl<T> From<[T; $dim]> for $t<T> {
-      fn from(a: [T; $dim]) -> Self {
-        Self(a)
+    match self.layouts.iter_mut().find(|l| l.name() == kind) {
+      Some(previous) => *previous = layout,
+      None => self.layouts.push(layout),
+    }
+  }
+
+  /// Import a [`ShaderModule`](module::ShaderModule) into this shader.
+  ///
+  /// The functions and constants of the module are added to the shader, and what the module exports is returned,
+  /// remapped to the handles of the added declarations. Importing the same module several times — directly or
+  /// through other modules — only adds its declarations once. Capabilities and extensions required by the module are
+  /// required by the shader as well.
+  ///
+  /// See the [`module`] module for further details.
+  pub fn import<T>(&mut self, module: &module::ShaderModule<T>) -> T
+  where
+    T: module::Exports,
+  {
+    if let Some(import) = self.imports.iter().find(|i| i.id == module.id) {
+      return module.exports.relocate(&import.relocation);
+    }
+
+    let mut relocation = module::Relocation::new(self.next_fun_handle, self.next_global_handle);
+
+    // modules imported by the module and already imported in this shader are not copied again
+    for import in &module.builder.imports {
+      if let Some(existing) = self.imports.iter().find(|i| i.id == import.id) {
+        relocation.redirect(import, existing);
       }
     }
-  };
-}
 
-make_vn!(V2, 2);
-make_vn!(V3, 3);
-make_vn!(V4, 4);
+    // comments annotate the declaration following them, and are dropped along with it
+    let mut comments = Vec::new();
+
+    for decl in &module.builder.decls {
+      if let ShaderDecl::Comment(_) = decl {
+        comments.push(relocation.decl(decl));
+      } else if relocation.is_redirected(decl) {
+        comments.clear();
+      } else {
+        self.decls.append(&mut comments);
+        self.decls.push(relocation.decl(decl));
+      }
+    }
 
-/// Representation of an expression.
-#[derive(Clone, Debug, PartialEq)]
-enum ErasedExpr {
-  // scalars
-  LitInt(i32),
-  LitUInt(u32),
-  LitFloat(f32),
-  LitBool(bool),
-  // vectors
-  LitInt2([i32; 2]),
-  LitUInt2([u32; 2]),
-  LitFloat2([f32; 2]),
-  LitBool2([bool; 2]),
-  LitInt3([i32; 3]),
-  LitUInt3([u32; 3]),
-  LitFloat3([f32; 3]),
-  LitBool3([bool; 3]),
-  LitInt4([i32; 4]),
-  LitUInt4([u32; 4]),
-  LitFloat4([f32; 4]),
-  LitBool4([bool; 4]),
-  // matrices
-  LitM22(M22),
-  // LitM23(M23),
-  // LitM24(M24),
-  // LitM32(M32),
-  LitM33(M33),
-  // LitM34(M34),
-  // LitM42(M42),
-  // LitM43(M43),
-  LitM44(M44),
-  // arrays
-  Array(Type, Vec<ErasedExpr>),
-  // var
-  Var(ScopedHandle),
-  // built-in functions and operators
-  Not(Box<Self>),
-  And(Box<Self>, Box<Self>),
-  Or(Box<Self>, Box<Self>),
-  Xor(Box<Self>, Box<Self>),
-  BitOr(Box<Self>, Box<Self>),
-  BitAnd(Box<Self>, Box<Self>),
-  BitXor(Box<Self>, Box<Self>),
-  Neg(Box<Self>),
-  Add(Box<Self>, Box<Self>),
-  Sub(Box<Self>, Box<Self>),
-  Mul(Box<Self>, Box<Self>),
-  Div(Box<Self>, Box<Self>),
-  Rem(Box<Self>, Box<Self>),
-  Shl(Box<Self>, Box<Self>),
-  Shr(Box<Self>, Box<Self>),
-  Eq(Box<Self>, Box<Self>),
-  Neq(Box<Self>, Box<Self>),
-  Lt(Box<Self>, Box<Self>),
-  Lte(Box<Self>, Box<Self>),
-  Gt(Box<Self>, Box<Self>),
-  Gte(Box<Self>, Box<Self>),
-  // function call
-  FunCall(ErasedFunHandle, Vec<Self>),
-  // swizzle
-  Swizzle(Box<Self>, Swizzle),
-  // field expression, as in a struct Foo { float x; }, foo.x is an Expr representing the x field on object foo
-  Field { object: Box<Self>, field: Box<Self> },
-  ArrayLookup { object: Box<Self>, index: Box<Self> },
-}
+    self.decls.append(&mut comments);
+    self.next_fun_handle += module.builder.next_fun_handle;
+    self.next_global_handle += module.builder.next_global_handle;
 
-impl ErasedExpr {
-  const fn new_builtin(builtin: BuiltIn) -> Self {
-    ErasedExpr::Var(ScopedHandle::builtin(builtin))
-  }
-}
+    for (&handle, name) in &module.builder.fun_names {
+      self
+        .fun_names
+        .insert(relocation.relocated_fun(handle), name.clone());
+    }
 
-/// Expression representation.
-///
-/// An expression is anything that carries a (typed) value and that can be combined in various ways with other
-/// expressions. A literal, a constant or a variable are all expressions. The sum (as in `a + b`) of two expressions is
-/// also an expression. A function call returning an expression is also an expression, as in `a * sin(b)`. Accessing an
-/// element in an array (which is an expression as it carries items) via an index (an expression) is also an
-/// expression — e.g. `levels[y * HEIGHT + x] * size`. The same thing applies to field access, swizzling, etc. etc.
-///
-/// On a general note, expressions are pretty central to the EDSL, as they are the _lower level concept_ you will be
-/// able to manipulate. Expressions are side effect free, so a variable, for instance, can either be considered as an
-/// expression or not. If `x` is a variable (see [`Var`]), then `x * 10` is an expression, but using `x` to mutate its
-/// content does not make a use of `x` as an expression. It means that expressions are read-only and even though you
-/// can go from higher constructs (like variables) to expressions, the opposite direction is forbidden.
-///
-/// # Literals
-///
-/// The most obvious kind of expression is a literal — e.g. `1`, `false`, `3.14`, etc. Any type `T` that defines an
-/// implementor `From<T> for Expr<T>` can be used as literal. You can then use, for instance, `1.into()`,
-/// `Expr::from(1)`, etc.
-///
-/// A much better and easier way to create literals is to use the [`lit!`](lit) macro, which basically does the lifting
-/// for you, but also accept more forms to create more complex literals, such as scalar vectors. See its documentation
-/// for further details.
-///
-/// It’s important to notice that because of how Rust infers type, type ambiguities might occur when using literals —
-/// hence, the use of [`lit!`](lit) should help. For instance, in `1 + 2`, the type of `1` is ambiguous because of how
-/// the implementors for [`Add`](std::ops::Add) are picked. In such a case, you are advised to use [`lit!`](lit).
-///
-/// ## Automatic lifting
-///
-/// Sometimes, you will want to pass literals to form other expressions, function calls, etc. Most of the API has been
-/// written in a way that if no ambiguity would occur, then you can use the Rust type directly. For instance, if `x`
-/// has the type `Expr<i32>`, then `x + 1` is the same as `x + lit!(1)`. You can use this property with literals too:
-/// `lit!(1) + 2 + 3 + 4`.
-///
-/// That automatic lifting is valid for a lot of traits and methods throughout this crate.
-///
-/// # Expressions from side-effects
-///
-/// Some side-effects will create expressions, such as creating a variable or a constant. Most of the time, you
-/// shouldn’t have to worry about the type of the expression as it should be inferred based on the side-effect.
-///
-/// # Expression macros
-///
-/// Some macros will create expressions for you, such as [`lit!`](lit), [`vec2!`](vec2), [`vec3!`](vec3) and
-/// [`vec4!`](vec4) or the [`sw!`](sw) macros. Most of the time, those macros will work by automatically adding a
-/// reference (`&`) to their arguments so that you don’t have to worry about that either.
-#[derive(Debug)]
-pub struct Expr<T>
-where
-  T: ?Sized,
-{
-  erased: ErasedExpr,
-  _phantom: PhantomData<T>,
-}
+    for (&handle, name) in &module.builder.global_names {
+      self
+        .global_names
+        .insert(relocation.relocated_global(handle), name.clone());
+    }
 
-impl<T> From<&'_ Self> for Expr<T>
-where
-  T: ?Sized,
-{
-  fn from(e: &Self) -> Self {
-    Self::new(e.erased.clone())
-  }
-}
+    for &capability in &module.builder.capabilities {
+      self.require(capability);
+    }
 
-impl<T> Clone for Expr<T>
-where
-  T: ?Sized,
-{
-  fn clone(&self) -> Self {
-    Self::new(self.erased.clone())
-  }
-}
+    for (name, behavior) in &module.builder.extensions {
+      self.require_extension(name, *behavior);
+    }
 
-impl<T> Expr<T>
-where
-  T: ?Sized,
-{
-  /// Type an [`ErasedExpr`] and return it wrapped in [`Expr<T>`].
-  const fn new(erased: ErasedExpr) -> Self {
-    Self {
-      erased,
-      _phantom: PhantomData,
+    // modules imported by the module are now available in this shader as well
+    for import in &module.builder.imports {
+      if self.imports.iter().all(|i| i.id != import.id) {
+        self.imports.push(import.then(&relocation));
+      }
     }
-  }
 
-  /// Equality expression.
-  ///
-  /// This method builds an expression representing the equality between two expressions.
-  ///
-  /// # Return
-  ///
-  /// An [`Expr<bool>`] representing the equality between the two input expressions.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::{lit, vec2};
-  ///
-  /// let _ = lit!(1).eq(1); // 1 == 1;
-  /// let _ = vec2!(1., 2.).eq(vec2!(0., 0.)); // vec2(1., 2.) == vec2(0., 0.)
-  /// # s.main_fun(|s: &mut Scope<()>| {})
-  /// # });
-  /// ```
-  pub fn eq(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
-    Expr::new(ErasedExpr::Eq(
-      Box::new(self.erased.clone()),
-      Box::new(rhs.into().erased),
-    ))
+    let exports = module.exports.relocate(&relocation);
+    self.imports.push(module::Import::new(module, relocation));
+
+    exports
   }
 
-  /// Inequality expression.
-  ///
-  /// This method builds an expression representing the inequality between two expressions.
-  ///
-  /// # Return
-  ///
-  /// An [`Expr<bool>`] representing the inequality between the two input expressions.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::{lit, vec2};
+  /// Enable subgroup operations for this shader.
   ///
-  /// let _ = lit!(1).neq(1); // 1 != 1;
-  /// let _ = vec2!(1., 2.).eq(vec2!(0., 0.)); // vec2(1., 2.) != vec2(0., 0.)
-  /// # s.main_fun(|s: &mut Scope<()>| {})
-  /// # });
-  /// ```
-  pub fn neq(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
-    Expr::new(ErasedExpr::Neq(
-      Box::new(self.erased.clone()),
-      Box::new(rhs.into().erased),
-    ))
+  /// This requires [`Capability::Subgroup`] and gives access to the subgroup built-ins and functions.
+  pub fn subgroups(&mut self) -> subgroup::Subgroups {
+    self.require(Capability::Subgroup);
+    subgroup::Subgroups::new()
   }
 }
 
-/// Trait allowing to create 2D scalar vector ([`V2`])constructors.
-/// 2D scalar vectors can be created from either two sole scalars or a single 2D scalar vector (identity function).
-///
+/// Optional capabilities a shader might require.
 ///
-/// The `A` type variable represents the arguments type. In the case of several arguments, tuples are used.
-///
-/// You are advised to use the [`vec2!`](vec2) macro instead as the interface of this function is not really
-/// user-friendly.
-pub trait Vec2<A> {
-  /// Make a [`V2`] from `A`.
-  fn vec2(args: A) -> Self;
+/// Capabilities are not available on every target; writers translate them into the requirements of their target
+/// language — e.g. extensions in GLSL.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Capability {
+  /// Subgroup operations; see the [`subgroup`] module.
+  Subgroup,
 }
 
-impl<T> Vec2<(Expr<T>, Expr<T>)> for Expr<V2<T>> {
-  fn vec2(args: (Expr<T>, Expr<T>)) -> Self {
-    let (x, y) = args;
-    Expr::new(ErasedExpr::FunCall(
-      ErasedFunHandle::Vec2,
-      vec![x.erased, y.erased],
-    ))
+/// Preprocessor directive written before anything else, such as `#define` or `#pragma`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum Directive {
+  /// Macro definition: name, then value.
+  Define(String, String),
+
+  /// Pragma.
+  Pragma(String),
+}
+
+impl Directive {
+  /// Human-readable name of the directive.
+  fn name(&self) -> String {
+    match self {
+      Directive::Define(name, _) => format!("define {}", name),
+      Directive::Pragma(pragma) => format!("pragma {}", pragma),
+    }
   }
 }
 
-/// Trait allowing to create 3D scalar vector ([`V3`])constructors.
-///
-/// 3D scalar vectors can be created from either three sole scalars, a single 2D scalar vector with a single scalar or
-/// a single 3D scalar vector (identity function).
-///
-/// The `A` type variable represents the arguments type. In the case of several arguments, tuples are used.
+/// Behavior of the compiler towards an extension, declared with [`ShaderBuilder::require_extension`].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Behavior {
+  /// The extension is used, and the shader is rejected if it is not supported — `require`.
+  Require,
+
+  /// The extension is used, with a warning if it is not supported — `enable`.
+  Enable,
+
+  /// The extension is used, with a warning for every use — `warn`.
+  Warn,
+
+  /// The extension is not used — `disable`.
+  Disable,
+}
+
+/// Stage-wide layout qualifier, such as `layout(invocations = 4) in`.
 ///
-/// You are advised to use the [`vec3!`](vec3) macro instead as the interface of this function is not really
-/// user-friendly.
-pub trait Vec3<A> {
-  /// Make a [`V3`] from `A`.
-  fn vec3(args: A) -> Self;
+/// Layout qualifiers only make sense in a given stage, so they are set by methods of the [`ShaderBuilder`] of that
+/// stage, such as [`ShaderBuilder::invocations`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum StageLayout {
+  /// Number of invocations of a geometry shader per input primitive.
+  Invocations(u32),
+
+  /// Number of vertices of the patch output by a tessellation control shader.
+  Vertices(u32),
+
+  /// Primitives, spacing and winding of the tessellation of the patches read by a tessellation evaluation shader.
+  Tessellation(TessPrimitive, TessSpacing, TessWinding),
+
+  /// Depth and stencil tests run before the fragment shader.
+  EarlyFragmentTests,
+
+  /// Size of the work groups of a compute shader, along the X, Y and Z axes.
+  LocalSize([LocalSize; 3]),
+
+  /// Primitives read by a geometry shader.
+  InputPrimitive(GeometryInput),
+
+  /// Size of the `gl_ClipDistance` output array.
+  ClipDistances(u32),
+
+  /// Size of the `gl_CullDistance` output array.
+  CullDistances(u32),
+
+  /// Stride of the vertices captured by transform feedback to a buffer, in bytes: buffer, then stride.
+  XfbStride(u32, u32),
 }
 
-impl<T> Vec3<(Expr<V2<T>>, Expr<T>)> for Expr<V3<T>> {
-  fn vec3(args: (Expr<V2<T>>, Expr<T>)) -> Self {
-    let (xy, z) = args;
-    Expr::new(ErasedExpr::FunCall(
-      ErasedFunHandle::Vec3,
-      vec![xy.erased, z.erased],
-    ))
+impl StageLayout {
+  /// Human-readable name of the layout qualifier, the same for all the values of a kind — transform feedback strides
+  /// being of one kind per buffer.
+  fn name(&self) -> String {
+    match self {
+      StageLayout::Invocations(_) => "layout invocations".to_owned(),
+      StageLayout::Vertices(_) => "layout vertices".to_owned(),
+      StageLayout::Tessellation(..) => "layout tessellation".to_owned(),
+      StageLayout::EarlyFragmentTests => "layout early_fragment_tests".to_owned(),
+      StageLayout::LocalSize(_) => "layout local_size".to_owned(),
+      StageLayout::InputPrimitive(_) => "layout input primitive".to_owned(),
+      StageLayout::ClipDistances(_) => "gl_ClipDistance".to_owned(),
+      StageLayout::CullDistances(_) => "gl_CullDistance".to_owned(),
+      StageLayout::XfbStride(buffer, _) => format!("layout xfb_stride of buffer {}", buffer),
+    }
   }
 }
 
-impl<T> Vec3<(Expr<T>, Expr<T>, Expr<T>)> for Expr<V3<T>> {
-  fn vec3(args: (Expr<T>, Expr<T>, Expr<T>)) -> Self {
-    let (x, y, z) = args;
-    Expr::new(ErasedExpr::FunCall(
-      ErasedFunHandle::Vec3,
-      vec![x.erased, y.erased, z.erased],
-    ))
+/// Layout qualifiers of an output, such as `layout(location = 0, index = 1)`.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct OutputLayout {
+  /// Location of the output — `location`.
+  pub location: Option<u32>,
+
+  /// Index of the output at its location, `0` or `1`, for dual-source blending — `index`.
+  pub index: Option<u32>,
+
+  /// Transform feedback buffer the output is captured to — `xfb_buffer`.
+  pub xfb_buffer: Option<u32>,
+
+  /// Offset of the output in the vertices captured to its transform feedback buffer, in bytes — `xfb_offset`.
+  pub xfb_offset: Option<u32>,
+}
+
+impl OutputLayout {
+  fn xfb(buffer: u32, offset: u32) -> Self {
+    OutputLayout {
+      xfb_buffer: Some(buffer),
+      xfb_offset: Some(offset),
+      ..OutputLayout::default()
+    }
   }
 }
 
-/// Trait allowing to create 4D scalar vector ([`V4`])constructors.
-///
-/// 4D scalar vectors can be created from either four sole scalars, a single 3D scalar vector with a single scalar,
-/// two 2D scalar vectors, a 2D scalar vector and a sole scalar or a single 4D scalar vector (identity function).
-///
-/// The `A` type variable represents the arguments type. In the case of several arguments, tuples are used.
-///
-/// You are advised to use the [`vec4!`](vec4) macro instead as the interface of this function is not really
-/// user-friendly.
-pub trait Vec4<A> {
-  /// Make a [`V4`] from `A`.
-  fn vec4(args: A) -> Self;
+/// Size of the work groups of a compute shader along an axis.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LocalSize {
+  /// Size known when the shader is written — `local_size_x = size`.
+  Const(u32),
+
+  /// Size set by the specialization constant `id` when the pipeline is created, `default` if not specialized —
+  /// `local_size_x = default, local_size_x_id = id`.
+  ///
+  /// Specialization constants are a SPIR-V feature; they are only available to shaders compiled to SPIR-V.
+  Spec {
+    /// ID of the specialization constant.
+    id: u32,
+
+    /// Size used if the constant is not specialized.
+    default: u32,
+  },
 }
 
-impl<T> Vec4<(Expr<V3<T>>, Expr<T>)> for Expr<V4<T>> {
-  fn vec4(args: (Expr<V3<T>>, Expr<T>)) -> Self {
-    let (xyz, w) = args;
-    Expr::new(ErasedExpr::FunCall(
-      ErasedFunHandle::Vec4,
-      vec![xyz.erased, w.erased],
-    ))
+impl LocalSize {
+  /// Size along the axis, if not specialized.
+  pub fn size(self) -> u32 {
+    match self {
+      LocalSize::Const(size) | LocalSize::Spec { default: size, .. } => size,
+    }
   }
 }
 
-impl<T> Vec4<(Expr<V2<T>>, Expr<V2<T>>)> for Expr<V4<T>> {
-  fn vec4(args: (Expr<V2<T>>, Expr<V2<T>>)) -> Self {
-    let (xy, zw) = args;
-    Expr::new(ErasedExpr::FunCall(
-      ErasedFunHandle::Vec4,
-      vec![xy.erased, zw.erased],
-    ))
+impl From<u32> for LocalSize {
+  fn from(size: u32) -> Self {
+    LocalSize::Const(size)
   }
 }
 
-impl<'a, T> Vec4<(Expr<V2<T>>, Expr<T>, Expr<T>)> for Expr<V4<T>> {
-  fn vec4(args: (Expr<V2<T>>, Expr<T>, Expr<T>)) -> Self {
-    let (xy, z, w) = args;
-    Expr::new(ErasedExpr::FunCall(
-      ErasedFunHandle::Vec4,
-      vec![xy.erased, z.erased, w.erased],
-    ))
-  }
+/// Primitives read by a geometry shader.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GeometryInput {
+  /// Points — `points`.
+  Points,
+
+  /// Lines — `lines`.
+  Lines,
+
+  /// Lines with their adjacent vertices — `lines_adjacency`.
+  LinesAdjacency,
+
+  /// Triangles — `triangles`.
+  Triangles,
+
+  /// Triangles with their adjacent vertices — `triangles_adjacency`.
+  TrianglesAdjacency,
 }
 
-impl<'a, T> Vec4<(Expr<T>, Expr<T>, Expr<T>, Expr<T>)> for Expr<V4<T>> {
-  fn vec4(args: (Expr<T>, Expr<T>, Expr<T>, Expr<T>)) -> Self {
-    let (x, y, z, w) = args;
-    Expr::new(ErasedExpr::FunCall(
-      ErasedFunHandle::Vec4,
-      vec![x.erased, y.erased, z.erased, w.erased],
-    ))
+impl GeometryInput {
+  /// Number of vertices of a primitive, which is the size of [`GeometryShaderEnv::input`].
+  pub const fn vertices(self) -> usize {
+    match self {
+      GeometryInput::Points => 1,
+      GeometryInput::Lines => 2,
+      GeometryInput::LinesAdjacency => 4,
+      GeometryInput::Triangles => 3,
+      GeometryInput::TrianglesAdjacency => 6,
+    }
   }
 }
 
-impl<T> Expr<T>
-where
-  T: PartialOrd,
-{
-  /// Less-than expression.
-  ///
-  /// This method builds an expression representing the binary operation `a < b`.
-  ///
-  /// # Return
-  ///
-  /// An [`Expr<bool>`] representing `a < b`.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::lit;
-  ///
-  /// let _ = lit!(1).lt(2); // 1 < 2;
-  /// # s.main_fun(|s: &mut Scope<()>| {})
-  /// # });
-  /// ```
-  pub fn lt(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
-    Expr::new(ErasedExpr::Lt(
-      Box::new(self.erased.clone()),
-      Box::new(rhs.into().erased),
-    ))
-  }
-
-  /// Less-than-or-equal expression.
-  ///
-  /// This method builds an expression representing the binary operation `a <= b`.
-  ///
-  /// # Return
-  ///
-  /// An [`Expr<bool>`] representing `a <= b`.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::lit;
-  ///
-  /// let _ = lit!(1).lte(2); // 1 <= 2;
-  /// # s.main_fun(|s: &mut Scope<()>| {})
-  /// # });
-  /// ```
-  pub fn lte(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
-    Expr::new(ErasedExpr::Lte(
-      Box::new(self.erased.clone()),
-      Box::new(rhs.into().erased),
-    ))
-  }
+/// Primitives a patch is tessellated into.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TessPrimitive {
+  /// Triangles, subdividing a triangle — `triangles`.
+  Triangles,
 
-  /// Greater-than expression.
-  ///
-  /// This method builds an expression representing the binary operation `a > b`.
-  ///
-  /// # Return
-  ///
-  /// An [`Expr<bool>`] representing `a > b`.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::lit;
-  ///
-  /// let _ = lit!(1).gt(2); // 1 > 2;
-  /// # s.main_fun(|s: &mut Scope<()>| {})
-  /// # });
-  /// ```
-  pub fn gt(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
-    Expr::new(ErasedExpr::Gt(
-      Box::new(self.erased.clone()),
-      Box::new(rhs.into().erased),
-    ))
-  }
+  /// Triangles, subdividing a quad — `quads`.
+  Quads,
 
-  /// Less-than-or-equal expression.
-  ///
-  /// This method builds an expression representing the binary operation `a <= b`.
-  ///
-  /// # Return
-  ///
-  /// An [`Expr<bool>`] representing `a <= b`.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::lit;
-  ///
-  /// let _ = lit!(1).lte(2); // 1 <= 2;
-  /// # s.main_fun(|s: &mut Scope<()>| {})
-  /// # });
-  /// ```
-  pub fn gte(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
-    Expr::new(ErasedExpr::Gte(
-      Box::new(self.erased.clone()),
-      Box::new(rhs.into().erased),
-    ))
-  }
+  /// Lines, subdividing a set of parallel lines — `isolines`.
+  Isolines,
 }
 
-impl Expr<bool> {
-  /// Logical _and_ expression.
-  ///
-  /// This method builds an expression representing the logical operation `a AND b`.
-  ///
-  /// # Return
-  ///
-  /// An [`Expr<bool>`] representing `a AND b`.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::lit;
-  ///
-  /// let _ = lit!(true).and(false); // true && false
-  /// # s.main_fun(|s: &mut Scope<()>| {})
-  /// # });
-  /// ```
-  pub fn and(&self, rhs: impl Into<Expr<bool>>) -> Expr<bool> {
-    Expr::new(ErasedExpr::And(
-      Box::new(self.erased.clone()),
-      Box::new(rhs.into().erased),
-    ))
-  }
+/// Spacing of the vertices along the edges of a tessellated patch.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TessSpacing {
+  /// Segments of equal length; levels are rounded up to integers — `equal_spacing`.
+  Equal,
 
-  /// Logical _or_ expression.
-  ///
-  /// This method builds an expression representing the logical operation `a OR b`.
-  ///
-  /// # Return
-  ///
-  /// An [`Expr<bool>`] representing `a OR b`.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::lit;
-  ///
-  /// let _ = lit!(true).or(false); // true || false
-  /// # s.main_fun(|s: &mut Scope<()>| {})
-  /// # });
-  /// ```
-  pub fn or(&self, rhs: impl Into<Expr<bool>>) -> Expr<bool> {
-    Expr::new(ErasedExpr::Or(
-      Box::new(self.erased.clone()),
-      Box::new(rhs.into().erased),
-    ))
-  }
+  /// Levels are rounded up to even integers, the fractional part shrinking two segments — `fractional_even_spacing`.
+  FractionalEven,
 
-  /// Logical _exclusive or_ expression.
-  ///
-  /// This method builds an expression representing the logical operation `a XOR b`.
-  ///
-  /// # Return
-  ///
-  /// An [`Expr<bool>`] representing `a XOR b`.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::lit;
-  ///
-  /// let _ = lit!(true).xor(false); // true ^^ false
-  /// # s.main_fun(|s: &mut Scope<()>| {})
-  /// # });
-  /// ```
-  pub fn xor(&self, rhs: impl Into<Expr<bool>>) -> Expr<bool> {
-    Expr::new(ErasedExpr::Xor(
-      Box::new(self.erased.clone()),
-      Box::new(rhs.into().erased),
-    ))
-  }
+  /// Levels are rounded up to odd integers, the fractional part shrinking two segments — `fractional_odd_spacing`.
+  FractionalOdd,
 }
 
-impl<T> Expr<[T]> {
-  /// Array lookup.
-  ///
-  /// The expression `a.at(i)` represents an _array lookup_, where `a` is an array — which type must be either
-  /// [`Expr<[T]>`](Expr) or [`Expr<[T; N]>`](Expr) – and `i` is an [`Expr<i32>`].
-  ///
-  /// # Return
-  ///
-  /// The resulting [`Expr<T>`] represents the array lookup in `a` at index `i`.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::lit;
-  ///
-  /// let _ = lit!([1, 2, 3]).at(2); // [1, 2, 3][2]
-  /// # s.main_fun(|s: &mut Scope<()>| {})
-  /// # });
-  /// ```
-  pub fn at(&self, index: impl Into<Expr<i32>>) -> Expr<T> {
-    Expr::new(ErasedExpr::ArrayLookup {
-      object: Box::new(self.erased.clone()),
-      index: Box::new(index.into().erased),
-    })
-  }
+/// Order of the vertices of the triangles produced by the tessellation.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TessWinding {
+  /// Counter-clockwise — `ccw`.
+  Ccw,
+
+  /// Clockwise — `cw`.
+  Cw,
 }
 
-impl<T, const N: usize> Expr<[T; N]> {
-  /// Array lookup.
-  ///
-  /// The expression `a.at(i)` represents an _array lookup_, where `a` is an array — which type must be either
-  /// [`Expr<[T]>`](Expr) or [`Expr<[T; N]>`](Expr) – and `i` is an [`Expr<i32>`].
-  ///
-  /// # Return
+/// Shader declaration.
+///
+/// This contain everything that can be declared at top-level of a shader. Shader declarations can be walked with the
+/// [`visit`] module.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ShaderDecl {
+  /// The `main` function declaration. The [`ErasedFun`] is a function that returns nothing and has no argument.
+  Main(ErasedFun),
+
+  /// A function definition.
   ///
-  /// The resulting [`Expr<T>`] represents the array lookup in `a` at index `i`.
+  /// The [`u16`] represents the _handle_ of the function, and is unique for each shader stage. The [`ErasedFun`] is
+  /// the representation of the function definition.
+  FunDef(u16, ErasedFun),
+
+  /// A constant definition.
   ///
-  /// # Examples
+  /// The [`u16`] represents the _handle_ of the constant, and is unique for each shader stage. The [`Type`] is the
+  /// the type of the constant expression. [`ErasedExpr`] is the representation of the constant.
+  Const(u16, Type, ErasedExpr),
+
+  /// An input definition.
   ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::lit;
+  /// The [`u16`] represents the _handle_ of the input, and is unique for each shader stage. The [`Type`] is the
+  /// the type of the input.
+  In(String, Type),
+
+  /// An output definition.
   ///
-  /// let _ = lit!([1, 2, 3]).at(2); // [1, 2, 3][2]
-  /// # s.main_fun(|s: &mut Scope<()>| {})
-  /// # });
-  /// ```
-  pub fn at(&self, index: impl Into<Expr<i32>>) -> Expr<T> {
-    Expr::new(ErasedExpr::ArrayLookup {
-      object: Box::new(self.erased.clone()),
-      index: Box::new(index.into().erased),
-    })
-  }
-}
+  /// The [`u16`] represents the _handle_ of the output, and is unique for each shader stage. The [`Type`] is the
+  /// the type of the output.
+  Out(String, Type),
 
-// not
-macro_rules! impl_Not_Expr {
-  ($t:ty) => {
-    impl ops::Not for Expr<$t> {
-      type Output = Self;
+  /// A uniform definition.
+  Uniform(String, Type),
 
-      fn not(self) -> Self::Output {
-        Expr::new(ErasedExpr::Not(Box::new(self.erased)))
-      }
-    }
+  /// A per-patch input definition, only valid in tessellation evaluation shaders.
+  PatchIn(String, Type),
 
-    impl<'a> ops::Not for &'a Expr<$t> {
-      type Output = Expr<$t>;
+  /// A per-patch output definition, only valid in tessellation control shaders.
+  PatchOut(String, Type),
 
-      fn not(self) -> Self::Output {
-        Expr::new(ErasedExpr::Not(Box::new(self.erased.clone())))
-      }
-    }
+  /// An output definition with explicit layout qualifiers.
+  LayoutOut(String, Type, OutputLayout),
 
-    impl ops::Not for Var<$t> {
-      type Output = Expr<$t>;
+  /// A shared variable definition, only valid in compute shaders.
+  ///
+  /// The [`u16`] represents the _handle_ of the variable, shared with constants.
+  Shared(u16, Type),
 
-      fn not(self) -> Self::Output {
-        Expr::new(ErasedExpr::Not(Box::new(self.0.erased)))
-      }
-    }
+  /// A comment, annotating the declaration following it.
+  Comment(String),
+}
 
-    impl<'a> ops::Not for &'a Var<$t> {
-      type Output = Expr<$t>;
+impl ShaderDecl {
+  /// Human-readable name of the declaration, such as `main`, `uniform time` or `function fun_0`.
+  fn name(&self) -> String {
+    match self {
+      ShaderDecl::Main(_) => "main".to_owned(),
+      ShaderDecl::FunDef(handle, _) => format!("function fun_{}", handle),
+      ShaderDecl::Const(handle, ..) => format!("constant glob_{}", handle),
+      ShaderDecl::In(name, _) => format!("input {}", name),
+      ShaderDecl::Out(name, _) | ShaderDecl::LayoutOut(name, ..) => format!("output {}", name),
+      ShaderDecl::Uniform(name, _) => format!("uniform {}", name),
+      ShaderDecl::PatchIn(name, _) => format!("patch input {}", name),
+      ShaderDecl::PatchOut(name, _) => format!("patch output {}", name),
+      ShaderDecl::Shared(handle, _) => format!("shared glob_{}", handle),
+      ShaderDecl::Comment(text) => format!("comment {}", text),
+    }
+  }
 
-      fn not(self) -> Self::Output {
-        Expr::new(ErasedExpr::Not(Box::new(self.0.erased.clone())))
-      }
+  /// Type of the declaration if it is part of the interface of the shader, such as an input or a uniform.
+  fn interface_ty(&self) -> Option<&Type> {
+    match self {
+      ShaderDecl::In(_, ty)
+      | ShaderDecl::Out(_, ty)
+      | ShaderDecl::LayoutOut(_, ty, _)
+      | ShaderDecl::Uniform(_, ty)
+      | ShaderDecl::PatchIn(_, ty)
+      | ShaderDecl::PatchOut(_, ty) => Some(ty),
+      _ => None,
     }
-  };
+  }
 }
 
-impl_Not_Expr!(bool);
-impl_Not_Expr!(V2<bool>);
-impl_Not_Expr!(V3<bool>);
-impl_Not_Expr!(V4<bool>);
-
-// neg
-macro_rules! impl_Neg {
-  ($t:ty) => {
-    impl ops::Neg for Expr<$t> {
-      type Output = Self;
-
-      fn neg(self) -> Self::Output {
-        Expr::new(ErasedExpr::Neg(Box::new(self.erased)))
+macro_rules! make_vn {
+  ($t:ident, $dim:expr) => {
+    /// Scalar vectors.
+    ///
+    /// Scalar vectors come into three flavors, based on the dimension used:
+    ///
+    /// - Two dimensions (2D): [`V2<T>`].
+    /// - Three dimensions (3D): [`V3<T>`].
+    /// - Four dimensions (4D): [`V4<T>`].
+    ///
+    /// Each type implements the [`From`] trait for sized array. For instance, if you want to make a `V3<f32>` from
+    /// constants / literals, you can simply use the implementor `From<[f32; 3]> for V3<f32>`.
+    ///
+    /// A builder macro version exists for each flavor:
+    ///
+    /// - [`vec2`]: build [`V2<T>`].
+    /// - [`vec3`]: build [`V3<T>`].
+    /// - [`vec4`]: build [`V4<T>`].
+    ///
+    /// Those three macros can also be used with literals.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct $t<T>([T; $dim]);
+
+    impl<T> From<[T; $dim]> for $t<T> {
+      fn from(a: [T; $dim]) -> Self {
+        Self(a)
       }
     }
+  };
+}
 
-    impl<'a> ops::Neg for &'a Expr<$t> {
-      type Output = Expr<$t>;
+make_vn!(V2, 2);
+make_vn!(V3, 3);
+make_vn!(V4, 4);
 
-      fn neg(self) -> Self::Output {
-        Expr::new(ErasedExpr::Neg(Box::new(self.erased.clone())))
-      }
-    }
+/// Representation of an expression.
+///
+/// This is the untyped version of [`Expr`]. It is exposed so that it can be inspected and transformed with the
+/// [`visit`] module.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ErasedExpr {
+  // scalars
+  LitInt(i32),
+  LitUInt(u32),
+  LitFloat(f32),
+  LitBool(bool),
+  // vectors
+  LitInt2([i32; 2]),
+  LitUInt2([u32; 2]),
+  LitFloat2([f32; 2]),
+  LitBool2([bool; 2]),
+  LitInt3([i32; 3]),
+  LitUInt3([u32; 3]),
+  LitFloat3([f32; 3]),
+  LitBool3([bool; 3]),
+  LitInt4([i32; 4]),
+  LitUInt4([u32; 4]),
+  LitFloat4([f32; 4]),
+  LitBool4([bool; 4]),
+  // matrices
+  LitM22(M22),
+  LitM23(M23),
+  LitM24(M24),
+  LitM32(M32),
+  LitM33(M33),
+  LitM34(M34),
+  LitM42(M42),
+  LitM43(M43),
+  LitM44(M44),
+  // arrays
+  Array(Type, Vec<Arc<Self>>),
+  // var
+  Var(ScopedHandle),
+  // built-in functions and operators
+  Not(Arc<Self>),
+  And(Arc<Self>, Arc<Self>),
+  Or(Arc<Self>, Arc<Self>),
+  Xor(Arc<Self>, Arc<Self>),
+  BitOr(Arc<Self>, Arc<Self>),
+  BitAnd(Arc<Self>, Arc<Self>),
+  BitXor(Arc<Self>, Arc<Self>),
+  Neg(Arc<Self>),
+  Add(Arc<Self>, Arc<Self>),
+  Sub(Arc<Self>, Arc<Self>),
+  Mul(Arc<Self>, Arc<Self>),
+  Div(Arc<Self>, Arc<Self>),
+  Rem(Arc<Self>, Arc<Self>),
+  Shl(Arc<Self>, Arc<Self>),
+  Shr(Arc<Self>, Arc<Self>),
+  Eq(Arc<Self>, Arc<Self>),
+  Neq(Arc<Self>, Arc<Self>),
+  Lt(Arc<Self>, Arc<Self>),
+  Lte(Arc<Self>, Arc<Self>),
+  Gt(Arc<Self>, Arc<Self>),
+  Gte(Arc<Self>, Arc<Self>),
+  // function call
+  FunCall(ErasedFunHandle, Vec<Arc<Self>>),
+  // swizzle
+  Swizzle(Arc<Self>, Swizzle),
+  // field expression, as in a struct Foo { float x; }, foo.x is an Expr representing the x field on object foo
+  Field { object: Arc<Self>, field: Arc<Self> },
+  ArrayLookup { object: Arc<Self>, index: Arc<Self> },
+  ArrayLength(Arc<Self>),
+  // type conversion
+  Cast(Type, Arc<Self>),
+}
 
-    impl ops::Neg for Var<$t> {
-      type Output = Expr<$t>;
+impl ErasedExpr {
+  const fn new_builtin(builtin: BuiltIn) -> Self {
+    ErasedExpr::Var(ScopedHandle::builtin(builtin))
+  }
 
-      fn neg(self) -> Self::Output {
-        Expr::new(ErasedExpr::Neg(Box::new(self.0.erased)))
+  /// Direct sub-expressions of this expression.
+  fn sub_exprs(&self) -> Vec<&Self> {
+    self.sub_nodes().into_iter().map(|node| &**node).collect()
+  }
+
+  /// Direct sub-expressions of this expression, as shared nodes.
+  fn sub_nodes(&self) -> Vec<&Arc<Self>> {
+    match self {
+      ErasedExpr::LitInt(_)
+      | ErasedExpr::LitUInt(_)
+      | ErasedExpr::LitFloat(_)
+      | ErasedExpr::LitBool(_)
+      | ErasedExpr::LitInt2(_)
+      | ErasedExpr::LitUInt2(_)
+      | ErasedExpr::LitFloat2(_)
+      | ErasedExpr::LitBool2(_)
+      | ErasedExpr::LitInt3(_)
+      | ErasedExpr::LitUInt3(_)
+      | ErasedExpr::LitFloat3(_)
+      | ErasedExpr::LitBool3(_)
+      | ErasedExpr::LitInt4(_)
+      | ErasedExpr::LitUInt4(_)
+      | ErasedExpr::LitFloat4(_)
+      | ErasedExpr::LitBool4(_)
+      | ErasedExpr::LitM22(_)
+      | ErasedExpr::LitM23(_)
+      | ErasedExpr::LitM24(_)
+      | ErasedExpr::LitM32(_)
+      | ErasedExpr::LitM33(_)
+      | ErasedExpr::LitM34(_)
+      | ErasedExpr::LitM42(_)
+      | ErasedExpr::LitM43(_)
+      | ErasedExpr::LitM44(_)
+      | ErasedExpr::Var(_) => Vec::new(),
+
+      ErasedExpr::Array(_, items) | ErasedExpr::FunCall(_, items) => items.iter().collect(),
+
+      ErasedExpr::Not(a)
+      | ErasedExpr::Neg(a)
+      | ErasedExpr::Swizzle(a, _)
+      | ErasedExpr::ArrayLength(a)
+      | ErasedExpr::Cast(_, a) => vec![a],
+
+      ErasedExpr::And(a, b)
+      | ErasedExpr::Or(a, b)
+      | ErasedExpr::Xor(a, b)
+      | ErasedExpr::BitOr(a, b)
+      | ErasedExpr::BitAnd(a, b)
+      | ErasedExpr::BitXor(a, b)
+      | ErasedExpr::Add(a, b)
+      | ErasedExpr::Sub(a, b)
+      | ErasedExpr::Mul(a, b)
+      | ErasedExpr::Div(a, b)
+      | ErasedExpr::Rem(a, b)
+      | ErasedExpr::Shl(a, b)
+      | ErasedExpr::Shr(a, b)
+      | ErasedExpr::Eq(a, b)
+      | ErasedExpr::Neq(a, b)
+      | ErasedExpr::Lt(a, b)
+      | ErasedExpr::Lte(a, b)
+      | ErasedExpr::Gt(a, b)
+      | ErasedExpr::Gte(a, b)
+      | ErasedExpr::Field {
+        object: a,
+        field: b,
       }
+      | ErasedExpr::ArrayLookup {
+        object: a,
+        index: b,
+      } => vec![a, b],
     }
+  }
 
-    impl<'a> ops::Neg for &'a Var<$t> {
-      type Output = Expr<$t>;
-
-      fn neg(self) -> Self::Output {
-        Expr::new(ErasedExpr::Neg(Box::new(self.0.erased.clone())))
+  /// Direct sub-expressions of this expression, mutably borrowed.
+  ///
+  /// Shared sub-expressions are copied, so that mutating them doesn’t affect the other expressions sharing them.
+  fn sub_exprs_mut(&mut self) -> Vec<&mut Self> {
+    self
+      .sub_nodes_mut()
+      .into_iter()
+      .map(Arc::make_mut)
+      .collect()
+  }
+
+  /// Direct sub-expressions of this expression, as shared nodes which can be replaced.
+  fn sub_nodes_mut(&mut self) -> Vec<&mut Arc<Self>> {
+    match self {
+      ErasedExpr::LitInt(_)
+      | ErasedExpr::LitUInt(_)
+      | ErasedExpr::LitFloat(_)
+      | ErasedExpr::LitBool(_)
+      | ErasedExpr::LitInt2(_)
+      | ErasedExpr::LitUInt2(_)
+      | ErasedExpr::LitFloat2(_)
+      | ErasedExpr::LitBool2(_)
+      | ErasedExpr::LitInt3(_)
+      | ErasedExpr::LitUInt3(_)
+      | ErasedExpr::LitFloat3(_)
+      | ErasedExpr::LitBool3(_)
+      | ErasedExpr::LitInt4(_)
+      | ErasedExpr::LitUInt4(_)
+      | ErasedExpr::LitFloat4(_)
+      | ErasedExpr::LitBool4(_)
+      | ErasedExpr::LitM22(_)
+      | ErasedExpr::LitM23(_)
+      | ErasedExpr::LitM24(_)
+      | ErasedExpr::LitM32(_)
+      | ErasedExpr::LitM33(_)
+      | ErasedExpr::LitM34(_)
+      | ErasedExpr::LitM42(_)
+      | ErasedExpr::LitM43(_)
+      | ErasedExpr::LitM44(_)
+      | ErasedExpr::Var(_) => Vec::new(),
+
+      ErasedExpr::Array(_, items) | ErasedExpr::FunCall(_, items) => items.iter_mut().collect(),
+
+      ErasedExpr::Not(a)
+      | ErasedExpr::Neg(a)
+      | ErasedExpr::Swizzle(a, _)
+      | ErasedExpr::ArrayLength(a)
+      | ErasedExpr::Cast(_, a) => vec![a],
+
+      ErasedExpr::And(a, b)
+      | ErasedExpr::Or(a, b)
+      | ErasedExpr::Xor(a, b)
+      | ErasedExpr::BitOr(a, b)
+      | ErasedExpr::BitAnd(a, b)
+      | ErasedExpr::BitXor(a, b)
+      | ErasedExpr::Add(a, b)
+      | ErasedExpr::Sub(a, b)
+      | ErasedExpr::Mul(a, b)
+      | ErasedExpr::Div(a, b)
+      | ErasedExpr::Rem(a, b)
+      | ErasedExpr::Shl(a, b)
+      | ErasedExpr::Shr(a, b)
+      | ErasedExpr::Eq(a, b)
+      | ErasedExpr::Neq(a, b)
+      | ErasedExpr::Lt(a, b)
+      | ErasedExpr::Lte(a, b)
+      | ErasedExpr::Gt(a, b)
+      | ErasedExpr::Gte(a, b)
+      | ErasedExpr::Field {
+        object: a,
+        field: b,
       }
+      | ErasedExpr::ArrayLookup {
+        object: a,
+        index: b,
+      } => vec![a, b],
     }
-  };
-}
+  }
 
-impl_Neg!(i32);
-impl_Neg!(V2<i32>);
-impl_Neg!(V3<i32>);
-impl_Neg!(V4<i32>);
+  /// Call `fun` with `args`.
+  fn fun_call(fun: ErasedFunHandle, args: Vec<Self>) -> Self {
+    ErasedExpr::FunCall(fun, args.into_iter().map(Self::intern).collect())
+  }
 
-impl_Neg!(u32);
-impl_Neg!(V2<u32>);
-impl_Neg!(V3<u32>);
-impl_Neg!(V4<u32>);
+  /// Array of type `ty`, made of `items`.
+  fn array(ty: Type, items: Vec<Self>) -> Self {
+    ErasedExpr::Array(ty, items.into_iter().map(Self::intern).collect())
+  }
 
-impl_Neg!(f32);
-impl_Neg!(V2<f32>);
-impl_Neg!(V3<f32>);
-impl_Neg!(V4<f32>);
+  /// Share this expression as a sub-expression of another one.
+  ///
+  /// Expressions are interned: if a structurally identical expression is already shared, its node is reused instead of
+  /// allocating a new one. Because sub-expressions are interned too, two nodes are identical if their sub-expressions
+  /// are the same nodes, so that finding a node doesn’t require to traverse the whole expression.
+  fn intern(self) -> Arc<Self> {
+    let mut key = String::new();
+    let _ = self.write_shallow(&mut key);
 
-// binary arithmetic and logical (+, -, *, /, %)
-// binop
-macro_rules! impl_binop_Expr {
-  ($op:ident, $meth_name:ident, $a:ty, $b:ty) => {
-    impl_binop_Expr!($op, $meth_name, $a, $b, $a);
-  };
+    INTERNER.with(|interner| interner.borrow_mut().intern(key, self))
+  }
 
-  ($op:ident, $meth_name:ident, $a:ty, $b:ty, $r:ty) => {
-    // expr OP expr
-    impl<'a> ops::$op<Expr<$b>> for Expr<$a> {
-      type Output = Expr<$r>;
+  /// Write a representation of this node, in which sub-expressions are represented by their address.
+  fn write_shallow(&self, f: &mut impl fmt::Write) -> fmt::Result {
+    write!(f, "{:?}(", mem::discriminant(self))?;
 
-      fn $meth_name(self, rhs: Expr<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(Box::new(self.erased), Box::new(rhs.erased)))
+    match self {
+      ErasedExpr::Array(ty, items) => {
+        write!(f, "{:?}", ty)?;
+        for item in items {
+          write!(f, ",{:p}", Arc::as_ptr(item))?;
+        }
       }
-    }
 
-    // var OP expr
-    impl<'a> ops::$op<Expr<$b>> for Var<$a> {
-      type Output = Expr<$r>;
+      ErasedExpr::FunCall(fun, args) => {
+        write!(f, "{:?}", fun)?;
+        for arg in args {
+          write!(f, ",{:p}", Arc::as_ptr(arg))?;
+        }
+      }
 
-      fn $meth_name(self, rhs: Expr<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.0.erased),
-          Box::new(rhs.erased),
-        ))
+      ErasedExpr::Not(a) | ErasedExpr::Neg(a) | ErasedExpr::ArrayLength(a) => {
+        write!(f, "{:p}", Arc::as_ptr(a))?
       }
+      ErasedExpr::Swizzle(a, sw) => write!(f, "{:p},{:?}", Arc::as_ptr(a), sw)?,
+      ErasedExpr::Cast(ty, a) => write!(f, "{:?},{:p}", ty, Arc::as_ptr(a))?,
+
+      ErasedExpr::And(a, b)
+      | ErasedExpr::Or(a, b)
+      | ErasedExpr::Xor(a, b)
+      | ErasedExpr::BitOr(a, b)
+      | ErasedExpr::BitAnd(a, b)
+      | ErasedExpr::BitXor(a, b)
+      | ErasedExpr::Add(a, b)
+      | ErasedExpr::Sub(a, b)
+      | ErasedExpr::Mul(a, b)
+      | ErasedExpr::Div(a, b)
+      | ErasedExpr::Rem(a, b)
+      | ErasedExpr::Shl(a, b)
+      | ErasedExpr::Shr(a, b)
+      | ErasedExpr::Eq(a, b)
+      | ErasedExpr::Neq(a, b)
+      | ErasedExpr::Lt(a, b)
+      | ErasedExpr::Lte(a, b)
+      | ErasedExpr::Gt(a, b)
+      | ErasedExpr::Gte(a, b)
+      | ErasedExpr::Field {
+        object: a,
+        field: b,
+      }
+      | ErasedExpr::ArrayLookup {
+        object: a,
+        index: b,
+      } => write!(f, "{:p},{:p}", Arc::as_ptr(a), Arc::as_ptr(b))?,
+
+      // literals and variables don’t have sub-expressions: their textual representation is exact, including for special
+      // floating-point values such as -0. and NaN
+      _ => write!(f, "{:?}", self)?,
     }
 
-    // expr OP var
-    impl<'a> ops::$op<Var<$b>> for Expr<$a> {
-      type Output = Expr<$r>;
+    f.write_str(")")
+  }
 
-      fn $meth_name(self, rhs: Var<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased),
-          Box::new(rhs.0.erased),
-        ))
-      }
-    }
+  /// Rewrite this expression bottom-up with `f`: its sub-expressions first, then the expression itself.
+  ///
+  /// See [`Rewritten`] for how shared sub-expressions are handled.
+  pub(crate) fn rewrite(&mut self, rewritten: &mut Rewritten, f: &mut impl FnMut(Self) -> Self) {
+    let node = mem::replace(self, ErasedExpr::LitBool(false)).intern();
+    *self = (*rewritten.node(&node, f)).clone();
+  }
 
-    // var OP var
-    impl<'a> ops::$op<Var<$b>> for Var<$a> {
-      type Output = Expr<$r>;
+  /// Fold this expression into a single literal if it is an operator applied to literals only.
+  ///
+  /// Operations the result of which is undefined or not representable as a literal — division by zero, overflowing
+  /// shifts, non-finite floating-point results, etc. — are left untouched.
+  fn fold_literals(self) -> Self {
+    let folded = match &self {
+      ErasedExpr::Not(a) => match Lit::from_expr(a) {
+        Some(Lit::Bool(a)) => Some(Lit::Bool(a.iter().map(|a| !a).collect())),
+        _ => None,
+      },
 
-      fn $meth_name(self, rhs: Var<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.0.erased),
-          Box::new(rhs.0.erased),
-        ))
-      }
-    }
+      ErasedExpr::Neg(a) => match Lit::from_expr(a) {
+        Some(Lit::Int(a)) => Some(Lit::Int(a.iter().map(|a| a.wrapping_neg()).collect())),
+        Some(Lit::Float(a)) => Some(Lit::Float(a.iter().map(|a| -a).collect())),
+        _ => None,
+      },
 
-    // expr OP &expr
-    impl<'a> ops::$op<&'a Expr<$b>> for Expr<$a> {
-      type Output = Expr<$r>;
+      ErasedExpr::And(a, b) => Lit::logical(a, b, |a, b| a && b),
+      ErasedExpr::Or(a, b) => Lit::logical(a, b, |a, b| a || b),
+      ErasedExpr::Xor(a, b) => Lit::logical(a, b, |a, b| a != b),
 
-      fn $meth_name(self, rhs: &'a Expr<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased),
-          Box::new(rhs.erased.clone()),
-        ))
+      ErasedExpr::BitOr(a, b) => {
+        Lit::arith(a, b, |a, b| Some(a | b), |a, b| Some(a | b), |_, _| None)
+      }
+      ErasedExpr::BitAnd(a, b) => {
+        Lit::arith(a, b, |a, b| Some(a & b), |a, b| Some(a & b), |_, _| None)
+      }
+      ErasedExpr::BitXor(a, b) => {
+        Lit::arith(a, b, |a, b| Some(a ^ b), |a, b| Some(a ^ b), |_, _| None)
       }
-    }
-
-    // var OP &expr
-    impl<'a> ops::$op<&'a Expr<$b>> for Var<$a> {
-      type Output = Expr<$r>;
 
-      fn $meth_name(self, rhs: &'a Expr<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.0.erased),
-          Box::new(rhs.erased.clone()),
-        ))
+      ErasedExpr::Add(a, b) => Lit::arith(
+        a,
+        b,
+        |a, b| Some(a.wrapping_add(b)),
+        |a, b| Some(a.wrapping_add(b)),
+        |a, b| Some(a + b),
+      ),
+      ErasedExpr::Sub(a, b) => Lit::arith(
+        a,
+        b,
+        |a, b| Some(a.wrapping_sub(b)),
+        |a, b| Some(a.wrapping_sub(b)),
+        |a, b| Some(a - b),
+      ),
+      ErasedExpr::Mul(a, b) => Lit::arith(
+        a,
+        b,
+        |a, b| Some(a.wrapping_mul(b)),
+        |a, b| Some(a.wrapping_mul(b)),
+        |a, b| Some(a * b),
+      ),
+      ErasedExpr::Div(a, b) => {
+        Lit::arith(a, b, i32::checked_div, u32::checked_div, |a, b| Some(a / b))
       }
-    }
+      // mod(a, b) is only defined for floating-point numbers
+      ErasedExpr::Rem(a, b) => Lit::arith(
+        a,
+        b,
+        |_, _| None,
+        |_, _| None,
+        |a, b| Some(a - b * (a / b).floor()),
+      ),
+
+      ErasedExpr::Shl(a, b) => Lit::shift(a, b, i32::checked_shl, u32::checked_shl),
+      ErasedExpr::Shr(a, b) => Lit::shift(a, b, i32::checked_shr, u32::checked_shr),
+
+      ErasedExpr::Eq(a, b) => Lit::equal(a, b).map(|eq| Lit::Bool(vec![eq])),
+      ErasedExpr::Neq(a, b) => Lit::equal(a, b).map(|eq| Lit::Bool(vec![!eq])),
+
+      ErasedExpr::Lt(a, b) => Lit::compare(a, b, |ord| ord == cmp::Ordering::Less),
+      ErasedExpr::Lte(a, b) => Lit::compare(a, b, |ord| ord != cmp::Ordering::Greater),
+      ErasedExpr::Gt(a, b) => Lit::compare(a, b, |ord| ord == cmp::Ordering::Greater),
+      ErasedExpr::Gte(a, b) => Lit::compare(a, b, |ord| ord != cmp::Ordering::Less),
+
+      _ => None,
+    };
 
-    // expr OP &var
-    impl<'a> ops::$op<&'a Var<$b>> for Expr<$a> {
-      type Output = Expr<$r>;
+    folded.map(Lit::into_expr).unwrap_or(self)
+  }
+}
 
-      fn $meth_name(self, rhs: &'a Var<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased),
-          Box::new(rhs.0.erased.clone()),
-        ))
-      }
-    }
+thread_local! {
+  static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
 
-    // var OP &var
-    impl<'a> ops::$op<&'a Var<$b>> for Var<$a> {
-      type Output = Expr<$r>;
+/// Table of the shared expression nodes alive in the current thread.
+///
+/// Nodes are weakly referenced, so that the table doesn’t keep expressions alive. Dead entries are purged every time
+/// the table doubles in size.
+#[derive(Debug, Default)]
+struct Interner {
+  nodes: HashMap<String, Weak<ErasedExpr>>,
+  purge_len: usize,
+}
 
-      fn $meth_name(self, rhs: &'a Var<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.0.erased),
-          Box::new(rhs.0.erased.clone()),
-        ))
-      }
-    }
+impl Interner {
+  const MIN_PURGE_LEN: usize = 1024;
 
-    // &expr OP expr
-    impl<'a> ops::$op<Expr<$b>> for &'a Expr<$a> {
-      type Output = Expr<$r>;
+  fn intern(&mut self, key: String, expr: ErasedExpr) -> Arc<ErasedExpr> {
+    if let Some(node) = self.nodes.get(&key).and_then(Weak::upgrade) {
+      return node;
+    }
 
-      fn $meth_name(self, rhs: Expr<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased.clone()),
-          Box::new(rhs.erased),
-        ))
-      }
+    if self.nodes.len() >= self.purge_len {
+      self.nodes.retain(|_, node| node.strong_count() > 0);
+      self.purge_len = cmp::max(Self::MIN_PURGE_LEN, self.nodes.len() * 2);
     }
 
-    // &var OP expr
-    impl<'a> ops::$op<Expr<$b>> for &'a Var<$a> {
-      type Output = Expr<$r>;
+    let node = Arc::new(expr);
+    self.nodes.insert(key, Arc::downgrade(&node));
+    node
+  }
+}
 
-      fn $meth_name(self, rhs: Expr<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.0.erased.clone()),
-          Box::new(rhs.erased),
-        ))
-      }
-    }
+/// Nodes already rewritten by [`ErasedExpr::rewrite`], by address of the original node.
+///
+/// Shared sub-expressions are rewritten once, and rewritten nodes are interned, so that the result is as shared as the
+/// original expression; copying shared nodes instead would make the cost of a rewrite grow exponentially with the
+/// nesting of the sharing. Since expressions are interned, nodes with different addresses are different expressions.
+///
+/// The original nodes are kept alive, so that their addresses are not reused for other nodes.
+#[derive(Debug, Default)]
+pub(crate) struct Rewritten(HashMap<*const ErasedExpr, (Arc<ErasedExpr>, Arc<ErasedExpr>)>);
 
-    // &expr OP var
-    impl<'a> ops::$op<Var<$b>> for &'a Expr<$a> {
-      type Output = Expr<$r>;
+impl Rewritten {
+  /// Replace `node` with `rewritten`, as a whole, wherever it appears in the expressions rewritten afterwards.
+  pub(crate) fn insert(&mut self, node: Arc<ErasedExpr>, rewritten: Arc<ErasedExpr>) {
+    self.0.insert(Arc::as_ptr(&node), (node, rewritten));
+  }
 
-      fn $meth_name(self, rhs: Var<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased.clone()),
-          Box::new(rhs.0.erased),
-        ))
-      }
+  fn node(
+    &mut self,
+    node: &Arc<ErasedExpr>,
+    f: &mut impl FnMut(ErasedExpr) -> ErasedExpr,
+  ) -> Arc<ErasedExpr> {
+    if let Some((_, rewritten)) = self.0.get(&Arc::as_ptr(node)) {
+      return rewritten.clone();
     }
 
-    // &var OP var
-    impl<'a> ops::$op<Var<$b>> for &'a Var<$a> {
-      type Output = Expr<$r>;
-
-      fn $meth_name(self, rhs: Var<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.0.erased.clone()),
-          Box::new(rhs.0.erased),
-        ))
-      }
+    let mut expr = (**node).clone();
+    for sub_node in expr.sub_nodes_mut() {
+      *sub_node = self.node(sub_node, f);
     }
 
-    // &expr OP &expr
-    impl<'a> ops::$op<&'a Expr<$b>> for &'a Expr<$a> {
-      type Output = Expr<$r>;
+    let rewritten = f(expr).intern();
+    self.insert(node.clone(), rewritten.clone());
+    rewritten
+  }
+}
 
-      fn $meth_name(self, rhs: &'a Expr<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased.clone()),
-          Box::new(rhs.erased.clone()),
-        ))
-      }
-    }
+/// Scalar and vector literals, used to fold expressions.
+///
+/// Scalars are represented as vectors with a single component.
+enum Lit {
+  Int(Vec<i32>),
+  UInt(Vec<u32>),
+  Float(Vec<f32>),
+  Bool(Vec<bool>),
+}
+
+impl Lit {
+  fn from_expr(expr: &ErasedExpr) -> Option<Self> {
+    let lit = match *expr {
+      ErasedExpr::LitInt(a) => Lit::Int(vec![a]),
+      ErasedExpr::LitUInt(a) => Lit::UInt(vec![a]),
+      ErasedExpr::LitFloat(a) => Lit::Float(vec![a]),
+      ErasedExpr::LitBool(a) => Lit::Bool(vec![a]),
+      ErasedExpr::LitInt2(a) => Lit::Int(a.to_vec()),
+      ErasedExpr::LitUInt2(a) => Lit::UInt(a.to_vec()),
+      ErasedExpr::LitFloat2(a) => Lit::Float(a.to_vec()),
+      ErasedExpr::LitBool2(a) => Lit::Bool(a.to_vec()),
+      ErasedExpr::LitInt3(a) => Lit::Int(a.to_vec()),
+      ErasedExpr::LitUInt3(a) => Lit::UInt(a.to_vec()),
+      ErasedExpr::LitFloat3(a) => Lit::Float(a.to_vec()),
+      ErasedExpr::LitBool3(a) => Lit::Bool(a.to_vec()),
+      ErasedExpr::LitInt4(a) => Lit::Int(a.to_vec()),
+      ErasedExpr::LitUInt4(a) => Lit::UInt(a.to_vec()),
+      ErasedExpr::LitFloat4(a) => Lit::Float(a.to_vec()),
+      ErasedExpr::LitBool4(a) => Lit::Bool(a.to_vec()),
+      _ => return None,
+    };
 
-    // &var OP &expr
-    impl<'a> ops::$op<&'a Expr<$b>> for &'a Var<$a> {
-      type Output = Expr<$r>;
+    Some(lit)
+  }
 
-      fn $meth_name(self, rhs: &'a Expr<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.0.erased.clone()),
-          Box::new(rhs.erased.clone()),
-        ))
-      }
+  fn into_expr(self) -> ErasedExpr {
+    match self {
+      Lit::Int(a) => match a[..] {
+        [x] => ErasedExpr::LitInt(x),
+        [x, y] => ErasedExpr::LitInt2([x, y]),
+        [x, y, z] => ErasedExpr::LitInt3([x, y, z]),
+        [x, y, z, w] => ErasedExpr::LitInt4([x, y, z, w]),
+        _ => unreachable!("literals have from one to four components"),
+      },
+
+      Lit::UInt(a) => match a[..] {
+        [x] => ErasedExpr::LitUInt(x),
+        [x, y] => ErasedExpr::LitUInt2([x, y]),
+        [x, y, z] => ErasedExpr::LitUInt3([x, y, z]),
+        [x, y, z, w] => ErasedExpr::LitUInt4([x, y, z, w]),
+        _ => unreachable!("literals have from one to four components"),
+      },
+
+      Lit::Float(a) => match a[..] {
+        [x] => ErasedExpr::LitFloat(x),
+        [x, y] => ErasedExpr::LitFloat2([x, y]),
+        [x, y, z] => ErasedExpr::LitFloat3([x, y, z]),
+        [x, y, z, w] => ErasedExpr::LitFloat4([x, y, z, w]),
+        _ => unreachable!("literals have from one to four components"),
+      },
+
+      Lit::Bool(a) => match a[..] {
+        [x] => ErasedExpr::LitBool(x),
+        [x, y] => ErasedExpr::LitBool2([x, y]),
+        [x, y, z] => ErasedExpr::LitBool3([x, y, z]),
+        [x, y, z, w] => ErasedExpr::LitBool4([x, y, z, w]),
+        _ => unreachable!("literals have from one to four components"),
+      },
     }
+  }
 
-    // &expr OP &var
-    impl<'a> ops::$op<&'a Var<$b>> for &'a Expr<$a> {
-      type Output = Expr<$r>;
+  /// Apply `f` component-wise, broadcasting scalars to vectors.
+  fn zip<T, U>(a: &[T], b: &[T], f: impl Fn(T, T) -> Option<U>) -> Option<Vec<U>>
+  where
+    T: Copy,
+  {
+    match (a.len(), b.len()) {
+      (n, m) if n == m => a.iter().zip(b).map(|(&a, &b)| f(a, b)).collect(),
+      (1, _) => b.iter().map(|&b| f(a[0], b)).collect(),
+      (_, 1) => a.iter().map(|&a| f(a, b[0])).collect(),
+      _ => None,
+    }
+  }
 
-      fn $meth_name(self, rhs: &'a Var<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased.clone()),
-          Box::new(rhs.0.erased.clone()),
-        ))
+  fn arith(
+    a: &ErasedExpr,
+    b: &ErasedExpr,
+    int: impl Fn(i32, i32) -> Option<i32>,
+    uint: impl Fn(u32, u32) -> Option<u32>,
+    float: impl Fn(f32, f32) -> Option<f32>,
+  ) -> Option<Self> {
+    match (Lit::from_expr(a)?, Lit::from_expr(b)?) {
+      (Lit::Int(a), Lit::Int(b)) => Lit::zip(&a, &b, int).map(Lit::Int),
+      (Lit::UInt(a), Lit::UInt(b)) => Lit::zip(&a, &b, uint).map(Lit::UInt),
+      (Lit::Float(a), Lit::Float(b)) => {
+        Lit::zip(&a, &b, |a, b| float(a, b).filter(|x| x.is_finite())).map(Lit::Float)
       }
+      _ => None,
     }
+  }
 
-    // &var OP &var
-    impl<'a> ops::$op<&'a Var<$b>> for &'a Var<$a> {
-      type Output = Expr<$r>;
+  fn logical(a: &ErasedExpr, b: &ErasedExpr, f: impl Fn(bool, bool) -> bool) -> Option<Self> {
+    match (Lit::from_expr(a)?, Lit::from_expr(b)?) {
+      (Lit::Bool(a), Lit::Bool(b)) => Lit::zip(&a, &b, |a, b| Some(f(a, b))).map(Lit::Bool),
+      _ => None,
+    }
+  }
 
-      fn $meth_name(self, rhs: &'a Var<$b>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.0.erased.clone()),
-          Box::new(rhs.0.erased.clone()),
-        ))
-      }
+  fn shift(
+    a: &ErasedExpr,
+    b: &ErasedExpr,
+    int: impl Fn(i32, u32) -> Option<i32>,
+    uint: impl Fn(u32, u32) -> Option<u32>,
+  ) -> Option<Self> {
+    match (Lit::from_expr(a)?, Lit::from_expr(b)?) {
+      (Lit::Int(a), Lit::UInt(b)) if b.len() == 1 => a
+        .iter()
+        .map(|&a| int(a, b[0]))
+        .collect::<Option<_>>()
+        .map(Lit::Int),
+      (Lit::UInt(a), Lit::UInt(b)) => Lit::zip(&a, &b, uint).map(Lit::UInt),
+      _ => None,
+    }
+  }
+
+  /// Whether both literals are equal, if they have the same type.
+  fn equal(a: &ErasedExpr, b: &ErasedExpr) -> Option<bool> {
+    match (Lit::from_expr(a)?, Lit::from_expr(b)?) {
+      (Lit::Int(a), Lit::Int(b)) => Some(a == b),
+      (Lit::UInt(a), Lit::UInt(b)) => Some(a == b),
+      (Lit::Float(a), Lit::Float(b)) => Some(a == b),
+      (Lit::Bool(a), Lit::Bool(b)) => Some(a == b),
+      _ => None,
+    }
+  }
+
+  /// Compare two scalar literals.
+  fn compare(a: &ErasedExpr, b: &ErasedExpr, f: impl Fn(cmp::Ordering) -> bool) -> Option<Self> {
+    let ord = match (Lit::from_expr(a)?, Lit::from_expr(b)?) {
+      (Lit::Int(a), Lit::Int(b)) if a.len() == 1 && b.len() == 1 => a[0].cmp(&b[0]),
+      (Lit::UInt(a), Lit::UInt(b)) if a.len() == 1 && b.len() == 1 => a[0].cmp(&b[0]),
+      (Lit::Float(a), Lit::Float(b)) if a.len() == 1 && b.len() == 1 => a[0].partial_cmp(&b[0])?,
+      _ => return None,
+    };
+
+    Some(Lit::Bool(vec![f(ord)]))
+  }
+}
+
+/// Expression representation.
+///
+/// An expression is anything that carries a (typed) value and that can be combined in various ways with other
+/// expressions. A literal, a constant or a variable are all expressions. The sum (as in `a + b`) of two expressions is
+/// also an expression. A function call returning an expression is also an expression, as in `a * sin(b)`. Accessing an
+/// element in an array (which is an expression as it carries items) via an index (an expression) is also an
+/// expression — e.g. `levels[y * HEIGHT + x] * size`. The same thing applies to field access, swizzling, etc. etc.
+///
+/// On a general note, expressions are pretty central to the EDSL, as they are the _lower level concept_ you will be
+/// able to manipulate. Expressions are side effect free, so a variable, for instance, can either be considered as an
+/// expression or not. If `x` is a variable (see [`Var`]), then `x * 10` is an expression, but using `x` to mutate its
+/// content does not make a use of `x` as an expression. It means that expressions are read-only and even though you
+/// can go from higher constructs (like variables) to expressions, the opposite direction is forbidden.
+///
+/// # Literals
+///
+/// The most obvious kind of expression is a literal — e.g. `1`, `false`, `3.14`, etc. Any type `T` that defines an
+/// implementor `From<T> for Expr<T>` can be used as literal. You can then use, for instance, `1.into()`,
+/// `Expr::from(1)`, etc.
+///
+/// A much better and easier way to create literals is to use the [`lit!`](lit) macro, which basically does the lifting
+/// for you, but also accept more forms to create more complex literals, such as scalar vectors. See its documentation
+/// for further details.
+///
+/// It’s important to notice that because of how Rust infers type, type ambiguities might occur when using literals —
+/// hence, the use of [`lit!`](lit) should help. For instance, in `1 + 2`, the type of `1` is ambiguous because of how
+/// the implementors for [`Add`](std::ops::Add) are picked. In such a case, you are advised to use [`lit!`](lit).
+///
+/// ## Automatic lifting
+///
+/// Sometimes, you will want to pass literals to form other expressions, function calls, etc. Most of the API has been
+/// written in a way that if no ambiguity would occur, then you can use the Rust type directly. For instance, if `x`
+/// has the type `Expr<i32>`, then `x + 1` is the same as `x + lit!(1)`. You can use this property with literals too:
+/// `lit!(1) + 2 + 3 + 4`.
+///
+/// That automatic lifting is valid for a lot of traits and methods throughout this crate.
+///
+/// ## Literal folding
+///
+/// Operators applied to scalar or vector literals only are folded into a single literal as soon as they are built.
+/// For instance, `lit!(1) + 2 + 3 + 4` is directly represented as `10`, which keeps the shading code small when
+/// generating lots of constant arithmetic. Operations whose results are undefined or cannot be represented as a literal
+/// — divisions by zero, overflowing shifts, etc. — are kept as-is.
+///
+/// ## Sharing
+///
+/// Sub-expressions are interned: structurally identical sub-expressions share a single node, even if they were built
+/// independently. Cloning an [`Expr`] and combining it with others doesn’t copy the whole tree, so reusing the same
+/// expression many times stays cheap.
+///
+/// # Expressions from side-effects
+///
+/// Some side-effects will create expressions, such as creating a variable or a constant. Most of the time, you
+/// shouldn’t have to worry about the type of the expression as it should be inferred based on the side-effect.
+///
+/// # Expression macros
+///
+/// Some macros will create expressions for you, such as [`lit!`](lit), [`vec2!`](vec2), [`vec3!`](vec3) and
+/// [`vec4!`](vec4) or the [`sw!`](sw) macros. Most of the time, those macros will work by automatically adding a
+/// reference (`&`) to their arguments so that you don’t have to worry about that either.
+#[derive(Debug)]
+pub struct Expr<T>
+where
+  T: ?Sized,
+{
+  erased: ErasedExpr,
+  _phantom: PhantomData<T>,
+}
+
+impl<T> From<&'_ Self> for Expr<T>
+where
+  T: ?Sized,
+{
+  fn from(e: &Self) -> Self {
+    Self::new(e.erased.clone())
+  }
+}
+
+impl<T> Clone for Expr<T>
+where
+  T: ?Sized,
+{
+  fn clone(&self) -> Self {
+    Self::new(self.erased.clone())
+  }
+}
+
+impl<T> Expr<T>
+where
+  T: ?Sized,
+{
+  /// Type an [`ErasedExpr`] and return it wrapped in [`Expr<T>`].
+  const fn new(erased: ErasedExpr) -> Self {
+    Self {
+      erased,
+      _phantom: PhantomData,
+    }
+  }
+
+  /// Equality expression.
+  ///
+  /// This method builds an expression representing the equality between two expressions.
+  ///
+  /// # Return
+  ///
+  /// An [`Expr<bool>`] representing the equality between the two input expressions.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::{lit, vec2};
+  ///
+  /// let _ = lit!(1).eq(1); // 1 == 1;
+  /// let _ = vec2!(1., 2.).eq(vec2!(0., 0.)); // vec2(1., 2.) == vec2(0., 0.)
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn eq(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+    Expr::new(
+      ErasedExpr::Eq(self.erased.clone().intern(), rhs.into().erased.intern()).fold_literals(),
+    )
+  }
+
+  /// Inequality expression.
+  ///
+  /// This method builds an expression representing the inequality between two expressions.
+  ///
+  /// # Return
+  ///
+  /// An [`Expr<bool>`] representing the inequality between the two input expressions.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::{lit, vec2};
+  ///
+  /// let _ = lit!(1).neq(1); // 1 != 1;
+  /// let _ = vec2!(1., 2.).eq(vec2!(0., 0.)); // vec2(1., 2.) != vec2(0., 0.)
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn neq(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+    Expr::new(
+      ErasedExpr::Neq(self.erased.clone().intern(), rhs.into().erased.intern()).fold_literals(),
+    )
+  }
+}
+
+/// Trait allowing to create 2D scalar vector ([`V2`])constructors.
+/// 2D scalar vectors can be created from either two sole scalars or a single 2D scalar vector (identity function).
+///
+///
+/// The `A` type variable represents the arguments type. In the case of several arguments, tuples are used.
+///
+/// You are advised to use the [`vec2!`](vec2) macro instead as the interface of this function is not really
+/// user-friendly.
+pub trait Vec2<A> {
+  /// Make a [`V2`] from `A`.
+  fn vec2(args: A) -> Self;
+}
+
+impl<T> Vec2<(Expr<T>, Expr<T>)> for Expr<V2<T>> {
+  fn vec2(args: (Expr<T>, Expr<T>)) -> Self {
+    let (x, y) = args;
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::Vec2,
+      vec![x.erased, y.erased],
+    ))
+  }
+}
+
+/// Trait allowing to create 3D scalar vector ([`V3`])constructors.
+///
+/// 3D scalar vectors can be created from either three sole scalars, a single 2D scalar vector with a single scalar or
+/// a single 3D scalar vector (identity function).
+///
+/// The `A` type variable represents the arguments type. In the case of several arguments, tuples are used.
+///
+/// You are advised to use the [`vec3!`](vec3) macro instead as the interface of this function is not really
+/// user-friendly.
+pub trait Vec3<A> {
+  /// Make a [`V3`] from `A`.
+  fn vec3(args: A) -> Self;
+}
+
+impl<T> Vec3<(Expr<V2<T>>, Expr<T>)> for Expr<V3<T>> {
+  fn vec3(args: (Expr<V2<T>>, Expr<T>)) -> Self {
+    let (xy, z) = args;
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::Vec3,
+      vec![xy.erased, z.erased],
+    ))
+  }
+}
+
+impl<T> Vec3<(Expr<T>, Expr<T>, Expr<T>)> for Expr<V3<T>> {
+  fn vec3(args: (Expr<T>, Expr<T>, Expr<T>)) -> Self {
+    let (x, y, z) = args;
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::Vec3,
+      vec![x.erased, y.erased, z.erased],
+    ))
+  }
+}
+
+/// Trait allowing to create 4D scalar vector ([`V4`])constructors.
+///
+/// 4D scalar vectors can be created from either four sole scalars, a single 3D scalar vector with a single scalar,
+/// two 2D scalar vectors, a 2D scalar vector and a sole scalar or a single 4D scalar vector (identity function).
+///
+/// The `A` type variable represents the arguments type. In the case of several arguments, tuples are used.
+///
+/// You are advised to use the [`vec4!`](vec4) macro instead as the interface of this function is not really
+/// user-friendly.
+pub trait Vec4<A> {
+  /// Make a [`V4`] from `A`.
+  fn vec4(args: A) -> Self;
+}
+
+impl<T> Vec4<(Expr<V3<T>>, Expr<T>)> for Expr<V4<T>> {
+  fn vec4(args: (Expr<V3<T>>, Expr<T>)) -> Self {
+    let (xyz, w) = args;
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::Vec4,
+      vec![xyz.erased, w.erased],
+    ))
+  }
+}
+
+impl<T> Vec4<(Expr<V2<T>>, Expr<V2<T>>)> for Expr<V4<T>> {
+  fn vec4(args: (Expr<V2<T>>, Expr<V2<T>>)) -> Self {
+    let (xy, zw) = args;
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::Vec4,
+      vec![xy.erased, zw.erased],
+    ))
+  }
+}
+
+impl<T> Vec4<(Expr<V2<T>>, Expr<T>, Expr<T>)> for Expr<V4<T>> {
+  fn vec4(args: (Expr<V2<T>>, Expr<T>, Expr<T>)) -> Self {
+    let (xy, z, w) = args;
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::Vec4,
+      vec![xy.erased, z.erased, w.erased],
+    ))
+  }
+}
+
+impl<T> Vec4<(Expr<T>, Expr<T>, Expr<T>, Expr<T>)> for Expr<V4<T>> {
+  fn vec4(args: (Expr<T>, Expr<T>, Expr<T>, Expr<T>)) -> Self {
+    let (x, y, z, w) = args;
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::Vec4,
+      vec![x.erased, y.erased, z.erased, w.erased],
+    ))
+  }
+}
+
+impl<T> Expr<T>
+where
+  T: PartialOrd,
+{
+  /// Less-than expression.
+  ///
+  /// This method builds an expression representing the binary operation `a < b`.
+  ///
+  /// # Return
+  ///
+  /// An [`Expr<bool>`] representing `a < b`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::lit;
+  ///
+  /// let _ = lit!(1).lt(2); // 1 < 2;
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn lt(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+    Expr::new(
+      ErasedExpr::Lt(self.erased.clone().intern(), rhs.into().erased.intern()).fold_literals(),
+    )
+  }
+
+  /// Less-than-or-equal expression.
+  ///
+  /// This method builds an expression representing the binary operation `a <= b`.
+  ///
+  /// # Return
+  ///
+  /// An [`Expr<bool>`] representing `a <= b`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::lit;
+  ///
+  /// let _ = lit!(1).lte(2); // 1 <= 2;
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn lte(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+    Expr::new(
+      ErasedExpr::Lte(self.erased.clone().intern(), rhs.into().erased.intern()).fold_literals(),
+    )
+  }
+
+  /// Greater-than expression.
+  ///
+  /// This method builds an expression representing the binary operation `a > b`.
+  ///
+  /// # Return
+  ///
+  /// An [`Expr<bool>`] representing `a > b`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::lit;
+  ///
+  /// let _ = lit!(1).gt(2); // 1 > 2;
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn gt(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+    Expr::new(
+      ErasedExpr::Gt(self.erased.clone().intern(), rhs.into().erased.intern()).fold_literals(),
+    )
+  }
+
+  /// Less-than-or-equal expression.
+  ///
+  /// This method builds an expression representing the binary operation `a <= b`.
+  ///
+  /// # Return
+  ///
+  /// An [`Expr<bool>`] representing `a <= b`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::lit;
+  ///
+  /// let _ = lit!(1).lte(2); // 1 <= 2;
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn gte(&self, rhs: impl Into<Expr<T>>) -> Expr<bool> {
+    Expr::new(
+      ErasedExpr::Gte(self.erased.clone().intern(), rhs.into().erased.intern()).fold_literals(),
+    )
+  }
+}
+
+impl Expr<bool> {
+  /// Logical _and_ expression.
+  ///
+  /// This method builds an expression representing the logical operation `a AND b`.
+  ///
+  /// # Return
+  ///
+  /// An [`Expr<bool>`] representing `a AND b`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::lit;
+  ///
+  /// let _ = lit!(true).and(false); // true && false
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn and(&self, rhs: impl Into<Expr<bool>>) -> Expr<bool> {
+    Expr::new(
+      ErasedExpr::And(self.erased.clone().intern(), rhs.into().erased.intern()).fold_literals(),
+    )
+  }
+
+  /// Logical _or_ expression.
+  ///
+  /// This method builds an expression representing the logical operation `a OR b`.
+  ///
+  /// # Return
+  ///
+  /// An [`Expr<bool>`] representing `a OR b`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::lit;
+  ///
+  /// let _ = lit!(true).or(false); // true || false
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn or(&self, rhs: impl Into<Expr<bool>>) -> Expr<bool> {
+    Expr::new(
+      ErasedExpr::Or(self.erased.clone().intern(), rhs.into().erased.intern()).fold_literals(),
+    )
+  }
+
+  /// Logical _exclusive or_ expression.
+  ///
+  /// This method builds an expression representing the logical operation `a XOR b`.
+  ///
+  /// # Return
+  ///
+  /// An [`Expr<bool>`] representing `a XOR b`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::lit;
+  ///
+  /// let _ = lit!(true).xor(false); // true ^^ false
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn xor(&self, rhs: impl Into<Expr<bool>>) -> Expr<bool> {
+    Expr::new(
+      ErasedExpr::Xor(self.erased.clone().intern(), rhs.into().erased.intern()).fold_literals(),
+    )
+  }
+}
+
+impl<T> Expr<[T]> {
+  /// Array lookup.
+  ///
+  /// The expression `a.at(i)` represents an _array lookup_, where `a` is an array — which type must be either
+  /// [`Expr<[T]>`](Expr) or [`Expr<[T; N]>`](Expr) – and `i` is an [`Expr<i32>`].
+  ///
+  /// # Return
+  ///
+  /// The resulting [`Expr<T>`] represents the array lookup in `a` at index `i`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::lit;
+  ///
+  /// let _ = lit!([1, 2, 3]).at(2); // [1, 2, 3][2]
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn at(&self, index: impl Into<Expr<i32>>) -> Expr<T> {
+    Expr::new(ErasedExpr::ArrayLookup {
+      object: self.erased.clone().intern(),
+      index: index.into().erased.intern(),
+    })
+  }
+
+  /// Array length.
+  ///
+  /// The expression `a.length()` represents the number of items in `a`. For unsized arrays — e.g. some built-ins — the
+  /// length is only known when the shader runs.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// let len = s.var(vertex.clip_distance.length()); // gl_ClipDistance.length()
+  /// #   })
+  /// # });
+  /// ```
+  pub fn length(&self) -> Expr<i32> {
+    Expr::new(ErasedExpr::ArrayLength(self.erased.clone().intern()))
+  }
+}
+
+impl<T, const N: usize> Expr<[T; N]> {
+  /// Array lookup.
+  ///
+  /// The expression `a.at(i)` represents an _array lookup_, where `a` is an array — which type must be either
+  /// [`Expr<[T]>`](Expr) or [`Expr<[T; N]>`](Expr) – and `i` is an [`Expr<i32>`].
+  ///
+  /// # Return
+  ///
+  /// The resulting [`Expr<T>`] represents the array lookup in `a` at index `i`. Literal indices out of the bounds of the
+  /// array are reported by [`Shader::check`].
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::lit;
+  ///
+  /// let _ = lit!([1, 2, 3]).at(2); // [1, 2, 3][2]
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn at(&self, index: impl Into<Expr<i32>>) -> Expr<T> {
+    Expr::new(ErasedExpr::ArrayLookup {
+      object: self.erased.clone().intern(),
+      index: index.into().erased.intern(),
+    })
+  }
+
+  /// Array length.
+  ///
+  /// As the length of the array is known from its type, the expression `a.length()` is the constant `N`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::lit;
+  ///
+  /// let _ = lit!([1, 2, 3]).length(); // 3
+  /// # s.main_fun(|s: &mut Scope<()>| {})
+  /// # });
+  /// ```
+  pub fn length(&self) -> Expr<i32> {
+    Expr::from(N as i32)
+  }
+}
+
+// not
+macro_rules! impl_Not_Expr {
+  ($t:ty) => {
+    impl ops::Not for Expr<$t> {
+      type Output = Self;
+
+      fn not(self) -> Self::Output {
+        Expr::new(ErasedExpr::Not(self.erased.intern()).fold_literals())
+      }
+    }
+
+    impl<'a> ops::Not for &'a Expr<$t> {
+      type Output = Expr<$t>;
+
+      fn not(self) -> Self::Output {
+        Expr::new(ErasedExpr::Not(self.erased.clone().intern()).fold_literals())
+      }
+    }
+
+    impl ops::Not for Var<$t> {
+      type Output = Expr<$t>;
+
+      fn not(self) -> Self::Output {
+        Expr::new(ErasedExpr::Not(self.0.erased.intern()).fold_literals())
+      }
+    }
+
+    impl<'a> ops::Not for &'a Var<$t> {
+      type Output = Expr<$t>;
+
+      fn not(self) -> Self::Output {
+        Expr::new(ErasedExpr::Not(self.0.erased.clone().intern()).fold_literals())
+      }
+    }
+  };
+}
+
+impl_Not_Expr!(bool);
+impl_Not_Expr!(V2<bool>);
+impl_Not_Expr!(V3<bool>);
+impl_Not_Expr!(V4<bool>);
+
+// neg
+macro_rules! impl_Neg {
+  ($t:ty) => {
+    impl ops::Neg for Expr<$t> {
+      type Output = Self;
+
+      fn neg(self) -> Self::Output {
+        Expr::new(ErasedExpr::Neg(self.erased.intern()).fold_literals())
+      }
+    }
+
+    impl<'a> ops::Neg for &'a Expr<$t> {
+      type Output = Expr<$t>;
+
+      fn neg(self) -> Self::Output {
+        Expr::new(ErasedExpr::Neg(self.erased.clone().intern()).fold_literals())
+      }
+    }
+
+    impl ops::Neg for Var<$t> {
+      type Output = Expr<$t>;
+
+      fn neg(self) -> Self::Output {
+        Expr::new(ErasedExpr::Neg(self.0.erased.intern()).fold_literals())
+      }
+    }
+
+    impl<'a> ops::Neg for &'a Var<$t> {
+      type Output = Expr<$t>;
+
+      fn neg(self) -> Self::Output {
+        Expr::new(ErasedExpr::Neg(self.0.erased.clone().intern()).fold_literals())
+      }
+    }
+  };
+}
+
+impl_Neg!(i32);
+impl_Neg!(V2<i32>);
+impl_Neg!(V3<i32>);
+impl_Neg!(V4<i32>);
+
+impl_Neg!(u32);
+impl_Neg!(V2<u32>);
+impl_Neg!(V3<u32>);
+impl_Neg!(V4<u32>);
+
+impl_Neg!(f32);
+impl_Neg!(V2<f32>);
+impl_Neg!(V3<f32>);
+impl_Neg!(V4<f32>);
+
+// binary arithmetic and logical (+, -, *, /, %)
+// binop
+macro_rules! impl_binop_Expr {
+  ($op:ident, $meth_name:ident, $a:ty, $b:ty) => {
+    impl_binop_Expr!($op, $meth_name, $a, $b, $a);
+  };
+
+  ($op:ident, $meth_name:ident, $a:ty, $b:ty, $r:ty) => {
+    // expr OP expr
+    impl<'a> ops::$op<Expr<$b>> for Expr<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: Expr<$b>) -> Self::Output {
+        Expr::new(ErasedExpr::$op(self.erased.intern(), rhs.erased.intern()).fold_literals())
+      }
+    }
+
+    // var OP expr
+    impl<'a> ops::$op<Expr<$b>> for Var<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: Expr<$b>) -> Self::Output {
+        Expr::new(ErasedExpr::$op(self.0.erased.intern(), rhs.erased.intern()).fold_literals())
+      }
+    }
+
+    // expr OP var
+    impl<'a> ops::$op<Var<$b>> for Expr<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: Var<$b>) -> Self::Output {
+        Expr::new(ErasedExpr::$op(self.erased.intern(), rhs.0.erased.intern()).fold_literals())
+      }
+    }
+
+    // var OP var
+    impl<'a> ops::$op<Var<$b>> for Var<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: Var<$b>) -> Self::Output {
+        Expr::new(ErasedExpr::$op(self.0.erased.intern(), rhs.0.erased.intern()).fold_literals())
+      }
+    }
+
+    // expr OP &expr
+    impl<'a> ops::$op<&'a Expr<$b>> for Expr<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: &'a Expr<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.erased.intern(), rhs.erased.clone().intern()).fold_literals(),
+        )
+      }
+    }
+
+    // var OP &expr
+    impl<'a> ops::$op<&'a Expr<$b>> for Var<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: &'a Expr<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.0.erased.intern(), rhs.erased.clone().intern()).fold_literals(),
+        )
+      }
+    }
+
+    // expr OP &var
+    impl<'a> ops::$op<&'a Var<$b>> for Expr<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: &'a Var<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.erased.intern(), rhs.0.erased.clone().intern()).fold_literals(),
+        )
+      }
+    }
+
+    // var OP &var
+    impl<'a> ops::$op<&'a Var<$b>> for Var<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: &'a Var<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.0.erased.intern(), rhs.0.erased.clone().intern()).fold_literals(),
+        )
+      }
+    }
+
+    // &expr OP expr
+    impl<'a> ops::$op<Expr<$b>> for &'a Expr<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: Expr<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.erased.clone().intern(), rhs.erased.intern()).fold_literals(),
+        )
+      }
+    }
+
+    // &var OP expr
+    impl<'a> ops::$op<Expr<$b>> for &'a Var<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: Expr<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.0.erased.clone().intern(), rhs.erased.intern()).fold_literals(),
+        )
+      }
+    }
+
+    // &expr OP var
+    impl<'a> ops::$op<Var<$b>> for &'a Expr<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: Var<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.erased.clone().intern(), rhs.0.erased.intern()).fold_literals(),
+        )
+      }
+    }
+
+    // &var OP var
+    impl<'a> ops::$op<Var<$b>> for &'a Var<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: Var<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.0.erased.clone().intern(), rhs.0.erased.intern()).fold_literals(),
+        )
+      }
+    }
+
+    // &expr OP &expr
+    impl<'a> ops::$op<&'a Expr<$b>> for &'a Expr<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: &'a Expr<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.erased.clone().intern(), rhs.erased.clone().intern())
+            .fold_literals(),
+        )
+      }
+    }
+
+    // &var OP &expr
+    impl<'a> ops::$op<&'a Expr<$b>> for &'a Var<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: &'a Expr<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.0.erased.clone().intern(), rhs.erased.clone().intern())
+            .fold_literals(),
+        )
+      }
+    }
+
+    // &expr OP &var
+    impl<'a> ops::$op<&'a Var<$b>> for &'a Expr<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: &'a Var<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.erased.clone().intern(), rhs.0.erased.clone().intern())
+            .fold_literals(),
+        )
+      }
+    }
+
+    // &var OP &var
+    impl<'a> ops::$op<&'a Var<$b>> for &'a Var<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: &'a Var<$b>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(
+            self.0.erased.clone().intern(),
+            rhs.0.erased.clone().intern(),
+          )
+          .fold_literals(),
+        )
+      }
     }
 
     // expr OP t, where t is automatically lifted
     impl<'a> ops::$op<$b> for Expr<$a> {
       type Output = Expr<$r>;
 
-      fn $meth_name(self, rhs: $b) -> Self::Output {
-        let rhs = Expr::from(rhs);
-        Expr::new(ErasedExpr::$op(Box::new(self.erased), Box::new(rhs.erased)))
-      }
-    }
+      fn $meth_name(self, rhs: $b) -> Self::Output {
+        let rhs = Expr::from(rhs);
+        Expr::new(ErasedExpr::$op(self.erased.intern(), rhs.erased.intern()).fold_literals())
+      }
+    }
+
+    // var OP t, where t is automatically lifted
+    impl<'a> ops::$op<$b> for Var<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: $b) -> Self::Output {
+        let rhs = Expr::from(rhs);
+        Expr::new(ErasedExpr::$op(self.0.erased.intern(), rhs.erased.intern()).fold_literals())
+      }
+    }
+
+    // &expr OP t, where t is automatically lifted
+    impl<'a> ops::$op<$b> for &'a Expr<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: $b) -> Self::Output {
+        let rhs: Expr<$b> = rhs.into();
+        Expr::new(
+          ErasedExpr::$op(self.erased.clone().intern(), rhs.erased.intern()).fold_literals(),
+        )
+      }
+    }
+
+    // &var OP t, where t is automatically lifted
+    impl<'a> ops::$op<$b> for &'a Var<$a> {
+      type Output = Expr<$r>;
+
+      fn $meth_name(self, rhs: $b) -> Self::Output {
+        let rhs: Expr<$b> = rhs.into();
+        Expr::new(
+          ErasedExpr::$op(self.0.erased.clone().intern(), rhs.erased.intern()).fold_literals(),
+        )
+      }
+    }
+  };
+}
+
+// t OP expr, where t is automatically lifted; the reflected version of “expr OP t”
+macro_rules! impl_binop_scalar_lhs_Expr {
+  ($op:ident, $meth_name:ident, $a:ty, $b:ty) => {
+    // t OP expr
+    impl ops::$op<Expr<$b>> for $a {
+      type Output = Expr<$b>;
+
+      fn $meth_name(self, rhs: Expr<$b>) -> Self::Output {
+        let lhs = Expr::from(self);
+        Expr::new(ErasedExpr::$op(lhs.erased.intern(), rhs.erased.intern()).fold_literals())
+      }
+    }
+
+    // t OP var
+    impl ops::$op<Var<$b>> for $a {
+      type Output = Expr<$b>;
+
+      fn $meth_name(self, rhs: Var<$b>) -> Self::Output {
+        let lhs = Expr::from(self);
+        Expr::new(ErasedExpr::$op(lhs.erased.intern(), rhs.0.erased.intern()).fold_literals())
+      }
+    }
+
+    // t OP &expr
+    impl<'a> ops::$op<&'a Expr<$b>> for $a {
+      type Output = Expr<$b>;
+
+      fn $meth_name(self, rhs: &'a Expr<$b>) -> Self::Output {
+        let lhs = Expr::from(self);
+        Expr::new(ErasedExpr::$op(lhs.erased.intern(), rhs.erased.clone().intern()).fold_literals())
+      }
+    }
+
+    // t OP &var
+    impl<'a> ops::$op<&'a Var<$b>> for $a {
+      type Output = Expr<$b>;
+
+      fn $meth_name(self, rhs: &'a Var<$b>) -> Self::Output {
+        let lhs = Expr::from(self);
+        Expr::new(
+          ErasedExpr::$op(lhs.erased.intern(), rhs.0.erased.clone().intern()).fold_literals(),
+        )
+      }
+    }
+  };
+}
+
+// or
+impl_binop_Expr!(BitOr, bitor, bool, bool);
+impl_binop_Expr!(BitOr, bitor, V2<bool>, V2<bool>);
+impl_binop_Expr!(BitOr, bitor, V2<bool>, bool);
+impl_binop_Expr!(BitOr, bitor, V3<bool>, V3<bool>);
+impl_binop_Expr!(BitOr, bitor, V3<bool>, bool);
+impl_binop_Expr!(BitOr, bitor, V4<bool>, V4<bool>);
+impl_binop_Expr!(BitOr, bitor, V4<bool>, bool);
+
+// and
+impl_binop_Expr!(BitAnd, bitand, bool, bool);
+impl_binop_Expr!(BitAnd, bitand, V2<bool>, V2<bool>);
+impl_binop_Expr!(BitAnd, bitand, V2<bool>, bool);
+impl_binop_Expr!(BitAnd, bitand, V3<bool>, V3<bool>);
+impl_binop_Expr!(BitAnd, bitand, V3<bool>, bool);
+impl_binop_Expr!(BitAnd, bitand, V4<bool>, V4<bool>);
+impl_binop_Expr!(BitAnd, bitand, V4<bool>, bool);
+
+// xor
+impl_binop_Expr!(BitXor, bitxor, bool, bool);
+impl_binop_Expr!(BitXor, bitxor, V2<bool>, V2<bool>);
+impl_binop_Expr!(BitXor, bitxor, V2<bool>, bool);
+impl_binop_Expr!(BitXor, bitxor, V3<bool>, V3<bool>);
+impl_binop_Expr!(BitXor, bitxor, V3<bool>, bool);
+impl_binop_Expr!(BitXor, bitxor, V4<bool>, V4<bool>);
+impl_binop_Expr!(BitXor, bitxor, V4<bool>, bool);
+
+/// Run a macro on all supported types to generate the impl for them
+///
+/// The macro has to have to take two `ty` as argument and yield a `std::ops` trait implementor.
+macro_rules! impl_binarith_Expr {
+  ($op:ident, $meth_name:ident) => {
+    impl_binop_Expr!($op, $meth_name, i32, i32);
+    impl_binop_Expr!($op, $meth_name, V2<i32>, V2<i32>);
+    impl_binop_Expr!($op, $meth_name, V2<i32>, i32);
+    impl_binop_Expr!($op, $meth_name, V3<i32>, V3<i32>);
+    impl_binop_Expr!($op, $meth_name, V3<i32>, i32);
+    impl_binop_Expr!($op, $meth_name, V4<i32>, V4<i32>);
+    impl_binop_Expr!($op, $meth_name, V4<i32>, i32);
+
+    impl_binop_Expr!($op, $meth_name, u32, u32);
+    impl_binop_Expr!($op, $meth_name, V2<u32>, V2<u32>);
+    impl_binop_Expr!($op, $meth_name, V2<u32>, u32);
+    impl_binop_Expr!($op, $meth_name, V3<u32>, V3<u32>);
+    impl_binop_Expr!($op, $meth_name, V3<u32>, u32);
+    impl_binop_Expr!($op, $meth_name, V4<u32>, V4<u32>);
+    impl_binop_Expr!($op, $meth_name, V4<u32>, u32);
+
+    impl_binop_Expr!($op, $meth_name, f32, f32);
+    impl_binop_Expr!($op, $meth_name, V2<f32>, V2<f32>);
+    impl_binop_Expr!($op, $meth_name, V2<f32>, f32);
+    impl_binop_Expr!($op, $meth_name, V3<f32>, V3<f32>);
+    impl_binop_Expr!($op, $meth_name, V3<f32>, f32);
+    impl_binop_Expr!($op, $meth_name, V4<f32>, V4<f32>);
+    impl_binop_Expr!($op, $meth_name, V4<f32>, f32);
+
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, i32, i32);
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, i32, V2<i32>);
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, i32, V3<i32>);
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, i32, V4<i32>);
+
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, u32, u32);
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, u32, V2<u32>);
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, u32, V3<u32>);
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, u32, V4<u32>);
+
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, f32, f32);
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, f32, V2<f32>);
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, f32, V3<f32>);
+    impl_binop_scalar_lhs_Expr!($op, $meth_name, f32, V4<f32>);
+  };
+}
+
+impl_binarith_Expr!(Add, add);
+impl_binarith_Expr!(Sub, sub);
+impl_binarith_Expr!(Mul, mul);
+impl_binarith_Expr!(Div, div);
+
+impl_binop_Expr!(Rem, rem, f32, f32);
+impl_binop_Expr!(Rem, rem, V2<f32>, V2<f32>);
+impl_binop_Expr!(Rem, rem, V2<f32>, f32);
+impl_binop_Expr!(Rem, rem, V3<f32>, V3<f32>);
+impl_binop_Expr!(Rem, rem, V3<f32>, f32);
+impl_binop_Expr!(Rem, rem, V4<f32>, V4<f32>);
+impl_binop_Expr!(Rem, rem, V4<f32>, f32);
+impl_binop_scalar_lhs_Expr!(Rem, rem, f32, f32);
+impl_binop_scalar_lhs_Expr!(Rem, rem, f32, V2<f32>);
+impl_binop_scalar_lhs_Expr!(Rem, rem, f32, V3<f32>);
+impl_binop_scalar_lhs_Expr!(Rem, rem, f32, V4<f32>);
+
+// matrix products; a vector on the left-hand side is a row vector, and the result is a vector as well
+impl_binop_Expr!(Mul, mul, M22, M22);
+impl_binop_Expr!(Mul, mul, M22, V2<f32>, V2<f32>);
+impl_binop_Expr!(Mul, mul, V2<f32>, M22, V2<f32>);
+impl_binop_Expr!(Mul, mul, M33, M33);
+impl_binop_Expr!(Mul, mul, M33, V3<f32>, V3<f32>);
+impl_binop_Expr!(Mul, mul, V3<f32>, M33, V3<f32>);
+impl_binop_Expr!(Mul, mul, M44, M44);
+impl_binop_Expr!(Mul, mul, M44, V4<f32>, V4<f32>);
+impl_binop_Expr!(Mul, mul, V4<f32>, M44, V4<f32>);
+
+// matrix scaling
+impl_binop_Expr!(Mul, mul, M22, f32);
+impl_binop_Expr!(Mul, mul, M33, f32);
+impl_binop_Expr!(Mul, mul, M44, f32);
+impl_binop_scalar_lhs_Expr!(Mul, mul, f32, M22);
+impl_binop_scalar_lhs_Expr!(Mul, mul, f32, M33);
+impl_binop_scalar_lhs_Expr!(Mul, mul, f32, M44);
+
+macro_rules! impl_binshift_Expr {
+  ($op:ident, $meth_name:ident, $ty:ty) => {
+    // expr OP expr
+    impl ops::$op<Expr<u32>> for Expr<$ty> {
+      type Output = Expr<$ty>;
+
+      fn $meth_name(self, rhs: Expr<u32>) -> Self::Output {
+        Expr::new(ErasedExpr::$op(self.erased.intern(), rhs.erased.intern()).fold_literals())
+      }
+    }
+
+    impl<'a> ops::$op<Expr<u32>> for &'a Expr<$ty> {
+      type Output = Expr<$ty>;
+
+      fn $meth_name(self, rhs: Expr<u32>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.erased.clone().intern(), rhs.erased.intern()).fold_literals(),
+        )
+      }
+    }
+
+    impl<'a> ops::$op<&'a Expr<u32>> for Expr<$ty> {
+      type Output = Expr<$ty>;
+
+      fn $meth_name(self, rhs: &'a Expr<u32>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.erased.intern(), rhs.erased.clone().intern()).fold_literals(),
+        )
+      }
+    }
+
+    impl<'a> ops::$op<&'a Expr<u32>> for &'a Expr<$ty> {
+      type Output = Expr<$ty>;
+
+      fn $meth_name(self, rhs: &'a Expr<u32>) -> Self::Output {
+        Expr::new(
+          ErasedExpr::$op(self.erased.clone().intern(), rhs.erased.clone().intern())
+            .fold_literals(),
+        )
+      }
+    }
+
+    // expr OP bits
+    impl ops::$op<u32> for Expr<$ty> {
+      type Output = Self;
+
+      fn $meth_name(self, rhs: u32) -> Self::Output {
+        let rhs = Expr::from(rhs);
+        Expr::new(ErasedExpr::$op(self.erased.intern(), rhs.erased.intern()).fold_literals())
+      }
+    }
+
+    impl<'a> ops::$op<u32> for &'a Expr<$ty> {
+      type Output = Expr<$ty>;
+
+      fn $meth_name(self, rhs: u32) -> Self::Output {
+        let rhs = Expr::from(rhs);
+        Expr::new(
+          ErasedExpr::$op(self.erased.clone().intern(), rhs.erased.intern()).fold_literals(),
+        )
+      }
+    }
+  };
+}
+
+/// Binary shift generating macro.
+macro_rules! impl_binshifts_Expr {
+  ($op:ident, $meth_name:ident) => {
+    impl_binshift_Expr!($op, $meth_name, i32);
+    impl_binshift_Expr!($op, $meth_name, V2<i32>);
+    impl_binshift_Expr!($op, $meth_name, V3<i32>);
+    impl_binshift_Expr!($op, $meth_name, V4<i32>);
+
+    impl_binshift_Expr!($op, $meth_name, u32);
+    impl_binshift_Expr!($op, $meth_name, V2<u32>);
+    impl_binshift_Expr!($op, $meth_name, V3<u32>);
+    impl_binshift_Expr!($op, $meth_name, V4<u32>);
+
+    impl_binshift_Expr!($op, $meth_name, f32);
+    impl_binshift_Expr!($op, $meth_name, V2<f32>);
+    impl_binshift_Expr!($op, $meth_name, V3<f32>);
+    impl_binshift_Expr!($op, $meth_name, V4<f32>);
+  };
+}
+
+impl_binshifts_Expr!(Shl, shl);
+impl_binshifts_Expr!(Shr, shr);
+
+macro_rules! impl_From_Expr_scalar {
+  ($t:ty, $q:ident) => {
+    impl From<$t> for Expr<$t> {
+      fn from(a: $t) -> Self {
+        Self::new(ErasedExpr::$q(a))
+      }
+    }
+
+    impl<'a> From<&'a $t> for Expr<$t> {
+      fn from(a: &'a $t) -> Self {
+        Self::new(ErasedExpr::$q(*a))
+      }
+    }
+  };
+}
+
+impl_From_Expr_scalar!(i32, LitInt);
+impl_From_Expr_scalar!(u32, LitUInt);
+impl_From_Expr_scalar!(f32, LitFloat);
+impl_From_Expr_scalar!(bool, LitBool);
+
+macro_rules! impl_From_Expr_vn {
+  ($t:ty, $q:ident) => {
+    impl From<$t> for Expr<$t> {
+      fn from(a: $t) -> Self {
+        Self::new(ErasedExpr::$q(a.0))
+      }
+    }
+
+    impl<'a> From<&'a $t> for Expr<$t> {
+      fn from(a: &'a $t) -> Self {
+        Self::new(ErasedExpr::$q(a.0))
+      }
+    }
+  };
+}
+
+impl_From_Expr_vn!(V2<i32>, LitInt2);
+impl_From_Expr_vn!(V2<u32>, LitUInt2);
+impl_From_Expr_vn!(V2<f32>, LitFloat2);
+impl_From_Expr_vn!(V2<bool>, LitBool2);
+impl_From_Expr_vn!(V3<i32>, LitInt3);
+impl_From_Expr_vn!(V3<u32>, LitUInt3);
+impl_From_Expr_vn!(V3<f32>, LitFloat3);
+impl_From_Expr_vn!(V3<bool>, LitBool3);
+impl_From_Expr_vn!(V4<i32>, LitInt4);
+impl_From_Expr_vn!(V4<u32>, LitUInt4);
+impl_From_Expr_vn!(V4<f32>, LitFloat4);
+impl_From_Expr_vn!(V4<bool>, LitBool4);
+
+impl<T, const N: usize> From<[T; N]> for Expr<[T; N]>
+where
+  Expr<T>: From<T>,
+  T: Clone + ToType,
+{
+  fn from(array: [T; N]) -> Self {
+    let array = array
+      .iter()
+      .cloned()
+      .map(|t| Expr::from(t).erased)
+      .collect();
+    Self::new(ErasedExpr::array(<[T; N] as ToType>::ty(), array))
+  }
+}
+
+impl<'a, T, const N: usize> From<&'a [T; N]> for Expr<[T; N]>
+where
+  Expr<T>: From<T>,
+  T: Clone + ToType,
+{
+  fn from(array: &'a [T; N]) -> Self {
+    let array = array
+      .iter()
+      .cloned()
+      .map(|t| Expr::from(t).erased)
+      .collect();
+    Self::new(ErasedExpr::array(<[T; N] as ToType>::ty(), array))
+  }
+}
+
+impl<T, const N: usize> From<[Expr<T>; N]> for Expr<[T; N]>
+where
+  Expr<T>: From<T>,
+  T: ToType,
+{
+  fn from(array: [Expr<T>; N]) -> Self {
+    let array = array.iter().cloned().map(|e| e.erased).collect();
+    Self::new(ErasedExpr::array(<[T; N] as ToType>::ty(), array))
+  }
+}
+
+impl<'a, T, const N: usize> From<&'a [Expr<T>; N]> for Expr<[T; N]>
+where
+  Expr<T>: From<T>,
+  T: ToType,
+{
+  fn from(array: &'a [Expr<T>; N]) -> Self {
+    let array = array.iter().cloned().map(|e| e.erased).collect();
+    Self::new(ErasedExpr::array(<[T; N] as ToType>::ty(), array))
+  }
+}
+
+/// Build an array expression out of computed expressions.
+///
+/// This allows to embed tables computed on the Rust side, such as precomputed kernel weights.
+///
+/// # Panics
+///
+/// Panics if the iterator doesn’t yield exactly `N` expressions.
+///
+/// # Examples
+///
+/// ```
+/// # use shades::{Scope, ShaderBuilder};
+/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+/// use shades::{Expr, lit};
+///
+/// let weights: Expr<[f32; 5]> = (0..5).map(|i| lit!((-(i * i) as f32 / 8.).exp())).collect();
+/// # s.main_fun(|s: &mut Scope<()>| {})
+/// # });
+/// ```
+impl<T, const N: usize> std::iter::FromIterator<Expr<T>> for Expr<[T; N]>
+where
+  T: ToType,
+{
+  fn from_iter<I>(iter: I) -> Self
+  where
+    I: IntoIterator<Item = Expr<T>>,
+  {
+    let array: Vec<_> = iter.into_iter().map(|e| e.erased).collect();
+
+    assert_eq!(
+      array.len(),
+      N,
+      "expected {} expressions to build the array, got {}",
+      N,
+      array.len()
+    );
+
+    Self::new(ErasedExpr::array(<[T; N] as ToType>::ty(), array))
+  }
+}
+
+impl<T> Expr<[T]>
+where
+  T: ToType,
+{
+  /// Build an array out of expressions, which number is only known at runtime.
+  ///
+  /// [`EmptyArray`] is returned if the iterator is empty, as arrays cannot be empty.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{Expr, lit};
+  ///
+  /// let squares = Expr::<[f32]>::try_from_iter((0..4).map(|i| lit!((i * i) as f32))).unwrap();
+  /// assert!(Expr::<[f32]>::try_from_iter(Vec::new()).is_err());
+  /// ```
+  pub fn try_from_iter<I>(iter: I) -> Result<Self, EmptyArray>
+  where
+    I: IntoIterator<Item = Expr<T>>,
+  {
+    let array: Vec<_> = iter.into_iter().map(|e| e.erased).collect();
+
+    if array.is_empty() {
+      return Err(EmptyArray);
+    }
+
+    Ok(Self::new(ErasedExpr::array(
+      array_ty::<T>(array.len()),
+      array,
+    )))
+  }
+}
+
+/// Array literal holding a copy of `items`, which length is only known at runtime.
+///
+/// This allows to embed data computed on the host in expressions without knowing its length at compile time.
+/// [`EmptyArray`] is returned if `items` is empty, as arrays cannot be empty.
+///
+/// # Examples
+///
+/// ```
+/// use shades::Expr;
+/// use std::convert::TryFrom as _;
+///
+/// let samples: Vec<i32> = (0..5).collect();
+/// let third = Expr::<[i32]>::try_from(samples.as_slice()).unwrap().at(2); // int[5](0,1,2,3,4)[2]
+/// ```
+impl<'a, T> TryFrom<&'a [T]> for Expr<[T]>
+where
+  T: Clone + ToType,
+  Expr<T>: From<T>,
+{
+  type Error = EmptyArray;
+
+  fn try_from(items: &'a [T]) -> Result<Self, Self::Error> {
+    Self::try_from_iter(items.iter().map(|item| Expr::from(item.clone())))
+  }
+}
+
+/// Error returned when building an array out of no items, as arrays cannot be empty.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EmptyArray;
+
+impl fmt::Display for EmptyArray {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("arrays cannot be empty")
+  }
+}
+
+impl Error for EmptyArray {}
+
+/// Type of arrays of `len` items of type `T`.
+fn array_ty<T>(len: usize) -> Type
+where
+  T: ToType,
+{
+  let Type {
+    prim_ty,
+    array_dims,
+  } = T::ty();
+
+  Type {
+    prim_ty,
+    array_dims: once(len).chain(array_dims).collect(),
+  }
+}
+
+/// Create various forms of literal expressions.
+///
+/// This macro allows you to create _literal expressions_ by lifting Rust constants into the EDSL. The way this is done
+/// is via several forms:
+///
+/// - `lit!(x)` lifts a single Rust expression into the EDSL. It’s isomorphic to `Expr::from(x)`.
+/// - `lit!(x, y)` lifts two Rust expressions into the EDSL as a 2D scalar vector. It’s isomorphic to
+///   `Expr::from(V2::from([x, y]))`.
+/// - `lit!(x, y, z)` lifts three Rust expressions into the EDSL as a 3D scalar vector. It’s isomorphic to
+///   `Expr::from(V3::from([x, y, z]))`.
+/// - `lit!(x, y, z, w)` lifts three Rust expressions into the EDSL as a 3D scalar vector. It’s isomorphic to
+///   `Expr::from(V4::from([x, y, z, w]))`.
+/// - `lit!([a, b, …])` lifts an array into the EDSL. Items can also be parenthesized vectors, in which case they are
+///   lifted as with the forms above; i.e. `lit!([(x0, y0), (x1, y1)])` is an array of 2D scalar vectors.
+/// - `lit!(mat m)` lifts a column-major matrix into the EDSL, where `m` is an array of columns. It’s isomorphic to
+///   `Expr::from(Matrix::from(m))`.
+///
+/// Most of the time, type inference will kick in and you shouldn’t have to annotate the return expression.
+///
+/// # Examples
+///
+/// ```
+/// # use shades::{Scope, ShaderBuilder};
+/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+/// use shades::{lit};
+///
+/// let _ = lit!(1);
+/// let _ = lit!(false);
+/// let _ = lit!(1., 2., 3., 4.);
+/// let _ = lit!([(1., 0.), (0., 1.), (-1., 0.)]);
+/// let _ = lit!(mat [[1., 0.], [0., 1.]]);
+/// # s.main_fun(|s: &mut Scope<()>| {})
+/// # });
+/// ```
+#[macro_export]
+macro_rules! lit {
+  (mat $m:expr) => {
+    $crate::Expr::from($crate::Matrix::from($m))
+  };
+
+  ([ $( ( $($c:expr),+ ) ),* ]) => {
+    $crate::Expr::from([ $( $crate::lit!($($c),+) ),* ])
+  };
+
+  ($e:expr) => {
+    $crate::Expr::from($e)
+  };
+
+  ($a:expr, $b:expr) => {
+    $crate::Expr::from($crate::V2::from([$a, $b]))
+  };
+
+  ($a:expr, $b:expr, $c:expr) => {
+    $crate::Expr::from($crate::V3::from([$a, $b, $c]))
+  };
+
+  ($a:expr, $b:expr, $c:expr, $d:expr) => {
+    $crate::Expr::from($crate::V4::from([$a, $b, $c, $d]))
+  };
+
+  ([ $($item:expr),* ]) => {
+    $crate::Expr::from([ $($item),* ])
+  }
+}
+
+/// Create 2D scalar vectors via different forms.
+///
+/// This macro allows to create 2D ([`V2`]) scalar vectors from two forms:
+///
+/// - `vec2!(xy)`, which acts as the cast operator. Only types `T` satisfying [`Vec2`] are castable.
+/// - `vec2!(x, y)`, which builds a [`V2<T>`] for `x: T` and `y: T`.
+///
+/// # Examples
+///
+/// ```
+/// # use shades::{Scope, ShaderBuilder};
+/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+/// use shades::vec2;
+///
+/// let _ = vec2!(1, 2);
+/// # s.main_fun(|s: &mut Scope<()>| {})
+/// # });
+/// ```
+#[macro_export]
+macro_rules! vec2 {
+  ($a:expr) => {
+    todo!("vec2 cast operator missing");
+  };
+
+  ($xy:expr, $z:expr) => {{
+    use $crate::Vec2 as _;
+    $crate::Expr::vec2(($crate::Expr::from(&$xy), $crate::Expr::from(&$z)))
+  }};
+}
+
+/// Create 3D scalar vectors via different forms.
+///
+/// This macro allows to create 3D ([`V3`]) scalar vectors from several forms:
+///
+/// - `vec3!(xyz)`, which acts as the cast operator. Only types `T` satisfying [`Vec3`] are castable.
+/// - `vec3!(xy, z)`, which builds a [`V3<T>`] with `xy` a value that can be turned into a `Expr<V2<T>>` and `z: T`
+/// - `vec3!(x, y, z)`, which builds a [`V3<T>`] for `x: T`, `y: T` and `z: T`.
+///
+/// # Examples
+///
+/// ```
+/// # use shades::{Scope, ShaderBuilder};
+/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+/// use shades::{vec2, vec3};
+///
+/// let _ = vec3!(1, 2, 3);
+/// let _ = vec3!(vec2!(1, 2), 3);
+/// # s.main_fun(|s: &mut Scope<()>| {})
+/// # });
+/// ```
+#[macro_export]
+macro_rules! vec3 {
+  ($a:expr) => {
+    todo!("vec3 cast operator missing");
+  };
+
+  ($xy:expr, $z:expr) => {{
+    use $crate::Vec3 as _;
+    $crate::Expr::vec3(($crate::Expr::from(&$xy), $crate::Expr::from(&$z)))
+  }};
+
+  ($x:expr, $y:expr, $z:expr) => {{
+    use $crate::Vec3 as _;
+    $crate::Expr::vec3((
+      $crate::Expr::from(&$x),
+      $crate::Expr::from(&$y),
+      $crate::Expr::from(&$z),
+    ))
+  }};
+}
+
+/// Create 4D scalar vectors via different forms.
+///
+/// This macro allows to create 4D ([`V4`]) scalar vectors from several forms:
+///
+/// - `vec4!(xyzw)`, which acts as the cast operator. Only types `T` satisfying [`Vec4`] are castable.
+/// - `vec4!(xyz, w)`, which builds a [`V4<T>`] with `xyz` a value that can be turned into a `Expr<V3<T>>` and `w: T`.
+/// - `vec4!(xy, zw)`, which builds a [`V4<T>`] with `xy` and `zw` values that can be turned into `Expr<V3<T>>`.
+/// - `vec4!(xy, z, w)`, which builds a [`V4<T>`] with `xy`, `z: T` and `w: T`.
+/// - `vec4!(x, y, z, w)`, which builds a [`V3<T>`] for `x: T`, `y: T` and `z: T`.
+///
+/// # Examples
+///
+/// ```
+/// # use shades::{Scope, ShaderBuilder};
+/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+/// use shades::{vec2, vec3, vec4};
+///
+/// let _ = vec4!(1, 2, 3, 4);
+/// let _ = vec4!(vec3!(1, 2, 3), 4);
+/// let _ = vec4!(vec2!(1, 2), vec2!(3, 4));
+/// let _ = vec4!(vec2!(1, 2), 3, 4);
+/// # s.main_fun(|s: &mut Scope<()>| {})
+/// # });
+/// ```
+#[macro_export]
+macro_rules! vec4 {
+  ($a:expr) => {
+    todo!("vec4 cast operator missing");
+  };
+
+  ($xy:expr, $zw:expr) => {{
+    use $crate::Vec4 as _;
+    $crate::Expr::vec4(($crate::Expr::from(&$xy), $crate::Expr::from(&$zw)))
+  }};
+
+  ($xy:expr, $z:expr, $w:expr) => {{
+    use $crate::Vec4 as _;
+    $crate::Expr::vec4((
+      $crate::Expr::from(&$xy),
+      $crate::Expr::from(&$z),
+      $crate::Expr::from(&$w),
+    ))
+  }};
+
+  ($x:expr, $y:expr, $z:expr, $w:expr) => {{
+    use $crate::Vec4 as _;
+    $crate::Expr::vec4((
+      $crate::Expr::from(&$x),
+      $crate::Expr::from(&$y),
+      $crate::Expr::from(&$z),
+      $crate::Expr::from(&$w),
+    ))
+  }};
+}
+
+/// Function return.
+///
+/// This type represents a function return and is used to annotate values that can be returned from functions (i.e.
+/// expressions).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Return {
+  erased: ErasedReturn,
+}
+
+/// Erased return.
+///
+/// Either `Void` (i.e. `void`) or an expression. The type of the expression is also present for convenience.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ErasedReturn {
+  Void,
+  Expr(Type, ErasedExpr),
+}
+
+impl From<()> for Return {
+  fn from(_: ()) -> Self {
+    Return {
+      erased: ErasedReturn::Void,
+    }
+  }
+}
+
+impl<T> From<Expr<T>> for Return
+where
+  T: ToType,
+{
+  fn from(expr: Expr<T>) -> Self {
+    Return {
+      erased: ErasedReturn::Expr(T::ty(), expr.erased),
+    }
+  }
+}
+
+/// Function definition injection.
+///
+/// This trait represents _function definition injection_, i.e. types that can provide a function definition — see
+/// [`FunDef`]. Ideally, only a very small number of types can do this: polymorphic types implementing the [`FnOnce`]
+/// trait with different number of arguments. Namely, closures / lambdas with various numbers of arguments.
+///
+/// You are not supposed to implement this type by yourself. Instead, when creating functions in the EDSL, you just have
+/// to pass lambdas to automatically get the proper function definition lifted into the EDSL.
+///
+/// See the [`ShaderBuilder::fun`] for further information.
+///
+/// # Caveats
+///
+/// This way of doing currently comes with a price: type inference is bad. You will — most of the time — have to
+/// annotate the closure’s arguments. This is currently working on but progress on that matter is slow.
+pub trait ToFun<R, A> {
+  fn build_fn(self) -> FunDef<R, A>;
+}
+
+impl<F, R> ToFun<R, ()> for F
+where
+  Self: FnOnce(&mut Scope<R>) -> R,
+  Return: From<R>,
+{
+  fn build_fn(self) -> FunDef<R, ()> {
+    let mut scope = Scope::new(0);
+    let ret = self(&mut scope);
+
+    let erased = ErasedFun::new(Vec::new(), scope.erased, Return::from(ret).erased);
+
+    FunDef::new(erased)
+  }
+}
+
+macro_rules! impl_ToFun_args {
+  ($($arg:ident , $arg_ident:ident , $arg_rank:expr),*) => {
+    impl<F, R, $($arg),*> ToFun<R, ($(Expr<$arg>),*)> for F
+    where
+      Self: FnOnce(&mut Scope<R>, $(Expr<$arg>),*) -> R,
+      Return: From<R>,
+      $($arg: ToType),*
+    {
+      fn build_fn(self) -> FunDef<R, ($(Expr<$arg>),*)> {
+        $( let $arg_ident = Expr::new(ErasedExpr::Var(ScopedHandle::fun_arg($arg_rank))); )*
+          let args = vec![$( $arg::ty() ),*];
+
+        let mut scope = Scope::new(0);
+        let ret = self(&mut scope, $($arg_ident),*);
+
+        let erased = ErasedFun::new(args, scope.erased, Return::from(ret).erased);
+
+        FunDef::new(erased)
+      }
+    }
+  }
+}
+
+impl<F, R, A> ToFun<R, Expr<A>> for F
+where
+  Self: FnOnce(&mut Scope<R>, Expr<A>) -> R,
+  Return: From<R>,
+  A: ToType,
+{
+  fn build_fn(self) -> FunDef<R, Expr<A>> {
+    let arg = Expr::new(ErasedExpr::Var(ScopedHandle::fun_arg(0)));
+
+    let mut scope = Scope::new(0);
+    let ret = self(&mut scope, arg);
+
+    let erased = ErasedFun::new(vec![A::ty()], scope.erased, Return::from(ret).erased);
+
+    FunDef::new(erased)
+  }
+}
+
+impl_ToFun_args!(A0, a0, 0, A1, a1, 1);
+impl_ToFun_args!(A0, a0, 0, A1, a1, 1, A2, a2, 2);
+impl_ToFun_args!(A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3);
+impl_ToFun_args!(A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4);
+impl_ToFun_args!(A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5);
+impl_ToFun_args!(A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6);
+impl_ToFun_args!(
+  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7
+);
+impl_ToFun_args!(
+  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8, 8
+);
+impl_ToFun_args!(
+  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
+  8, A9, a9, 9
+);
+impl_ToFun_args!(
+  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
+  8, A9, a9, 9, A10, a10, 10
+);
+impl_ToFun_args!(
+  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
+  8, A9, a9, 9, A10, a10, 10, A11, a11, 11
+);
+impl_ToFun_args!(
+  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
+  8, A9, a9, 9, A10, a10, 10, A11, a11, 11, A12, a12, 12
+);
+impl_ToFun_args!(
+  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
+  8, A9, a9, 9, A10, a10, 10, A11, a11, 11, A12, a12, 12, A13, a13, 13
+);
+impl_ToFun_args!(
+  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
+  8, A9, a9, 9, A10, a10, 10, A11, a11, 11, A12, a12, 12, A13, a13, 13, A14, a14, 14
+);
+impl_ToFun_args!(
+  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
+  8, A9, a9, 9, A10, a10, 10, A11, a11, 11, A12, a12, 12, A13, a13, 13, A14, a14, 14, A15, a15, 15
+);
+impl_ToFun_args!(
+  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
+  8, A9, a9, 9, A10, a10, 10, A11, a11, 11, A12, a12, 12, A13, a13, 13, A14, a14, 14, A15, a15, 15,
+  A16, a16, 16
+);
+
+/// An opaque function handle, used to call user-defined functions.
+///
+/// Function handles are created with the [`ShaderBuilder::fun`] function, introducing new functions in the EDSL. You
+/// can then call the functions in the context of generating new expressions, returning them or creating variables.
+///
+/// Injecting a function call in the EDSL is done via two current mechanisms:
+///
+/// - Either call the [`FunHandle::call`] method:
+///   - It is a function without argument if the represented function doesn’t have any argument.
+///   - It is a unary function if the represented function has a single argument.
+///   - It takes a tuple encapsulating the arguments for a n-ary represented function.
+/// - **On nightly only**, you can enable the `fun-call` feature-gate and calling the function will do the same thing
+///   as [`FunHandle::call`]. However, because of how the various [`FnOnce`], [`FnMut`] and [`Fn`] traits are made,
+///   functions taking several arguments take them as separate arguments as if it was a regular Rust function (they
+///   don’t take a tuple as with the [`FunHandle::call`] method).
+///
+/// # Examples
+///
+/// A unary function squaring its argument, the regular way:
+///
+/// ```
+/// # use shades::ShaderBuilder;
+/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+/// use shades::{Expr, FunHandle, Scope, lit, vec2};
+///
+/// let square = s.fun(|s: &mut Scope<Expr<i32>>, a: Expr<i32>| {
+///   &a * &a
+/// });
+///
+/// s.main_fun(|s: &mut Scope<()>| {
+///   // call square with 3 and bind the result to a variable
+///   let squared = s.var(square.call(lit!(3)));
+/// })
+/// # });
+/// ```
+///
+/// The same function but with the `fun-call` feature-gate enabled:
+///
+/// ```
+/// # use shades::ShaderBuilder;
+/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+/// use shades::{Expr, Scope, lit};
+///
+/// let square = s.fun(|s: &mut Scope<Expr<i32>>, a: Expr<i32>| {
+///   &a * &a
+/// });
+///
+/// s.main_fun(|s: &mut Scope<()>| {
+///   // call square with 3 and bind the result to a variable
+///   # #[cfg(feature = "fun-call")]
+///   let squared = s.var(square(lit!(3)));
+/// })
+/// # });
+/// ```
+///
+/// A function taking two 3D vectors and a floating scalar and returning their linear interpolation, called with
+/// three arguments:
+///
+/// ```
+/// # use shades::ShaderBuilder;
+/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+/// use shades::{Expr, Mix as _, Scope, V3, lit, vec3};
+///
+/// let lerp = s.fun(|s: &mut Scope<Expr<V3<f32>>>, a: Expr<V3<f32>>, b: Expr<V3<f32>>, t: Expr<f32>| {
+///   a.mix(b, t)
+/// });
+///
+/// s.main_fun(|s: &mut Scope<()>| {
+///   # #[cfg(feature = "fun-call")]
+///   let a = vec3!(0., 0., 0.);
+///   let b = vec3!(1., 1., 1.);
+///
+///   // call lerp here and bind it to a local variable
+/// # #[cfg(feature = "fun-call")]
+///   let result = s.var(lerp(a, b, lit!(0.75)));
+/// })
+/// # });
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunHandle<R, A> {
+  erased: ErasedFunHandle,
+  _phantom: PhantomData<(R, A)>,
+}
+
+impl<R> FunHandle<Expr<R>, ()> {
+  /// Create an expression representing a function call to this function.
+  ///
+  /// See the documentation of [`FunHandle`] for examples.
+  pub fn call(&self) -> Expr<R> {
+    Expr::new(ErasedExpr::fun_call(self.erased.clone(), Vec::new()))
+  }
+}
+
+#[cfg(feature = "fun-call")]
+impl<R> FnOnce<()> for FunHandle<Expr<R>, ()> {
+  type Output = Expr<R>;
+
+  extern "rust-call" fn call_once(self, _: ()) -> Self::Output {
+    self.call()
+  }
+}
+
+#[cfg(feature = "fun-call")]
+impl<R> FnMut<()> for FunHandle<Expr<R>, ()> {
+  extern "rust-call" fn call_mut(&mut self, _: ()) -> Self::Output {
+    self.call()
+  }
+}
+
+#[cfg(feature = "fun-call")]
+impl<R> Fn<()> for FunHandle<Expr<R>, ()> {
+  extern "rust-call" fn call(&self, _: ()) -> Self::Output {
+    self.call()
+  }
+}
+
+impl<R, A> FunHandle<Expr<R>, Expr<A>> {
+  /// Create an expression representing a function call to this function.
+  ///
+  /// See the documentation of [`FunHandle`] for examples.
+  pub fn call(&self, a: Expr<A>) -> Expr<R> {
+    Expr::new(ErasedExpr::fun_call(self.erased.clone(), vec![a.erased]))
+  }
+}
+
+#[cfg(feature = "fun-call")]
+impl<R, A> FnOnce<(Expr<A>,)> for FunHandle<Expr<R>, Expr<A>> {
+  type Output = Expr<R>;
+
+  extern "rust-call" fn call_once(self, a: (Expr<A>,)) -> Self::Output {
+    self.call(a.0)
+  }
+}
+
+#[cfg(feature = "fun-call")]
+impl<R, A> FnMut<(Expr<A>,)> for FunHandle<Expr<R>, Expr<A>> {
+  extern "rust-call" fn call_mut(&mut self, a: (Expr<A>,)) -> Self::Output {
+    self.call(a.0)
+  }
+}
+
+#[cfg(feature = "fun-call")]
+impl<R, A> Fn<(Expr<A>,)> for FunHandle<Expr<R>, Expr<A>> {
+  extern "rust-call" fn call(&self, a: (Expr<A>,)) -> Self::Output {
+    self.call(a.0)
+  }
+}
+
+// the first stage must be named S0
+macro_rules! impl_FunCall {
+  ( $( ( $arg_name:ident, $arg_ty:ident ) ),*) => {
+    impl<R, $($arg_ty),*> FunHandle<Expr<R>, ($(Expr<$arg_ty>),*)>
+    {
+      /// Create an expression representing a function call to this function.
+      ///
+      /// See the documentation of [`FunHandle`] for examples.
+      #[allow(clippy::too_many_arguments)]
+      pub fn call(&self, $($arg_name : Expr<$arg_ty>),*) -> Expr<R> {
+        Expr::new(ErasedExpr::fun_call(self.erased.clone(), vec![$($arg_name.erased),*]))
+      }
+    }
+
+    #[cfg(feature = "fun-call")]
+    impl<R, $($arg_ty),*> FnOnce<($(Expr<$arg_ty>),*)> for FunHandle<Expr<R>, ($(Expr<$arg_ty>),*)>
+    {
+      type Output = Expr<R>;
+
+      extern "rust-call" fn call_once(self, ($($arg_name),*): ($(Expr<$arg_ty>),*)) -> Self::Output {
+        self.call($($arg_name),*)
+      }
+    }
+
+    #[cfg(feature = "fun-call")]
+    impl<R, $($arg_ty),*> FnMut<($(Expr<$arg_ty>),*)> for FunHandle<Expr<R>, ($(Expr<$arg_ty>),*)>
+    {
+      extern "rust-call" fn call_mut(&mut self, ($($arg_name),*): ($(Expr<$arg_ty>),*)) -> Self::Output {
+        self.call($($arg_name),*)
+      }
+    }
+
+    #[cfg(feature = "fun-call")]
+    impl<R, $($arg_ty),*> Fn<($(Expr<$arg_ty>),*)> for FunHandle<Expr<R>, ($(Expr<$arg_ty>),*)>
+    {
+      extern "rust-call" fn call(&self, ($($arg_name),*): ($(Expr<$arg_ty>),*)) -> Self::Output {
+        self.call($($arg_name),*)
+      }
+    }
+  };
+}
+
+// implement function calls for Expr up to 16 arguments
+macro_rules! impl_FunCall_rec {
+  ( ( $a:ident, $b:ident ) , ( $x:ident, $y:ident )) => {
+    impl_FunCall!(($a, $b), ($x, $y));
+  };
+
+  ( ( $a:ident, $b:ident ) , ( $x: ident, $y: ident ) , $($r:tt)* ) => {
+    impl_FunCall_rec!(($a, $b), $($r)*);
+    impl_FunCall!(($a, $b), ($x, $y), $($r)*);
+  };
+}
+impl_FunCall_rec!(
+  (a, A),
+  (b, B),
+  (c, C),
+  (d, D),
+  (e, E),
+  (f, F),
+  (g, G),
+  (h, H),
+  (i, I),
+  (j, J),
+  (k, K),
+  (l, L),
+  (m, M),
+  (n, N),
+  (o, O),
+  (p, P)
+);
+
+/// Erased function handle.
+// some handles are not exposed by the typed API yet
+#[allow(dead_code)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ErasedFunHandle {
+  // cast operators
+  Vec2,
+  Vec3,
+  Vec4,
+  // trigonometry
+  Radians,
+  Degrees,
+  Sin,
+  Cos,
+  Tan,
+  ASin,
+  ACos,
+  ATan,
+  SinH,
+  CosH,
+  TanH,
+  ASinH,
+  ACosH,
+  ATanH,
+  // exponential
+  Pow,
+  Exp,
+  Exp2,
+  Log,
+  Log2,
+  Sqrt,
+  InverseSqrt,
+  // common
+  Abs,
+  Sign,
+  Floor,
+  Trunc,
+  Round,
+  RoundEven,
+  Ceil,
+  Fract,
+  Mod,
+  Modf,
+  Min,
+  Max,
+  Clamp,
+  Mix,
+  Step,
+  SmoothStep,
+  IsNan,
+  IsInf,
+  FloatBitsToInt,
+  IntBitsToFloat,
+  UIntBitsToFloat,
+  Fma,
+  Frexp,
+  Ldexp,
+  // floating-point pack and unpack functions
+  PackUnorm2x16,
+  PackSnorm2x16,
+  PackUnorm4x8,
+  PackSnorm4x8,
+  UnpackUnorm2x16,
+  UnpackSnorm2x16,
+  UnpackUnorm4x8,
+  UnpackSnorm4x8,
+  PackHalf2x16,
+  UnpackHalf2x16,
+  // geometry functions
+  Length,
+  Distance,
+  Dot,
+  Cross,
+  Normalize,
+  FaceForward,
+  Reflect,
+  Refract,
+  // matrix functions
+  // TODO
+  // vector relational functions
+  VLt,
+  VLte,
+  VGt,
+  VGte,
+  VEq,
+  VNeq,
+  VAny,
+  VAll,
+  VNot,
+  // integer functions
+  UAddCarry,
+  USubBorrow,
+  UMulExtended,
+  IMulExtended,
+  BitfieldExtract,
+  BitfieldInsert,
+  BitfieldReverse,
+  BitCount,
+  FindLSB,
+  FindMSB,
+  // texture functions
+  // TODO
+  // geometry shader functions
+  EmitStreamVertex,
+  EndStreamPrimitive,
+  EmitVertex,
+  EndPrimitive,
+  // fragment processing functions
+  DfDx,
+  DfDy,
+  DfDxFine,
+  DfDyFine,
+  DfDxCoarse,
+  DfDyCoarse,
+  FWidth,
+  FWidthFine,
+  FWidthCoarse,
+  InterpolateAtCentroid,
+  InterpolateAtSample,
+  InterpolateAtOffset,
+  // shader invocation control functions
+  Barrier,
+  MemoryBarrier,
+  MemoryBarrierAtomic,
+  MemoryBarrierBuffer,
+  MemoryBarrierShared,
+  MemoryBarrierImage,
+  GroupMemoryBarrier,
+  // shader invocation group functions
+  AnyInvocation,
+  AllInvocations,
+  AllInvocationsEqual,
+  // subgroup functions
+  SubgroupBarrier,
+  SubgroupElect,
+  SubgroupAll,
+  SubgroupAny,
+  SubgroupBallot,
+  SubgroupBroadcastFirst,
+  SubgroupShuffle,
+  SubgroupAdd,
+  SubgroupMul,
+  SubgroupMin,
+  SubgroupMax,
+  UserDefined(u16),
+}
+
+/// A function definition.
+///
+/// Function definitions contain the information required to know how to represent a function’s arguments, return type
+/// and its body.
+#[derive(Debug)]
+pub struct FunDef<R, A> {
+  erased: ErasedFun,
+  _phantom: PhantomData<(R, A)>,
+}
+
+impl<R, A> FunDef<R, A> {
+  fn new(erased: ErasedFun) -> Self {
+    Self {
+      erased,
+      _phantom: PhantomData,
+    }
+  }
+}
+
+/// Erased function definition.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug)]
+pub struct ErasedFun {
+  args: Vec<Type>,
+  scope: ErasedScope,
+  ret: ErasedReturn,
+}
+
+impl ErasedFun {
+  fn new(args: Vec<Type>, scope: ErasedScope, ret: ErasedReturn) -> Self {
+    Self { args, scope, ret }
+  }
+
+  /// Types of the arguments of the function.
+  pub fn args(&self) -> &[Type] {
+    &self.args
+  }
+
+  /// Body of the function.
+  pub fn scope(&self) -> &ErasedScope {
+    &self.scope
+  }
+
+  /// Returned expression of the function.
+  pub fn ret(&self) -> &ErasedReturn {
+    &self.ret
+  }
+}
+
+/// Lexical scope that must output a `R`.
+///
+/// Scopes are the only way to add control flow expressions to shaders. [`Scope<R>`] is the most general one, parent
+/// of all scopes. Depending on the kind of control flow, several kind of scopes are possible:
+///
+/// - [`Scope<R>`] is the most general one and every scopes share its features.
+/// - [`EscapeScope<R>`] is a special kind of [`Scope<R>`] that allows escaping from anywhere in the scope.
+/// - [`LoopScope<R>`] is a special kind of [`EscapeScope<R>`] that also allows to escape local looping expressions,
+///   such as `for` and `while` loops.
+///
+/// A [`Scope<R>`] allows to perform a bunch of actions:
+///
+/// - Creating variable via [`Scope::var`]. Expressions of type [`Expr<T>`] where [`T: ToType`](ToType) are bound in a
+///   [`Scope<R>`] via [`Scope::var`] and a [`Var<T>`] is returned, representing the bound variable.
+/// - Variable mutation via [`Scope::set`]. Any [`Var<T>`] declared previously and still reachable in the current [`Scope`]
+///   can be mutated.
+/// - Introducing conditional statements with [`Scope::when`] and [`Scope::unless`].
+/// - Introducing looping statements with [`Scope::loop_for`] and [`Scope::loop_while`].
+#[derive(Debug)]
+pub struct Scope<R> {
+  erased: ErasedScope,
+  // ID of the next scope created in the same function
+  next_id: Rc<Cell<u16>>,
+  _phantom: PhantomData<R>,
+}
+
+impl<R> Scope<R>
+where
+  Return: From<R>,
+{
+  /// Create a new [`Scope<R>`] for which the ID is explicitly passed.
+  ///
+  /// This is the top-level scope of a function; scopes nested in it are created with [`Scope::deeper`].
+  fn new(id: u16) -> Self {
+    Self {
+      erased: ErasedScope::new(id),
+      next_id: Rc::new(Cell::new(id + 1)),
+      _phantom: PhantomData,
+    }
+  }
+
+  /// Create a new fresh scope under the current scope.
+  ///
+  /// Every scope of a function gets its own ID, even sibling scopes. Variables are named after the ID of the scope
+  /// they are declared in, so that a variable escaping its scope — e.g. captured in the closure of a
+  /// [`Scope::when`] and used after it — cannot be mistaken for a variable of another scope: such a use is reported by
+  /// [`Shader::check`].
+  fn deeper(&self) -> Self {
+    let id = self.next_id.get();
+    self.next_id.set(id + 1);
+
+    Self {
+      erased: ErasedScope::new(id),
+      next_id: self.next_id.clone(),
+      _phantom: PhantomData,
+    }
+  }
+
+  /// Bind an expression to a variable in the current scope.
+  ///
+  /// `let v = s.var(e);` binds the `e` expression to `v` in the `s` [`Scope<T>`], and `e` must have type [`Expr<T>`]
+  /// and `v` must be a [`Var<T>`], with [`T: ToType`](ToType).
+  ///
+  /// # Return
+  ///
+  /// The resulting [`Var<T>`] contains the representation of the binding in the EDSL and the actual binding is
+  /// recorded in the current scope.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// let v = s.var(3.1415); // assign the literal 3.1415 to v
+  /// let q = s.var(v * 2.); // assign v * 2. to q
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn var<T>(&mut self, init_value: impl Into<Expr<T>>) -> Var<T>
+  where
+    T: ToType,
+  {
+    let n = self.erased.next_var;
+    let handle = ScopedHandle::fun_var(self.erased.id, n);
+
+    self.erased.next_var += 1;
+
+    self.erased.push(ScopeInstr::VarDecl {
+      ty: T::ty(),
+      handle: handle.clone(),
+      init_value: init_value.into().erased,
+    });
+
+    Var::new(handle)
+  }
+
+  /// Declare a new variable in the current scope, left uninitialized — `T name;`.
+  ///
+  /// This is useful when the variable is assigned in every branch of a following conditional, which avoids inventing
+  /// an initial value. Reading the variable before assigning it yields an undefined value.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{CanEscape as _, EscapeScope, Scope, ShaderBuilder, inputs};
+  ///
+  /// ShaderBuilder::new_fragment_shader(|mut s, _| {
+  ///   inputs!(s, t: f32);
+  ///
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     let side = s.var_uninit::<i32>(); // int var_0_0;
+  ///
+  ///     s.when(t.lt(0.5), |s: &mut EscapeScope<()>| s.set(&side, 0))
+  ///       .or(|s| s.set(&side, 1));
+  ///   })
+  /// });
+  /// ```
+  #[track_caller]
+  pub fn var_uninit<T>(&mut self) -> Var<T>
+  where
+    T: ToType,
+  {
+    let n = self.erased.next_var;
+    let handle = ScopedHandle::fun_var(self.erased.id, n);
+
+    self.erased.next_var += 1;
+
+    self.erased.push(ScopeInstr::VarDeclUninit {
+      ty: T::ty(),
+      handle: handle.clone(),
+    });
+
+    Var::new(handle)
+  }
+
+  /// Conditional expression.
+  ///
+  /// `s.if_else(cond, |s| a, |s| b)` represents the value `a` if `cond` is `true`, or `b` otherwise. Each closure is
+  /// given the scope of its branch, so that it can compute its value with statements, which only run if the branch is
+  /// taken.
+  ///
+  /// # Return
+  ///
+  /// An [`Expr<T>`] representing the value of the branch that was taken. It is backed by a temporary variable that
+  /// is assigned in both branches.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// let x = s.var(3);
+  /// let y = s.if_else(x.lt(10), |s| {
+  ///   let t = s.var(&x * 2);
+  ///   t.to_expr()
+  /// }, |_| x.to_expr());
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn if_else<T>(
+    &mut self,
+    condition: impl Into<Expr<bool>>,
+    then_branch: impl FnOnce(&mut EscapeScope<R>) -> Expr<T>,
+    else_branch: impl FnOnce(&mut EscapeScope<R>) -> Expr<T>,
+  ) -> Expr<T>
+  where
+    T: ToType,
+  {
+    let var = self.var_uninit();
+
+    self
+      .when(condition, |s| {
+        let value = then_branch(s);
+        s.set(&var, value);
+      })
+      .or(|s| {
+        let value = else_branch(s);
+        s.set(&var, value);
+      });
+
+    var.to_expr()
+  }
+
+  /// For looping statement — `for`.
+  ///
+  /// `s.loop_for(i, |i| /* cond */, |i| /* fold */, |i| /* body */ )` inserts a looping statement into the EDSL
+  /// representing a typical “for” loop. `i` is an [`Expr<T>`] satisfying [`T: ToType`](ToType) and is used as
+  /// _initial_ value.
+  ///
+  /// In all the following closures, `i` refers to the initial value.
+  ///
+  /// The first `cond` closure must return an [`Expr<bool>`], representing the condition that is held until the loop
+  /// exits. The second `fold` closure is a pure computation that must return an [`Expr<T>`] and that will be evaluated
+  /// at the end of each iteration before the next check on `cond`. The last and third `body` closure is the body of the
+  /// loop.
+  ///
+  /// The behaviors of the first two closures is important to understand. Those are akin to _filtering_ and _folding_.
+  /// The closure returning the [`Expr<bool>`] is given the [`Expr<T>`] at each iteration and the second closure creates
+  /// the new [`Expr<T>`] for the next iteration. Normally, people are used to write this pattern as `i++`, for
+  /// instance, but in the case of our EDSL, it is more akin go `i + 1`, and this value is affected to a local
+  /// accumulator hidden from the user.
+  ///
+  /// The [`LoopScope<R>`] argument to the `body` closure is a specialization of [`Scope<R>`] that allows breaking out
+  /// of loops.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{CanEscape as _, LoopScope, Scope, ShaderBuilder};
+  ///
+  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     s.loop_for(0, |i| i.lt(10), |i| i + 1, |s: &mut LoopScope<()>, i| {
+  ///       s.when(i.eq(5), |s: &mut LoopScope<()>| {
+  ///         // when i == 5, abort from the main function
+  ///         s.abort();
+  ///       });
+  ///     });
+  ///   })
+  /// });
+  /// ```
+  #[track_caller]
+  pub fn loop_for<T>(
+    &mut self,
+    init_value: impl Into<Expr<T>>,
+    condition: impl FnOnce(&Expr<T>) -> Expr<bool>,
+    iter_fold: impl FnOnce(&Expr<T>) -> Expr<T>,
+    body: impl FnOnce(&mut LoopScope<R>, &Expr<T>),
+  ) where
+    T: ToType,
+  {
+    let mut scope = LoopScope::new(self.deeper());
+
+    let init_expr: Expr<T> = init_value.into();
+
+    let init_var = Var::new(ScopedHandle::fun_var(scope.erased.id, 0));
+    scope.erased.next_var += 1;
+
+    let condition = condition(&init_var);
+
+    // generate the “post expr”, which is basically the free from of the third part of the for loop; people usually
+    // set this to ++i, i++, etc., but in our case, the expression is to treat as a fold’s accumulator
+    let post_expr = iter_fold(&init_var);
+
+    body(&mut scope, &init_var);
+
+    let scope = Scope::from(scope);
+
+    self.erased.push(ScopeInstr::For {
+      init_ty: T::ty(),
+      init_handle: ScopedHandle::fun_var(scope.erased.id, 0),
+      init_expr: init_expr.erased,
+      condition: condition.erased,
+      post_expr: post_expr.erased,
+      scope: scope.erased,
+    });
+  }
+
+  /// For looping statement with two accumulators — `for`.
+  ///
+  /// `s.loop_for2((a, b), |a, b| /* cond */, |a, b| /* fold */, |s, a, b| /* body */)` behaves like
+  /// [`Scope::loop_for`], but carries two accumulators, which can have different types. The `fold` closure returns the
+  /// new values of both accumulators, computed from their values at the end of the iteration — i.e. the second
+  /// accumulator doesn’t see the new value of the first one.
+  ///
+  /// Unlike with [`Scope::loop_for`], the accumulators are declared as variables in the current scope, right before the
+  /// loop.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{Geometry as _, LoopScope, Scope, ShaderBuilder, vec3};
+  ///
+  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     let gravity = vec3!(0., -9.81, 0.);
+  ///
+  ///     s.loop_for2(
+  ///       (vec3!(0., 0., 0.), vec3!(1., 0., 0.)),
+  ///       |pos, _| pos.length().lt(10.),
+  ///       |pos, vel| (pos + vel, vel + &gravity),
+  ///       |s: &mut LoopScope<()>, pos, vel| {
+  ///         // do something with pos and vel
+  ///       },
+  ///     );
+  ///   })
+  /// });
+  /// ```
+  #[track_caller]
+  pub fn loop_for2<A, B>(
+    &mut self,
+    init_values: (impl Into<Expr<A>>, impl Into<Expr<B>>),
+    condition: impl FnOnce(&Expr<A>, &Expr<B>) -> Expr<bool>,
+    iter_fold: impl FnOnce(&Expr<A>, &Expr<B>) -> (Expr<A>, Expr<B>),
+    body: impl FnOnce(&mut LoopScope<R>, &Expr<A>, &Expr<B>),
+  ) where
+    A: ToType,
+    B: ToType,
+  {
+    let a = self.var(init_values.0);
+    let b = self.var(init_values.1);
+
+    // the first accumulator is updated through a temporary variable so that folding the second one sees its old value
+    let tmp = self.var_uninit::<A>();
+
+    let mut scope = LoopScope::new(self.deeper());
+
+    let condition = condition(&a, &b);
+    let (next_a, next_b) = iter_fold(&a, &b);
+
+    body(&mut scope, &a, &b);
+
+    self.erased.push(ScopeInstr::ForMulti {
+      condition: condition.erased,
+      post_exprs: vec![
+        (tmp.0.erased.clone(), next_a.erased),
+        (b.0.erased, next_b.erased),
+        (a.0.erased, tmp.0.erased),
+      ],
+      scope: Scope::from(scope).erased,
+    });
+  }
+
+  /// Folding looping statement — `for`.
+  ///
+  /// `s.loop_fold(init, |acc| /* cond */, |acc| /* fold */, |s, acc| /* body */)` behaves like [`Scope::loop_for`],
+  /// but declares the accumulator as a variable in the current scope, right before the loop, and returns its value once
+  /// the loop is done — either because `cond` doesn’t hold anymore or because the loop was broken out of. This allows
+  /// reductions to be used like any other expression.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{CanEscape as _, LoopScope, Scope, ShaderBuilder, lit};
+  ///
+  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     // march along a ray by steps of 0.5, until it gets too far or past a distance of 1
+  ///     let depth = s.loop_fold(
+  ///       0.,
+  ///       |t| t.lt(100.),
+  ///       |t| t + 0.5,
+  ///       |s: &mut LoopScope<()>, t| {
+  ///         s.when(t.gt(lit!(1.)), |s: &mut LoopScope<()>| s.loop_break());
+  ///       },
+  ///     );
+  ///     let _depth = s.var(depth);
+  ///   })
+  /// });
+  /// ```
+  #[track_caller]
+  pub fn loop_fold<T>(
+    &mut self,
+    init_value: impl Into<Expr<T>>,
+    condition: impl FnOnce(&Expr<T>) -> Expr<bool>,
+    iter_fold: impl FnOnce(&Expr<T>) -> Expr<T>,
+    body: impl FnOnce(&mut LoopScope<R>, &Expr<T>),
+  ) -> Expr<T>
+  where
+    T: ToType,
+  {
+    let acc = self.var(init_value);
+
+    let mut scope = LoopScope::new(self.deeper());
+
+    let condition = condition(&acc);
+    let next = iter_fold(&acc);
+
+    body(&mut scope, &acc);
+
+    self.erased.push(ScopeInstr::ForMulti {
+      condition: condition.erased,
+      post_exprs: vec![(acc.0.erased.clone(), next.erased)],
+      scope: Scope::from(scope).erased,
+    });
+
+    acc.to_expr()
+  }
+
+  /// Integer range looping statement — `for`.
+  ///
+  /// `s.loop_range(start, end, |s, i| /* body */)` is a shortcut for the common case of [`Scope::loop_for`] iterating
+  /// over the half-open range `[start; end)`, akin to `for i in start..end` in Rust. `i` is the current index.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{LoopScope, Scope, ShaderBuilder};
+  ///
+  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     let sum = s.var(0);
+  ///
+  ///     s.loop_range(0, 10, |s: &mut LoopScope<()>, i| {
+  ///       s.set(&sum, &sum + i);
+  ///     });
+  ///   })
+  /// });
+  /// ```
+  #[track_caller]
+  pub fn loop_range(
+    &mut self,
+    start: impl Into<Expr<i32>>,
+    end: impl Into<Expr<i32>>,
+    body: impl FnOnce(&mut LoopScope<R>, &Expr<i32>),
+  ) {
+    let end = end.into();
+    self.loop_for(start, |i| i.lt(end), |i| i + 1, body);
+  }
+
+  /// Labeled looping statements.
+  ///
+  /// `s.loop_labeled(|s, label| /* body */)` gives a [`LoopLabel`] to the loops created in `body`. From any loop
+  /// nested in those, [`LoopScope::loop_break_to`] can then be used with `label` to break out of all the loops up to and
+  /// including the labeled ones.
+  ///
+  /// Because not every target language supports labeled breaks, they are lowered into a flag variable, checked after
+  /// every nested loop that might break to the label.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{CanEscape as _, LoopScope, Scope, ShaderBuilder};
+  ///
+  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     s.loop_labeled(|s, outer| {
+  ///       s.loop_range(0, 10, |s: &mut LoopScope<()>, i| {
+  ///         s.loop_range(0, 10, |s: &mut LoopScope<()>, j| {
+  ///           s.when((i * j).eq(42), |s: &mut LoopScope<()>| {
+  ///             // leave both loops
+  ///             s.loop_break_to(outer);
+  ///           });
+  ///         });
+  ///       });
+  ///     });
+  ///   })
+  /// });
+  /// ```
+  #[track_caller]
+  pub fn loop_labeled(&mut self, body: impl FnOnce(&mut Self, &LoopLabel)) {
+    let label = LoopLabel {
+      flag: self.var(false),
+    };
+    let start = self.erased.instructions.len();
+
+    body(self, &label);
+
+    // the labeled loops directly break out; their nested loops need to check the flag once done
+    for instr in &mut self.erased.instructions[start..] {
+      for scope in instr.break_target_scopes_mut() {
+        scope.lower_break_to(&label.flag.erased);
+      }
+    }
+  }
+
+  /// Iterate over the elements of an array — `for`.
+  ///
+  /// `s.for_each(array, |s, elem| /* body */)` inserts a looping statement into the EDSL iterating over all the
+  /// elements of `array`, an [`Expr<[T; N]>`](Expr). The index arithmetic is generated for you, bounded by `N`, and
+  /// `elem` is the current element.
+  ///
+  /// The [`LoopScope<R>`] argument to the `body` closure is a specialization of [`Scope<R>`] that allows breaking out
+  /// of loops.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{LoopScope, Scope, ShaderBuilder, lit};
+  ///
+  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     let sum = s.var(0.);
+  ///
+  ///     s.for_each(lit!([1., 2., 4.]), |s: &mut LoopScope<()>, x| {
+  ///       s.set(&sum, &sum + x);
+  ///     });
+  ///   })
+  /// });
+  /// ```
+  #[track_caller]
+  pub fn for_each<T, const N: usize>(
+    &mut self,
+    array: impl Into<Expr<[T; N]>>,
+    body: impl FnOnce(&mut LoopScope<R>, &Expr<T>),
+  ) {
+    let array = array.into();
+    self.loop_range(0, N as i32, |s, i| body(s, &array.at(i)));
+  }
+
+  /// While looping statement — `while`.
+  ///
+  /// `s.loop_while(cond, body)` inserts a looping statement into the EDSL representing a typical “while” loop.
+  ///
+  /// `cond` is an [`Expr<bool>`], representing the condition that is held until the loop exits. `body` is the content
+  /// the loop will execute at each iteration.
+  ///
+  /// The [`LoopScope<R>`] argument to the `body` closure is a specialization of [`Scope<R>`] that allows breaking out
+  /// of loops.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{CanEscape as _, LoopScope, Scope, ShaderBuilder};
+  ///
+  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     let i = s.var(10);
+  ///
+  ///     s.loop_while(i.lt(10), |s| {
+  ///       s.set(&i, &i + 1);
+  ///     });
+  ///   })
+  /// });
+  /// ```
+  #[track_caller]
+  pub fn loop_while(
+    &mut self,
+    condition: impl Into<Expr<bool>>,
+    body: impl FnOnce(&mut LoopScope<R>),
+  ) {
+    let mut scope = LoopScope::new(self.deeper());
+    body(&mut scope);
+
+    self.erased.push(ScopeInstr::While {
+      condition: condition.into().erased,
+      scope: Scope::from(scope).erased,
+    });
+  }
+
+  /// Infinite looping statement — `while (true)`.
+  ///
+  /// `s.loop_forever(body)` inserts a looping statement into the EDSL that never exits by itself: exiting the loop must
+  /// be done from the body, typically with [`LoopScope::loop_break`].
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{CanEscape as _, LoopScope, Scope, ShaderBuilder};
+  ///
+  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     let i = s.var(0);
+  ///
+  ///     s.loop_forever(|s: &mut LoopScope<()>| {
+  ///       s.set(&i, &i + 1);
+  ///
+  ///       s.when(i.eq(10), |s: &mut LoopScope<()>| {
+  ///         s.loop_break();
+  ///       });
+  ///     });
+  ///   })
+  /// });
+  /// ```
+  #[track_caller]
+  pub fn loop_forever(&mut self, body: impl FnOnce(&mut LoopScope<R>)) {
+    let mut scope = LoopScope::new(self.deeper());
+    body(&mut scope);
+
+    self.erased.push(ScopeInstr::Loop {
+      scope: Scope::from(scope).erased,
+    });
+  }
+
+  /// Do-while looping statement — `do { … } while`.
+  ///
+  /// `s.loop_do_while(body, cond)` inserts a looping statement into the EDSL representing a typical “do-while” loop.
+  /// It behaves like [`Scope::loop_while`], but `body` always runs at least once, as `cond` is only checked at the end
+  /// of each iteration.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{LoopScope, Scope, ShaderBuilder};
+  ///
+  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     let i = s.var(10);
+  ///
+  ///     s.loop_do_while(|s: &mut LoopScope<()>| {
+  ///       s.set(&i, &i + 1);
+  ///     }, i.lt(10));
+  ///   })
+  /// });
+  /// ```
+  #[track_caller]
+  pub fn loop_do_while(
+    &mut self,
+    body: impl FnOnce(&mut LoopScope<R>),
+    condition: impl Into<Expr<bool>>,
+  ) {
+    let mut scope = LoopScope::new(self.deeper());
+    body(&mut scope);
+
+    self.erased.push(ScopeInstr::DoWhile {
+      condition: condition.into().erased,
+      scope: Scope::from(scope).erased,
+    });
+  }
+
+  /// Switch statement — `switch`.
+  ///
+  /// `s.switch_on(e)` inserts a `switch` statement dispatching on the value of `e`, which must be an integer
+  /// expression ([`Expr<i32>`] or [`Expr<u32>`]), as enforced by [`SwitchLabel`].
+  ///
+  /// # Return
+  ///
+  /// A [`Switch<R, T>`], used to add the `case` and `default` branches of the switch.
+  ///
+  /// Have a look at the documentation of [`Switch`] for further information.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// let x = s.var(1);
+  /// let y = s.var(0.);
+  ///
+  /// s.switch_on(&x)
+  ///   .case(0, |s| s.set(&y, 1.))
+  ///   .case(1, |s| s.set(&y, 2.))
+  ///   .default(|s| s.abort());
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn switch_on<'a, T>(&'a mut self, expr: impl Into<Expr<T>>) -> Switch<'a, R, T>
+  where
+    T: SwitchLabel,
+  {
+    self.erased.push(ScopeInstr::Switch {
+      expr: expr.into().erased,
+      cases: Vec::new(),
+    });
+
+    Switch {
+      parent_scope: self,
+      _phantom: PhantomData,
+    }
+  }
+
+  /// Mutate a variable in the current scope.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// let v = s.var(1); // v = 1
+  /// s.set(&v, 10); // v = 10
+  /// #   })
+  /// # });
+  /// ```
+  ///
+  /// Swizzling a [`Var`] without selecting a component more than once yields a [`Var`], so some components of a vector
+  /// variable can be mutated that way as well.
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// use shades::{Swizzlable, lit, sw};
+  ///
+  /// let v = s.var(lit!(1., 2., 3.)); // v = vec3(1., 2., 3.)
+  /// s.set(sw!(v, .x.z), lit!(0., 10.)); // v.xz = vec2(0., 10.)
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn set<T>(&mut self, var: impl Into<Var<T>>, value: impl Into<Expr<T>>) {
+    self.erased.push(ScopeInstr::MutateVar {
+      var: var.into().to_expr().erased,
+      expr: value.into().erased,
+    });
+  }
+
+  /// Mutate some of the components of a vector variable in the current scope.
+  ///
+  /// `s.set_sw(&v, mask, value)` only writes the components of `v` selected by `mask`, which is typically built with
+  /// the [`sw_mask!`](sw_mask) macro. The type of `value` depends on the number of selected components, as with
+  /// [`Swizzlable`]. `mask` must select each component at most once, as the assignment would be ambiguous otherwise —
+  /// see the [`swizzle`] module.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// use shades::{lit, sw_mask};
+  ///
+  /// let v = s.var(lit!(1., 2., 3.)); // v = vec3(1., 2., 3.)
+  /// s.set_sw(&v, sw_mask!(.x.z), lit!(0., 10.)); // v.xz = vec2(0., 10.)
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn set_sw<T, U, S>(&mut self, var: impl Into<Var<T>>, mask: S, value: impl Into<Expr<U>>)
+  where
+    Var<T>: Swizzlable<S, Output = Var<U>>,
+  {
+    self.set(<Var<T> as Swizzlable<S>>::swizzle(&var.into(), mask), value);
+  }
+
+  /// Add a comment at this point of the scope — `// text`.
+  ///
+  /// Comments have no effect on the shader, but are written in the generated code — one `//` line per line of `text`
+  /// —, which helps finding one’s way in the code dumped by a driver or shown by a debugger such as RenderDoc.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// s.comment("gamma of the display");
+  /// let gamma = s.var(2.2);
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn comment(&mut self, text: &str) {
+    self.erased.push(ScopeInstr::Comment(text.to_owned()));
+  }
+}
+
+/// A special kind of [`Scope`] that can also escape expressions out of its parent scope.
+#[derive(Debug)]
+pub struct EscapeScope<R>(Scope<R>);
+
+impl<R> From<EscapeScope<R>> for Scope<R> {
+  fn from(s: EscapeScope<R>) -> Self {
+    s.0
+  }
+}
+
+impl<R> Deref for EscapeScope<R> {
+  type Target = Scope<R>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+impl<R> DerefMut for EscapeScope<R> {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.0
+  }
+}
+
+impl<R> EscapeScope<R>
+where
+  Return: From<R>,
+{
+  fn new(s: Scope<R>) -> Self {
+    Self(s)
+  }
+
+  /// Early-return the current function with an expression.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::ShaderBuilder;
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::{CanEscape as _, Expr, Scope};
+  ///
+  /// let _fun = s.fun(|s: &mut Scope<Expr<i32>>, arg: Expr<i32>| {
+  ///   // if arg is less than 10, early-return with 0
+  ///   s.when(arg.lt(10), |s| {
+  ///     s.leave(0);
+  ///   });
+  ///
+  ///   arg
+  /// });
+  ///
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn leave(&mut self, ret: impl Into<R>) {
+    self
+      .erased
+      .push(ScopeInstr::Return(Return::from(ret.into()).erased));
+  }
+}
+
+impl EscapeScope<()> {
+  /// Early-abort the current function.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::ShaderBuilder;
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// use shades::{CanEscape as _, Expr, Scope};
+  ///
+  /// let _fun = s.fun(|s: &mut Scope<()>, arg: Expr<i32>| {
+  ///   // if arg is less than 10, early-return with 0
+  ///   s.when(arg.lt(10), |s| {
+  ///     s.abort();
+  ///   });
+  ///
+  ///   // do something else…
+  /// });
+  ///
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn abort(&mut self) {
+    self.erased.push(ScopeInstr::Return(ErasedReturn::Void));
+  }
+}
+
+/// A special kind of [`EscapeScope`] that can also break loops.
+#[derive(Debug)]
+pub struct LoopScope<R>(EscapeScope<R>);
+
+impl<R> From<LoopScope<R>> for Scope<R> {
+  fn from(s: LoopScope<R>) -> Self {
+    s.0.into()
+  }
+}
+
+impl<R> Deref for LoopScope<R> {
+  type Target = EscapeScope<R>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+impl<R> DerefMut for LoopScope<R> {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.0
+  }
+}
+
+impl<R> LoopScope<R>
+where
+  Return: From<R>,
+{
+  fn new(s: Scope<R>) -> Self {
+    Self(EscapeScope::new(s))
+  }
+
+  /// Break the current iteration of the nearest loop and continue to the next iteration.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// use shades::CanEscape as _;
+  ///
+  /// s.loop_while(true, |s| {
+  ///   s.loop_continue();
+  /// });
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn loop_continue(&mut self) {
+    self.erased.push(ScopeInstr::Continue);
+  }
+
+  /// Break the nearest loop.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// use shades::CanEscape as _;
+  ///
+  /// s.loop_while(true, |s| {
+  ///   s.loop_break();
+  /// });
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn loop_break(&mut self) {
+    self.erased.push(ScopeInstr::Break);
+  }
+
+  /// Break all the loops up to and including the loops labeled with `label`.
+  ///
+  /// See [`Scope::loop_labeled`] for further details.
+  #[track_caller]
+  pub fn loop_break_to(&mut self, label: &LoopLabel) {
+    self.erased.push(ScopeInstr::MutateVar {
+      var: label.flag.erased.clone(),
+      expr: ErasedExpr::LitBool(true),
+    });
+    self.erased.push(ScopeInstr::Break);
+  }
+}
+
+/// Label of loops, created with [`Scope::loop_labeled`].
+#[derive(Debug)]
+pub struct LoopLabel {
+  /// Flag set when breaking to this label.
+  flag: Var<bool>,
+}
+
+/// Erased scope, made of a list of instructions.
+///
+/// Scopes also remember where each of their instructions was added in the Rust code, which is reported by
+/// [`Shader::check`] and can be written as comments by [`glsl::write_shader_with_locations`]. Those locations are not
+/// part of the code: two scopes with the same instructions are equal and have the same [digest](Shader::digest),
+/// wherever they were built.
+///
+/// [`glsl::write_shader_with_locations`]: writer::glsl::write_shader_with_locations
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone)]
+pub struct ErasedScope {
+  id: u16,
+  instructions: Vec<ScopeInstr>,
+  // source location of each instruction, None for the ones generated by shades; might be shorter than instructions
+  #[cfg_attr(feature = "arbitrary", arbitrary(default))]
+  locations: Vec<Option<&'static panic::Location<'static>>>,
+  next_var: u16,
+}
+
+impl fmt::Debug for ErasedScope {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("ErasedScope")
+      .field("id", &self.id)
+      .field("instructions", &self.instructions)
+      .field("next_var", &self.next_var)
+      .finish()
+  }
+}
+
+impl PartialEq for ErasedScope {
+  fn eq(&self, other: &Self) -> bool {
+    self.id == other.id
+      && self.instructions == other.instructions
+      && self.next_var == other.next_var
+  }
+}
+
+impl ErasedScope {
+  fn new(id: u16) -> Self {
+    Self {
+      id,
+      instructions: Vec::new(),
+      locations: Vec::new(),
+      next_var: 0,
+    }
+  }
+
+  /// Instructions of the scope, in order.
+  pub fn instructions(&self) -> &[ScopeInstr] {
+    &self.instructions
+  }
+
+  /// Location in the Rust code where the instruction at index `index` was added, if known.
+  ///
+  /// Instructions generated by shades itself — e.g. the `break` ending a `case` or the ones added by passes — have the
+  /// location of the instruction they were generated from, or none.
+  pub fn location(&self, index: usize) -> Option<&'static panic::Location<'static>> {
+    self.locations.get(index).copied().flatten()
+  }
+
+  /// Add an instruction at the end of the scope, located at the caller.
+  #[track_caller]
+  fn push(&mut self, instr: ScopeInstr) {
+    self.push_at(instr, Some(panic::Location::caller()));
+  }
+
+  /// Add an instruction at the end of the scope, with the given location.
+  pub(crate) fn push_at(
+    &mut self,
+    instr: ScopeInstr,
+    location: Option<&'static panic::Location<'static>>,
+  ) {
+    self.locations.resize(self.instructions.len(), None);
+    self.instructions.push(instr);
+    self.locations.push(location);
+  }
+
+  /// Remove all the instructions of the scope, along with their locations.
+  pub(crate) fn take_instructions(
+    &mut self,
+  ) -> Vec<(ScopeInstr, Option<&'static panic::Location<'static>>)> {
+    let locations = mem::take(&mut self.locations);
+    let locations = locations.into_iter().chain(repeat(None));
+    mem::take(&mut self.instructions)
+      .into_iter()
+      .zip(locations)
+      .collect()
+  }
+
+  /// Insert a check on `flag` after every loop or switch that breaks to the label `flag` belongs to.
+  ///
+  /// Return whether this scope breaks to that label.
+  fn lower_break_to(&mut self, flag: &ErasedExpr) -> bool {
+    let mut breaks_to = false;
+    let mut i = 0;
+
+    while i < self.instructions.len() {
+      let instr = &mut self.instructions[i];
+      i += 1;
+
+      match instr {
+        ScopeInstr::MutateVar { var, .. } if var == flag => breaks_to = true,
+
+        ScopeInstr::If { scope, .. }
+        | ScopeInstr::ElseIf { scope, .. }
+        | ScopeInstr::Else { scope }
+        | ScopeInstr::Block { scope } => {
+          breaks_to |= scope.lower_break_to(flag);
+        }
+
+        _ => {
+          // every nested scope must be lowered, so don’t short-circuit
+          let mut nested = false;
+          for scope in instr.break_target_scopes_mut() {
+            nested |= scope.lower_break_to(flag);
+          }
+
+          if nested {
+            breaks_to = true;
+
+            // the check is located at the loop it follows
+            let location = self.location(i - 1);
+            let mut check = ErasedScope::new(self.id + 1);
+            check.push_at(ScopeInstr::Break, location);
+            self.locations.resize(self.instructions.len(), None);
+            self.instructions.insert(
+              i,
+              ScopeInstr::If {
+                condition: flag.clone(),
+                scope: check,
+              },
+            );
+            self.locations.insert(i, location);
+            i += 1;
+          }
+        }
+      }
+    }
+
+    breaks_to
+  }
+}
+
+/// Scopes allowing to enter conditional scopes.
+///
+/// Conditional scopes allow to break out of a function by early-return / aborting the function.
+pub trait CanEscape<R>
+where
+  Return: From<R>,
+{
+  /// Scope type inside the scope of the conditional.
+  type InnerScope;
+
+  /// Conditional statement — `if`.
+  ///
+  /// `s.when(cond, |s: &mut EscapeScope<R>| { /* body */ })` inserts a conditional branch in the EDSL using the `cond`
+  /// expression as truth and the passed closure as body to run when the represented condition is `true`. The
+  /// [`EscapeScope<R>`] provides you with the possibility to escape and leave the function earlier, either by returning
+  /// an expression or by aborting the function, depending on the value of `R`: `Expr<_>` allows for early-returns and
+  /// `()` allows for aborting.
+  ///
+  /// # Return
+  ///
+  /// A [`When<R>`], authorizing the same escape rules with `R`. This object allows you to chain other conditional
+  /// statements, commonly referred to as `else if` and `else` in common languages.
+  ///
+  /// Have a look at the documentation of [`When`] for further information.
+  ///
+  /// # Examples
+  ///
+  /// Early-return:
+  ///
+  /// ```
+  /// use shades::{CanEscape as _, EscapeScope, Expr, Scope, ShaderBuilder, lit};
+  ///
+  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  ///   let f = s.fun(|s: &mut Scope<Expr<i32>>| {
+  ///     s.when(lit!(1).lt(3), |s: &mut EscapeScope<Expr<i32>>| {
+  ///       // do something in here
+  ///
+  ///       // early-return with 0; only possible if the function returns Expr<i32>
+  ///       s.leave(0);
+  ///     });
+  ///
+  ///     lit!(1)
+  ///   });
+  ///
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  /// # #[cfg(feature = "fun-call")]
+  ///     let x = s.var(f());
+  ///   })
+  /// });
+  /// ```
+  ///
+  /// Aborting a function:
+  ///
+  /// ```
+  /// use shades::{CanEscape as _, EscapeScope, Scope, ShaderBuilder, lit};
+  ///
+  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  ///   s.main_fun(|s: &mut Scope<()>| {
+  ///     s.when(lit!(1).lt(3), |s: &mut EscapeScope<()>| {
+  ///       // do something in here
+  ///
+  ///       // break the parent function by aborting; this is possible because the return type is ()
+  ///       s.abort();
+  ///     });
+  ///   })
+  /// });
+  /// ```
+  #[track_caller]
+  fn when<'a>(
+    &'a mut self,
+    condition: impl Into<Expr<bool>>,
+    body: impl FnOnce(&mut Self::InnerScope),
+  ) -> When<'a, R>;
+
+  /// Complement form of [`Scope::when`].
+  ///
+  /// This method does the same thing as [`Scope::when`] but applies the [`Not::not`](std::ops::Not::not) operator on
+  /// the condition first.
+  #[track_caller]
+  fn unless<'a>(
+    &'a mut self,
+    condition: impl Into<Expr<bool>>,
+    body: impl FnOnce(&mut Self::InnerScope),
+  ) -> When<'a, R> {
+    self.when(!condition.into(), body)
+  }
+}
+
+impl<R> CanEscape<R> for Scope<R>
+where
+  Return: From<R>,
+{
+  type InnerScope = EscapeScope<R>;
+
+  #[track_caller]
+  fn when<'a>(
+    &'a mut self,
+    condition: impl Into<Expr<bool>>,
+    body: impl FnOnce(&mut Self::InnerScope),
+  ) -> When<'a, R> {
+    let mut scope = EscapeScope::new(self.deeper());
+    body(&mut scope);
+
+    self.erased.push(ScopeInstr::If {
+      condition: condition.into().erased,
+      scope: Scope::from(scope).erased,
+    });
+
+    When { parent_scope: self }
+  }
+}
+
+impl<R> CanEscape<R> for LoopScope<R>
+where
+  Return: From<R>,
+{
+  type InnerScope = LoopScope<R>;
+
+  #[track_caller]
+  fn when<'a>(
+    &'a mut self,
+    condition: impl Into<Expr<bool>>,
+    body: impl FnOnce(&mut Self::InnerScope),
+  ) -> When<'a, R> {
+    let mut scope = LoopScope::new(self.deeper());
+    body(&mut scope);
+
+    self.erased.push(ScopeInstr::If {
+      condition: condition.into().erased,
+      scope: Scope::from(scope).erased,
+    });
+
+    When { parent_scope: self }
+  }
+}
+
+/// Conditional combinator.
+///
+/// A [`When<R>`] is returned from functions such as [`CanEscape::when`] or [`CanEscape::unless`] and allows to continue
+/// chaining conditional statements, encoding the concept of `else if` and `else` in more traditional languages.
+#[derive(Debug)]
+pub struct When<'a, R> {
+  /// The scope from which this [`When`] expression comes from.
+  ///
+  /// This will be handy if we want to chain this when with others (corresponding to `else if` and `else`, for
+  /// instance).
+  parent_scope: &'a mut Scope<R>,
+}
+
+impl<R> When<'_, R>
+where
+  Return: From<R>,
+{
+  /// Add a conditional branch — `else if`.
+  ///
+  /// This method is often found chained after [`CanEscape::when`] and allows to add a new conditional if the previous
+  /// conditional fails (i.e. `else if`). The behavior is the same as with [`CanEscape::when`].
+  ///
+  /// # Return
+  ///
+  /// Another [`When<R>`], allowing to add more conditional branches.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// use shades::{CanEscape as _, lit};
+  ///
+  /// let x = lit!(1);
+  ///
+  /// // you will need CanEscape in order to use when
+  /// s.when(x.lt(2), |s| {
+  ///   // do something if x < 2
+  /// }).or_else(x.lt(10), |s| {
+  ///   // do something if x < 10
+  /// });
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn or_else(
+    self,
+    condition: impl Into<Expr<bool>>,
+    body: impl FnOnce(&mut EscapeScope<R>),
+  ) -> Self {
+    let mut scope = EscapeScope::new(self.parent_scope.deeper());
+    body(&mut scope);
+
+    self.parent_scope.erased.push(ScopeInstr::ElseIf {
+      condition: condition.into().erased,
+      scope: Scope::from(scope).erased,
+    });
+
+    self
+  }
+
+  /// Add a final catch-all conditional branch — `else`.
+  ///
+  /// This method is often found chained after [`CanEscape::when`] and allows to finish the chain of conditional
+  /// branches if the previous conditional fails (i.e. `else`). The behavior is the same as with [`CanEscape::when`].
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// use shades::{CanEscape as _, lit};
+  ///
+  /// let x = lit!(1);
+  ///
+  /// // you will need CanEscape in order to use when
+  /// s.when(x.lt(2), |s| {
+  ///   // do something if x < 2
+  /// }).or(|s| {
+  ///   // do something if x >= 2
+  /// });
+  /// #   })
+  /// # });
+  /// ```
+  ///
+  /// Can chain and mix conditional but [`When::or`] cannot be anywhere else but the end of the chain:
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// use shades::{CanEscape as _, lit};
+  ///
+  /// let x = lit!(1);
+  ///
+  /// // you will need CanEscape in order to use when
+  /// s.when(x.lt(2), |s| {
+  ///   // do something if x < 2
+  /// }).or_else(x.lt(5), |s| {
+  ///   // do something if x < 5
+  /// }).or_else(x.lt(10), |s| {
+  ///   // do something if x < 10
+  /// }).or(|s| {
+  ///   // else, do this
+  /// });
+  /// #   })
+  /// # });
+  /// ```
+  #[track_caller]
+  pub fn or(self, body: impl FnOnce(&mut EscapeScope<R>)) {
+    let mut scope = EscapeScope::new(self.parent_scope.deeper());
+    body(&mut scope);
+
+    self.parent_scope.erased.push(ScopeInstr::Else {
+      scope: Scope::from(scope).erased,
+    });
+  }
+}
 
-    // var OP t, where t is automatically lifted
-    impl<'a> ops::$op<$b> for Var<$a> {
-      type Output = Expr<$r>;
+/// Switch combinator.
+///
+/// A [`Switch<R, T>`] is returned from [`Scope::switch_on`] and allows to add branches to the switch, encoding the
+/// concept of `case` and `default` in more traditional languages.
+///
+/// Branches are added with [`Switch::case`] and [`Switch::default`], and don’t fall through by default: the next
+/// branch is not executed once a branch is done. Use [`Switch::case_fallthrough`] when you need to fall through to the
+/// next branch.
+///
+/// # Panics
+///
+/// Adding two `case` branches with the same label panics.
+#[derive(Debug)]
+pub struct Switch<'a, R, T> {
+  /// The scope the switch statement was inserted in.
+  parent_scope: &'a mut Scope<R>,
+  _phantom: PhantomData<T>,
+}
 
-      fn $meth_name(self, rhs: $b) -> Self::Output {
-        let rhs = Expr::from(rhs);
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.0.erased),
-          Box::new(rhs.erased),
-        ))
-      }
+impl<R, T> Switch<'_, R, T>
+where
+  Return: From<R>,
+  T: SwitchLabel,
+{
+  #[track_caller]
+  fn add_case(
+    self,
+    label: Option<ErasedExpr>,
+    fallthrough: bool,
+    body: impl FnOnce(&mut EscapeScope<R>),
+  ) -> Self {
+    if let (Some(label), Some(ScopeInstr::Switch { cases, .. })) =
+      (&label, self.parent_scope.erased.instructions.last())
+    {
+      assert!(
+        cases.iter().all(|(other, _)| other.as_ref() != Some(label)),
+        "duplicate case label {:?} in switch",
+        label
+      );
     }
 
-    // &expr OP t, where t is automatically lifted
-    impl<'a> ops::$op<$b> for &'a Expr<$a> {
-      type Output = Expr<$r>;
+    let mut scope = EscapeScope::new(self.parent_scope.deeper());
+    body(&mut scope);
 
-      fn $meth_name(self, rhs: $b) -> Self::Output {
-        let rhs: Expr<$b> = rhs.into();
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased.clone()),
-          Box::new(rhs.erased),
-        ))
-      }
-    }
+    // a break following a return, break or continue would be unreachable
+    let terminated = matches!(
+      scope.erased.instructions.last(),
+      Some(ScopeInstr::Return(_) | ScopeInstr::Break | ScopeInstr::Continue)
+    );
 
-    // &var OP t, where t is automatically lifted
-    impl<'a> ops::$op<$b> for &'a Var<$a> {
-      type Output = Expr<$r>;
+    if !fallthrough && !terminated {
+      scope.erased.push(ScopeInstr::Break);
+    }
 
-      fn $meth_name(self, rhs: $b) -> Self::Output {
-        let rhs: Expr<$b> = rhs.into();
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.0.erased.clone()),
-          Box::new(rhs.erased),
-        ))
-      }
+    if let Some(ScopeInstr::Switch { cases, .. }) = self.parent_scope.erased.instructions.last_mut()
+    {
+      cases.push((label, Scope::from(scope).erased));
     }
-  };
+
+    self
+  }
+
+  /// Add a branch taken when the switched expression is equal to `label` — `case`.
+  ///
+  /// `label` must be a constant expression. The branch doesn’t fall through.
+  #[track_caller]
+  pub fn case(self, label: impl Into<Expr<T>>, body: impl FnOnce(&mut EscapeScope<R>)) -> Self {
+    self.add_case(Some(label.into().erased), false, body)
+  }
+
+  /// Same as [`Switch::case`], but the branch falls through to the next branch.
+  #[track_caller]
+  pub fn case_fallthrough(
+    self,
+    label: impl Into<Expr<T>>,
+    body: impl FnOnce(&mut EscapeScope<R>),
+  ) -> Self {
+    self.add_case(Some(label.into().erased), true, body)
+  }
+
+  /// Add a catch-all branch taken when no other branch matches — `default`.
+  #[track_caller]
+  pub fn default(self, body: impl FnOnce(&mut EscapeScope<R>)) {
+    self.add_case(None, false, body);
+  }
 }
 
-// or
-impl_binop_Expr!(BitOr, bitor, bool, bool);
-impl_binop_Expr!(BitOr, bitor, V2<bool>, V2<bool>);
-impl_binop_Expr!(BitOr, bitor, V2<bool>, bool);
-impl_binop_Expr!(BitOr, bitor, V3<bool>, V3<bool>);
-impl_binop_Expr!(BitOr, bitor, V3<bool>, bool);
-impl_binop_Expr!(BitOr, bitor, V4<bool>, V4<bool>);
-impl_binop_Expr!(BitOr, bitor, V4<bool>, bool);
+/// Types of the expressions a [`Switch`] can dispatch on: [`i32`] and [`u32`].
+///
+/// This trait is sealed and cannot be implemented outside of this crate.
+pub trait SwitchLabel: switch_label::Sealed {}
 
-// and
-impl_binop_Expr!(BitAnd, bitand, bool, bool);
-impl_binop_Expr!(BitAnd, bitand, V2<bool>, V2<bool>);
-impl_binop_Expr!(BitAnd, bitand, V2<bool>, bool);
-impl_binop_Expr!(BitAnd, bitand, V3<bool>, V3<bool>);
-impl_binop_Expr!(BitAnd, bitand, V3<bool>, bool);
-impl_binop_Expr!(BitAnd, bitand, V4<bool>, V4<bool>);
-impl_binop_Expr!(BitAnd, bitand, V4<bool>, bool);
+impl SwitchLabel for i32 {}
+impl SwitchLabel for u32 {}
 
-// xor
-impl_binop_Expr!(BitXor, bitxor, bool, bool);
-impl_binop_Expr!(BitXor, bitxor, V2<bool>, V2<bool>);
-impl_binop_Expr!(BitXor, bitxor, V2<bool>, bool);
-impl_binop_Expr!(BitXor, bitxor, V3<bool>, V3<bool>);
-impl_binop_Expr!(BitXor, bitxor, V3<bool>, bool);
-impl_binop_Expr!(BitXor, bitxor, V4<bool>, V4<bool>);
-impl_binop_Expr!(BitXor, bitxor, V4<bool>, bool);
+mod switch_label {
+  pub trait Sealed {}
 
-/// Run a macro on all supported types to generate the impl for them
+  impl Sealed for i32 {}
+  impl Sealed for u32 {}
+}
+
+/// Mutable variable.
 ///
-/// The macro has to have to take two `ty` as argument and yield a `std::ops` trait implementor.
-macro_rules! impl_binarith_Expr {
-  ($op:ident, $meth_name:ident) => {
-    impl_binop_Expr!($op, $meth_name, i32, i32);
-    impl_binop_Expr!($op, $meth_name, V2<i32>, V2<i32>);
-    impl_binop_Expr!($op, $meth_name, V2<i32>, i32);
-    impl_binop_Expr!($op, $meth_name, V3<i32>, V3<i32>);
-    impl_binop_Expr!($op, $meth_name, V3<i32>, i32);
-    impl_binop_Expr!($op, $meth_name, V4<i32>, V4<i32>);
-    impl_binop_Expr!($op, $meth_name, V4<i32>, i32);
+/// A [`Var<T>`] is akin to an [`Expr<T>`] that can be mutated. You can go from a [`Var<T>`] to an [`Expr<T>`] via
+/// either the [`From`] or [`Var::to_expr`] method.
+///
+/// Variables, because they allow mutations, allow to write more complicated shader functions. Also, lots of graphics
+/// pipelines’ properties are variables you will have to write to, such as [`VertexShaderEnv::position`].
+#[derive(Debug)]
+pub struct Var<T>(Expr<T>)
+where
+  T: ?Sized;
 
-    impl_binop_Expr!($op, $meth_name, u32, u32);
-    impl_binop_Expr!($op, $meth_name, V2<u32>, V2<u32>);
-    impl_binop_Expr!($op, $meth_name, V2<u32>, u32);
-    impl_binop_Expr!($op, $meth_name, V3<u32>, V3<u32>);
-    impl_binop_Expr!($op, $meth_name, V3<u32>, u32);
-    impl_binop_Expr!($op, $meth_name, V4<u32>, V4<u32>);
-    impl_binop_Expr!($op, $meth_name, V4<u32>, u32);
+impl<'a, T> From<&'a Var<T>> for Var<T>
+where
+  T: ?Sized,
+{
+  fn from(v: &'a Self) -> Self {
+    Var(v.0.clone())
+  }
+}
 
-    impl_binop_Expr!($op, $meth_name, f32, f32);
-    impl_binop_Expr!($op, $meth_name, V2<f32>, V2<f32>);
-    impl_binop_Expr!($op, $meth_name, V2<f32>, f32);
-    impl_binop_Expr!($op, $meth_name, V3<f32>, V3<f32>);
-    impl_binop_Expr!($op, $meth_name, V3<f32>, f32);
-    impl_binop_Expr!($op, $meth_name, V4<f32>, V4<f32>);
-    impl_binop_Expr!($op, $meth_name, V4<f32>, f32);
-  };
+impl<T> From<Var<T>> for Expr<T>
+where
+  T: ?Sized,
+{
+  fn from(v: Var<T>) -> Self {
+    v.0
+  }
 }
 
-impl_binarith_Expr!(Add, add);
-impl_binarith_Expr!(Sub, sub);
-impl_binarith_Expr!(Mul, mul);
-impl_binarith_Expr!(Div, div);
+impl<'a, T> From<&'a Var<T>> for Expr<T>
+where
+  T: ?Sized,
+{
+  fn from(v: &'a Var<T>) -> Self {
+    v.0.clone()
+  }
+}
 
-impl_binop_Expr!(Rem, rem, f32, f32);
-impl_binop_Expr!(Rem, rem, V2<f32>, V2<f32>);
-impl_binop_Expr!(Rem, rem, V2<f32>, f32);
-impl_binop_Expr!(Rem, rem, V3<f32>, V3<f32>);
-impl_binop_Expr!(Rem, rem, V3<f32>, f32);
-impl_binop_Expr!(Rem, rem, V4<f32>, V4<f32>);
-impl_binop_Expr!(Rem, rem, V4<f32>, f32);
+impl<T> Var<T>
+where
+  T: ?Sized,
+{
+  /// Create a new [`Var<T>`] from a [`ScopedHandle`].
+  pub const fn new(handle: ScopedHandle) -> Self {
+    Self(Expr::new(ErasedExpr::Var(handle)))
+  }
 
-impl_binop_Expr!(Mul, mul, M22, M22);
-impl_binop_Expr!(Mul, mul, M22, V2<f32>, V2<f32>);
-impl_binop_Expr!(Mul, mul, V2<f32>, M22, M22);
-impl_binop_Expr!(Mul, mul, M33, M33);
-impl_binop_Expr!(Mul, mul, M33, V3<f32>, V3<f32>);
-impl_binop_Expr!(Mul, mul, V3<f32>, M33, M33);
-impl_binop_Expr!(Mul, mul, M44, M44);
-impl_binop_Expr!(Mul, mul, M44, V4<f32>, V4<f32>);
-impl_binop_Expr!(Mul, mul, V4<f32>, M44, M44);
+  /// Coerce [`Var<T>`] into [`Expr<T>`].
+  ///
+  /// Remember that doing so will move the [`Var<T>`]. `clone` it if you want to preserve the source variable.
+  ///
+  /// > Note: use this function only when necessary. Lots of functions will accept both [`Expr<T>`] and [`Var<T>`],
+  /// > performing the coercion for you automatically.
+  ///
+  /// # Return
+  ///
+  /// The expression representation of [`Var<T>`], allowing to pass the variable to functions or expressions that don’t
+  /// easily coerce it automatically to [`Expr<T>`] already.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use shades::{Scope, ShaderBuilder};
+  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+  /// #   s.main_fun(|s: &mut Scope<()>| {
+  /// let v = s.var(123); // Var<i32>
+  /// let e = v.to_expr(); // Expr<i32>
+  /// #   })
+  /// # });
+  /// ```
+  pub fn to_expr(&self) -> Expr<T> {
+    self.0.clone()
+  }
+}
 
-macro_rules! impl_binshift_Expr {
-  ($op:ident, $meth_name:ident, $ty:ty) => {
-    // expr OP expr
-    impl ops::$op<Expr<u32>> for Expr<$ty> {
-      type Output = Expr<$ty>;
+impl<T> Var<[T]> {
+  pub fn at(&self, index: impl Into<Expr<i32>>) -> Var<T> {
+    Var(self.to_expr().at(index))
+  }
+}
 
-      fn $meth_name(self, rhs: Expr<u32>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(Box::new(self.erased), Box::new(rhs.erased)))
-      }
-    }
+impl<T, const N: usize> Var<[T; N]> {
+  pub fn at(&self, index: impl Into<Expr<i32>>) -> Var<T> {
+    Var(self.to_expr().at(index))
+  }
+}
+
+impl<T> ops::Deref for Var<T>
+where
+  T: ?Sized,
+{
+  type Target = Expr<T>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+/// Hierarchical and namespaced handle.
+///
+/// Handles live in different namespaces:
+///
+/// - The _built-in_ namespace gathers all built-ins.
+/// - The _global_ namespace gathers everything that can be declared at top-level of a shader stage — i.e. mainly
+///   constants for this namespace.
+/// - The _input_ namespace gathers inputs.
+/// - The _output_ namespace gathers outputs.
+/// - The _function argument_ namespace gives handles to function arguments, which exist only in a function body.
+/// - The _function variable_ namespace gives handles to variables defined in function bodies. This namespace is
+///   hierarchical: for each scope, a new namespace is created. The depth at which a namespace is located is referred
+///   to as its _subscope_.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ScopedHandle {
+  BuiltIn(BuiltIn),
+  Global(u16),
+  FunArg(u16),
+  FunVar { subscope: u16, handle: u16 },
+  Input(String),
+  Output(String),
+  Uniform(String),
+}
 
-    impl<'a> ops::$op<Expr<u32>> for &'a Expr<$ty> {
-      type Output = Expr<$ty>;
+impl ScopedHandle {
+  const fn builtin(b: BuiltIn) -> Self {
+    Self::BuiltIn(b)
+  }
 
-      fn $meth_name(self, rhs: Expr<u32>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased.clone()),
-          Box::new(rhs.erased),
-        ))
-      }
-    }
+  const fn global(handle: u16) -> Self {
+    Self::Global(handle)
+  }
 
-    impl<'a> ops::$op<&'a Expr<u32>> for Expr<$ty> {
-      type Output = Expr<$ty>;
+  const fn fun_arg(handle: u16) -> Self {
+    Self::FunArg(handle)
+  }
 
-      fn $meth_name(self, rhs: &'a Expr<u32>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased),
-          Box::new(rhs.erased.clone()),
-        ))
-      }
-    }
+  const fn fun_var(subscope: u16, handle: u16) -> Self {
+    Self::FunVar { subscope, handle }
+  }
 
-    impl<'a> ops::$op<&'a Expr<u32>> for &'a Expr<$ty> {
-      type Output = Expr<$ty>;
+  pub fn uniform(name: impl Into<String>) -> Self {
+    Self::Uniform(name.into())
+  }
+}
 
-      fn $meth_name(self, rhs: &'a Expr<u32>) -> Self::Output {
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased.clone()),
-          Box::new(rhs.erased.clone()),
-        ))
-      }
-    }
+/// Instruction of a scope.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ScopeInstr {
+  VarDecl {
+    ty: Type,
+    handle: ScopedHandle,
+    init_value: ErasedExpr,
+  },
 
-    // expr OP bits
-    impl ops::$op<u32> for Expr<$ty> {
-      type Output = Self;
+  VarDeclUninit {
+    ty: Type,
+    handle: ScopedHandle,
+  },
 
-      fn $meth_name(self, rhs: u32) -> Self::Output {
-        let rhs = Expr::from(rhs);
-        Expr::new(ErasedExpr::$op(Box::new(self.erased), Box::new(rhs.erased)))
-      }
-    }
+  Return(ErasedReturn),
 
-    impl<'a> ops::$op<u32> for &'a Expr<$ty> {
-      type Output = Expr<$ty>;
+  Continue,
 
-      fn $meth_name(self, rhs: u32) -> Self::Output {
-        let rhs = Expr::from(rhs);
-        Expr::new(ErasedExpr::$op(
-          Box::new(self.erased.clone()),
-          Box::new(rhs.erased),
-        ))
-      }
-    }
-  };
-}
+  Break,
 
-/// Binary shift generating macro.
-macro_rules! impl_binshifts_Expr {
-  ($op:ident, $meth_name:ident) => {
-    impl_binshift_Expr!($op, $meth_name, i32);
-    impl_binshift_Expr!($op, $meth_name, V2<i32>);
-    impl_binshift_Expr!($op, $meth_name, V3<i32>);
-    impl_binshift_Expr!($op, $meth_name, V4<i32>);
+  If {
+    condition: ErasedExpr,
+    scope: ErasedScope,
+  },
 
-    impl_binshift_Expr!($op, $meth_name, u32);
-    impl_binshift_Expr!($op, $meth_name, V2<u32>);
-    impl_binshift_Expr!($op, $meth_name, V3<u32>);
-    impl_binshift_Expr!($op, $meth_name, V4<u32>);
+  ElseIf {
+    condition: ErasedExpr,
+    scope: ErasedScope,
+  },
 
-    impl_binshift_Expr!($op, $meth_name, f32);
-    impl_binshift_Expr!($op, $meth_name, V2<f32>);
-    impl_binshift_Expr!($op, $meth_name, V3<f32>);
-    impl_binshift_Expr!($op, $meth_name, V4<f32>);
-  };
-}
+  Else {
+    scope: ErasedScope,
+  },
 
-impl_binshifts_Expr!(Shl, shl);
-impl_binshifts_Expr!(Shr, shr);
+  // a nested scope, which doesn’t run conditionally
+  Block {
+    scope: ErasedScope,
+  },
 
-macro_rules! impl_From_Expr_scalar {
-  ($t:ty, $q:ident) => {
-    impl From<$t> for Expr<$t> {
-      fn from(a: $t) -> Self {
-        Self::new(ErasedExpr::$q(a))
-      }
-    }
+  For {
+    init_ty: Type,
+    init_handle: ScopedHandle,
+    init_expr: ErasedExpr,
+    condition: ErasedExpr,
+    post_expr: ErasedExpr,
+    scope: ErasedScope,
+  },
 
-    impl<'a> From<&'a $t> for Expr<$t> {
-      fn from(a: &'a $t) -> Self {
-        Self::new(ErasedExpr::$q(*a))
-      }
-    }
-  };
-}
+  ForMulti {
+    condition: ErasedExpr,
+    post_exprs: Vec<(ErasedExpr, ErasedExpr)>,
+    scope: ErasedScope,
+  },
 
-impl_From_Expr_scalar!(i32, LitInt);
-impl_From_Expr_scalar!(u32, LitUInt);
-impl_From_Expr_scalar!(f32, LitFloat);
-impl_From_Expr_scalar!(bool, LitBool);
+  While {
+    condition: ErasedExpr,
+    scope: ErasedScope,
+  },
 
-macro_rules! impl_From_Expr_vn {
-  ($t:ty, $q:ident) => {
-    impl From<$t> for Expr<$t> {
-      fn from(a: $t) -> Self {
-        Self::new(ErasedExpr::$q(a.0))
-      }
-    }
+  DoWhile {
+    condition: ErasedExpr,
+    scope: ErasedScope,
+  },
 
-    impl<'a> From<&'a $t> for Expr<$t> {
-      fn from(a: &'a $t) -> Self {
-        Self::new(ErasedExpr::$q(a.0))
-      }
-    }
-  };
-}
+  Loop {
+    scope: ErasedScope,
+  },
 
-impl_From_Expr_vn!(V2<i32>, LitInt2);
-impl_From_Expr_vn!(V2<u32>, LitUInt2);
-impl_From_Expr_vn!(V2<f32>, LitFloat2);
-impl_From_Expr_vn!(V2<bool>, LitBool2);
-impl_From_Expr_vn!(V3<i32>, LitInt3);
-impl_From_Expr_vn!(V3<u32>, LitUInt3);
-impl_From_Expr_vn!(V3<f32>, LitFloat3);
-impl_From_Expr_vn!(V3<bool>, LitBool3);
-impl_From_Expr_vn!(V4<i32>, LitInt4);
-impl_From_Expr_vn!(V4<u32>, LitUInt4);
-impl_From_Expr_vn!(V4<f32>, LitFloat4);
-impl_From_Expr_vn!(V4<bool>, LitBool4);
+  MutateVar {
+    var: ErasedExpr,
+    expr: ErasedExpr,
+  },
 
-impl<T, const N: usize> From<[T; N]> for Expr<[T; N]>
-where
-  Expr<T>: From<T>,
-  T: Clone + ToType,
-{
-  fn from(array: [T; N]) -> Self {
-    let array = array
-      .iter()
-      .cloned()
-      .map(|t| Expr::from(t).erased)
-      .collect();
-    Self::new(ErasedExpr::Array(<[T; N] as ToType>::ty(), array))
-  }
-}
+  Call(ErasedFunHandle, Vec<ErasedExpr>),
 
-impl<'a, T, const N: usize> From<&'a [T; N]> for Expr<[T; N]>
-where
-  Expr<T>: From<T>,
-  T: Clone + ToType,
-{
-  fn from(array: &'a [T; N]) -> Self {
-    let array = array
-      .iter()
-      .cloned()
-      .map(|t| Expr::from(t).erased)
-      .collect();
-    Self::new(ErasedExpr::Array(<[T; N] as ToType>::ty(), array))
-  }
-}
+  Switch {
+    expr: ErasedExpr,
+    cases: Vec<(Option<ErasedExpr>, ErasedScope)>,
+  },
 
-impl<T, const N: usize> From<[Expr<T>; N]> for Expr<[T; N]>
-where
-  Expr<T>: From<T>,
-  T: ToType,
-{
-  fn from(array: [Expr<T>; N]) -> Self {
-    let array = array.iter().cloned().map(|e| e.erased).collect();
-    Self::new(ErasedExpr::Array(<[T; N] as ToType>::ty(), array))
-  }
+  Comment(String),
 }
 
-impl<'a, T, const N: usize> From<&'a [Expr<T>; N]> for Expr<[T; N]>
-where
-  Expr<T>: From<T>,
-  T: ToType,
-{
-  fn from(array: &'a [Expr<T>; N]) -> Self {
-    let array = array.iter().cloned().map(|e| e.erased).collect();
-    Self::new(ErasedExpr::Array(<[T; N] as ToType>::ty(), array))
+impl ScopeInstr {
+  /// Scopes a `break` in which exits this instruction — i.e. loop bodies and switch branches.
+  fn break_target_scopes_mut(&mut self) -> Vec<&mut ErasedScope> {
+    match self {
+      ScopeInstr::For { scope, .. }
+      | ScopeInstr::ForMulti { scope, .. }
+      | ScopeInstr::While { scope, .. }
+      | ScopeInstr::DoWhile { scope, .. }
+      | ScopeInstr::Loop { scope } => vec![scope],
+      ScopeInstr::Switch { cases, .. } => cases.iter_mut().map(|(_, scope)| scope).collect(),
+      _ => Vec::new(),
+    }
   }
-}
-
-/// Create various forms of literal expressions.
-///
-/// This macro allows you to create _literal expressions_ by lifting Rust constants into the EDSL. The way this is done
-/// is via several forms:
-///
-/// - `lit!(x)` lifts a single Rust expression into the EDSL. It’s isomorphic to `Expr::from(x)`.
-/// - `lit!(x, y)` lifts two Rust expressions into the EDSL as a 2D scalar vector. It’s isomorphic to
-///   `Expr::from(V2::from([x, y]))`.
-/// - `lit!(x, y, z)` lifts three Rust expressions into the EDSL as a 3D scalar vector. It’s isomorphic to
-///   `Expr::from(V3::from([x, y, z]))`.
-/// - `lit!(x, y, z, w)` lifts three Rust expressions into the EDSL as a 3D scalar vector. It’s isomorphic to
-///   `Expr::from(V4::from([x, y, z, w]))`.
-///
-/// Most of the time, type inference will kick in and you shouldn’t have to annotate the return expression.
-///
-/// # Examples
-///
-/// ```
-/// # use shades::{Scope, ShaderBuilder};
-/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-/// use shades::{lit};
-///
-/// let _ = lit!(1);
-/// let _ = lit!(false);
-/// let _ = lit!(1., 2., 3., 4.);
-/// # s.main_fun(|s: &mut Scope<()>| {})
-/// # });
-/// ```
-#[macro_export]
-macro_rules! lit {
-  ($e:expr) => {
-    $crate::Expr::from($e)
-  };
 
-  ($a:expr, $b:expr) => {
-    $crate::Expr::from($crate::V2::from([$a, $b]))
-  };
+  /// Expressions directly used by this instruction, excluding the ones in nested scopes.
+  fn exprs(&self) -> Vec<&ErasedExpr> {
+    match self {
+      ScopeInstr::VarDecl { init_value, .. } => vec![init_value],
+      ScopeInstr::Return(ErasedReturn::Expr(_, expr)) => vec![expr],
+      ScopeInstr::If { condition, .. }
+      | ScopeInstr::ElseIf { condition, .. }
+      | ScopeInstr::While { condition, .. }
+      | ScopeInstr::DoWhile { condition, .. } => vec![condition],
+      ScopeInstr::For {
+        init_expr,
+        condition,
+        post_expr,
+        ..
+      } => vec![init_expr, condition, post_expr],
+      ScopeInstr::ForMulti {
+        condition,
+        post_exprs,
+        ..
+      } => once(condition)
+        .chain(post_exprs.iter().flat_map(|(var, expr)| vec![var, expr]))
+        .collect(),
+      ScopeInstr::MutateVar { var, expr } => vec![var, expr],
+      ScopeInstr::Call(_, args) => args.iter().collect(),
+      ScopeInstr::Switch { expr, cases } => once(expr)
+        .chain(cases.iter().filter_map(|(case, _)| case.as_ref()))
+        .collect(),
+      ScopeInstr::VarDeclUninit { .. }
+      | ScopeInstr::Return(ErasedReturn::Void)
+      | ScopeInstr::Continue
+      | ScopeInstr::Break
+      | ScopeInstr::Comment(_)
+      | ScopeInstr::Else { .. }
+      | ScopeInstr::Block { .. }
+      | ScopeInstr::Loop { .. } => Vec::new(),
+    }
+  }
 
-  ($a:expr, $b:expr, $c:expr) => {
-    $crate::Expr::from($crate::V3::from([$a, $b, $c]))
-  };
+  /// Scopes nested in this instruction.
+  fn scopes(&self) -> Vec<&ErasedScope> {
+    match self {
+      ScopeInstr::If { scope, .. }
+      | ScopeInstr::ElseIf { scope, .. }
+      | ScopeInstr::Else { scope }
+      | ScopeInstr::Block { scope }
+      | ScopeInstr::For { scope, .. }
+      | ScopeInstr::ForMulti { scope, .. }
+      | ScopeInstr::While { scope, .. }
+      | ScopeInstr::DoWhile { scope, .. }
+      | ScopeInstr::Loop { scope } => vec![scope],
+      ScopeInstr::Switch { cases, .. } => cases.iter().map(|(_, scope)| scope).collect(),
+      _ => Vec::new(),
+    }
+  }
 
-  ($a:expr, $b:expr, $c:expr, $d:expr) => {
-    $crate::Expr::from($crate::V4::from([$a, $b, $c, $d]))
-  };
+  /// Whether this instruction is a loop.
+  fn is_loop(&self) -> bool {
+    matches!(
+      self,
+      ScopeInstr::For { .. }
+        | ScopeInstr::ForMulti { .. }
+        | ScopeInstr::While { .. }
+        | ScopeInstr::DoWhile { .. }
+        | ScopeInstr::Loop { .. }
+    )
+  }
+
+  /// Expressions directly used by this instruction, excluding the ones in nested scopes.
+  fn exprs_mut(&mut self) -> Vec<&mut ErasedExpr> {
+    match self {
+      ScopeInstr::VarDecl { init_value, .. } => vec![init_value],
+      ScopeInstr::Return(ErasedReturn::Expr(_, expr)) => vec![expr],
+      ScopeInstr::If { condition, .. }
+      | ScopeInstr::ElseIf { condition, .. }
+      | ScopeInstr::While { condition, .. }
+      | ScopeInstr::DoWhile { condition, .. } => vec![condition],
+      ScopeInstr::For {
+        init_expr,
+        condition,
+        post_expr,
+        ..
+      } => vec![init_expr, condition, post_expr],
+      ScopeInstr::ForMulti {
+        condition,
+        post_exprs,
+        ..
+      } => once(condition)
+        .chain(
+          post_exprs
+            .iter_mut()
+            .flat_map(|(var, expr)| vec![var, expr]),
+        )
+        .collect(),
+      ScopeInstr::MutateVar { var, expr } => vec![var, expr],
+      ScopeInstr::Call(_, args) => args.iter_mut().collect(),
+      ScopeInstr::Switch { expr, cases } => once(expr)
+        .chain(cases.iter_mut().filter_map(|(case, _)| case.as_mut()))
+        .collect(),
+      ScopeInstr::VarDeclUninit { .. }
+      | ScopeInstr::Return(ErasedReturn::Void)
+      | ScopeInstr::Continue
+      | ScopeInstr::Break
+      | ScopeInstr::Comment(_)
+      | ScopeInstr::Else { .. }
+      | ScopeInstr::Block { .. }
+      | ScopeInstr::Loop { .. } => Vec::new(),
+    }
+  }
 
-  ([ $($item:expr),* ]) => {
-    $crate::Expr::from([ $($item),* ])
+  /// Scopes nested in this instruction.
+  fn scopes_mut(&mut self) -> Vec<&mut ErasedScope> {
+    match self {
+      ScopeInstr::If { scope, .. }
+      | ScopeInstr::ElseIf { scope, .. }
+      | ScopeInstr::Else { scope }
+      | ScopeInstr::Block { scope } => vec![scope],
+      _ => self.break_target_scopes_mut(),
+    }
   }
 }
 
-/// Create 2D scalar vectors via different forms.
-///
-/// This macro allows to create 2D ([`V2`]) scalar vectors from two forms:
-///
-/// - `vec2!(xy)`, which acts as the cast operator. Only types `T` satisfying [`Vec2`] are castable.
-/// - `vec2!(x, y)`, which builds a [`V2<T>`] for `x: T` and `y: T`.
-///
-/// # Examples
+/// Dimension of a primitive type.
 ///
-/// ```
-/// # use shades::{Scope, ShaderBuilder};
-/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-/// use shades::vec2;
+/// Primitive types currently can have one of four dimension:
 ///
-/// let _ = vec2!(1, 2);
-/// # s.main_fun(|s: &mut Scope<()>| {})
-/// # });
-/// ```
-#[macro_export]
-macro_rules! vec2 {
-  ($a:expr) => {
-    todo!("vec2 cast operator missing");
-  };
+/// - [`Dim::Scalar`]: designates a scalar value.
+/// - [`Dim::D2`]: designates a 2D vector.
+/// - [`Dim::D3`]: designates a 3D vector.
+/// - [`Dim::D4`]: designates a 4D vector.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Dim {
+  /// Scalar value.
+  Scalar,
 
-  ($xy:expr, $z:expr) => {{
-    use $crate::Vec2 as _;
-    $crate::Expr::vec2(($crate::Expr::from(&$xy), $crate::Expr::from(&$z)))
-  }};
+  /// 2D vector.
+  D2,
+
+  /// 3D vector.
+  D3,
+
+  /// 4D vector.
+  D4,
 }
 
-/// Create 3D scalar vectors via different forms.
-///
-/// This macro allows to create 3D ([`V3`]) scalar vectors from several forms:
-///
-/// - `vec3!(xyz)`, which acts as the cast operator. Only types `T` satisfying [`Vec3`] are castable.
-/// - `vec3!(xy, z)`, which builds a [`V3<T>`] with `xy` a value that can be turned into a `Expr<V2<T>>` and `z: T`
-/// - `vec3!(x, y, z)`, which builds a [`V3<T>`] for `x: T`, `y: T` and `z: T`.
-///
-/// # Examples
-///
-/// ```
-/// # use shades::{Scope, ShaderBuilder};
-/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-/// use shades::{vec2, vec3};
+/// Matrix wrapper.
 ///
-/// let _ = vec3!(1, 2, 3);
-/// let _ = vec3!(vec2!(1, 2), 3);
-/// # s.main_fun(|s: &mut Scope<()>| {})
-/// # });
-/// ```
-#[macro_export]
-macro_rules! vec3 {
-  ($a:expr) => {
-    todo!("vec3 cast operator missing");
-  };
+/// This type represents a matrix of a given dimension, deduced from the wrapped type.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Matrix<T>(T);
 
-  ($xy:expr, $z:expr) => {{
-    use $crate::Vec3 as _;
-    $crate::Expr::vec3(($crate::Expr::from(&$xy), $crate::Expr::from(&$z)))
-  }};
+impl<T, const M: usize, const N: usize> From<[[T; N]; M]> for Matrix<[[T; N]; M]> {
+  fn from(a: [[T; N]; M]) -> Self {
+    Matrix(a)
+  }
+}
 
-  ($x:expr, $y:expr, $z:expr) => {{
-    use $crate::Vec3 as _;
-    $crate::Expr::vec3((
-      $crate::Expr::from(&$x),
-      $crate::Expr::from(&$y),
-      $crate::Expr::from(&$z),
-    ))
-  }};
+macro_rules! make_mat_ty {
+  ($t:ident, $lit:ident, $m:expr, $n:expr, $mdim:ident) => {
+    pub type $t = Matrix<[[f32; $n]; $m]>;
+
+    impl ToPrimType for Matrix<[[f32; $n]; $m]> {
+      const PRIM_TYPE: PrimType = PrimType::Matrix(MatrixDim::$mdim);
+    }
+
+    impl From<Matrix<[[f32; $n]; $m]>> for Expr<Matrix<[[f32; $n]; $m]>> {
+      fn from(matrix: Matrix<[[f32; $n]; $m]>) -> Self {
+        Self::new(ErasedExpr::$lit(matrix))
+      }
+    }
+  };
 }
 
-/// Create 4D scalar vectors via different forms.
-///
-/// This macro allows to create 4D ([`V4`]) scalar vectors from several forms:
-///
-/// - `vec4!(xyzw)`, which acts as the cast operator. Only types `T` satisfying [`Vec4`] are castable.
-/// - `vec4!(xyz, w)`, which builds a [`V4<T>`] with `xyz` a value that can be turned into a `Expr<V3<T>>` and `w: T`.
-/// - `vec4!(xy, zw)`, which builds a [`V4<T>`] with `xy` and `zw` values that can be turned into `Expr<V3<T>>`.
-/// - `vec4!(xy, z, w)`, which builds a [`V4<T>`] with `xy`, `z: T` and `w: T`.
-/// - `vec4!(x, y, z, w)`, which builds a [`V3<T>`] for `x: T`, `y: T` and `z: T`.
+make_mat_ty!(M22, LitM22, 2, 2, D22);
+make_mat_ty!(M23, LitM23, 2, 3, D23);
+make_mat_ty!(M24, LitM24, 2, 4, D24);
+make_mat_ty!(M32, LitM32, 3, 2, D32);
+make_mat_ty!(M33, LitM33, 3, 3, D33);
+make_mat_ty!(M34, LitM34, 3, 4, D34);
+make_mat_ty!(M42, LitM42, 4, 2, D42);
+make_mat_ty!(M43, LitM43, 4, 3, D43);
+make_mat_ty!(M44, LitM44, 4, 4, D44);
+
+/// Matrix dimension.
 ///
-/// # Examples
+/// Matrices can have several dimensions. Most of the time, you will be interested in squared dimensions, e.g. 2×2, 3×3
+/// and 4×4. However, other dimensions exist.
 ///
-/// ```
-/// # use shades::{Scope, ShaderBuilder};
-/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-/// use shades::{vec2, vec3, vec4};
+/// > Note: matrices are expressed in column-major.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum MatrixDim {
+  /// Squared 2 dimension.
+  D22,
+  /// 2×3 dimension.
+  D23,
+  /// 2×4 dimension.
+  D24,
+  /// 3×2 dimension.
+  D32,
+  /// Squared 3 dimension.
+  D33,
+  /// 3×4 dimension.
+  D34,
+  /// 4×2 dimension.
+  D42,
+  /// 4×3 dimension.
+  D43,
+  /// Squared 4 dimension.
+  D44,
+}
+
+/// Type representation — akin to [`PrimType`] glued with array dimensions, if any.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Type {
+  /// Primitive type, representing a type without array dimensions.
+  prim_ty: PrimType,
+
+  /// Array dimensions, if any.
+  ///
+  /// Dimensions are sorted from outer to inner; i.e. `[[i32; N]; M]`’s dimensions is encoded as `vec![M, N]`.
+  array_dims: Vec<usize>,
+}
+
+impl Type {
+  /// Primitive type, representing the type without array dimensions.
+  pub fn prim_ty(&self) -> &PrimType {
+    &self.prim_ty
+  }
+
+  /// Array dimensions, sorted from outer to inner. Empty if the type is not an array.
+  pub fn array_dims(&self) -> &[usize] {
+    &self.array_dims
+  }
+}
+
+/// Primitive supported types.
 ///
-/// let _ = vec4!(1, 2, 3, 4);
-/// let _ = vec4!(vec3!(1, 2, 3), 4);
-/// let _ = vec4!(vec2!(1, 2), vec2!(3, 4));
-/// let _ = vec4!(vec2!(1, 2), 3, 4);
-/// # s.main_fun(|s: &mut Scope<()>| {})
-/// # });
-/// ```
-#[macro_export]
-macro_rules! vec4 {
-  ($a:expr) => {
-    todo!("vec4 cast operator missing");
-  };
+/// Types without array dimensions are known as _primitive types_ and are exhaustively constructed thanks to
+/// [`PrimType`].
+#[non_exhaustive]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum PrimType {
+  /// An integral type.
+  ///
+  /// The [`Dim`] argument represents the vector dimension — do not confuse it with an array dimension.
+  Int(Dim),
 
-  ($xy:expr, $zw:expr) => {{
-    use $crate::Vec4 as _;
-    $crate::Expr::vec4(($crate::Expr::from(&$xy), $crate::Expr::from(&$zw)))
-  }};
+  /// An unsigned integral type.
+  ///
+  /// The [`Dim`] argument represents the vector dimension — do not confuse it with an array dimension.
+  UInt(Dim),
 
-  ($xy:expr, $z:expr, $w:expr) => {{
-    use $crate::Vec4 as _;
-    $crate::Expr::vec4((
-      $crate::Expr::from(&$xy),
-      $crate::Expr::from(&$z),
-      $crate::Expr::from(&$w),
-    ))
-  }};
+  /// An floating type.
+  ///
+  /// The [`Dim`] argument represents the vector dimension — do not confuse it with an array dimension.
+  Float(Dim),
 
-  ($x:expr, $y:expr, $z:expr, $w:expr) => {{
-    use $crate::Vec4 as _;
-    $crate::Expr::vec4((
-      $crate::Expr::from(&$x),
-      $crate::Expr::from(&$y),
-      $crate::Expr::from(&$z),
-      $crate::Expr::from(&$w),
-    ))
-  }};
+  /// A boolean type.
+  ///
+  /// The [`Dim`] argument represents the vector dimension — do not confuse it with an array dimension.
+  Bool(Dim),
+
+  /// A N×M floating matrix.
+  ///
+  /// The [`MatrixDim`] provides the information required to know the exact dimension of the matrix.
+  Matrix(MatrixDim),
 }
 
-/// Function return.
+/// Class of types that are recognized by the EDSL.
 ///
-/// This type represents a function return and is used to annotate values that can be returned from functions (i.e.
-/// expressions).
-#[derive(Clone, Debug, PartialEq)]
-pub struct Return {
-  erased: ErasedReturn,
+/// Any type implementing this type family is _representable_ in the EDSL.
+pub trait ToPrimType {
+  /// Mapped primitive type.
+  const PRIM_TYPE: PrimType;
+}
+
+macro_rules! impl_ToPrimType {
+  ($t:ty, $q:ident, $d:ident) => {
+    impl ToPrimType for $t {
+      const PRIM_TYPE: PrimType = PrimType::$q(Dim::$d);
+    }
+  };
 }
 
-/// Erased return.
+impl_ToPrimType!(i32, Int, Scalar);
+impl_ToPrimType!(u32, UInt, Scalar);
+impl_ToPrimType!(f32, Float, Scalar);
+impl_ToPrimType!(bool, Bool, Scalar);
+impl_ToPrimType!(V2<i32>, Int, D2);
+impl_ToPrimType!(V2<u32>, UInt, D2);
+impl_ToPrimType!(V2<f32>, Float, D2);
+impl_ToPrimType!(V2<bool>, Bool, D2);
+impl_ToPrimType!(V3<i32>, Int, D3);
+impl_ToPrimType!(V3<u32>, UInt, D3);
+impl_ToPrimType!(V3<f32>, Float, D3);
+impl_ToPrimType!(V3<bool>, Bool, D3);
+impl_ToPrimType!(V4<i32>, Int, D4);
+impl_ToPrimType!(V4<u32>, UInt, D4);
+impl_ToPrimType!(V4<f32>, Float, D4);
+impl_ToPrimType!(V4<bool>, Bool, D4);
+
+/// Represent a type (primitive type and array dimension) in the EDSL.
 ///
-/// Either `Void` (i.e. `void`) or an expression. The type of the expression is also present for convenience.
-#[derive(Clone, Debug, PartialEq)]
-enum ErasedReturn {
-  Void,
-  Expr(Type, ErasedExpr),
+/// Any type implementing [`ToType`] is representable in the EDSL. Any type implementing [`ToPrimType`] automatically
+/// also implements [`ToType`].
+pub trait ToType {
+  fn ty() -> Type;
 }
 
-impl From<()> for Return {
-  fn from(_: ()) -> Self {
-    Return {
-      erased: ErasedReturn::Void,
+impl<T> ToType for T
+where
+  T: ToPrimType,
+{
+  fn ty() -> Type {
+    Type {
+      prim_ty: T::PRIM_TYPE,
+      array_dims: Vec::new(),
     }
   }
 }
 
-impl<T> From<Expr<T>> for Return
+impl<T, const N: usize> ToType for [T; N]
 where
   T: ToType,
 {
-  fn from(expr: Expr<T>) -> Self {
-    Return {
-      erased: ErasedReturn::Expr(T::ty(), expr.erased),
+  fn ty() -> Type {
+    let Type {
+      prim_ty,
+      array_dims,
+    } = T::ty();
+    let array_dims = once(N).chain(array_dims).collect();
+
+    Type {
+      prim_ty,
+      array_dims,
     }
   }
 }
 
-/// Function definition injection.
-///
-/// This trait represents _function definition injection_, i.e. types that can provide a function definition — see
-/// [`FunDef`]. Ideally, only a very small number of types can do this: polymorphic types implementing the [`FnOnce`]
-/// trait with different number of arguments. Namely, closures / lambdas with various numbers of arguments.
+/// Select a channel to extract from into a swizzled expession.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SwizzleSelector {
+  /// Select the `.x` (or `.r`, or `.s`) channel.
+  X,
+
+  /// Select the `.y` (or `.g`, or `.t`) channel.
+  Y,
+
+  /// Select the `.z` (or `.b`, or `.p`) channel.
+  Z,
+
+  /// Select the `.w` (or `.a`, or `.q`) channel.
+  W,
+}
+
+/// Swizzle channel selector.
 ///
-/// You are not supposed to implement this type by yourself. Instead, when creating functions in the EDSL, you just have
-/// to pass lambdas to automatically get the proper function definition lifted into the EDSL.
+/// This type gives the dimension of the target expression (output) and dimension of the source expression (input). The
+/// [`SwizzleSelector`] also to select a specific channel in the input expression.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Swizzle {
+  /// Create a one-channel expression.
+  D1(SwizzleSelector),
+
+  /// Create a two-channel expression.
+  D2(SwizzleSelector, SwizzleSelector),
+
+  /// Create a three-channel expression.
+  D3(SwizzleSelector, SwizzleSelector, SwizzleSelector),
+
+  /// Create a four-channel expression.
+  D4(
+    SwizzleSelector,
+    SwizzleSelector,
+    SwizzleSelector,
+    SwizzleSelector,
+  ),
+}
+
+/// Interface to implement to swizzle an expression.
 ///
-/// See the [`ShaderBuilder::fun`] for further information.
+/// Expressions are swizzled with masks of the following types:
 ///
-/// # Caveats
+/// - [`SwizzleSelector`]: to implement `.x`.
+/// - [[`SwizzleSelector`]; 2]: to implement `.xx`.
+/// - [[`SwizzleSelector`]; 3]: to implement `.xxx`.
+/// - [[`SwizzleSelector`]; 4]: to implement `.xxxx`.
 ///
-/// This way of doing currently comes with a price: type inference is bad. You will — most of the time — have to
-/// annotate the closure’s arguments. This is currently working on but progress on that matter is slow.
-pub trait ToFun<R, A> {
-  fn build_fn(self) -> FunDef<R, A>;
+/// The [`sw!`](sw) macro uses the [compile-time masks](swizzle) instead, which [`Expr`] turns into those types. Such
+/// masks give [`Var`]s rather than [`Expr`]s when swizzling a [`Var`] without selecting any component twice.
+pub trait Swizzlable<S> {
+  type Output;
+
+  fn swizzle(&self, sw: S) -> Self::Output;
 }
 
-impl<F, R> ToFun<R, ()> for F
-where
-  Self: FnOnce(&mut Scope<R>) -> R,
-  Return: From<R>,
-{
-  fn build_fn(self) -> FunDef<R, ()> {
-    let mut scope = Scope::new(0);
-    let ret = self(&mut scope);
+// 2D
+impl<T> Swizzlable<SwizzleSelector> for Expr<V2<T>> {
+  type Output = Expr<T>;
 
-    let erased = ErasedFun::new(Vec::new(), scope.erased, Return::from(ret).erased);
+  fn swizzle(&self, x: SwizzleSelector) -> Self::Output {
+    Expr::new(ErasedExpr::Swizzle(
+      self.erased.clone().intern(),
+      Swizzle::D1(x),
+    ))
+  }
+}
 
-    FunDef::new(erased)
+impl<T> Swizzlable<[SwizzleSelector; 2]> for Expr<V2<T>> {
+  type Output = Self;
+
+  fn swizzle(&self, [x, y]: [SwizzleSelector; 2]) -> Self::Output {
+    Expr::new(ErasedExpr::Swizzle(
+      self.erased.clone().intern(),
+      Swizzle::D2(x, y),
+    ))
   }
 }
 
-macro_rules! impl_ToFun_args {
-  ($($arg:ident , $arg_ident:ident , $arg_rank:expr),*) => {
-    impl<F, R, $($arg),*> ToFun<R, ($(Expr<$arg>),*)> for F
-    where
-      Self: FnOnce(&mut Scope<R>, $(Expr<$arg>),*) -> R,
-      Return: From<R>,
-      $($arg: ToType),*
-    {
-      fn build_fn(self) -> FunDef<R, ($(Expr<$arg>),*)> {
-        $( let $arg_ident = Expr::new(ErasedExpr::Var(ScopedHandle::fun_arg($arg_rank))); )*
-          let args = vec![$( $arg::ty() ),*];
+impl<T> Swizzlable<[SwizzleSelector; 3]> for Expr<V2<T>> {
+  type Output = Expr<V3<T>>;
 
-        let mut scope = Scope::new(0);
-        let ret = self(&mut scope, $($arg_ident),*);
+  fn swizzle(&self, [x, y, z]: [SwizzleSelector; 3]) -> Self::Output {
+    Expr::new(ErasedExpr::Swizzle(
+      self.erased.clone().intern(),
+      Swizzle::D3(x, y, z),
+    ))
+  }
+}
 
-        let erased = ErasedFun::new(args, scope.erased, Return::from(ret).erased);
+// 3D
+impl<T> Swizzlable<SwizzleSelector> for Expr<V3<T>> {
+  type Output = Expr<T>;
 
-        FunDef::new(erased)
-      }
-    }
+  fn swizzle(&self, x: SwizzleSelector) -> Self::Output {
+    Expr::new(ErasedExpr::Swizzle(
+      self.erased.clone().intern(),
+      Swizzle::D1(x),
+    ))
   }
 }
 
-impl<F, R, A> ToFun<R, Expr<A>> for F
-where
-  Self: FnOnce(&mut Scope<R>, Expr<A>) -> R,
-  Return: From<R>,
-  A: ToType,
-{
-  fn build_fn(self) -> FunDef<R, Expr<A>> {
-    let arg = Expr::new(ErasedExpr::Var(ScopedHandle::fun_arg(0)));
+impl<T> Swizzlable<[SwizzleSelector; 2]> for Expr<V3<T>> {
+  type Output = Expr<V2<T>>;
 
-    let mut scope = Scope::new(0);
-    let ret = self(&mut scope, arg);
+  fn swizzle(&self, [x, y]: [SwizzleSelector; 2]) -> Self::Output {
+    Expr::new(ErasedExpr::Swizzle(
+      self.erased.clone().intern(),
+      Swizzle::D2(x, y),
+    ))
+  }
+}
 
-    let erased = ErasedFun::new(vec![A::ty()], scope.erased, Return::from(ret).erased);
+impl<T> Swizzlable<[SwizzleSelector; 3]> for Expr<V3<T>> {
+  type Output = Self;
 
-    FunDef::new(erased)
+  fn swizzle(&self, [x, y, z]: [SwizzleSelector; 3]) -> Self::Output {
+    Expr::new(ErasedExpr::Swizzle(
+      self.erased.clone().intern(),
+      Swizzle::D3(x, y, z),
+    ))
   }
 }
 
-impl_ToFun_args!(A0, a0, 0, A1, a1, 1);
-impl_ToFun_args!(A0, a0, 0, A1, a1, 1, A2, a2, 2);
-impl_ToFun_args!(A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3);
-impl_ToFun_args!(A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4);
-impl_ToFun_args!(A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5);
-impl_ToFun_args!(A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6);
-impl_ToFun_args!(
-  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7
-);
-impl_ToFun_args!(
-  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8, 8
-);
-impl_ToFun_args!(
-  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
-  8, A9, a9, 9
-);
-impl_ToFun_args!(
-  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
-  8, A9, a9, 9, A10, a10, 10
-);
-impl_ToFun_args!(
-  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
-  8, A9, a9, 9, A10, a10, 10, A11, a11, 11
-);
-impl_ToFun_args!(
-  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
-  8, A9, a9, 9, A10, a10, 10, A11, a11, 11, A12, a12, 12
-);
-impl_ToFun_args!(
-  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
-  8, A9, a9, 9, A10, a10, 10, A11, a11, 11, A12, a12, 12, A13, a13, 13
-);
-impl_ToFun_args!(
-  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
-  8, A9, a9, 9, A10, a10, 10, A11, a11, 11, A12, a12, 12, A13, a13, 13, A14, a14, 14
-);
-impl_ToFun_args!(
-  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
-  8, A9, a9, 9, A10, a10, 10, A11, a11, 11, A12, a12, 12, A13, a13, 13, A14, a14, 14, A15, a15, 15
-);
-impl_ToFun_args!(
-  A0, a0, 0, A1, a1, 1, A2, a2, 2, A3, a3, 3, A4, a4, 4, A5, a5, 5, A6, a6, 6, A7, a7, 7, A8, a8,
-  8, A9, a9, 9, A10, a10, 10, A11, a11, 11, A12, a12, 12, A13, a13, 13, A14, a14, 14, A15, a15, 15,
-  A16, a16, 16
-);
+// 4D
+impl<T> Swizzlable<SwizzleSelector> for Expr<V4<T>> {
+  type Output = Expr<T>;
+
+  fn swizzle(&self, x: SwizzleSelector) -> Self::Output {
+    Expr::new(ErasedExpr::Swizzle(
+      self.erased.clone().intern(),
+      Swizzle::D1(x),
+    ))
+  }
+}
+
+impl<T> Swizzlable<[SwizzleSelector; 2]> for Expr<V4<T>> {
+  type Output = Expr<V2<T>>;
+
+  fn swizzle(&self, [x, y]: [SwizzleSelector; 2]) -> Self::Output {
+    Expr::new(ErasedExpr::Swizzle(
+      self.erased.clone().intern(),
+      Swizzle::D2(x, y),
+    ))
+  }
+}
 
-/// An opaque function handle, used to call user-defined functions.
-///
-/// Function handles are created with the [`ShaderBuilder::fun`] function, introducing new functions in the EDSL. You
-/// can then call the functions in the context of generating new expressions, returning them or creating variables.
-///
-/// Injecting a function call in the EDSL is done via two current mechanisms:
-///
-/// - Either call the [`FunHandle::call`] method:
-///   - It is a function without argument if the represented function doesn’t have any argument.
-///   - It is a unary function if the represented function has a single argument.
-///   - It takes a tuple encapsulating the arguments for a n-ary represented function.
-/// - **On nightly only**, you can enable the `fun-call` feature-gate and calling the function will do the same thing
-///   as [`FunHandle::call`]. However, because of how the various [`FnOnce`], [`FnMut`] and [`Fn`] traits are made,
-///   functions taking several arguments take them as separate arguments as if it was a regular Rust function (they
-///   don’t take a tuple as with the [`FunHandle::call`] method).
-///
-/// # Examples
-///
-/// A unary function squaring its argument, the regular way:
-///
-/// ```
-/// # use shades::ShaderBuilder;
-/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-/// use shades::{Expr, FunHandle, Scope, lit, vec2};
-///
-/// let square = s.fun(|s: &mut Scope<Expr<i32>>, a: Expr<i32>| {
-///   &a * &a
-/// });
-///
-/// s.main_fun(|s: &mut Scope<()>| {
-///   // call square with 3 and bind the result to a variable
-///   let squared = s.var(square.call(lit!(3)));
-/// })
-/// # });
-/// ```
-///
-/// The same function but with the `fun-call` feature-gate enabled:
-///
-/// ```
-/// # use shades::ShaderBuilder;
-/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-/// use shades::{Expr, Scope, lit};
-///
-/// let square = s.fun(|s: &mut Scope<Expr<i32>>, a: Expr<i32>| {
-///   &a * &a
-/// });
-///
-/// s.main_fun(|s: &mut Scope<()>| {
-///   // call square with 3 and bind the result to a variable
-///   # #[cfg(feature = "fun-call")]
-///   let squared = s.var(square(lit!(3)));
-/// })
-/// # });
-/// ```
-///
-/// A function taking two 3D vectors and a floating scalar and returning their linear interpolation, called with
-/// three arguments:
-///
-/// ```
-/// # use shades::ShaderBuilder;
-/// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-/// use shades::{Expr, Mix as _, Scope, V3, lit, vec3};
-///
-/// let lerp = s.fun(|s: &mut Scope<Expr<V3<f32>>>, a: Expr<V3<f32>>, b: Expr<V3<f32>>, t: Expr<f32>| {
-///   a.mix(b, t)
-/// });
-///
-/// s.main_fun(|s: &mut Scope<()>| {
-///   # #[cfg(feature = "fun-call")]
-///   let a = vec3!(0., 0., 0.);
-///   let b = vec3!(1., 1., 1.);
-///
-///   // call lerp here and bind it to a local variable
-/// # #[cfg(feature = "fun-call")]
-///   let result = s.var(lerp(a, b, lit!(0.75)));
-/// })
-/// # });
-/// ```
-#[derive(Clone, Debug, PartialEq)]
-pub struct FunHandle<R, A> {
-  erased: ErasedFunHandle,
-  _phantom: PhantomData<(R, A)>,
+impl<T> Swizzlable<[SwizzleSelector; 3]> for Expr<V4<T>> {
+  type Output = Expr<V3<T>>;
+
+  fn swizzle(&self, [x, y, z]: [SwizzleSelector; 3]) -> Self::Output {
+    Expr::new(ErasedExpr::Swizzle(
+      self.erased.clone().intern(),
+      Swizzle::D3(x, y, z),
+    ))
+  }
 }
 
-impl<R> FunHandle<Expr<R>, ()> {
-  /// Create an expression representing a function call to this function.
-  ///
-  /// See the documentation of [`FunHandle`] for examples.
-  pub fn call(&self) -> Expr<R> {
-    Expr::new(ErasedExpr::FunCall(self.erased.clone(), Vec::new()))
+impl<T> Swizzlable<[SwizzleSelector; 4]> for Expr<V4<T>> {
+  type Output = Self;
+
+  fn swizzle(&self, [x, y, z, w]: [SwizzleSelector; 4]) -> Self::Output {
+    Expr::new(ErasedExpr::Swizzle(
+      self.erased.clone().intern(),
+      Swizzle::D4(x, y, z, w),
+    ))
   }
 }
 
-#[cfg(feature = "fun-call")]
-impl<R> FnOnce<()> for FunHandle<Expr<R>, ()> {
-  type Output = Expr<R>;
+// compile-time masks
+impl<T, M> Swizzlable<M> for Expr<T>
+where
+  M: swizzle::StaticMask,
+  Self: Swizzlable<M::Selectors>,
+{
+  type Output = <Self as Swizzlable<M::Selectors>>::Output;
 
-  extern "rust-call" fn call_once(self, _: ()) -> Self::Output {
-    self.call()
+  fn swizzle(&self, mask: M) -> Self::Output {
+    self.swizzle(mask.selectors())
   }
 }
 
-#[cfg(feature = "fun-call")]
-impl<R> FnMut<()> for FunHandle<Expr<R>, ()> {
-  extern "rust-call" fn call_mut(&mut self, _: ()) -> Self::Output {
-    self.call()
+// variables
+//
+// Swizzling a variable without selecting a component twice yields a variable, so that it can be assigned to via
+// [`Scope::set`]; other swizzles yield expressions.
+impl<T, U, M> Swizzlable<M> for Var<T>
+where
+  M: swizzle::SwizzleMask,
+  Expr<T>: Swizzlable<M, Output = Expr<U>>,
+{
+  type Output = <M::Repetition as swizzle::Repetition>::Swizzled<U>;
+
+  fn swizzle(&self, mask: M) -> Self::Output {
+    <M::Repetition as swizzle::Repetition>::swizzled(self.0.swizzle(mask))
   }
 }
 
-#[cfg(feature = "fun-call")]
-impl<R> Fn<()> for FunHandle<Expr<R>, ()> {
-  extern "rust-call" fn call(&self, _: ()) -> Self::Output {
-    self.call()
+/// Expressions having a `x`, `r` or `s` coordinate.
+///
+/// Akin to swizzling with `.x`, `.r` or `.s`, but easier.
+pub trait HasX {
+  type Output;
+
+  fn x(&self) -> Self::Output;
+  fn r(&self) -> Self::Output {
+    self.x()
+  }
+  fn s(&self) -> Self::Output {
+    self.x()
   }
 }
 
-impl<R, A> FunHandle<Expr<R>, Expr<A>> {
-  /// Create an expression representing a function call to this function.
-  ///
-  /// See the documentation of [`FunHandle`] for examples.
-  pub fn call(&self, a: Expr<A>) -> Expr<R> {
-    Expr::new(ErasedExpr::FunCall(self.erased.clone(), vec![a.erased]))
+/// Expressions having a `y`, `g` or `t` coordinate.
+///
+/// Akin to swizzling with `.y`, `.g` or `.t`, but easier.
+pub trait HasY {
+  type Output;
+
+  fn y(&self) -> Self::Output;
+  fn g(&self) -> Self::Output {
+    self.y()
+  }
+  fn t(&self) -> Self::Output {
+    self.y()
   }
 }
 
-#[cfg(feature = "fun-call")]
-impl<R, A> FnOnce<(Expr<A>,)> for FunHandle<Expr<R>, Expr<A>> {
-  type Output = Expr<R>;
+/// Expressions having a `z`, `b` or `p` coordinate.
+///
+/// Akin to swizzling with `.z`, `.b` or `.p`, but easier.
+pub trait HasZ {
+  type Output;
 
-  extern "rust-call" fn call_once(self, a: (Expr<A>,)) -> Self::Output {
-    self.call(a.0)
+  fn z(&self) -> Self::Output;
+  fn b(&self) -> Self::Output {
+    self.z()
+  }
+  fn p(&self) -> Self::Output {
+    self.z()
   }
 }
 
-#[cfg(feature = "fun-call")]
-impl<R, A> FnMut<(Expr<A>,)> for FunHandle<Expr<R>, Expr<A>> {
-  extern "rust-call" fn call_mut(&mut self, a: (Expr<A>,)) -> Self::Output {
-    self.call(a.0)
+/// Expressions having a `w`, `a` or `q` coordinate.
+///
+/// Akin to swizzling with `.w`, `.a` or `.q`, but easier.
+pub trait HasW {
+  type Output;
+
+  fn w(&self) -> Self::Output;
+  fn a(&self) -> Self::Output {
+    self.w()
+  }
+  fn q(&self) -> Self::Output {
+    self.w()
   }
 }
 
-#[cfg(feature = "fun-call")]
-impl<R, A> Fn<(Expr<A>,)> for FunHandle<Expr<R>, Expr<A>> {
-  extern "rust-call" fn call(&self, a: (Expr<A>,)) -> Self::Output {
-    self.call(a.0)
-  }
-}
+macro_rules! impl_has_k {
+  ($trait:ident, $name:ident, $selector:ident, $t:ident) => {
+    impl<T> $trait for Expr<$t<T>> {
+      type Output = Expr<T>;
+
+      fn $name(&self) -> Self::Output {
+        self.swizzle(SwizzleSelector::$selector)
+      }
+    }
+  };
+}
+
+impl_has_k!(HasX, x, X, V2);
+impl_has_k!(HasX, x, X, V3);
+impl_has_k!(HasX, x, X, V4);
+
+impl_has_k!(HasY, y, Y, V2);
+impl_has_k!(HasY, y, Y, V3);
+impl_has_k!(HasY, y, Y, V4);
+
+impl_has_k!(HasZ, z, Z, V3);
+impl_has_k!(HasZ, z, Z, V4);
+
+impl_has_k!(HasW, w, W, V4);
+
+/// Swizzle macro.
+///
+/// This macro allows to swizzle expressions to yield expressions reorganizing the vector attributes. For instance,
+/// `sw!(color, .r.g.b.r)` will take a 4D color and will output a 4D color for which the alpha channel is overridden
+/// with the red channel.
+///
+/// Channels can be selected with the `xyzw` (positions), `rgba` (colors) and `stpq` (texture coordinates) sets.
+///
+/// The current syntax allows to extract and construct from a lot of types. Have a look at [`Swizzlable`] for a
+/// comprehensive list of what you can do.
+#[macro_export]
+macro_rules! sw {
+  ($e:expr, . $a:tt) => {
+    $e.swizzle($crate::sw_extract!($a))
+  };
+
+  ($e:expr, . $a:tt . $b:tt) => {
+    $e.swizzle(($crate::sw_extract!($a), $crate::sw_extract!($b)))
+  };
+
+  ($e:expr, . $a:tt . $b:tt . $c:tt) => {
+    $e.swizzle((
+      $crate::sw_extract!($a),
+      $crate::sw_extract!($b),
+      $crate::sw_extract!($c),
+    ))
+  };
+
+  ($e:expr, . $a:tt . $b:tt . $c:tt . $d:tt) => {
+    $e.swizzle((
+      $crate::sw_extract!($a),
+      $crate::sw_extract!($b),
+      $crate::sw_extract!($c),
+      $crate::sw_extract!($d),
+    ))
+  };
+}
+
+/// Swizzle mask macro.
+///
+/// This macro builds the selectors of a swizzle, without applying them to an expression. For instance, `sw_mask!(.x.z)`
+/// selects the first and third channels. It is mainly used to assign to some components of a variable with
+/// [`Scope::set_sw`].
+#[macro_export]
+macro_rules! sw_mask {
+  (. $a:tt) => {
+    $crate::sw_extract!($a)
+  };
+
+  (. $a:tt . $b:tt) => {
+    ($crate::sw_extract!($a), $crate::sw_extract!($b))
+  };
+
+  (. $a:tt . $b:tt . $c:tt) => {
+    (
+      $crate::sw_extract!($a),
+      $crate::sw_extract!($b),
+      $crate::sw_extract!($c),
+    )
+  };
+
+  (. $a:tt . $b:tt . $c:tt . $d:tt) => {
+    (
+      $crate::sw_extract!($a),
+      $crate::sw_extract!($b),
+      $crate::sw_extract!($c),
+      $crate::sw_extract!($d),
+    )
+  };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! sw_extract {
+  (x) => {
+    $crate::swizzle::X
+  };
+
+  (r) => {
+    $crate::swizzle::X
+  };
+
+  (y) => {
+    $crate::swizzle::Y
+  };
 
-// the first stage must be named S0
-macro_rules! impl_FunCall {
-  ( $( ( $arg_name:ident, $arg_ty:ident ) ),*) => {
-    impl<R, $($arg_ty),*> FunHandle<Expr<R>, ($(Expr<$arg_ty>),*)>
-    {
-      /// Create an expression representing a function call to this function.
-      ///
-      /// See the documentation of [`FunHandle`] for examples.
-      pub fn call(&self, $($arg_name : Expr<$arg_ty>),*) -> Expr<R> {
-        Expr::new(ErasedExpr::FunCall(self.erased.clone(), vec![$($arg_name.erased),*]))
-      }
-    }
+  (g) => {
+    $crate::swizzle::Y
+  };
 
-    #[cfg(feature = "fun-call")]
-    impl<R, $($arg_ty),*> FnOnce<($(Expr<$arg_ty>),*)> for FunHandle<Expr<R>, ($(Expr<$arg_ty>),*)>
-    {
-      type Output = Expr<R>;
+  (z) => {
+    $crate::swizzle::Z
+  };
 
-      extern "rust-call" fn call_once(self, ($($arg_name),*): ($(Expr<$arg_ty>),*)) -> Self::Output {
-        self.call($($arg_name),*)
-      }
-    }
+  (b) => {
+    $crate::swizzle::Z
+  };
 
-    #[cfg(feature = "fun-call")]
-    impl<R, $($arg_ty),*> FnMut<($(Expr<$arg_ty>),*)> for FunHandle<Expr<R>, ($(Expr<$arg_ty>),*)>
-    {
-      extern "rust-call" fn call_mut(&mut self, ($($arg_name),*): ($(Expr<$arg_ty>),*)) -> Self::Output {
-        self.call($($arg_name),*)
-      }
-    }
+  (w) => {
+    $crate::swizzle::W
+  };
 
-    #[cfg(feature = "fun-call")]
-    impl<R, $($arg_ty),*> Fn<($(Expr<$arg_ty>),*)> for FunHandle<Expr<R>, ($(Expr<$arg_ty>),*)>
-    {
-      extern "rust-call" fn call(&self, ($($arg_name),*): ($(Expr<$arg_ty>),*)) -> Self::Output {
-        self.call($($arg_name),*)
-      }
-    }
+  (a) => {
+    $crate::swizzle::W
   };
-}
 
-// implement function calls for Expr up to 16 arguments
-macro_rules! impl_FunCall_rec {
-  ( ( $a:ident, $b:ident ) , ( $x:ident, $y:ident )) => {
-    impl_FunCall!(($a, $b), ($x, $y));
+  (s) => {
+    $crate::swizzle::X
   };
 
-  ( ( $a:ident, $b:ident ) , ( $x: ident, $y: ident ) , $($r:tt)* ) => {
-    impl_FunCall_rec!(($a, $b), $($r)*);
-    impl_FunCall!(($a, $b), ($x, $y), $($r)*);
+  (t) => {
+    $crate::swizzle::Y
+  };
+
+  (p) => {
+    $crate::swizzle::Z
+  };
+
+  (q) => {
+    $crate::swizzle::W
   };
 }
-impl_FunCall_rec!(
-  (a, A),
-  (b, B),
-  (c, C),
-  (d, D),
-  (e, E),
-  (f, F),
-  (g, G),
-  (h, H),
-  (i, I),
-  (j, J),
-  (k, K),
-  (l, L),
-  (m, M),
-  (n, N),
-  (o, O),
-  (p, P)
-);
 
-/// Erased function handle.
-#[derive(Clone, Debug, PartialEq)]
-enum ErasedFunHandle {
-  // cast operators
-  Vec2,
-  Vec3,
-  Vec4,
-  // trigonometry
-  Radians,
-  Degrees,
-  Sin,
-  Cos,
-  Tan,
-  ASin,
-  ACos,
-  ATan,
-  SinH,
-  CosH,
-  TanH,
-  ASinH,
-  ACosH,
-  ATanH,
-  // exponential
-  Pow,
-  Exp,
-  Exp2,
-  Log,
-  Log2,
-  Sqrt,
-  InverseSqrt,
-  // common
-  Abs,
-  Sign,
-  Floor,
-  Trunc,
-  Round,
-  RoundEven,
-  Ceil,
-  Fract,
-  Min,
-  Max,
-  Clamp,
-  Mix,
-  Step,
-  SmoothStep,
-  IsNan,
-  IsInf,
-  FloatBitsToInt,
-  IntBitsToFloat,
-  UIntBitsToFloat,
-  FMA,
-  Frexp,
-  Ldexp,
-  // floating-point pack and unpack functions
-  PackUnorm2x16,
-  PackSnorm2x16,
-  PackUnorm4x8,
-  PackSnorm4x8,
-  UnpackUnorm2x16,
-  UnpackSnorm2x16,
-  UnpackUnorm4x8,
-  UnpackSnorm4x8,
-  PackHalf2x16,
-  UnpackHalf2x16,
-  // geometry functions
-  Length,
-  Distance,
-  Dot,
-  Cross,
-  Normalize,
-  FaceForward,
-  Reflect,
-  Refract,
-  // matrix functions
-  // TODO
-  // vector relational functions
-  VLt,
-  VLte,
-  VGt,
-  VGte,
-  VEq,
-  VNeq,
-  VAny,
-  VAll,
-  VNot,
-  // integer functions
-  UAddCarry,
-  USubBorrow,
-  UMulExtended,
-  IMulExtended,
-  BitfieldExtract,
-  BitfieldInsert,
-  BitfieldReverse,
-  BitCount,
-  FindLSB,
-  FindMSB,
-  // texture functions
-  // TODO
-  // geometry shader functions
-  EmitStreamVertex,
-  EndStreamPrimitive,
-  EmitVertex,
-  EndPrimitive,
-  // fragment processing functions
-  DFDX,
-  DFDY,
-  DFDXFine,
-  DFDYFine,
-  DFDXCoarse,
-  DFDYCoarse,
-  FWidth,
-  FWidthFine,
-  FWidthCoarse,
-  InterpolateAtCentroid,
-  InterpolateAtSample,
-  InterpolateAtOffset,
-  // shader invocation control functions
-  Barrier,
-  MemoryBarrier,
-  MemoryBarrierAtomic,
-  MemoryBarrierBuffer,
-  MemoryBarrierShared,
-  MemoryBarrierImage,
-  GroupMemoryBarrier,
-  // shader invocation group functions
-  AnyInvocation,
-  AllInvocations,
-  AllInvocationsEqual,
-  UserDefined(u16),
+// Swizzle shortcuts
+pub trait V2Swizzlable<T> {
+  fn xyx(&self) -> Expr<V3<T>>;
+  fn yxx(&self) -> Expr<V3<T>>;
+  fn xxy(&self) -> Expr<V3<T>>;
 }
 
-/// A function definition.
-///
-/// Function definitions contain the information required to know how to represent a function’s arguments, return type
-/// and its body.
-#[derive(Debug)]
-pub struct FunDef<R, A> {
-  erased: ErasedFun,
-  _phantom: PhantomData<(R, A)>,
+pub trait V3Swizzlable<T> {
+  fn xy(&self) -> Expr<V2<T>>;
+  fn yz(&self) -> Expr<V2<T>>;
+  fn zx(&self) -> Expr<V2<T>>;
+  fn zy(&self) -> Expr<V2<T>>;
 }
 
-impl<R, A> FunDef<R, A> {
-  fn new(erased: ErasedFun) -> Self {
-    Self {
-      erased,
-      _phantom: PhantomData,
-    }
+pub trait V4Swizzlable<T> {
+  fn xy(&self) -> Expr<V2<T>>;
+  fn xyz(&self) -> Expr<V3<T>>;
+}
+
+impl<T> V4Swizzlable<T> for Expr<V4<T>> {
+  fn xy(&self) -> Expr<V2<T>> {
+    self.swizzle([SwizzleSelector::X, SwizzleSelector::Y])
+  }
+
+  fn xyz(&self) -> Expr<V3<T>> {
+    self.swizzle([SwizzleSelector::X, SwizzleSelector::Y, SwizzleSelector::Z])
   }
 }
 
-/// Erased function definition.
-#[derive(Debug)]
-struct ErasedFun {
-  args: Vec<Type>,
-  scope: ErasedScope,
-  ret: ErasedReturn,
+impl<T> V3Swizzlable<T> for Expr<V3<T>> {
+  fn xy(&self) -> Expr<V2<T>> {
+    self.swizzle([SwizzleSelector::X, SwizzleSelector::Y])
+  }
+
+  fn yz(&self) -> Expr<V2<T>> {
+    self.swizzle([SwizzleSelector::Y, SwizzleSelector::Z])
+  }
+
+  fn zx(&self) -> Expr<V2<T>> {
+    self.swizzle([SwizzleSelector::Z, SwizzleSelector::X])
+  }
+
+  fn zy(&self) -> Expr<V2<T>> {
+    self.swizzle([SwizzleSelector::Z, SwizzleSelector::Y])
+  }
 }
 
-impl ErasedFun {
-  fn new(args: Vec<Type>, scope: ErasedScope, ret: ErasedReturn) -> Self {
-    Self { args, scope, ret }
+impl<T> V2Swizzlable<T> for Expr<V2<T>> {
+  fn xyx(&self) -> Expr<V3<T>> {
+    self.swizzle([SwizzleSelector::X, SwizzleSelector::Y, SwizzleSelector::X])
+  }
+  fn yxx(&self) -> Expr<V3<T>> {
+    self.swizzle([SwizzleSelector::Y, SwizzleSelector::X, SwizzleSelector::X])
+  }
+  fn xxy(&self) -> Expr<V3<T>> {
+    self.swizzle([SwizzleSelector::X, SwizzleSelector::X, SwizzleSelector::Y])
   }
 }
 
-/// Lexical scope that must output a `R`.
+/// Input declaration.
 ///
-/// Scopes are the only way to add control flow expressions to shaders. [`Scope<R>`] is the most general one, parent
-/// of all scopes. Depending on the kind of control flow, several kind of scopes are possible:
+/// # Examples
 ///
-/// - [`Scope<R>`] is the most general one and every scopes share its features.
-/// - [`EscapeScope<R>`] is a special kind of [`Scope<R>`] that allows escaping from anywhere in the scope.
-/// - [`LoopScope<R>`] is a special kind of [`EscapeScope<R>`] that also allows to escape local looping expressions,
-///   such as `for` and `while` loops.
+/// ```
+/// use shades::{Scope, ShaderBuilder, Swizzlable, V3, V4, inputs, sw};
 ///
-/// A [`Scope<R>`] allows to perform a bunch of actions:
+/// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+///   inputs!(s,
+///     position: V3<f32>,
+///     color: V4<f32>
+///   );
 ///
-/// - Creating variable via [`Scope::var`]. Expressions of type [`Expr<T>`] where [`T: ToType`](ToType) are bound in a
-///   [`Scope<R>`] via [`Scope::var`] and a [`Var<T>`] is returned, representing the bound variable.
-/// - Variable mutation via [`Scope::set`]. Any [`Var<T>`] declared previously and still reachable in the current [`Scope`]
-///   can be mutated.
-/// - Introducing conditional statements with [`Scope::when`] and [`Scope::unless`].
-/// - Introducing looping statements with [`Scope::loop_for`] and [`Scope::loop_while`].
-#[derive(Debug)]
-pub struct Scope<R> {
-  erased: ErasedScope,
-  _phantom: PhantomData<R>,
+///   s.main_fun(|s: &mut Scope<()>| {
+///     let rgb = sw!(color, .r.g.b);
+///   })
+/// });
+/// ```
+#[macro_export]
+macro_rules! inputs {
+  ($s:ident, $( $name:ident : $t:ty ),+) => {
+    $(
+      let $name = unsafe { $s.input::<$t>(stringify!($name)) };
+    )+
+  }
 }
 
-impl<R> Scope<R>
-where
-  Return: From<R>,
-{
-  /// Create a new [`Scope<R>`] for which the ID is explicitly passed.
-  ///
-  /// The ID is unique in the scope hierarchy, but is not necessarily unique in the parent scope. What it means is that
-  /// creating a scope `s` in a (parent) scope of ID `p` will give `s` the ID `p + 1`. So any scope created directly
-  /// under the scope of ID `p` will get the `p + 1` ID. The reason for this is that variables go out of scope at the
-  /// end of the scope they were created in, so it’s safe to reuse the same ID for sibling scopes, as they can’t share
-  /// variables.
-  fn new(id: u16) -> Self {
-    Self {
-      erased: ErasedScope::new(id),
-      _phantom: PhantomData,
-    }
+/// Output declaration.
+///
+/// # Examples
+///
+/// ```
+/// use shades::{Scope, ShaderBuilder, V3, lit, outputs};
+///
+/// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+///   outputs!(s,
+///     position: V3<f32>
+///   );
+///
+///   s.main_fun(|s: &mut Scope<()>| {
+///     s.set(&position, lit!(0., 0., 0.));
+///   })
+/// });
+/// ```
+#[macro_export]
+macro_rules! outputs {
+  ($s:ident, $( $name:ident : $t:ty ),+) => {
+    $(
+      let $name = unsafe { $s.output::<$t>(stringify!($name)) };
+    )+
   }
+}
 
-  /// Create a new fresh scope under the current scope.
-  fn deeper(&self) -> Self {
-    Scope::new(self.erased.id + 1)
+/// Uniform declaration.
+///
+/// # Examples
+///
+/// ```
+/// use shades::{Scope, ShaderBuilder, V3, uniforms, vec4};
+///
+/// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+///   uniforms!(s,
+///     time: f32
+///   );
+///
+///   s.main_fun(|s: &mut Scope<()>| {
+///     s.set(vertex.position, vec4!(time, 0., 0., 1.));
+///   })
+/// });
+/// ```
+#[macro_export]
+macro_rules! uniforms {
+  ($s:ident, $( $name:ident : $t:ty ),+) => {
+    $(
+      let $name = unsafe { $s.uniform::<$t>(stringify!($name)) };
+    )+
   }
+}
 
-  /// Bind an expression to a variable in the current scope.
-  ///
-  /// `let v = s.var(e);` binds the `e` expression to `v` in the `s` [`Scope<T>`], and `e` must have type [`Expr<T>`]
-  /// and `v` must be a [`Var<T>`], with [`T: ToType`](ToType).
-  ///
-  /// # Return
-  ///
-  /// The resulting [`Var<T>`] contains the representation of the binding in the EDSL and the actual binding is
-  /// recorded in the current scope.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// #   s.main_fun(|s: &mut Scope<()>| {
-  /// let v = s.var(3.1415); // assign the literal 3.1415 to v
-  /// let q = s.var(v * 2.); // assign v * 2. to q
-  /// #   })
-  /// # });
-  /// ```
-  pub fn var<T>(&mut self, init_value: impl Into<Expr<T>>) -> Var<T>
-  where
-    T: ToType,
-  {
-    let n = self.erased.next_var;
-    let handle = ScopedHandle::fun_var(self.erased.id, n);
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum BuiltIn {
+  Vertex(VertexBuiltIn),
+  TessCtrl(TessCtrlBuiltIn),
+  TessEval(TessEvalBuiltIn),
+  Geometry(GeometryBuiltIn),
+  Fragment(FragmentBuiltIn),
+  Compute(ComputeBuiltIn),
+  Subgroup(SubgroupBuiltIn),
+}
 
-    self.erased.next_var += 1;
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum VertexBuiltIn {
+  VertexID,
+  InstanceID,
+  BaseVertex,
+  BaseInstance,
+  Position,
+  PointSize,
+  ClipDistance,
+}
 
-    self.erased.instructions.push(ScopeInstr::VarDecl {
-      ty: T::ty(),
-      handle: handle.clone(),
-      init_value: init_value.into().erased,
-    });
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum TessCtrlBuiltIn {
+  MaxPatchVerticesIn,
+  PatchVerticesIn,
+  PrimitiveID,
+  InvocationID,
+  TessellationLevelOuter,
+  TessellationLevelInner,
+  In,
+  Out,
+  Position,
+  PointSize,
+  ClipDistance,
+  CullDistance,
+}
 
-    Var::new(handle)
-  }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum TessEvalBuiltIn {
+  TessCoord,
+  MaxPatchVerticesIn,
+  PatchVerticesIn,
+  PrimitiveID,
+  TessellationLevelOuter,
+  TessellationLevelInner,
+  In,
+  Out,
+  Position,
+  PointSize,
+  ClipDistance,
+  CullDistance,
+}
 
-  /// For looping statement — `for`.
-  ///
-  /// `s.loop_for(i, |i| /* cond */, |i| /* fold */, |i| /* body */ )` inserts a looping statement into the EDSL
-  /// representing a typical “for” loop. `i` is an [`Expr<T>`] satisfying [`T: ToType`](ToType) and is used as
-  /// _initial_ value.
-  ///
-  /// In all the following closures, `i` refers to the initial value.
-  ///
-  /// The first `cond` closure must return an [`Expr<bool>`], representing the condition that is held until the loop
-  /// exits. The second `fold` closure is a pure computation that must return an [`Expr<T>`] and that will be evaluated
-  /// at the end of each iteration before the next check on `cond`. The last and third `body` closure is the body of the
-  /// loop.
-  ///
-  /// The behaviors of the first two closures is important to understand. Those are akin to _filtering_ and _folding_.
-  /// The closure returning the [`Expr<bool>`] is given the [`Expr<T>`] at each iteration and the second closure creates
-  /// the new [`Expr<T>`] for the next iteration. Normally, people are used to write this pattern as `i++`, for
-  /// instance, but in the case of our EDSL, it is more akin go `i + 1`, and this value is affected to a local
-  /// accumulator hidden from the user.
-  ///
-  /// The [`LoopScope<R>`] argument to the `body` closure is a specialization of [`Scope<R>`] that allows breaking out
-  /// of loops.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// use shades::{CanEscape as _, LoopScope, Scope, ShaderBuilder};
-  ///
-  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  ///   s.main_fun(|s: &mut Scope<()>| {
-  ///     s.loop_for(0, |i| i.lt(10), |i| i + 1, |s: &mut LoopScope<()>, i| {
-  ///       s.when(i.eq(5), |s: &mut LoopScope<()>| {
-  ///         // when i == 5, abort from the main function
-  ///         s.abort();
-  ///       });
-  ///     });
-  ///   })
-  /// });
-  /// ```
-  pub fn loop_for<T>(
-    &mut self,
-    init_value: impl Into<Expr<T>>,
-    condition: impl FnOnce(&Expr<T>) -> Expr<bool>,
-    // iter_fold: impl FnOnce(&Expr<T>) -> Expr<T>,
-    body: impl FnOnce(&mut LoopScope<R>, &Expr<T>),
-  ) where
-    T: ToType,
-  {
-    let mut scope = LoopScope::new(self.deeper());
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum GeometryBuiltIn {
+  In,
+  Out,
+  Position,
+  PointSize,
+  ClipDistance,
+  CullDistance,
+  PrimitiveID,
+  PrimitiveIDIn,
+  InvocationID,
+  Layer,
+  ViewportIndex,
+}
 
-    let init_expr: Expr<T> = init_value.into();
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum FragmentBuiltIn {
+  FragCoord,
+  FrontFacing,
+  PointCoord,
+  SampleID,
+  SamplePosition,
+  SampleMaskIn,
+  ClipDistance,
+  CullDistance,
+  PrimitiveID,
+  Layer,
+  ViewportIndex,
+  FragDepth,
+  SampleMask,
+  HelperInvocation,
+}
 
-    let init_var = Var::new(ScopedHandle::fun_var(scope.erased.id, 0));
-    scope.erased.next_var += 1;
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ComputeBuiltIn {
+  NumWorkGroups,
+  WorkGroupID,
+  LocalInvocationID,
+  GlobalInvocationID,
+  LocalInvocationIndex,
+}
 
-    let condition = condition(&init_var);
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum SubgroupBuiltIn {
+  SubgroupSize,
+  SubgroupInvocationID,
+}
 
-    // generate the “post expr”, which is basically the free from of the third part of the for loop; people usually
-    // set this to ++i, i++, etc., but in our case, the expression is to treat as a fold’s accumulator
-    // let post_expr = iter_fold(&init_var);
+/// Vertex shader environment.
+#[derive(Debug)]
+pub struct VertexShaderEnv {
+  // inputs
+  /// ID of the current vertex.
+  pub vertex_id: Expr<i32>,
 
-    body(&mut scope, &init_var);
+  /// Instance ID of the current vertex.
+  pub instance_id: Expr<i32>,
 
-    let mut scope = Scope::from(scope);
+  /// Base vertex offset.
+  pub base_vertex: Expr<i32>,
 
-    self.erased.instructions.push(ScopeInstr::For {
-      init_ty: T::ty(),
-      init_handle: ScopedHandle::fun_var(scope.erased.id, 0),
-      init_expr: init_expr.erased,
-      condition: condition.erased,
-      // post_expr: post_expr.erased,
-      scope: scope.erased,
-    });
-  }
+  /// Base instance vertex offset.
+  pub base_instance: Expr<i32>,
 
-  /// While looping statement — `while`.
-  ///
-  /// `s.loop_while(cond, body)` inserts a looping statement into the EDSL representing a typical “while” loop.
-  ///
-  /// `cond` is an [`Expr<bool>`], representing the condition that is held until the loop exits. `body` is the content
-  /// the loop will execute at each iteration.
-  ///
-  /// The [`LoopScope<R>`] argument to the `body` closure is a specialization of [`Scope<R>`] that allows breaking out
-  /// of loops.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// use shades::{CanEscape as _, LoopScope, Scope, ShaderBuilder};
-  ///
-  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  ///   s.main_fun(|s: &mut Scope<()>| {
-  ///     let i = s.var(10);
-  ///
-  ///     s.loop_while(i.lt(10), |s| {
-  ///       s.set(&i, &i + 1);
-  ///     });
-  ///   })
-  /// });
-  /// ```
-  pub fn loop_while(
-    &mut self,
-    condition: impl Into<Expr<bool>>,
-    body: impl FnOnce(&mut LoopScope<R>),
-  ) {
-    let mut scope = LoopScope::new(self.deeper());
-    body(&mut scope);
+  // outputs
+  /// 4D position of the vertex.
+  pub position: Var<V4<f32>>,
 
-    self.erased.instructions.push(ScopeInstr::While {
-      condition: condition.into().erased,
-      scope: Scope::from(scope).erased,
-    });
-  }
+  /// Point size of the vertex.
+  pub point_size: Var<f32>,
 
-  /// Mutate a variable in the current scope.
-  ///
-  /// # Examples
+  /// Clip distances to user-defined planes.
   ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// #   s.main_fun(|s: &mut Scope<()>| {
-  /// let v = s.var(1); // v = 1
-  /// s.set(&v, 10); // v = 10
-  /// #   })
-  /// # });
-  /// ```
-  pub fn set<T>(&mut self, var: impl Into<Var<T>>, value: impl Into<Expr<T>>) {
-    self.erased.instructions.push(ScopeInstr::MutateVar {
-      var: var.into().to_expr().erased,
-      expr: value.into().erased,
-    });
-  }
+  /// The size of the array is set with [`ShaderBuilder::clip_distances`].
+  pub clip_distance: Var<[f32]>,
 }
 
-/// A special kind of [`Scope`] that can also escape expressions out of its parent scope.
-#[derive(Debug)]
-pub struct EscapeScope<R>(Scope<R>);
+impl VertexShaderEnv {
+  fn new() -> Self {
+    let vertex_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
+      VertexBuiltIn::VertexID,
+    )));
+    let instance_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
+      VertexBuiltIn::InstanceID,
+    )));
+    let base_vertex = Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
+      VertexBuiltIn::BaseVertex,
+    )));
+    let base_instance = Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
+      VertexBuiltIn::BaseInstance,
+    )));
+    let position = Var(Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
+      VertexBuiltIn::Position,
+    ))));
+    let point_size = Var(Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
+      VertexBuiltIn::PointSize,
+    ))));
+    let clip_distance = Var(Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
+      VertexBuiltIn::ClipDistance,
+    ))));
 
-impl<R> From<EscapeScope<R>> for Scope<R> {
-  fn from(s: EscapeScope<R>) -> Self {
-    s.0
+    Self {
+      vertex_id,
+      instance_id,
+      base_vertex,
+      base_instance,
+      position,
+      point_size,
+      clip_distance,
+    }
   }
 }
 
-impl<R> Deref for EscapeScope<R> {
-  type Target = Scope<R>;
+/// Tessellation control shader environment.
+#[derive(Debug)]
+pub struct TessCtrlShaderEnv {
+  // inputs
+  /// Maximum number of vertices per patch.
+  pub max_patch_vertices_in: Expr<i32>,
 
-  fn deref(&self) -> &Self::Target {
-    &self.0
-  }
+  /// Number of vertices for the current patch.
+  pub patch_vertices_in: Expr<i32>,
+
+  /// ID of the current primitive.
+  pub primitive_id: Expr<i32>,
+
+  /// ID of the current tessellation control shader invocation.
+  pub invocation_id: Expr<i32>,
+
+  /// Array of per-vertex input expressions.
+  pub input: Expr<[TessControlPerVertexIn]>,
+
+  // outputs
+  /// Outer tessellation levels.
+  pub tess_level_outer: Var<[f32; 4]>,
+
+  /// Inner tessellation levels.
+  pub tess_level_inner: Var<[f32; 2]>,
+
+  /// Array of per-vertex output variables.
+  ///
+  /// Its size is set by [`ShaderBuilder::vertices`], which returns the same array with a sized type.
+  pub output: Var<[TessControlPerVertexOut]>,
 }
 
-impl<R> DerefMut for EscapeScope<R> {
-  fn deref_mut(&mut self) -> &mut Self::Target {
-    &mut self.0
+impl TessCtrlShaderEnv {
+  fn new() -> Self {
+    let max_patch_vertices_in = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
+      TessCtrlBuiltIn::MaxPatchVerticesIn,
+    )));
+    let patch_vertices_in = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
+      TessCtrlBuiltIn::PatchVerticesIn,
+    )));
+    let primitive_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
+      TessCtrlBuiltIn::PrimitiveID,
+    )));
+    let invocation_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
+      TessCtrlBuiltIn::InvocationID,
+    )));
+    let input = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
+      TessCtrlBuiltIn::In,
+    )));
+    let tess_level_outer = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessCtrl(
+      TessCtrlBuiltIn::TessellationLevelOuter,
+    )));
+    let tess_level_inner = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessCtrl(
+      TessCtrlBuiltIn::TessellationLevelInner,
+    )));
+    let output = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessCtrl(
+      TessCtrlBuiltIn::Out,
+    )));
+
+    Self {
+      max_patch_vertices_in,
+      patch_vertices_in,
+      primitive_id,
+      invocation_id,
+      input,
+      tess_level_outer,
+      tess_level_inner,
+      output,
+    }
   }
 }
 
-impl<R> EscapeScope<R>
-where
-  Return: From<R>,
-{
-  fn new(s: Scope<R>) -> Self {
-    Self(s)
+/// Invocation control and memory barriers.
+///
+/// Those are only available in stages organizing invocations in groups — i.e. tessellation control shaders and
+/// compute shaders — so this trait is only implemented by the environments of those stages. The methods build
+/// statements, so they take the scope to emit into as argument.
+pub trait Barriers {
+  /// Synchronize all the invocations of the group — `barrier`.
+  #[track_caller]
+  fn barrier<R>(&self, scope: &mut Scope<R>) {
+    scope
+      .erased
+      .push(ScopeInstr::Call(ErasedFunHandle::Barrier, Vec::new()));
   }
 
-  /// Early-return the current function with an expression.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::ShaderBuilder;
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::{CanEscape as _, Expr, Scope};
-  ///
-  /// let _fun = s.fun(|s: &mut Scope<Expr<i32>>, arg: Expr<i32>| {
-  ///   // if arg is less than 10, early-return with 0
-  ///   s.when(arg.lt(10), |s| {
-  ///     s.leave(0);
-  ///   });
-  ///
-  ///   arg
-  /// });
-  ///
-  /// #   s.main_fun(|s: &mut Scope<()>| {
-  /// #   })
-  /// # });
-  /// ```
-  pub fn leave(&mut self, ret: impl Into<R>) {
-    self
+  /// Order all memory transactions — `memoryBarrier`.
+  #[track_caller]
+  fn memory_barrier<R>(&self, scope: &mut Scope<R>) {
+    scope
       .erased
-      .instructions
-      .push(ScopeInstr::Return(Return::from(ret.into()).erased));
+      .push(ScopeInstr::Call(ErasedFunHandle::MemoryBarrier, Vec::new()));
   }
-}
 
-impl EscapeScope<()> {
-  /// Early-abort the current function.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::ShaderBuilder;
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// use shades::{CanEscape as _, Expr, Scope};
-  ///
-  /// let _fun = s.fun(|s: &mut Scope<()>, arg: Expr<i32>| {
-  ///   // if arg is less than 10, early-return with 0
-  ///   s.when(arg.lt(10), |s| {
-  ///     s.abort();
-  ///   });
+  /// Order atomic counter memory transactions — `memoryBarrierAtomicCounter`.
+  #[track_caller]
+  fn memory_barrier_atomic<R>(&self, scope: &mut Scope<R>) {
+    scope.erased.push(ScopeInstr::Call(
+      ErasedFunHandle::MemoryBarrierAtomic,
+      Vec::new(),
+    ));
+  }
+
+  /// Order buffer variable memory transactions — `memoryBarrierBuffer`.
+  #[track_caller]
+  fn memory_barrier_buffer<R>(&self, scope: &mut Scope<R>) {
+    scope.erased.push(ScopeInstr::Call(
+      ErasedFunHandle::MemoryBarrierBuffer,
+      Vec::new(),
+    ));
+  }
+
+  /// Order image memory transactions — `memoryBarrierImage`.
+  #[track_caller]
+  fn memory_barrier_image<R>(&self, scope: &mut Scope<R>) {
+    scope.erased.push(ScopeInstr::Call(
+      ErasedFunHandle::MemoryBarrierImage,
+      Vec::new(),
+    ));
+  }
+
+  /// Order shared variable memory transactions — `memoryBarrierShared`.
   ///
-  ///   // do something else…
-  /// });
+  /// Shared variables only exist in compute shaders, so this barrier must not be used in tessellation control shaders.
+  #[track_caller]
+  fn memory_barrier_shared<R>(&self, scope: &mut Scope<R>) {
+    scope.erased.push(ScopeInstr::Call(
+      ErasedFunHandle::MemoryBarrierShared,
+      Vec::new(),
+    ));
+  }
+
+  /// Order all memory transactions, as seen by the invocations of the group only — `groupMemoryBarrier`.
   ///
-  /// #   s.main_fun(|s: &mut Scope<()>| {
-  /// #   })
-  /// # });
-  /// ```
-  pub fn abort(&mut self) {
-    self
-      .erased
-      .instructions
-      .push(ScopeInstr::Return(ErasedReturn::Void));
+  /// Like [`Barriers::memory_barrier_shared`], this barrier must not be used in tessellation control shaders.
+  #[track_caller]
+  fn group_memory_barrier<R>(&self, scope: &mut Scope<R>) {
+    scope.erased.push(ScopeInstr::Call(
+      ErasedFunHandle::GroupMemoryBarrier,
+      Vec::new(),
+    ));
   }
 }
 
-/// A special kind of [`EscapeScope`] that can also break loops.
+impl Barriers for TessCtrlShaderEnv {}
+
+/// Read-only, input tessellation control shader environment.
 #[derive(Debug)]
-pub struct LoopScope<R>(EscapeScope<R>);
+pub struct TessControlPerVertexIn;
 
-impl<R> From<LoopScope<R>> for Scope<R> {
-  fn from(s: LoopScope<R>) -> Self {
-    s.0.into()
+impl Expr<TessControlPerVertexIn> {
+  /// 4D position of the vertex.
+  pub fn position(&self) -> Expr<V4<f32>> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessCtrl(TessCtrlBuiltIn::Position)).intern(),
+    };
+
+    Expr::new(erased)
   }
-}
 
-impl<R> Deref for LoopScope<R> {
-  type Target = EscapeScope<R>;
+  pub fn point_size(&self) -> Expr<f32> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessCtrl(TessCtrlBuiltIn::PointSize)).intern(),
+    };
 
-  fn deref(&self) -> &Self::Target {
-    &self.0
+    Expr::new(erased)
   }
-}
 
-impl<R> DerefMut for LoopScope<R> {
-  fn deref_mut(&mut self) -> &mut Self::Target {
-    &mut self.0
+  pub fn clip_distance(&self) -> Expr<[f32]> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessCtrl(TessCtrlBuiltIn::ClipDistance)).intern(),
+    };
+
+    Expr::new(erased)
+  }
+
+  pub fn cull_distance(&self) -> Expr<[f32]> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessCtrl(TessCtrlBuiltIn::CullDistance)).intern(),
+    };
+
+    Expr::new(erased)
   }
 }
 
-impl<R> LoopScope<R>
-where
-  Return: From<R>,
-{
-  fn new(s: Scope<R>) -> Self {
-    Self(EscapeScope::new(s))
+/// Output tessellation control shader environment.
+#[derive(Debug)]
+pub struct TessControlPerVertexOut(());
+
+impl Expr<TessControlPerVertexOut> {
+  /// 4D position of the verte.
+  pub fn position(&self) -> Var<V4<f32>> {
+    let expr = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessCtrl(TessCtrlBuiltIn::Position)).intern(),
+    };
+
+    Var(Expr::new(expr))
   }
 
-  /// Break the current iteration of the nearest loop and continue to the next iteration.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// #   s.main_fun(|s: &mut Scope<()>| {
-  /// use shades::CanEscape as _;
-  ///
-  /// s.loop_while(true, |s| {
-  ///   s.loop_continue();
-  /// });
-  /// #   })
-  /// # });
-  /// ```
-  pub fn loop_continue(&mut self) {
-    self.erased.instructions.push(ScopeInstr::Continue);
+  /// Point size of the vertex.
+  pub fn point_size(&self) -> Var<f32> {
+    let expr = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessCtrl(TessCtrlBuiltIn::PointSize)).intern(),
+    };
+
+    Var(Expr::new(expr))
   }
 
-  /// Break the nearest loop.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// #   s.main_fun(|s: &mut Scope<()>| {
-  /// use shades::CanEscape as _;
-  ///
-  /// s.loop_while(true, |s| {
-  ///   s.loop_break();
-  /// });
-  /// #   })
-  /// # });
-  /// ```
-  pub fn loop_break(&mut self) {
-    self.erased.instructions.push(ScopeInstr::Break);
+  /// Clip distances to user-defined planes.
+  pub fn clip_distance(&self) -> Var<[f32]> {
+    let expr = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessCtrl(TessCtrlBuiltIn::ClipDistance)).intern(),
+    };
+
+    Var(Expr::new(expr))
   }
-}
 
-#[derive(Debug, PartialEq)]
-struct ErasedScope {
-  id: u16,
-  instructions: Vec<ScopeInstr>,
-  next_var: u16,
-}
+  /// Cull distances to user-defined planes.
+  pub fn cull_distance(&self) -> Var<[f32]> {
+    let expr = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessCtrl(TessCtrlBuiltIn::CullDistance)).intern(),
+    };
 
-impl ErasedScope {
-  fn new(id: u16) -> Self {
-    Self {
-      id,
-      instructions: Vec::new(),
-      next_var: 0,
-    }
+    Var(Expr::new(expr))
   }
 }
 
-/// Scopes allowing to enter conditional scopes.
-///
-/// Conditional scopes allow to break out of a function by early-return / aborting the function.
-pub trait CanEscape<R>
-where
-  Return: From<R>,
-{
-  /// Scope type inside the scope of the conditional.
-  type InnerScope;
+/// Tessellation evalution shader environm.nt
+#[derive(Debug)]
+pub struct TessEvalShaderEnv {
+  // inputs
+  /// Number of vertices in the current patch.
+  pub patch_vertices_in: Expr<i32>,
 
-  /// Conditional statement — `if`.
-  ///
-  /// `s.when(cond, |s: &mut EscapeScope<R>| { /* body */ })` inserts a conditional branch in the EDSL using the `cond`
-  /// expression as truth and the passed closure as body to run when the represented condition is `true`. The
-  /// [`EscapeScope<R>`] provides you with the possibility to escape and leave the function earlier, either by returning
-  /// an expression or by aborting the function, depending on the value of `R`: `Expr<_>` allows for early-returns and
-  /// `()` allows for aborting.
-  ///
-  /// # Return
-  ///
-  /// A [`When<R>`], authorizing the same escape rules with `R`. This object allows you to chain other conditional
-  /// statements, commonly referred to as `else if` and `else` in common languages.
-  ///
-  /// Have a look at the documentation of [`When`] for further information.
-  ///
-  /// # Examples
-  ///
-  /// Early-return:
-  ///
-  /// ```
-  /// use shades::{CanEscape as _, EscapeScope, Expr, Scope, ShaderBuilder, lit};
-  ///
-  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  ///   let f = s.fun(|s: &mut Scope<Expr<i32>>| {
-  ///     s.when(lit!(1).lt(3), |s: &mut EscapeScope<Expr<i32>>| {
-  ///       // do something in here
-  ///
-  ///       // early-return with 0; only possible if the function returns Expr<i32>
-  ///       s.leave(0);
-  ///     });
-  ///
-  ///     lit!(1)
-  ///   });
-  ///
-  ///   s.main_fun(|s: &mut Scope<()>| {
-  /// # #[cfg(feature = "fun-call")]
-  ///     let x = s.var(f());
-  ///   })
-  /// });
-  /// ```
-  ///
-  /// Aborting a function:
-  ///
-  /// ```
-  /// use shades::{CanEscape as _, EscapeScope, Scope, ShaderBuilder, lit};
-  ///
-  /// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  ///   s.main_fun(|s: &mut Scope<()>| {
-  ///     s.when(lit!(1).lt(3), |s: &mut EscapeScope<()>| {
-  ///       // do something in here
-  ///
-  ///       // break the parent function by aborting; this is possible because the return type is ()
-  ///       s.abort();
-  ///     });
-  ///   })
-  /// });
-  /// ```
-  fn when<'a>(
-    &'a mut self,
-    condition: impl Into<Expr<bool>>,
-    body: impl FnOnce(&mut Self::InnerScope),
-  ) -> When<'a, R>;
+  /// ID of the current primitive.
+  pub primitive_id: Expr<i32>,
+
+  /// Tessellation coordinates of the current vertex.
+  pub tess_coord: Expr<V3<f32>>,
+
+  /// Outer tessellation levels.
+  pub tess_level_outer: Expr<[f32; 4]>,
+
+  /// Inner tessellation levels.
+  pub tess_level_inner: Expr<[f32; 2]>,
+
+  /// Array of per-evertex expressions.
+  pub input: Expr<[TessEvaluationPerVertexIn]>,
+
+  // outputs
+  /// 4D position of the vertex.
+  pub position: Var<V4<f32>>,
+
+  /// Point size of the vertex.
+  pub point_size: Var<f32>,
+
+  /// Clip distances to user-defined planes.
+  pub clip_distance: Var<[f32]>,
 
-  /// Complement form of [`Scope::when`].
-  ///
-  /// This method does the same thing as [`Scope::when`] but applies the [`Not::not`](std::ops::Not::not) operator on
-  /// the condition first.
-  fn unless<'a>(
-    &'a mut self,
-    condition: impl Into<Expr<bool>>,
-    body: impl FnOnce(&mut Self::InnerScope),
-  ) -> When<'a, R> {
-    self.when(!condition.into(), body)
-  }
+  /// Cull distances to user-defined planes.
+  pub cull_distance: Var<[f32]>,
 }
 
-impl<R> CanEscape<R> for Scope<R>
-where
-  Return: From<R>,
-{
-  type InnerScope = EscapeScope<R>;
+impl TessEvalShaderEnv {
+  fn new() -> Self {
+    let patch_vertices_in = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
+      TessEvalBuiltIn::PatchVerticesIn,
+    )));
+    let primitive_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
+      TessEvalBuiltIn::PrimitiveID,
+    )));
+    let tess_coord = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
+      TessEvalBuiltIn::TessCoord,
+    )));
+    let tess_level_outer = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
+      TessEvalBuiltIn::TessellationLevelOuter,
+    )));
+    let tess_level_inner = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
+      TessEvalBuiltIn::TessellationLevelInner,
+    )));
+    let input = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
+      TessEvalBuiltIn::In,
+    )));
 
-  fn when<'a>(
-    &'a mut self,
-    condition: impl Into<Expr<bool>>,
-    body: impl FnOnce(&mut Self::InnerScope),
-  ) -> When<'a, R> {
-    let mut scope = EscapeScope::new(self.deeper());
-    body(&mut scope);
+    let position = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessEval(
+      TessEvalBuiltIn::Position,
+    )));
+    let point_size = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessEval(
+      TessEvalBuiltIn::PointSize,
+    )));
+    let clip_distance = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessEval(
+      TessEvalBuiltIn::ClipDistance,
+    )));
+    let cull_distance = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessEval(
+      TessEvalBuiltIn::CullDistance,
+    )));
 
-    self.erased.instructions.push(ScopeInstr::If {
-      condition: condition.into().erased,
-      scope: Scope::from(scope).erased,
-    });
+    Self {
+      patch_vertices_in,
+      primitive_id,
+      tess_coord,
+      tess_level_outer,
+      tess_level_inner,
+      input,
+      position,
+      point_size,
+      clip_distance,
+      cull_distance,
+    }
+  }
+}
 
-    When { parent_scope: self }
+impl TessEvalShaderEnv {
+  /// Declare how the patches are tessellated — e.g. `layout(triangles, fractional_odd_spacing, cw) in`.
+  ///
+  /// The primitive mode is mandatory; [`Shader::check`] reports shaders lacking it. The winding is ignored for
+  /// [`TessPrimitive::Isolines`]. Declaring the tessellation again replaces the previous declaration.
+  pub fn tessellation(
+    &self,
+    builder: &mut ShaderBuilder<stage::TessEval>,
+    primitive: TessPrimitive,
+    spacing: TessSpacing,
+    winding: TessWinding,
+  ) {
+    builder.set_layout(StageLayout::Tessellation(primitive, spacing, winding));
   }
 }
 
-impl<R> CanEscape<R> for LoopScope<R>
-where
-  Return: From<R>,
-{
-  type InnerScope = LoopScope<R>;
+/// Tessellation evaluation per-vertex expression.
+#[derive(Debug)]
+pub struct TessEvaluationPerVertexIn;
 
-  fn when<'a>(
-    &'a mut self,
-    condition: impl Into<Expr<bool>>,
-    body: impl FnOnce(&mut Self::InnerScope),
-  ) -> When<'a, R> {
-    let mut scope = LoopScope::new(self.deeper());
-    body(&mut scope);
+impl Expr<TessEvaluationPerVertexIn> {
+  /// 4D position of the vertex.
+  pub fn position(&self) -> Expr<V4<f32>> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessEval(TessEvalBuiltIn::Position)).intern(),
+    };
 
-    self.erased.instructions.push(ScopeInstr::If {
-      condition: condition.into().erased,
-      scope: Scope::from(scope).erased,
-    });
+    Expr::new(erased)
+  }
 
-    When { parent_scope: self }
+  /// Point size of the vertex.
+  pub fn point_size(&self) -> Expr<f32> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessEval(TessEvalBuiltIn::PointSize)).intern(),
+    };
+
+    Expr::new(erased)
+  }
+
+  /// Clip distances to user-defined planes.
+  pub fn clip_distance(&self) -> Expr<[f32]> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessEval(TessEvalBuiltIn::ClipDistance)).intern(),
+    };
+
+    Expr::new(erased)
+  }
+
+  /// Cull distances to user-defined planes.
+  pub fn cull_distance(&self) -> Expr<[f32]> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::TessEval(TessEvalBuiltIn::CullDistance)).intern(),
+    };
+
+    Expr::new(erased)
   }
 }
 
-/// Conditional combinator.
-///
-/// A [`When<R>`] is returned from functions such as [`CanEscape::when`] or [`CanEscape::unless`] and allows to continue
-/// chaining conditional statements, encoding the concept of `else if` and `else` in more traditional languages.
+/// Geometry shader environment.
 #[derive(Debug)]
-pub struct When<'a, R> {
-  /// The scope from which this [`When`] expression comes from.
+pub struct GeometryShaderEnv {
+  // inputs
+  /// Contains the index of the current primitive.
+  pub primitive_id_in: Expr<i32>,
+
+  /// ID of the current invocation of the geometry shader, from `0` to the number of invocations set with
+  /// [`ShaderBuilder::invocations`], excluded.
+  pub invocation_id: Expr<i32>,
+
+  /// Read-only environment for each vertices.
   ///
-  /// This will be handy if we want to chain this when with others (corresponding to `else if` and `else`, for
-  /// instance).
-  parent_scope: &'a mut Scope<R>,
+  /// [`ShaderBuilder::input_primitive`] returns the same array, sized with the number of vertices of the primitives.
+  pub input: Expr<[GeometryPerVertexIn]>,
+
+  // outputs
+  /// Output 4D vertex position.
+  pub position: Var<V4<f32>>,
+
+  /// Output vertex point size.
+  pub point_size: Var<f32>,
+
+  /// Output clip distances to user-defined planes.
+  pub clip_distance: Var<[f32]>,
+
+  /// Output cull distances to user-defined planes.
+  pub cull_distance: Var<[f32]>,
+
+  /// Primitive ID to write to in.
+  pub primitive_id: Var<i32>,
+
+  /// Layer to write to in.
+  pub layer: Var<i32>,
+
+  /// Viewport index to write to.
+  pub viewport_index: Var<i32>,
 }
 
-impl<R> When<'_, R>
-where
-  Return: From<R>,
-{
-  /// Add a conditional branch — `else if`.
-  ///
-  /// This method is often found chained after [`CanEscape::when`] and allows to add a new conditional if the previous
-  /// conditional fails (i.e. `else if`). The behavior is the same as with [`CanEscape::when`].
-  ///
-  /// # Return
-  ///
-  /// Another [`When<R>`], allowing to add more conditional branches.
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// #   s.main_fun(|s: &mut Scope<()>| {
-  /// use shades::{CanEscape as _, lit};
-  ///
-  /// let x = lit!(1);
-  ///
-  /// // you will need CanEscape in order to use when
-  /// s.when(x.lt(2), |s| {
-  ///   // do something if x < 2
-  /// }).or_else(x.lt(10), |s| {
-  ///   // do something if x < 10
-  /// });
-  /// #   })
-  /// # });
-  /// ```
-  pub fn or_else(
-    self,
-    condition: impl Into<Expr<bool>>,
-    body: impl FnOnce(&mut EscapeScope<R>),
-  ) -> Self {
-    let mut scope = EscapeScope::new(self.parent_scope.deeper());
-    body(&mut scope);
+impl GeometryShaderEnv {
+  fn new() -> Self {
+    let primitive_id_in = Expr::new(ErasedExpr::new_builtin(BuiltIn::Geometry(
+      GeometryBuiltIn::PrimitiveIDIn,
+    )));
+    let invocation_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Geometry(
+      GeometryBuiltIn::InvocationID,
+    )));
+    let input = Expr::new(ErasedExpr::new_builtin(BuiltIn::Geometry(
+      GeometryBuiltIn::In,
+    )));
 
-    self
-      .parent_scope
+    let position = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
+      GeometryBuiltIn::Position,
+    )));
+    let point_size = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
+      GeometryBuiltIn::PointSize,
+    )));
+    let clip_distance = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
+      GeometryBuiltIn::ClipDistance,
+    )));
+    let cull_distance = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
+      GeometryBuiltIn::CullDistance,
+    )));
+    let primitive_id = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
+      GeometryBuiltIn::PrimitiveID,
+    )));
+    let layer = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
+      GeometryBuiltIn::Layer,
+    )));
+    let viewport_index = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
+      GeometryBuiltIn::ViewportIndex,
+    )));
+
+    Self {
+      primitive_id_in,
+      invocation_id,
+      input,
+      position,
+      point_size,
+      clip_distance,
+      cull_distance,
+      primitive_id,
+      layer,
+      viewport_index,
+    }
+  }
+}
+
+/// Primitive functions.
+///
+/// Those build statements, so they take the scope to emit into as argument.
+impl GeometryShaderEnv {
+  /// Emit the current values of the output variables as a new vertex of the current primitive — `EmitVertex`.
+  #[track_caller]
+  pub fn emit_vertex<R>(&self, scope: &mut Scope<R>) {
+    scope
       .erased
-      .instructions
-      .push(ScopeInstr::ElseIf {
-        condition: condition.into().erased,
-        scope: Scope::from(scope).erased,
-      });
+      .push(ScopeInstr::Call(ErasedFunHandle::EmitVertex, Vec::new()));
+  }
 
-    self
+  /// Complete the current primitive and start a new one — `EndPrimitive`.
+  #[track_caller]
+  pub fn end_primitive<R>(&self, scope: &mut Scope<R>) {
+    scope
+      .erased
+      .push(ScopeInstr::Call(ErasedFunHandle::EndPrimitive, Vec::new()));
   }
 
-  /// Add a final catch-all conditional branch — `else`.
-  ///
-  /// This method is often found chained after [`CanEscape::when`] and allows to finish the chain of conditional
-  /// branches if the previous conditional fails (i.e. `else`). The behavior is the same as with [`CanEscape::when`].
-  ///
-  /// # Examples
-  ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// #   s.main_fun(|s: &mut Scope<()>| {
-  /// use shades::{CanEscape as _, lit};
-  ///
-  /// let x = lit!(1);
-  ///
-  /// // you will need CanEscape in order to use when
-  /// s.when(x.lt(2), |s| {
-  ///   // do something if x < 2
-  /// }).or(|s| {
-  ///   // do something if x >= 2
-  /// });
-  /// #   })
-  /// # });
-  /// ```
-  ///
-  /// Can chain and mix conditional but [`When::or`] cannot be anywhere else but the end of the chain:
+  /// Like [`GeometryShaderEnv::emit_vertex`], but emit to the vertex stream `stream` — `EmitStreamVertex`.
   ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// #   s.main_fun(|s: &mut Scope<()>| {
-  /// use shades::{CanEscape as _, lit};
-  ///
-  /// let x = lit!(1);
-  ///
-  /// // you will need CanEscape in order to use when
-  /// s.when(x.lt(2), |s| {
-  ///   // do something if x < 2
-  /// }).or_else(x.lt(5), |s| {
-  ///   // do something if x < 5
-  /// }).or_else(x.lt(10), |s| {
-  ///   // do something if x < 10
-  /// }).or(|s| {
-  ///   // else, do this
-  /// });
-  /// #   })
-  /// # });
-  /// ```
-  pub fn or(self, body: impl FnOnce(&mut EscapeScope<R>)) {
-    let mut scope = EscapeScope::new(self.parent_scope.deeper());
-    body(&mut scope);
+  /// `stream` must be a constant expression.
+  #[track_caller]
+  pub fn emit_stream_vertex<R>(&self, scope: &mut Scope<R>, stream: impl Into<Expr<i32>>) {
+    scope.erased.push(ScopeInstr::Call(
+      ErasedFunHandle::EmitStreamVertex,
+      vec![stream.into().erased],
+    ));
+  }
 
-    self
-      .parent_scope
-      .erased
-      .instructions
-      .push(ScopeInstr::Else {
-        scope: Scope::from(scope).erased,
-      });
+  /// Like [`GeometryShaderEnv::end_primitive`], but for the vertex stream `stream` — `EndStreamPrimitive`.
+  ///
+  /// `stream` must be a constant expression.
+  #[track_caller]
+  pub fn end_stream_primitive<R>(&self, scope: &mut Scope<R>, stream: impl Into<Expr<i32>>) {
+    scope.erased.push(ScopeInstr::Call(
+      ErasedFunHandle::EndStreamPrimitive,
+      vec![stream.into().erased],
+    ));
   }
 }
 
-/// Mutable variable.
-///
-/// A [`Var<T>`] is akin to an [`Expr<T>`] that can be mutated. You can go from a [`Var<T>`] to an [`Expr<T>`] via
-/// either the [`From`] or [`Var::to_expr`] method.
-///
-/// Variables, because they allow mutations, allow to write more complicated shader functions. Also, lots of graphics
-/// pipelines’ properties are variables you will have to write to, such as [`VertexShaderEnv::position`].
+/// Read-only, input geometry shader environment.
 #[derive(Debug)]
-pub struct Var<T>(Expr<T>)
-where
-  T: ?Sized;
+pub struct GeometryPerVertexIn;
 
-impl<'a, T> From<&'a Var<T>> for Var<T>
-where
-  T: ?Sized,
-{
-  fn from(v: &'a Self) -> Self {
-    Var(v.0.clone())
+impl Expr<GeometryPerVertexIn> {
+  /// Provides 4D the position of the vertex.
+  pub fn position(&self) -> Expr<V4<f32>> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::Geometry(GeometryBuiltIn::Position)).intern(),
+    };
+
+    Expr::new(erased)
   }
-}
 
-impl<T> From<Var<T>> for Expr<T>
-where
-  T: ?Sized,
-{
-  fn from(v: Var<T>) -> Self {
-    v.0
+  /// Provides the size point of the vertex if it’s currently being rendered in point mode.
+  pub fn point_size(&self) -> Expr<f32> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::Geometry(GeometryBuiltIn::PointSize)).intern(),
+    };
+
+    Expr::new(erased)
   }
-}
 
-impl<'a, T> From<&'a Var<T>> for Expr<T>
-where
-  T: ?Sized,
-{
-  fn from(v: &'a Var<T>) -> Self {
-    v.0.clone()
+  /// Clip distances to user planes of the vertex.
+  pub fn clip_distance(&self) -> Expr<[f32]> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::Geometry(GeometryBuiltIn::ClipDistance)).intern(),
+    };
+
+    Expr::new(erased)
   }
-}
 
-impl<T> Var<T>
-where
-  T: ?Sized,
-{
-  /// Create a new [`Var<T>`] from a [`ScopedHandle`].
-  pub const fn new(handle: ScopedHandle) -> Self {
-    Self(Expr::new(ErasedExpr::Var(handle)))
+  /// Cull distances to user planes of the vertex.
+  pub fn cull_distance(&self) -> Expr<[f32]> {
+    let erased = ErasedExpr::Field {
+      object: self.erased.clone().intern(),
+      field: ErasedExpr::new_builtin(BuiltIn::Geometry(GeometryBuiltIn::CullDistance)).intern(),
+    };
+
+    Expr::new(erased)
   }
+}
 
-  /// Coerce [`Var<T>`] into [`Expr<T>`].
-  ///
-  /// Remember that doing so will move the [`Var<T>`]. `clone` it if you want to preserve the source variable.
-  ///
-  /// > Note: use this function only when necessary. Lots of functions will accept both [`Expr<T>`] and [`Var<T>`],
-  /// > performing the coercion for you automatically.
-  ///
-  /// # Return
-  ///
-  /// The expression representation of [`Var<T>`], allowing to pass the variable to functions or expressions that don’t
-  /// easily coerce it automatically to [`Expr<T>`] already.
+/// Fragment shader environment.
+///
+/// This type contains everything you have access to when writing a fragment shader.
+#[derive(Debug)]
+pub struct FragmentShaderEnv {
+  // inputs
+  /// Fragment coordinate in the framebuffer.
+  pub frag_coord: Expr<V4<f32>>,
+
+  /// Whether the fragment is front-facing.
+  pub front_facing: Expr<bool>,
+
+  /// Clip distances to user planes.
   ///
-  /// # Examples
+  /// This is an array giving the clip distances to each of the user clip planes.
+  pub clip_distance: Expr<[f32]>,
+
+  /// Cull distances to user planes.
   ///
-  /// ```
-  /// # use shades::{Scope, ShaderBuilder};
-  /// # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-  /// #   s.main_fun(|s: &mut Scope<()>| {
-  /// let v = s.var(123); // Var<i32>
-  /// let e = v.to_expr(); // Expr<i32>
-  /// #   })
-  /// # });
-  /// ```
-  pub fn to_expr(&self) -> Expr<T> {
-    self.0.clone()
+  /// This is an array giving the cull distances to each of the user clip planes.
+  pub cull_distance: Expr<[f32]>,
+
+  /// Contains the 2D coordinates of a fragment within a point primitive.
+  pub point_coord: Expr<V2<f32>>,
+
+  /// ID of the primitive being currently rendered.
+  pub primitive_id: Expr<i32>,
+
+  /// ID of the sample being currently rendered.
+  pub sample_id: Expr<i32>,
+
+  /// Sample 2D coordinates.
+  pub sample_position: Expr<V2<f32>>,
+
+  /// Contains the computed sample coverage mask for the current fragment.
+  pub sample_mask_in: Expr<i32>,
+
+  /// Layer the fragment will be written to.
+  pub layer: Expr<i32>,
+
+  /// Viewport index the fragment will be written to.
+  pub viewport_index: Expr<i32>,
+
+  /// Indicates whether we are in a helper invocation of a fragment shader.
+  pub helper_invocation: Expr<bool>,
+
+  // outputs
+  /// Depth of the fragment.
+  pub frag_depth: Var<f32>,
+
+  /// Sample mask of the fragment.
+  pub sample_mask: Var<[i32]>,
+}
+
+impl FragmentShaderEnv {
+  fn new() -> Self {
+    let frag_coord = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::FragCoord,
+    )));
+    let front_facing = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::FrontFacing,
+    )));
+    let clip_distance = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::ClipDistance,
+    )));
+    let cull_distance = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::CullDistance,
+    )));
+    let point_coord = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::PointCoord,
+    )));
+    let primitive_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::PrimitiveID,
+    )));
+    let sample_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::SampleID,
+    )));
+    let sample_position = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::SamplePosition,
+    )));
+    let sample_mask_in = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::SampleMaskIn,
+    )));
+    let layer = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::Layer,
+    )));
+    let viewport_index = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::ViewportIndex,
+    )));
+    let helper_invocation = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
+      FragmentBuiltIn::HelperInvocation,
+    )));
+
+    let frag_depth = Var::new(ScopedHandle::BuiltIn(BuiltIn::Fragment(
+      FragmentBuiltIn::FragDepth,
+    )));
+    let sample_mask = Var::new(ScopedHandle::BuiltIn(BuiltIn::Fragment(
+      FragmentBuiltIn::SampleMask,
+    )));
+
+    Self {
+      frag_coord,
+      front_facing,
+      clip_distance,
+      cull_distance,
+      point_coord,
+      primitive_id,
+      sample_id,
+      sample_position,
+      sample_mask_in,
+      layer,
+      viewport_index,
+      helper_invocation,
+      frag_depth,
+      sample_mask,
+    }
   }
 }
 
-impl<T> Var<[T]> {
-  pub fn at(&self, index: impl Into<Expr<i32>>) -> Var<T> {
-    Var(self.to_expr().at(index))
+/// Derivative functions.
+///
+/// Derivatives are only available in fragment shaders, which is why they are exposed on the fragment environment
+/// only.
+impl FragmentShaderEnv {
+  /// Partial derivative of `e` with respect to the window X coordinate — `dFdx`.
+  pub fn dfdx<T>(&self, e: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::DfDx,
+      vec![e.into().erased],
+    ))
   }
-}
 
-impl<T, const N: usize> Var<[T; N]> {
-  pub fn at(&self, index: impl Into<Expr<i32>>) -> Var<T> {
-    Var(self.to_expr().at(index))
+  /// Partial derivative of `e` with respect to the window Y coordinate — `dFdy`.
+  pub fn dfdy<T>(&self, e: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::DfDy,
+      vec![e.into().erased],
+    ))
   }
-}
-
-impl<T> ops::Deref for Var<T>
-where
-  T: ?Sized,
-{
-  type Target = Expr<T>;
 
-  fn deref(&self) -> &Self::Target {
-    &self.0
+  /// Like [`FragmentShaderEnv::dfdx`], but computed from the current fragment and its immediate neighbors — `dFdxFine`.
+  pub fn dfdx_fine<T>(&self, e: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::DfDxFine,
+      vec![e.into().erased],
+    ))
   }
-}
 
-/// Hierarchical and namespaced handle.
-///
-/// Handles live in different namespaces:
-///
-/// - The _built-in_ namespace gathers all built-ins.
-/// - The _global_ namespace gathers everything that can be declared at top-level of a shader stage — i.e. mainly
-///   constants for this namespace.
-/// - The _input_ namespace gathers inputs.
-/// - The _output_ namespace gathers outputs.
-/// - The _function argument_ namespace gives handles to function arguments, which exist only in a function body.
-/// - The _function variable_ namespace gives handles to variables defined in function bodies. This namespace is
-/// hierarchical: for each scope, a new namespace is created. The depth at which a namespace is located is referred to
-/// as its _subscope_.
-#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub enum ScopedHandle {
-  BuiltIn(BuiltIn),
-  Global(u16),
-  FunArg(u16),
-  FunVar { subscope: u16, handle: u16 },
-  Input(String),
-  Output(String),
-  Uniform(String),
-}
+  /// Like [`FragmentShaderEnv::dfdy`], but computed from the current fragment and its immediate neighbors — `dFdyFine`.
+  pub fn dfdy_fine<T>(&self, e: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::DfDyFine,
+      vec![e.into().erased],
+    ))
+  }
 
-impl ScopedHandle {
-  const fn builtin(b: BuiltIn) -> Self {
-    Self::BuiltIn(b)
+  /// Like [`FragmentShaderEnv::dfdx`], but possibly shared across several fragments — `dFdxCoarse`.
+  pub fn dfdx_coarse<T>(&self, e: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::DfDxCoarse,
+      vec![e.into().erased],
+    ))
   }
 
-  const fn global(handle: u16) -> Self {
-    Self::Global(handle)
+  /// Like [`FragmentShaderEnv::dfdy`], but possibly shared across several fragments — `dFdyCoarse`.
+  pub fn dfdy_coarse<T>(&self, e: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::DfDyCoarse,
+      vec![e.into().erased],
+    ))
   }
 
-  const fn fun_arg(handle: u16) -> Self {
-    Self::FunArg(handle)
+  /// Sum of the absolute derivatives of `e` in X and Y — `fwidth`.
+  pub fn fwidth<T>(&self, e: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::FWidth,
+      vec![e.into().erased],
+    ))
   }
 
-  const fn fun_var(subscope: u16, handle: u16) -> Self {
-    Self::FunVar { subscope, handle }
+  /// Like [`FragmentShaderEnv::fwidth`], using fine derivatives — `fwidthFine`.
+  pub fn fwidth_fine<T>(&self, e: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::FWidthFine,
+      vec![e.into().erased],
+    ))
   }
 
-  pub fn uniform(name: impl Into<String>) -> Self {
-    Self::Uniform(name.into())
+  /// Like [`FragmentShaderEnv::fwidth`], using coarse derivatives — `fwidthCoarse`.
+  pub fn fwidth_coarse<T>(&self, e: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::FWidthCoarse,
+      vec![e.into().erased],
+    ))
   }
 }
 
-#[derive(Debug, PartialEq)]
-enum ScopeInstr {
-  VarDecl {
-    ty: Type,
-    handle: ScopedHandle,
-    init_value: ErasedExpr,
-  },
+/// Interpolation functions.
+///
+/// The interpolant must be a fragment input variable, as declared with [`inputs!`](inputs); those functions are then
+/// only available in fragment shaders, which is why they are exposed on the fragment environment only.
+impl FragmentShaderEnv {
+  /// Sample `interpolant` at the centroid of the pixel — `interpolateAtCentroid`.
+  pub fn interpolate_at_centroid<T>(&self, interpolant: &Var<T>) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::InterpolateAtCentroid,
+      vec![interpolant.0.erased.clone()],
+    ))
+  }
 
-  Return(ErasedReturn),
+  /// Sample `interpolant` at the location of the sample `sample` — `interpolateAtSample`.
+  pub fn interpolate_at_sample<T>(
+    &self,
+    interpolant: &Var<T>,
+    sample: impl Into<Expr<i32>>,
+  ) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::InterpolateAtSample,
+      vec![interpolant.0.erased.clone(), sample.into().erased],
+    ))
+  }
 
-  Continue,
+  /// Sample `interpolant` at `offset` from the center of the pixel — `interpolateAtOffset`.
+  pub fn interpolate_at_offset<T>(
+    &self,
+    interpolant: &Var<T>,
+    offset: impl Into<Expr<V2<f32>>>,
+  ) -> Expr<T>
+  where
+    Expr<T>: Floating,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::InterpolateAtOffset,
+      vec![interpolant.0.erased.clone(), offset.into().erased],
+    ))
+  }
+}
 
-  Break,
+/// Compute shader environment.
+#[derive(Debug)]
+pub struct ComputeShaderEnv {
+  /// Number of work groups the shader is dispatched with.
+  pub num_work_groups: Expr<V3<u32>>,
 
-  If {
-    condition: ErasedExpr,
-    scope: ErasedScope,
-  },
+  /// ID of the work group of the current invocation, from `0` to [`ComputeShaderEnv::num_work_groups`], excluded.
+  pub work_group_id: Expr<V3<u32>>,
 
-  ElseIf {
-    condition: ErasedExpr,
-    scope: ErasedScope,
-  },
+  /// ID of the current invocation in its work group, from `0` to the [local size](ShaderBuilder::local_size),
+  /// excluded.
+  pub local_invocation_id: Expr<V3<u32>>,
 
-  Else {
-    scope: ErasedScope,
-  },
+  /// ID of the current invocation among all the invocations of the dispatch — `work_group_id * local_size +
+  /// local_invocation_id`.
+  pub global_invocation_id: Expr<V3<u32>>,
 
-  For {
-    init_ty: Type,
-    init_handle: ScopedHandle,
-    init_expr: ErasedExpr,
-    condition: ErasedExpr,
-    // post_expr: ErasedExpr,
-    scope: ErasedScope,
-  },
+  /// Flattened [`ComputeShaderEnv::local_invocation_id`] — `z * local_size.x * local_size.y + y * local_size.x + x`.
+  pub local_invocation_index: Expr<u32>,
+}
 
-  While {
-    condition: ErasedExpr,
-    scope: ErasedScope,
-  },
+impl ComputeShaderEnv {
+  fn new() -> Self {
+    let num_work_groups = Expr::new(ErasedExpr::new_builtin(BuiltIn::Compute(
+      ComputeBuiltIn::NumWorkGroups,
+    )));
+    let work_group_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Compute(
+      ComputeBuiltIn::WorkGroupID,
+    )));
+    let local_invocation_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Compute(
+      ComputeBuiltIn::LocalInvocationID,
+    )));
+    let global_invocation_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Compute(
+      ComputeBuiltIn::GlobalInvocationID,
+    )));
+    let local_invocation_index = Expr::new(ErasedExpr::new_builtin(BuiltIn::Compute(
+      ComputeBuiltIn::LocalInvocationIndex,
+    )));
 
-  MutateVar {
-    var: ErasedExpr,
-    expr: ErasedExpr,
-  },
+    Self {
+      num_work_groups,
+      work_group_id,
+      local_invocation_id,
+      global_invocation_id,
+      local_invocation_index,
+    }
+  }
 }
 
-/// Dimension of a primitive type.
-///
-/// Primitive types currently can have one of four dimension:
-///
-/// - [`Dim::Scalar`]: designates a scalar value.
-/// - [`Dim::D2`]: designates a 2D vector.
-/// - [`Dim::D3`]: designates a 3D vector.
-/// - [`Dim::D4`]: designates a 4D vector.
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub enum Dim {
-  /// Scalar value.
-  Scalar,
+impl Barriers for ComputeShaderEnv {}
 
-  /// 2D vector.
-  D2,
+// standard library
 
-  /// 3D vector.
-  D3,
+pub trait Trigonometry {
+  fn radians(&self) -> Self;
 
-  /// 4D vector.
-  D4,
-}
+  fn degrees(&self) -> Self;
 
-/// Matrix wrapper.
-///
-/// This type represents a matrix of a given dimension, deduced from the wrapped type.
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub struct Matrix<T>(T);
+  fn sin(&self) -> Self;
 
-impl<T, const M: usize, const N: usize> From<[[T; N]; M]> for Matrix<[[T; N]; M]> {
-  fn from(a: [[T; N]; M]) -> Self {
-    Matrix(a)
-  }
-}
+  fn cos(&self) -> Self;
 
-macro_rules! make_mat_ty {
-  ($t:ident, $lit:ident, $m:expr, $n:expr, $mdim:ident) => {
-    pub type $t = Matrix<[[f32; $n]; $m]>;
+  fn tan(&self) -> Self;
 
-    impl ToPrimType for Matrix<[[f32; $n]; $m]> {
-      const PRIM_TYPE: PrimType = PrimType::Matrix(MatrixDim::$mdim);
-    }
+  fn asin(&self) -> Self;
 
-    impl From<Matrix<[[f32; $n]; $m]>> for Expr<Matrix<[[f32; $n]; $m]>> {
-      fn from(matrix: Matrix<[[f32; $n]; $m]>) -> Self {
-        Self::new(ErasedExpr::$lit(matrix))
-      }
-    }
-  };
-}
+  fn acos(&self) -> Self;
 
-make_mat_ty!(M22, LitM22, 2, 2, D22);
-// make_mat_ty!(M23, LitM23, 2, 3, D23);
-// make_mat_ty!(M24, LitM24, 2, 4, D24);
-// make_mat_ty!(M32, LitM32, 3, 2, D32);
-make_mat_ty!(M33, LitM33, 3, 3, D33);
-// make_mat_ty!(M34, LitM34, 3, 4, D34);
-// make_mat_ty!(M42, LitM42, 4, 2, D42);
-// make_mat_ty!(M43, LitM43, 4, 3, D43);
-make_mat_ty!(M44, LitM44, 4, 4, D44);
+  fn atan(&self) -> Self;
 
-/// Matrix dimension.
-///
-/// Matrices can have several dimensions. Most of the time, you will be interested in squared dimensions, e.g. 2×2, 3×3
-/// and 4×4. However, other dimensions exist.
-///
-/// > Note: matrices are expressed in column-major.
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub enum MatrixDim {
-  /// Squared 2 dimension.
-  D22,
-  /// 2×3 dimension.
-  D23,
-  /// 2×4 dimension.
-  D24,
-  /// 3×2 dimension.
-  D32,
-  /// Squared 3 dimension.
-  D33,
-  /// 3×4 dimension.
-  D34,
-  /// 4×2 dimension.
-  D42,
-  /// 4×3 dimension.
-  D43,
-  /// Squared 4 dimension.
-  D44,
+  fn sinh(&self) -> Self;
+
+  fn cosh(&self) -> Self;
+
+  fn tanh(&self) -> Self;
+
+  fn asinh(&self) -> Self;
+
+  fn acosh(&self) -> Self;
+
+  fn atanh(&self) -> Self;
 }
 
-/// Type representation — akin to [`PrimType`] glued with array dimensions, if any.
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub struct Type {
-  /// Primitive type, representing a type without array dimensions.
-  prim_ty: PrimType,
+macro_rules! impl_Trigonometry {
+  ($t:ty) => {
+    impl Trigonometry for Expr<$t> {
+      fn radians(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Radians,
+          vec![self.erased.clone()],
+        ))
+      }
+
+      fn degrees(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Degrees,
+          vec![self.erased.clone()],
+        ))
+      }
+
+      fn sin(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Sin,
+          vec![self.erased.clone()],
+        ))
+      }
+
+      fn cos(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Cos,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  /// Array dimensions, if any.
-  ///
-  /// Dimensions are sorted from outer to inner; i.e. `[[i32; N]; M]`’s dimensions is encoded as `vec![M, N]`.
-  array_dims: Vec<usize>,
-}
+      fn tan(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Tan,
+          vec![self.erased.clone()],
+        ))
+      }
 
-/// Primitive supported types.
-///
-/// Types without array dimensions are known as _primitive types_ and are exhaustively constructed thanks to
-/// [`PrimType`].
-#[non_exhaustive]
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub enum PrimType {
-  /// An integral type.
-  ///
-  /// The [`Dim`] argument represents the vector dimension — do not confuse it with an array dimension.
-  Int(Dim),
+      fn asin(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::ASin,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  /// An unsigned integral type.
-  ///
-  /// The [`Dim`] argument represents the vector dimension — do not confuse it with an array dimension.
-  UInt(Dim),
+      fn acos(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::ACos,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  /// An floating type.
-  ///
-  /// The [`Dim`] argument represents the vector dimension — do not confuse it with an array dimension.
-  Float(Dim),
+      fn atan(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::ATan,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  /// A boolean type.
-  ///
-  /// The [`Dim`] argument represents the vector dimension — do not confuse it with an array dimension.
-  Bool(Dim),
+      fn sinh(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::SinH,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  /// A N×M floating matrix.
-  ///
-  /// The [`MatrixDim`] provides the information required to know the exact dimension of the matrix.
-  Matrix(MatrixDim),
-}
+      fn cosh(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::CosH,
+          vec![self.erased.clone()],
+        ))
+      }
 
-/// Class of types that are recognized by the EDSL.
-///
-/// Any type implementing this type family is _representable_ in the EDSL.
-pub trait ToPrimType {
-  /// Mapped primitive type.
-  const PRIM_TYPE: PrimType;
-}
+      fn tanh(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::TanH,
+          vec![self.erased.clone()],
+        ))
+      }
 
-macro_rules! impl_ToPrimType {
-  ($t:ty, $q:ident, $d:ident) => {
-    impl ToPrimType for $t {
-      const PRIM_TYPE: PrimType = PrimType::$q(Dim::$d);
+      fn asinh(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::ASinH,
+          vec![self.erased.clone()],
+        ))
+      }
+
+      fn acosh(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::ACosH,
+          vec![self.erased.clone()],
+        ))
+      }
+
+      fn atanh(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::ATanH,
+          vec![self.erased.clone()],
+        ))
+      }
     }
   };
 }
 
-impl_ToPrimType!(i32, Int, Scalar);
-impl_ToPrimType!(u32, UInt, Scalar);
-impl_ToPrimType!(f32, Float, Scalar);
-impl_ToPrimType!(bool, Bool, Scalar);
-impl_ToPrimType!(V2<i32>, Int, D2);
-impl_ToPrimType!(V2<u32>, UInt, D2);
-impl_ToPrimType!(V2<f32>, Float, D2);
-impl_ToPrimType!(V2<bool>, Bool, D2);
-impl_ToPrimType!(V3<i32>, Int, D3);
-impl_ToPrimType!(V3<u32>, UInt, D3);
-impl_ToPrimType!(V3<f32>, Float, D3);
-impl_ToPrimType!(V3<bool>, Bool, D3);
-impl_ToPrimType!(V4<i32>, Int, D4);
-impl_ToPrimType!(V4<u32>, UInt, D4);
-impl_ToPrimType!(V4<f32>, Float, D4);
-impl_ToPrimType!(V4<bool>, Bool, D4);
+impl_Trigonometry!(f32);
+impl_Trigonometry!(V2<f32>);
+impl_Trigonometry!(V3<f32>);
+impl_Trigonometry!(V4<f32>);
 
-/// Represent a type (primitive type and array dimension) in the EDSL.
-///
-/// Any type implementing [`ToType`] is representable in the EDSL. Any type implementing [`ToPrimType`] automatically
-/// also implements [`ToType`].
-pub trait ToType {
-  fn ty() -> Type;
-}
+pub trait Exponential: Sized {
+  fn pow(&self, p: impl Into<Self>) -> Self;
 
-impl<T> ToType for T
-where
-  T: ToPrimType,
-{
-  fn ty() -> Type {
-    Type {
-      prim_ty: T::PRIM_TYPE,
-      array_dims: Vec::new(),
-    }
-  }
-}
+  /// Raise all the components to the same power `p`.
+  ///
+  /// GLSL has no `pow` taking a vector and a scalar, so `p` is turned into a vector first: `v.pow_scalar(2.2)` is
+  /// written `pow(v, vec3(2.2))`.
+  fn pow_scalar(&self, p: impl Into<Expr<f32>>) -> Self;
 
-impl<T, const N: usize> ToType for [T; N]
-where
-  T: ToType,
-{
-  fn ty() -> Type {
-    let Type {
-      prim_ty,
-      array_dims,
-    } = T::ty();
-    let array_dims = once(N).chain(array_dims).collect();
+  fn exp(&self) -> Self;
 
-    Type {
-      prim_ty,
-      array_dims,
-    }
-  }
-}
+  fn exp2(&self) -> Self;
 
-/// Select a channel to extract from into a swizzled expession.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum SwizzleSelector {
-  /// Select the `.x` (or `.r`) channel.
-  X,
+  fn log(&self) -> Self;
 
-  /// Select the `.y` (or `.g`) channel.
-  Y,
+  fn log2(&self) -> Self;
 
-  /// Select the `.z` (or `.b`) channel.
-  Z,
+  fn sqrt(&self) -> Self;
 
-  /// Select the `.w` (or `.a`) channel.
-  W,
+  fn isqrt(&self) -> Self;
 }
 
-/// Swizzle channel selector.
-///
-/// This type gives the dimension of the target expression (output) and dimension of the source expression (input). The
-/// [`SwizzleSelector`] also to select a specific channel in the input expression.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum Swizzle {
-  /// Create a one-channel expression.
-  D1(SwizzleSelector),
+macro_rules! impl_Exponential {
+  ($t:ty) => {
+    impl Exponential for Expr<$t> {
+      fn pow(&self, p: impl Into<Self>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Pow,
+          vec![self.erased.clone(), p.into().erased],
+        ))
+      }
 
-  /// Create a two-channel expression.
-  D2(SwizzleSelector, SwizzleSelector),
+      fn pow_scalar(&self, p: impl Into<Expr<f32>>) -> Self {
+        let ty = <$t as ToType>::ty();
+        let p = p.into().erased;
+        let p = if ty == f32::ty() {
+          p
+        } else {
+          ErasedExpr::Cast(ty, p.intern())
+        };
 
-  /// Create a three-channel expression.
-  D3(SwizzleSelector, SwizzleSelector, SwizzleSelector),
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Pow,
+          vec![self.erased.clone(), p],
+        ))
+      }
 
-  /// Create a four-channel expression.
-  D4(
-    SwizzleSelector,
-    SwizzleSelector,
-    SwizzleSelector,
-    SwizzleSelector,
-  ),
-}
+      fn exp(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Exp,
+          vec![self.erased.clone()],
+        ))
+      }
 
-/// Interface to implement to swizzle an expression.
-///
-/// If you plan to use your implementor with the [`sw!`](sw) macro, `S` must be one of the following types:
-///
-/// - [`SwizzleSelector`]: to implement `sw!(.x)`.
-/// - [[`SwizzleSelector`]; 2]: to implement `sw!(.xx)`.
-/// - [[`SwizzleSelector`]; 3]: to implement `sw!(.xxx)`.
-/// - [[`SwizzleSelector`]; 4]: to implement `sw!(.xxxx)`.
-pub trait Swizzlable<S> {
-  type Output;
+      fn exp2(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Exp2,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  fn swizzle(&self, sw: S) -> Self::Output;
+      fn log(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Log,
+          vec![self.erased.clone()],
+        ))
+      }
+
+      fn log2(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Log2,
+          vec![self.erased.clone()],
+        ))
+      }
+
+      fn sqrt(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Sqrt,
+          vec![self.erased.clone()],
+        ))
+      }
+
+      fn isqrt(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::InverseSqrt,
+          vec![self.erased.clone()],
+        ))
+      }
+    }
+  };
 }
 
-// 2D
-impl<T> Swizzlable<SwizzleSelector> for Expr<V2<T>> {
-  type Output = Expr<T>;
+impl_Exponential!(f32);
+impl_Exponential!(V2<f32>);
+impl_Exponential!(V3<f32>);
+impl_Exponential!(V4<f32>);
+
+pub trait Relative {
+  fn abs(&self) -> Self;
 
-  fn swizzle(&self, x: SwizzleSelector) -> Self::Output {
-    Expr::new(ErasedExpr::Swizzle(
-      Box::new(self.erased.clone()),
-      Swizzle::D1(x),
-    ))
-  }
+  fn sign(&self) -> Self;
 }
 
-impl<T> Swizzlable<[SwizzleSelector; 2]> for Expr<V2<T>> {
-  type Output = Self;
+macro_rules! impl_Relative {
+  ($t:ty) => {
+    impl Relative for Expr<$t> {
+      fn abs(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Abs,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  fn swizzle(&self, [x, y]: [SwizzleSelector; 2]) -> Self::Output {
-    Expr::new(ErasedExpr::Swizzle(
-      Box::new(self.erased.clone()),
-      Swizzle::D2(x, y),
-    ))
-  }
+      fn sign(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Sign,
+          vec![self.erased.clone()],
+        ))
+      }
+    }
+  };
 }
 
-impl<T> Swizzlable<[SwizzleSelector; 3]> for Expr<V2<T>> {
-  type Output = Expr<V3<T>>;
+impl_Relative!(i32);
+impl_Relative!(V2<i32>);
+impl_Relative!(V3<i32>);
+impl_Relative!(V4<i32>);
+impl_Relative!(f32);
+impl_Relative!(V2<f32>);
+impl_Relative!(V3<f32>);
+impl_Relative!(V4<f32>);
 
-  fn swizzle(&self, [x, y, z]: [SwizzleSelector; 3]) -> Self::Output {
-    Expr::new(ErasedExpr::Swizzle(
-      Box::new(self.erased.clone()),
-      Swizzle::D3(x, y, z),
-    ))
-  }
-}
+pub trait Floating {
+  fn floor(&self) -> Self;
 
-// 3D
-impl<T> Swizzlable<SwizzleSelector> for Expr<V3<T>> {
-  type Output = Expr<T>;
+  fn trunc(&self) -> Self;
 
-  fn swizzle(&self, x: SwizzleSelector) -> Self::Output {
-    Expr::new(ErasedExpr::Swizzle(
-      Box::new(self.erased.clone()),
-      Swizzle::D1(x),
-    ))
-  }
-}
+  fn round(&self) -> Self;
 
-impl<T> Swizzlable<[SwizzleSelector; 2]> for Expr<V3<T>> {
-  type Output = Expr<V2<T>>;
+  fn ceil(&self) -> Self;
 
-  fn swizzle(&self, [x, y]: [SwizzleSelector; 2]) -> Self::Output {
-    Expr::new(ErasedExpr::Swizzle(
-      Box::new(self.erased.clone()),
-      Swizzle::D2(x, y),
-    ))
-  }
+  fn fract(&self) -> Self;
 }
 
-impl<T> Swizzlable<[SwizzleSelector; 3]> for Expr<V3<T>> {
-  type Output = Self;
+macro_rules! impl_Floating {
+  ($t:ty) => {
+    impl Floating for Expr<$t> {
+      fn floor(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Floor,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  fn swizzle(&self, [x, y, z]: [SwizzleSelector; 3]) -> Self::Output {
-    Expr::new(ErasedExpr::Swizzle(
-      Box::new(self.erased.clone()),
-      Swizzle::D3(x, y, z),
-    ))
-  }
-}
+      fn trunc(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Trunc,
+          vec![self.erased.clone()],
+        ))
+      }
 
-// 4D
-impl<T> Swizzlable<SwizzleSelector> for Expr<V4<T>> {
-  type Output = Expr<T>;
+      fn round(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Round,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  fn swizzle(&self, x: SwizzleSelector) -> Self::Output {
-    Expr::new(ErasedExpr::Swizzle(
-      Box::new(self.erased.clone()),
-      Swizzle::D1(x),
-    ))
-  }
+      fn ceil(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Ceil,
+          vec![self.erased.clone()],
+        ))
+      }
+
+      fn fract(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Fract,
+          vec![self.erased.clone()],
+        ))
+      }
+    }
+  };
 }
 
-impl<T> Swizzlable<[SwizzleSelector; 2]> for Expr<V4<T>> {
-  type Output = Expr<V2<T>>;
+impl_Floating!(f32);
+impl_Floating!(V2<f32>);
+impl_Floating!(V3<f32>);
+impl_Floating!(V4<f32>);
 
-  fn swizzle(&self, [x, y]: [SwizzleSelector; 2]) -> Self::Output {
-    Expr::new(ErasedExpr::Swizzle(
-      Box::new(self.erased.clone()),
-      Swizzle::D2(x, y),
-    ))
-  }
-}
+/// Modulo functions.
+///
+/// Those functions are akin to the [`Rem`](std::ops::Rem) operator (`%`), but their semantics is explicit, especially
+/// when negative operands are involved.
+pub trait Modulo: Sized {
+  /// Variable the integral part of [`Modulo::modf`] is written to.
+  type IntPart;
 
-impl<T> Swizzlable<[SwizzleSelector; 3]> for Expr<V4<T>> {
-  type Output = Expr<V3<T>>;
+  /// Floored modulo — `mod`.
+  ///
+  /// `x.mod_euclid(y)` computes `x - y * floor(x / y)`. The result takes the sign of `y`; for a positive `y`, it is
+  /// the same as [`f32::rem_euclid`].
+  fn mod_euclid(&self, y: impl Into<Self>) -> Self;
 
-  fn swizzle(&self, [x, y, z]: [SwizzleSelector; 3]) -> Self::Output {
-    Expr::new(ErasedExpr::Swizzle(
-      Box::new(self.erased.clone()),
-      Swizzle::D3(x, y, z),
-    ))
-  }
+  /// Split into fractional and integral parts — `modf`.
+  ///
+  /// The fractional part is returned and the integral part is written to `int_part`. Both parts have the same sign as
+  /// the input expression.
+  fn modf(&self, int_part: &Self::IntPart) -> Self;
 }
 
-impl<T> Swizzlable<[SwizzleSelector; 4]> for Expr<V4<T>> {
-  type Output = Self;
+macro_rules! impl_Modulo {
+  ($t:ty) => {
+    impl Modulo for Expr<$t> {
+      type IntPart = Var<$t>;
 
-  fn swizzle(&self, [x, y, z, w]: [SwizzleSelector; 4]) -> Self::Output {
-    Expr::new(ErasedExpr::Swizzle(
-      Box::new(self.erased.clone()),
-      Swizzle::D4(x, y, z, w),
-    ))
-  }
+      fn mod_euclid(&self, y: impl Into<Self>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Mod,
+          vec![self.erased.clone(), y.into().erased],
+        ))
+      }
+
+      fn modf(&self, int_part: &Self::IntPart) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Modf,
+          vec![self.erased.clone(), int_part.0.erased.clone()],
+        ))
+      }
+    }
+  };
 }
 
-/// Expressions having a `x` or `r` coordinate.
-///
-/// Akin to swizzling with `.x` or `.r`, but easier.
-pub trait HasX {
-  type Output;
+impl_Modulo!(f32);
+impl_Modulo!(V2<f32>);
+impl_Modulo!(V3<f32>);
+impl_Modulo!(V4<f32>);
 
-  fn x(&self) -> Self::Output;
-  fn r(&self) -> Self::Output {
-    self.x()
-  }
-}
+/// Integer bitfield functions.
+/// Floating-point arithmetic functions with explicit precision or exponent semantics.
+pub trait FloatingArith: Sized {
+  /// Exponent expression, with the same dimension as `Self`.
+  type ExpExpr;
 
-/// Expressions having a `y` or `g` coordinate.
-///
-/// Akin to swizzling with `.y` or `.g`, but easier.
-pub trait HasY {
-  type Output;
+  /// Variable the exponent of [`FloatingArith::frexp`] is written to.
+  type ExpVar;
 
-  fn y(&self) -> Self::Output;
-  fn g(&self) -> Self::Output {
-    self.y()
-  }
-}
+  /// Fused multiply-add — `fma`.
+  ///
+  /// `a.fma(b, c)` computes `a * b + c` as a single operation.
+  fn fma(&self, b: impl Into<Self>, c: impl Into<Self>) -> Self;
 
-/// Expressions having a `z` or `b` coordinate.
-///
-/// Akin to swizzling with `.z` or `.b`, but easier.
-pub trait HasZ {
-  type Output;
+  /// Split into significand and exponent — `frexp`.
+  ///
+  /// The significand, in the range `[0.5; 1)`, is returned and the exponent is written to `exp`, so that
+  /// `self = significand * 2^exp`.
+  fn frexp(&self, exp: &Self::ExpVar) -> Self;
 
-  fn z(&self) -> Self::Output;
-  fn b(&self) -> Self::Output {
-    self.z()
-  }
+  /// Build a floating-point number from a significand and an exponent — `ldexp`.
+  ///
+  /// `a.ldexp(exp)` computes `a * 2^exp`. This is the inverse of [`FloatingArith::frexp`].
+  fn ldexp(&self, exp: impl Into<Self::ExpExpr>) -> Self;
 }
 
-/// Expressions having a `w` or `a` coordinate.
-///
-/// Akin to swizzling with `.w` or `.a`, but easier.
-pub trait HasW {
-  type Output;
+macro_rules! impl_FloatingArith {
+  ($t:ty, $e:ty) => {
+    impl FloatingArith for Expr<$t> {
+      type ExpExpr = Expr<$e>;
 
-  fn w(&self) -> Self::Output;
-  fn a(&self) -> Self::Output {
-    self.w()
-  }
-}
+      type ExpVar = Var<$e>;
 
-macro_rules! impl_has_k {
-  ($trait:ident, $name:ident, $selector:ident, $t:ident) => {
-    impl<T> $trait for Expr<$t<T>> {
-      type Output = Expr<T>;
+      fn fma(&self, b: impl Into<Self>, c: impl Into<Self>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Fma,
+          vec![self.erased.clone(), b.into().erased, c.into().erased],
+        ))
+      }
 
-      fn $name(&self) -> Self::Output {
-        self.swizzle(SwizzleSelector::$selector)
+      fn frexp(&self, exp: &Self::ExpVar) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Frexp,
+          vec![self.erased.clone(), exp.0.erased.clone()],
+        ))
+      }
+
+      fn ldexp(&self, exp: impl Into<Self::ExpExpr>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Ldexp,
+          vec![self.erased.clone(), exp.into().erased],
+        ))
       }
     }
   };
 }
 
-impl_has_k!(HasX, x, X, V2);
-impl_has_k!(HasX, x, X, V3);
-impl_has_k!(HasX, x, X, V4);
+impl_FloatingArith!(f32, i32);
+impl_FloatingArith!(V2<f32>, V2<i32>);
+impl_FloatingArith!(V3<f32>, V3<i32>);
+impl_FloatingArith!(V4<f32>, V4<i32>);
 
-impl_has_k!(HasY, y, Y, V2);
-impl_has_k!(HasY, y, Y, V3);
-impl_has_k!(HasY, y, Y, V4);
+pub trait Bitfield: Sized {
+  /// Signed integer expression with the same dimension as `Self`, used to report bit counts and positions.
+  type BitsExpr;
 
-impl_has_k!(HasZ, z, Z, V3);
-impl_has_k!(HasZ, z, Z, V4);
+  /// Extract `bits` bits starting at `offset` — `bitfieldExtract`.
+  ///
+  /// For signed integers, the result is sign-extended.
+  fn bitfield_extract(&self, offset: impl Into<Expr<i32>>, bits: impl Into<Expr<i32>>) -> Self;
 
-impl_has_k!(HasW, w, W, V4);
+  /// Replace `bits` bits starting at `offset` with the least significant bits of `insert` — `bitfieldInsert`.
+  fn bitfield_insert(
+    &self,
+    insert: impl Into<Self>,
+    offset: impl Into<Expr<i32>>,
+    bits: impl Into<Expr<i32>>,
+  ) -> Self;
 
-/// Swizzle macro.
-///
-/// This macro allows to swizzle expressions to yield expressions reorganizing the vector attributes. For instance,
-/// `sw!(color, .rgbr)` will take a 4D color and will output a 4D color for which the alpha channel is overridden with
-/// the red channel.
-///
-/// The current syntax allows to extract and construct from a lot of types. Have a look at [`Swizzlable`] for a
-/// comprehensive list of what you can do.
-#[macro_export]
-macro_rules! sw {
-  ($e:expr, . $a:tt) => {
-    $e.swizzle($crate::sw_extract!($a))
-  };
+  /// Reverse the order of the bits — `bitfieldReverse`.
+  fn bitfield_reverse(&self) -> Self;
 
-  ($e:expr, . $a:tt . $b:tt) => {
-    $e.swizzle([$crate::sw_extract!($a), $crate::sw_extract!($b)])
-  };
+  /// Number of bits set to `1` — `bitCount`.
+  fn bit_count(&self) -> Self::BitsExpr;
 
-  ($e:expr, . $a:tt . $b:tt . $c:tt) => {
-    $e.swizzle([
-      $crate::sw_extract!($a),
-      $crate::sw_extract!($b),
-      $crate::sw_extract!($c),
-    ])
-  };
+  /// Position of the least significant bit set to `1`, or `-1` if none — `findLSB`.
+  fn find_lsb(&self) -> Self::BitsExpr;
 
-  ($e:expr, . $a:tt . $b:tt . $c:tt . $d:tt) => {
-    $e.swizzle([
-      $crate::sw_extract!($a),
-      $crate::sw_extract!($b),
-      $crate::sw_extract!($c),
-      $crate::sw_extract!($d),
-    ])
-  };
+  /// Position of the most significant bit set to `1` (or `0` for negative numbers), or `-1` if none — `findMSB`.
+  fn find_msb(&self) -> Self::BitsExpr;
 }
 
-#[doc(hidden)]
-#[macro_export]
-macro_rules! sw_extract {
-  (x) => {
-    $crate::SwizzleSelector::X
-  };
-
-  (r) => {
-    $crate::SwizzleSelector::X
-  };
+macro_rules! impl_Bitfield {
+  ($t:ty, $bits:ty) => {
+    impl Bitfield for Expr<$t> {
+      type BitsExpr = Expr<$bits>;
 
-  (y) => {
-    $crate::SwizzleSelector::Y
-  };
+      fn bitfield_extract(&self, offset: impl Into<Expr<i32>>, bits: impl Into<Expr<i32>>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::BitfieldExtract,
+          vec![
+            self.erased.clone(),
+            offset.into().erased,
+            bits.into().erased,
+          ],
+        ))
+      }
 
-  (g) => {
-    $crate::SwizzleSelector::Y
-  };
+      fn bitfield_insert(
+        &self,
+        insert: impl Into<Self>,
+        offset: impl Into<Expr<i32>>,
+        bits: impl Into<Expr<i32>>,
+      ) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::BitfieldInsert,
+          vec![
+            self.erased.clone(),
+            insert.into().erased,
+            offset.into().erased,
+            bits.into().erased,
+          ],
+        ))
+      }
 
-  (z) => {
-    $crate::SwizzleSelector::Z
-  };
+      fn bitfield_reverse(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::BitfieldReverse,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  (b) => {
-    $crate::SwizzleSelector::Z
-  };
+      fn bit_count(&self) -> Self::BitsExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::BitCount,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  (w) => {
-    $crate::SwizzleSelector::W
-  };
+      fn find_lsb(&self) -> Self::BitsExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::FindLSB,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  (a) => {
-    $crate::SwizzleSelector::W
+      fn find_msb(&self) -> Self::BitsExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::FindMSB,
+          vec![self.erased.clone()],
+        ))
+      }
+    }
   };
 }
 
-// Swizzle shortcuts
-pub trait V2Swizzlable<T> {
-  fn xyx(&self) -> Expr<V3<T>>;
-  fn yxx(&self) -> Expr<V3<T>>;
-  fn xxy(&self) -> Expr<V3<T>>;
-}
-
-pub trait V3Swizzlable<T> {
-  fn xy(&self) -> Expr<V2<T>>;
-  fn yz(&self) -> Expr<V2<T>>;
-  fn zx(&self) -> Expr<V2<T>>;
-  fn zy(&self) -> Expr<V2<T>>;
-}
+impl_Bitfield!(i32, i32);
+impl_Bitfield!(V2<i32>, V2<i32>);
+impl_Bitfield!(V3<i32>, V3<i32>);
+impl_Bitfield!(V4<i32>, V4<i32>);
+impl_Bitfield!(u32, i32);
+impl_Bitfield!(V2<u32>, V2<i32>);
+impl_Bitfield!(V3<u32>, V3<i32>);
+impl_Bitfield!(V4<u32>, V4<i32>);
 
-pub trait V4Swizzlable<T> {
-  fn xy(&self) -> Expr<V2<T>>;
-  fn xyz(&self) -> Expr<V3<T>>;
-}
+/// Component-wise relational functions on vectors.
+///
+/// Those are the vector counterparts of [`Expr::lt`], [`Expr::eq`], etc., yielding a boolean vector with one component
+/// per compared component.
+pub trait VectorRelational: Sized {
+  /// Boolean vector expression with the same dimension as `Self`.
+  type BoolExpr;
 
-impl<T> V4Swizzlable<T> for Expr<V4<T>> {
-  fn xy(&self) -> Expr<V2<T>> {
-    self.swizzle([SwizzleSelector::X, SwizzleSelector::Y])
-  }
+  /// Component-wise `a < b` — `lessThan`.
+  fn less_than(&self, rhs: impl Into<Self>) -> Self::BoolExpr;
 
-  fn xyz(&self) -> Expr<V3<T>> {
-    self.swizzle([SwizzleSelector::X, SwizzleSelector::Y, SwizzleSelector::Z])
-  }
-}
+  /// Component-wise `a <= b` — `lessThanEqual`.
+  fn less_than_equal(&self, rhs: impl Into<Self>) -> Self::BoolExpr;
 
-impl<T> V3Swizzlable<T> for Expr<V3<T>> {
-  fn xy(&self) -> Expr<V2<T>> {
-    self.swizzle([SwizzleSelector::X, SwizzleSelector::Y])
-  }
+  /// Component-wise `a > b` — `greaterThan`.
+  fn greater_than(&self, rhs: impl Into<Self>) -> Self::BoolExpr;
 
-  fn yz(&self) -> Expr<V2<T>> {
-    self.swizzle([SwizzleSelector::Y, SwizzleSelector::Z])
-  }
+  /// Component-wise `a >= b` — `greaterThanEqual`.
+  fn greater_than_equal(&self, rhs: impl Into<Self>) -> Self::BoolExpr;
 
-  fn zx(&self) -> Expr<V2<T>> {
-    self.swizzle([SwizzleSelector::Z, SwizzleSelector::X])
-  }
+  /// Component-wise `a == b` — `equal`.
+  fn equal(&self, rhs: impl Into<Self>) -> Self::BoolExpr;
 
-  fn zy(&self) -> Expr<V2<T>> {
-    self.swizzle([SwizzleSelector::Z, SwizzleSelector::Y])
-  }
+  /// Component-wise `a != b` — `notEqual`.
+  fn not_equal(&self, rhs: impl Into<Self>) -> Self::BoolExpr;
 }
 
-impl<T> V2Swizzlable<T> for Expr<V2<T>> {
-  fn xyx(&self) -> Expr<V3<T>> {
-    self.swizzle([SwizzleSelector::X, SwizzleSelector::Y, SwizzleSelector::X])
-  }
-  fn yxx(&self) -> Expr<V3<T>> {
-    self.swizzle([SwizzleSelector::Y, SwizzleSelector::X, SwizzleSelector::X])
-  }
-  fn xxy(&self) -> Expr<V3<T>> {
-    self.swizzle([SwizzleSelector::X, SwizzleSelector::X, SwizzleSelector::Y])
-  }
-}
+macro_rules! impl_VectorRelational {
+  ($t:ty, $bool_expr:ty) => {
+    impl VectorRelational for Expr<$t> {
+      type BoolExpr = Expr<$bool_expr>;
 
-/// Input declaration.
-///
-/// # Examples
-///
-/// ```
-/// use shades::{Scope, ShaderBuilder, Swizzlable, V3, V4, inputs, sw};
-///
-/// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-///   inputs!(s,
-///     position: V3<f32>,
-///     color: V4<f32>
-///   );
-///
-///   s.main_fun(|s: &mut Scope<()>| {
-///     let rgb = sw!(color, .r.g.b);
-///   })
-/// });
-/// ```
-#[macro_export]
-macro_rules! inputs {
-  ($s:ident, $( $name:ident : $t:ty ),+) => {
-    $(
-      let $name = unsafe { $s.input::<$t>(stringify!($name)) };
-    )+
-  }
-}
+      fn less_than(&self, rhs: impl Into<Self>) -> Self::BoolExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::VLt,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
 
-/// Output declaration.
-///
-/// # Examples
-///
-/// ```
-/// use shades::{Scope, ShaderBuilder, V3, lit, outputs};
-///
-/// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-///   outputs!(s,
-///     position: V3<f32>
-///   );
-///
-///   s.main_fun(|s: &mut Scope<()>| {
-///     s.set(&position, lit!(0., 0., 0.));
-///   })
-/// });
-/// ```
-#[macro_export]
-macro_rules! outputs {
-  ($s:ident, $( $name:ident : $t:ty ),+) => {
-    $(
-      let $name = unsafe { $s.output::<$t>(stringify!($name)) };
-    )+
-  }
-}
+      fn less_than_equal(&self, rhs: impl Into<Self>) -> Self::BoolExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::VLte,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
 
-/// Uniform declaration.
-///
-/// # Examples
-///
-/// ```
-/// use shades::{Scope, ShaderBuilder, V3, uniforms, vec4};
-///
-/// ShaderBuilder::new_vertex_shader(|mut s, vertex| {
-///   uniforms!(s,
-///     time: f32
-///   );
-///
-///   s.main_fun(|s: &mut Scope<()>| {
-///     s.set(vertex.position, vec4!(time, 0., 0., 1.));
-///   })
-/// });
-/// ```
-#[macro_export]
-macro_rules! uniforms {
-  ($s:ident, $( $name:ident : $t:ty ),+) => {
-    $(
-      let $name = unsafe { $s.uniform::<$t>(stringify!($name)) };
-    )+
-  }
-}
+      fn greater_than(&self, rhs: impl Into<Self>) -> Self::BoolExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::VGt,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub enum BuiltIn {
-  Vertex(VertexBuiltIn),
-  TessCtrl(TessCtrlBuiltIn),
-  TessEval(TessEvalBuiltIn),
-  Geometry(GeometryBuiltIn),
-  Fragment(FragmentBuiltIn),
-}
+      fn greater_than_equal(&self, rhs: impl Into<Self>) -> Self::BoolExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::VGte,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub enum VertexBuiltIn {
-  VertexID,
-  InstanceID,
-  BaseVertex,
-  BaseInstance,
-  Position,
-  PointSize,
-  ClipDistance,
-}
+      fn equal(&self, rhs: impl Into<Self>) -> Self::BoolExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::VEq,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub enum TessCtrlBuiltIn {
-  MaxPatchVerticesIn,
-  PatchVerticesIn,
-  PrimitiveID,
-  InvocationID,
-  TessellationLevelOuter,
-  TessellationLevelInner,
-  In,
-  Out,
-  Position,
-  PointSize,
-  ClipDistance,
-  CullDistance,
+      fn not_equal(&self, rhs: impl Into<Self>) -> Self::BoolExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::VNeq,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
+    }
+  };
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub enum TessEvalBuiltIn {
-  TessCoord,
-  MaxPatchVerticesIn,
-  PatchVerticesIn,
-  PrimitiveID,
-  TessellationLevelOuter,
-  TessellationLevelInner,
-  In,
-  Out,
-  Position,
-  PointSize,
-  ClipDistance,
-  CullDistance,
+impl_VectorRelational!(V2<i32>, V2<bool>);
+impl_VectorRelational!(V3<i32>, V3<bool>);
+impl_VectorRelational!(V4<i32>, V4<bool>);
+impl_VectorRelational!(V2<u32>, V2<bool>);
+impl_VectorRelational!(V3<u32>, V3<bool>);
+impl_VectorRelational!(V4<u32>, V4<bool>);
+impl_VectorRelational!(V2<f32>, V2<bool>);
+impl_VectorRelational!(V3<f32>, V3<bool>);
+impl_VectorRelational!(V4<f32>, V4<bool>);
+
+macro_rules! impl_select {
+  ($v:ident) => {
+    impl Expr<$v<bool>> {
+      /// Component-wise selection.
+      ///
+      /// `cond.select(a, b)` picks, for each component, the component of `a` if the one of `cond` is `true`, or the
+      /// one of `b` otherwise. This is the branchless, vector version of `cond ? a : b`, and maps to `mix(b, a, cond)`.
+      pub fn select<T>(&self, a: impl Into<Expr<$v<T>>>, b: impl Into<Expr<$v<T>>>) -> Expr<$v<T>> {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Mix,
+          vec![b.into().erased, a.into().erased, self.erased.clone()],
+        ))
+      }
+    }
+  };
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub enum GeometryBuiltIn {
-  In,
-  Out,
-  Position,
-  PointSize,
-  ClipDistance,
-  CullDistance,
-  PrimitiveID,
-  PrimitiveIDIn,
-  InvocationID,
-  Layer,
-  ViewportIndex,
-}
+impl_select!(V2);
+impl_select!(V3);
+impl_select!(V4);
 
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub enum FragmentBuiltIn {
-  FragCoord,
-  FrontFacing,
-  PointCoord,
-  SampleID,
-  SamplePosition,
-  SampleMaskIn,
-  ClipDistance,
-  CullDistance,
-  PrimitiveID,
-  Layer,
-  ViewportIndex,
-  FragDepth,
-  SampleMask,
-  HelperInvocation,
+/// Reductions, negation and comparisons of boolean vectors.
+///
+/// Boolean vectors cannot be ordered, so only [`VectorBool::equal`] and [`VectorBool::not_equal`] of the
+/// [`VectorRelational`] comparisons are available.
+pub trait VectorBool: Sized {
+  /// Whether any component is `true` — `any`.
+  fn any(&self) -> Expr<bool>;
+
+  /// Whether all components are `true` — `all`.
+  fn all(&self) -> Expr<bool>;
+
+  /// Component-wise logical complement — `not`.
+  fn not(&self) -> Self;
+
+  /// Component-wise `a == b` — `equal`.
+  fn equal(&self, rhs: impl Into<Self>) -> Self;
+
+  /// Component-wise `a != b` — `notEqual`.
+  fn not_equal(&self, rhs: impl Into<Self>) -> Self;
 }
 
-/// Vertex shader environment.
-#[derive(Debug)]
-pub struct VertexShaderEnv {
-  // inputs
-  /// ID of the current vertex.
-  pub vertex_id: Expr<i32>,
+macro_rules! impl_VectorBool {
+  ($t:ty) => {
+    impl VectorBool for Expr<$t> {
+      fn any(&self) -> Expr<bool> {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::VAny,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  /// Instance ID of the current vertex.
-  pub instance_id: Expr<i32>,
+      fn all(&self) -> Expr<bool> {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::VAll,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  /// Base vertex offset.
-  pub base_vertex: Expr<i32>,
+      fn not(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::VNot,
+          vec![self.erased.clone()],
+        ))
+      }
 
-  /// Base instance vertex offset.
-  pub base_instance: Expr<i32>,
+      fn equal(&self, rhs: impl Into<Self>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::VEq,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
 
-  // outputs
-  /// 4D position of the vertex.
-  pub position: Var<V4<f32>>,
+      fn not_equal(&self, rhs: impl Into<Self>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::VNeq,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
+    }
+  };
+}
 
-  /// Point size of the vertex.
-  pub point_size: Var<f32>,
+impl_VectorBool!(V2<bool>);
+impl_VectorBool!(V3<bool>);
+impl_VectorBool!(V4<bool>);
 
-  // Clip distances to user-defined plans.
-  pub clip_distance: Var<[f32]>,
+pub trait Bounded: Sized {
+  fn min(&self, rhs: impl Into<Self>) -> Self;
+
+  fn max(&self, rhs: impl Into<Self>) -> Self;
+
+  fn clamp(&self, min_value: impl Into<Self>, max_value: impl Into<Self>) -> Self;
 }
 
-impl VertexShaderEnv {
-  fn new() -> Self {
-    let vertex_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
-      VertexBuiltIn::VertexID,
-    )));
-    let instance_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
-      VertexBuiltIn::InstanceID,
-    )));
-    let base_vertex = Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
-      VertexBuiltIn::BaseVertex,
-    )));
-    let base_instance = Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
-      VertexBuiltIn::BaseInstance,
-    )));
-    let position = Var(Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
-      VertexBuiltIn::Position,
-    ))));
-    let point_size = Var(Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
-      VertexBuiltIn::PointSize,
-    ))));
-    let clip_distance = Var(Expr::new(ErasedExpr::new_builtin(BuiltIn::Vertex(
-      VertexBuiltIn::ClipDistance,
-    ))));
+macro_rules! impl_Bounded {
+  ($t:ty) => {
+    impl Bounded for Expr<$t> {
+      fn min(&self, rhs: impl Into<Self>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Min,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
 
-    Self {
-      vertex_id,
-      instance_id,
-      base_vertex,
-      base_instance,
-      position,
-      point_size,
-      clip_distance,
+      fn max(&self, rhs: impl Into<Self>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Max,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
+
+      fn clamp(&self, min_value: impl Into<Self>, max_value: impl Into<Self>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Clamp,
+          vec![
+            self.erased.clone(),
+            min_value.into().erased,
+            max_value.into().erased,
+          ],
+        ))
+      }
     }
-  }
+  };
 }
 
-/// Tessellation control shader environment.
-#[derive(Debug)]
-pub struct TessCtrlShaderEnv {
-  // inputs
-  /// Maximum number of vertices per patch.
-  pub max_patch_vertices_in: Expr<i32>,
+impl_Bounded!(i32);
+impl_Bounded!(V2<i32>);
+impl_Bounded!(V3<i32>);
+impl_Bounded!(V4<i32>);
 
-  /// Number of vertices for the current patch.
-  pub patch_vertices_in: Expr<i32>,
+impl_Bounded!(u32);
+impl_Bounded!(V2<u32>);
+impl_Bounded!(V3<u32>);
+impl_Bounded!(V4<u32>);
 
-  /// ID of the current primitive.
-  pub primitive_id: Expr<i32>,
+impl_Bounded!(f32);
+impl_Bounded!(V2<f32>);
+impl_Bounded!(V3<f32>);
+impl_Bounded!(V4<f32>);
 
-  /// ID of the current tessellation control shader invocation.
-  pub invocation_id: Expr<i32>,
+impl_Bounded!(bool);
+impl_Bounded!(V2<bool>);
+impl_Bounded!(V3<bool>);
+impl_Bounded!(V4<bool>);
 
-  /// Array of per-vertex input expressions.
-  pub input: Expr<[TessControlPerVertexIn]>,
+/// Bounds of vectors by scalars.
+///
+/// Those are the versions of [`Bounded`] taking the same scalar for all the components, such as
+/// `clamp(vec3, float, float)`, which avoids splatting the bounds into vectors first.
+///
+/// # Examples
+///
+/// ```
+/// use shades::{ScalarBounded as _, vec3};
+///
+/// let color = vec3!(-0.5, 0.5, 1.5);
+/// let saturated = color.clamp_scalar(0., 1.); // clamp(color, 0., 1.)
+/// ```
+pub trait ScalarBounded: Sized {
+  /// Scalar expression with the same component type as `Self`.
+  type ScalarExpr;
 
-  // outputs
-  /// Outer tessellation levels.
-  pub tess_level_outer: Var<[f32; 4]>,
+  /// Component-wise minimum with `rhs` — `min`.
+  fn min_scalar(&self, rhs: impl Into<Self::ScalarExpr>) -> Self;
 
-  /// Inner tessellation levels.
-  pub tess_level_inner: Var<[f32; 2]>,
+  /// Component-wise maximum with `rhs` — `max`.
+  fn max_scalar(&self, rhs: impl Into<Self::ScalarExpr>) -> Self;
 
-  /// Array of per-vertex output variables.
-  pub output: Var<[TessControlPerVertexOut]>,
+  /// Component-wise clamping between `min_value` and `max_value` — `clamp`.
+  fn clamp_scalar(
+    &self,
+    min_value: impl Into<Self::ScalarExpr>,
+    max_value: impl Into<Self::ScalarExpr>,
+  ) -> Self;
 }
 
-impl TessCtrlShaderEnv {
-  fn new() -> Self {
-    let max_patch_vertices_in = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-      TessCtrlBuiltIn::MaxPatchVerticesIn,
-    )));
-    let patch_vertices_in = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-      TessCtrlBuiltIn::PatchVerticesIn,
-    )));
-    let primitive_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-      TessCtrlBuiltIn::PrimitiveID,
-    )));
-    let invocation_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-      TessCtrlBuiltIn::InvocationID,
-    )));
-    let input = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-      TessCtrlBuiltIn::In,
-    )));
-    let tess_level_outer = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessCtrl(
-      TessCtrlBuiltIn::TessellationLevelOuter,
-    )));
-    let tess_level_inner = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessCtrl(
-      TessCtrlBuiltIn::TessellationLevelInner,
-    )));
-    let output = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessCtrl(
-      TessCtrlBuiltIn::Out,
-    )));
+macro_rules! impl_ScalarBounded {
+  ($t:ty, $s:ty) => {
+    impl ScalarBounded for Expr<$t> {
+      type ScalarExpr = Expr<$s>;
 
-    Self {
-      max_patch_vertices_in,
-      patch_vertices_in,
-      primitive_id,
-      invocation_id,
-      input,
-      tess_level_outer,
-      tess_level_inner,
-      output,
+      fn min_scalar(&self, rhs: impl Into<Self::ScalarExpr>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Min,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
+
+      fn max_scalar(&self, rhs: impl Into<Self::ScalarExpr>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Max,
+          vec![self.erased.clone(), rhs.into().erased],
+        ))
+      }
+
+      fn clamp_scalar(
+        &self,
+        min_value: impl Into<Self::ScalarExpr>,
+        max_value: impl Into<Self::ScalarExpr>,
+      ) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Clamp,
+          vec![
+            self.erased.clone(),
+            min_value.into().erased,
+            max_value.into().erased,
+          ],
+        ))
+      }
     }
-  }
+  };
 }
 
-/// Read-only, input tessellation control shader environment.
-#[derive(Debug)]
-pub struct TessControlPerVertexIn;
+impl_ScalarBounded!(V2<i32>, i32);
+impl_ScalarBounded!(V3<i32>, i32);
+impl_ScalarBounded!(V4<i32>, i32);
 
-impl Expr<TessControlPerVertexIn> {
-  ///
-  pub fn position(&self) -> Expr<V4<f32>> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-        TessCtrlBuiltIn::Position,
-      ))),
-    };
+impl_ScalarBounded!(V2<u32>, u32);
+impl_ScalarBounded!(V3<u32>, u32);
+impl_ScalarBounded!(V4<u32>, u32);
 
-    Expr::new(erased)
-  }
+impl_ScalarBounded!(V2<f32>, f32);
+impl_ScalarBounded!(V3<f32>, f32);
+impl_ScalarBounded!(V4<f32>, f32);
 
-  pub fn point_size(&self) -> Expr<f32> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-        TessCtrlBuiltIn::PointSize,
-      ))),
-    };
+/// Interpolation and thresholding of floats.
+///
+/// `RHS` is the type of the weights and edges: either the same type as `Self`, for component-wise weights and edges,
+/// or [`Expr<f32>`], used for all the components at once. Selecting components with a boolean vector instead of
+/// weights — `mix(x, y, bvec)` — is done with [`Expr::select`].
+///
+/// # Examples
+///
+/// ```
+/// use shades::{Mix as _, lit, vec3};
+///
+/// let color = vec3!(0.2, 0.5, 0.8);
+/// let mask = color.step(lit!(0.5)); // step(0.5, color)
+/// let edges = color.smooth_step(lit!(0.25), lit!(0.75)); // smoothstep(0.25, 0.75, color)
+/// ```
+pub trait Mix<RHS>: Sized {
+  /// Linear interpolation between `self` and `y`, with weight `a` — `mix`.
+  fn mix(&self, y: impl Into<Self>, a: RHS) -> Self;
 
-    Expr::new(erased)
-  }
+  /// `0` where `self` is lower than `edge`, `1` otherwise — `step(edge, self)`.
+  fn step(&self, edge: RHS) -> Self;
 
-  pub fn clip_distance(&self) -> Expr<[f32]> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-        TessCtrlBuiltIn::ClipDistance,
-      ))),
-    };
+  /// Hermite interpolation of `self` between `edge_a` and `edge_b` — `smoothstep(edge_a, edge_b, self)`.
+  fn smooth_step(&self, edge_a: RHS, edge_b: RHS) -> Self;
+}
 
-    Expr::new(erased)
-  }
+macro_rules! impl_Mix {
+  ($t:ty, $q:ty) => {
+    impl Mix<Expr<$q>> for Expr<$t> {
+      fn mix(&self, y: impl Into<Self>, a: Expr<$q>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Mix,
+          vec![self.erased.clone(), y.into().erased, a.erased],
+        ))
+      }
 
-  pub fn cull_distance(&self) -> Expr<[f32]> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-        TessCtrlBuiltIn::CullDistance,
-      ))),
-    };
+      fn step(&self, edge: Expr<$q>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Step,
+          vec![edge.erased, self.erased.clone()],
+        ))
+      }
 
-    Expr::new(erased)
-  }
+      fn smooth_step(&self, edge_a: Expr<$q>, edge_b: Expr<$q>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::SmoothStep,
+          vec![edge_a.erased, edge_b.erased, self.erased.clone()],
+        ))
+      }
+    }
+  };
 }
 
-/// Output tessellation control shader environment.
-#[derive(Debug)]
-pub struct TessControlPerVertexOut(());
+impl_Mix!(f32, f32);
+impl_Mix!(V2<f32>, f32);
+impl_Mix!(V2<f32>, V2<f32>);
 
-impl Expr<TessControlPerVertexOut> {
-  /// 4D position of the verte.
-  pub fn position(&self) -> Var<V4<f32>> {
-    let expr = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-        TessCtrlBuiltIn::Position,
-      ))),
-    };
+impl_Mix!(V3<f32>, f32);
+impl_Mix!(V3<f32>, V3<f32>);
 
-    Var(Expr::new(expr))
-  }
+impl_Mix!(V4<f32>, f32);
+impl_Mix!(V4<f32>, V4<f32>);
 
-  /// Point size of the vertex.
-  pub fn point_size(&self) -> Var<f32> {
-    let expr = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-        TessCtrlBuiltIn::PointSize,
-      ))),
-    };
+pub trait FloatingExt {
+  type BoolExpr;
 
-    Var(Expr::new(expr))
-  }
+  fn is_nan(&self) -> Self::BoolExpr;
 
-  /// Clip distances to user-defined planes.
-  pub fn clip_distance(&self) -> Var<[f32]> {
-    let expr = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-        TessCtrlBuiltIn::ClipDistance,
-      ))),
-    };
+  fn is_inf(&self) -> Self::BoolExpr;
+}
 
-    Var(Expr::new(expr))
-  }
+macro_rules! impl_FloatingExt {
+  ($t:ty, $bool_expr:ty) => {
+    impl FloatingExt for Expr<$t> {
+      type BoolExpr = Expr<$bool_expr>;
 
-  /// Cull distances to user-defined planes.
-  pub fn cull_distance(&self) -> Var<[f32]> {
-    let expr = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessCtrl(
-        TessCtrlBuiltIn::CullDistance,
-      ))),
-    };
+      fn is_nan(&self) -> Self::BoolExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::IsNan,
+          vec![self.erased.clone()],
+        ))
+      }
 
-    Var(Expr::new(expr))
-  }
+      fn is_inf(&self) -> Self::BoolExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::IsInf,
+          vec![self.erased.clone()],
+        ))
+      }
+    }
+  };
 }
 
-/// Tessellation evalution shader environm.nt
-#[derive(Debug)]
-pub struct TessEvalShaderEnv {
-  // inputs
-  /// Number of vertices in the current patch.
-  pub patch_vertices_in: Expr<i32>,
-
-  /// ID of the current primitive.
-  pub primitive_id: Expr<i32>,
+impl_FloatingExt!(f32, bool);
+impl_FloatingExt!(V2<f32>, V2<bool>);
+impl_FloatingExt!(V3<f32>, V3<bool>);
+impl_FloatingExt!(V4<f32>, V4<bool>);
 
-  /// Tessellation coordinates of the current vertex.
-  pub tess_coord: Expr<V3<f32>>,
+/// Geometric functions on float vectors.
+///
+/// [`Expr::cross`] is only defined on 3D vectors, so it is not part of this trait.
+pub trait Geometry: Sized {
+  /// Scalar type of lengths and dot products.
+  type LengthExpr;
 
-  /// Outer tessellation levels.
-  pub tess_level_outer: Expr<[f32; 4]>,
+  /// Length of the vector — `length`.
+  fn length(&self) -> Self::LengthExpr;
 
-  /// Inner tessellation levels.
-  pub tess_level_inner: Expr<[f32; 2]>,
+  /// Distance between two points — `distance`.
+  fn distance(&self, other: impl Into<Self>) -> Self::LengthExpr;
 
-  /// Array of per-evertex expressions.
-  pub input: Expr<[TessEvaluationPerVertexIn]>,
+  /// Dot product — `dot`.
+  fn dot(&self, other: impl Into<Self>) -> Self::LengthExpr;
 
-  // outputs
-  /// 4D position of the vertex.
-  pub position: Var<V4<f32>>,
+  /// Vector of length `1` in the same direction — `normalize`.
+  fn normalize(&self) -> Self;
 
-  /// Point size of the vertex.
-  pub point_size: Var<f32>,
+  /// Orient `normal` against `self`, taken as the incident vector — `faceforward(normal, self, reference)`.
+  ///
+  /// `normal` is returned as-is if `reference` points against `self`, and negated otherwise.
+  fn face_forward(&self, normal: impl Into<Self>, reference: impl Into<Self>) -> Self;
 
-  /// Clip distances to user-defined planes.
-  pub clip_distance: Var<[f32]>,
+  /// Reflect `self`, taken as the incident vector, about the surface of normal `normal` — `reflect`.
+  ///
+  /// `normal` must be normalized.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{Geometry as _, vec3};
+  ///
+  /// let view = vec3!(1., -1., 0.).normalize();
+  /// let reflected = view.reflect(vec3!(0., 1., 0.));
+  /// ```
+  fn reflect(&self, normal: impl Into<Self>) -> Self;
 
-  /// Cull distances to user-defined planes.
-  pub cull_distance: Var<[f32]>,
+  /// Refract `self`, taken as the incident vector, through the surface of normal `normal` — `refract`.
+  ///
+  /// `eta` is the ratio of the indices of refraction. Both `self` and `normal` must be normalized. On total internal
+  /// reflection, the result is the zero vector.
+  fn refract(&self, normal: impl Into<Self>, eta: impl Into<Expr<f32>>) -> Self;
 }
 
-impl TessEvalShaderEnv {
-  fn new() -> Self {
-    let patch_vertices_in = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
-      TessEvalBuiltIn::PatchVerticesIn,
-    )));
-    let primitive_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
-      TessEvalBuiltIn::PrimitiveID,
-    )));
-    let tess_coord = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
-      TessEvalBuiltIn::TessCoord,
-    )));
-    let tess_level_outer = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
-      TessEvalBuiltIn::TessellationLevelOuter,
-    )));
-    let tess_level_inner = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
-      TessEvalBuiltIn::TessellationLevelInner,
-    )));
-    let input = Expr::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
-      TessEvalBuiltIn::In,
-    )));
+macro_rules! impl_Geometry {
+  ($t:ty, $l:ty) => {
+    impl Geometry for Expr<$t> {
+      type LengthExpr = Expr<$l>;
 
-    let position = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessEval(
-      TessEvalBuiltIn::Position,
-    )));
-    let point_size = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessEval(
-      TessEvalBuiltIn::PointSize,
-    )));
-    let clip_distance = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessEval(
-      TessEvalBuiltIn::ClipDistance,
-    )));
-    let cull_distance = Var::new(ScopedHandle::BuiltIn(BuiltIn::TessEval(
-      TessEvalBuiltIn::ClipDistance,
-    )));
+      fn length(&self) -> Self::LengthExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Length,
+          vec![self.erased.clone()],
+        ))
+      }
 
-    Self {
-      patch_vertices_in,
-      primitive_id,
-      tess_coord,
-      tess_level_outer,
-      tess_level_inner,
-      input,
-      position,
-      point_size,
-      clip_distance,
-      cull_distance,
-    }
-  }
-}
+      fn distance(&self, other: impl Into<Self>) -> Self::LengthExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Distance,
+          vec![self.erased.clone(), other.into().erased],
+        ))
+      }
 
-/// Tessellation evaluation per-vertex expression.
-#[derive(Debug)]
-pub struct TessEvaluationPerVertexIn;
+      fn dot(&self, other: impl Into<Self>) -> Self::LengthExpr {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Dot,
+          vec![self.erased.clone(), other.into().erased],
+        ))
+      }
 
-impl Expr<TessEvaluationPerVertexIn> {
-  /// 4D position of the vertex.
-  pub fn position(&self) -> Expr<V4<f32>> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
-        TessEvalBuiltIn::Position,
-      ))),
-    };
+      fn normalize(&self) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Normalize,
+          vec![self.erased.clone()],
+        ))
+      }
 
-    Expr::new(erased)
-  }
+      fn face_forward(&self, normal: impl Into<Self>, reference: impl Into<Self>) -> Self {
+        // note: this function call is super weird as the normal and incident (i.e. self) arguments are swapped
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::FaceForward,
+          vec![
+            normal.into().erased,
+            self.erased.clone(),
+            reference.into().erased,
+          ],
+        ))
+      }
 
-  /// Point size of the vertex.
-  pub fn point_size(&self) -> Expr<f32> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
-        TessEvalBuiltIn::PointSize,
-      ))),
-    };
+      fn reflect(&self, normal: impl Into<Self>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Reflect,
+          vec![self.erased.clone(), normal.into().erased],
+        ))
+      }
 
-    Expr::new(erased)
-  }
+      fn refract(&self, normal: impl Into<Self>, eta: impl Into<Expr<f32>>) -> Self {
+        Expr::new(ErasedExpr::fun_call(
+          ErasedFunHandle::Refract,
+          vec![self.erased.clone(), normal.into().erased, eta.into().erased],
+        ))
+      }
+    }
+  };
+}
 
-  /// Clip distances to user-defined planes.
-  pub fn clip_distance(&self) -> Expr<[f32]> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
-        TessEvalBuiltIn::ClipDistance,
-      ))),
-    };
+impl_Geometry!(V2<f32>, f32);
+impl_Geometry!(V3<f32>, f32);
+impl_Geometry!(V4<f32>, f32);
 
-    Expr::new(erased)
+impl Expr<V3<f32>> {
+  /// Cross product — `cross`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use shades::{Expr, V3, vec3};
+  ///
+  /// let x = vec3!(1., 0., 0.);
+  /// let z: Expr<V3<f32>> = x.cross(vec3!(0., 1., 0.));
+  /// ```
+  pub fn cross(&self, other: impl Into<Self>) -> Self {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::Cross,
+      vec![self.erased.clone(), other.into().erased],
+    ))
   }
+}
 
-  /// Cull distances to user-defined planes.
-  pub fn cull_distance(&self) -> Expr<[f32]> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::TessEval(
-        TessEvalBuiltIn::CullDistance,
-      ))),
-    };
+/// Type conversions.
+///
+/// Those convert scalars and vectors component-wise, keeping the same dimension: for instance, casting an
+/// [`Expr<V3<i32>>`] with [`Cast::as_f32`] yields an [`Expr<V3<f32>>`].
+pub trait Cast {
+  /// Signed integer version of `Self`.
+  type I32;
 
-    Expr::new(erased)
-  }
-}
+  /// Unsigned integer version of `Self`.
+  type U32;
 
-/// Geometry shader environment.
-#[derive(Debug)]
-pub struct GeometryShaderEnv {
-  // inputs
-  /// Contains the index of the current primitive.
-  pub primitive_id_in: Expr<i32>,
+  /// Floating-point version of `Self`.
+  type F32;
 
-  /// ID of the current invocation of the geometry shader.
-  pub invocation_id: Expr<i32>,
+  /// Boolean version of `Self`.
+  type Bool;
 
-  /// Read-only environment for each vertices.
-  pub input: Expr<[GeometryPerVertexIn]>,
+  /// Convert to signed integers, truncating floating-point values.
+  fn as_i32(&self) -> Self::I32;
 
-  // outputs
-  /// Output 4D vertex position.
-  pub position: Var<V4<f32>>,
+  /// Convert to unsigned integers, truncating floating-point values.
+  ///
+  /// Signed integers are reinterpreted, keeping their bits: negative ones wrap around, e.g. `-1` becomes `u32::MAX`.
+  fn as_u32(&self) -> Self::U32;
 
-  /// Output vertex point size.
-  pub point_size: Var<f32>,
+  /// Convert to floating-point values.
+  fn as_f32(&self) -> Self::F32;
+
+  /// Convert to booleans; zero converts to `false` and any other value to `true`.
+  fn as_bool(&self) -> Self::Bool;
+}
 
-  /// Output clip distances to user-defined planes.
-  pub clip_distance: Var<[f32]>,
+macro_rules! impl_Cast {
+  ($t:ty, $i:ty, $u:ty, $f:ty, $b:ty) => {
+    impl Cast for Expr<$t> {
+      type I32 = Expr<$i>;
 
-  /// Output cull distances to user-defined planes.
-  pub cull_distance: Var<[f32]>,
+      type U32 = Expr<$u>;
 
-  /// Primitive ID to write to in.
-  pub primitive_id: Var<i32>,
+      type F32 = Expr<$f>;
 
-  /// Layer to write to in.
-  pub layer: Var<i32>,
+      type Bool = Expr<$b>;
 
-  /// Viewport index to write to.
-  pub viewport_index: Var<i32>,
-}
+      fn as_i32(&self) -> Self::I32 {
+        Expr::new(ErasedExpr::Cast(
+          <$i as ToType>::ty(),
+          self.erased.clone().intern(),
+        ))
+      }
 
-impl GeometryShaderEnv {
-  fn new() -> Self {
-    let primitive_id_in = Expr::new(ErasedExpr::new_builtin(BuiltIn::Geometry(
-      GeometryBuiltIn::PrimitiveIDIn,
-    )));
-    let invocation_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Geometry(
-      GeometryBuiltIn::InvocationID,
-    )));
-    let input = Expr::new(ErasedExpr::new_builtin(BuiltIn::Geometry(
-      GeometryBuiltIn::In,
-    )));
+      fn as_u32(&self) -> Self::U32 {
+        Expr::new(ErasedExpr::Cast(
+          <$u as ToType>::ty(),
+          self.erased.clone().intern(),
+        ))
+      }
 
-    let position = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
-      GeometryBuiltIn::Position,
-    )));
-    let point_size = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
-      GeometryBuiltIn::PointSize,
-    )));
-    let clip_distance = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
-      GeometryBuiltIn::ClipDistance,
-    )));
-    let cull_distance = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
-      GeometryBuiltIn::CullDistance,
-    )));
-    let primitive_id = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
-      GeometryBuiltIn::PrimitiveID,
-    )));
-    let layer = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
-      GeometryBuiltIn::Layer,
-    )));
-    let viewport_index = Var::new(ScopedHandle::BuiltIn(BuiltIn::Geometry(
-      GeometryBuiltIn::ViewportIndex,
-    )));
+      fn as_f32(&self) -> Self::F32 {
+        Expr::new(ErasedExpr::Cast(
+          <$f as ToType>::ty(),
+          self.erased.clone().intern(),
+        ))
+      }
 
-    Self {
-      primitive_id_in,
-      invocation_id,
-      input,
-      position,
-      point_size,
-      clip_distance,
-      cull_distance,
-      primitive_id,
-      layer,
-      viewport_index,
+      fn as_bool(&self) -> Self::Bool {
+        Expr::new(ErasedExpr::Cast(
+          <$b as ToType>::ty(),
+          self.erased.clone().intern(),
+        ))
+      }
     }
-  }
+  };
 }
 
-/// Read-only, input geometry shader environment.
-#[derive(Debug)]
-pub struct GeometryPerVertexIn;
+macro_rules! impl_Cast_dims {
+  ($($t:ty),*) => {
+    $(
+      impl_Cast!($t, i32, u32, f32, bool);
+      impl_Cast!(V2<$t>, V2<i32>, V2<u32>, V2<f32>, V2<bool>);
+      impl_Cast!(V3<$t>, V3<i32>, V3<u32>, V3<f32>, V3<bool>);
+      impl_Cast!(V4<$t>, V4<i32>, V4<u32>, V4<f32>, V4<bool>);
+    )*
+  };
+}
 
-impl Expr<GeometryPerVertexIn> {
-  /// Provides 4D the position of the vertex.
-  pub fn position(&self) -> Expr<V4<f32>> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::Geometry(
-        GeometryBuiltIn::Position,
-      ))),
-    };
+impl_Cast_dims!(i32, u32, f32, bool);
 
-    Expr::new(erased)
+impl Expr<V2<f32>> {
+  /// Pack a normalized (`[0; 1]`) vector into a single [`u32`], 16-bit per component — `packUnorm2x16`.
+  pub fn pack_unorm2x16(&self) -> Expr<u32> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::PackUnorm2x16,
+      vec![self.erased.clone()],
+    ))
   }
 
-  /// Provides the size point of the vertex if it’s currently being rendered in point mode.
-  pub fn point_size(&self) -> Expr<f32> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::Geometry(
-        GeometryBuiltIn::PointSize,
-      ))),
-    };
-
-    Expr::new(erased)
+  /// Pack a signed normalized (`[-1; 1]`) vector into a single [`u32`], 16-bit per component — `packSnorm2x16`.
+  pub fn pack_snorm2x16(&self) -> Expr<u32> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::PackSnorm2x16,
+      vec![self.erased.clone()],
+    ))
   }
 
-  /// Clip distances to user planes of the vertex.
-  pub fn clip_distance(&self) -> Expr<[f32]> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::Geometry(
-        GeometryBuiltIn::ClipDistance,
-      ))),
-    };
-
-    Expr::new(erased)
+  /// Pack a vector into a single [`u32`] as two half-precision floats — `packHalf2x16`.
+  pub fn pack_half2x16(&self) -> Expr<u32> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::PackHalf2x16,
+      vec![self.erased.clone()],
+    ))
   }
+}
 
-  /// Cull distances to user planes of the vertex.
-  pub fn cull_distance(&self) -> Expr<[f32]> {
-    let erased = ErasedExpr::Field {
-      object: Box::new(self.erased.clone()),
-      field: Box::new(ErasedExpr::new_builtin(BuiltIn::Geometry(
-        GeometryBuiltIn::CullDistance,
-      ))),
-    };
+impl Expr<V4<f32>> {
+  /// Pack a normalized (`[0; 1]`) vector into a single [`u32`], 8-bit per component — `packUnorm4x8`.
+  pub fn pack_unorm4x8(&self) -> Expr<u32> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::PackUnorm4x8,
+      vec![self.erased.clone()],
+    ))
+  }
 
-    Expr::new(erased)
+  /// Pack a signed normalized (`[-1; 1]`) vector into a single [`u32`], 8-bit per component — `packSnorm4x8`.
+  pub fn pack_snorm4x8(&self) -> Expr<u32> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::PackSnorm4x8,
+      vec![self.erased.clone()],
+    ))
   }
 }
 
-/// Fragment shader environment.
-///
-/// This type contains everything you have access to when writing a fragment shader.
-#[derive(Debug)]
-pub struct FragmentShaderEnv {
-  // inputs
-  /// Fragment coordinate in the framebuffer.
-  pub frag_coord: Expr<V4<f32>>,
-
-  /// Whether the fragment is front-facing.
-  pub front_facing: Expr<bool>,
-
-  /// Clip distances to user planes.
+impl Expr<u32> {
+  /// Unpack two normalized 16-bit components — `unpackUnorm2x16`.
   ///
-  /// This is an array giving the clip distances to each of the user clip planes.
-  pub clip_distance: Expr<[f32]>,
+  /// This is the inverse of [`Expr::pack_unorm2x16`].
+  pub fn unpack_unorm2x16(&self) -> Expr<V2<f32>> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::UnpackUnorm2x16,
+      vec![self.erased.clone()],
+    ))
+  }
 
-  /// Cull distances to user planes.
+  /// Unpack two signed normalized 16-bit components — `unpackSnorm2x16`.
   ///
-  /// This is an array giving the cull distances to each of the user clip planes.
-  pub cull_distance: Expr<[f32]>,
-
-  /// Contains the 2D coordinates of a fragment within a point primitive.
-  pub point_coord: Expr<V2<f32>>,
+  /// This is the inverse of [`Expr::pack_snorm2x16`].
+  pub fn unpack_snorm2x16(&self) -> Expr<V2<f32>> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::UnpackSnorm2x16,
+      vec![self.erased.clone()],
+    ))
+  }
 
-  /// ID of the primitive being currently rendered.
-  pub primitive_id: Expr<i32>,
+  /// Unpack four normalized 8-bit components — `unpackUnorm4x8`.
+  ///
+  /// This is the inverse of [`Expr::pack_unorm4x8`].
+  pub fn unpack_unorm4x8(&self) -> Expr<V4<f32>> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::UnpackUnorm4x8,
+      vec![self.erased.clone()],
+    ))
+  }
 
-  /// ID of the sample being currently rendered.
-  pub sample_id: Expr<i32>,
+  /// Unpack four signed normalized 8-bit components — `unpackSnorm4x8`.
+  ///
+  /// This is the inverse of [`Expr::pack_snorm4x8`].
+  pub fn unpack_snorm4x8(&self) -> Expr<V4<f32>> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::UnpackSnorm4x8,
+      vec![self.erased.clone()],
+    ))
+  }
 
-  /// Sample 2D coordinates.
-  pub sample_position: Expr<V2<f32>>,
+  /// Unpack two half-precision floats — `unpackHalf2x16`.
+  ///
+  /// This is the inverse of [`Expr::pack_half2x16`].
+  pub fn unpack_half2x16(&self) -> Expr<V2<f32>> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::UnpackHalf2x16,
+      vec![self.erased.clone()],
+    ))
+  }
+}
 
-  /// Contains the computed sample coverage mask for the current fragment.
-  pub sample_mask_in: Expr<i32>,
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-  /// Layer the fragment will be written to.
-  pub layer: Expr<i32>,
+  #[test]
+  fn expr_lit() {
+    assert_eq!(lit!(true).erased, ErasedExpr::LitBool(true));
+    assert_eq!(lit![1, 2].erased, ErasedExpr::LitInt2([1, 2]));
+  }
 
-  /// Viewport index the fragment will be written to.
-  pub viewport_index: Expr<i32>,
+  #[test]
+  fn array_from_iter() {
+    let a: Expr<[f32; 3]> = (0..3).map(|i| lit!(i as f32)).collect();
 
-  /// Indicates whether we are in a helper invocation of a fragment shader.
-  pub helper_invocation: Expr<bool>,
+    assert_eq!(
+      a.erased,
+      ErasedExpr::array(
+        <[f32; 3] as ToType>::ty(),
+        vec![
+          ErasedExpr::LitFloat(0.),
+          ErasedExpr::LitFloat(1.),
+          ErasedExpr::LitFloat(2.)
+        ]
+      )
+    );
+  }
 
-  // outputs
-  /// Depth of the fragment.
-  pub frag_depth: Var<f32>,
+  #[test]
+  fn array_from_slice() {
+    let items = [V2::from([1, 2]), V2::from([3, 4])];
+    let a = Expr::<[V2<i32>]>::try_from(&items[..]).unwrap();
 
-  /// Sample mask of the fragment.
-  pub sample_mask: Var<[i32]>,
-}
+    assert_eq!(
+      a.erased,
+      ErasedExpr::array(
+        <[V2<i32>; 2] as ToType>::ty(),
+        vec![ErasedExpr::LitInt2([1, 2]), ErasedExpr::LitInt2([3, 4])]
+      )
+    );
 
-impl FragmentShaderEnv {
-  fn new() -> Self {
-    let frag_coord = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::FragCoord,
-    )));
-    let front_facing = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::FrontFacing,
-    )));
-    let clip_distance = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::ClipDistance,
-    )));
-    let cull_distance = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::CullDistance,
-    )));
-    let point_coord = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::PointCoord,
-    )));
-    let primitive_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::PrimitiveID,
-    )));
-    let sample_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::SampleID,
-    )));
-    let sample_position = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::SamplePosition,
-    )));
-    let sample_mask_in = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::SampleMaskIn,
-    )));
-    let layer = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::Layer,
-    )));
-    let viewport_index = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::ViewportIndex,
-    )));
-    let helper_invocation = Expr::new(ErasedExpr::new_builtin(BuiltIn::Fragment(
-      FragmentBuiltIn::HelperInvocation,
-    )));
+    let b = Expr::<[f32]>::try_from_iter((0..3).map(|i| lit!(i as f32))).unwrap();
+    assert_eq!(
+      b.erased,
+      ErasedExpr::array(
+        <[f32; 3] as ToType>::ty(),
+        vec![
+          ErasedExpr::LitFloat(0.),
+          ErasedExpr::LitFloat(1.),
+          ErasedExpr::LitFloat(2.)
+        ]
+      )
+    );
+  }
 
-    let frag_depth = Var::new(ScopedHandle::BuiltIn(BuiltIn::Fragment(
-      FragmentBuiltIn::FragDepth,
-    )));
-    let sample_mask = Var::new(ScopedHandle::BuiltIn(BuiltIn::Fragment(
-      FragmentBuiltIn::SampleMask,
-    )));
+  #[test]
+  fn array_from_empty_slice() {
+    assert_eq!(
+      Expr::<[f32]>::try_from(&[] as &[f32]).err(),
+      Some(EmptyArray)
+    );
+    assert_eq!(
+      Expr::<[f32]>::try_from_iter(Vec::new()).err(),
+      Some(EmptyArray)
+    );
+  }
 
-    Self {
-      frag_coord,
-      front_facing,
-      clip_distance,
-      cull_distance,
-      point_coord,
-      primitive_id,
-      sample_id,
-      sample_position,
-      sample_mask_in,
-      layer,
-      viewport_index,
-      helper_invocation,
-      frag_depth,
-      sample_mask,
-    }
+  #[test]
+  #[should_panic]
+  fn array_from_iter_wrong_len() {
+    let _: Expr<[f32; 3]> = (0..2).map(|i| lit!(i as f32)).collect();
   }
-}
 
-// standard library
+  #[test]
+  fn array_length() {
+    assert_eq!(lit!([1, 2, 3]).length().erased, ErasedExpr::LitInt(3));
 
-pub trait Trigonometry {
-  fn radians(&self) -> Self;
+    let env = VertexShaderEnv::new();
+    assert_eq!(
+      env.clip_distance.length().erased,
+      ErasedExpr::ArrayLength(Arc::new(env.clip_distance.0.erased.clone()))
+    );
+  }
 
-  fn degrees(&self) -> Self;
+  #[test]
+  fn expr_lit_aggregates() {
+    assert_eq!(
+      lit!(mat [[1., 2.], [3., 4.]]).erased,
+      ErasedExpr::LitM22(Matrix([[1., 2.], [3., 4.]]))
+    );
+    assert_eq!(
+      lit!(mat [[1., 2., 3.], [4., 5., 6.]]).erased,
+      ErasedExpr::LitM23(Matrix([[1., 2., 3.], [4., 5., 6.]]))
+    );
+    assert_eq!(
+      lit!([(1., 2.), (3., 4.)]).erased,
+      ErasedExpr::array(
+        <[V2<f32>; 2] as ToType>::ty(),
+        vec![
+          ErasedExpr::LitFloat2([1., 2.]),
+          ErasedExpr::LitFloat2([3., 4.])
+        ]
+      )
+    );
+  }
 
-  fn sin(&self) -> Self;
+  #[test]
+  fn expr_unary() {
+    let mut scope = Scope::<()>::new(0);
 
-  fn cos(&self) -> Self;
+    let a = !lit!(true);
+    let b = -lit!(3i32);
+    let c = scope.var(17i32);
 
-  fn tan(&self) -> Self;
+    assert_eq!(a.erased, ErasedExpr::LitBool(false));
+    assert_eq!(b.erased, ErasedExpr::LitInt(-3));
+    assert_eq!(c.erased, ErasedExpr::Var(ScopedHandle::fun_var(0, 0)));
 
-  fn asin(&self) -> Self;
+    let d = !c.eq(1);
+    let e = -&c;
 
-  fn acos(&self) -> Self;
+    assert_eq!(
+      d.erased,
+      ErasedExpr::Not(Arc::new(ErasedExpr::Eq(
+        Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+        Arc::new(ErasedExpr::LitInt(1)),
+      )))
+    );
+    assert_eq!(
+      e.erased,
+      ErasedExpr::Neg(Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))))
+    );
+  }
 
-  fn atan(&self) -> Self;
+  #[test]
+  fn expr_binary() {
+    let a = lit!(1i32) + lit!(2);
+    let b = lit!(1i32) + 2;
 
-  fn sinh(&self) -> Self;
+    assert_eq!(a.erased, b.erased);
+    assert_eq!(a.erased, ErasedExpr::LitInt(3));
+    assert_eq!(b.erased, ErasedExpr::LitInt(3));
 
-  fn cosh(&self) -> Self;
+    let a = lit!(1i32) - lit!(2);
+    let b = lit!(1i32) - 2;
 
-  fn tanh(&self) -> Self;
+    assert_eq!(a.erased, b.erased);
+    assert_eq!(a.erased, ErasedExpr::LitInt(-1));
+    assert_eq!(b.erased, ErasedExpr::LitInt(-1));
 
-  fn asinh(&self) -> Self;
+    let a = lit!(1i32) * lit!(2);
+    let b = lit!(1i32) * 2;
 
-  fn acosh(&self) -> Self;
+    assert_eq!(a.erased, b.erased);
+    assert_eq!(a.erased, ErasedExpr::LitInt(2));
+    assert_eq!(b.erased, ErasedExpr::LitInt(2));
 
-  fn atanh(&self) -> Self;
-}
+    let a = lit!(1i32) / lit!(2);
+    let b = lit!(1i32) / 2;
 
-macro_rules! impl_Trigonometry {
-  ($t:ty) => {
-    impl Trigonometry for Expr<$t> {
-      fn radians(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Radians,
-          vec![self.erased.clone()],
-        ))
-      }
+    assert_eq!(a.erased, b.erased);
+    assert_eq!(a.erased, ErasedExpr::LitInt(0));
+    assert_eq!(b.erased, ErasedExpr::LitInt(0));
 
-      fn degrees(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Degrees,
-          vec![self.erased.clone()],
-        ))
-      }
+    let mut scope = Scope::<()>::new(0);
+    let x = scope.var(1i32);
+    let a = &x + lit!(2);
+    let b = lit!(1i32) / 0;
 
-      fn sin(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Sin,
-          vec![self.erased.clone()],
-        ))
-      }
+    assert_eq!(
+      a.erased,
+      ErasedExpr::Add(
+        Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+        Arc::new(ErasedExpr::LitInt(2)),
+      )
+    );
+    assert_eq!(
+      b.erased,
+      ErasedExpr::Div(
+        Arc::new(ErasedExpr::LitInt(1)),
+        Arc::new(ErasedExpr::LitInt(0)),
+      )
+    );
+  }
 
-      fn cos(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Cos,
-          vec![self.erased.clone()],
-        ))
-      }
+  #[test]
+  fn expr_binary_scalar_lhs() {
+    let a = 1i32 + lit!(2i32);
+    let b = 2. * lit!(1., 2.);
 
-      fn tan(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Tan,
-          vec![self.erased.clone()],
-        ))
-      }
+    assert_eq!(a.erased, ErasedExpr::LitInt(3));
+    assert_eq!(b.erased, ErasedExpr::LitFloat2([2., 4.]));
+  }
 
-      fn asin(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::ASin,
-          vec![self.erased.clone()],
-        ))
-      }
+  #[test]
+  fn matrix_mul() {
+    let m: Expr<M22> = Matrix::from([[1., 2.], [3., 4.]]).into();
+    let v = lit!(1., 0.);
 
-      fn acos(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::ACos,
-          vec![self.erased.clone()],
-        ))
-      }
+    let column: Expr<V2<f32>> = &m * &v;
+    let row: Expr<V2<f32>> = &v * &m;
+    let product: Expr<M22> = &m * &m;
+    let scaled: Expr<M22> = 2. * (&m * lit!(0.5));
 
-      fn atan(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::ATan,
-          vec![self.erased.clone()],
-        ))
-      }
+    assert_eq!(
+      column.erased,
+      ErasedExpr::Mul(m.erased.clone().intern(), v.erased.clone().intern())
+    );
+    assert_eq!(
+      row.erased,
+      ErasedExpr::Mul(v.erased.clone().intern(), m.erased.clone().intern())
+    );
+    assert_eq!(
+      product.erased,
+      ErasedExpr::Mul(m.erased.clone().intern(), m.erased.clone().intern())
+    );
+    assert_eq!(
+      scaled.erased,
+      ErasedExpr::Mul(
+        ErasedExpr::LitFloat(2.).intern(),
+        ErasedExpr::Mul(
+          m.erased.clone().intern(),
+          ErasedExpr::LitFloat(0.5).intern()
+        )
+        .intern()
+      )
+    );
+  }
 
-      fn sinh(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::SinH,
-          vec![self.erased.clone()],
-        ))
-      }
+  #[test]
+  fn fold_literals() {
+    assert_eq!(
+      (lit!(1., 2.) * 2. + lit!(1., 1.)).erased,
+      ErasedExpr::LitFloat2([3., 5.])
+    );
+    assert_eq!((lit!(7u32) << 2u32).erased, ErasedExpr::LitUInt(28));
+    assert_eq!((lit!(-7.) % 2.).erased, ErasedExpr::LitFloat(1.));
+    assert_eq!(lit!(2i32).lt(3).and(true).erased, ErasedExpr::LitBool(true));
+    assert_eq!(
+      lit!(1i32, 2).eq(lit!(1, 2)).erased,
+      ErasedExpr::LitBool(true)
+    );
 
-      fn cosh(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::CosH,
-          vec![self.erased.clone()],
-        ))
-      }
+    // undefined or not representable results are not folded
+    assert_eq!(
+      (lit!(1.) / 0.).erased,
+      ErasedExpr::Div(
+        Arc::new(ErasedExpr::LitFloat(1.)),
+        Arc::new(ErasedExpr::LitFloat(0.))
+      )
+    );
+    assert_eq!(
+      (lit!(1.) % 0.).erased,
+      ErasedExpr::Rem(
+        Arc::new(ErasedExpr::LitFloat(1.)),
+        Arc::new(ErasedExpr::LitFloat(0.))
+      )
+    );
+    assert_eq!(
+      (lit!(1u32) << 32u32).erased,
+      ErasedExpr::Shl(
+        Arc::new(ErasedExpr::LitUInt(1)),
+        Arc::new(ErasedExpr::LitUInt(32))
+      )
+    );
+  }
 
-      fn tanh(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::TanH,
-          vec![self.erased.clone()],
-        ))
-      }
+  #[test]
+  fn intern_exprs() {
+    let x = Expr::<f32>::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0)));
+    let a = (&x + 1.) * 2.;
+    let b = (&x + 1.) * 2.;
+
+    // identical expressions built independently share their sub-expressions
+    match (&a.erased, &b.erased) {
+      (ErasedExpr::Mul(a, _), ErasedExpr::Mul(b, _)) => assert!(Arc::ptr_eq(a, b)),
+      _ => panic!("unexpected expressions"),
+    }
 
-      fn asinh(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::ASinH,
-          vec![self.erased.clone()],
-        ))
-      }
+    // special floating-point values are not mixed up
+    match ((&x + 0.).erased, (&x + -0.).erased) {
+      (ErasedExpr::Add(_, a), ErasedExpr::Add(_, b)) => assert!(!Arc::ptr_eq(&a, &b)),
+      _ => panic!("unexpected expressions"),
+    }
+  }
 
-      fn acosh(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::ACosH,
-          vec![self.erased.clone()],
-        ))
-      }
+  #[test]
+  fn share_fun_call_args() {
+    let mut e = Expr::<f32>::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0)));
 
-      fn atanh(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::ATanH,
-          vec![self.erased.clone()],
-        ))
-      }
+    // copying the arguments would build a tree of 2⁶⁴ nodes
+    for _ in 0..64 {
+      e = e.max(&e);
     }
-  };
-}
 
-impl_Trigonometry!(f32);
-impl_Trigonometry!(V2<f32>);
-impl_Trigonometry!(V3<f32>);
-impl_Trigonometry!(V4<f32>);
+    match &e.erased {
+      ErasedExpr::FunCall(ErasedFunHandle::Max, args) => assert!(Arc::ptr_eq(&args[0], &args[1])),
+      _ => panic!("unexpected expression"),
+    }
+  }
 
-pub trait Exponential: Sized {
-  fn pow(&self, p: impl Into<Self>) -> Self;
+  #[cfg(feature = "arbitrary")]
+  #[test]
+  fn arbitrary_shaders() {
+    use arbitrary::{Arbitrary as _, Unstructured};
+
+    // xorshift, so that the test is deterministic
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut bytes = vec![0; 4096];
+
+    for _ in 0..256 {
+      for byte in &mut bytes {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        *byte = state as u8;
+      }
 
-  fn exp(&self) -> Self;
+      let mut shader = Shader::<stage::Vertex>::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+      let _ = writer::glsl::write_shader_to_str(&shader);
 
-  fn exp2(&self) -> Self;
+      pass::simplify(&mut shader);
+      pass::eliminate_common_subexpressions(&mut shader);
+      pass::unroll_loops(&mut shader, 4);
+      let _ = writer::glsl::write_shader_to_str(&shader);
+    }
+  }
 
-  fn log(&self) -> Self;
+  #[test]
+  fn expr_ref_inference() {
+    let a = lit!(1i32);
+    let b = a.clone() + 1;
+    let c = a + 1;
 
-  fn log2(&self) -> Self;
+    assert_eq!(b.erased, c.erased);
+  }
 
-  fn sqrt(&self) -> Self;
+  #[test]
+  fn expr_var() {
+    let mut scope = Scope::<()>::new(0);
 
-  fn isqrt(&self) -> Self;
-}
+    let x = scope.var(0);
+    let y = scope.var(1u32);
+    let z = scope.var(lit![false, true, false]);
 
-macro_rules! impl_Exponential {
-  ($t:ty) => {
-    impl Exponential for Expr<$t> {
-      fn pow(&self, p: impl Into<Self>) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Pow,
-          vec![self.erased.clone(), p.into().erased],
-        ))
+    assert_eq!(x.erased, ErasedExpr::Var(ScopedHandle::fun_var(0, 0)));
+    assert_eq!(y.erased, ErasedExpr::Var(ScopedHandle::fun_var(0, 1)));
+    assert_eq!(z.erased, ErasedExpr::Var(ScopedHandle::fun_var(0, 2)));
+    assert_eq!(scope.erased.instructions.len(), 3);
+    assert_eq!(
+      scope.erased.instructions[0],
+      ScopeInstr::VarDecl {
+        ty: Type {
+          prim_ty: PrimType::Int(Dim::Scalar),
+          array_dims: Vec::new(),
+        },
+        handle: ScopedHandle::fun_var(0, 0),
+        init_value: ErasedExpr::LitInt(0),
+      }
+    );
+    assert_eq!(
+      scope.erased.instructions[1],
+      ScopeInstr::VarDecl {
+        ty: Type {
+          prim_ty: PrimType::UInt(Dim::Scalar),
+          array_dims: Vec::new(),
+        },
+        handle: ScopedHandle::fun_var(0, 1),
+        init_value: ErasedExpr::LitUInt(1),
+      }
+    );
+    assert_eq!(
+      scope.erased.instructions[2],
+      ScopeInstr::VarDecl {
+        ty: Type {
+          prim_ty: PrimType::Bool(Dim::D3),
+          array_dims: Vec::new(),
+        },
+        handle: ScopedHandle::fun_var(0, 2),
+        init_value: ErasedExpr::LitBool3([false, true, false]),
       }
+    );
+  }
+
+  #[test]
+  fn min_max_clamp() {
+    let a = lit!(1i32);
+    let b = lit!(2);
+    let c = lit!(3);
 
-      fn exp(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Exp,
-          vec![self.erased.clone()],
-        ))
-      }
+    assert_eq!(
+      a.min(&b).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Min,
+        vec![ErasedExpr::LitInt(1), ErasedExpr::LitInt(2)],
+      )
+    );
 
-      fn exp2(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Exp2,
-          vec![self.erased.clone()],
-        ))
-      }
+    assert_eq!(
+      a.max(&b).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Max,
+        vec![ErasedExpr::LitInt(1), ErasedExpr::LitInt(2)],
+      )
+    );
 
-      fn log(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Log,
-          vec![self.erased.clone()],
-        ))
-      }
+    assert_eq!(
+      a.clamp(b, c).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Clamp,
+        vec![
+          ErasedExpr::LitInt(1),
+          ErasedExpr::LitInt(2),
+          ErasedExpr::LitInt(3)
+        ],
+      )
+    );
+  }
 
-      fn log2(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Log2,
-          vec![self.erased.clone()],
-        ))
-      }
+  #[test]
+  fn geometry() {
+    let a = lit!(1., 2., 3.);
+    let b = lit!(4., 5., 6.);
 
-      fn sqrt(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Sqrt,
-          vec![self.erased.clone()],
-        ))
-      }
+    let dot: Expr<f32> = a.dot(&b);
+    assert_eq!(
+      dot.erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Dot,
+        vec![a.erased.clone(), b.erased.clone()],
+      )
+    );
 
-      fn isqrt(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::InverseSqrt,
-          vec![self.erased.clone()],
-        ))
-      }
-    }
-  };
-}
+    let distance: Expr<f32> = a.distance(&b);
+    assert_eq!(
+      distance.erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Distance,
+        vec![a.erased.clone(), b.erased.clone()],
+      )
+    );
 
-impl_Exponential!(f32);
-impl_Exponential!(V2<f32>);
-impl_Exponential!(V3<f32>);
-impl_Exponential!(V4<f32>);
+    let cross: Expr<V3<f32>> = a.cross(&b);
+    assert_eq!(
+      cross.erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Cross,
+        vec![a.erased.clone(), b.erased.clone()],
+      )
+    );
 
-pub trait Relative {
-  fn abs(&self) -> Self;
+    let length: Expr<f32> = cross.normalize().length();
+    assert_eq!(
+      length.erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Length,
+        vec![ErasedExpr::fun_call(
+          ErasedFunHandle::Normalize,
+          vec![cross.erased.clone()],
+        )],
+      )
+    );
+  }
 
-  fn sign(&self) -> Self;
-}
+  #[test]
+  fn lighting() {
+    let i = lit!(1., -1.);
+    let n = lit!(0., 1.);
 
-macro_rules! impl_Relative {
-  ($t:ty) => {
-    impl Relative for Expr<$t> {
-      fn abs(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Abs,
-          vec![self.erased.clone()],
-        ))
-      }
+    assert_eq!(
+      i.face_forward(&n, &n).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::FaceForward,
+        vec![n.erased.clone(), i.erased.clone(), n.erased.clone()],
+      )
+    );
 
-      fn sign(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Sign,
-          vec![self.erased.clone()],
-        ))
-      }
-    }
-  };
-}
+    assert_eq!(
+      i.reflect(&n).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Reflect,
+        vec![i.erased.clone(), n.erased.clone()],
+      )
+    );
 
-impl_Relative!(i32);
-impl_Relative!(V2<i32>);
-impl_Relative!(V3<i32>);
-impl_Relative!(V4<i32>);
-impl_Relative!(f32);
-impl_Relative!(V2<f32>);
-impl_Relative!(V3<f32>);
-impl_Relative!(V4<f32>);
+    assert_eq!(
+      i.refract(&n, 1.5).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Refract,
+        vec![
+          i.erased.clone(),
+          n.erased.clone(),
+          ErasedExpr::LitFloat(1.5)
+        ],
+      )
+    );
+  }
 
-pub trait Floating {
-  fn floor(&self) -> Self;
+  #[test]
+  fn step_smooth_step() {
+    let x = lit!(0.2, 0.5, 0.8);
 
-  fn trunc(&self) -> Self;
+    assert_eq!(
+      x.step(lit!(0.5)).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Step,
+        vec![ErasedExpr::LitFloat(0.5), x.erased.clone()],
+      )
+    );
 
-  fn round(&self) -> Self;
+    assert_eq!(
+      x.step(lit!(0.1, 0.6, 0.7)).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Step,
+        vec![ErasedExpr::LitFloat3([0.1, 0.6, 0.7]), x.erased.clone()],
+      )
+    );
 
-  fn ceil(&self) -> Self;
+    assert_eq!(
+      x.smooth_step(lit!(0.25), lit!(0.75)).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::SmoothStep,
+        vec![
+          ErasedExpr::LitFloat(0.25),
+          ErasedExpr::LitFloat(0.75),
+          x.erased.clone()
+        ],
+      )
+    );
+  }
 
-  fn fract(&self) -> Self;
-}
+  #[test]
+  fn scalar_bounds() {
+    let v = lit!(1., 2., 3.);
 
-macro_rules! impl_Floating {
-  ($t:ty) => {
-    impl Floating for Expr<$t> {
-      fn floor(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Floor,
-          vec![self.erased.clone()],
-        ))
-      }
+    assert_eq!(
+      v.min_scalar(2.).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Min,
+        vec![v.erased.clone(), ErasedExpr::LitFloat(2.)],
+      )
+    );
 
-      fn trunc(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Trunc,
-          vec![self.erased.clone()],
-        ))
-      }
+    assert_eq!(
+      v.max_scalar(lit!(2.)).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Max,
+        vec![v.erased.clone(), ErasedExpr::LitFloat(2.)],
+      )
+    );
 
-      fn round(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Round,
-          vec![self.erased.clone()],
-        ))
-      }
+    assert_eq!(
+      v.clamp_scalar(0., 1.).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Clamp,
+        vec![
+          v.erased.clone(),
+          ErasedExpr::LitFloat(0.),
+          ErasedExpr::LitFloat(1.)
+        ],
+      )
+    );
+  }
 
-      fn ceil(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Ceil,
-          vec![self.erased.clone()],
-        ))
-      }
+  #[test]
+  fn pow_scalar() {
+    let x = lit!(0.5);
+    let v = lit!(0.25, 0.5, 1.);
 
-      fn fract(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Fract,
-          vec![self.erased.clone()],
-        ))
-      }
-    }
-  };
-}
+    assert_eq!(
+      x.pow_scalar(2.2).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Pow,
+        vec![ErasedExpr::LitFloat(0.5), ErasedExpr::LitFloat(2.2)],
+      )
+    );
 
-impl_Floating!(f32);
-impl_Floating!(V2<f32>);
-impl_Floating!(V3<f32>);
-impl_Floating!(V4<f32>);
+    let gamma = v.pow_scalar(2.2);
+    assert_eq!(
+      gamma.erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Pow,
+        vec![
+          v.erased.clone(),
+          ErasedExpr::Cast(V3::<f32>::ty(), ErasedExpr::LitFloat(2.2).intern())
+        ],
+      )
+    );
+    assert_eq!(
+      writer::glsl::write_expr_to_str(&gamma).unwrap(),
+      "pow(vec3(.25, .5, 1.), vec3(2.2))"
+    );
+  }
 
-pub trait Bounded: Sized {
-  fn min(&self, rhs: impl Into<Self>) -> Self;
+  #[test]
+  fn mod_modf() {
+    let mut scope = Scope::<()>::new(0);
+    let a = lit!(-1.5f32);
+    let int_part = scope.var(0.);
 
-  fn max(&self, rhs: impl Into<Self>) -> Self;
+    assert_eq!(
+      a.mod_euclid(2.).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Mod,
+        vec![ErasedExpr::LitFloat(-1.5), ErasedExpr::LitFloat(2.)],
+      )
+    );
 
-  fn clamp(&self, min_value: impl Into<Self>, max_value: impl Into<Self>) -> Self;
-}
+    assert_eq!(
+      a.modf(&int_part).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Modf,
+        vec![
+          ErasedExpr::LitFloat(-1.5),
+          ErasedExpr::Var(ScopedHandle::fun_var(0, 0))
+        ],
+      )
+    );
+  }
 
-macro_rules! impl_Bounded {
-  ($t:ty) => {
-    impl Bounded for Expr<$t> {
-      fn min(&self, rhs: impl Into<Self>) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Min,
-          vec![self.erased.clone(), rhs.into().erased],
-        ))
-      }
+  #[test]
+  fn cast() {
+    assert_eq!(
+      lit!(1i32).as_f32().erased,
+      ErasedExpr::Cast(f32::ty(), Arc::new(ErasedExpr::LitInt(1)))
+    );
+    assert_eq!(
+      lit!(1., 2.).as_bool().erased,
+      ErasedExpr::Cast(
+        <V2<bool> as ToType>::ty(),
+        Arc::new(ErasedExpr::LitFloat2([1., 2.]))
+      )
+    );
+  }
 
-      fn max(&self, rhs: impl Into<Self>) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Max,
-          vec![self.erased.clone(), rhs.into().erased],
-        ))
-      }
+  #[test]
+  fn pack_unpack() {
+    assert_eq!(
+      lit!(0.5, 1.).pack_unorm2x16().erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::PackUnorm2x16,
+        vec![ErasedExpr::LitFloat2([0.5, 1.])],
+      )
+    );
 
-      fn clamp(&self, min_value: impl Into<Self>, max_value: impl Into<Self>) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Clamp,
-          vec![
-            self.erased.clone(),
-            min_value.into().erased,
-            max_value.into().erased,
-          ],
-        ))
-      }
-    }
-  };
-}
+    assert_eq!(
+      lit!(0u32).unpack_snorm4x8().erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::UnpackSnorm4x8,
+        vec![ErasedExpr::LitUInt(0)],
+      )
+    );
+  }
 
-impl_Bounded!(i32);
-impl_Bounded!(V2<i32>);
-impl_Bounded!(V3<i32>);
-impl_Bounded!(V4<i32>);
+  #[test]
+  fn fma_frexp_ldexp() {
+    let mut scope = Scope::<()>::new(0);
+    let a = lit!(3.0f32);
+    let exp = scope.var(0);
 
-impl_Bounded!(u32);
-impl_Bounded!(V2<u32>);
-impl_Bounded!(V3<u32>);
-impl_Bounded!(V4<u32>);
+    assert_eq!(
+      a.fma(2., 1.).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Fma,
+        vec![
+          ErasedExpr::LitFloat(3.),
+          ErasedExpr::LitFloat(2.),
+          ErasedExpr::LitFloat(1.)
+        ],
+      )
+    );
 
-impl_Bounded!(f32);
-impl_Bounded!(V2<f32>);
-impl_Bounded!(V3<f32>);
-impl_Bounded!(V4<f32>);
+    assert_eq!(
+      a.frexp(&exp).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Frexp,
+        vec![
+          ErasedExpr::LitFloat(3.),
+          ErasedExpr::Var(ScopedHandle::fun_var(0, 0))
+        ],
+      )
+    );
 
-impl_Bounded!(bool);
-impl_Bounded!(V2<bool>);
-impl_Bounded!(V3<bool>);
-impl_Bounded!(V4<bool>);
+    assert_eq!(
+      a.ldexp(&exp).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Ldexp,
+        vec![
+          ErasedExpr::LitFloat(3.),
+          ErasedExpr::Var(ScopedHandle::fun_var(0, 0))
+        ],
+      )
+    );
+  }
 
-pub trait Mix<RHS>: Sized {
-  fn mix(&self, y: impl Into<Self>, a: RHS) -> Self;
+  #[test]
+  fn bitfield() {
+    let a = lit!(0xF0u32);
 
-  fn step(&self, edge: RHS) -> Self;
+    assert_eq!(
+      a.bitfield_extract(4, 4).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::BitfieldExtract,
+        vec![
+          ErasedExpr::LitUInt(0xF0),
+          ErasedExpr::LitInt(4),
+          ErasedExpr::LitInt(4)
+        ],
+      )
+    );
 
-  fn smooth_step(&self, edge_a: RHS, edge_b: RHS) -> Self;
-}
+    assert_eq!(
+      a.bitfield_insert(1u32, 0, 1).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::BitfieldInsert,
+        vec![
+          ErasedExpr::LitUInt(0xF0),
+          ErasedExpr::LitUInt(1),
+          ErasedExpr::LitInt(0),
+          ErasedExpr::LitInt(1)
+        ],
+      )
+    );
 
-macro_rules! impl_Mix {
-  ($t:ty, $q:ty) => {
-    impl Mix<Expr<$q>> for Expr<$t> {
-      fn mix(&self, y: impl Into<Self>, a: Expr<$q>) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Mix,
-          vec![self.erased.clone(), y.into().erased, a.erased],
-        ))
-      }
+    let count: Expr<i32> = a.bit_count();
+    assert_eq!(
+      count.erased,
+      ErasedExpr::fun_call(ErasedFunHandle::BitCount, vec![ErasedExpr::LitUInt(0xF0)])
+    );
+  }
 
-      fn step(&self, edge: Expr<$q>) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Step,
-          vec![edge.erased, self.erased.clone()],
-        ))
-      }
+  #[test]
+  fn vector_relational() {
+    let a = lit!(1i32, 2);
+    let lt = a.less_than(lit!(2, 2));
 
-      fn smooth_step(&self, edge_a: Expr<$q>, edge_b: Expr<$q>) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::SmoothStep,
-          vec![edge_a.erased, edge_b.erased, self.erased.clone()],
-        ))
-      }
-    }
-  };
-}
+    assert_eq!(
+      lt.erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::VLt,
+        vec![ErasedExpr::LitInt2([1, 2]), ErasedExpr::LitInt2([2, 2])],
+      )
+    );
 
-impl_Mix!(f32, f32);
-impl_Mix!(V2<f32>, f32);
-impl_Mix!(V2<f32>, V2<f32>);
+    assert_eq!(
+      VectorBool::not(&lt).any().erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::VAny,
+        vec![ErasedExpr::fun_call(
+          ErasedFunHandle::VNot,
+          vec![lt.erased.clone()]
+        )],
+      )
+    );
 
-impl_Mix!(V3<f32>, f32);
-impl_Mix!(V3<f32>, V3<f32>);
+    let eq = lt.equal(lit!(true, false));
+    assert_eq!(
+      eq.erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::VEq,
+        vec![lt.erased.clone(), ErasedExpr::LitBool2([true, false])],
+      )
+    );
+    assert_eq!(
+      lt.not_equal(&eq).erased,
+      ErasedExpr::fun_call(ErasedFunHandle::VNeq, vec![lt.erased, eq.erased]),
+    );
+  }
 
-impl_Mix!(V4<f32>, f32);
-impl_Mix!(V4<f32>, V4<f32>);
+  #[test]
+  fn derivatives() {
+    let env = FragmentShaderEnv::new();
+    let dx = env.dfdx(&env.frag_coord);
+    let w = env.fwidth_coarse(lit!(1.0f32));
 
-pub trait FloatingExt {
-  type BoolExpr;
+    assert_eq!(
+      dx.erased,
+      ErasedExpr::fun_call(ErasedFunHandle::DfDx, vec![env.frag_coord.erased.clone()])
+    );
+    assert_eq!(
+      w.erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::FWidthCoarse,
+        vec![ErasedExpr::LitFloat(1.)]
+      )
+    );
+  }
 
-  fn is_nan(&self) -> Self::BoolExpr;
+  #[test]
+  fn interpolate_at() {
+    let env = FragmentShaderEnv::new();
+    let color = Var::<V3<f32>>::new(ScopedHandle::Input("color".to_owned()));
 
-  fn is_inf(&self) -> Self::BoolExpr;
-}
+    assert_eq!(
+      env.interpolate_at_sample(&color, 2).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::InterpolateAtSample,
+        vec![
+          ErasedExpr::Var(ScopedHandle::Input("color".to_owned())),
+          ErasedExpr::LitInt(2)
+        ],
+      )
+    );
+  }
 
-macro_rules! impl_FloatingExt {
-  ($t:ty, $bool_expr:ty) => {
-    impl FloatingExt for Expr<$t> {
-      type BoolExpr = Expr<$bool_expr>;
+  #[test]
+  fn geometry_primitives() {
+    let env = GeometryShaderEnv::new();
+    let mut scope = Scope::<()>::new(0);
 
-      fn is_nan(&self) -> Self::BoolExpr {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::IsNan,
-          vec![self.erased.clone()],
-        ))
-      }
+    env.emit_vertex(&mut scope);
+    env.end_stream_primitive(&mut scope, 1);
 
-      fn is_inf(&self) -> Self::BoolExpr {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::IsInf,
-          vec![self.erased.clone()],
-        ))
-      }
-    }
-  };
-}
+    assert_eq!(
+      scope.erased.instructions,
+      vec![
+        ScopeInstr::Call(ErasedFunHandle::EmitVertex, vec![]),
+        ScopeInstr::Call(
+          ErasedFunHandle::EndStreamPrimitive,
+          vec![ErasedExpr::LitInt(1)]
+        ),
+      ]
+    );
+  }
 
-impl_FloatingExt!(f32, bool);
-impl_FloatingExt!(V2<f32>, V2<bool>);
-impl_FloatingExt!(V3<f32>, V3<bool>);
-impl_FloatingExt!(V4<f32>, V4<bool>);
+  #[test]
+  fn barriers() {
+    let env = TessCtrlShaderEnv::new();
+    let mut scope = Scope::<()>::new(0);
 
-pub trait Geometry: Sized {
-  type LengthExpr;
+    env.memory_barrier_buffer(&mut scope);
+    env.barrier(&mut scope);
 
-  fn length(&self) -> Self::LengthExpr;
+    assert_eq!(
+      scope.erased.instructions,
+      vec![
+        ScopeInstr::Call(ErasedFunHandle::MemoryBarrierBuffer, vec![]),
+        ScopeInstr::Call(ErasedFunHandle::Barrier, vec![]),
+      ]
+    );
+  }
 
-  fn distance(&self, other: impl Into<Self>) -> Self::LengthExpr;
+  #[test]
+  fn if_else_expr() {
+    let mut scope = Scope::<()>::new(0);
+    let x = scope.if_else(true, |_| lit!(1), |_| lit!(2));
 
-  fn dot(&self, other: impl Into<Self>) -> Self::LengthExpr;
+    assert_eq!(x.erased, ErasedExpr::Var(ScopedHandle::fun_var(0, 0)));
 
-  fn cross(&self, other: impl Into<Self>) -> Self;
+    let mut then_scope = ErasedScope::new(1);
+    then_scope.instructions.push(ScopeInstr::MutateVar {
+      var: ErasedExpr::Var(ScopedHandle::fun_var(0, 0)),
+      expr: ErasedExpr::LitInt(1),
+    });
 
-  fn normalize(&self) -> Self;
+    let mut else_scope = ErasedScope::new(2);
+    else_scope.instructions.push(ScopeInstr::MutateVar {
+      var: ErasedExpr::Var(ScopedHandle::fun_var(0, 0)),
+      expr: ErasedExpr::LitInt(2),
+    });
 
-  fn face_forward(&self, normal: impl Into<Self>, reference: impl Into<Self>) -> Self;
+    assert_eq!(
+      scope.erased.instructions,
+      vec![
+        ScopeInstr::VarDeclUninit {
+          ty: i32::ty(),
+          handle: ScopedHandle::fun_var(0, 0),
+        },
+        ScopeInstr::If {
+          condition: ErasedExpr::LitBool(true),
+          scope: then_scope,
+        },
+        ScopeInstr::Else { scope: else_scope },
+      ]
+    );
+  }
 
-  fn reflect(&self, normal: impl Into<Self>) -> Self;
+  #[test]
+  fn labeled_loop() {
+    let mut scope = Scope::<()>::new(0);
 
-  fn refract(&self, normal: impl Into<Self>, eta: impl Into<Expr<f32>>) -> Self;
-}
+    scope.loop_labeled(|s, outer| {
+      s.loop_while(true, |s| {
+        s.loop_while(true, |s| {
+          s.loop_break_to(outer);
+        });
+      });
+    });
 
-macro_rules! impl_Geometry {
-  ($t:ty, $l:ty) => {
-    impl Geometry for Expr<$t> {
-      type LengthExpr = Expr<$l>;
+    let flag = ErasedExpr::Var(ScopedHandle::fun_var(0, 0));
 
-      fn length(&self) -> Self::LengthExpr {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Length,
-          vec![self.erased.clone()],
-        ))
-      }
+    let mut inner = ErasedScope::new(2);
+    inner.instructions.push(ScopeInstr::MutateVar {
+      var: flag.clone(),
+      expr: ErasedExpr::LitBool(true),
+    });
+    inner.instructions.push(ScopeInstr::Break);
 
-      fn distance(&self, other: impl Into<Self>) -> Self::LengthExpr {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Distance,
-          vec![self.erased.clone(), other.into().erased],
-        ))
-      }
+    let mut check = ErasedScope::new(2);
+    check.instructions.push(ScopeInstr::Break);
 
-      fn dot(&self, other: impl Into<Self>) -> Self::LengthExpr {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Dot,
-          vec![self.erased.clone(), other.into().erased],
-        ))
-      }
+    let mut outer = ErasedScope::new(1);
+    outer.instructions.push(ScopeInstr::While {
+      condition: ErasedExpr::LitBool(true),
+      scope: inner,
+    });
+    outer.instructions.push(ScopeInstr::If {
+      condition: flag,
+      scope: check,
+    });
 
-      fn cross(&self, other: impl Into<Self>) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Cross,
-          vec![self.erased.clone(), other.into().erased],
-        ))
-      }
+    assert_eq!(
+      scope.erased.instructions,
+      vec![
+        ScopeInstr::VarDecl {
+          ty: bool::ty(),
+          handle: ScopedHandle::fun_var(0, 0),
+          init_value: ErasedExpr::LitBool(false),
+        },
+        ScopeInstr::While {
+          condition: ErasedExpr::LitBool(true),
+          scope: outer,
+        },
+      ]
+    );
+  }
 
-      fn normalize(&self) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Normalize,
-          vec![self.erased.clone()],
-        ))
-      }
+  #[test]
+  fn for2_loop() {
+    let mut scope = Scope::<()>::new(0);
+    scope.loop_for2(
+      (0, 1.),
+      |a, _| a.lt(10),
+      |a, b| (a + 1, b * 2.),
+      |_, _, _| {},
+    );
 
-      fn face_forward(&self, normal: impl Into<Self>, reference: impl Into<Self>) -> Self {
-        // note: this function call is super weird as the normal and incident (i.e. self) arguments are swapped
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::FaceForward,
-          vec![
-            normal.into().erased,
-            self.erased.clone(),
-            reference.into().erased,
-          ],
-        ))
-      }
+    let a = ErasedExpr::Var(ScopedHandle::fun_var(0, 0));
+    let b = ErasedExpr::Var(ScopedHandle::fun_var(0, 1));
+    let tmp = ErasedExpr::Var(ScopedHandle::fun_var(0, 2));
 
-      fn reflect(&self, normal: impl Into<Self>) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Reflect,
-          vec![self.erased.clone(), normal.into().erased],
-        ))
+    assert_eq!(scope.erased.instructions.len(), 4);
+    assert_eq!(
+      scope.erased.instructions[2],
+      ScopeInstr::VarDeclUninit {
+        ty: i32::ty(),
+        handle: ScopedHandle::fun_var(0, 2),
       }
-
-      fn refract(&self, normal: impl Into<Self>, eta: impl Into<Expr<f32>>) -> Self {
-        Expr::new(ErasedExpr::FunCall(
-          ErasedFunHandle::Refract,
-          vec![self.erased.clone(), normal.into().erased, eta.into().erased],
-        ))
+    );
+    assert_eq!(
+      scope.erased.instructions[3],
+      ScopeInstr::ForMulti {
+        condition: ErasedExpr::Lt(Arc::new(a.clone()), Arc::new(ErasedExpr::LitInt(10))),
+        post_exprs: vec![
+          (
+            tmp.clone(),
+            ErasedExpr::Add(Arc::new(a.clone()), Arc::new(ErasedExpr::LitInt(1)))
+          ),
+          (
+            b.clone(),
+            ErasedExpr::Mul(Arc::new(b), Arc::new(ErasedExpr::LitFloat(2.)))
+          ),
+          (a, tmp),
+        ],
+        scope: ErasedScope::new(1),
       }
-    }
-  };
-}
-
-impl_Geometry!(V2<f32>, f32);
-impl_Geometry!(V3<f32>, f32);
-impl_Geometry!(V4<f32>, f32);
-
-#[cfg(test)]
-mod tests {
-  use super::*;
+    );
+  }
 
   #[test]
-  fn expr_lit() {
-    assert_eq!(lit!(true).erased, ErasedExpr::LitBool(true));
-    assert_eq!(lit![1, 2].erased, ErasedExpr::LitInt2([1, 2]));
+  fn var_uninit() {
+    let mut scope = Scope::<()>::new(0);
+    let x = scope.var_uninit::<V2<f32>>();
+    scope.set(&x, lit!(1., 2.));
+
+    assert_eq!(x.0.erased, ErasedExpr::Var(ScopedHandle::fun_var(0, 0)));
+    assert_eq!(
+      scope.erased.instructions[0],
+      ScopeInstr::VarDeclUninit {
+        ty: V2::<f32>::ty(),
+        handle: ScopedHandle::fun_var(0, 0),
+      }
+    );
   }
 
   #[test]
-  fn expr_unary() {
+  fn fold_loop() {
     let mut scope = Scope::<()>::new(0);
+    let sum = scope.loop_fold(1, |a| a.lt(100), |a| a * 2, |_, _| {});
 
-    let a = !lit!(true);
-    let b = -lit!(3i32);
-    let c = scope.var(17);
+    let a = ErasedExpr::Var(ScopedHandle::fun_var(0, 0));
 
+    assert_eq!(sum.erased, a);
+    assert_eq!(scope.erased.instructions.len(), 2);
     assert_eq!(
-      a.erased,
-      ErasedExpr::Not(Box::new(ErasedExpr::LitBool(true)))
+      scope.erased.instructions[1],
+      ScopeInstr::ForMulti {
+        condition: ErasedExpr::Lt(Arc::new(a.clone()), Arc::new(ErasedExpr::LitInt(100))),
+        post_exprs: vec![(
+          a.clone(),
+          ErasedExpr::Mul(Arc::new(a), Arc::new(ErasedExpr::LitInt(2)))
+        )],
+        scope: ErasedScope::new(1),
+      }
     );
-    assert_eq!(b.erased, ErasedExpr::Neg(Box::new(ErasedExpr::LitInt(3))));
-    assert_eq!(c.erased, ErasedExpr::Var(ScopedHandle::fun_var(0, 0)));
   }
 
   #[test]
-  fn expr_binary() {
-    let a = lit!(1i32) + lit!(2);
-    let b = lit!(1i32) + 2;
+  fn range_loop() {
+    let mut scope = Scope::<()>::new(0);
+    scope.loop_range(2, 5, |_, _| {});
 
-    assert_eq!(a.erased, b.erased);
-    assert_eq!(
-      a.erased,
-      ErasedExpr::Add(
-        Box::new(ErasedExpr::LitInt(1)),
-        Box::new(ErasedExpr::LitInt(2)),
-      )
-    );
     assert_eq!(
-      b.erased,
-      ErasedExpr::Add(
-        Box::new(ErasedExpr::LitInt(1)),
-        Box::new(ErasedExpr::LitInt(2)),
-      )
+      scope.erased.instructions[0],
+      ScopeInstr::For {
+        init_ty: i32::ty(),
+        init_handle: ScopedHandle::fun_var(1, 0),
+        init_expr: ErasedExpr::LitInt(2),
+        condition: ErasedExpr::Lt(
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(1, 0))),
+          Arc::new(ErasedExpr::LitInt(5)),
+        ),
+        post_expr: ErasedExpr::Add(
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(1, 0))),
+          Arc::new(ErasedExpr::LitInt(1)),
+        ),
+        scope: ErasedScope {
+          id: 1,
+          instructions: vec![],
+          locations: vec![],
+          next_var: 1,
+        },
+      }
     );
+  }
 
-    let a = lit!(1i32) - lit!(2);
-    let b = lit!(1i32) - 2;
+  #[test]
+  fn for_each_loop() {
+    let mut scope = Scope::<()>::new(0);
 
-    assert_eq!(a.erased, b.erased);
-    assert_eq!(
-      a.erased,
-      ErasedExpr::Sub(
-        Box::new(ErasedExpr::LitInt(1)),
-        Box::new(ErasedExpr::LitInt(2)),
-      )
+    scope.for_each(lit!([1, 2]), |s, x| {
+      s.var(x.clone());
+    });
+
+    let array = ErasedExpr::array(
+      <[i32; 2] as ToType>::ty(),
+      vec![ErasedExpr::LitInt(1), ErasedExpr::LitInt(2)],
     );
+    let mut loop_scope = ErasedScope::new(1);
+    loop_scope.next_var = 2;
+    loop_scope.instructions.push(ScopeInstr::VarDecl {
+      ty: i32::ty(),
+      handle: ScopedHandle::fun_var(1, 1),
+      init_value: ErasedExpr::ArrayLookup {
+        object: Arc::new(array),
+        index: Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(1, 0))),
+      },
+    });
+
     assert_eq!(
-      b.erased,
-      ErasedExpr::Sub(
-        Box::new(ErasedExpr::LitInt(1)),
-        Box::new(ErasedExpr::LitInt(2)),
-      )
+      scope.erased.instructions[0],
+      ScopeInstr::For {
+        init_ty: i32::ty(),
+        init_handle: ScopedHandle::fun_var(1, 0),
+        init_expr: ErasedExpr::LitInt(0),
+        condition: ErasedExpr::Lt(
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(1, 0))),
+          Arc::new(ErasedExpr::LitInt(2)),
+        ),
+        post_expr: ErasedExpr::Add(
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(1, 0))),
+          Arc::new(ErasedExpr::LitInt(1)),
+        ),
+        scope: loop_scope,
+      }
     );
+  }
 
-    let a = lit!(1i32) * lit!(2);
-    let b = lit!(1i32) * 2;
+  #[test]
+  fn forever_loop() {
+    let mut scope = Scope::<()>::new(0);
+    scope.loop_forever(|s| s.loop_break());
+
+    let mut loop_scope = ErasedScope::new(1);
+    loop_scope.instructions.push(ScopeInstr::Break);
 
-    assert_eq!(a.erased, b.erased);
     assert_eq!(
-      a.erased,
-      ErasedExpr::Mul(
-        Box::new(ErasedExpr::LitInt(1)),
-        Box::new(ErasedExpr::LitInt(2)),
-      )
+      scope.erased.instructions,
+      vec![ScopeInstr::Loop { scope: loop_scope }]
     );
+  }
+
+  #[test]
+  fn do_while_loop() {
+    let mut scope = Scope::<()>::new(0);
+    let i = scope.var(0);
+
+    scope.loop_do_while(|s| s.set(&i, &i + 1), i.lt(10));
+
+    let mut loop_scope = ErasedScope::new(1);
+    loop_scope.instructions.push(ScopeInstr::MutateVar {
+      var: ErasedExpr::Var(ScopedHandle::fun_var(0, 0)),
+      expr: ErasedExpr::Add(
+        Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+        Arc::new(ErasedExpr::LitInt(1)),
+      ),
+    });
+
     assert_eq!(
-      b.erased,
-      ErasedExpr::Mul(
-        Box::new(ErasedExpr::LitInt(1)),
-        Box::new(ErasedExpr::LitInt(2)),
-      )
+      scope.erased.instructions[1],
+      ScopeInstr::DoWhile {
+        condition: ErasedExpr::Lt(
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+          Arc::new(ErasedExpr::LitInt(10)),
+        ),
+        scope: loop_scope,
+      }
     );
+  }
 
-    let a = lit!(1i32) / lit!(2);
-    let b = lit!(1i32) / 2;
+  #[test]
+  fn switch() {
+    let mut scope = Scope::<()>::new(0);
+    let x = scope.var(1);
+
+    scope
+      .switch_on(&x)
+      .case(0, |_| {})
+      .case_fallthrough(1, |s| s.abort())
+      .default(|_| {});
+
+    assert_eq!(scope.erased.instructions.len(), 2);
+
+    let mut case0 = ErasedScope::new(1);
+    case0.instructions.push(ScopeInstr::Break);
+
+    let mut case1 = ErasedScope::new(2);
+    case1
+      .instructions
+      .push(ScopeInstr::Return(ErasedReturn::Void));
+
+    let mut default = ErasedScope::new(3);
+    default.instructions.push(ScopeInstr::Break);
 
-    assert_eq!(a.erased, b.erased);
     assert_eq!(
-      a.erased,
-      ErasedExpr::Div(
-        Box::new(ErasedExpr::LitInt(1)),
-        Box::new(ErasedExpr::LitInt(2)),
-      )
+      scope.erased.instructions[1],
+      ScopeInstr::Switch {
+        expr: ErasedExpr::Var(ScopedHandle::fun_var(0, 0)),
+        cases: vec![
+          (Some(ErasedExpr::LitInt(0)), case0),
+          (Some(ErasedExpr::LitInt(1)), case1),
+          (None, default),
+        ],
+      }
     );
+  }
+
+  #[test]
+  fn switch_terminated_case() {
+    let mut scope = Scope::<()>::new(0);
+    let x = scope.var(1);
+
+    scope
+      .switch_on(&x)
+      .case(0, |s| s.abort())
+      .default(|s| s.abort());
+
+    let mut case0 = ErasedScope::new(1);
+    case0
+      .instructions
+      .push(ScopeInstr::Return(ErasedReturn::Void));
+
+    let mut default = ErasedScope::new(2);
+    default
+      .instructions
+      .push(ScopeInstr::Return(ErasedReturn::Void));
+
     assert_eq!(
-      b.erased,
-      ErasedExpr::Div(
-        Box::new(ErasedExpr::LitInt(1)),
-        Box::new(ErasedExpr::LitInt(2)),
-      )
+      scope.erased.instructions[1],
+      ScopeInstr::Switch {
+        expr: ErasedExpr::Var(ScopedHandle::fun_var(0, 0)),
+        cases: vec![(Some(ErasedExpr::LitInt(0)), case0), (None, default)],
+      }
     );
   }
 
   #[test]
-  fn expr_ref_inference() {
-    let a = lit!(1i32);
-    let b = a.clone() + 1;
-    let c = a + 1;
+  #[should_panic(expected = "duplicate case label")]
+  fn switch_duplicate_label() {
+    let mut scope = Scope::<()>::new(0);
+    let x = scope.var(1);
 
-    assert_eq!(b.erased, c.erased);
+    scope
+      .switch_on(&x)
+      .case(0, |_| {})
+      .case(0, |_| {})
+      .default(|_| {});
   }
 
   #[test]
-  fn expr_var() {
-    let mut scope = Scope::<()>::new(0);
+  fn swizzle_selector_sets() {
+    use SwizzleSelector::*;
 
-    let x = scope.var(0);
-    let y = scope.var(1u32);
-    let z = scope.var(lit![false, true, false]);
+    let v = lit!(1., 2., 3., 4.);
+    let xyzw = Swizzle::D4(X, Y, Z, W);
 
-    assert_eq!(x.erased, ErasedExpr::Var(ScopedHandle::fun_var(0, 0)));
-    assert_eq!(y.erased, ErasedExpr::Var(ScopedHandle::fun_var(0, 1)));
     assert_eq!(
-      z.erased,
-      ErasedExpr::Var(ScopedHandle::fun_var(0, 2).into())
+      sw!(v, .x.y.z.w).erased,
+      ErasedExpr::Swizzle(Arc::new(v.erased.clone()), xyzw)
     );
-    assert_eq!(scope.erased.instructions.len(), 3);
+    assert_eq!(sw!(v, .r.g.b.a).erased, sw!(v, .x.y.z.w).erased);
+    assert_eq!(sw!(v, .s.t.p.q).erased, sw!(v, .x.y.z.w).erased);
+    assert_eq!(v.q().erased, v.w().erased);
+    assert_eq!(v.a().erased, v.w().erased);
+  }
+
+  #[test]
+  fn set_swizzle() {
+    let mut scope = Scope::<()>::new(0);
+    let v = scope.var(lit!(1., 2., 3.));
+
+    scope.set_sw(&v, sw_mask!(.x.z), lit!(0., 10.));
+
     assert_eq!(
-      scope.erased.instructions[0],
-      ScopeInstr::VarDecl {
-        ty: Type {
-          prim_ty: PrimType::Int(Dim::Scalar),
-          array_dims: Vec::new(),
-        },
-        handle: ScopedHandle::fun_var(0, 0),
-        init_value: ErasedExpr::LitInt(0),
+      scope.erased.instructions[1],
+      ScopeInstr::MutateVar {
+        var: ErasedExpr::Swizzle(
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+          Swizzle::D2(SwizzleSelector::X, SwizzleSelector::Z),
+        ),
+        expr: ErasedExpr::LitFloat2([0., 10.]),
       }
     );
+  }
+
+  #[test]
+  fn set_var_swizzle() {
+    let mut scope = Scope::<()>::new(0);
+    let v = scope.var(lit!(1., 2., 3., 4.));
+
+    scope.set(sw!(v, .y.w), lit!(0., 10.));
+    scope.set(sw!(v, .x), 5.);
+
     assert_eq!(
       scope.erased.instructions[1],
-      ScopeInstr::VarDecl {
-        ty: Type {
-          prim_ty: PrimType::UInt(Dim::Scalar),
-          array_dims: Vec::new(),
-        },
-        handle: ScopedHandle::fun_var(0, 1),
-        init_value: ErasedExpr::LitUInt(1),
+      ScopeInstr::MutateVar {
+        var: ErasedExpr::Swizzle(
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+          Swizzle::D2(SwizzleSelector::Y, SwizzleSelector::W),
+        ),
+        expr: ErasedExpr::LitFloat2([0., 10.]),
       }
     );
     assert_eq!(
       scope.erased.instructions[2],
-      ScopeInstr::VarDecl {
-        ty: Type {
-          prim_ty: PrimType::Bool(Dim::D3),
-          array_dims: Vec::new(),
-        },
-        handle: ScopedHandle::fun_var(0, 2),
-        init_value: ErasedExpr::LitBool3([false, true, false]),
+      ScopeInstr::MutateVar {
+        var: ErasedExpr::Swizzle(
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+          Swizzle::D1(SwizzleSelector::X),
+        ),
+        expr: ErasedExpr::LitFloat(5.),
       }
     );
   }
 
   #[test]
-  fn min_max_clamp() {
-    let a = lit!(1i32);
-    let b = lit!(2);
-    let c = lit!(3);
+  fn var_swizzle_masks() {
+    let mut scope = Scope::<()>::new(0);
+    let v = scope.var(lit!(1., 2., 3.));
+
+    let _: Var<V3<f32>> = sw!(v, .z.x.y);
+    let _: Expr<V3<f32>> = sw!(v, .z.x.z);
+    let _: Var<f32> = sw!(v, .y);
+
+    // runtime masks are not known to select components once, so they don’t yield variables
+    let v_zy: Expr<V2<f32>> = v.swizzle([SwizzleSelector::Z, SwizzleSelector::Y]);
+
+    assert_eq!(v_zy.erased, sw!(v, .z.y).erased);
+  }
+
+  #[test]
+  fn select() {
+    let cond = lit!(true, false);
 
     assert_eq!(
-      a.min(&b).erased,
-      ErasedExpr::FunCall(
-        ErasedFunHandle::Min,
-        vec![ErasedExpr::LitInt(1), ErasedExpr::LitInt(2)],
+      cond.select(lit!(1, 2), lit!(3, 4)).erased,
+      ErasedExpr::fun_call(
+        ErasedFunHandle::Mix,
+        vec![
+          ErasedExpr::LitInt2([3, 4]),
+          ErasedExpr::LitInt2([1, 2]),
+          ErasedExpr::LitBool2([true, false])
+        ],
       )
     );
+  }
+
+  #[test]
+  fn reflection() {
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, _| {
+      inputs!(s, position: V3<f32>, normal: V3<f32>);
+      outputs!(s, color: V4<f32>);
+      uniforms!(s, time: f32);
+      let _ = (&position, &normal, &color, &time);
+
+      s.fun(|_: &mut Scope<Expr<f32>>, a: Expr<f32>, _: Expr<[i32; 2]>| a);
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
 
+    let inputs: Vec<_> = shader.inputs().collect();
+    assert_eq!(inputs.len(), 2);
+    assert_eq!(inputs[1].name(), "normal");
+    assert_eq!(inputs[1].ty(), &V3::<f32>::ty());
     assert_eq!(
-      a.max(&b).erased,
-      ErasedExpr::FunCall(
-        ErasedFunHandle::Max,
-        vec![ErasedExpr::LitInt(1), ErasedExpr::LitInt(2)],
-      )
+      inputs[1].handle(),
+      &ScopedHandle::Input("normal".to_owned())
     );
+    assert_eq!(inputs[1].index(), 1);
+
+    let outputs: Vec<_> = shader.outputs().collect();
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[0].name(), "color");
+    assert_eq!(outputs[0].index(), 0);
+
+    let uniforms: Vec<_> = shader.uniforms().collect();
+    assert_eq!(uniforms.len(), 1);
+    assert_eq!(uniforms[0].handle(), &ScopedHandle::uniform("time"));
+
+    let functions: Vec<_> = shader.functions().collect();
+    assert_eq!(functions.len(), 1);
+    assert_eq!(functions[0].handle(), 0);
+    assert_eq!(functions[0].args(), &[f32::ty(), <[i32; 2]>::ty()]);
+    assert_eq!(functions[0].ret(), Some(&f32::ty()));
+  }
+
+  #[test]
+  fn digest() {
+    let shader = |require_first| {
+      ShaderBuilder::new_vertex_shader(move |mut s, vertex| {
+        if require_first {
+          s.require(Capability::Subgroup);
+        }
+
+        inputs!(s, position: V4<f32>);
+
+        s.main_fun(|s: &mut Scope<()>| {
+          s.set(vertex.position, position);
+        })
+      })
+    };
+    let other = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      s.require(Capability::Subgroup);
+      inputs!(s, position: V4<f32>);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        s.set(vertex.position, -position);
+      })
+    });
+
+    assert_eq!(shader(true).digest(), shader(true).digest());
+    assert_ne!(shader(true).digest(), shader(false).digest());
+    assert_ne!(shader(true).digest(), other.digest());
+
+    let mut digest = Digest::new();
+    std::fmt::Write::write_str(&mut digest, "a").unwrap();
+    assert_eq!(digest.0, 0xaf63_dc4c_8601_ec8c);
+  }
+
+  #[test]
+  fn merge() {
+    let mut shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, uv: V2<f32>);
+      outputs!(s, color: V4<f32>);
+      let half = s.fun(|_: &mut Scope<Expr<f32>>, a: Expr<f32>| a * 0.5);
+
+      s.main_fun(|s: &mut Scope<()>| s.set(color, vec4!(uv, half.call(lit!(1.)), 1.)))
+    });
+
+    let mut double = None;
+    let other = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, uv: V2<f32>);
+      outputs!(s, normal: V2<f32>);
+      let scale = s.named_constant("scale", lit!(2.));
+      let fun = s.fun(move |_: &mut Scope<Expr<V2<f32>>>, a: Expr<V2<f32>>| a * scale.clone());
+      double = Some(fun.clone());
+
+      s.comment("normals");
+      s.main_fun(|s: &mut Scope<()>| s.set(normal, fun.call(uv.to_expr())))
+    });
+
+    let relocation = shader.merge(&other).unwrap();
+    let double = module::Exports::relocate(&double.unwrap(), &relocation);
+    assert_eq!(double.erased, ErasedFunHandle::UserDefined(1));
 
     assert_eq!(
-      a.clamp(b, c).erased,
-      ErasedExpr::FunCall(
-        ErasedFunHandle::Clamp,
-        vec![
-          ErasedExpr::LitInt(1),
-          ErasedExpr::LitInt(2),
-          ErasedExpr::LitInt(3)
-        ],
-      )
+      writer::glsl::write_shader_to_str(&shader).unwrap(),
+      "in vec2 uv;
+out vec4 color;
+
+float fun_0(float arg_0) {
+  return (arg_0 * .5);
+}
+out vec2 normal;
+const float scale = 2.;
+
+vec2 fun_1(vec2 arg_0) {
+  return (arg_0 * scale);
+}
+
+// normals
+void fun_2() {
+  normal = fun_1(uv);
+}
+
+void main() {
+  color = vec4(uv, fun_0(1.), 1.);
+  fun_2();
+}"
     );
+    assert!(shader.check().is_ok());
   }
 
   #[test]
   fn fun0() {
-    let mut shader = ShaderBuilder::new();
+    let mut shader = ShaderBuilder::<stage::Vertex>::new();
     let fun = shader.fun(|s: &mut Scope<()>| {
       let _x = s.var(3);
     });
@@ -5523,7 +11092,7 @@ mod tests {
 
   #[test]
   fn fun1() {
-    let mut shader = ShaderBuilder::new();
+    let mut shader = ShaderBuilder::<stage::Vertex>::new();
     let fun = shader.fun(|f: &mut Scope<Expr<i32>>, _arg: Expr<i32>| {
       let x = f.var(lit!(3i32));
       x.into()
@@ -5561,17 +11130,45 @@ mod tests {
     }
   }
 
+  #[test]
+  fn merge_type_mismatch() {
+    let mut shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      uniforms!(s, time: f32);
+      outputs!(s, color: V4<f32>);
+      s.main_fun(|s: &mut Scope<()>| s.set(color, vec4!(time, 0., 0., 1.)))
+    });
+
+    let other = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      uniforms!(s, time: i32);
+      outputs!(s, index: i32);
+      s.main_fun(|s: &mut Scope<()>| s.set(index, time))
+    });
+
+    assert_eq!(
+      shader.merge(&other),
+      Err(MergeError::TypeMismatch {
+        name: "uniform time".to_owned(),
+        ty: <f32 as ToType>::ty(),
+        other_ty: <i32 as ToType>::ty(),
+      })
+    );
+
+    // nothing is merged
+    assert_eq!(shader.outputs().count(), 1);
+  }
+
   #[test]
   fn swizzling() {
     let mut scope = Scope::<()>::new(0);
     let foo = scope.var(lit![1, 2]);
-    let foo_xy: Expr<V2<_>> = sw!(foo, .x.y);
+    let foo_xy: Var<V2<_>> = sw!(foo, .x.y);
+    // selecting a component twice doesn’t yield a variable
     let foo_xx: Expr<V2<_>> = sw!(foo, .x.x);
 
     assert_eq!(
       foo_xy.erased,
       ErasedExpr::Swizzle(
-        Box::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+        Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
         Swizzle::D2(SwizzleSelector::X, SwizzleSelector::Y),
       )
     );
@@ -5579,7 +11176,7 @@ mod tests {
     assert_eq!(
       foo_xx.erased,
       ErasedExpr::Swizzle(
-        Box::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+        Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
         Swizzle::D2(SwizzleSelector::X, SwizzleSelector::X),
       )
     );
@@ -5633,15 +11230,15 @@ mod tests {
       s.erased.instructions[1],
       ScopeInstr::If {
         condition: ErasedExpr::Eq(
-          Box::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
-          Box::new(ErasedExpr::LitInt(2)),
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+          Arc::new(ErasedExpr::LitInt(2)),
         ),
         scope,
       }
     );
 
     // else if
-    let mut scope = ErasedScope::new(1);
+    let mut scope = ErasedScope::new(2);
     scope
       .instructions
       .push(ScopeInstr::Return(ErasedReturn::Expr(
@@ -5653,8 +11250,8 @@ mod tests {
       s.erased.instructions[2],
       ScopeInstr::ElseIf {
         condition: ErasedExpr::Eq(
-          Box::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
-          Box::new(ErasedExpr::LitInt(0)),
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+          Arc::new(ErasedExpr::LitInt(0)),
         ),
         scope,
       }
@@ -5664,7 +11261,7 @@ mod tests {
     assert_eq!(
       s.erased.instructions[3],
       ScopeInstr::Else {
-        scope: ErasedScope::new(1)
+        scope: ErasedScope::new(3)
       }
     );
   }
@@ -5676,7 +11273,7 @@ mod tests {
     scope.loop_for(
       0,
       |a| a.lt(lit!(10)),
-      // |a| a + 1,
+      |a| a + 1,
       |s, a| {
         s.leave(a);
       },
@@ -5686,14 +11283,6 @@ mod tests {
 
     let mut loop_scope = ErasedScope::new(1);
     loop_scope.next_var = 1;
-    loop_scope.instructions.push(ScopeInstr::VarDecl {
-      ty: Type {
-        prim_ty: PrimType::Int(Dim::Scalar),
-        array_dims: Vec::new(),
-      },
-      handle: ScopedHandle::fun_var(1, 0),
-      init_value: ErasedExpr::LitInt(0),
-    });
     loop_scope
       .instructions
       .push(ScopeInstr::Return(ErasedReturn::Expr(
@@ -5706,15 +11295,15 @@ mod tests {
       ScopeInstr::For {
         init_ty: i32::ty(),
         init_handle: ScopedHandle::fun_var(1, 0),
-        init_expr: ErasedExpr::Var(ScopedHandle::fun_var(1, 0)),
+        init_expr: ErasedExpr::LitInt(0),
         condition: ErasedExpr::Lt(
-          Box::new(ErasedExpr::Var(ScopedHandle::fun_var(1, 0))),
-          Box::new(ErasedExpr::LitInt(10)),
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(1, 0))),
+          Arc::new(ErasedExpr::LitInt(10)),
+        ),
+        post_expr: ErasedExpr::Add(
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(1, 0))),
+          Arc::new(ErasedExpr::LitInt(1)),
         ),
-        // post_expr: ErasedExpr::Add(
-        //   Box::new(ErasedExpr::Var(ScopedHandle::fun_var(1, 0))),
-        //   Box::new(ErasedExpr::LitInt(1)),
-        // ),
         scope: loop_scope,
       }
     );
@@ -5724,18 +11313,19 @@ mod tests {
   fn while_loop() {
     let mut scope: Scope<Expr<i32>> = Scope::new(0);
 
-    scope.loop_while(lit!(1).lt(lit!(2)), LoopScope::loop_continue);
+    let x = scope.var(1);
+    scope.loop_while(x.lt(lit!(2)), LoopScope::loop_continue);
 
     let mut loop_scope = ErasedScope::new(1);
     loop_scope.instructions.push(ScopeInstr::Continue);
 
-    assert_eq!(scope.erased.instructions.len(), 1);
+    assert_eq!(scope.erased.instructions.len(), 2);
     assert_eq!(
-      scope.erased.instructions[0],
+      scope.erased.instructions[1],
       ScopeInstr::While {
         condition: ErasedExpr::Lt(
-          Box::new(ErasedExpr::LitInt(1)),
-          Box::new(ErasedExpr::LitInt(2)),
+          Arc::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))),
+          Arc::new(ErasedExpr::LitInt(2)),
         ),
         scope: loop_scope,
       }
@@ -5759,8 +11349,8 @@ mod tests {
     assert_eq!(
       clip_dist_expr.erased,
       ErasedExpr::ArrayLookup {
-        object: Box::new(vertex.clip_distance.erased.clone()),
-        index: Box::new(ErasedExpr::LitInt(1)),
+        object: Arc::new(vertex.clip_distance.erased.clone()),
+        index: Arc::new(ErasedExpr::LitInt(1)),
       }
     );
   }
@@ -5774,14 +11364,14 @@ mod tests {
 
     assert_eq!(
       two_d.erased,
-      ErasedExpr::Array(
+      ErasedExpr::array(
         <[[i32; 2]; 2] as ToType>::ty(),
         vec![
-          ErasedExpr::Array(
+          ErasedExpr::array(
             <[i32; 2] as ToType>::ty(),
             vec![ErasedExpr::LitInt(1), ErasedExpr::LitInt(2)]
           ),
-          ErasedExpr::Array(
+          ErasedExpr::array(
             <[i32; 2] as ToType>::ty(),
             vec![ErasedExpr::LitInt(3), ErasedExpr::LitInt(4)]
           )
@@ -5798,12 +11388,12 @@ mod tests {
 
     assert_eq!(
       xyz2.erased,
-      ErasedExpr::FunCall(ErasedFunHandle::Vec3, vec![xy.erased, lit!(3.).erased])
+      ErasedExpr::fun_call(ErasedFunHandle::Vec3, vec![xy.erased, lit!(3.).erased])
     );
 
     assert_eq!(
       xyz3.erased,
-      ErasedExpr::FunCall(
+      ErasedExpr::fun_call(
         ErasedFunHandle::Vec3,
         vec![lit!(1.).erased, lit!(2.).erased, lit!(3.).erased]
       )
@@ -5820,7 +11410,7 @@ mod tests {
 
     assert_eq!(
       xyzw22.erased,
-      ErasedExpr::FunCall(
+      ErasedExpr::fun_call(
         ErasedFunHandle::Vec4,
         vec![xy.clone().erased, xy.clone().erased]
       )
@@ -5828,7 +11418,7 @@ mod tests {
 
     assert_eq!(
       xyzw211.erased,
-      ErasedExpr::FunCall(
+      ErasedExpr::fun_call(
         ErasedFunHandle::Vec4,
         vec![xy.clone().erased, lit!(3.).erased, lit!(4.).erased]
       )
@@ -5836,7 +11426,7 @@ mod tests {
 
     assert_eq!(
       xyzw31.erased,
-      ErasedExpr::FunCall(
+      ErasedExpr::fun_call(
         ErasedFunHandle::Vec4,
         vec![vec3!(1., 2., 3.).erased, lit!(4.).erased]
       )
@@ -5844,7 +11434,7 @@ mod tests {
 
     assert_eq!(
       xyzw4.erased,
-      ErasedExpr::FunCall(
+      ErasedExpr::fun_call(
         ErasedFunHandle::Vec4,
         vec![
           lit!(1.).erased,
@@ -5876,7 +11466,7 @@ mod tests {
 
 //   fn add(self, rhs: Expr<V3<T>>) -> Self::Output {
 //     Expr::new(
-//       ErasedExpr::Add(Box::new(self), Box::new(rhs))
+//       ErasedExpr::Add(Arc::new(self), Arc::new(rhs))
 //     )
 //   }
 // }
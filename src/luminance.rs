@@ -0,0 +1,267 @@
+//! [luminance] integration, enabled with the `luminance` feature gate.
+//!
+//! A shades [`Program`] can be turned into a luminance program in one call with [`new_program`]: the stages are written
+//! in GLSL and passed to luminance, and the uniforms of all the stages are gathered in a [`Uniforms`] interface. The
+//! variables returned when declaring the uniforms are then used to look up the luminance [`Uniform`]s, so that the
+//! values set at runtime have the types declared in the shaders.
+//!
+//! # Examples
+//!
+//! ```
+//! use luminance::{context::GraphicsContext, shader::Program as LuminanceProgram};
+//! use shades::{Scope, ShaderBuilder, V3, V4, inputs, outputs, uniforms, vec4};
+//! use shades::luminance::{self as shades_luminance, UniformBackend, Uniforms};
+//! use shades::program::ProgramBuilder;
+//!
+//! fn render<C>(ctx: &mut C)
+//! where
+//!   C: GraphicsContext,
+//!   C::Backend: UniformBackend,
+//! {
+//!   let mut time = None;
+//!
+//!   let vertex_shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+//!     inputs!(s, position: V3<f32>);
+//!     uniforms!(s, t: f32);
+//!     time = Some(t.clone());
+//!
+//!     s.main_fun(|s: &mut Scope<()>| s.set(vertex.position, vec4!(position * t, 1.)))
+//!   });
+//!
+//!   let fragment_shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+//!     outputs!(s, color: V4<f32>);
+//!     s.main_fun(|s: &mut Scope<()>| s.set(color, vec4!(1., 0., 0., 1.)))
+//!   });
+//!
+//!   let program = ProgramBuilder::new(vertex_shader, fragment_shader).link().unwrap();
+//!   let mut program: LuminanceProgram<_, (), (), Uniforms> =
+//!     shades_luminance::new_program(ctx, &program).unwrap().ignore_warnings();
+//!
+//!   // in a shading gate
+//!   # let _ = |iface: &mut luminance::shader::ProgramInterface<C::Backend>, uniforms: &Uniforms| {
+//!   let time = uniforms.get(time.as_ref().unwrap()).unwrap();
+//!   iface.set(&time, 1.5);
+//!   # };
+//! }
+//! ```
+//!
+//! [luminance]: https://crates.io/crates/luminance
+
+use crate::{
+  program::Program,
+  writer::glsl::{self, ProgramSources},
+  ErasedExpr, Expr, ScopedHandle, ToType, Type, M22, M33, M44, V2, V3, V4,
+};
+use ::luminance::{
+  backend::shader::{Shader, Uniformable},
+  context::GraphicsContext,
+  shader::{
+    types::{Mat22, Mat33, Mat44, Vec2, Vec3, Vec4},
+    BuiltProgram, ProgramError, TessellationStages, Uniform, UniformBuilder, UniformInterface,
+    UniformWarning,
+  },
+  vertex::Semantics,
+};
+use std::collections::HashMap;
+
+impl ProgramSources {
+  /// Tessellation stages, as expected by luminance’s [`ProgramBuilder`](::luminance::shader::ProgramBuilder).
+  pub fn tess_stages(&self) -> Option<TessellationStages<'_, str>> {
+    match (&self.tess_ctrl, &self.tess_eval) {
+      (Some(control), Some(evaluation)) => Some(TessellationStages {
+        control,
+        evaluation,
+      }),
+      _ => None,
+    }
+  }
+}
+
+/// Write the stages of `program` in GLSL and create a luminance program out of them, along with its [`Uniforms`].
+pub fn new_program<C, Sem, Out>(
+  ctx: &mut C,
+  program: &Program,
+) -> Result<BuiltProgram<C::Backend, Sem, Out, Uniforms>, ProgramError>
+where
+  C: GraphicsContext,
+  C::Backend: UniformBackend,
+  Sem: Semantics,
+{
+  let sources = glsl::write_program_to_str(program)
+    .map_err(|_| ProgramError::creation_failed("cannot write the program in GLSL"))?;
+
+  ctx.new_shader_program().from_strings_env(
+    &sources.vertex,
+    sources.tess_stages(),
+    sources.geometry.as_deref(),
+    &sources.fragment,
+    &mut &*program,
+  )
+}
+
+/// Shades types which can be set as luminance uniforms.
+pub trait UniformValue: ToType {
+  /// Type of the luminance [`Uniform`].
+  type Luminance;
+}
+
+macro_rules! uniform_values {
+  ($($t:ty => $luminance:ty),* $(,)?) => {
+    /// Backends supporting all the [`UniformValue`]s, set by value.
+    pub trait UniformBackend:
+      Shader $(+ for<'u> Uniformable<'u, $luminance, Target = $luminance>)*
+    {
+    }
+
+    impl<B> UniformBackend for B
+    where
+      B: Shader $(+ for<'u> Uniformable<'u, $luminance, Target = $luminance>)*
+    {
+    }
+
+    $(
+      impl UniformValue for $t {
+        type Luminance = $luminance;
+      }
+    )*
+
+    /// Ask `builder` for the uniform `name` of type `ty`, and return its index; [`None`] if the type is not supported.
+    fn ask<B>(builder: &mut UniformBuilder<B>, name: &str, ty: &Type) -> Option<i32>
+    where
+      B: UniformBackend,
+    {
+      $(
+        if *ty == <$t>::ty() {
+          return Some(builder.ask_or_unbound::<$luminance>(name).index());
+        }
+      )*
+
+      None
+    }
+  };
+}
+
+uniform_values! {
+  f32 => f32,
+  i32 => i32,
+  u32 => u32,
+  bool => bool,
+  V2<f32> => Vec2<f32>,
+  V3<f32> => Vec3<f32>,
+  V4<f32> => Vec4<f32>,
+  V2<i32> => Vec2<i32>,
+  V3<i32> => Vec3<i32>,
+  V4<i32> => Vec4<i32>,
+  V2<u32> => Vec2<u32>,
+  V3<u32> => Vec3<u32>,
+  V4<u32> => Vec4<u32>,
+  V2<bool> => Vec2<bool>,
+  V3<bool> => Vec3<bool>,
+  V4<bool> => Vec4<bool>,
+  M22 => Mat22<f32>,
+  M33 => Mat33<f32>,
+  M44 => Mat44<f32>,
+}
+
+/// Uniform interface made of the uniforms of all the stages of a [`Program`].
+///
+/// Uniforms of types luminance doesn’t support — arrays, non-square matrices, etc. — are left out, and uniforms
+/// optimized out by the driver are unbound, with a warning.
+#[derive(Debug)]
+pub struct Uniforms {
+  uniforms: HashMap<String, (Type, i32)>,
+}
+
+impl Uniforms {
+  /// Luminance [`Uniform`] of the uniform `uniform`, as returned by [`ShaderBuilder::uniform`].
+  ///
+  /// Return [`None`] if `uniform` is not a uniform of the program.
+  ///
+  /// [`ShaderBuilder::uniform`]: crate::ShaderBuilder::uniform
+  pub fn get<T>(&self, uniform: &Expr<T>) -> Option<Uniform<T::Luminance>>
+  where
+    T: UniformValue,
+  {
+    let name = match uniform.erased {
+      ErasedExpr::Var(ScopedHandle::Uniform(ref name)) => name,
+      _ => return None,
+    };
+
+    match self.uniforms.get(name) {
+      // SAFETY: the uniform was asked to the backend with the luminance type of T
+      Some((ty, index)) if *ty == T::ty() => Some(unsafe { Uniform::new(*index) }),
+      _ => None,
+    }
+  }
+}
+
+impl<'a, B> UniformInterface<B, &'a Program> for Uniforms
+where
+  B: UniformBackend,
+{
+  fn uniform_interface<'b>(
+    builder: &mut UniformBuilder<'b, B>,
+    program: &mut &'a Program,
+  ) -> Result<Self, UniformWarning> {
+    let stages = [
+      program.vertex().uniforms().collect::<Vec<_>>(),
+      program
+        .tess_ctrl()
+        .map(|s| s.uniforms().collect())
+        .unwrap_or_default(),
+      program
+        .tess_eval()
+        .map(|s| s.uniforms().collect())
+        .unwrap_or_default(),
+      program
+        .geometry()
+        .map(|s| s.uniforms().collect())
+        .unwrap_or_default(),
+      program.fragment().uniforms().collect(),
+    ];
+    let mut uniforms = HashMap::new();
+
+    for uniform in stages.iter().flatten() {
+      if uniforms.contains_key(uniform.name()) {
+        continue;
+      }
+
+      if let Some(index) = ask(builder, uniform.name(), uniform.ty()) {
+        uniforms.insert(uniform.name().to_owned(), (uniform.ty().clone(), index));
+      }
+    }
+
+    Ok(Uniforms { uniforms })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{outputs, program::ProgramBuilder, vec4, Scope, ShaderBuilder};
+
+  #[test]
+  fn tess_stages() {
+    let vertex = ShaderBuilder::new_vertex_shader(|s, vertex| {
+      s.main_fun(|s: &mut Scope<()>| s.set(vertex.position, vec4!(0., 0., 0., 1.)))
+    });
+    let fragment = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      outputs!(s, color: V4<f32>);
+      s.main_fun(|s: &mut Scope<()>| s.set(color, vec4!(1., 0., 0., 1.)))
+    });
+    let program = ProgramBuilder::new(vertex, fragment).link().unwrap();
+    let sources = glsl::write_program_to_str(&program).unwrap();
+
+    assert!(sources.tess_stages().is_none());
+
+    let sources = ProgramSources {
+      tess_ctrl: Some("control".to_owned()),
+      tess_eval: Some("evaluation".to_owned()),
+      ..sources
+    };
+    let stages = sources.tess_stages().unwrap();
+
+    assert_eq!(stages.control, "control");
+    assert_eq!(stages.evaluation, "evaluation");
+  }
+}
@@ -0,0 +1,461 @@
+//! Reusable libraries of shader functions and constants.
+//!
+//! A [`ShaderModule`] is built once with [`ShaderModule::new`], and gathers functions and constants — noise, lighting,
+//! tone mapping, etc. It can then be imported into as many shaders (or other modules) as needed with
+//! [`ShaderBuilder::import`]. Importing a module copies its declarations into the shader, remapping their handles so that
+//! they don’t clash with the declarations already present. Importing the same module several times in the same shader
+//! copies its declarations only once.
+//!
+//! A module _exports_ a value built alongside its declarations, typically a [`FunHandle`] or a tuple / struct of those.
+//! That value must implement [`Exports`], so that its handles can be remapped when the module is imported.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{Exponential as _, Expr, Scope, ShaderBuilder, lit};
+//! use shades::module::ShaderModule;
+//!
+//! let lib = ShaderModule::new(|m| {
+//!   let square = m.fun(|_: &mut Scope<Expr<f32>>, a: Expr<f32>| a.pow(2.));
+//!   let cube = m.fun(|_: &mut Scope<Expr<f32>>, a: Expr<f32>| a.pow(3.));
+//!   (square, cube)
+//! });
+//!
+//! let shader = ShaderBuilder::new_vertex_shader(|mut s, _| {
+//!   let (square, cube) = s.import(&lib);
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     let _x = s.var(square.call(lit!(2.)) + cube.call(lit!(2.)));
+//!   })
+//! });
+//! ```
+//!
+//! [`ShaderBuilder::import`]: crate::ShaderBuilder::import
+
+use crate::{
+  Behavior, Capability, EmptyArray, ErasedExpr, ErasedFun, ErasedFunHandle, ErasedReturn,
+  ErasedScope, Expr, FunHandle, Rewritten, ScopeInstr, ScopedHandle, ShaderBuilder, ShaderDecl,
+  ToFun, ToType,
+};
+use std::{
+  marker::PhantomData,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Source of unique module identifiers.
+static NEXT_MODULE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A library of functions and constants, built once and imported into several shaders.
+///
+/// `T` is the type of the value exported by the module.
+#[derive(Debug)]
+pub struct ShaderModule<T> {
+  pub(crate) id: usize,
+  pub(crate) builder: ShaderBuilder<()>,
+  pub(crate) exports: T,
+}
+
+impl<T> ShaderModule<T> {
+  /// Create a new module.
+  ///
+  /// The input closure declares the functions and constants of the module via the [`ModuleBuilder`], and returns what
+  /// the module exports.
+  pub fn new(f: impl FnOnce(&mut ModuleBuilder) -> T) -> Self {
+    let mut module_builder = ModuleBuilder {
+      builder: ShaderBuilder::new(),
+    };
+    let exports = f(&mut module_builder);
+
+    Self {
+      id: NEXT_MODULE_ID.fetch_add(1, Ordering::Relaxed),
+      builder: module_builder.builder,
+      exports,
+    }
+  }
+}
+
+/// A [`ShaderModule`] builder.
+///
+/// It allows to declare functions and constants, akin to what [`ShaderBuilder`] provides.
+#[derive(Debug)]
+pub struct ModuleBuilder {
+  builder: ShaderBuilder<()>,
+}
+
+impl ModuleBuilder {
+  /// Create a new function in the module and get its handle for future use.
+  ///
+  /// See [`ShaderBuilder::fun`] for further details.
+  pub fn fun<F, R, A>(&mut self, f: F) -> FunHandle<R, A>
+  where
+    F: ToFun<R, A>,
+  {
+    self.builder.fun(f)
+  }
+
+  /// Declare a new constant in the module.
+  ///
+  /// See [`ShaderBuilder::constant`] for further details.
+  pub fn constant<T>(&mut self, expr: impl Into<Expr<T>>) -> Expr<T>
+  where
+    T: ToType,
+  {
+    self.builder.constant(expr)
+  }
+
+  /// Declare a new constant array in the module.
+  ///
+  /// See [`ShaderBuilder::constant_array`] for further details.
+  pub fn constant_array<T>(&mut self, items: &[T]) -> Result<Expr<[T]>, EmptyArray>
+  where
+    T: Clone + ToType,
+    Expr<T>: From<T>,
+  {
+    self.builder.constant_array(items)
+  }
+
+  /// Create a new function in the module, named `name` in the generated code.
+  ///
+  /// See [`ShaderBuilder::named_fun`] for further details.
+  pub fn named_fun<F, R, A>(&mut self, name: &str, f: F) -> FunHandle<R, A>
+  where
+    F: ToFun<R, A>,
+  {
+    self.builder.named_fun(name, f)
+  }
+
+  /// Declare a new constant in the module, named `name` in the generated code.
+  ///
+  /// See [`ShaderBuilder::named_constant`] for further details.
+  pub fn named_constant<T>(&mut self, name: &str, expr: impl Into<Expr<T>>) -> Expr<T>
+  where
+    T: ToType,
+  {
+    self.builder.named_constant(name, expr)
+  }
+
+  /// Require a [`Capability`] for every shader importing the module.
+  pub fn require(&mut self, capability: Capability) {
+    self.builder.require(capability)
+  }
+
+  /// Declare the `behavior` of the compiler towards the extension `name` for every shader importing the module.
+  ///
+  /// See [`ShaderBuilder::require_extension`] for further details.
+  pub fn require_extension(&mut self, name: &str, behavior: Behavior) {
+    self.builder.require_extension(name, behavior)
+  }
+
+  /// Import another module into this module.
+  ///
+  /// See [`ShaderBuilder::import`] for further details.
+  pub fn import<T>(&mut self, module: &ShaderModule<T>) -> T
+  where
+    T: Exports,
+  {
+    self.builder.import(module)
+  }
+}
+
+/// A module imported into a shader or into another module.
+#[derive(Clone, Debug)]
+pub(crate) struct Import {
+  pub(crate) id: usize,
+
+  /// Relocation from the handles of the module to the ones of the importer.
+  pub(crate) relocation: Relocation,
+
+  // handles the declarations of the module were copied at in the importer, and number of those, by kind
+  first_fun: u16,
+  first_global: u16,
+  funs: u16,
+  globals: u16,
+}
+
+impl Import {
+  pub(crate) fn new<T>(module: &ShaderModule<T>, relocation: Relocation) -> Self {
+    Import {
+      id: module.id,
+      first_fun: relocation.steps[0].fun_offset,
+      first_global: relocation.steps[0].global_offset,
+      relocation,
+      funs: module.builder.next_fun_handle,
+      globals: module.builder.next_global_handle,
+    }
+  }
+
+  /// Same import, seen from a shader the importer was copied into with `outer`.
+  pub(crate) fn then(&self, outer: &Relocation) -> Self {
+    Import {
+      id: self.id,
+      relocation: self.relocation.then(outer),
+      first_fun: outer.relocated_fun(self.first_fun),
+      first_global: outer.relocated_global(self.first_global),
+      funs: self.funs,
+      globals: self.globals,
+    }
+  }
+}
+
+/// Handle remapping applied to the declarations of a module when it is imported.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Relocation {
+  // applied in order; the steps of modules imported by modules come first
+  steps: Vec<RelocationStep>,
+}
+
+/// Offsets applied to the handles of a module, except the ones of the modules it imports that the importer already has.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct RelocationStep {
+  fun_offset: u16,
+  global_offset: u16,
+  redirects: Vec<Redirect>,
+}
+
+/// Handles of a module imported by the module being imported, which the importer already has a copy of.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct Redirect {
+  first_fun: u16,
+  funs: u16,
+  first_global: u16,
+  globals: u16,
+
+  /// Relocation to the copy the importer already has.
+  target: Relocation,
+}
+
+impl RelocationStep {
+  fn fun(&self, handle: u16) -> u16 {
+    self
+      .redirects
+      .iter()
+      .find(|r| (r.first_fun..r.first_fun + r.funs).contains(&handle))
+      .map_or(handle + self.fun_offset, |r| {
+        r.target.relocated_fun(handle - r.first_fun)
+      })
+  }
+
+  fn global(&self, handle: u16) -> u16 {
+    self
+      .redirects
+      .iter()
+      .find(|r| (r.first_global..r.first_global + r.globals).contains(&handle))
+      .map_or(handle + self.global_offset, |r| {
+        r.target.relocated_global(handle - r.first_global)
+      })
+  }
+}
+
+impl Relocation {
+  /// Relocation applied when importing a module already relocated by `self` into a shader, with `outer`.
+  pub(crate) fn then(&self, outer: &Relocation) -> Self {
+    Relocation {
+      steps: self.steps.iter().chain(&outer.steps).cloned().collect(),
+    }
+  }
+
+  pub(crate) fn new(fun_offset: u16, global_offset: u16) -> Self {
+    Relocation {
+      steps: vec![RelocationStep {
+        fun_offset,
+        global_offset,
+        redirects: Vec::new(),
+      }],
+    }
+  }
+
+  /// Map the declarations of `nested` — a module imported by the module being imported — to the copy of it the
+  /// importer already has, `existing`, instead of copying them again.
+  ///
+  /// Only relocations created with [`Relocation::new`] can be redirected.
+  pub(crate) fn redirect(&mut self, nested: &Import, existing: &Import) {
+    self.steps[0].redirects.push(Redirect {
+      first_fun: nested.first_fun,
+      funs: nested.funs,
+      first_global: nested.first_global,
+      globals: nested.globals,
+      target: existing.relocation.clone(),
+    });
+  }
+
+  /// Whether `decl` is redirected to a copy the importer already has, and must not be copied.
+  pub(crate) fn is_redirected(&self, decl: &ShaderDecl) -> bool {
+    let step = &self.steps[0];
+
+    match *decl {
+      ShaderDecl::FunDef(handle, _) => step
+        .redirects
+        .iter()
+        .any(|r| (r.first_fun..r.first_fun + r.funs).contains(&handle)),
+      ShaderDecl::Const(handle, ..) | ShaderDecl::Shared(handle, _) => step
+        .redirects
+        .iter()
+        .any(|r| (r.first_global..r.first_global + r.globals).contains(&handle)),
+      _ => false,
+    }
+  }
+
+  /// Relocated handle of the function `handle`.
+  pub(crate) fn relocated_fun(&self, handle: u16) -> u16 {
+    self
+      .steps
+      .iter()
+      .fold(handle, |handle, step| step.fun(handle))
+  }
+
+  /// Relocated handle of the constant `handle`.
+  pub(crate) fn relocated_global(&self, handle: u16) -> u16 {
+    self
+      .steps
+      .iter()
+      .fold(handle, |handle, step| step.global(handle))
+  }
+
+  fn fun_handle(&self, handle: &mut ErasedFunHandle) {
+    if let ErasedFunHandle::UserDefined(handle) = handle {
+      *handle = self.relocated_fun(*handle);
+    }
+  }
+
+  fn expr(&self, expr: &mut ErasedExpr) {
+    expr.rewrite(&mut Rewritten::default(), &mut |mut expr| {
+      match &mut expr {
+        ErasedExpr::FunCall(handle, _) => self.fun_handle(handle),
+        ErasedExpr::Var(ScopedHandle::Global(handle)) => *handle = self.relocated_global(*handle),
+        _ => (),
+      }
+
+      expr
+    });
+  }
+
+  fn scope(&self, scope: &mut ErasedScope) {
+    for instr in &mut scope.instructions {
+      if let ScopeInstr::Call(handle, _) = instr {
+        self.fun_handle(handle);
+      }
+
+      for expr in instr.exprs_mut() {
+        self.expr(expr);
+      }
+
+      for scope in instr.scopes_mut() {
+        self.scope(scope);
+      }
+    }
+  }
+
+  fn fun(&self, fun: &mut ErasedFun) {
+    self.scope(&mut fun.scope);
+
+    if let ErasedReturn::Expr(_, ref mut expr) = fun.ret {
+      self.expr(expr);
+    }
+  }
+
+  /// Relocate a declaration of a module or of a merged shader.
+  pub(crate) fn decl(&self, decl: &ShaderDecl) -> ShaderDecl {
+    match decl {
+      ShaderDecl::Main(fun) => {
+        let mut fun = fun.clone();
+        self.fun(&mut fun);
+        ShaderDecl::Main(fun)
+      }
+
+      ShaderDecl::FunDef(handle, fun) => {
+        let mut fun = fun.clone();
+        self.fun(&mut fun);
+        ShaderDecl::FunDef(self.relocated_fun(*handle), fun)
+      }
+
+      ShaderDecl::Const(handle, ty, expr) => {
+        let mut expr = expr.clone();
+        self.expr(&mut expr);
+        ShaderDecl::Const(self.relocated_global(*handle), ty.clone(), expr)
+      }
+
+      ShaderDecl::Shared(handle, ty) => {
+        ShaderDecl::Shared(self.relocated_global(*handle), ty.clone())
+      }
+
+      ShaderDecl::Comment(text) => ShaderDecl::Comment(text.clone()),
+      ShaderDecl::In(name, ty) => ShaderDecl::In(name.clone(), ty.clone()),
+      ShaderDecl::Out(name, ty) => ShaderDecl::Out(name.clone(), ty.clone()),
+      ShaderDecl::LayoutOut(name, ty, layout) => {
+        ShaderDecl::LayoutOut(name.clone(), ty.clone(), *layout)
+      }
+      ShaderDecl::Uniform(name, ty) => ShaderDecl::Uniform(name.clone(), ty.clone()),
+      ShaderDecl::PatchIn(name, ty) => ShaderDecl::PatchIn(name.clone(), ty.clone()),
+      ShaderDecl::PatchOut(name, ty) => ShaderDecl::PatchOut(name.clone(), ty.clone()),
+    }
+  }
+}
+
+/// Values exported by a [`ShaderModule`].
+///
+/// When a module is imported, the handles it exports are remapped to the handles of the imported declarations. Implement
+/// this trait for your own types by relocating each of their fields.
+pub trait Exports {
+  /// Remap the handles of `self` with `relocation`.
+  fn relocate(&self, relocation: &Relocation) -> Self;
+}
+
+impl Exports for () {
+  fn relocate(&self, _: &Relocation) -> Self {}
+}
+
+impl<R, A> Exports for FunHandle<R, A> {
+  fn relocate(&self, relocation: &Relocation) -> Self {
+    let mut erased = self.erased.clone();
+    relocation.fun_handle(&mut erased);
+
+    FunHandle {
+      erased,
+      _phantom: PhantomData,
+    }
+  }
+}
+
+impl<T> Exports for Expr<T>
+where
+  T: ?Sized,
+{
+  fn relocate(&self, relocation: &Relocation) -> Self {
+    let mut expr = self.clone();
+    relocation.expr(&mut expr.erased);
+    expr
+  }
+}
+
+impl<T> Exports for Vec<T>
+where
+  T: Exports,
+{
+  fn relocate(&self, relocation: &Relocation) -> Self {
+    self.iter().map(|x| x.relocate(relocation)).collect()
+  }
+}
+
+macro_rules! impl_Exports_tuple {
+  ($($t:ident),+) => {
+    impl<$($t),+> Exports for ($($t,)+)
+    where
+      $($t: Exports),+
+    {
+      #[allow(non_snake_case)]
+      fn relocate(&self, relocation: &Relocation) -> Self {
+        let ($($t,)+) = self;
+        ($($t.relocate(relocation),)+)
+      }
+    }
+  };
+}
+
+impl_Exports_tuple!(A);
+impl_Exports_tuple!(A, B);
+impl_Exports_tuple!(A, B, C);
+impl_Exports_tuple!(A, B, C, D);
+impl_Exports_tuple!(A, B, C, D, E);
+impl_Exports_tuple!(A, B, C, D, E, F);
+impl_Exports_tuple!(A, B, C, D, E, F, G);
+impl_Exports_tuple!(A, B, C, D, E, F, G, H);
@@ -0,0 +1,938 @@
+//! Passes transforming built shaders.
+//!
+//! Passes rewrite the AST of a [`Shader`] before it is written, in order to improve the quality of the generated code.
+//! This is especially useful for drivers with weak front-end optimizers.
+
+use crate::{
+  infer::TypeEnv, writer::glsl, ErasedExpr, ErasedFunHandle, ErasedReturn, ErasedScope, Rewritten,
+  ScopeInstr, ScopedHandle, Shader, ShaderDecl, Type,
+};
+use std::{
+  collections::{HashMap, HashSet},
+  mem,
+  sync::Arc,
+};
+
+/// Fold constants and simplify algebraic identities.
+///
+/// The following rewrites are applied, recursively, to every expression of the shader:
+///
+/// - Operators applied to literals only are folded into a single literal, as described in [`Expr`](crate::Expr).
+/// - `x + 0`, `0 + x`, `x - 0`, `x * 1`, `1 * x` and `x / 1` are replaced with `x`, where `0` and `1` are scalar
+///   literals.
+/// - `0 - x` is replaced with `-x`.
+/// - `--x` and `!!x` are replaced with `x`.
+/// - `x && true`, `true && x`, `x || false` and `false || x` are replaced with `x`.
+///
+/// # Examples
+///
+/// ```
+/// use shades::{Scope, ShaderBuilder, V3, inputs, vec4};
+/// use shades::{pass, writer::glsl};
+///
+/// let mut shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+///   inputs!(s, position: V3<f32>);
+///
+///   s.main_fun(|s: &mut Scope<()>| {
+///     s.set(vertex.position, vec4!(-(-position) * 1., 1.));
+///   })
+/// });
+///
+/// pass::simplify(&mut shader);
+///
+/// assert!(glsl::write_shader_to_str(&shader).unwrap().contains("gl_Position = vec4(position, 1.);"));
+/// ```
+pub fn simplify<S>(shader: &mut Shader<S>) {
+  let mut rewritten = Rewritten::default();
+  for_each_root_expr(&mut shader.builder.decls, &mut |expr| {
+    expr.rewrite(&mut rewritten, &mut simplify_expr)
+  });
+}
+
+/// Call `f` on every expression of `decls` which is not a sub-expression of another expression.
+fn for_each_root_expr(decls: &mut [ShaderDecl], f: &mut impl FnMut(&mut ErasedExpr)) {
+  for decl in decls {
+    match decl {
+      ShaderDecl::Main(fun) | ShaderDecl::FunDef(_, fun) => {
+        for_each_root_expr_in_scope(&mut fun.scope, f);
+
+        if let ErasedReturn::Expr(_, ref mut expr) = fun.ret {
+          f(expr);
+        }
+      }
+
+      ShaderDecl::Const(_, _, expr) => f(expr),
+
+      ShaderDecl::In(..)
+      | ShaderDecl::Out(..)
+      | ShaderDecl::LayoutOut(..)
+      | ShaderDecl::Uniform(..)
+      | ShaderDecl::PatchIn(..)
+      | ShaderDecl::PatchOut(..)
+      | ShaderDecl::Shared(..)
+      | ShaderDecl::Comment(_) => (),
+    }
+  }
+}
+
+fn for_each_root_expr_in_scope(scope: &mut ErasedScope, f: &mut impl FnMut(&mut ErasedExpr)) {
+  for instr in &mut scope.instructions {
+    for expr in instr.exprs_mut() {
+      f(expr);
+    }
+
+    for scope in instr.scopes_mut() {
+      for_each_root_expr_in_scope(scope, f);
+    }
+  }
+}
+
+/// Simplify `expr`, the sub-expressions of which are already simplified.
+fn simplify_expr(expr: ErasedExpr) -> ErasedExpr {
+  match expr {
+    ErasedExpr::Add(a, b) if is_zero(&b) => Arc::unwrap_or_clone(a),
+    ErasedExpr::Add(a, b) if is_zero(&a) => Arc::unwrap_or_clone(b),
+    ErasedExpr::Sub(a, b) if is_zero(&b) => Arc::unwrap_or_clone(a),
+    ErasedExpr::Sub(a, b) if is_zero(&a) => ErasedExpr::Neg(b).fold_literals(),
+    ErasedExpr::Mul(a, b) if is_one(&b) => Arc::unwrap_or_clone(a),
+    ErasedExpr::Mul(a, b) if is_one(&a) => Arc::unwrap_or_clone(b),
+    ErasedExpr::Div(a, b) if is_one(&b) => Arc::unwrap_or_clone(a),
+    ErasedExpr::Neg(a) => match Arc::unwrap_or_clone(a) {
+      ErasedExpr::Neg(a) => Arc::unwrap_or_clone(a),
+      a => ErasedExpr::Neg(a.intern()).fold_literals(),
+    },
+    ErasedExpr::Not(a) => match Arc::unwrap_or_clone(a) {
+      ErasedExpr::Not(a) => Arc::unwrap_or_clone(a),
+      a => ErasedExpr::Not(a.intern()).fold_literals(),
+    },
+    ErasedExpr::And(a, b) if *b == ErasedExpr::LitBool(true) => Arc::unwrap_or_clone(a),
+    ErasedExpr::And(a, b) if *a == ErasedExpr::LitBool(true) => Arc::unwrap_or_clone(b),
+    ErasedExpr::Or(a, b) if *b == ErasedExpr::LitBool(false) => Arc::unwrap_or_clone(a),
+    ErasedExpr::Or(a, b) if *a == ErasedExpr::LitBool(false) => Arc::unwrap_or_clone(b),
+    expr => expr.fold_literals(),
+  }
+}
+
+fn is_zero(expr: &ErasedExpr) -> bool {
+  matches!(expr, ErasedExpr::LitInt(0) | ErasedExpr::LitUInt(0))
+    || *expr == ErasedExpr::LitFloat(0.)
+}
+
+fn is_one(expr: &ErasedExpr) -> bool {
+  matches!(expr, ErasedExpr::LitInt(1) | ErasedExpr::LitUInt(1))
+    || *expr == ErasedExpr::LitFloat(1.)
+}
+
+/// Hoist repeated subexpressions into local variables.
+///
+/// Because [`Expr`](crate::Expr) is cloned by value, using the same expression several times duplicates it in the
+/// generated code. This pass looks for subexpressions appearing several times in the same instruction, and evaluates
+/// them once in a new variable, declared right before the instruction.
+///
+/// Only pure expressions are hoisted: instructions calling user-defined functions are left untouched, as well as
+/// conditions of loops and `else if`, which are evaluated several times or conditionally. Operands on the right side of
+/// `&&` and `||` are not evaluated if the left side decides the result already, so a subexpression appearing there
+/// doesn’t count as a repetition. Variables, literals and swizzles of those are cheap and never hoisted.
+///
+/// # Examples
+///
+/// ```
+/// use shades::{Scope, ShaderBuilder, V3, inputs, vec4};
+/// use shades::{pass, writer::glsl};
+///
+/// let mut shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+///   inputs!(s, position: V3<f32>);
+///
+///   s.main_fun(|s: &mut Scope<()>| {
+///     let scaled = &position * 2.;
+///     s.set(vertex.position, vec4!(&scaled + &scaled, 1.));
+///   })
+/// });
+///
+/// pass::eliminate_common_subexpressions(&mut shader);
+///
+/// let code = glsl::write_shader_to_str(&shader).unwrap();
+/// assert!(code.contains("vec3 var_0_0 = (position * 2.);"));
+/// assert!(code.contains("gl_Position = vec4((var_0_0 + var_0_0), 1.);"));
+/// ```
+pub fn eliminate_common_subexpressions<S>(shader: &mut Shader<S>) {
+  let globals = TypeEnv::new(&shader.builder.decls);
+
+  for decl in &mut shader.builder.decls {
+    if let ShaderDecl::Main(fun) | ShaderDecl::FunDef(_, fun) = decl {
+      let mut env = globals.with_fun_args(fun);
+      eliminate_common_subexprs_in_scope(&mut fun.scope, &mut env);
+
+      // the return expression is evaluated after the body of the function
+      if let ErasedReturn::Expr(_, ref mut expr) = fun.ret {
+        let decls = hoist_common_subexprs(&mut fun.scope, &mut env, vec![expr]);
+        for decl in decls {
+          fun.scope.push_at(decl, None);
+        }
+      }
+    }
+  }
+}
+
+/// Hoist the common subexpressions of every instruction of `scope`.
+///
+/// `env` is updated with the variables declared in `scope`, so that the types of later expressions can be inferred.
+fn eliminate_common_subexprs_in_scope(scope: &mut ErasedScope, env: &mut TypeEnv) {
+  for (mut instr, location) in scope.take_instructions() {
+    let roots = match &mut instr {
+      ScopeInstr::VarDecl { init_value, .. } => vec![init_value],
+      ScopeInstr::Return(ErasedReturn::Expr(_, expr)) => vec![expr],
+      ScopeInstr::If { condition, .. }
+      | ScopeInstr::Switch {
+        expr: condition, ..
+      } => vec![condition],
+      ScopeInstr::For { init_expr, .. } => vec![init_expr],
+      ScopeInstr::MutateVar { expr, .. } => vec![expr],
+      ScopeInstr::Call(_, args) => args.iter_mut().collect(),
+      _ => Vec::new(),
+    };
+
+    // the hoisted variables are located at the instruction they are hoisted from
+    let decls = hoist_common_subexprs(scope, env, roots);
+    for decl in decls {
+      scope.push_at(decl, location);
+    }
+
+    env.declare(&instr);
+    for nested in instr.scopes_mut() {
+      eliminate_common_subexprs_in_scope(nested, &mut env.clone());
+    }
+
+    scope.push_at(instr, location);
+  }
+}
+
+/// Replace the subexpressions repeated in `roots` with new variables of `scope`, and return their declarations.
+fn hoist_common_subexprs(
+  scope: &mut ErasedScope,
+  env: &mut TypeEnv,
+  mut roots: Vec<&mut ErasedExpr>,
+) -> Vec<ScopeInstr> {
+  let mut decls = Vec::new();
+
+  if roots.iter().any(|root| calls_user_fun(root)) {
+    return decls;
+  }
+
+  loop {
+    let nodes: Vec<_> = roots
+      .iter()
+      .map(|root| (**root).clone())
+      .chain(decls.iter().flat_map(ScopeInstr::exprs).cloned())
+      .map(ErasedExpr::intern)
+      .collect();
+
+    // hoist the biggest expressions first, so that the ones they contain are hoisted from their declaration afterwards
+    let mut sizes = HashMap::new();
+    let mut best: Option<(Arc<ErasedExpr>, Type, usize)> = None;
+    for (candidate, count) in count_subexprs(&nodes) {
+      let size = expr_size(&candidate, &mut sizes);
+
+      if count < 2 || matches!(best, Some((_, _, best_size)) if size <= best_size) {
+        continue;
+      }
+
+      if let Some(ty) = env.infer(&candidate) {
+        best = Some((candidate, ty, size));
+      }
+    }
+
+    let (candidate, ty, _) = match best {
+      Some(best) => best,
+      None => break,
+    };
+
+    let handle = ScopedHandle::fun_var(scope.id, scope.next_var);
+    scope.next_var += 1;
+
+    let mut rewritten = Rewritten::default();
+    rewritten.insert(candidate.clone(), ErasedExpr::Var(handle.clone()).intern());
+    for root in &mut roots {
+      root.rewrite(&mut rewritten, &mut |expr| expr);
+    }
+    for decl in &mut decls {
+      for expr in ScopeInstr::exprs_mut(decl) {
+        expr.rewrite(&mut rewritten, &mut |expr| expr);
+      }
+    }
+
+    // declare the variable after the ones it depends on
+    let index = decls
+      .iter()
+      .rposition(|decl| match decl {
+        ScopeInstr::VarDecl { handle, .. } => uses_var(&candidate, handle),
+        _ => false,
+      })
+      .map_or(0, |i| i + 1);
+
+    env.insert(handle.clone(), ty.clone());
+    decls.insert(
+      index,
+      ScopeInstr::VarDecl {
+        ty,
+        handle,
+        init_value: Arc::unwrap_or_clone(candidate),
+      },
+    );
+  }
+
+  decls
+}
+
+/// Count the occurrences of the subexpressions of `roots` worth hoisting, in the order they are first met.
+///
+/// Shared nodes are traversed once: the occurrences of a node are the number of paths leading to it from the roots.
+fn count_subexprs(roots: &[Arc<ErasedExpr>]) -> Vec<(Arc<ErasedExpr>, usize)> {
+  #[derive(Default)]
+  struct Nodes {
+    // nodes in pre-order, with the indices of the sub-expressions evaluated unconditionally
+    nodes: Vec<(Arc<ErasedExpr>, Vec<usize>)>,
+    indices: HashMap<*const ErasedExpr, usize>,
+    post_order: Vec<usize>,
+  }
+
+  impl Nodes {
+    fn visit(&mut self, node: &Arc<ErasedExpr>) -> usize {
+      if let Some(&index) = self.indices.get(&Arc::as_ptr(node)) {
+        return index;
+      }
+
+      let index = self.nodes.len();
+      self.indices.insert(Arc::as_ptr(node), index);
+      self.nodes.push((node.clone(), Vec::new()));
+
+      let sub_nodes = match &**node {
+        // the right operand is evaluated conditionally
+        ErasedExpr::And(a, _) | ErasedExpr::Or(a, _) => vec![a],
+        expr => expr.sub_nodes(),
+      };
+      let sub_nodes = sub_nodes
+        .into_iter()
+        .map(|sub_node| self.visit(sub_node))
+        .collect();
+
+      self.nodes[index].1 = sub_nodes;
+      self.post_order.push(index);
+      index
+    }
+  }
+
+  let mut nodes = Nodes::default();
+  let mut counts = Vec::new();
+  for root in roots {
+    let index = nodes.visit(root);
+    counts.resize(nodes.nodes.len(), 0_usize);
+    counts[index] += 1;
+  }
+
+  // parents come before their sub-expressions in reverse post-order
+  for &index in nodes.post_order.iter().rev() {
+    for &sub_node in &nodes.nodes[index].1 {
+      counts[sub_node] = counts[sub_node].saturating_add(counts[index]);
+    }
+  }
+
+  nodes
+    .nodes
+    .into_iter()
+    .zip(counts)
+    .filter(|((node, _), _)| is_hoistable(node))
+    .map(|((node, _), count)| (node, count))
+    .collect()
+}
+
+/// Whether `expr` is too expensive to be duplicated.
+fn is_hoistable(expr: &ErasedExpr) -> bool {
+  match expr {
+    ErasedExpr::Swizzle(a, _) | ErasedExpr::ArrayLength(a) => !is_leaf(a),
+    ErasedExpr::Field { object, .. } => !is_leaf(object),
+    ErasedExpr::ArrayLookup { object, index } => !is_leaf(object) || !is_leaf(index),
+    _ => !is_leaf(expr),
+  }
+}
+
+/// Variables, literals and calls without arguments.
+fn is_leaf(expr: &ErasedExpr) -> bool {
+  expr.sub_exprs().is_empty()
+}
+
+/// Number of nodes of `expr` once written, memoized in `sizes` for shared sub-expressions.
+fn expr_size(expr: &ErasedExpr, sizes: &mut HashMap<*const ErasedExpr, usize>) -> usize {
+  if let Some(&size) = sizes.get(&(expr as *const _)) {
+    return size;
+  }
+
+  let size = expr
+    .sub_exprs()
+    .into_iter()
+    .fold(1_usize, |size, sub_expr| {
+      size.saturating_add(expr_size(sub_expr, sizes))
+    });
+  sizes.insert(expr, size);
+  size
+}
+
+/// Whether `pred` holds for `expr` or one of its subexpressions, each shared sub-expression being tested once.
+fn any_subexpr(expr: &ErasedExpr, pred: &impl Fn(&ErasedExpr) -> bool) -> bool {
+  fn any(
+    expr: &ErasedExpr,
+    pred: &impl Fn(&ErasedExpr) -> bool,
+    seen: &mut HashSet<*const ErasedExpr>,
+  ) -> bool {
+    pred(expr)
+      || expr
+        .sub_exprs()
+        .into_iter()
+        .any(|sub_expr| seen.insert(sub_expr) && any(sub_expr, pred, seen))
+  }
+
+  any(expr, pred, &mut HashSet::new())
+}
+
+fn calls_user_fun(expr: &ErasedExpr) -> bool {
+  any_subexpr(expr, &|expr| {
+    matches!(
+      expr,
+      ErasedExpr::FunCall(ErasedFunHandle::UserDefined(_), _)
+    )
+  })
+}
+
+fn uses_var(expr: &ErasedExpr, handle: &ScopedHandle) -> bool {
+  any_subexpr(
+    expr,
+    &|expr| matches!(expr, ErasedExpr::Var(var) if var == handle),
+  )
+}
+
+/// Replace the variable `handle` with `value` in `expr`.
+fn replace_var(expr: &mut ErasedExpr, handle: &ScopedHandle, value: &ErasedExpr) {
+  expr.rewrite(&mut Rewritten::default(), &mut |expr| match expr {
+    ErasedExpr::Var(ref var) if var == handle => value.clone(),
+    expr => expr,
+  });
+}
+
+/// Unroll `for` loops with literal bounds.
+///
+/// A loop built with [`Scope::loop_for`](crate::Scope::loop_for) is unrolled if its initial value is a scalar literal,
+/// and if its condition and fold, evaluated iteration after iteration, only yield literals — e.g.
+/// `s.loop_for(0, |i| i.lt(4), |i| i + 1, …)`. Each iteration is replaced with a copy of the body in its own block, in
+/// which the accumulator is replaced with its value for that iteration. Those values are not folded further; run
+/// [`simplify`] afterwards to do so.
+///
+/// Loops iterating more than `max_iterations` times are left untouched, as well as loops which body contains a `break`
+/// or `continue` targeting the loop itself. Nested loops are unrolled first.
+///
+/// # Examples
+///
+/// ```
+/// use shades::{LoopScope, Scope, ShaderBuilder, V4, outputs, vec4};
+/// use shades::{pass, writer::glsl};
+///
+/// let mut shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+///   outputs!(s, color: V4<f32>);
+///
+///   s.main_fun(|s: &mut Scope<()>| {
+///     let c = s.var(0.);
+///     s.loop_for(0., |i| i.lt(2.), |i| i + 1., |s: &mut LoopScope<()>, i| {
+///       s.set(&c, &c + i);
+///     });
+///     s.set(color, vec4!(c, 0., 0., 1.));
+///   })
+/// });
+///
+/// pass::unroll_loops(&mut shader, 8);
+///
+/// let code = glsl::write_shader_to_str(&shader).unwrap();
+/// assert!(!code.contains("for"));
+/// assert!(code.contains("var_0_0 = (var_0_0 + 1.);"));
+/// ```
+pub fn unroll_loops<S>(shader: &mut Shader<S>, max_iterations: usize) {
+  for decl in &mut shader.builder.decls {
+    if let ShaderDecl::Main(fun) | ShaderDecl::FunDef(_, fun) = decl {
+      unroll_loops_in_scope(&mut fun.scope, max_iterations);
+    }
+  }
+}
+
+fn unroll_loops_in_scope(scope: &mut ErasedScope, max_iterations: usize) {
+  for (mut instr, location) in scope.take_instructions() {
+    for nested in instr.scopes_mut() {
+      unroll_loops_in_scope(nested, max_iterations);
+    }
+
+    if let ScopeInstr::For {
+      init_handle,
+      init_expr,
+      condition,
+      post_expr,
+      scope: body,
+      ..
+    } = &instr
+    {
+      let values = loop_iterations(init_handle, init_expr, condition, post_expr, max_iterations);
+
+      if let Some(values) = values.filter(|_| !escapes_loop(body, false)) {
+        for value in values {
+          let mut iteration = body.clone();
+          for_each_root_expr_in_scope(&mut iteration, &mut |expr| {
+            replace_var(expr, init_handle, &value)
+          });
+
+          scope.push_at(ScopeInstr::Block { scope: iteration }, location);
+        }
+
+        continue;
+      }
+    }
+
+    scope.push_at(instr, location);
+  }
+}
+
+/// Values taken by the accumulator of a `for` loop, if they are all literals and there are at most `max_iterations` of
+/// them.
+fn loop_iterations(
+  accumulator: &ScopedHandle,
+  init_expr: &ErasedExpr,
+  condition: &ErasedExpr,
+  post_expr: &ErasedExpr,
+  max_iterations: usize,
+) -> Option<Vec<ErasedExpr>> {
+  let eval = |expr: &ErasedExpr, value: Option<&ErasedExpr>| {
+    let mut expr = expr.clone();
+    expr.rewrite(&mut Rewritten::default(), &mut |expr| match (expr, value) {
+      (ErasedExpr::Var(ref var), Some(value)) if var == accumulator => value.clone(),
+      (expr, _) => simplify_expr(expr),
+    });
+    expr
+  };
+
+  let mut value = eval(init_expr, None);
+  let mut values = Vec::new();
+
+  loop {
+    if !matches!(
+      value,
+      ErasedExpr::LitInt(_) | ErasedExpr::LitUInt(_) | ErasedExpr::LitFloat(_)
+    ) {
+      return None;
+    }
+
+    match eval(condition, Some(&value)) {
+      ErasedExpr::LitBool(true) if values.len() < max_iterations => (),
+      ErasedExpr::LitBool(false) => return Some(values),
+      _ => return None,
+    }
+
+    let next = eval(post_expr, Some(&value));
+    values.push(mem::replace(&mut value, next));
+  }
+}
+
+/// Whether `scope` contains a `break` or a `continue` exiting the loop it belongs to.
+///
+/// `in_switch` is `true` if `scope` is a branch of a `switch`, in which case `break` exits the `switch` only.
+fn escapes_loop(scope: &ErasedScope, in_switch: bool) -> bool {
+  scope.instructions.iter().any(|instr| match instr {
+    ScopeInstr::Continue => true,
+    ScopeInstr::Break => !in_switch,
+
+    ScopeInstr::If { scope, .. }
+    | ScopeInstr::ElseIf { scope, .. }
+    | ScopeInstr::Else { scope }
+    | ScopeInstr::Block { scope } => escapes_loop(scope, in_switch),
+
+    ScopeInstr::Switch { cases, .. } => cases.iter().any(|(_, scope)| escapes_loop(scope, true)),
+
+    // nested loops catch their own break and continue
+    _ => false,
+  })
+}
+
+/// Rename the inputs, outputs and uniforms whose name cannot be used in GLSL.
+///
+/// Names are chosen by the user, and might be reserved keywords, start with the reserved `gl_` prefix or clash with the
+/// names generated by the writer, which [`Shader::check`] reports as errors. This pass fixes such names — for
+/// instance, `sample` becomes `sample_` and `gl_color` becomes `_gl_color` — and updates the variables referring to
+/// them. The renaming is deterministic, so outputs and inputs matching in different stages still match once renamed.
+///
+/// The renamed declarations are returned as `(old_name, new_name)` pairs, in declaration order, so that the host code
+/// can map attributes and uniforms accordingly.
+///
+/// # Examples
+///
+/// ```
+/// use shades::{Scope, ShaderBuilder, V3, inputs, vec4};
+/// use shades::{pass, writer::glsl};
+///
+/// let mut shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+///   inputs!(s, sample: V3<f32>);
+///
+///   s.main_fun(|s: &mut Scope<()>| {
+///     s.set(vertex.position, vec4!(sample, 1.));
+///   })
+/// });
+///
+/// assert!(shader.check().is_err());
+///
+/// let renamed = pass::rename_identifiers(&mut shader);
+/// assert_eq!(renamed, [("sample".to_owned(), "sample_".to_owned())]);
+/// assert!(shader.check().is_ok());
+/// assert!(glsl::write_shader_to_str(&shader).unwrap().contains("gl_Position = vec4(sample_, 1.);"));
+/// ```
+pub fn rename_identifiers<S>(shader: &mut Shader<S>) -> Vec<(String, String)> {
+  let mut taken = interface_names(&shader.builder.decls);
+
+  let mut renamed = Vec::new();
+  let mut handles = HashMap::new();
+
+  for decl in &mut shader.builder.decls {
+    let (name, handle): (_, fn(String) -> ScopedHandle) = match decl {
+      ShaderDecl::In(name, _) | ShaderDecl::PatchIn(name, _) => (name, ScopedHandle::Input),
+      ShaderDecl::Out(name, _)
+      | ShaderDecl::PatchOut(name, _)
+      | ShaderDecl::LayoutOut(name, ..) => (name, ScopedHandle::Output),
+      ShaderDecl::Uniform(name, _) => (name, ScopedHandle::uniform),
+      _ => continue,
+    };
+
+    if glsl::identifier_problem(name).is_none() {
+      continue;
+    }
+
+    let new_name = fixed_identifier(name, &taken);
+    taken.insert(new_name.clone());
+    handles.insert(handle(name.clone()), handle(new_name.clone()));
+    renamed.push((mem::replace(name, new_name.clone()), new_name));
+  }
+
+  if !handles.is_empty() {
+    let mut rewritten = Rewritten::default();
+    for_each_root_expr(&mut shader.builder.decls, &mut |expr| {
+      rename_vars(expr, &handles, &mut rewritten)
+    });
+  }
+
+  for attribute in &mut shader.builder.attributes {
+    if let Some((_, new_name)) = renamed
+      .iter()
+      .find(|(old_name, _)| *old_name == attribute.name)
+    {
+      attribute.name = new_name.clone();
+    }
+  }
+
+  renamed
+}
+
+/// Closest identifier to `name` which can be used in GLSL and is not `taken`.
+pub(crate) fn fixed_identifier(name: &str, taken: &HashSet<String>) -> String {
+  let mut fixed: String = name
+    .chars()
+    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+    .collect();
+
+  while fixed.contains("__") {
+    fixed = fixed.replace("__", "_");
+  }
+
+  if fixed.is_empty() || fixed.starts_with(|c: char| c.is_ascii_digit()) || fixed.starts_with("gl_")
+  {
+    fixed.insert(0, '_');
+  }
+
+  if glsl::identifier_problem(&fixed).is_some() {
+    // reserved keywords and generated names
+    if fixed.ends_with(|c: char| c.is_ascii_digit()) {
+      fixed.insert(0, '_');
+    } else if !fixed.ends_with('_') {
+      fixed.push('_');
+    }
+  }
+
+  let mut candidate = fixed.clone();
+  let mut i = 0;
+
+  while glsl::identifier_problem(&candidate).is_some() || taken.contains(&candidate) {
+    candidate = format!("{}{}", fixed, i);
+    i += 1;
+  }
+
+  candidate
+}
+
+/// Names of the inputs, outputs and uniforms declared in `decls`.
+pub(crate) fn interface_names(decls: &[ShaderDecl]) -> HashSet<String> {
+  decls
+    .iter()
+    .filter_map(|decl| match decl {
+      ShaderDecl::In(name, _)
+      | ShaderDecl::Out(name, _)
+      | ShaderDecl::LayoutOut(name, ..)
+      | ShaderDecl::Uniform(name, _)
+      | ShaderDecl::PatchIn(name, _)
+      | ShaderDecl::PatchOut(name, _) => Some(name.clone()),
+      _ => None,
+    })
+    .collect()
+}
+
+fn rename_vars(
+  expr: &mut ErasedExpr,
+  handles: &HashMap<ScopedHandle, ScopedHandle>,
+  rewritten: &mut Rewritten,
+) {
+  expr.rewrite(rewritten, &mut |expr| match expr {
+    ErasedExpr::Var(ref handle) if handles.contains_key(handle) => {
+      ErasedExpr::Var(handles[handle].clone())
+    }
+    expr => expr,
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    lit, outputs, vec4, writer::glsl, CanEscape as _, Expr, LoopScope, Scope, ShaderBuilder, V2, V4,
+  };
+
+  fn simplified<T>(expr: Expr<T>) -> ErasedExpr {
+    let mut erased = expr.erased;
+    erased.rewrite(&mut Rewritten::default(), &mut simplify_expr);
+    erased
+  }
+
+  #[test]
+  fn simplify_identities() {
+    let x = Expr::<f32>::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0)));
+    let v = Expr::<V2<f32>>::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 1)));
+    let b = Expr::<bool>::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 2)));
+
+    assert_eq!(simplified((&x + 0.) * 1.), x.erased);
+    assert_eq!(simplified(1. * (0. + &x) / 1. - 0.), x.erased);
+    assert_eq!(simplified(-(-&x)), x.erased);
+    assert_eq!(
+      simplified(0. - &x),
+      ErasedExpr::Neg(Arc::new(x.erased.clone()))
+    );
+    assert_eq!(simplified(!!&b), b.erased);
+    assert_eq!(simplified(b.and(true).or(false)), b.erased);
+    assert_eq!(simplified(&v * 1.), v.erased);
+
+    // literals are folded even if the expression was not built with the operators
+    let mut e = ErasedExpr::Mul(
+      Arc::new(ErasedExpr::Add(
+        Arc::new(ErasedExpr::LitFloat(1.)),
+        Arc::new(ErasedExpr::LitFloat(2.)),
+      )),
+      Arc::new(ErasedExpr::LitFloat(2.)),
+    );
+    e.rewrite(&mut Rewritten::default(), &mut simplify_expr);
+    assert_eq!(e, ErasedExpr::LitFloat(6.));
+  }
+
+  #[test]
+  fn eliminate_common_subexprs() {
+    let mut shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      outputs!(s, flag: bool);
+
+      let square = s.fun(|_: &mut Scope<Expr<f32>>, a: Expr<f32>| &a * &a + &a * &a);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let x = s.var(1.);
+        let y = &x * 2.;
+        let v = lit!(1., 2.) * &y;
+
+        s.set(vertex.position, vec4!(&v + &v, (&y + 1.) * (&y + 1.), 1.));
+        s.set(flag, x.lt(3.).or(y.gt(4.)).and(y.gt(4.)));
+        s.set(&x, square.call(y.clone()) + square.call(y.clone()));
+      })
+    });
+
+    eliminate_common_subexpressions(&mut shader);
+
+    let code = glsl::write_shader_to_str(&shader).unwrap();
+
+    // functions are processed too, including their return expression
+    assert!(code.contains("  float var_0_0 = (arg_0 * arg_0);\n  return (var_0_0 + var_0_0);"));
+
+    // biggest expressions are hoisted first, and declared after the variables they depend on
+    assert!(code.contains(
+      "  float var_0_3 = (var_0_0 * 2.);
+  float var_0_2 = (var_0_3 + 1.);
+  vec2 var_0_1 = (vec2(1., 2.) * var_0_3);
+  gl_Position = vec4((var_0_1 + var_0_1), (var_0_2 * var_0_2), 1.);"
+    ));
+
+    // right operands of || and && are evaluated conditionally
+    assert!(code.contains("((var_0_0 < 3.) || ((var_0_0 * 2.) > 4.))&&((var_0_0 * 2.) > 4.));"));
+
+    // user-defined functions might have side-effects
+    assert!(code.contains("var_0_0 = (fun_0((var_0_0 * 2.)) + fun_0((var_0_0 * 2.)));"));
+  }
+
+  #[test]
+  fn deeply_shared_subexprs() {
+    let mut shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      outputs!(s, y: f32);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let x = s.var(1.);
+
+        // the expression stands for a tree of 2³² nodes
+        let mut e = &x * 1.;
+        for _ in 0..32 {
+          e = (&e + 0.) * (&e + 1.);
+        }
+
+        s.set(y, e);
+      })
+    });
+
+    assert!(shader.check().is_ok());
+    simplify(&mut shader);
+    eliminate_common_subexpressions(&mut shader);
+    assert!(shader.check().is_ok());
+
+    // every level is evaluated once
+    let code = glsl::write_shader_to_str(&shader).unwrap();
+    assert!(code.contains("  float var_0_31 = (var_0_0 * (var_0_0 + 1.));\n"));
+    assert!(code.contains(
+      "  float var_0_1 = (var_0_2 * (var_0_2 + 1.));\n  y = (var_0_1 * (var_0_1 + 1.));\n"
+    ));
+    assert_eq!(code.lines().count(), 37);
+  }
+
+  #[test]
+  fn unroll_loops_literal_bounds() {
+    let shader = || {
+      ShaderBuilder::new_vertex_shader(|s, _| {
+        s.main_fun(|s: &mut Scope<()>| {
+          let x = s.var(0);
+
+          s.loop_for(
+            0,
+            |i| i.lt(2),
+            |i| i + 1,
+            |s: &mut LoopScope<()>, i| {
+              s.loop_for(
+                0,
+                |j| j.lt(3),
+                |j| j + 2,
+                |s: &mut LoopScope<()>, j| {
+                  s.set(&x, &x + i + j);
+                },
+              );
+
+              s.loop_for(
+                0,
+                |j| j.lt(&x),
+                |j| j + 1,
+                |s: &mut LoopScope<()>, j| {
+                  s.set(&x, &x + j);
+                },
+              );
+            },
+          );
+
+          s.loop_for(
+            0,
+            |i| i.lt(2),
+            |i| i + 1,
+            |s: &mut LoopScope<()>, _| {
+              s.when(x.gt(2), |s: &mut LoopScope<()>| s.loop_break());
+            },
+          );
+        })
+      })
+    };
+
+    let mut unrolled = shader();
+    unroll_loops(&mut unrolled, 2);
+    let code = glsl::write_shader_to_str(&unrolled).unwrap();
+
+    // nested loops are unrolled first, then the outer loop duplicates them
+    assert!(code.contains(
+      "  {
+    {
+      var_0_0 = ((var_0_0 + 1) + 0);
+    }
+    {
+      var_0_0 = ((var_0_0 + 1) + 2);
+    }
+    for (int var_3_0 = 0; (var_3_0 < var_0_0); var_3_0 = (var_3_0 + 1)) {"
+    ));
+    assert_eq!(code.matches("for (int var_3_0").count(), 2);
+
+    // the last loop breaks out of itself
+    assert!(
+      code.contains("for (int var_4_0 = 0; (var_4_0 < 2); var_4_0 = (var_4_0 + 1)) {\n    if")
+    );
+
+    // loops iterating more than the threshold are kept
+    let mut kept = shader();
+    unroll_loops(&mut kept, 1);
+    assert_eq!(
+      glsl::write_shader_to_str(&kept).unwrap(),
+      glsl::write_shader_to_str(shader()).unwrap()
+    );
+  }
+
+  #[test]
+  fn rename_reserved_identifiers() {
+    let mut shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      let sample = unsafe { s.input::<f32>("sample") };
+      let color = unsafe { s.input::<V4<f32>>("gl_color") };
+      let out = unsafe { s.output::<V4<f32>>("var_0") };
+      let time = unsafe { s.uniform::<f32>("my__time") };
+      let taken = unsafe { s.uniform::<f32>("sample_") };
+
+      s.main_fun(|s: &mut Scope<()>| {
+        s.set(out, color * sample * time * taken);
+      })
+    });
+
+    let renamed = rename_identifiers(&mut shader);
+
+    assert_eq!(
+      renamed,
+      [
+        ("sample".to_owned(), "sample_0".to_owned()),
+        ("gl_color".to_owned(), "_gl_color".to_owned()),
+        ("var_0".to_owned(), "_var_0".to_owned()),
+        ("my__time".to_owned(), "my_time".to_owned()),
+      ]
+    );
+    assert!(shader.check().is_ok());
+    assert!(glsl::write_shader_to_str(&shader)
+      .unwrap()
+      .contains("_var_0 = (((_gl_color * sample_0) * my_time) * sample_);"));
+
+    // names are already valid
+    assert!(rename_identifiers(&mut shader).is_empty());
+  }
+
+  #[test]
+  fn rename_attributes() {
+    use crate::attribute::{Semantic, VertexFormat};
+
+    let mut shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      let position =
+        unsafe { s.attribute::<V4<f32>>("gl_pos", VertexFormat::Float32x4, Semantic::Position) };
+      s.main_fun(|s: &mut Scope<()>| s.set(vertex.position, position))
+    });
+
+    rename_identifiers(&mut shader);
+
+    let position = shader.attribute(&Semantic::Position).unwrap();
+    assert_eq!(position.var().name(), "_gl_pos");
+  }
+}
@@ -0,0 +1,70 @@
+//! Type-level primitives read by geometry shaders.
+//!
+//! [`ShaderBuilder::input_primitive`](crate::ShaderBuilder::input_primitive) takes one of the types of this module
+//! rather than a [`GeometryInput`], so that the number of vertices of the primitive — and thus the size of the
+//! per-vertex inputs it returns — follows from the primitive, with no way to get it wrong.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{GeometryPerVertexIn, Expr, Scope, ShaderBuilder};
+//! use shades::primitive::Triangles;
+//!
+//! ShaderBuilder::new_geometry_shader(|mut s, _| {
+//!   let input: Expr<[GeometryPerVertexIn; 3]> = s.input_primitive(Triangles);
+//!   let _ = &input;
+//!
+//!   s.main_fun(|_: &mut Scope<()>| {})
+//! });
+//! ```
+
+use crate::{GeometryInput, GeometryPerVertexIn};
+
+/// Primitives read by a geometry shader, known at compile time.
+///
+/// This trait is sealed and cannot be implemented outside of this crate.
+pub trait InputPrimitive: sealed::Sealed {
+  /// Primitive, as set in the shader.
+  const PRIMITIVE: GeometryInput;
+
+  /// Number of vertices of the primitive.
+  const VERTICES: usize;
+
+  /// Per-vertex inputs of the shader: an array of [`Self::VERTICES`] [`GeometryPerVertexIn`].
+  type Vertices;
+}
+
+mod sealed {
+  pub trait Sealed {}
+}
+
+macro_rules! input_primitives {
+  ($($(#[$doc:meta])* $t:ident),*) => {
+    $(
+      $(#[$doc])*
+      #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+      pub struct $t;
+
+      impl sealed::Sealed for $t {}
+
+      impl InputPrimitive for $t {
+        const PRIMITIVE: GeometryInput = GeometryInput::$t;
+        const VERTICES: usize = GeometryInput::$t.vertices();
+        type Vertices = [GeometryPerVertexIn; GeometryInput::$t.vertices()];
+      }
+    )*
+  };
+}
+
+input_primitives!(
+  /// Points — `points`.
+  Points,
+  /// Lines — `lines`.
+  Lines,
+  /// Lines with their adjacent vertices — `lines_adjacency`.
+  LinesAdjacency,
+  /// Triangles — `triangles`.
+  Triangles,
+  /// Triangles with their adjacent vertices — `triangles_adjacency`.
+  TrianglesAdjacency
+);
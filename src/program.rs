@@ -0,0 +1,591 @@
+//! Programs, linking several shader stages together.
+//!
+//! A [`Program`] gathers a vertex [`Shader`], an optional pair of tessellation [`Shader`]s, an optional geometry
+//! [`Shader`] and a fragment [`Shader`]. Building a [`Program`] with [`ProgramBuilder::link`] checks that the inputs of
+//! each stage are provided by the outputs of the previous stage, with the same types and locations, so that interface
+//! mismatches are caught before the shading code reaches the GPU driver.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{Scope, ShaderBuilder, V3, V4, inputs, outputs, vec4};
+//! use shades::program::ProgramBuilder;
+//!
+//! let vertex_shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+//!   inputs!(s, position: V3<f32>);
+//!   outputs!(s, color: V3<f32>);
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     s.set(color, position.clone());
+//!     s.set(vertex.position, vec4!(position, 1.));
+//!   })
+//! });
+//!
+//! let fragment_shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+//!   inputs!(s, color: V3<f32>);
+//!   outputs!(s, frag: V4<f32>);
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     s.set(frag, vec4!(color, 1.));
+//!   })
+//! });
+//!
+//! let program = ProgramBuilder::new(vertex_shader, fragment_shader).link();
+//! assert!(program.is_ok());
+//! ```
+
+use crate::{
+  stage::{self, Stage, StageKind},
+  Shader, ShaderDecl, Type,
+};
+use std::{error::Error, fmt};
+
+/// A set of shader stages linked together.
+///
+/// Use a [`ProgramBuilder`] to create a [`Program`].
+#[derive(Debug)]
+pub struct Program {
+  vertex: Shader<stage::Vertex>,
+  tessellation: Option<(Shader<stage::TessCtrl>, Shader<stage::TessEval>)>,
+  geometry: Option<Shader<stage::Geometry>>,
+  fragment: Shader<stage::Fragment>,
+}
+
+impl Program {
+  /// Vertex stage of the program.
+  pub fn vertex(&self) -> &Shader<stage::Vertex> {
+    &self.vertex
+  }
+
+  /// Tessellation control stage of the program, if any.
+  pub fn tess_ctrl(&self) -> Option<&Shader<stage::TessCtrl>> {
+    self.tessellation.as_ref().map(|(ctrl, _)| ctrl)
+  }
+
+  /// Tessellation evaluation stage of the program, if any.
+  pub fn tess_eval(&self) -> Option<&Shader<stage::TessEval>> {
+    self.tessellation.as_ref().map(|(_, eval)| eval)
+  }
+
+  /// Geometry stage of the program, if any.
+  pub fn geometry(&self) -> Option<&Shader<stage::Geometry>> {
+    self.geometry.as_ref()
+  }
+
+  /// Fragment stage of the program.
+  pub fn fragment(&self) -> &Shader<stage::Fragment> {
+    &self.fragment
+  }
+}
+
+/// A [`Program`] builder.
+///
+/// The vertex and fragment stages are mandatory and passed to [`ProgramBuilder::new`]. The other stages are optional
+/// and can be added with [`ProgramBuilder::tessellation`] and [`ProgramBuilder::geometry`].
+#[derive(Debug)]
+pub struct ProgramBuilder {
+  program: Program,
+}
+
+impl ProgramBuilder {
+  /// Start building a [`Program`] out of a vertex and a fragment stage.
+  pub fn new(vertex: Shader<stage::Vertex>, fragment: Shader<stage::Fragment>) -> Self {
+    Self {
+      program: Program {
+        vertex,
+        tessellation: None,
+        geometry: None,
+        fragment,
+      },
+    }
+  }
+
+  /// Add the tessellation stages, which run between the vertex and the geometry (or fragment) stages.
+  pub fn tessellation(
+    mut self,
+    tess_ctrl: Shader<stage::TessCtrl>,
+    tess_eval: Shader<stage::TessEval>,
+  ) -> Self {
+    self.program.tessellation = Some((tess_ctrl, tess_eval));
+    self
+  }
+
+  /// Add the geometry stage, which runs right before the fragment stage.
+  pub fn geometry(mut self, geometry: Shader<stage::Geometry>) -> Self {
+    self.program.geometry = Some(geometry);
+    self
+  }
+
+  /// Link the stages together.
+  ///
+  /// Every input of a stage must be matched by an output with the same name and type in the previous stage. Inputs
+  /// of the tessellation and geometry stages, as well as outputs of the tessellation control stage, are per-vertex:
+  /// they must be declared as arrays of the type on the other side of the interface. As inputs cannot have explicit
+  /// locations, outputs read by the next stage must not have one either.
+  ///
+  /// Outputs that are not read by the next stage are allowed.
+  pub fn link(self) -> Result<Program, LinkError> {
+    let program = self.program;
+
+    let mut stages = vec![Interface::new(&program.vertex)];
+
+    if let Some((ref ctrl, ref eval)) = program.tessellation {
+      stages.push(Interface::new(ctrl));
+      stages.push(Interface::new(eval));
+    }
+
+    if let Some(ref geometry) = program.geometry {
+      stages.push(Interface::new(geometry));
+    }
+
+    stages.push(Interface::new(&program.fragment));
+
+    for pair in stages.windows(2) {
+      pair[0].link(&pair[1])?;
+    }
+
+    Ok(program)
+  }
+}
+
+/// Interface of a stage: its inputs and outputs, per-vertex and per-patch.
+///
+/// Per-vertex inputs and outputs come with their explicit locations, if any.
+struct Interface<'a> {
+  stage: StageKind,
+  inputs: Vec<(&'a str, &'a Type, Option<u32>)>,
+  outputs: Vec<(&'a str, &'a Type, Option<u32>)>,
+  patch_inputs: Vec<(&'a str, &'a Type)>,
+  patch_outputs: Vec<(&'a str, &'a Type)>,
+}
+
+impl<'a> Interface<'a> {
+  fn new<S>(shader: &'a Shader<S>) -> Self
+  where
+    S: Stage,
+  {
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    let mut patch_inputs = Vec::new();
+    let mut patch_outputs = Vec::new();
+
+    for decl in &shader.builder.decls {
+      match decl {
+        // inputs cannot be given explicit locations
+        ShaderDecl::In(name, ty) => inputs.push((name.as_str(), ty, None)),
+        ShaderDecl::Out(name, ty) => outputs.push((name.as_str(), ty, None)),
+        ShaderDecl::LayoutOut(name, ty, layout) => {
+          outputs.push((name.as_str(), ty, layout.location))
+        }
+        ShaderDecl::PatchIn(name, ty) => patch_inputs.push((name.as_str(), ty)),
+        ShaderDecl::PatchOut(name, ty) => patch_outputs.push((name.as_str(), ty)),
+        _ => (),
+      }
+    }
+
+    Self {
+      stage: S::KIND,
+      inputs,
+      outputs,
+      patch_inputs,
+      patch_outputs,
+    }
+  }
+
+  /// Check that the inputs of `next` are all provided by the outputs of `self`.
+  fn link(&self, next: &Self) -> Result<(), LinkError> {
+    // per-patch variables have a single value per patch, so they are never arrayed
+    for &(name, input_ty) in &next.patch_inputs {
+      let output_ty = self
+        .patch_outputs
+        .iter()
+        .find(|(output_name, _)| *output_name == name)
+        .map(|&(_, ty)| ty)
+        .ok_or_else(|| LinkError::MissingOutput {
+          producer: self.stage,
+          consumer: next.stage,
+          name: name.to_owned(),
+        })?;
+
+      if output_ty != input_ty {
+        return Err(LinkError::TypeMismatch {
+          producer: self.stage,
+          consumer: next.stage,
+          name: name.to_owned(),
+          output_ty: output_ty.clone(),
+          input_ty: input_ty.clone(),
+        });
+      }
+    }
+
+    for &(name, input_ty, input_location) in &next.inputs {
+      let (output_ty, output_location) = self
+        .outputs
+        .iter()
+        .find(|(output_name, _, _)| *output_name == name)
+        .map(|&(_, ty, location)| (ty, location))
+        .ok_or_else(|| LinkError::MissingOutput {
+          producer: self.stage,
+          consumer: next.stage,
+          name: name.to_owned(),
+        })?;
+
+      let not_arrayed = |stage, ty: &Type| LinkError::NotArrayed {
+        stage,
+        name: name.to_owned(),
+        ty: ty.clone(),
+      };
+      let output_per_vertex = per_vertex(output_ty, self.stage == StageKind::TessCtrl)
+        .ok_or_else(|| not_arrayed(self.stage, output_ty))?;
+      let input_per_vertex = per_vertex(
+        input_ty,
+        matches!(
+          next.stage,
+          StageKind::TessCtrl | StageKind::TessEval | StageKind::Geometry
+        ),
+      )
+      .ok_or_else(|| not_arrayed(next.stage, input_ty))?;
+
+      if output_per_vertex != input_per_vertex {
+        return Err(LinkError::TypeMismatch {
+          producer: self.stage,
+          consumer: next.stage,
+          name: name.to_owned(),
+          output_ty: output_ty.clone(),
+          input_ty: input_ty.clone(),
+        });
+      }
+
+      if output_location != input_location {
+        return Err(LinkError::LocationMismatch {
+          producer: self.stage,
+          consumer: next.stage,
+          name: name.to_owned(),
+          output_location,
+          input_location,
+        });
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// Per-vertex type of an interface variable.
+///
+/// `arrayed` interface variables have one value per vertex, so their outermost array dimension is removed. If such a
+/// variable is not an array, [`None`] is returned.
+fn per_vertex(ty: &Type, arrayed: bool) -> Option<Type> {
+  if !arrayed {
+    return Some(ty.clone());
+  }
+
+  if ty.array_dims.is_empty() {
+    return None;
+  }
+
+  Some(Type {
+    prim_ty: ty.prim_ty.clone(),
+    array_dims: ty.array_dims[1..].to_vec(),
+  })
+}
+
+/// Errors that can occur while linking a [`Program`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LinkError {
+  /// An input of the `consumer` stage is not an output of the `producer` stage.
+  MissingOutput {
+    producer: StageKind,
+    consumer: StageKind,
+    name: String,
+  },
+
+  /// An input of the `consumer` stage doesn’t have the same type as the corresponding output of the `producer` stage.
+  TypeMismatch {
+    producer: StageKind,
+    consumer: StageKind,
+    name: String,
+    output_ty: Type,
+    input_ty: Type,
+  },
+
+  /// A per-vertex input or output of the `stage` stage is not an array.
+  ///
+  /// Inputs of the tessellation and geometry stages, as well as outputs of the tessellation control stage, have one
+  /// value per vertex, so they must be declared as arrays.
+  NotArrayed {
+    stage: StageKind,
+    name: String,
+    ty: Type,
+  },
+
+  /// An input of the `consumer` stage doesn’t have the same explicit location as the corresponding output of the
+  /// `producer` stage.
+  ///
+  /// Stages are matched by location when one side has an explicit location, and inputs cannot have one, so an output
+  /// read by the next stage must not be given an explicit location.
+  LocationMismatch {
+    producer: StageKind,
+    consumer: StageKind,
+    name: String,
+    output_location: Option<u32>,
+    input_location: Option<u32>,
+  },
+}
+
+impl fmt::Display for LinkError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LinkError::MissingOutput {
+        producer,
+        consumer,
+        name,
+      } => write!(
+        f,
+        "input {} of the {} stage is not an output of the {} stage",
+        name, consumer, producer
+      ),
+
+      LinkError::TypeMismatch {
+        producer,
+        consumer,
+        name,
+        output_ty,
+        input_ty,
+      } => write!(
+        f,
+        "input {} of the {} stage has type {:?}, but the {} stage outputs {:?}",
+        name, consumer, input_ty, producer, output_ty
+      ),
+
+      LinkError::NotArrayed { stage, name, ty } => write!(
+        f,
+        "{} of the {} stage has one value per vertex, but its type {:?} is not an array",
+        name, stage, ty
+      ),
+
+      LinkError::LocationMismatch {
+        producer,
+        consumer,
+        name,
+        output_location,
+        input_location,
+      } => write!(
+        f,
+        "input {} of the {} stage has location {:?}, but the {} stage outputs it at location {:?}",
+        name, consumer, input_location, producer, output_location
+      ),
+    }
+  }
+}
+
+impl Error for LinkError {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{inputs, outputs, OutputLayout, Scope, ShaderBuilder, ToType as _, V3, V4};
+
+  fn vertex_shader() -> Shader<stage::Vertex> {
+    ShaderBuilder::new_vertex_shader(|mut s, _| {
+      outputs!(s, color: V3<f32>);
+      s.main_fun(|s: &mut Scope<()>| s.set(color, crate::lit!(1., 0., 0.)))
+    })
+  }
+
+  #[test]
+  fn link_missing_output() {
+    let fragment_shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, normal: V3<f32>);
+      s.main_fun(|_: &mut Scope<()>| {
+        let _ = &normal;
+      })
+    });
+
+    assert_eq!(
+      ProgramBuilder::new(vertex_shader(), fragment_shader)
+        .link()
+        .err(),
+      Some(LinkError::MissingOutput {
+        producer: StageKind::Vertex,
+        consumer: StageKind::Fragment,
+        name: "normal".to_owned(),
+      })
+    );
+  }
+
+  #[test]
+  fn link_type_mismatch() {
+    let fragment_shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, color: V4<f32>);
+      s.main_fun(|_: &mut Scope<()>| {
+        let _ = &color;
+      })
+    });
+
+    assert_eq!(
+      ProgramBuilder::new(vertex_shader(), fragment_shader)
+        .link()
+        .err(),
+      Some(LinkError::TypeMismatch {
+        producer: StageKind::Vertex,
+        consumer: StageKind::Fragment,
+        name: "color".to_owned(),
+        output_ty: V3::<f32>::ty(),
+        input_ty: V4::<f32>::ty(),
+      })
+    );
+  }
+
+  #[test]
+  fn link_geometry_per_vertex() {
+    let geometry_shader = |n| {
+      ShaderBuilder::new_geometry_shader(move |mut s, _| {
+        if n == 3 {
+          inputs!(s, color: [V3<f32>; 3]);
+          let _ = &color;
+        } else {
+          inputs!(s, color: V3<f32>);
+          let _ = &color;
+        }
+
+        outputs!(s, color_out: V3<f32>);
+        s.main_fun(|_: &mut Scope<()>| {
+          let _ = &color_out;
+        })
+      })
+    };
+    let fragment_shader =
+      || ShaderBuilder::new_fragment_shader(|s, _| s.main_fun(|_: &mut Scope<()>| {}));
+
+    assert!(ProgramBuilder::new(vertex_shader(), fragment_shader())
+      .geometry(geometry_shader(3))
+      .link()
+      .is_ok());
+
+    assert_eq!(
+      ProgramBuilder::new(vertex_shader(), fragment_shader())
+        .geometry(geometry_shader(1))
+        .link()
+        .err(),
+      Some(LinkError::NotArrayed {
+        stage: StageKind::Geometry,
+        name: "color".to_owned(),
+        ty: V3::<f32>::ty(),
+      })
+    );
+  }
+
+  #[test]
+  fn link_patch() {
+    let tess_ctrl_shader = ShaderBuilder::new_tess_ctrl_shader(|mut s, _| {
+      s.vertices::<3>();
+      let center = unsafe { s.patch_output::<V3<f32>>("center") };
+      s.main_fun(move |s: &mut Scope<()>| s.set(center, crate::lit!(0., 0., 0.)))
+    });
+    let tess_eval_shader = |per_patch| {
+      ShaderBuilder::new_tess_eval_shader(move |mut s, _| {
+        // per-patch inputs are not arrayed, per-vertex ones are
+        if per_patch {
+          let _ = unsafe { s.patch_input::<V3<f32>>("center") };
+        } else {
+          inputs!(s, center: [V3<f32>; 3]);
+          let _ = &center;
+        }
+
+        s.main_fun(|_: &mut Scope<()>| {})
+      })
+    };
+    let fragment_shader =
+      || ShaderBuilder::new_fragment_shader(|s, _| s.main_fun(|_: &mut Scope<()>| {}));
+
+    assert!(ProgramBuilder::new(vertex_shader(), fragment_shader())
+      .tessellation(tess_ctrl_shader, tess_eval_shader(true))
+      .link()
+      .is_ok());
+
+    let tess_ctrl_shader = ShaderBuilder::new_tess_ctrl_shader(|mut s, _| {
+      s.vertices::<3>();
+      let center = unsafe { s.patch_output::<V3<f32>>("center") };
+      s.main_fun(move |s: &mut Scope<()>| s.set(center, crate::lit!(0., 0., 0.)))
+    });
+
+    assert_eq!(
+      ProgramBuilder::new(vertex_shader(), fragment_shader())
+        .tessellation(tess_ctrl_shader, tess_eval_shader(false))
+        .link()
+        .err(),
+      Some(LinkError::MissingOutput {
+        producer: StageKind::TessCtrl,
+        consumer: StageKind::TessEval,
+        name: "center".to_owned(),
+      })
+    );
+  }
+
+  #[test]
+  fn link_location_mismatch() {
+    let mut vertex_shader = vertex_shader();
+    for decl in &mut vertex_shader.builder.decls {
+      if let ShaderDecl::Out(name, ty) = decl {
+        let layout = OutputLayout {
+          location: Some(1),
+          ..OutputLayout::default()
+        };
+        *decl = ShaderDecl::LayoutOut(name.clone(), ty.clone(), layout);
+      }
+    }
+
+    let fragment_shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, color: V3<f32>);
+      s.main_fun(|_: &mut Scope<()>| {
+        let _ = &color;
+      })
+    });
+
+    assert_eq!(
+      ProgramBuilder::new(vertex_shader, fragment_shader)
+        .link()
+        .err(),
+      Some(LinkError::LocationMismatch {
+        producer: StageKind::Vertex,
+        consumer: StageKind::Fragment,
+        name: "color".to_owned(),
+        output_location: Some(1),
+        input_location: None,
+      })
+    );
+  }
+
+  #[test]
+  fn link_tess_ctrl_output_not_arrayed() {
+    let tess_ctrl_shader = ShaderBuilder::new_tess_ctrl_shader(|mut s, _| {
+      s.vertices::<3>();
+      inputs!(s, color: [V3<f32>; 3]);
+      outputs!(s, color_out: V3<f32>);
+      s.main_fun(|_: &mut Scope<()>| {
+        let _ = (&color, &color_out);
+      })
+    });
+    let tess_eval_shader = ShaderBuilder::new_tess_eval_shader(|mut s, _| {
+      inputs!(s, color_out: [V3<f32>; 3]);
+      s.main_fun(|_: &mut Scope<()>| {
+        let _ = &color_out;
+      })
+    });
+    let fragment_shader =
+      ShaderBuilder::new_fragment_shader(|s, _| s.main_fun(|_: &mut Scope<()>| {}));
+
+    assert_eq!(
+      ProgramBuilder::new(vertex_shader(), fragment_shader)
+        .tessellation(tess_ctrl_shader, tess_eval_shader)
+        .link()
+        .err(),
+      Some(LinkError::NotArrayed {
+        stage: StageKind::TessCtrl,
+        name: "color_out".to_owned(),
+        ty: V3::<f32>::ty(),
+      })
+    );
+  }
+}
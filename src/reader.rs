@@ -0,0 +1,2 @@
+//! All available _lang -> shades_ readers.
+pub mod glsl;
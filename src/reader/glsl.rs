@@ -0,0 +1,1893 @@
+//! GLSL reader, enabled with the `glsl` feature gate.
+//!
+//! [`read_shader`] parses GLSL source code into a [`Shader`], so that existing shaders can be imported, transformed with
+//! the [passes](crate::pass) and written again with any [writer](crate::writer). Parsing is done by the
+//! [glsl](https://crates.io/crates/glsl) crate.
+//!
+//! Only the subset of GLSL shades can represent is supported:
+//!
+//! - `in`, `out`, `uniform` and `const` declarations of scalars, vectors, matrices and arrays of those. `attribute` is
+//!   read as `in`, and `varying` as `out` in the vertex stage and `in` in the others. Layout, precision, interpolation
+//!   and invariance qualifiers are dropped.
+//! - Functions taking `in` parameters only. Functions returning a value must end with a `return` statement, and
+//!   functions must be defined before being called.
+//! - Variable declarations, assignments, increments, function calls and `if`, `switch`, `for`, `while`, `do`, `break`,
+//!   `continue` and `return` statements.
+//! - Conditional expressions `c ? a : b`, which are lowered to `if` statements in front of the statement they appear
+//!   in. They are not supported in loop conditions, `else if` conditions and constants, which are not evaluated once
+//!   right before their statement.
+//! - `#version` directives, which are ignored, `#pragma` directives, which are kept with [`ShaderBuilder::pragma`],
+//!   and `#extension` directives. The `GL_KHR_shader_subgroup_*` extensions require [`Capability::Subgroup`]; the
+//!   others are declared with [`ShaderBuilder::require_extension`].
+//!
+//! Anything else — macros, interface blocks, structs, samplers, double-precision types, `discard`, etc. — makes
+//! [`read_shader`] fail with [`ReadError::Unsupported`].
+//!
+//! Inputs, outputs and uniforms keep their names, but functions, constants and variables are renamed, as if the shader
+//! had been built with a [`ShaderBuilder`].
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{reader::glsl::read_shader, stage, writer::glsl::write_shader_to_str};
+//!
+//! let shader = read_shader::<stage::Fragment>(
+//!   "
+//!   uniform float time;
+//!   out vec4 color;
+//!
+//!   float pulse(float t) {
+//!     return abs(sin(t));
+//!   }
+//!
+//!   void main() {
+//!     color = vec4(pulse(time), 0., 0., 1.);
+//!   }
+//!   ",
+//! )
+//! .unwrap();
+//!
+//! let code = write_shader_to_str(&shader).unwrap();
+//! assert!(code.contains("float fun_0(float arg_0) {"));
+//! assert!(code.contains("color = vec4(fun_0(time), 0., 0., 1.);"));
+//! ```
+
+use crate::{
+  infer::TypeEnv,
+  stage::{Stage, StageKind},
+  Behavior, BuiltIn, Capability, ComputeBuiltIn, Dim, ErasedExpr, ErasedFun, ErasedFunHandle,
+  ErasedReturn, ErasedScope, FragmentBuiltIn, GeometryBuiltIn, Matrix, MatrixDim, PrimType,
+  ScopeInstr, ScopedHandle, Shader, ShaderBuilder, ShaderDecl, SubgroupBuiltIn, Swizzle,
+  SwizzleSelector, TessCtrlBuiltIn, TessEvalBuiltIn, Type, VertexBuiltIn,
+};
+use ::glsl::{parser::Parse as _, syntax};
+use std::{collections::HashMap, convert::TryInto as _, error::Error, fmt, mem, sync::Arc};
+
+/// Read a shader of stage `S` from its GLSL `source`.
+pub fn read_shader<S>(source: &str) -> Result<Shader<S>, ReadError>
+where
+  S: Stage,
+{
+  let unit = syntax::TranslationUnit::parse(source).map_err(|e| ReadError::Parse(e.info))?;
+  let mut reader = Reader {
+    stage: S::KIND,
+    builder: ShaderBuilder::new(),
+    globals: HashMap::new(),
+    funs: HashMap::new(),
+    has_main: false,
+  };
+
+  for decl in &(unit.0).0 {
+    reader.external_decl(decl)?;
+  }
+
+  if !reader.has_main {
+    return Err(ReadError::MissingMain);
+  }
+
+  Ok(Shader {
+    builder: reader.builder,
+  })
+}
+
+/// Errors that can occur while reading a shader.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ReadError {
+  /// The source is not valid GLSL.
+  Parse(String),
+
+  /// The source uses something shades cannot represent.
+  Unsupported(String),
+
+  /// The source refers to a variable or function which is not declared — or not declared yet.
+  Undefined(String),
+
+  /// The source doesn’t define a `main` function.
+  MissingMain,
+}
+
+impl fmt::Display for ReadError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ReadError::Parse(info) => write!(f, "cannot parse GLSL: {}", info),
+      ReadError::Unsupported(what) => write!(f, "unsupported: {}", what),
+      ReadError::Undefined(name) => write!(f, "{} is not defined", name),
+      ReadError::MissingMain => f.write_str("no main function"),
+    }
+  }
+}
+
+impl Error for ReadError {}
+
+fn unsupported<T>(what: impl Into<String>) -> Result<T, ReadError> {
+  Err(ReadError::Unsupported(what.into()))
+}
+
+/// Reader of the top-level declarations of a shader.
+struct Reader<S> {
+  stage: StageKind,
+  builder: ShaderBuilder<S>,
+  // inputs, outputs, uniforms and constants, by name
+  globals: HashMap<String, ScopedHandle>,
+  // handles and argument types of the functions defined so far, by name; overloaded names have several of them
+  funs: HashMap<String, Vec<(u16, Vec<Type>)>>,
+  has_main: bool,
+}
+
+impl<S> Reader<S> {
+  fn external_decl(&mut self, decl: &syntax::ExternalDeclaration) -> Result<(), ReadError> {
+    match decl {
+      syntax::ExternalDeclaration::Preprocessor(preprocessor) => self.preprocessor(preprocessor),
+      syntax::ExternalDeclaration::FunctionDefinition(def) => self.fun_def(def),
+      syntax::ExternalDeclaration::Declaration(decl) => self.decl(decl),
+    }
+  }
+
+  fn preprocessor(&mut self, preprocessor: &syntax::Preprocessor) -> Result<(), ReadError> {
+    match preprocessor {
+      // writers pick the version they target
+      syntax::Preprocessor::Version(_) => Ok(()),
+
+      syntax::Preprocessor::Pragma(pragma) => {
+        self.builder.pragma(&pragma.command);
+        Ok(())
+      }
+
+      syntax::Preprocessor::Extension(syntax::PreprocessorExtension {
+        name: syntax::PreprocessorExtensionName::Specific(name),
+        behavior,
+      }) => {
+        if name.starts_with("GL_KHR_shader_subgroup_") {
+          self.builder.require(Capability::Subgroup);
+        } else {
+          let behavior = match behavior {
+            Some(syntax::PreprocessorExtensionBehavior::Require) | None => Behavior::Require,
+            Some(syntax::PreprocessorExtensionBehavior::Enable) => Behavior::Enable,
+            Some(syntax::PreprocessorExtensionBehavior::Warn) => Behavior::Warn,
+            Some(syntax::PreprocessorExtensionBehavior::Disable) => Behavior::Disable,
+          };
+          self.builder.require_extension(name, behavior);
+        }
+
+        Ok(())
+      }
+
+      syntax::Preprocessor::Extension(_) => unsupported("#extension all"),
+
+      _ => unsupported("preprocessor directives other than #version, #extension and #pragma"),
+    }
+  }
+
+  fn decl(&mut self, decl: &syntax::Declaration) -> Result<(), ReadError> {
+    match decl {
+      // precision is left to the target
+      syntax::Declaration::Precision(..) => Ok(()),
+
+      // functions must be defined before being called, so prototypes bring nothing
+      syntax::Declaration::FunctionPrototype(_) => Ok(()),
+
+      syntax::Declaration::InitDeclaratorList(list) => {
+        let storage = storage(list.head.ty.qualifier.as_ref())?;
+
+        for (name, ty, init) in declarators(list)? {
+          self.global(storage, name, ty, init)?;
+        }
+
+        Ok(())
+      }
+
+      syntax::Declaration::Block(_) => unsupported("interface blocks"),
+
+      syntax::Declaration::Global(..) => unsupported("declarations made of qualifiers only"),
+    }
+  }
+
+  fn global(
+    &mut self,
+    storage: Option<&syntax::StorageQualifier>,
+    name: &str,
+    ty: Type,
+    init: Option<&syntax::Expr>,
+  ) -> Result<(), ReadError> {
+    if let Some(syntax::StorageQualifier::Const) = storage {
+      let init = match init {
+        Some(init) => FunReader::new(self, &[], None).expr(init)?,
+        None => return unsupported("constants without initializer"),
+      };
+
+      let handle = self.builder.next_global_handle;
+      self.builder.next_global_handle += 1;
+
+      self
+        .globals
+        .insert(name.to_owned(), ScopedHandle::global(handle));
+      self.builder.decls.push(ShaderDecl::Const(handle, ty, init));
+
+      return Ok(());
+    }
+
+    if init.is_some() {
+      return unsupported("initialized inputs, outputs and uniforms");
+    }
+
+    let name = name.to_owned();
+    let (handle, decl) = match storage {
+      Some(syntax::StorageQualifier::In) | Some(syntax::StorageQualifier::Attribute) => (
+        ScopedHandle::Input(name.clone()),
+        ShaderDecl::In(name.clone(), ty),
+      ),
+
+      Some(syntax::StorageQualifier::Varying) if self.stage != StageKind::Vertex => (
+        ScopedHandle::Input(name.clone()),
+        ShaderDecl::In(name.clone(), ty),
+      ),
+
+      Some(syntax::StorageQualifier::Out) | Some(syntax::StorageQualifier::Varying) => (
+        ScopedHandle::Output(name.clone()),
+        ShaderDecl::Out(name.clone(), ty),
+      ),
+
+      Some(syntax::StorageQualifier::Uniform) => (
+        ScopedHandle::uniform(name.clone()),
+        ShaderDecl::Uniform(name.clone(), ty),
+      ),
+
+      Some(storage) => return unsupported(format!("{} declarations", storage_name(storage))),
+
+      None => return unsupported("global variables"),
+    };
+
+    self.globals.insert(name, handle);
+    self.builder.decls.push(decl);
+
+    Ok(())
+  }
+
+  fn fun_def(&mut self, def: &syntax::FunctionDefinition) -> Result<(), ReadError> {
+    let proto = &def.prototype;
+    let name = proto.name.as_str();
+
+    let ret = match proto.ty.ty {
+      syntax::TypeSpecifier {
+        ty: syntax::TypeSpecifierNonArray::Void,
+        array_specifier: None,
+      } => None,
+      ref ty => Some(read_ty(ty, None)?),
+    };
+
+    let mut args = Vec::new();
+    for param in &proto.parameters {
+      let (qualifier, arg) = match param {
+        syntax::FunctionParameterDeclaration::Named(qualifier, decl) => (
+          qualifier,
+          (
+            Some(decl.ident.ident.as_str()),
+            read_ty(&decl.ty, decl.ident.array_spec.as_ref())?,
+          ),
+        ),
+
+        // f(void)
+        syntax::FunctionParameterDeclaration::Unnamed(
+          None,
+          syntax::TypeSpecifier {
+            ty: syntax::TypeSpecifierNonArray::Void,
+            array_specifier: None,
+          },
+        ) => continue,
+
+        syntax::FunctionParameterDeclaration::Unnamed(qualifier, ty) => {
+          (qualifier, (None, read_ty(ty, None)?))
+        }
+      };
+
+      match storage(qualifier.as_ref())? {
+        None | Some(syntax::StorageQualifier::In) | Some(syntax::StorageQualifier::Const) => (),
+        Some(storage) => return unsupported(format!("{} parameters", storage_name(storage))),
+      }
+
+      args.push(arg);
+    }
+
+    let (scope, erased_ret) = FunReader::new(self, &args, ret).fun_body(&def.statement)?;
+    let arg_tys: Vec<_> = args.into_iter().map(|(_, ty)| ty).collect();
+
+    if name == "main" {
+      if !arg_tys.is_empty() || erased_ret != ErasedReturn::Void {
+        return unsupported("main functions with arguments or returning a value");
+      }
+
+      self
+        .builder
+        .decls
+        .push(ShaderDecl::Main(ErasedFun::new(arg_tys, scope, erased_ret)));
+      self.has_main = true;
+    } else {
+      let handle = self.builder.next_fun_handle;
+      self.builder.next_fun_handle += 1;
+
+      self.builder.decls.push(ShaderDecl::FunDef(
+        handle,
+        ErasedFun::new(arg_tys.clone(), scope, erased_ret),
+      ));
+      self
+        .funs
+        .entry(name.to_owned())
+        .or_default()
+        .push((handle, arg_tys));
+    }
+
+    Ok(())
+  }
+}
+
+/// Reader of the body of a function — or of a constant, which has none.
+struct FunReader<'a> {
+  stage: StageKind,
+  subgroups: bool,
+  globals: &'a HashMap<String, ScopedHandle>,
+  funs: &'a HashMap<String, Vec<(u16, Vec<Type>)>>,
+  env: TypeEnv,
+  args: HashMap<String, ScopedHandle>,
+  // scopes being read, innermost last, along with the variables they declare by name
+  frames: Vec<(ErasedScope, HashMap<String, ScopedHandle>)>,
+  next_scope_id: u16,
+  // whether conditional expressions can be hoisted out of the expression being read, in front of its statement
+  hoist: bool,
+  ret: Option<Type>,
+}
+
+impl<'a> FunReader<'a> {
+  fn new<S>(reader: &'a Reader<S>, args: &[(Option<&str>, Type)], ret: Option<Type>) -> Self {
+    let mut env = TypeEnv::new(&reader.builder.decls);
+    let mut arg_names = HashMap::new();
+
+    for (i, (name, ty)) in args.iter().enumerate() {
+      let handle = ScopedHandle::fun_arg(i as u16);
+      env.insert(handle.clone(), ty.clone());
+
+      if let Some(name) = name {
+        arg_names.insert((*name).to_owned(), handle);
+      }
+    }
+
+    Self {
+      stage: reader.stage,
+      subgroups: reader.builder.capabilities.contains(&Capability::Subgroup),
+      globals: &reader.globals,
+      funs: &reader.funs,
+      env,
+      args: arg_names,
+      frames: Vec::new(),
+      next_scope_id: 0,
+      hoist: false,
+      ret,
+    }
+  }
+
+  /// Read the body of the function, which must end with a `return` statement if the function returns something.
+  fn fun_body(
+    mut self,
+    body: &syntax::CompoundStatement,
+  ) -> Result<(ErasedScope, ErasedReturn), ReadError> {
+    let stmts = body.statement_list.as_slice();
+    let (stmts, ret_expr) = match (&self.ret, stmts.split_last()) {
+      (None, _) => (stmts, None),
+
+      (Some(_), Some((syntax::Statement::Simple(last), init))) => match **last {
+        syntax::SimpleStatement::Jump(syntax::JumpStatement::Return(Some(ref expr))) => {
+          (init, Some(expr))
+        }
+        _ => return unsupported("functions returning a value not ending with a return statement"),
+      },
+
+      (Some(_), _) => {
+        return unsupported("functions returning a value not ending with a return statement")
+      }
+    };
+
+    self.scope(|r| {
+      for stmt in stmts {
+        r.stmt(stmt)?;
+      }
+
+      match (r.ret.clone(), ret_expr) {
+        (Some(ty), Some(expr)) => Ok(ErasedReturn::Expr(ty, r.hoisted(|r| r.expr(expr))?)),
+        _ => Ok(ErasedReturn::Void),
+      }
+    })
+  }
+
+  /// Read a new scope with `f`, returning the scope along with what `f` returns.
+  fn scope<T>(
+    &mut self,
+    f: impl FnOnce(&mut Self) -> Result<T, ReadError>,
+  ) -> Result<(ErasedScope, T), ReadError> {
+    let id = self.next_scope_id;
+    self.next_scope_id += 1;
+    self.frames.push((ErasedScope::new(id), HashMap::new()));
+
+    let result = f(self);
+
+    match self.frames.pop() {
+      Some((scope, _)) => result.map(|t| (scope, t)),
+      None => unreachable!("scopes are popped by the call that pushed them"),
+    }
+  }
+
+  /// Innermost scope being read.
+  fn frame(&mut self) -> &mut (ErasedScope, HashMap<String, ScopedHandle>) {
+    match self.frames.last_mut() {
+      Some(frame) => frame,
+      None => unreachable!("statements are only read in scopes"),
+    }
+  }
+
+  /// Add an instruction at the end of the innermost scope.
+  fn push(&mut self, instr: ScopeInstr) {
+    self.env.declare(&instr);
+    self.frame().0.push_at(instr, None);
+  }
+
+  /// Handle of a new variable of the innermost scope.
+  fn new_var(&mut self) -> ScopedHandle {
+    let scope = &mut self.frame().0;
+    let handle = ScopedHandle::fun_var(scope.id, scope.next_var);
+    scope.next_var += 1;
+    handle
+  }
+
+  /// Make `name` refer to `handle` in the innermost scope.
+  fn bind(&mut self, name: &str, handle: ScopedHandle) {
+    self.frame().1.insert(name.to_owned(), handle);
+  }
+
+  /// Read with `f`, allowing conditional expressions to be hoisted in front of the statement being read.
+  fn hoisted<T>(
+    &mut self,
+    f: impl FnOnce(&mut Self) -> Result<T, ReadError>,
+  ) -> Result<T, ReadError> {
+    let hoist = mem::replace(&mut self.hoist, true);
+    let result = f(self);
+    self.hoist = hoist;
+    result
+  }
+
+  fn stmt(&mut self, stmt: &syntax::Statement) -> Result<(), ReadError> {
+    let stmt = match stmt {
+      syntax::Statement::Compound(_) => {
+        let scope = self.body(stmt)?;
+        self.push(ScopeInstr::Block { scope });
+        return Ok(());
+      }
+
+      syntax::Statement::Simple(stmt) => &**stmt,
+    };
+
+    match stmt {
+      syntax::SimpleStatement::Declaration(decl) => self.local_decl(decl),
+      syntax::SimpleStatement::Expression(None) => Ok(()),
+      syntax::SimpleStatement::Expression(Some(expr)) => self.expr_stmt(expr),
+      syntax::SimpleStatement::Selection(selection) => self.selection(selection),
+      syntax::SimpleStatement::Switch(switch) => self.switch(switch),
+      syntax::SimpleStatement::CaseLabel(_) => unsupported("case labels out of switch statements"),
+      syntax::SimpleStatement::Iteration(iteration) => self.iteration(iteration),
+      syntax::SimpleStatement::Jump(jump) => self.jump(jump),
+    }
+  }
+
+  /// Read the statements of `stmt` in the innermost scope: the statements of a compound statement, or `stmt` itself.
+  fn stmts_in(&mut self, stmt: &syntax::Statement) -> Result<(), ReadError> {
+    match stmt {
+      syntax::Statement::Compound(compound) => compound
+        .statement_list
+        .iter()
+        .try_for_each(|stmt| self.stmt(stmt)),
+      syntax::Statement::Simple(_) => self.stmt(stmt),
+    }
+  }
+
+  /// Read the body of a control-flow statement in a new scope.
+  fn body(&mut self, stmt: &syntax::Statement) -> Result<ErasedScope, ReadError> {
+    self.scope(|r| r.stmts_in(stmt)).map(|(scope, _)| scope)
+  }
+
+  fn local_decl(&mut self, decl: &syntax::Declaration) -> Result<(), ReadError> {
+    let list = match decl {
+      syntax::Declaration::InitDeclaratorList(list) => list,
+      syntax::Declaration::Precision(..) => return Ok(()),
+      _ => return unsupported("local declarations of anything but variables"),
+    };
+
+    match storage(list.head.ty.qualifier.as_ref())? {
+      // shades doesn’t distinguish constant variables
+      None | Some(syntax::StorageQualifier::Const) => (),
+      Some(storage) => {
+        return unsupported(format!("{} local variables", storage_name(storage)));
+      }
+    }
+
+    for (name, ty, init) in declarators(list)? {
+      let instr = match init {
+        Some(init) => {
+          let init_value = self.hoisted(|r| r.expr(init))?;
+          let handle = self.new_var();
+          ScopeInstr::VarDecl {
+            ty,
+            handle,
+            init_value,
+          }
+        }
+
+        None => {
+          let handle = self.new_var();
+          ScopeInstr::VarDeclUninit { ty, handle }
+        }
+      };
+
+      if let ScopeInstr::VarDecl { handle, .. } | ScopeInstr::VarDeclUninit { handle, .. } = &instr
+      {
+        self.bind(name, handle.clone());
+      }
+
+      self.push(instr);
+    }
+
+    Ok(())
+  }
+
+  fn expr_stmt(&mut self, expr: &syntax::Expr) -> Result<(), ReadError> {
+    match expr {
+      syntax::Expr::FunCall(..) => match self.hoisted(|r| r.expr(expr))? {
+        ErasedExpr::FunCall(fun, args) => {
+          let args = args.iter().map(|arg| (**arg).clone()).collect();
+          self.push(ScopeInstr::Call(fun, args));
+          Ok(())
+        }
+
+        _ => unsupported("expression statements without side effects"),
+      },
+
+      syntax::Expr::Comma(a, b) => {
+        self.expr_stmt(a)?;
+        self.expr_stmt(b)
+      }
+
+      _ => {
+        let (var, expr) = self.hoisted(|r| r.update(expr))?;
+        self.push(ScopeInstr::MutateVar { var, expr });
+        Ok(())
+      }
+    }
+  }
+
+  /// Read an assignment or an increment, returning the variable and its new value.
+  fn update(&mut self, expr: &syntax::Expr) -> Result<(ErasedExpr, ErasedExpr), ReadError> {
+    let (var, op, value) = match expr {
+      syntax::Expr::Assignment(var, op, value) => (var, op, value),
+
+      syntax::Expr::PostInc(var) | syntax::Expr::Unary(syntax::UnaryOp::Inc, var) => {
+        return self.increment(var, ErasedExpr::Add);
+      }
+
+      syntax::Expr::PostDec(var) | syntax::Expr::Unary(syntax::UnaryOp::Dec, var) => {
+        return self.increment(var, ErasedExpr::Sub);
+      }
+
+      _ => return unsupported("expression statements without side effects"),
+    };
+
+    let var = self.expr(var)?;
+    let value = self.expr(value)?;
+
+    let op = match op {
+      syntax::AssignmentOp::Equal => return Ok((var, value)),
+      syntax::AssignmentOp::Mult => ErasedExpr::Mul,
+      syntax::AssignmentOp::Div => ErasedExpr::Div,
+      syntax::AssignmentOp::Mod => ErasedExpr::Rem,
+      syntax::AssignmentOp::Add => ErasedExpr::Add,
+      syntax::AssignmentOp::Sub => ErasedExpr::Sub,
+      syntax::AssignmentOp::LShift => ErasedExpr::Shl,
+      syntax::AssignmentOp::RShift => ErasedExpr::Shr,
+      syntax::AssignmentOp::And => ErasedExpr::BitAnd,
+      syntax::AssignmentOp::Xor => ErasedExpr::BitXor,
+      syntax::AssignmentOp::Or => ErasedExpr::BitOr,
+    };
+
+    let expr = op(var.clone().intern(), value.intern());
+    Ok((var, expr))
+  }
+
+  fn increment(
+    &mut self,
+    var: &syntax::Expr,
+    op: fn(Arc<ErasedExpr>, Arc<ErasedExpr>) -> ErasedExpr,
+  ) -> Result<(ErasedExpr, ErasedExpr), ReadError> {
+    let var = self.expr(var)?;
+    let prim_ty = self
+      .env
+      .infer(&var)
+      .filter(|ty| ty.array_dims.is_empty())
+      .map(|ty| ty.prim_ty);
+
+    let one = match prim_ty {
+      Some(PrimType::Int(Dim::Scalar)) => ErasedExpr::LitInt(1),
+      Some(PrimType::UInt(Dim::Scalar)) => ErasedExpr::LitUInt(1),
+      Some(PrimType::Float(Dim::Scalar)) => ErasedExpr::LitFloat(1.),
+      _ => return unsupported("increments of anything but scalars"),
+    };
+
+    let expr = op(var.clone().intern(), one.intern());
+    Ok((var, expr))
+  }
+
+  fn selection(&mut self, selection: &syntax::SelectionStatement) -> Result<(), ReadError> {
+    let condition = self.hoisted(|r| r.expr(&selection.cond))?;
+    let (scope, mut rest) = self.selection_rest(&selection.rest)?;
+    self.push(ScopeInstr::If { condition, scope });
+
+    while let Some(stmt) = rest {
+      match stmt {
+        syntax::Statement::Simple(simple) => {
+          if let syntax::SimpleStatement::Selection(selection) = &**simple {
+            let condition = self.expr(&selection.cond)?;
+            let (scope, next) = self.selection_rest(&selection.rest)?;
+            self.push(ScopeInstr::ElseIf { condition, scope });
+            rest = next;
+            continue;
+          }
+        }
+
+        syntax::Statement::Compound(_) => (),
+      }
+
+      let scope = self.body(stmt)?;
+      self.push(ScopeInstr::Else { scope });
+      rest = None;
+    }
+
+    Ok(())
+  }
+
+  /// Read the statement run if the condition of an `if` holds, and return the `else` statement, if any.
+  fn selection_rest<'b>(
+    &mut self,
+    rest: &'b syntax::SelectionRestStatement,
+  ) -> Result<(ErasedScope, Option<&'b syntax::Statement>), ReadError> {
+    match rest {
+      syntax::SelectionRestStatement::Statement(stmt) => Ok((self.body(stmt)?, None)),
+      syntax::SelectionRestStatement::Else(stmt, rest) => Ok((self.body(stmt)?, Some(rest))),
+    }
+  }
+
+  fn switch(&mut self, switch: &syntax::SwitchStatement) -> Result<(), ReadError> {
+    let expr = self.hoisted(|r| r.expr(&switch.head))?;
+
+    // statements grouped by case label
+    let mut groups: Vec<(&syntax::CaseLabel, Vec<&syntax::Statement>)> = Vec::new();
+    for stmt in &switch.body {
+      if let syntax::Statement::Simple(simple) = stmt {
+        if let syntax::SimpleStatement::CaseLabel(label) = &**simple {
+          groups.push((label, Vec::new()));
+          continue;
+        }
+      }
+
+      match groups.last_mut() {
+        Some((_, stmts)) => stmts.push(stmt),
+        None => return unsupported("statements before the first case label"),
+      }
+    }
+
+    let mut cases = Vec::new();
+    for (label, stmts) in groups {
+      let label = match label {
+        syntax::CaseLabel::Case(label) => Some(self.expr(label)?),
+        syntax::CaseLabel::Def => None,
+      };
+
+      let (scope, _) = self.scope(|r| match stmts.as_slice() {
+        // the braces of `case x: { … }` delimit the scope of the case
+        [stmt @ syntax::Statement::Compound(_)] => r.stmts_in(stmt),
+        _ => stmts.iter().try_for_each(|stmt| r.stmt(stmt)),
+      })?;
+
+      cases.push((label, scope));
+    }
+
+    self.push(ScopeInstr::Switch { expr, cases });
+    Ok(())
+  }
+
+  fn iteration(&mut self, iteration: &syntax::IterationStatement) -> Result<(), ReadError> {
+    match iteration {
+      syntax::IterationStatement::While(condition, body) => {
+        let condition = self.condition(Some(condition))?;
+        let scope = self.body(body)?;
+        self.push(ScopeInstr::While { condition, scope });
+      }
+
+      syntax::IterationStatement::DoWhile(body, condition) => {
+        let scope = self.body(body)?;
+        let condition = self.expr(condition)?;
+        self.push(ScopeInstr::DoWhile { condition, scope });
+      }
+
+      syntax::IterationStatement::For(init, rest, body) => self.for_loop(init, rest, body)?,
+    }
+
+    Ok(())
+  }
+
+  /// Read the condition of a loop, which is `true` if absent.
+  fn condition(&mut self, condition: Option<&syntax::Condition>) -> Result<ErasedExpr, ReadError> {
+    match condition {
+      None => Ok(ErasedExpr::LitBool(true)),
+      Some(syntax::Condition::Expr(expr)) => self.expr(expr),
+      Some(syntax::Condition::Assignment(..)) => unsupported("declarations in loop conditions"),
+    }
+  }
+
+  fn for_loop(
+    &mut self,
+    init: &syntax::ForInitStatement,
+    rest: &syntax::ForRestStatement,
+    body: &syntax::Statement,
+  ) -> Result<(), ReadError> {
+    // loops declaring a single variable and updating it are read as the loops of Scope::loop_for
+    if let Some((init_ty, name, init_expr)) = for_init_var(init) {
+      if let Some(post) = rest
+        .post_expr
+        .as_deref()
+        .filter(|post| updated_var(post) == Some(name))
+      {
+        let init_expr = self.hoisted(|r| r.expr(init_expr))?;
+        let (scope, (init_handle, condition, post_expr)) = self.scope(|r| {
+          // the loop variable is the first variable of the body
+          let handle = r.new_var();
+          r.bind(name, handle.clone());
+          r.env.insert(handle.clone(), init_ty.clone());
+
+          let condition = r.condition(rest.condition.as_ref())?;
+          let (_, post_expr) = r.update(post)?;
+          r.stmts_in(body)?;
+
+          Ok((handle, condition, post_expr))
+        })?;
+
+        self.push(ScopeInstr::For {
+          init_ty,
+          init_handle,
+          init_expr,
+          condition,
+          post_expr,
+          scope,
+        });
+
+        return Ok(());
+      }
+    }
+
+    // other loops are read as a block running the initialization, followed by a loop updating any number of variables
+    match init {
+      syntax::ForInitStatement::Expression(None) => self.for_multi(rest, body),
+
+      _ => {
+        let (scope, _) = self.scope(|r| {
+          match init {
+            syntax::ForInitStatement::Expression(Some(expr)) => r.expr_stmt(expr)?,
+            syntax::ForInitStatement::Expression(None) => (),
+            syntax::ForInitStatement::Declaration(decl) => r.local_decl(decl)?,
+          }
+
+          r.for_multi(rest, body)
+        })?;
+
+        self.push(ScopeInstr::Block { scope });
+        Ok(())
+      }
+    }
+  }
+
+  fn for_multi(
+    &mut self,
+    rest: &syntax::ForRestStatement,
+    body: &syntax::Statement,
+  ) -> Result<(), ReadError> {
+    let condition = self.condition(rest.condition.as_ref())?;
+
+    let mut post_exprs = Vec::new();
+    if let Some(post) = &rest.post_expr {
+      self.updates(post, &mut post_exprs)?;
+    }
+
+    let scope = self.body(body)?;
+    self.push(ScopeInstr::ForMulti {
+      condition,
+      post_exprs,
+      scope,
+    });
+
+    Ok(())
+  }
+
+  /// Read the comma-separated updates of `expr`.
+  fn updates(
+    &mut self,
+    expr: &syntax::Expr,
+    updates: &mut Vec<(ErasedExpr, ErasedExpr)>,
+  ) -> Result<(), ReadError> {
+    match expr {
+      syntax::Expr::Comma(a, b) => {
+        self.updates(a, updates)?;
+        self.updates(b, updates)
+      }
+
+      _ => {
+        updates.push(self.update(expr)?);
+        Ok(())
+      }
+    }
+  }
+
+  fn jump(&mut self, jump: &syntax::JumpStatement) -> Result<(), ReadError> {
+    let instr = match jump {
+      syntax::JumpStatement::Continue => ScopeInstr::Continue,
+      syntax::JumpStatement::Break => ScopeInstr::Break,
+      syntax::JumpStatement::Return(None) => ScopeInstr::Return(ErasedReturn::Void),
+
+      syntax::JumpStatement::Return(Some(expr)) => {
+        let ty = match self.ret.clone() {
+          Some(ty) => ty,
+          None => return unsupported("returning a value from a void function"),
+        };
+
+        ScopeInstr::Return(ErasedReturn::Expr(ty, self.hoisted(|r| r.expr(expr))?))
+      }
+
+      syntax::JumpStatement::Discard => return unsupported("discard"),
+    };
+
+    self.push(instr);
+    Ok(())
+  }
+
+  fn expr(&mut self, expr: &syntax::Expr) -> Result<ErasedExpr, ReadError> {
+    let expr = match expr {
+      syntax::Expr::Variable(name) => ErasedExpr::Var(self.var(name.as_str())?),
+      syntax::Expr::IntConst(x) => ErasedExpr::LitInt(*x),
+      syntax::Expr::UIntConst(x) => ErasedExpr::LitUInt(*x),
+      syntax::Expr::BoolConst(x) => ErasedExpr::LitBool(*x),
+      syntax::Expr::FloatConst(x) => ErasedExpr::LitFloat(*x),
+      syntax::Expr::DoubleConst(_) => return unsupported("double-precision literals"),
+
+      syntax::Expr::Unary(op, a) => match (op, &**a) {
+        // negative literals
+        (syntax::UnaryOp::Minus, syntax::Expr::IntConst(x)) => ErasedExpr::LitInt(x.wrapping_neg()),
+        (syntax::UnaryOp::Minus, syntax::Expr::FloatConst(x)) => ErasedExpr::LitFloat(-x),
+        (syntax::UnaryOp::Minus, a) => ErasedExpr::Neg(self.expr(a)?.intern()),
+        (syntax::UnaryOp::Add, a) => self.expr(a)?,
+        (syntax::UnaryOp::Not, a) => ErasedExpr::Not(self.expr(a)?.intern()),
+        (syntax::UnaryOp::Complement, _) => return unsupported("bitwise complements"),
+        (syntax::UnaryOp::Inc, _) | (syntax::UnaryOp::Dec, _) => {
+          return unsupported("increments in expressions")
+        }
+      },
+
+      syntax::Expr::Binary(op, a, b) => {
+        let a = self.expr(a)?.intern();
+        let b = self.expr(b)?.intern();
+
+        match op {
+          syntax::BinaryOp::Or => ErasedExpr::Or(a, b),
+          syntax::BinaryOp::Xor => ErasedExpr::Xor(a, b),
+          syntax::BinaryOp::And => ErasedExpr::And(a, b),
+          syntax::BinaryOp::BitOr => ErasedExpr::BitOr(a, b),
+          syntax::BinaryOp::BitXor => ErasedExpr::BitXor(a, b),
+          syntax::BinaryOp::BitAnd => ErasedExpr::BitAnd(a, b),
+          syntax::BinaryOp::Equal => ErasedExpr::Eq(a, b),
+          syntax::BinaryOp::NonEqual => ErasedExpr::Neq(a, b),
+          syntax::BinaryOp::LT => ErasedExpr::Lt(a, b),
+          syntax::BinaryOp::GT => ErasedExpr::Gt(a, b),
+          syntax::BinaryOp::LTE => ErasedExpr::Lte(a, b),
+          syntax::BinaryOp::GTE => ErasedExpr::Gte(a, b),
+          syntax::BinaryOp::LShift => ErasedExpr::Shl(a, b),
+          syntax::BinaryOp::RShift => ErasedExpr::Shr(a, b),
+          syntax::BinaryOp::Add => ErasedExpr::Add(a, b),
+          syntax::BinaryOp::Sub => ErasedExpr::Sub(a, b),
+          syntax::BinaryOp::Mult => ErasedExpr::Mul(a, b),
+          syntax::BinaryOp::Div => ErasedExpr::Div(a, b),
+          syntax::BinaryOp::Mod => ErasedExpr::Rem(a, b),
+        }
+      }
+
+      syntax::Expr::Ternary(condition, a, b) => self.ternary(condition, a, b)?,
+
+      syntax::Expr::Assignment(..) => return unsupported("assignments in expressions"),
+
+      syntax::Expr::Bracket(object, spec) => {
+        let mut object = self.expr(object)?;
+
+        for dim in &spec.dimensions.0 {
+          let index = match dim {
+            syntax::ArraySpecifierDimension::ExplicitlySized(index) => self.expr(index)?,
+            syntax::ArraySpecifierDimension::Unsized => return unsupported("empty subscripts"),
+          };
+
+          object = ErasedExpr::ArrayLookup {
+            object: object.intern(),
+            index: index.intern(),
+          };
+        }
+
+        object
+      }
+
+      syntax::Expr::FunCall(syntax::FunIdentifier::Identifier(name), args) => {
+        self.call(name.as_str(), args)?
+      }
+
+      syntax::Expr::FunCall(syntax::FunIdentifier::Expr(fun), args) => match &**fun {
+        syntax::Expr::Dot(object, method) if method.as_str() == "length" && args.is_empty() => {
+          ErasedExpr::ArrayLength(self.expr(object)?.intern())
+        }
+
+        // array constructors, such as float[2](a, b)
+        syntax::Expr::Bracket(..) => {
+          let ty = constructor_ty(fun, args.len())?;
+          let items = args
+            .iter()
+            .map(|arg| self.expr(arg))
+            .collect::<Result<_, _>>()?;
+          ErasedExpr::array(ty, items)
+        }
+
+        _ => return unsupported("calls of expressions"),
+      },
+
+      syntax::Expr::Dot(object, field) => {
+        let object = self.expr(object)?.intern();
+
+        // members of the per-vertex built-ins, such as gl_in[i].gl_Position
+        if let Some(builtin) = self.builtin(field.as_str()) {
+          ErasedExpr::Field {
+            object,
+            field: ErasedExpr::new_builtin(builtin).intern(),
+          }
+        } else if let Some(swizzle) = swizzle(field.as_str()) {
+          ErasedExpr::Swizzle(object, swizzle)
+        } else {
+          return unsupported(format!("field {}", field.as_str()));
+        }
+      }
+
+      syntax::Expr::PostInc(_) | syntax::Expr::PostDec(_) => {
+        return unsupported("increments in expressions")
+      }
+
+      syntax::Expr::Comma(..) => return unsupported("comma expressions"),
+    };
+
+    Ok(expr)
+  }
+
+  /// Read `condition ? a : b` as a new variable, assigned `a` or `b` by an `if` statement hoisted in front of the
+  /// statement being read.
+  fn ternary(
+    &mut self,
+    condition: &syntax::Expr,
+    a: &syntax::Expr,
+    b: &syntax::Expr,
+  ) -> Result<ErasedExpr, ReadError> {
+    if !self.hoist {
+      return unsupported(
+        "conditional expressions in loop conditions, else if conditions and constants",
+      );
+    }
+
+    let condition = self.expr(condition)?;
+
+    // each branch is read in its own scope, so that only one of them is evaluated
+    let (mut then_scope, a) = self.scope(|r| r.expr(a))?;
+    let ty = match self.env.infer(&a) {
+      Some(ty) => ty,
+      None => return unsupported("conditional expressions of unknown type"),
+    };
+
+    let handle = self.new_var();
+    self.push(ScopeInstr::VarDeclUninit {
+      ty,
+      handle: handle.clone(),
+    });
+
+    let var = ErasedExpr::Var(handle);
+    then_scope.push_at(
+      ScopeInstr::MutateVar {
+        var: var.clone(),
+        expr: a,
+      },
+      None,
+    );
+
+    let (else_scope, _) = self.scope(|r| {
+      let b = r.expr(b)?;
+      r.push(ScopeInstr::MutateVar {
+        var: var.clone(),
+        expr: b,
+      });
+      Ok(())
+    })?;
+
+    self.push(ScopeInstr::If {
+      condition,
+      scope: then_scope,
+    });
+    self.push(ScopeInstr::Else { scope: else_scope });
+
+    Ok(var)
+  }
+
+  /// Resolve `name`, looking for local variables, arguments, globals and then built-ins.
+  fn var(&self, name: &str) -> Result<ScopedHandle, ReadError> {
+    self
+      .frames
+      .iter()
+      .rev()
+      .find_map(|(_, names)| names.get(name))
+      .or_else(|| self.args.get(name))
+      .or_else(|| self.globals.get(name))
+      .cloned()
+      .or_else(|| self.builtin(name).map(ScopedHandle::builtin))
+      .ok_or_else(|| ReadError::Undefined(name.to_owned()))
+  }
+
+  /// Built-in named `name` available in the stage being read.
+  fn builtin(&self, name: &str) -> Option<BuiltIn> {
+    builtin(self.stage, name).or_else(|| subgroup_builtin(name).filter(|_| self.subgroups))
+  }
+
+  fn call(&mut self, name: &str, args: &[syntax::Expr]) -> Result<ErasedExpr, ReadError> {
+    let args = args
+      .iter()
+      .map(|arg| self.expr(arg))
+      .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(prim_ty) = constructor_prim_ty(name) {
+      return constructor(name, prim_ty, args);
+    }
+
+    let funs = self.funs;
+    let fun = if let Some(overloads) = funs.get(name) {
+      ErasedFunHandle::UserDefined(self.overload(name, overloads, &args)?)
+    } else if let Some(fun) = fun_handle(name) {
+      fun
+    } else if let Some(fun) = subgroup_fun_handle(name).filter(|_| self.subgroups) {
+      fun
+    } else {
+      return Err(ReadError::Undefined(name.to_owned()));
+    };
+
+    Ok(ErasedExpr::fun_call(fun, args))
+  }
+
+  /// Pick the overload of a function called with `args`, by number of arguments first, and then by argument types.
+  fn overload(
+    &self,
+    name: &str,
+    overloads: &[(u16, Vec<Type>)],
+    args: &[ErasedExpr],
+  ) -> Result<u16, ReadError> {
+    let candidates: Vec<_> = overloads
+      .iter()
+      .filter(|(_, arg_tys)| arg_tys.len() == args.len())
+      .collect();
+
+    if let [(handle, _)] = candidates.as_slice() {
+      return Ok(*handle);
+    }
+
+    let tys: Vec<_> = args.iter().map(|arg| self.env.infer(arg)).collect();
+    candidates
+      .into_iter()
+      .find(|(_, arg_tys)| {
+        arg_tys
+          .iter()
+          .zip(&tys)
+          .all(|(arg_ty, ty)| ty.as_ref() == Some(arg_ty))
+      })
+      .map(|(handle, _)| *handle)
+      .ok_or_else(|| ReadError::Undefined(format!("overload of {} for these arguments", name)))
+  }
+}
+
+/// Name, type and initializer of a declared variable.
+type Declarator<'a> = (&'a str, Type, Option<&'a syntax::Expr>);
+
+/// Declarators of the variables declared by `list`.
+fn declarators(list: &syntax::InitDeclaratorList) -> Result<Vec<Declarator<'_>>, ReadError> {
+  let head = &list.head;
+  let name = match head.name {
+    Some(ref name) => name.as_str(),
+    None => return unsupported("declarations of types"),
+  };
+
+  let mut declarators = vec![(
+    name,
+    read_ty(&head.ty.ty, head.array_specifier.as_ref())?,
+    initializer(head.initializer.as_ref())?,
+  )];
+
+  for decl in &list.tail {
+    declarators.push((
+      decl.ident.ident.as_str(),
+      read_ty(&head.ty.ty, decl.ident.array_spec.as_ref())?,
+      initializer(decl.initializer.as_ref())?,
+    ));
+  }
+
+  Ok(declarators)
+}
+
+fn initializer(
+  initializer: Option<&syntax::Initializer>,
+) -> Result<Option<&syntax::Expr>, ReadError> {
+  match initializer {
+    None => Ok(None),
+    Some(syntax::Initializer::Simple(expr)) => Ok(Some(expr)),
+    Some(syntax::Initializer::List(_)) => unsupported("initializer lists"),
+  }
+}
+
+/// Type, name and initializer of the variable declared by the initialization of a `for` loop, if it declares a single
+/// initialized variable.
+fn for_init_var(init: &syntax::ForInitStatement) -> Option<(Type, &str, &syntax::Expr)> {
+  let list = match init {
+    syntax::ForInitStatement::Declaration(decl) => match **decl {
+      syntax::Declaration::InitDeclaratorList(ref list) if list.tail.is_empty() => list,
+      _ => return None,
+    },
+    _ => return None,
+  };
+
+  if storage(list.head.ty.qualifier.as_ref()).ok()?.is_some() {
+    return None;
+  }
+
+  match declarators(list).ok()?.pop()? {
+    (name, ty, Some(init)) => Some((ty, name, init)),
+    _ => None,
+  }
+}
+
+/// Name of the variable assigned or incremented by `expr`, if any.
+fn updated_var(expr: &syntax::Expr) -> Option<&str> {
+  match expr {
+    syntax::Expr::Assignment(var, ..)
+    | syntax::Expr::PostInc(var)
+    | syntax::Expr::PostDec(var)
+    | syntax::Expr::Unary(syntax::UnaryOp::Inc, var)
+    | syntax::Expr::Unary(syntax::UnaryOp::Dec, var) => match **var {
+      syntax::Expr::Variable(ref name) => Some(name.as_str()),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+/// Storage qualifier of a declaration, if any.
+///
+/// Other qualifiers are dropped, as well as the `centroid` and `sample` auxiliary storage qualifiers, which are about
+/// interpolation.
+fn storage(
+  qualifier: Option<&syntax::TypeQualifier>,
+) -> Result<Option<&syntax::StorageQualifier>, ReadError> {
+  let mut storages = qualifier
+    .into_iter()
+    .flat_map(|qualifier| &qualifier.qualifiers.0)
+    .filter_map(|spec| match spec {
+      syntax::TypeQualifierSpec::Storage(syntax::StorageQualifier::Centroid)
+      | syntax::TypeQualifierSpec::Storage(syntax::StorageQualifier::Sample) => None,
+      syntax::TypeQualifierSpec::Storage(storage) => Some(storage),
+      _ => None,
+    });
+
+  match (storages.next(), storages.next()) {
+    (storage, None) => Ok(storage),
+    _ => unsupported("several storage qualifiers"),
+  }
+}
+
+fn storage_name(storage: &syntax::StorageQualifier) -> String {
+  match storage {
+    syntax::StorageQualifier::Subroutine(_) => "subroutine".to_owned(),
+    _ => format!("{:?}", storage).to_lowercase(),
+  }
+}
+
+/// Type of a declaration, made of its type specifier and of the array specifier following its name, if any.
+fn read_ty(
+  ty: &syntax::TypeSpecifier,
+  name_array_spec: Option<&syntax::ArraySpecifier>,
+) -> Result<Type, ReadError> {
+  use syntax::TypeSpecifierNonArray as T;
+
+  let prim_ty = match ty.ty {
+    T::Bool => PrimType::Bool(Dim::Scalar),
+    T::BVec2 => PrimType::Bool(Dim::D2),
+    T::BVec3 => PrimType::Bool(Dim::D3),
+    T::BVec4 => PrimType::Bool(Dim::D4),
+    T::Int => PrimType::Int(Dim::Scalar),
+    T::IVec2 => PrimType::Int(Dim::D2),
+    T::IVec3 => PrimType::Int(Dim::D3),
+    T::IVec4 => PrimType::Int(Dim::D4),
+    T::UInt => PrimType::UInt(Dim::Scalar),
+    T::UVec2 => PrimType::UInt(Dim::D2),
+    T::UVec3 => PrimType::UInt(Dim::D3),
+    T::UVec4 => PrimType::UInt(Dim::D4),
+    T::Float => PrimType::Float(Dim::Scalar),
+    T::Vec2 => PrimType::Float(Dim::D2),
+    T::Vec3 => PrimType::Float(Dim::D3),
+    T::Vec4 => PrimType::Float(Dim::D4),
+    T::Mat2 => PrimType::Matrix(MatrixDim::D22),
+    T::Mat23 => PrimType::Matrix(MatrixDim::D23),
+    T::Mat24 => PrimType::Matrix(MatrixDim::D24),
+    T::Mat32 => PrimType::Matrix(MatrixDim::D32),
+    T::Mat3 => PrimType::Matrix(MatrixDim::D33),
+    T::Mat34 => PrimType::Matrix(MatrixDim::D34),
+    T::Mat42 => PrimType::Matrix(MatrixDim::D42),
+    T::Mat43 => PrimType::Matrix(MatrixDim::D43),
+    T::Mat4 => PrimType::Matrix(MatrixDim::D44),
+
+    T::Void => return unsupported("void variables"),
+
+    T::Double
+    | T::DVec2
+    | T::DVec3
+    | T::DVec4
+    | T::DMat2
+    | T::DMat3
+    | T::DMat4
+    | T::DMat23
+    | T::DMat24
+    | T::DMat32
+    | T::DMat34
+    | T::DMat42
+    | T::DMat43 => return unsupported("double-precision types"),
+
+    T::Struct(_) | T::TypeName(_) => return unsupported("structs"),
+
+    _ => return unsupported("opaque types"),
+  };
+
+  // float[2] x[3] is an array of three float[2]
+  let mut array_dims = Vec::new();
+  for spec in name_array_spec
+    .into_iter()
+    .chain(ty.array_specifier.as_ref())
+  {
+    for dim in &spec.dimensions.0 {
+      match dim {
+        syntax::ArraySpecifierDimension::ExplicitlySized(size) => {
+          array_dims.push(array_size(size)?)
+        }
+        syntax::ArraySpecifierDimension::Unsized => return unsupported("unsized arrays"),
+      }
+    }
+  }
+
+  Ok(Type {
+    prim_ty,
+    array_dims,
+  })
+}
+
+fn array_size(size: &syntax::Expr) -> Result<usize, ReadError> {
+  match *size {
+    syntax::Expr::IntConst(size) if size > 0 => Ok(size as usize),
+    syntax::Expr::UIntConst(size) if size > 0 => Ok(size as usize),
+    _ => unsupported("array sizes other than integral literals"),
+  }
+}
+
+/// Type of an array constructor, such as `float[2]` in `float[2](a, b)`. Unsized dimensions have `len` elements.
+fn constructor_ty(ty: &syntax::Expr, len: usize) -> Result<Type, ReadError> {
+  match ty {
+    syntax::Expr::Variable(name) => match constructor_prim_ty(name.as_str()) {
+      Some(prim_ty) => Ok(Type {
+        prim_ty,
+        array_dims: Vec::new(),
+      }),
+      None => unsupported(format!("arrays of {}", name.as_str())),
+    },
+
+    syntax::Expr::Bracket(ty, spec) => {
+      let mut ty = constructor_ty(ty, len)?;
+
+      for dim in &spec.dimensions.0 {
+        ty.array_dims.push(match dim {
+          syntax::ArraySpecifierDimension::ExplicitlySized(size) => array_size(size)?,
+          syntax::ArraySpecifierDimension::Unsized => len,
+        });
+      }
+
+      Ok(ty)
+    }
+
+    _ => unsupported("calls of expressions"),
+  }
+}
+
+/// Primitive type built by the constructor named `name`, if any.
+fn constructor_prim_ty(name: &str) -> Option<PrimType> {
+  let prim_ty = match name {
+    "bool" => PrimType::Bool(Dim::Scalar),
+    "bvec2" => PrimType::Bool(Dim::D2),
+    "bvec3" => PrimType::Bool(Dim::D3),
+    "bvec4" => PrimType::Bool(Dim::D4),
+    "int" => PrimType::Int(Dim::Scalar),
+    "ivec2" => PrimType::Int(Dim::D2),
+    "ivec3" => PrimType::Int(Dim::D3),
+    "ivec4" => PrimType::Int(Dim::D4),
+    "uint" => PrimType::UInt(Dim::Scalar),
+    "uvec2" => PrimType::UInt(Dim::D2),
+    "uvec3" => PrimType::UInt(Dim::D3),
+    "uvec4" => PrimType::UInt(Dim::D4),
+    "float" => PrimType::Float(Dim::Scalar),
+    "vec2" => PrimType::Float(Dim::D2),
+    "vec3" => PrimType::Float(Dim::D3),
+    "vec4" => PrimType::Float(Dim::D4),
+    "mat2" | "mat2x2" => PrimType::Matrix(MatrixDim::D22),
+    "mat2x3" => PrimType::Matrix(MatrixDim::D23),
+    "mat2x4" => PrimType::Matrix(MatrixDim::D24),
+    "mat3x2" => PrimType::Matrix(MatrixDim::D32),
+    "mat3" | "mat3x3" => PrimType::Matrix(MatrixDim::D33),
+    "mat3x4" => PrimType::Matrix(MatrixDim::D34),
+    "mat4x2" => PrimType::Matrix(MatrixDim::D42),
+    "mat4x3" => PrimType::Matrix(MatrixDim::D43),
+    "mat4" | "mat4x4" => PrimType::Matrix(MatrixDim::D44),
+    _ => return None,
+  };
+
+  Some(prim_ty)
+}
+
+/// Call of the constructor of `prim_ty`, named `name`, with `args`.
+///
+/// Constructors of literals are folded into literals, so that they are written as they are read.
+fn constructor(
+  name: &str,
+  prim_ty: PrimType,
+  mut args: Vec<ErasedExpr>,
+) -> Result<ErasedExpr, ReadError> {
+  let components = args
+    .iter()
+    .map(lit_components)
+    .collect::<Option<Vec<_>>>()
+    .map(|components| components.concat());
+
+  if let Some(lit) = components.and_then(|components| lit(&prim_ty, &components)) {
+    return Ok(lit);
+  }
+
+  let ty = Type {
+    prim_ty,
+    array_dims: Vec::new(),
+  };
+
+  if args.len() == 1 {
+    return Ok(ErasedExpr::Cast(ty, args.remove(0).intern()));
+  }
+
+  let fun = match ty.prim_ty {
+    PrimType::Float(Dim::D2) => ErasedFunHandle::Vec2,
+    PrimType::Float(Dim::D3) => ErasedFunHandle::Vec3,
+    PrimType::Float(Dim::D4) => ErasedFunHandle::Vec4,
+    _ => {
+      return unsupported(format!(
+        "{} constructors of several non-literal arguments",
+        name
+      ))
+    }
+  };
+
+  Ok(ErasedExpr::fun_call(fun, args))
+}
+
+/// Components of a scalar or vector literal.
+fn lit_components(expr: &ErasedExpr) -> Option<Vec<f64>> {
+  fn from_bool(x: &bool) -> f64 {
+    f64::from(u8::from(*x))
+  }
+
+  let components = match expr {
+    ErasedExpr::LitInt(x) => vec![f64::from(*x)],
+    ErasedExpr::LitUInt(x) => vec![f64::from(*x)],
+    ErasedExpr::LitFloat(x) => vec![f64::from(*x)],
+    ErasedExpr::LitBool(x) => vec![from_bool(x)],
+    ErasedExpr::LitInt2(v) => v.iter().copied().map(f64::from).collect(),
+    ErasedExpr::LitUInt2(v) => v.iter().copied().map(f64::from).collect(),
+    ErasedExpr::LitFloat2(v) => v.iter().copied().map(f64::from).collect(),
+    ErasedExpr::LitBool2(v) => v.iter().map(from_bool).collect(),
+    ErasedExpr::LitInt3(v) => v.iter().copied().map(f64::from).collect(),
+    ErasedExpr::LitUInt3(v) => v.iter().copied().map(f64::from).collect(),
+    ErasedExpr::LitFloat3(v) => v.iter().copied().map(f64::from).collect(),
+    ErasedExpr::LitBool3(v) => v.iter().map(from_bool).collect(),
+    ErasedExpr::LitInt4(v) => v.iter().copied().map(f64::from).collect(),
+    ErasedExpr::LitUInt4(v) => v.iter().copied().map(f64::from).collect(),
+    ErasedExpr::LitFloat4(v) => v.iter().copied().map(f64::from).collect(),
+    ErasedExpr::LitBool4(v) => v.iter().map(from_bool).collect(),
+    _ => return None,
+  };
+
+  Some(components)
+}
+
+/// Literal of type `prim_ty` made of `components`, converted to the type of the literal, if their number matches.
+fn lit(prim_ty: &PrimType, components: &[f64]) -> Option<ErasedExpr> {
+  let int = |x: f64| x as i32;
+  let uint = |x: f64| x as u32;
+  let float = |x: f64| x as f32;
+  let bool = |x: f64| x != 0.;
+
+  let lit = match prim_ty {
+    PrimType::Int(Dim::Scalar) => ErasedExpr::LitInt(vector::<_, 1>(components, int)?[0]),
+    PrimType::Int(Dim::D2) => ErasedExpr::LitInt2(vector(components, int)?),
+    PrimType::Int(Dim::D3) => ErasedExpr::LitInt3(vector(components, int)?),
+    PrimType::Int(Dim::D4) => ErasedExpr::LitInt4(vector(components, int)?),
+    PrimType::UInt(Dim::Scalar) => ErasedExpr::LitUInt(vector::<_, 1>(components, uint)?[0]),
+    PrimType::UInt(Dim::D2) => ErasedExpr::LitUInt2(vector(components, uint)?),
+    PrimType::UInt(Dim::D3) => ErasedExpr::LitUInt3(vector(components, uint)?),
+    PrimType::UInt(Dim::D4) => ErasedExpr::LitUInt4(vector(components, uint)?),
+    PrimType::Float(Dim::Scalar) => ErasedExpr::LitFloat(vector::<_, 1>(components, float)?[0]),
+    PrimType::Float(Dim::D2) => ErasedExpr::LitFloat2(vector(components, float)?),
+    PrimType::Float(Dim::D3) => ErasedExpr::LitFloat3(vector(components, float)?),
+    PrimType::Float(Dim::D4) => ErasedExpr::LitFloat4(vector(components, float)?),
+    PrimType::Bool(Dim::Scalar) => ErasedExpr::LitBool(vector::<_, 1>(components, bool)?[0]),
+    PrimType::Bool(Dim::D2) => ErasedExpr::LitBool2(vector(components, bool)?),
+    PrimType::Bool(Dim::D3) => ErasedExpr::LitBool3(vector(components, bool)?),
+    PrimType::Bool(Dim::D4) => ErasedExpr::LitBool4(vector(components, bool)?),
+
+    PrimType::Matrix(MatrixDim::D22) => ErasedExpr::LitM22(matrix(components)?),
+    PrimType::Matrix(MatrixDim::D23) => ErasedExpr::LitM23(matrix(components)?),
+    PrimType::Matrix(MatrixDim::D24) => ErasedExpr::LitM24(matrix(components)?),
+    PrimType::Matrix(MatrixDim::D32) => ErasedExpr::LitM32(matrix(components)?),
+    PrimType::Matrix(MatrixDim::D33) => ErasedExpr::LitM33(matrix(components)?),
+    PrimType::Matrix(MatrixDim::D34) => ErasedExpr::LitM34(matrix(components)?),
+    PrimType::Matrix(MatrixDim::D42) => ErasedExpr::LitM42(matrix(components)?),
+    PrimType::Matrix(MatrixDim::D43) => ErasedExpr::LitM43(matrix(components)?),
+    PrimType::Matrix(MatrixDim::D44) => ErasedExpr::LitM44(matrix(components)?),
+  };
+
+  Some(lit)
+}
+
+fn vector<T, const N: usize>(components: &[f64], f: impl Fn(f64) -> T) -> Option<[T; N]> {
+  let components: Vec<_> = components.iter().copied().map(f).collect();
+  components.try_into().ok()
+}
+
+/// Matrix made of `components`, given column after column.
+fn matrix<const M: usize, const N: usize>(components: &[f64]) -> Option<Matrix<[[f32; N]; M]>> {
+  if components.len() != M * N {
+    return None;
+  }
+
+  let mut columns = [[0.; N]; M];
+  for (i, x) in components.iter().enumerate() {
+    columns[i / N][i % N] = *x as f32;
+  }
+
+  Some(Matrix(columns))
+}
+
+fn swizzle(field: &str) -> Option<Swizzle> {
+  let selectors = field
+    .chars()
+    .map(|c| match c {
+      'x' | 'r' | 's' => Some(SwizzleSelector::X),
+      'y' | 'g' | 't' => Some(SwizzleSelector::Y),
+      'z' | 'b' | 'p' => Some(SwizzleSelector::Z),
+      'w' | 'a' | 'q' => Some(SwizzleSelector::W),
+      _ => None,
+    })
+    .collect::<Option<Vec<_>>>()?;
+
+  match *selectors.as_slice() {
+    [a] => Some(Swizzle::D1(a)),
+    [a, b] => Some(Swizzle::D2(a, b)),
+    [a, b, c] => Some(Swizzle::D3(a, b, c)),
+    [a, b, c, d] => Some(Swizzle::D4(a, b, c, d)),
+    _ => None,
+  }
+}
+
+/// Built-in of `stage` named `name`.
+///
+/// The names written by the GLSL writer are recognized along with the standard ones, when they differ.
+fn builtin(stage: StageKind, name: &str) -> Option<BuiltIn> {
+  let builtin = match stage {
+    StageKind::Vertex => BuiltIn::Vertex(match name {
+      "gl_VertexID" => VertexBuiltIn::VertexID,
+      "gl_InstanceID" => VertexBuiltIn::InstanceID,
+      "gl_BaseVertex" => VertexBuiltIn::BaseVertex,
+      "gl_BaseInstance" => VertexBuiltIn::BaseInstance,
+      "gl_Position" => VertexBuiltIn::Position,
+      "gl_PointSize" => VertexBuiltIn::PointSize,
+      "gl_ClipDistance" => VertexBuiltIn::ClipDistance,
+      _ => return None,
+    }),
+
+    StageKind::TessCtrl => BuiltIn::TessCtrl(match name {
+      "gl_MaxPatchVerticesIn" => TessCtrlBuiltIn::MaxPatchVerticesIn,
+      "gl_PatchVerticesIn" => TessCtrlBuiltIn::PatchVerticesIn,
+      "gl_PrimitiveID" => TessCtrlBuiltIn::PrimitiveID,
+      "gl_InvocationID" => TessCtrlBuiltIn::InvocationID,
+      "gl_TessLevelOuter" | "gl_TessellationLevelOuter" => TessCtrlBuiltIn::TessellationLevelOuter,
+      "gl_TessLevelInner" | "gl_TessellationLevelInner" => TessCtrlBuiltIn::TessellationLevelInner,
+      "gl_in" | "gl_In" => TessCtrlBuiltIn::In,
+      "gl_out" | "gl_Out" => TessCtrlBuiltIn::Out,
+      "gl_Position" => TessCtrlBuiltIn::Position,
+      "gl_PointSize" => TessCtrlBuiltIn::PointSize,
+      "gl_ClipDistance" => TessCtrlBuiltIn::ClipDistance,
+      "gl_CullDistance" => TessCtrlBuiltIn::CullDistance,
+      _ => return None,
+    }),
+
+    StageKind::TessEval => BuiltIn::TessEval(match name {
+      "gl_TessCoord" => TessEvalBuiltIn::TessCoord,
+      "gl_MaxPatchVerticesIn" => TessEvalBuiltIn::MaxPatchVerticesIn,
+      "gl_PatchVerticesIn" => TessEvalBuiltIn::PatchVerticesIn,
+      "gl_PrimitiveID" => TessEvalBuiltIn::PrimitiveID,
+      "gl_TessLevelOuter" | "gl_TessellationLevelOuter" => TessEvalBuiltIn::TessellationLevelOuter,
+      "gl_TessLevelInner" | "gl_TessellationLevelInner" => TessEvalBuiltIn::TessellationLevelInner,
+      "gl_in" | "gl_In" => TessEvalBuiltIn::In,
+      "gl_out" | "gl_Out" => TessEvalBuiltIn::Out,
+      "gl_Position" => TessEvalBuiltIn::Position,
+      "gl_PointSize" => TessEvalBuiltIn::PointSize,
+      "gl_ClipDistance" => TessEvalBuiltIn::ClipDistance,
+      "gl_CullDistance" => TessEvalBuiltIn::CullDistance,
+      _ => return None,
+    }),
+
+    StageKind::Geometry => BuiltIn::Geometry(match name {
+      "gl_in" | "gl_In" => GeometryBuiltIn::In,
+      "gl_out" | "gl_Out" => GeometryBuiltIn::Out,
+      "gl_Position" => GeometryBuiltIn::Position,
+      "gl_PointSize" => GeometryBuiltIn::PointSize,
+      "gl_ClipDistance" => GeometryBuiltIn::ClipDistance,
+      "gl_CullDistance" => GeometryBuiltIn::CullDistance,
+      "gl_PrimitiveID" => GeometryBuiltIn::PrimitiveID,
+      "gl_PrimitiveIDIn" => GeometryBuiltIn::PrimitiveIDIn,
+      "gl_InvocationID" => GeometryBuiltIn::InvocationID,
+      "gl_Layer" => GeometryBuiltIn::Layer,
+      "gl_ViewportIndex" => GeometryBuiltIn::ViewportIndex,
+      _ => return None,
+    }),
+
+    StageKind::Fragment => BuiltIn::Fragment(match name {
+      "gl_FragCoord" => FragmentBuiltIn::FragCoord,
+      "gl_FrontFacing" => FragmentBuiltIn::FrontFacing,
+      "gl_PointCoord" => FragmentBuiltIn::PointCoord,
+      "gl_SampleID" => FragmentBuiltIn::SampleID,
+      "gl_SamplePosition" => FragmentBuiltIn::SamplePosition,
+      "gl_SampleMaskIn" => FragmentBuiltIn::SampleMaskIn,
+      "gl_ClipDistance" => FragmentBuiltIn::ClipDistance,
+      "gl_CullDistance" => FragmentBuiltIn::CullDistance,
+      "gl_PrimitiveID" => FragmentBuiltIn::PrimitiveID,
+      "gl_Layer" => FragmentBuiltIn::Layer,
+      "gl_ViewportIndex" => FragmentBuiltIn::ViewportIndex,
+      "gl_FragDepth" => FragmentBuiltIn::FragDepth,
+      "gl_SampleMask" => FragmentBuiltIn::SampleMask,
+      "gl_HelperInvocation" => FragmentBuiltIn::HelperInvocation,
+      _ => return None,
+    }),
+
+    StageKind::Compute => BuiltIn::Compute(match name {
+      "gl_NumWorkGroups" => ComputeBuiltIn::NumWorkGroups,
+      "gl_WorkGroupID" => ComputeBuiltIn::WorkGroupID,
+      "gl_LocalInvocationID" => ComputeBuiltIn::LocalInvocationID,
+      "gl_GlobalInvocationID" => ComputeBuiltIn::GlobalInvocationID,
+      "gl_LocalInvocationIndex" => ComputeBuiltIn::LocalInvocationIndex,
+      _ => return None,
+    }),
+  };
+
+  Some(builtin)
+}
+
+fn subgroup_builtin(name: &str) -> Option<BuiltIn> {
+  let builtin = match name {
+    "gl_SubgroupSize" => SubgroupBuiltIn::SubgroupSize,
+    "gl_SubgroupInvocationID" => SubgroupBuiltIn::SubgroupInvocationID,
+    _ => return None,
+  };
+
+  Some(BuiltIn::Subgroup(builtin))
+}
+
+/// Built-in function named `name`, if any.
+///
+/// As with built-ins, the names written by the GLSL writer are recognized along with the standard ones.
+fn fun_handle(name: &str) -> Option<ErasedFunHandle> {
+  let fun = match name {
+    "radians" => ErasedFunHandle::Radians,
+    "degrees" => ErasedFunHandle::Degrees,
+    "sin" => ErasedFunHandle::Sin,
+    "cos" => ErasedFunHandle::Cos,
+    "tan" => ErasedFunHandle::Tan,
+    "asin" => ErasedFunHandle::ASin,
+    "acos" => ErasedFunHandle::ACos,
+    "atan" => ErasedFunHandle::ATan,
+    "sinh" => ErasedFunHandle::SinH,
+    "cosh" => ErasedFunHandle::CosH,
+    "tanh" => ErasedFunHandle::TanH,
+    "asinh" => ErasedFunHandle::ASinH,
+    "acosh" => ErasedFunHandle::ACosH,
+    "atanh" => ErasedFunHandle::ATanH,
+    "pow" => ErasedFunHandle::Pow,
+    "exp" => ErasedFunHandle::Exp,
+    "exp2" => ErasedFunHandle::Exp2,
+    "log" => ErasedFunHandle::Log,
+    "log2" => ErasedFunHandle::Log2,
+    "sqrt" => ErasedFunHandle::Sqrt,
+    "inversesqrt" => ErasedFunHandle::InverseSqrt,
+    "abs" => ErasedFunHandle::Abs,
+    "sign" => ErasedFunHandle::Sign,
+    "floor" => ErasedFunHandle::Floor,
+    "trunc" => ErasedFunHandle::Trunc,
+    "round" => ErasedFunHandle::Round,
+    "roundEven" => ErasedFunHandle::RoundEven,
+    "ceil" => ErasedFunHandle::Ceil,
+    "fract" => ErasedFunHandle::Fract,
+    "mod" => ErasedFunHandle::Mod,
+    "modf" => ErasedFunHandle::Modf,
+    "min" => ErasedFunHandle::Min,
+    "max" => ErasedFunHandle::Max,
+    "clamp" => ErasedFunHandle::Clamp,
+    "mix" => ErasedFunHandle::Mix,
+    "step" => ErasedFunHandle::Step,
+    "smoothstep" => ErasedFunHandle::SmoothStep,
+    "isnan" => ErasedFunHandle::IsNan,
+    "isinf" => ErasedFunHandle::IsInf,
+    "floatBitsToInt" => ErasedFunHandle::FloatBitsToInt,
+    "intBitsToFloat" => ErasedFunHandle::IntBitsToFloat,
+    "uintBitsToFloat" | "uIntBitsToFloat" => ErasedFunHandle::UIntBitsToFloat,
+    "fma" => ErasedFunHandle::Fma,
+    "frexp" => ErasedFunHandle::Frexp,
+    "ldexp" => ErasedFunHandle::Ldexp,
+    "packUnorm2x16" => ErasedFunHandle::PackUnorm2x16,
+    "packSnorm2x16" => ErasedFunHandle::PackSnorm2x16,
+    "packUnorm4x8" => ErasedFunHandle::PackUnorm4x8,
+    "packSnorm4x8" => ErasedFunHandle::PackSnorm4x8,
+    "unpackUnorm2x16" => ErasedFunHandle::UnpackUnorm2x16,
+    "unpackSnorm2x16" => ErasedFunHandle::UnpackSnorm2x16,
+    "unpackUnorm4x8" => ErasedFunHandle::UnpackUnorm4x8,
+    "unpackSnorm4x8" => ErasedFunHandle::UnpackSnorm4x8,
+    "packHalf2x16" => ErasedFunHandle::PackHalf2x16,
+    "unpackHalf2x16" => ErasedFunHandle::UnpackHalf2x16,
+    "length" => ErasedFunHandle::Length,
+    "distance" => ErasedFunHandle::Distance,
+    "dot" => ErasedFunHandle::Dot,
+    "cross" => ErasedFunHandle::Cross,
+    "normalize" => ErasedFunHandle::Normalize,
+    "faceforward" => ErasedFunHandle::FaceForward,
+    "reflect" => ErasedFunHandle::Reflect,
+    "refract" => ErasedFunHandle::Refract,
+    "lessThan" => ErasedFunHandle::VLt,
+    "lessThanEqual" => ErasedFunHandle::VLte,
+    "greaterThan" => ErasedFunHandle::VGt,
+    "greaterThanEqual" => ErasedFunHandle::VGte,
+    "equal" => ErasedFunHandle::VEq,
+    "notEqual" => ErasedFunHandle::VNeq,
+    "any" => ErasedFunHandle::VAny,
+    "all" => ErasedFunHandle::VAll,
+    "not" => ErasedFunHandle::VNot,
+    "uaddCarry" => ErasedFunHandle::UAddCarry,
+    "usubBorrow" => ErasedFunHandle::USubBorrow,
+    "umulExtended" => ErasedFunHandle::UMulExtended,
+    "imulExtended" => ErasedFunHandle::IMulExtended,
+    "bitfieldExtract" => ErasedFunHandle::BitfieldExtract,
+    "bitfieldInsert" => ErasedFunHandle::BitfieldInsert,
+    "bitfieldReverse" => ErasedFunHandle::BitfieldReverse,
+    "bitCount" => ErasedFunHandle::BitCount,
+    "findLSB" => ErasedFunHandle::FindLSB,
+    "findMSB" => ErasedFunHandle::FindMSB,
+    "EmitStreamVertex" => ErasedFunHandle::EmitStreamVertex,
+    "EndStreamPrimitive" => ErasedFunHandle::EndStreamPrimitive,
+    "EmitVertex" => ErasedFunHandle::EmitVertex,
+    "EndPrimitive" => ErasedFunHandle::EndPrimitive,
+    "dFdx" => ErasedFunHandle::DfDx,
+    "dFdy" => ErasedFunHandle::DfDy,
+    "dFdxFine" => ErasedFunHandle::DfDxFine,
+    "dFdyFine" => ErasedFunHandle::DfDyFine,
+    "dFdxCoarse" => ErasedFunHandle::DfDxCoarse,
+    "dFdyCoarse" => ErasedFunHandle::DfDyCoarse,
+    "fwidth" => ErasedFunHandle::FWidth,
+    "fwidthFine" => ErasedFunHandle::FWidthFine,
+    "fwidthCoarse" => ErasedFunHandle::FWidthCoarse,
+    "interpolateAtCentroid" => ErasedFunHandle::InterpolateAtCentroid,
+    "interpolateAtSample" => ErasedFunHandle::InterpolateAtSample,
+    "interpolateAtOffset" => ErasedFunHandle::InterpolateAtOffset,
+    "barrier" => ErasedFunHandle::Barrier,
+    "memoryBarrier" => ErasedFunHandle::MemoryBarrier,
+    "memoryBarrierAtomicCounter" => ErasedFunHandle::MemoryBarrierAtomic,
+    "memoryBarrierBuffer" => ErasedFunHandle::MemoryBarrierBuffer,
+    "memoryBarrierShared" => ErasedFunHandle::MemoryBarrierShared,
+    "memoryBarrierImage" => ErasedFunHandle::MemoryBarrierImage,
+    "groupMemoryBarrier" => ErasedFunHandle::GroupMemoryBarrier,
+    "anyInvocation" => ErasedFunHandle::AnyInvocation,
+    "allInvocations" => ErasedFunHandle::AllInvocations,
+    "allInvocationsEqual" => ErasedFunHandle::AllInvocationsEqual,
+    _ => return None,
+  };
+
+  Some(fun)
+}
+
+fn subgroup_fun_handle(name: &str) -> Option<ErasedFunHandle> {
+  let fun = match name {
+    "subgroupBarrier" => ErasedFunHandle::SubgroupBarrier,
+    "subgroupElect" => ErasedFunHandle::SubgroupElect,
+    "subgroupAll" => ErasedFunHandle::SubgroupAll,
+    "subgroupAny" => ErasedFunHandle::SubgroupAny,
+    "subgroupBallot" => ErasedFunHandle::SubgroupBallot,
+    "subgroupBroadcastFirst" => ErasedFunHandle::SubgroupBroadcastFirst,
+    "subgroupShuffle" => ErasedFunHandle::SubgroupShuffle,
+    "subgroupAdd" => ErasedFunHandle::SubgroupAdd,
+    "subgroupMul" => ErasedFunHandle::SubgroupMul,
+    "subgroupMin" => ErasedFunHandle::SubgroupMin,
+    "subgroupMax" => ErasedFunHandle::SubgroupMax,
+    _ => return None,
+  };
+
+  Some(fun)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    eval::{Eval, Value},
+    inputs, lit, outputs, stage, sw,
+    writer::glsl::write_shader_to_str,
+    CanEscape as _, Directive, Expr, LoopScope, Scope, Swizzlable as _, M22, V4,
+  };
+
+  #[test]
+  fn round_trip() {
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, n: i32);
+      outputs!(s, sum: i32, color: V4<f32>);
+
+      let square = s.fun(|_: &mut Scope<Expr<i32>>, a: Expr<i32>| &a * &a);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        s.loop_for(
+          0,
+          |i| i.lt(&n),
+          |i| i + 1,
+          |s: &mut LoopScope<()>, i| {
+            s.when(i.eq(2), |s| s.loop_continue());
+            s.set(&sum, &sum + square.call(i.clone()));
+          },
+        );
+
+        let m: Expr<M22> = Matrix::from([[0., 1.], [1., 0.]]).into();
+        let v = s.var(m * lit!(1., 2.));
+
+        s.switch_on(&n)
+          .case(3, |s| s.set(sw!(color, .x.y), &v))
+          .default(|s| s.set(sw!(color, .z.w), &v));
+      })
+    });
+
+    let code = write_shader_to_str(&shader).unwrap();
+    let read = read_shader::<stage::Fragment>(&code).unwrap();
+    assert_eq!(write_shader_to_str(&read).unwrap(), code);
+  }
+
+  #[test]
+  fn read_and_eval() {
+    let shader = read_shader::<stage::Fragment>(
+      "
+      #version 330 core
+      precision highp float;
+
+      in int n;
+      out int sum;
+      out float x;
+      const int[3] weights = int[](1, 2, 3);
+
+      int weight(int i) {
+        return weights[i % weights.length()];
+      }
+
+      float weight(float f) {
+        return f * 2.;
+      }
+
+      void main() {
+        sum = 0;
+        for (int i = 0, j = 1; i < n; i++, j *= 2) {
+          sum += i > 1 ? weight(i) * j : 0;
+        }
+
+        x = float(sum);
+        x = weight(x);
+      }
+      ",
+    )
+    .unwrap();
+
+    // i = 2, 3: 3 * 4 + 1 * 8
+    let outputs = Eval::new(&shader).input("n", 4).run().unwrap();
+    assert_eq!(outputs.get("sum"), Some(&Value::Int(vec![20])));
+    assert_eq!(outputs.get("x"), Some(&Value::Float(vec![40.])));
+  }
+
+  #[test]
+  fn directives() {
+    let shader = read_shader::<stage::Fragment>(
+      "
+      #extension GL_KHR_shader_subgroup_basic : require
+      #extension GL_EXT_debug_printf : enable
+      #pragma optimize(off)
+
+      void main() {
+      }
+      ",
+    )
+    .unwrap();
+
+    assert_eq!(shader.builder.capabilities, [Capability::Subgroup]);
+    assert_eq!(
+      shader.builder.extensions,
+      [("GL_EXT_debug_printf".to_owned(), Behavior::Enable)]
+    );
+    assert_eq!(
+      shader.builder.directives,
+      [Directive::Pragma("optimize(off)".to_owned())]
+    );
+  }
+
+  #[test]
+  fn errors() {
+    let error = |source| read_shader::<stage::Fragment>(source).err();
+
+    assert!(matches!(error("void main() {"), Some(ReadError::Parse(_))));
+    assert_eq!(
+      error("void main() { discard; }"),
+      Some(ReadError::Unsupported("discard".to_owned()))
+    );
+    assert_eq!(
+      error("void main() { int x = y; }"),
+      Some(ReadError::Undefined("y".to_owned()))
+    );
+    assert_eq!(error("void f() {}"), Some(ReadError::MissingMain));
+    assert_eq!(
+      error("uniform int n; void main() { while (n > 0 ? true : false) {} }"),
+      Some(ReadError::Unsupported(
+        "conditional expressions in loop conditions, else if conditions and constants".to_owned()
+      ))
+    );
+  }
+}
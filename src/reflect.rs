@@ -0,0 +1,191 @@
+//! Reflection over built shaders.
+//!
+//! Once a [`Shader`] is built, its interface can be inspected with [`Shader::inputs`], [`Shader::outputs`],
+//! [`Shader::uniforms`] and [`Shader::functions`]. This allows engine code to set up vertex formats and uniform uploads
+//! from the same source of truth as the shading code.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{PrimType, Dim, Scope, ShaderBuilder, V3, inputs, uniforms, vec4};
+//!
+//! let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+//!   inputs!(s, position: V3<f32>);
+//!   uniforms!(s, scale: f32);
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     s.set(vertex.position, vec4!(position * scale, 1.));
+//!   })
+//! });
+//!
+//! let inputs: Vec<_> = shader.inputs().collect();
+//! assert_eq!(inputs.len(), 1);
+//! assert_eq!(inputs[0].name(), "position");
+//! assert_eq!(inputs[0].ty().prim_ty(), &PrimType::Float(Dim::D3));
+//! ```
+//!
+//! [`Shader`]: crate::Shader
+//! [`Shader::inputs`]: crate::Shader::inputs
+//! [`Shader::outputs`]: crate::Shader::outputs
+//! [`Shader::uniforms`]: crate::Shader::uniforms
+//! [`Shader::functions`]: crate::Shader::functions
+
+use crate::{
+  attribute::{Semantic, VertexAttribute, VertexFormat},
+  ErasedReturn, ScopedHandle, ShaderDecl, Type,
+};
+
+/// Reflected input, output or uniform declaration.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct VarInfo<'a> {
+  name: &'a str,
+  ty: &'a Type,
+  handle: ScopedHandle,
+  index: usize,
+}
+
+impl<'a> VarInfo<'a> {
+  /// Name of the declaration, as used in the generated code.
+  pub fn name(&self) -> &'a str {
+    self.name
+  }
+
+  /// Type of the declaration.
+  pub fn ty(&self) -> &'a Type {
+    self.ty
+  }
+
+  /// Handle of the declaration, as used in expressions.
+  pub fn handle(&self) -> &ScopedHandle {
+    &self.handle
+  }
+
+  /// Index of the declaration among the declarations of the same kind (inputs, outputs or uniforms), in declaration
+  /// order.
+  pub fn index(&self) -> usize {
+    self.index
+  }
+}
+
+/// Reflected vertex attribute, declared with [`ShaderBuilder::attribute`](crate::ShaderBuilder::attribute).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct AttributeInfo<'a> {
+  var: VarInfo<'a>,
+  format: VertexFormat,
+  semantic: &'a Semantic,
+}
+
+impl<'a> AttributeInfo<'a> {
+  /// Input the attribute is read from; its [index](VarInfo::index) is its location with the
+  /// [`wgpu`](crate::wgpu) writer.
+  pub fn var(&self) -> &VarInfo<'a> {
+    &self.var
+  }
+
+  /// Format of the attribute in vertex buffers.
+  pub fn format(&self) -> VertexFormat {
+    self.format
+  }
+
+  /// Semantic of the attribute.
+  pub fn semantic(&self) -> &'a Semantic {
+    self.semantic
+  }
+}
+
+/// Reflected function definition.
+///
+/// The `main` function is not part of the reflected functions.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct FunInfo<'a> {
+  handle: u16,
+  args: &'a [Type],
+  ret: Option<&'a Type>,
+}
+
+impl<'a> FunInfo<'a> {
+  /// Handle of the function, unique in its shader.
+  pub fn handle(&self) -> u16 {
+    self.handle
+  }
+
+  /// Types of the arguments of the function.
+  pub fn args(&self) -> &'a [Type] {
+    self.args
+  }
+
+  /// Return type of the function, if it returns something.
+  pub fn ret(&self) -> Option<&'a Type> {
+    self.ret
+  }
+}
+
+pub(crate) fn inputs(decls: &[ShaderDecl]) -> impl Iterator<Item = VarInfo<'_>> {
+  vars(decls, |decl| match decl {
+    ShaderDecl::In(name, ty) | ShaderDecl::PatchIn(name, ty) => {
+      Some((name, ty, ScopedHandle::Input(name.clone())))
+    }
+    _ => None,
+  })
+}
+
+pub(crate) fn attributes<'a>(
+  decls: &'a [ShaderDecl],
+  attributes: &'a [VertexAttribute],
+) -> impl Iterator<Item = AttributeInfo<'a>> {
+  attributes.iter().filter_map(move |attribute| {
+    let var = inputs(decls).find(|var| var.name == attribute.name)?;
+
+    Some(AttributeInfo {
+      var,
+      format: attribute.format,
+      semantic: &attribute.semantic,
+    })
+  })
+}
+
+pub(crate) fn outputs(decls: &[ShaderDecl]) -> impl Iterator<Item = VarInfo<'_>> {
+  vars(decls, |decl| match decl {
+    ShaderDecl::Out(name, ty)
+    | ShaderDecl::PatchOut(name, ty)
+    | ShaderDecl::LayoutOut(name, ty, _) => Some((name, ty, ScopedHandle::Output(name.clone()))),
+    _ => None,
+  })
+}
+
+pub(crate) fn uniforms(decls: &[ShaderDecl]) -> impl Iterator<Item = VarInfo<'_>> {
+  vars(decls, |decl| match decl {
+    ShaderDecl::Uniform(name, ty) => Some((name, ty, ScopedHandle::uniform(name.clone()))),
+    _ => None,
+  })
+}
+
+pub(crate) fn functions(decls: &[ShaderDecl]) -> impl Iterator<Item = FunInfo<'_>> {
+  decls.iter().filter_map(|decl| match decl {
+    ShaderDecl::FunDef(handle, fun) => Some(FunInfo {
+      handle: *handle,
+      args: &fun.args,
+      ret: match fun.ret {
+        ErasedReturn::Void => None,
+        ErasedReturn::Expr(ref ty, _) => Some(ty),
+      },
+    }),
+    _ => None,
+  })
+}
+
+fn vars<'a>(
+  decls: &'a [ShaderDecl],
+  select: impl Fn(&'a ShaderDecl) -> Option<(&'a String, &'a Type, ScopedHandle)>,
+) -> impl Iterator<Item = VarInfo<'a>> {
+  decls
+    .iter()
+    .filter_map(select)
+    .enumerate()
+    .map(|(index, (name, ty, handle))| VarInfo {
+      name,
+      ty,
+      handle,
+      index,
+    })
+}
@@ -0,0 +1,101 @@
+//! Shader stages.
+//!
+//! Those types are used as type parameters of [`Shader`] and [`ShaderBuilder`] to statically track which stage is being
+//! built, so that stage-specific declarations can only be added to the right stage, and so that stages cannot be
+//! mixed up.
+//!
+//! [`Shader`]: crate::Shader
+//! [`ShaderBuilder`]: crate::ShaderBuilder
+
+use std::fmt;
+
+/// Vertex stage.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Vertex;
+
+/// Tessellation control stage.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct TessCtrl;
+
+/// Tessellation evaluation stage.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct TessEval;
+
+/// Geometry stage.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Geometry;
+
+/// Fragment stage.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Fragment;
+
+/// Compute stage.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Compute;
+
+/// Stage known at runtime.
+///
+/// This is the value-level counterpart of the stage types, which can be obtained from any of them via [`Stage::KIND`].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum StageKind {
+  /// Vertex stage.
+  Vertex,
+
+  /// Tessellation control stage.
+  TessCtrl,
+
+  /// Tessellation evaluation stage.
+  TessEval,
+
+  /// Geometry stage.
+  Geometry,
+
+  /// Fragment stage.
+  Fragment,
+
+  /// Compute stage.
+  Compute,
+}
+
+impl fmt::Display for StageKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      StageKind::Vertex => f.write_str("vertex"),
+      StageKind::TessCtrl => f.write_str("tessellation control"),
+      StageKind::TessEval => f.write_str("tessellation evaluation"),
+      StageKind::Geometry => f.write_str("geometry"),
+      StageKind::Fragment => f.write_str("fragment"),
+      StageKind::Compute => f.write_str("compute"),
+    }
+  }
+}
+
+/// Class of stage types.
+pub trait Stage {
+  /// Runtime representation of the stage.
+  const KIND: StageKind;
+}
+
+impl Stage for Vertex {
+  const KIND: StageKind = StageKind::Vertex;
+}
+
+impl Stage for TessCtrl {
+  const KIND: StageKind = StageKind::TessCtrl;
+}
+
+impl Stage for TessEval {
+  const KIND: StageKind = StageKind::TessEval;
+}
+
+impl Stage for Geometry {
+  const KIND: StageKind = StageKind::Geometry;
+}
+
+impl Stage for Fragment {
+  const KIND: StageKind = StageKind::Fragment;
+}
+
+impl Stage for Compute {
+  const KIND: StageKind = StageKind::Compute;
+}
@@ -0,0 +1,216 @@
+//! Statistics about built shaders.
+//!
+//! Procedural shader generators can easily produce more code than a driver is happy to compile. [`Shader::stats`]
+//! reports a few metrics about a shader, so that generators can enforce complexity budgets before writing it.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{LoopScope, Scope, ShaderBuilder, V3, inputs, vec4};
+//!
+//! let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+//!   inputs!(s, position: V3<f32>);
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     let scale = s.var(1.);
+//!
+//!     s.loop_for(0, |i| i.lt(4), |i| i + 1, |s: &mut LoopScope<()>, _| {
+//!       s.set(&scale, &scale * 2.);
+//!     });
+//!
+//!     s.set(vertex.position, vec4!(position * scale, 1.));
+//!   })
+//! });
+//!
+//! let stats = shader.stats();
+//! assert_eq!(stats.inputs(), 1);
+//! assert_eq!(stats.instructions(), 4);
+//! assert_eq!(stats.max_loop_depth(), 1);
+//! ```
+//!
+//! [`Shader::stats`]: crate::Shader::stats
+
+use crate::{writer::glsl, ErasedExpr, ErasedReturn, ErasedScope, Shader, ShaderDecl};
+use std::{collections::HashMap, fmt};
+
+/// Metrics about the complexity of a [`Shader`].
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ShaderStats {
+  functions: usize,
+  constants: usize,
+  inputs: usize,
+  outputs: usize,
+  uniforms: usize,
+  instructions: usize,
+  expr_nodes: usize,
+  max_loop_depth: usize,
+  output_size: usize,
+}
+
+impl ShaderStats {
+  pub(crate) fn new<S>(shader: &Shader<S>) -> Self {
+    let mut stats = Self::default();
+    let mut expr_nodes = ExprNodes::default();
+
+    for decl in &shader.builder.decls {
+      match decl {
+        ShaderDecl::Main(fun) | ShaderDecl::FunDef(_, fun) => {
+          if let ShaderDecl::FunDef(..) = decl {
+            stats.functions += 1;
+          }
+
+          stats.scope(&fun.scope, 0, &mut expr_nodes);
+
+          if let ErasedReturn::Expr(_, ref expr) = fun.ret {
+            stats.expr_nodes += expr_nodes.count(expr);
+          }
+        }
+
+        ShaderDecl::Const(_, _, expr) => {
+          stats.constants += 1;
+          stats.expr_nodes += expr_nodes.count(expr);
+        }
+
+        ShaderDecl::In(..) | ShaderDecl::PatchIn(..) => stats.inputs += 1,
+        ShaderDecl::Out(..) | ShaderDecl::PatchOut(..) | ShaderDecl::LayoutOut(..) => {
+          stats.outputs += 1
+        }
+        ShaderDecl::Uniform(..) => stats.uniforms += 1,
+        ShaderDecl::Shared(..) | ShaderDecl::Comment(_) => (),
+      }
+    }
+
+    let mut output_size = OutputSize(0);
+    // writing to an OutputSize never fails
+    let _ = glsl::write_shader(&mut output_size, shader);
+    stats.output_size = output_size.0;
+
+    stats
+  }
+
+  fn scope(&mut self, scope: &ErasedScope, loop_depth: usize, expr_nodes: &mut ExprNodes) {
+    for instr in &scope.instructions {
+      self.instructions += 1;
+
+      for expr in instr.exprs() {
+        self.expr_nodes += expr_nodes.count(expr);
+      }
+
+      let loop_depth = loop_depth + instr.is_loop() as usize;
+      self.max_loop_depth = self.max_loop_depth.max(loop_depth);
+
+      for scope in instr.scopes() {
+        self.scope(scope, loop_depth, expr_nodes);
+      }
+    }
+  }
+
+  /// Number of user-defined functions, excluding `main`.
+  pub fn functions(&self) -> usize {
+    self.functions
+  }
+
+  /// Number of constants.
+  pub fn constants(&self) -> usize {
+    self.constants
+  }
+
+  /// Number of inputs.
+  pub fn inputs(&self) -> usize {
+    self.inputs
+  }
+
+  /// Number of outputs.
+  pub fn outputs(&self) -> usize {
+    self.outputs
+  }
+
+  /// Number of uniforms.
+  pub fn uniforms(&self) -> usize {
+    self.uniforms
+  }
+
+  /// Number of instructions in all the functions, including `main` and the instructions of nested scopes.
+  pub fn instructions(&self) -> usize {
+    self.instructions
+  }
+
+  /// Number of expression nodes, as they appear in the generated code.
+  ///
+  /// An expression used several times is counted every time it is used, even though it is only stored once.
+  pub fn expr_nodes(&self) -> usize {
+    self.expr_nodes
+  }
+
+  /// Maximum nesting depth of loops; `0` if the shader doesn’t contain any loop.
+  pub fn max_loop_depth(&self) -> usize {
+    self.max_loop_depth
+  }
+
+  /// Size of the shader in bytes, once written with [`glsl::write_shader`].
+  pub fn output_size(&self) -> usize {
+    self.output_size
+  }
+}
+
+/// Number of nodes of expressions, memoized so that shared sub-expressions are only traversed once.
+#[derive(Default)]
+struct ExprNodes(HashMap<*const ErasedExpr, usize>);
+
+impl ExprNodes {
+  fn count(&mut self, expr: &ErasedExpr) -> usize {
+    if let Some(&count) = self.0.get(&(expr as *const _)) {
+      return count;
+    }
+
+    let count = expr
+      .sub_exprs()
+      .into_iter()
+      .fold(1usize, |count, sub_expr| {
+        count.saturating_add(self.count(sub_expr))
+      });
+    self.0.insert(expr, count);
+    count
+  }
+}
+
+/// [`fmt::Write`] implementor counting the written bytes.
+struct OutputSize(usize);
+
+impl fmt::Write for OutputSize {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    self.0 += s.len();
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{lit, Expr, Scope, ShaderBuilder};
+
+  #[test]
+  fn stats() {
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      let square = s.fun(|_: &mut Scope<Expr<f32>>, a: Expr<f32>| &a * &a);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let x = s.var(square.call(lit!(2.)));
+        let v = s.var(lit!(1., 2., 3., 4.) * x.clone());
+        s.set(vertex.position, &v + &v);
+      })
+    });
+
+    let stats = shader.stats();
+
+    assert_eq!(stats.functions(), 1);
+    assert_eq!(stats.instructions(), 3);
+    // square: a * a (3); x: square(2.) (2); v: vec4 * x (3); position: gl_Position (1) and v + v (3)
+    assert_eq!(stats.expr_nodes(), 12);
+    assert_eq!(stats.max_loop_depth(), 0);
+    assert_eq!(
+      stats.output_size(),
+      glsl::write_shader_to_str(&shader).unwrap().len()
+    );
+  }
+}
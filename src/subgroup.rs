@@ -0,0 +1,162 @@
+//! Subgroup operations.
+//!
+//! A _subgroup_ is a set of invocations running together on the same hardware unit, also known as a _wave_ or a
+//! _warp_. Subgroup operations allow those invocations to exchange data without going through memory.
+//!
+//! Subgroup operations are not available on every target, so they are gated behind [`Capability::Subgroup`]. The only
+//! way to get a [`Subgroups`] value is via [`ShaderBuilder::subgroups`], which requires that capability for the shader
+//! being built.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{Scope, ShaderBuilder};
+//!
+//! let shader = ShaderBuilder::new_fragment_shader(|mut s, fragment| {
+//!   let subgroups = s.subgroups();
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     let _sum = s.var(subgroups.add(fragment.frag_coord.clone()));
+//!   })
+//! });
+//! ```
+//!
+//! [`Capability::Subgroup`]: crate::Capability::Subgroup
+//! [`ShaderBuilder::subgroups`]: crate::ShaderBuilder::subgroups
+
+use crate::{
+  Bounded, BuiltIn, ErasedExpr, ErasedFunHandle, Expr, Scope, ScopeInstr, SubgroupBuiltIn, V4,
+};
+use std::ops;
+
+/// Subgroup built-ins and functions.
+///
+/// Those are available in every stage once [`ShaderBuilder::subgroups`](crate::ShaderBuilder::subgroups) has been
+/// called.
+#[derive(Debug)]
+pub struct Subgroups {
+  /// Number of invocations in the subgroup.
+  pub subgroup_size: Expr<u32>,
+
+  /// Index of the current invocation in the subgroup, in the range `[0; subgroup_size)`.
+  pub subgroup_invocation_id: Expr<u32>,
+}
+
+impl Subgroups {
+  pub(crate) fn new() -> Self {
+    let subgroup_size = Expr::new(ErasedExpr::new_builtin(BuiltIn::Subgroup(
+      SubgroupBuiltIn::SubgroupSize,
+    )));
+    let subgroup_invocation_id = Expr::new(ErasedExpr::new_builtin(BuiltIn::Subgroup(
+      SubgroupBuiltIn::SubgroupInvocationID,
+    )));
+
+    Self {
+      subgroup_size,
+      subgroup_invocation_id,
+    }
+  }
+
+  /// Synchronize all the invocations of the subgroup — `subgroupBarrier`.
+  ///
+  /// This builds a statement, so it takes the scope to emit into as argument.
+  #[track_caller]
+  pub fn barrier<R>(&self, scope: &mut Scope<R>) {
+    scope.erased.push(ScopeInstr::Call(
+      ErasedFunHandle::SubgroupBarrier,
+      Vec::new(),
+    ));
+  }
+
+  /// `true` for exactly one active invocation of the subgroup — `subgroupElect`.
+  pub fn elect(&self) -> Expr<bool> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::SubgroupElect,
+      Vec::new(),
+    ))
+  }
+
+  /// Whether `value` is `true` for all active invocations — `subgroupAll`.
+  pub fn all(&self, value: impl Into<Expr<bool>>) -> Expr<bool> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::SubgroupAll,
+      vec![value.into().erased],
+    ))
+  }
+
+  /// Whether `value` is `true` for any active invocation — `subgroupAny`.
+  pub fn any(&self, value: impl Into<Expr<bool>>) -> Expr<bool> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::SubgroupAny,
+      vec![value.into().erased],
+    ))
+  }
+
+  /// Bitfield of the active invocations for which `value` is `true` — `subgroupBallot`.
+  pub fn ballot(&self, value: impl Into<Expr<bool>>) -> Expr<V4<u32>> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::SubgroupBallot,
+      vec![value.into().erased],
+    ))
+  }
+
+  /// Value of `value` in the active invocation with the lowest ID — `subgroupBroadcastFirst`.
+  pub fn broadcast_first<T>(&self, value: impl Into<Expr<T>>) -> Expr<T> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::SubgroupBroadcastFirst,
+      vec![value.into().erased],
+    ))
+  }
+
+  /// Value of `value` in the invocation `id` — `subgroupShuffle`.
+  pub fn shuffle<T>(&self, value: impl Into<Expr<T>>, id: impl Into<Expr<u32>>) -> Expr<T> {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::SubgroupShuffle,
+      vec![value.into().erased, id.into().erased],
+    ))
+  }
+
+  /// Sum of `value` over all active invocations — `subgroupAdd`.
+  pub fn add<T>(&self, value: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: ops::Add<Output = Expr<T>>,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::SubgroupAdd,
+      vec![value.into().erased],
+    ))
+  }
+
+  /// Product of `value` over all active invocations — `subgroupMul`.
+  pub fn mul<T>(&self, value: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: ops::Mul<Output = Expr<T>>,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::SubgroupMul,
+      vec![value.into().erased],
+    ))
+  }
+
+  /// Minimum of `value` over all active invocations — `subgroupMin`.
+  pub fn min<T>(&self, value: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: Bounded,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::SubgroupMin,
+      vec![value.into().erased],
+    ))
+  }
+
+  /// Maximum of `value` over all active invocations — `subgroupMax`.
+  pub fn max<T>(&self, value: impl Into<Expr<T>>) -> Expr<T>
+  where
+    Expr<T>: Bounded,
+  {
+    Expr::new(ErasedExpr::fun_call(
+      ErasedFunHandle::SubgroupMax,
+      vec![value.into().erased],
+    ))
+  }
+}
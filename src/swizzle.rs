@@ -0,0 +1,228 @@
+//! Swizzle masks known at compile time.
+//!
+//! The [`sw!`](crate::sw) and [`sw_mask!`](crate::sw_mask) macros select channels with the [`X`], [`Y`], [`Z`] and
+//! [`W`] types rather than with [`SwizzleSelector`] values, so that the channels a swizzle selects are part of its
+//! type. Such a mask — a selector type or a tuple of them — implements [`StaticMask`].
+//!
+//! Knowing the channels allows to swizzle a [`Var`] into a [`Var`] only when each channel is selected at most once:
+//! selecting a channel several times yields an [`Expr`], that can be read but not assigned to, as such an assignment
+//! would be ambiguous.
+//!
+//! ```compile_fail
+//! # use shades::{Scope, ShaderBuilder};
+//! # ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+//! #   s.main_fun(|s: &mut Scope<()>| {
+//! use shades::{Swizzlable, lit, sw};
+//!
+//! let v = s.var(lit!(1., 2., 3.));
+//! s.set(sw!(v, .x.x), lit!(0., 10.)); // v.xx is not a variable
+//! #   })
+//! # });
+//! ```
+//!
+//! Masks built out of [`SwizzleSelector`] values are only known at runtime, so swizzling a [`Var`] with them always
+//! yields an [`Expr`].
+
+use crate::{Expr, SwizzleSelector, Var};
+
+/// Type-level [`SwizzleSelector`].
+pub trait Selector {
+  /// Channel selected by this type.
+  const SELECTOR: SwizzleSelector;
+}
+
+macro_rules! selectors {
+  ($($(#[$doc:meta])* $t:ident),*) => {
+    $(
+      $(#[$doc])*
+      #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+      pub struct $t;
+
+      impl Selector for $t {
+        const SELECTOR: SwizzleSelector = SwizzleSelector::$t;
+      }
+    )*
+  };
+}
+
+selectors!(
+  /// Select the `.x` (or `.r`, or `.s`) channel.
+  X,
+  /// Select the `.y` (or `.g`, or `.t`) channel.
+  Y,
+  /// Select the `.z` (or `.b`, or `.p`) channel.
+  Z,
+  /// Select the `.w` (or `.a`, or `.q`) channel.
+  W
+);
+
+/// Whether a swizzle mask selects a channel several times: either [`Distinct`] or [`Repeated`].
+pub trait Repetition {
+  /// Repetition of the concatenation of two masks, [`Repeated`] if either `Self` or `R` is.
+  type Or<R: Repetition>: Repetition;
+
+  /// Result of swizzling a variable into a `T`: a [`Var<T>`] if channels are [`Distinct`], an [`Expr<T>`] otherwise.
+  type Swizzled<T>;
+
+  /// Wrap the swizzled expression of a variable.
+  fn swizzled<T>(expr: Expr<T>) -> Self::Swizzled<T>;
+}
+
+/// Each channel is selected at most once.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Distinct {}
+
+impl Repetition for Distinct {
+  type Or<R: Repetition> = R;
+  type Swizzled<T> = Var<T>;
+
+  fn swizzled<T>(expr: Expr<T>) -> Self::Swizzled<T> {
+    Var(expr)
+  }
+}
+
+/// A channel is, or might be, selected several times.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Repeated {}
+
+impl Repetition for Repeated {
+  type Or<R: Repetition> = Repeated;
+  type Swizzled<T> = Expr<T>;
+
+  fn swizzled<T>(expr: Expr<T>) -> Self::Swizzled<T> {
+    expr
+  }
+}
+
+/// Repetition of a pair of selectors: [`Repeated`] if both select the same channel.
+pub trait Overlap<S> {
+  type Repetition: Repetition;
+}
+
+macro_rules! overlaps {
+  ($($a:ident $b:ident => $repetition:ident),* $(,)?) => {
+    $(
+      impl Overlap<$b> for $a {
+        type Repetition = $repetition;
+      }
+    )*
+  };
+}
+
+overlaps!(
+  X X => Repeated, X Y => Distinct, X Z => Distinct, X W => Distinct,
+  Y X => Distinct, Y Y => Repeated, Y Z => Distinct, Y W => Distinct,
+  Z X => Distinct, Z Y => Distinct, Z Z => Repeated, Z W => Distinct,
+  W X => Distinct, W Y => Distinct, W Z => Distinct, W W => Repeated,
+);
+
+type Pair<A, B> = <A as Overlap<B>>::Repetition;
+type Or<R, S> = <R as Repetition>::Or<S>;
+
+/// Swizzle masks, telling whether they select a channel several times.
+pub trait SwizzleMask {
+  /// [`Distinct`] if the mask is known to select each channel at most once, [`Repeated`] otherwise.
+  type Repetition: Repetition;
+}
+
+impl SwizzleMask for SwizzleSelector {
+  type Repetition = Repeated;
+}
+
+impl<const N: usize> SwizzleMask for [SwizzleSelector; N] {
+  type Repetition = Repeated;
+}
+
+/// Swizzle masks known at compile time, turned into their [`SwizzleSelector`]s to swizzle expressions.
+pub trait StaticMask: SwizzleMask {
+  /// Runtime mask: a [`SwizzleSelector`] or an array of them.
+  type Selectors;
+
+  /// Runtime mask selecting the same channels.
+  fn selectors(self) -> Self::Selectors;
+}
+
+impl<A> SwizzleMask for A
+where
+  A: Selector,
+{
+  type Repetition = Distinct;
+}
+
+impl<A> StaticMask for A
+where
+  A: Selector,
+{
+  type Selectors = SwizzleSelector;
+
+  fn selectors(self) -> Self::Selectors {
+    A::SELECTOR
+  }
+}
+
+impl<A, B> SwizzleMask for (A, B)
+where
+  A: Selector + Overlap<B>,
+  B: Selector,
+{
+  type Repetition = Pair<A, B>;
+}
+
+impl<A, B> StaticMask for (A, B)
+where
+  A: Selector + Overlap<B>,
+  B: Selector,
+{
+  type Selectors = [SwizzleSelector; 2];
+
+  fn selectors(self) -> Self::Selectors {
+    [A::SELECTOR, B::SELECTOR]
+  }
+}
+
+impl<A, B, C> SwizzleMask for (A, B, C)
+where
+  A: Selector + Overlap<B> + Overlap<C>,
+  B: Selector + Overlap<C>,
+  C: Selector,
+{
+  type Repetition = Or<Or<Pair<A, B>, Pair<A, C>>, Pair<B, C>>;
+}
+
+impl<A, B, C> StaticMask for (A, B, C)
+where
+  A: Selector + Overlap<B> + Overlap<C>,
+  B: Selector + Overlap<C>,
+  C: Selector,
+{
+  type Selectors = [SwizzleSelector; 3];
+
+  fn selectors(self) -> Self::Selectors {
+    [A::SELECTOR, B::SELECTOR, C::SELECTOR]
+  }
+}
+
+impl<A, B, C, D> SwizzleMask for (A, B, C, D)
+where
+  A: Selector + Overlap<B> + Overlap<C> + Overlap<D>,
+  B: Selector + Overlap<C> + Overlap<D>,
+  C: Selector + Overlap<D>,
+  D: Selector,
+{
+  type Repetition =
+    Or<Or<Or<Pair<A, B>, Pair<A, C>>, Pair<A, D>>, Or<Or<Pair<B, C>, Pair<B, D>>, Pair<C, D>>>;
+}
+
+impl<A, B, C, D> StaticMask for (A, B, C, D)
+where
+  A: Selector + Overlap<B> + Overlap<C> + Overlap<D>,
+  B: Selector + Overlap<C> + Overlap<D>,
+  C: Selector + Overlap<D>,
+  D: Selector,
+{
+  type Selectors = [SwizzleSelector; 4];
+
+  fn selectors(self) -> Self::Selectors {
+    [A::SELECTOR, B::SELECTOR, C::SELECTOR, D::SELECTOR]
+  }
+}
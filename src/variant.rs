@@ -0,0 +1,189 @@
+//! Shader variants.
+//!
+//! Engines often need several versions of the same shader, differing by a few features — normal mapping, number of
+//! lights, skinning, etc. Instead of writing the builder closure several times, you can declare the _flags_ the shader
+//! depends on in a [`Variants`], and build all the permutations at once with [`Variants::build`]. Each permutation is
+//! described by a [`Variant`], which is passed to the builder closure so that it can branch on the flags while building
+//! the AST.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{Scope, ShaderBuilder, V4, outputs, lit};
+//! use shades::variant::Variants;
+//!
+//! let variants = Variants::new().flag("fog").int("lights", 1..=2);
+//! let shaders = variants.build(|variant| {
+//!   ShaderBuilder::new_fragment_shader(|mut s, _| {
+//!     outputs!(s, color: V4<f32>);
+//!
+//!     s.main_fun(|s: &mut Scope<()>| {
+//!       let mut c = lit!(1., 1., 1., 1.) * (variant.int("lights") as f32);
+//!
+//!       if variant.flag("fog") {
+//!         c = c * 0.5;
+//!       }
+//!
+//!       s.set(color, c);
+//!     })
+//!   })
+//! });
+//!
+//! assert_eq!(shaders.len(), 4);
+//! assert_eq!(shaders[3].0.to_string(), "fog=1,lights=2");
+//! ```
+
+use crate::Shader;
+use std::fmt;
+
+/// Set of flags a shader depends on, with all the values each flag can take.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Variants {
+  flags: Vec<(String, Vec<i32>)>,
+}
+
+impl Variants {
+  /// Create an empty set of flags, which has a single permutation.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Declare a boolean flag, which can be either `false` or `true`.
+  pub fn flag(self, name: impl Into<String>) -> Self {
+    self.int(name, 0..=1)
+  }
+
+  /// Declare an integer flag, which can take any of `values`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if a flag with the same name was already declared.
+  pub fn int(mut self, name: impl Into<String>, values: impl IntoIterator<Item = i32>) -> Self {
+    let name = name.into();
+
+    assert!(
+      self.flags.iter().all(|(flag, _)| *flag != name),
+      "flag {} declared several times",
+      name
+    );
+
+    self.flags.push((name, values.into_iter().collect()));
+    self
+  }
+
+  /// Iterate over all the permutations of the flags.
+  ///
+  /// Permutations are ordered lexicographically, the first declared flag being the most significant.
+  pub fn permutations(&self) -> impl Iterator<Item = Variant> + '_ {
+    let count = self.flags.iter().map(|(_, values)| values.len()).product();
+
+    (0..count).map(move |mut index| {
+      let mut values = vec![0; self.flags.len()];
+
+      for (i, (_, flag_values)) in self.flags.iter().enumerate().rev() {
+        values[i] = flag_values[index % flag_values.len()];
+        index /= flag_values.len();
+      }
+
+      Variant {
+        values: self
+          .flags
+          .iter()
+          .zip(values)
+          .map(|((name, _), value)| (name.clone(), value))
+          .collect(),
+      }
+    })
+  }
+
+  /// Build a shader for every permutation of the flags.
+  pub fn build<S>(&self, mut f: impl FnMut(&Variant) -> Shader<S>) -> Vec<(Variant, Shader<S>)> {
+    self
+      .permutations()
+      .map(|variant| {
+        let shader = f(&variant);
+        (variant, shader)
+      })
+      .collect()
+  }
+}
+
+/// A permutation of the flags of a [`Variants`].
+///
+/// Its [`Display`](fmt::Display) implementation lists all the flags and their values, which is suitable to key caches.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Variant {
+  values: Vec<(String, i32)>,
+}
+
+impl Variant {
+  /// Value of the boolean flag `name`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if there is no flag named `name`.
+  pub fn flag(&self, name: &str) -> bool {
+    self.int(name) != 0
+  }
+
+  /// Value of the integer flag `name`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if there is no flag named `name`.
+  pub fn int(&self, name: &str) -> i32 {
+    self
+      .values
+      .iter()
+      .find(|(flag, _)| flag == name)
+      .map(|&(_, value)| value)
+      .unwrap_or_else(|| panic!("unknown flag {}", name))
+  }
+}
+
+impl fmt::Display for Variant {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for (i, (name, value)) in self.values.iter().enumerate() {
+      if i != 0 {
+        f.write_str(",")?;
+      }
+
+      write!(f, "{}={}", name, value)?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn permutations() {
+    let variants = Variants::new().int("a", vec![1, 5, 7]).flag("b");
+    let permutations: Vec<_> = variants.permutations().map(|v| v.to_string()).collect();
+
+    assert_eq!(
+      permutations,
+      vec!["a=1,b=0", "a=1,b=1", "a=5,b=0", "a=5,b=1", "a=7,b=0", "a=7,b=1"]
+    );
+
+    let variant = variants.permutations().nth(3).unwrap();
+    assert_eq!(variant.int("a"), 5);
+    assert!(variant.flag("b"));
+
+    assert_eq!(Variants::new().permutations().count(), 1);
+  }
+
+  #[test]
+  #[should_panic]
+  fn unknown_flag() {
+    Variants::new()
+      .flag("a")
+      .permutations()
+      .next()
+      .unwrap()
+      .flag("b");
+  }
+}
@@ -0,0 +1,324 @@
+//! Traversals of the AST.
+//!
+//! [`Visit`] walks a shader by reference, which is suitable for analyses, and [`Fold`] walks it by value, which is
+//! suitable for transformations. Both traits have a method per kind of node — declarations, functions, scopes,
+//! instructions and expressions. The default implementation of every method walks the children of the node by calling
+//! the free function with the same name, so that an implementor only needs to override the methods for the nodes it is
+//! interested in, and can call back the free function to keep walking.
+//!
+//! Expressions shared between several nodes are walked every time they appear.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{ErasedExpr, Scope, ShaderBuilder, V4, lit, outputs};
+//! use shades::visit::{self, Fold, Visit};
+//!
+//! // count the float literals of a shader
+//! #[derive(Default)]
+//! struct FloatLits(usize);
+//!
+//! impl Visit for FloatLits {
+//!   fn visit_expr(&mut self, expr: &ErasedExpr) {
+//!     if let ErasedExpr::LitFloat(_) = expr {
+//!       self.0 += 1;
+//!     }
+//!
+//!     visit::visit_expr(self, expr);
+//!   }
+//! }
+//!
+//! // halve all the float literals of a shader
+//! struct Halve;
+//!
+//! impl Fold for Halve {
+//!   fn fold_expr(&mut self, expr: ErasedExpr) -> ErasedExpr {
+//!     match visit::fold_expr(self, expr) {
+//!       ErasedExpr::LitFloat(x) => ErasedExpr::LitFloat(x * 0.5),
+//!       expr => expr,
+//!     }
+//!   }
+//! }
+//!
+//! let mut shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+//!   outputs!(s, color: V4<f32>);
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     let x = s.var(lit!(1.));
+//!     s.set(color, lit!(1., 1., 1., 1.) * (x + 2.));
+//!   })
+//! });
+//!
+//! let mut lits = FloatLits::default();
+//! shader.visit(&mut lits);
+//! assert_eq!(lits.0, 2);
+//!
+//! shader.fold(&mut Halve);
+//! let code = shades::writer::glsl::write_shader_to_str(&shader).unwrap();
+//! assert!(code.contains("float var_0_0 = .5;"));
+//! ```
+
+use crate::{ErasedExpr, ErasedFun, ErasedReturn, ErasedScope, ScopeInstr, ShaderDecl};
+use std::mem;
+
+/// Walk the AST by reference.
+pub trait Visit {
+  /// Visit a top-level declaration.
+  fn visit_decl(&mut self, decl: &ShaderDecl) {
+    visit_decl(self, decl)
+  }
+
+  /// Visit a function definition, including `main`.
+  fn visit_fun(&mut self, fun: &ErasedFun) {
+    visit_fun(self, fun)
+  }
+
+  /// Visit a scope.
+  fn visit_scope(&mut self, scope: &ErasedScope) {
+    visit_scope(self, scope)
+  }
+
+  /// Visit an instruction.
+  fn visit_instr(&mut self, instr: &ScopeInstr) {
+    visit_instr(self, instr)
+  }
+
+  /// Visit an expression.
+  fn visit_expr(&mut self, expr: &ErasedExpr) {
+    visit_expr(self, expr)
+  }
+}
+
+/// Visit the function or the constant expression of a declaration.
+pub fn visit_decl<V>(visitor: &mut V, decl: &ShaderDecl)
+where
+  V: Visit + ?Sized,
+{
+  match decl {
+    ShaderDecl::Main(fun) | ShaderDecl::FunDef(_, fun) => visitor.visit_fun(fun),
+    ShaderDecl::Const(_, _, expr) => visitor.visit_expr(expr),
+    ShaderDecl::In(..)
+    | ShaderDecl::Out(..)
+    | ShaderDecl::LayoutOut(..)
+    | ShaderDecl::Uniform(..)
+    | ShaderDecl::PatchIn(..)
+    | ShaderDecl::PatchOut(..)
+    | ShaderDecl::Shared(..)
+    | ShaderDecl::Comment(_) => (),
+  }
+}
+
+/// Visit the body and then the returned expression of a function.
+pub fn visit_fun<V>(visitor: &mut V, fun: &ErasedFun)
+where
+  V: Visit + ?Sized,
+{
+  visitor.visit_scope(&fun.scope);
+
+  if let ErasedReturn::Expr(_, ref expr) = fun.ret {
+    visitor.visit_expr(expr);
+  }
+}
+
+/// Visit the instructions of a scope, in order.
+pub fn visit_scope<V>(visitor: &mut V, scope: &ErasedScope)
+where
+  V: Visit + ?Sized,
+{
+  for instr in &scope.instructions {
+    visitor.visit_instr(instr);
+  }
+}
+
+/// Visit the expressions and then the nested scopes of an instruction.
+pub fn visit_instr<V>(visitor: &mut V, instr: &ScopeInstr)
+where
+  V: Visit + ?Sized,
+{
+  for expr in instr.exprs() {
+    visitor.visit_expr(expr);
+  }
+
+  for scope in instr.scopes() {
+    visitor.visit_scope(scope);
+  }
+}
+
+/// Visit the direct sub-expressions of an expression.
+pub fn visit_expr<V>(visitor: &mut V, expr: &ErasedExpr)
+where
+  V: Visit + ?Sized,
+{
+  for sub_expr in expr.sub_exprs() {
+    visitor.visit_expr(sub_expr);
+  }
+}
+
+/// Transform the AST by value.
+pub trait Fold {
+  /// Fold a top-level declaration.
+  fn fold_decl(&mut self, decl: ShaderDecl) -> ShaderDecl {
+    fold_decl(self, decl)
+  }
+
+  /// Fold a function definition, including `main`.
+  fn fold_fun(&mut self, fun: ErasedFun) -> ErasedFun {
+    fold_fun(self, fun)
+  }
+
+  /// Fold a scope.
+  fn fold_scope(&mut self, scope: ErasedScope) -> ErasedScope {
+    fold_scope(self, scope)
+  }
+
+  /// Fold an instruction.
+  fn fold_instr(&mut self, instr: ScopeInstr) -> ScopeInstr {
+    fold_instr(self, instr)
+  }
+
+  /// Fold an expression.
+  fn fold_expr(&mut self, expr: ErasedExpr) -> ErasedExpr {
+    fold_expr(self, expr)
+  }
+}
+
+/// Fold the function or the constant expression of a declaration.
+pub fn fold_decl<F>(folder: &mut F, decl: ShaderDecl) -> ShaderDecl
+where
+  F: Fold + ?Sized,
+{
+  match decl {
+    ShaderDecl::Main(fun) => ShaderDecl::Main(folder.fold_fun(fun)),
+    ShaderDecl::FunDef(handle, fun) => ShaderDecl::FunDef(handle, folder.fold_fun(fun)),
+    ShaderDecl::Const(handle, ty, expr) => ShaderDecl::Const(handle, ty, folder.fold_expr(expr)),
+    decl => decl,
+  }
+}
+
+/// Fold the body and then the returned expression of a function.
+pub fn fold_fun<F>(folder: &mut F, fun: ErasedFun) -> ErasedFun
+where
+  F: Fold + ?Sized,
+{
+  let scope = folder.fold_scope(fun.scope);
+  let ret = match fun.ret {
+    ErasedReturn::Expr(ty, expr) => ErasedReturn::Expr(ty, folder.fold_expr(expr)),
+    ret => ret,
+  };
+
+  ErasedFun::new(fun.args, scope, ret)
+}
+
+/// Fold the instructions of a scope, in order.
+pub fn fold_scope<F>(folder: &mut F, mut scope: ErasedScope) -> ErasedScope
+where
+  F: Fold + ?Sized,
+{
+  scope.instructions = mem::take(&mut scope.instructions)
+    .into_iter()
+    .map(|instr| folder.fold_instr(instr))
+    .collect();
+  scope
+}
+
+/// Fold the expressions and then the nested scopes of an instruction.
+pub fn fold_instr<F>(folder: &mut F, mut instr: ScopeInstr) -> ScopeInstr
+where
+  F: Fold + ?Sized,
+{
+  for expr in instr.exprs_mut() {
+    *expr = folder.fold_expr(mem::replace(expr, ErasedExpr::LitBool(false)));
+  }
+
+  for scope in instr.scopes_mut() {
+    let id = scope.id;
+    *scope = folder.fold_scope(mem::replace(scope, ErasedScope::new(id)));
+  }
+
+  instr
+}
+
+/// Fold the direct sub-expressions of an expression.
+pub fn fold_expr<F>(folder: &mut F, mut expr: ErasedExpr) -> ErasedExpr
+where
+  F: Fold + ?Sized,
+{
+  for sub_expr in expr.sub_exprs_mut() {
+    *sub_expr = folder.fold_expr(mem::replace(sub_expr, ErasedExpr::LitBool(false)));
+  }
+
+  expr
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{lit, writer::glsl, CanEscape as _, Expr, Scope, ShaderBuilder};
+
+  #[test]
+  fn visit_fold() {
+    #[derive(Default)]
+    struct Counts {
+      funs: usize,
+      instrs: usize,
+      exprs: usize,
+    }
+
+    impl Visit for Counts {
+      fn visit_fun(&mut self, fun: &ErasedFun) {
+        self.funs += 1;
+        visit_fun(self, fun);
+      }
+
+      fn visit_instr(&mut self, instr: &ScopeInstr) {
+        self.instrs += 1;
+        visit_instr(self, instr);
+      }
+
+      fn visit_expr(&mut self, expr: &ErasedExpr) {
+        self.exprs += 1;
+        visit_expr(self, expr);
+      }
+    }
+
+    // swap the operands of additions
+    struct Swap;
+
+    impl Fold for Swap {
+      fn fold_expr(&mut self, expr: ErasedExpr) -> ErasedExpr {
+        match fold_expr(self, expr) {
+          ErasedExpr::Add(a, b) => ErasedExpr::Add(b, a),
+          expr => expr,
+        }
+      }
+    }
+
+    let mut shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      let square = s.fun(|_: &mut Scope<Expr<f32>>, a: Expr<f32>| &a * &a);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let x = s.var(square.call(lit!(2.)));
+        s.when(x.gt(1.), |s| {
+          s.set(
+            vertex.position,
+            lit!(1., 2., 3., 4.) * x.clone() + lit!(4., 3., 2., 1.),
+          );
+        });
+      })
+    });
+
+    let mut counts = Counts::default();
+    shader.visit(&mut counts);
+
+    assert_eq!(counts.funs, 2);
+    assert_eq!(counts.instrs, 3);
+    // square: a * a (3); x: square(2.) (2); x > 1. (3); position: gl_Position (1) and vec4 * x + vec4 (5)
+    assert_eq!(counts.exprs, 14);
+
+    shader.fold(&mut Swap);
+
+    assert!(glsl::write_shader_to_str(&shader)
+      .unwrap()
+      .contains("gl_Position = (vec4(4., 3., 2., 1.) + (vec4(1., 2., 3., 4.) * var_0_0));"));
+  }
+}
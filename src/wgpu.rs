@@ -0,0 +1,403 @@
+//! [wgpu] integration, enabled with the `wgpu` feature gate.
+//!
+//! wgpu doesn’t accept the GLSL written by [`glsl::write_shader`] as-is: its GLSL front end expects Vulkan-flavored
+//! GLSL, where inputs and outputs have explicit locations and uniforms live in uniform blocks bound to descriptor sets.
+//! [`write_shader`] writes such code, and [`Shader::to_shader_module_descriptor`] and [`Shader::to_wgpu`] turn a
+//! shader into a wgpu shader module in one call.
+//!
+//! Inputs and outputs are assigned consecutive locations in declaration order, starting at `0`. As stages are linked by
+//! location, the stages of a [`Program`] must be written together with [`write_program_to_str`], which gives the
+//! inputs of the fragment stage the locations of the vertex outputs of the same name. All the uniforms are
+//! gathered in a single uniform block bound to the binding `0` of the set `0`, in declaration order. The buffer
+//! bound there must follow the std140 layout of that block, which [`Shader::uniform_block_layout`] computes with
+//! [`Layout::Std140`].
+//!
+//! wgpu only supports the vertex and fragment stages, so only [`Shader<Vertex>`] and [`Shader<Fragment>`] can be
+//! converted.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{Scope, ShaderBuilder, V3, inputs, uniforms, vec4};
+//! use shades::wgpu::write_shader_to_str;
+//!
+//! let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+//!   inputs!(s, position: V3<f32>);
+//!   uniforms!(s, scale: f32);
+//!
+//!   s.main_fun(|s: &mut Scope<()>| s.set(vertex.position, vec4!(position * scale, 1.)))
+//! });
+//!
+//! let code = write_shader_to_str(&shader).unwrap();
+//! assert!(code.starts_with("#version 450\n"));
+//! assert!(code.contains("layout(location = 0) in vec3 position;"));
+//! assert!(code.contains("layout(set = 0, binding = 0) uniform Uniforms {\n  float scale;\n};"));
+//!
+//! // with a wgpu::Device
+//! # let _ = |device: &wgpu::Device| {
+//! let module = shader.to_wgpu(device);
+//! # };
+//! ```
+//!
+//! [`Layout::Std140`]: crate::layout::Layout::Std140
+//! [`Program`]: crate::program::Program
+//! [`Shader<Vertex>`]: crate::Shader
+//! [`Shader<Fragment>`]: crate::Shader
+//! [wgpu]: https://crates.io/crates/wgpu
+
+use crate::{
+  infer::matrix_size,
+  program::Program,
+  stage::{Fragment, Stage, Vertex},
+  writer::glsl,
+  Dim, OutputLayout, PrimType, Shader, ShaderDecl, Type,
+};
+use ::wgpu::{naga, Device, ShaderModule, ShaderModuleDescriptor, ShaderSource};
+use std::{collections::HashMap, fmt};
+
+/// Stages supported by wgpu.
+pub trait WgpuStage: Stage {
+  /// Stage, as expected by the GLSL front end of wgpu.
+  const NAGA_STAGE: naga::ShaderStage;
+}
+
+impl WgpuStage for Vertex {
+  const NAGA_STAGE: naga::ShaderStage = naga::ShaderStage::Vertex;
+}
+
+impl WgpuStage for Fragment {
+  const NAGA_STAGE: naga::ShaderStage = naga::ShaderStage::Fragment;
+}
+
+impl<S> Shader<S>
+where
+  S: WgpuStage,
+{
+  /// Descriptor of a wgpu shader module made of this shader, written with [`write_shader`].
+  pub fn to_shader_module_descriptor(&self) -> ShaderModuleDescriptor<'static> {
+    // writing to a String never fails
+    let code = write_shader_to_str(self).unwrap_or_default();
+
+    ShaderModuleDescriptor {
+      label: None,
+      source: ShaderSource::Glsl {
+        shader: code.into(),
+        stage: S::NAGA_STAGE,
+        defines: Default::default(),
+      },
+    }
+  }
+
+  /// Create a wgpu shader module made of this shader on `device`.
+  pub fn to_wgpu(&self, device: &Device) -> ShaderModule {
+    device.create_shader_module(self.to_shader_module_descriptor())
+  }
+}
+
+/// Write a [`Shader`] in Vulkan-flavored GLSL to a [`String`].
+pub fn write_shader_to_str<S>(shader: impl AsRef<Shader<S>>) -> Result<String, fmt::Error> {
+  let mut output = String::new();
+  write_shader(&mut output, shader)?;
+  Ok(output)
+}
+
+/// Write a [`Shader`] in Vulkan-flavored GLSL to a [`fmt::Write`](std::fmt::Write).
+pub fn write_shader<S>(
+  f: &mut impl fmt::Write,
+  shader: impl AsRef<Shader<S>>,
+) -> Result<(), fmt::Error> {
+  write_shader_linked(f, shader.as_ref(), &HashMap::new()).map(|_| ())
+}
+
+/// Vulkan-flavored GLSL sources of the stages of a [`Program`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProgramSources {
+  /// Vertex stage source.
+  pub vertex: String,
+
+  /// Fragment stage source.
+  pub fragment: String,
+}
+
+/// Write the stages of a [`Program`] in Vulkan-flavored GLSL to [`String`]s.
+///
+/// Stages are linked by name, so each input of the fragment stage is given the location of the vertex output of the
+/// same name, whatever the order they are declared in.
+///
+/// [`None`] is returned if the program has tessellation or geometry stages, which wgpu doesn’t support.
+pub fn write_program_to_str(program: &Program) -> Option<Result<ProgramSources, fmt::Error>> {
+  if program.tess_ctrl().is_some() || program.tess_eval().is_some() || program.geometry().is_some()
+  {
+    return None;
+  }
+
+  let write = || {
+    let mut vertex = String::new();
+    let outputs = write_shader_linked(&mut vertex, program.vertex(), &HashMap::new())?;
+
+    let mut fragment = String::new();
+    write_shader_linked(&mut fragment, program.fragment(), &outputs)?;
+
+    Ok(ProgramSources { vertex, fragment })
+  };
+
+  Some(write())
+}
+
+/// Write `shader`, giving its inputs the locations found in `input_locations` by name, and return the locations of its
+/// outputs.
+///
+/// Inputs not in `input_locations` and outputs without explicit locations are given the next free locations in
+/// declaration order.
+fn write_shader_linked<S>(
+  f: &mut impl fmt::Write,
+  shader: &Shader<S>,
+  input_locations: &HashMap<String, usize>,
+) -> Result<HashMap<String, usize>, fmt::Error> {
+  let mut output_locations = HashMap::new();
+
+  f.write_str("#version 450\n")?;
+
+  for directive in &shader.builder.directives {
+    glsl::write_directive(f, directive)?;
+  }
+
+  for capability in &shader.builder.capabilities {
+    glsl::write_capability(f, capability)?;
+  }
+
+  for (name, behavior) in &shader.builder.extensions {
+    glsl::write_extension(f, name, *behavior)?;
+  }
+
+  for layout in &shader.builder.layouts {
+    glsl::write_stage_layout(f, layout)?;
+  }
+
+  let names = glsl::Identifiers::new(&shader.builder);
+  let mut input_location = 0;
+  let mut output_location = 0;
+  let mut uniforms = Some(shader.uniforms());
+
+  for (index, decl) in shader.builder.decls.iter().enumerate() {
+    match decl {
+      ShaderDecl::In(name, ty) | ShaderDecl::PatchIn(name, ty) => {
+        let patch = if let ShaderDecl::PatchIn(..) = decl {
+          "patch "
+        } else {
+          ""
+        };
+        let location = match input_locations.get(name) {
+          Some(&location) => location,
+          None => {
+            input_location += locations(ty);
+            input_location - locations(ty)
+          }
+        };
+
+        write!(f, "layout(location = {}) {}in ", location, patch)?;
+        glsl::write_type(f, ty)?;
+        writeln!(f, " {};", name)?;
+      }
+
+      ShaderDecl::Out(name, ty) | ShaderDecl::PatchOut(name, ty) => {
+        let patch = if let ShaderDecl::PatchOut(..) = decl {
+          "patch "
+        } else {
+          ""
+        };
+        write!(f, "layout(location = {}) {}out ", output_location, patch)?;
+        glsl::write_type(f, ty)?;
+        writeln!(f, " {};", name)?;
+        output_locations.insert(name.clone(), output_location);
+        output_location += locations(ty);
+      }
+
+      // explicit locations are kept, and the next implicit ones come after them
+      ShaderDecl::LayoutOut(name, ty, layout) => {
+        let location = layout.location.unwrap_or(output_location as u32);
+        let layout = OutputLayout {
+          location: Some(location),
+          ..*layout
+        };
+
+        glsl::write_output_layout(f, &layout)?;
+        f.write_str("out ")?;
+        glsl::write_type(f, ty)?;
+        writeln!(f, " {};", name)?;
+        output_locations.insert(name.clone(), location as usize);
+        output_location = output_location.max(location as usize + locations(ty));
+      }
+
+      // the block holding all the uniforms is written in place of the first one
+      ShaderDecl::Uniform(..) => {
+        if let Some(uniforms) = uniforms.take() {
+          f.write_str("layout(set = 0, binding = 0) uniform Uniforms {\n")?;
+
+          for uniform in uniforms {
+            f.write_str("  ")?;
+            glsl::write_type(f, uniform.ty())?;
+            writeln!(f, " {};", uniform.name())?;
+          }
+
+          f.write_str("};\n")?;
+        }
+      }
+
+      _ => glsl::write_nth_decl(f, &names, &shader.builder.decls, index, false)?,
+    }
+  }
+
+  Ok(output_locations)
+}
+
+/// Number of locations an input or output of type `ty` uses.
+fn locations(ty: &Type) -> usize {
+  let columns = match ty.prim_ty {
+    PrimType::Matrix(ref dim) => match matrix_size(dim).0 {
+      Dim::Scalar => 1,
+      Dim::D2 => 2,
+      Dim::D3 => 3,
+      Dim::D4 => 4,
+    },
+    _ => 1,
+  };
+
+  ty.array_dims.iter().product::<usize>() * columns
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    inputs, outputs, program::ProgramBuilder, sw, uniforms, vec4, Scope, ShaderBuilder, Swizzlable,
+    M44, V2, V3, V4,
+  };
+
+  fn parse<S>(shader: &Shader<S>) -> naga::Module
+  where
+    S: WgpuStage,
+  {
+    let code = write_shader_to_str(shader).unwrap();
+    let module = naga::front::glsl::Frontend::default()
+      .parse(&S::NAGA_STAGE.into(), &code)
+      .unwrap_or_else(|e| panic!("{}\n{}", code, e.emit_to_string(&code)));
+
+    naga::valid::Validator::new(
+      naga::valid::ValidationFlags::all(),
+      naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)
+    .unwrap_or_else(|e| panic!("{}\n{:?}", code, e));
+
+    module
+  }
+
+  #[test]
+  fn vertex_shader() {
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      inputs!(s, position: V3<f32>, uv: V2<f32>);
+      uniforms!(s, scale: f32);
+      outputs!(s, v_uv: V2<f32>, v_color: V4<f32>);
+      uniforms!(s, projection: M44);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        s.set(v_uv, uv);
+        s.set(v_color, vec4!(1., 0., 0., 1.));
+        s.set(vertex.position, projection * vec4!(position * scale, 1.));
+      })
+    });
+
+    let code = write_shader_to_str(&shader).unwrap();
+
+    assert!(code.contains(
+      "#version 450
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 uv;
+layout(set = 0, binding = 0) uniform Uniforms {
+  float scale;
+  mat4 projection;
+};
+layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+"
+    ));
+    assert_eq!(code.matches("uniform").count(), 1);
+
+    let module = parse(&shader);
+    assert_eq!(module.entry_points.len(), 1);
+  }
+
+  #[test]
+  fn wide_locations() {
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      inputs!(s, model: M44, weights: [f32; 3], position: V4<f32>);
+      let _ = weights;
+
+      s.main_fun(|s: &mut Scope<()>| s.set(vertex.position, model * position))
+    });
+
+    assert!(write_shader_to_str(&shader).unwrap().contains(
+      "layout(location = 0) in mat4 model;
+layout(location = 4) in float[3] weights;
+layout(location = 7) in vec4 position;
+"
+    ));
+  }
+
+  #[test]
+  fn fragment_shader() {
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, v_color: V4<f32>);
+      outputs!(s, color: V4<f32>);
+
+      s.main_fun(|s: &mut Scope<()>| s.set(color, v_color))
+    });
+
+    parse(&shader);
+
+    let descriptor = shader.to_shader_module_descriptor();
+    assert!(matches!(
+      descriptor.source,
+      ShaderSource::Glsl {
+        stage: naga::ShaderStage::Fragment,
+        ..
+      }
+    ));
+  }
+
+  #[test]
+  fn program_locations_by_name() {
+    let vertex = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      inputs!(s, position: V4<f32>);
+      outputs!(s, v_uv: V2<f32>, v_color: V4<f32>);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        s.set(v_uv, sw!(position, .x.y));
+        s.set(v_color, &position);
+        s.set(vertex.position, position);
+      })
+    });
+
+    let fragment = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      inputs!(s, v_color: V4<f32>, v_uv: V2<f32>);
+      outputs!(s, color: V4<f32>);
+
+      s.main_fun(|s: &mut Scope<()>| s.set(color, v_color * sw!(v_uv, .x)))
+    });
+
+    let program = ProgramBuilder::new(vertex, fragment).link().unwrap();
+    let sources = write_program_to_str(&program).unwrap().unwrap();
+
+    assert!(sources.vertex.contains(
+      "layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+"
+    ));
+    assert!(sources.fragment.contains(
+      "layout(location = 1) in vec4 v_color;
+layout(location = 0) in vec2 v_uv;
+"
+    ));
+  }
+}
@@ -0,0 +1,249 @@
+//! Work group reductions and scans.
+//!
+//! Combining a value computed by every invocation of a work group — summing it, finding its maximum, computing its
+//! prefix sums — is a common compute pattern, which goes through [shared memory](crate::ShaderBuilder::shared) and
+//! [barriers](crate::Barriers::barrier). The functions of this module generate those algorithms, unrolled:
+//!
+//! - reductions are tree reductions, halving the number of active invocations at every step;
+//! - scans are Hillis–Steele inclusive scans, doubling the distance between combined values at every step.
+//!
+//! Both take `log2(N)` steps, where `N` is the length of the shared array the values go through. That array must hold
+//! one element per invocation, so `N` must be the number of invocations of the work group — the product of its
+//! [local size](crate::ShaderBuilder::local_size). All the invocations must call them, in uniform control flow, since
+//! they contain barriers.
+//!
+//! # Examples
+//!
+//! ```
+//! use shades::{Scope, ShaderBuilder};
+//!
+//! let shader = ShaderBuilder::new_compute_shader(|mut s, compute| {
+//!   s.local_size(64, 1, 1);
+//!   let values = s.shared::<[u32; 64]>();
+//!
+//!   s.main_fun(|s: &mut Scope<()>| {
+//!     let total = compute.workgroup_reduce_add(s, &values, compute.local_invocation_index.clone());
+//!     let _total = s.var(total);
+//!   })
+//! });
+//! ```
+
+use crate::{
+  Barriers as _, Bounded, CanEscape as _, Cast as _, ComputeShaderEnv, EscapeScope, Expr, Return,
+  Scope, ToType, Var,
+};
+use std::ops;
+
+impl ComputeShaderEnv {
+  /// Sum `value` over all the invocations of the work group, going through `values`.
+  ///
+  /// Every invocation gets the sum, and `values` can be reused right away.
+  #[track_caller]
+  pub fn workgroup_reduce_add<T, R, const N: usize>(
+    &self,
+    scope: &mut Scope<R>,
+    values: &Var<[T; N]>,
+    value: impl Into<Expr<T>>,
+  ) -> Expr<T>
+  where
+    T: ToType,
+    Expr<T>: ops::Add<Output = Expr<T>>,
+    Return: From<R>,
+  {
+    self.workgroup_reduce(scope, values, value, |a, b| a + b)
+  }
+
+  /// Minimum of `value` over all the invocations of the work group, going through `values`.
+  ///
+  /// Every invocation gets the minimum, and `values` can be reused right away.
+  #[track_caller]
+  pub fn workgroup_reduce_min<T, R, const N: usize>(
+    &self,
+    scope: &mut Scope<R>,
+    values: &Var<[T; N]>,
+    value: impl Into<Expr<T>>,
+  ) -> Expr<T>
+  where
+    T: ToType,
+    Expr<T>: Bounded,
+    Return: From<R>,
+  {
+    self.workgroup_reduce(scope, values, value, |a, b| a.min(b))
+  }
+
+  /// Maximum of `value` over all the invocations of the work group, going through `values`.
+  ///
+  /// Every invocation gets the maximum, and `values` can be reused right away.
+  #[track_caller]
+  pub fn workgroup_reduce_max<T, R, const N: usize>(
+    &self,
+    scope: &mut Scope<R>,
+    values: &Var<[T; N]>,
+    value: impl Into<Expr<T>>,
+  ) -> Expr<T>
+  where
+    T: ToType,
+    Expr<T>: Bounded,
+    Return: From<R>,
+  {
+    self.workgroup_reduce(scope, values, value, |a, b| a.max(b))
+  }
+
+  /// Sum `value` over the invocations of the work group up to the current one, included, going through `values`.
+  ///
+  /// Invocations are ordered by [`ComputeShaderEnv::local_invocation_index`]. Once done, `values` holds the sums of all
+  /// the invocations.
+  #[track_caller]
+  pub fn workgroup_scan_add<T, R, const N: usize>(
+    &self,
+    scope: &mut Scope<R>,
+    values: &Var<[T; N]>,
+    value: impl Into<Expr<T>>,
+  ) -> Expr<T>
+  where
+    T: ToType,
+    Expr<T>: ops::Add<Output = Expr<T>>,
+    Return: From<R>,
+  {
+    let index = self.local_invocation_index.as_i32();
+    let sum = scope.var(value);
+
+    scope.set(values.at(index.clone()), sum.to_expr());
+    self.barrier(scope);
+
+    let mut offset = 1;
+    while offset < N {
+      let previous = values.at(index.clone() - offset as i32).to_expr();
+      scope.when(index.gte(offset as i32), |s: &mut EscapeScope<R>| {
+        s.set(&sum, sum.to_expr() + previous);
+      });
+      self.barrier(scope);
+
+      scope.set(values.at(index.clone()), sum.to_expr());
+      self.barrier(scope);
+
+      offset *= 2;
+    }
+
+    sum.to_expr()
+  }
+
+  /// Tree reduction of `value` with `op`, which must be associative and commutative.
+  #[track_caller]
+  fn workgroup_reduce<T, R, const N: usize>(
+    &self,
+    scope: &mut Scope<R>,
+    values: &Var<[T; N]>,
+    value: impl Into<Expr<T>>,
+    op: impl Fn(Expr<T>, Expr<T>) -> Expr<T>,
+  ) -> Expr<T>
+  where
+    T: ToType,
+    Return: From<R>,
+  {
+    let index = self.local_invocation_index.as_i32();
+
+    scope.set(values.at(index.clone()), value);
+    self.barrier(scope);
+
+    // the first half combines itself with the second half; when N is not a power of two, the middle elements don’t
+    // have a counterpart yet and are left as they are
+    let mut stride = N.next_power_of_two() / 2;
+    while stride > 0 {
+      let active = stride.min(N - stride) as i32;
+      let combined = op(
+        values.at(index.clone()).to_expr(),
+        values.at(index.clone() + stride as i32).to_expr(),
+      );
+      scope.when(index.lt(active), |s: &mut EscapeScope<R>| {
+        s.set(values.at(index.clone()), combined);
+      });
+      self.barrier(scope);
+
+      stride /= 2;
+    }
+
+    let result = scope.var(values.at(0));
+
+    // invocations must be done reading the result before values is written again
+    self.barrier(scope);
+
+    result.to_expr()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{writer::glsl, Scope, ShaderBuilder};
+
+  #[test]
+  fn reduce() {
+    let shader = ShaderBuilder::new_compute_shader(|mut s, compute| {
+      s.local_size(3, 1, 1);
+      let values = s.shared::<[f32; 3]>();
+
+      s.main_fun(|s: &mut Scope<()>| {
+        compute.workgroup_reduce_max(s, &values, 1.);
+      })
+    });
+
+    assert_eq!(
+      glsl::write_shader_to_str(&shader).unwrap(),
+      "layout(local_size_x = 3, local_size_y = 1, local_size_z = 1) in;
+shared float[3] glob_0;
+
+void main() {
+  glob_0[int(gl_LocalInvocationIndex)] = 1.;
+  barrier();
+  if ((int(gl_LocalInvocationIndex) < 1)) {
+    glob_0[int(gl_LocalInvocationIndex)] = max(glob_0[int(gl_LocalInvocationIndex)], glob_0[(int(gl_LocalInvocationIndex) + 2)]);
+  }
+  barrier();
+  if ((int(gl_LocalInvocationIndex) < 1)) {
+    glob_0[int(gl_LocalInvocationIndex)] = max(glob_0[int(gl_LocalInvocationIndex)], glob_0[(int(gl_LocalInvocationIndex) + 1)]);
+  }
+  barrier();
+  float var_0_0 = glob_0[0];
+  barrier();
+}"
+    );
+    assert!(shader.check().is_ok());
+  }
+
+  #[test]
+  fn scan() {
+    let shader = ShaderBuilder::new_compute_shader(|mut s, compute| {
+      s.local_size(4, 1, 1);
+      let values = s.shared::<[u32; 4]>();
+
+      s.main_fun(|s: &mut Scope<()>| {
+        compute.workgroup_scan_add(s, &values, 1u32);
+      })
+    });
+
+    assert_eq!(
+      glsl::write_shader_to_str(&shader).unwrap(),
+      "layout(local_size_x = 4, local_size_y = 1, local_size_z = 1) in;
+shared uint[4] glob_0;
+
+void main() {
+  uint var_0_0 = 1;
+  glob_0[int(gl_LocalInvocationIndex)] = var_0_0;
+  barrier();
+  if ((int(gl_LocalInvocationIndex) >= 1)) {
+    var_0_0 = (var_0_0 + glob_0[(int(gl_LocalInvocationIndex) - 1)]);
+  }
+  barrier();
+  glob_0[int(gl_LocalInvocationIndex)] = var_0_0;
+  barrier();
+  if ((int(gl_LocalInvocationIndex) >= 2)) {
+    var_0_0 = (var_0_0 + glob_0[(int(gl_LocalInvocationIndex) - 2)]);
+  }
+  barrier();
+  glob_0[int(gl_LocalInvocationIndex)] = var_0_0;
+  barrier();
+}"
+    );
+    assert!(shader.check().is_ok());
+  }
+}
@@ -1,48 +1,400 @@
 //! GLSL writers.
 
 use crate::{
-  BuiltIn, Dim, ErasedExpr, ErasedFun, ErasedFunHandle, ErasedReturn, ErasedScope, FragmentBuiltIn,
-  GeometryBuiltIn, MatrixDim, PrimType, ScopeInstr, ScopedHandle, Shader, ShaderDecl, Swizzle,
-  SwizzleSelector, TessCtrlBuiltIn, TessEvalBuiltIn, Type, VertexBuiltIn,
+  pass, program::Program, Behavior, BuiltIn, Capability, ComputeBuiltIn, Dim, Directive,
+  ErasedExpr, ErasedFun, ErasedFunHandle, ErasedReturn, ErasedScope, Expr, FragmentBuiltIn,
+  GeometryBuiltIn, GeometryInput, LocalSize, MatrixDim, OutputLayout, PrimType, ScopeInstr,
+  ScopedHandle, Shader, ShaderBuilder, ShaderDecl, StageLayout, SubgroupBuiltIn, Swizzle,
+  SwizzleSelector, TessCtrlBuiltIn, TessEvalBuiltIn, TessPrimitive, TessSpacing, TessWinding, Type,
+  VertexBuiltIn,
 };
-use std::fmt;
+use std::{borrow::Borrow, collections::HashMap, fmt};
 
 // Number of space an indent level represents.
 const INDENT_SPACES: usize = 2;
 
+// Width beyond which constant arrays are written over several lines.
+const ARRAY_LINE_WIDTH: usize = 100;
+
 /// Write a [`Shader`] to a [`String`].
-pub fn write_shader_to_str(shader: impl AsRef<Shader>) -> Result<String, fmt::Error> {
+pub fn write_shader_to_str<S>(shader: impl AsRef<Shader<S>>) -> Result<String, fmt::Error> {
   let mut output = String::new();
   write_shader(&mut output, shader)?;
   Ok(output)
 }
 
+/// GLSL sources of all the stages of a [`Program`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProgramSources {
+  /// Vertex stage source.
+  pub vertex: String,
+
+  /// Tessellation control stage source, if any.
+  pub tess_ctrl: Option<String>,
+
+  /// Tessellation evaluation stage source, if any.
+  pub tess_eval: Option<String>,
+
+  /// Geometry stage source, if any.
+  pub geometry: Option<String>,
+
+  /// Fragment stage source.
+  pub fragment: String,
+}
+
+/// Write all the stages of a [`Program`] to [`String`]s.
+pub fn write_program_to_str(program: &Program) -> Result<ProgramSources, fmt::Error> {
+  Ok(ProgramSources {
+    vertex: write_shader_to_str(program.vertex())?,
+    tess_ctrl: program.tess_ctrl().map(write_shader_to_str).transpose()?,
+    tess_eval: program.tess_eval().map(write_shader_to_str).transpose()?,
+    geometry: program.geometry().map(write_shader_to_str).transpose()?,
+    fragment: write_shader_to_str(program.fragment())?,
+  })
+}
+
+/// Write a standalone [`Expr`] to a [`String`].
+///
+/// This is mostly useful for debugging and logging, as the variables the expression refers to are not declared.
+pub fn write_expr_to_str<T>(expr: &Expr<T>) -> Result<String, fmt::Error>
+where
+  T: ?Sized,
+{
+  let mut output = String::new();
+  write_expr(&mut output, &Identifiers::default(), &expr.erased)?;
+  Ok(output)
+}
+
 /// Write a [`Shader`] to a [`fmt::Write`](std::fmt::Write).
-pub fn write_shader(f: &mut impl fmt::Write, shader: impl AsRef<Shader>) -> Result<(), fmt::Error> {
-  for decl in &shader.as_ref().builder.decls {
-    match decl {
-      ShaderDecl::Main(fun) => write_main_fun(f, fun)?,
-      ShaderDecl::FunDef(handle, fun) => write_fun_def(f, *handle, fun)?,
-      ShaderDecl::Const(handle, ty, ref constant) => write_constant(f, *handle, ty, constant)?,
-      ShaderDecl::In(name, ty) => write_input(f, name, ty)?,
-      ShaderDecl::Out(name, ty) => write_output(f, name, ty)?,
-      ShaderDecl::Uniform(name, ty) => write_uniform(f, name, ty)?,
+pub fn write_shader<S>(
+  f: &mut impl fmt::Write,
+  shader: impl AsRef<Shader<S>>,
+) -> Result<(), fmt::Error> {
+  write_shader_decls(f, shader.as_ref(), false)
+}
+
+/// Write a [`Shader`] to a [`String`], preceding every instruction with a comment giving where it was added in the
+/// Rust code.
+///
+/// # Examples
+///
+/// ```
+/// use shades::{Scope, ShaderBuilder, V4, lit, outputs};
+/// use shades::writer::glsl;
+///
+/// let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+///   outputs!(s, color: V4<f32>);
+///   s.main_fun(|s: &mut Scope<()>| s.set(color, lit!(1., 0., 0., 1.)))
+/// });
+///
+/// let code = glsl::write_shader_with_locations_to_str(&shader).unwrap();
+/// // `color = vec4(1., 0., 0., 1.);` is preceded by `// path/to/file.rs:line:column`
+/// assert!(code.contains(&format!("  // {}:", file!())));
+/// ```
+pub fn write_shader_with_locations_to_str<S>(
+  shader: impl AsRef<Shader<S>>,
+) -> Result<String, fmt::Error> {
+  let mut output = String::new();
+  write_shader_with_locations(&mut output, shader)?;
+  Ok(output)
+}
+
+/// Same as [`write_shader`], but precede every instruction with a comment giving where it was added in the Rust code.
+///
+/// This is mostly useful for debugging, in order to map the code reported by a driver back to the Rust code building
+/// it.
+pub fn write_shader_with_locations<S>(
+  f: &mut impl fmt::Write,
+  shader: impl AsRef<Shader<S>>,
+) -> Result<(), fmt::Error> {
+  write_shader_decls(f, shader.as_ref(), true)
+}
+
+fn write_shader_decls<S>(
+  f: &mut impl fmt::Write,
+  shader: &Shader<S>,
+  locations: bool,
+) -> Result<(), fmt::Error> {
+  for directive in &shader.builder.directives {
+    write_directive(f, directive)?;
+  }
+
+  // extensions must be written before any declaration
+  for capability in &shader.builder.capabilities {
+    write_capability(f, capability)?;
+  }
+
+  for (name, behavior) in &shader.builder.extensions {
+    write_extension(f, name, *behavior)?;
+  }
+
+  for layout in &shader.builder.layouts {
+    write_stage_layout(f, layout)?;
+  }
+
+  let names = Identifiers::new(&shader.builder);
+
+  for index in 0..shader.builder.decls.len() {
+    write_nth_decl(f, &names, &shader.builder.decls, index, locations)?;
+  }
+
+  Ok(())
+}
+
+/// Identifiers of the functions and constants named by the user, by handle.
+#[derive(Debug, Default)]
+pub(crate) struct Identifiers {
+  funs: HashMap<u16, String>,
+  globals: HashMap<u16, String>,
+}
+
+impl Identifiers {
+  /// Fix the names of `builder`, in declaration order, so that they are valid and unique among themselves and the
+  /// inputs, outputs and uniforms.
+  pub(crate) fn new<S>(builder: &ShaderBuilder<S>) -> Self {
+    let mut taken = pass::interface_names(&builder.decls);
+    let mut names = Self::default();
+
+    for decl in &builder.decls {
+      let (name, handle, identifiers) = match decl {
+        ShaderDecl::FunDef(handle, _) => (builder.fun_names.get(handle), handle, &mut names.funs),
+        ShaderDecl::Const(handle, ..) => {
+          (builder.global_names.get(handle), handle, &mut names.globals)
+        }
+        _ => continue,
+      };
+
+      if let Some(name) = name {
+        let identifier = pass::fixed_identifier(name, &taken);
+        taken.insert(identifier.clone());
+        identifiers.insert(*handle, identifier);
+      }
+    }
+
+    names
+  }
+}
+
+/// Write the declaration at `index` in `decls`.
+///
+/// Functions are preceded by a blank line, which goes in front of their comments instead, so that comments stick to the
+/// functions they annotate.
+pub(crate) fn write_nth_decl(
+  f: &mut impl fmt::Write,
+  names: &Identifiers,
+  decls: &[ShaderDecl],
+  index: usize,
+  locations: bool,
+) -> Result<(), fmt::Error> {
+  let follows_comment = index > 0 && matches!(decls[index - 1], ShaderDecl::Comment(_));
+
+  match &decls[index] {
+    ShaderDecl::Comment(text) => {
+      let annotated = decls[index..]
+        .iter()
+        .find(|decl| !matches!(decl, ShaderDecl::Comment(_)));
+
+      if !follows_comment
+        && matches!(
+          annotated,
+          Some(ShaderDecl::Main(_) | ShaderDecl::FunDef(..))
+        )
+      {
+        f.write_str("\n")?;
+      }
+
+      write_comment(f, text)
+    }
+    ShaderDecl::Main(fun) if follows_comment => write_main_fun(f, names, fun, locations),
+    ShaderDecl::FunDef(handle, fun) if follows_comment => {
+      write_fun_def(f, names, *handle, fun, locations)
+    }
+    decl => write_decl(f, names, decl, locations),
+  }
+}
+
+pub(crate) fn write_decl(
+  f: &mut impl fmt::Write,
+  names: &Identifiers,
+  decl: &ShaderDecl,
+  locations: bool,
+) -> Result<(), fmt::Error> {
+  match decl {
+    // just for aesthetics :')
+    ShaderDecl::Main(fun) => {
+      f.write_str("\n")?;
+      write_main_fun(f, names, fun, locations)
+    }
+    ShaderDecl::FunDef(handle, fun) => {
+      f.write_str("\n")?;
+      write_fun_def(f, names, *handle, fun, locations)
+    }
+    ShaderDecl::Const(handle, ty, constant) => write_constant(f, names, *handle, ty, constant),
+    ShaderDecl::In(name, ty) => write_input(f, name, ty),
+    ShaderDecl::Out(name, ty) => write_output(f, name, ty),
+    ShaderDecl::LayoutOut(name, ty, layout) => {
+      write_output_layout(f, layout)?;
+      write_output(f, name, ty)
     }
+    ShaderDecl::Uniform(name, ty) => write_uniform(f, name, ty),
+    ShaderDecl::PatchIn(name, ty) => {
+      f.write_str("patch ")?;
+      write_input(f, name, ty)
+    }
+    ShaderDecl::PatchOut(name, ty) => {
+      f.write_str("patch ")?;
+      write_output(f, name, ty)
+    }
+    ShaderDecl::Shared(handle, ty) => write_shared(f, names, *handle, ty),
+    ShaderDecl::Comment(text) => write_comment(f, text),
+  }
+}
+
+fn write_comment(f: &mut impl fmt::Write, text: &str) -> Result<(), fmt::Error> {
+  for line in text.split('\n') {
+    writeln!(f, "{}", format!("// {}", line).trim_end())?;
   }
 
   Ok(())
 }
 
-fn write_main_fun(f: &mut impl fmt::Write, fun: &ErasedFun) -> Result<(), fmt::Error> {
-  f.write_str("\nvoid main() {\n")?;
-  write_scope(f, &fun.scope, 1)?;
-  f.write_str("}")
+pub(crate) fn write_capability(
+  f: &mut impl fmt::Write,
+  capability: &Capability,
+) -> Result<(), fmt::Error> {
+  let extensions: &[&str] = match capability {
+    Capability::Subgroup => &[
+      "GL_KHR_shader_subgroup_basic",
+      "GL_KHR_shader_subgroup_vote",
+      "GL_KHR_shader_subgroup_ballot",
+      "GL_KHR_shader_subgroup_shuffle",
+      "GL_KHR_shader_subgroup_arithmetic",
+    ],
+  };
+
+  for extension in extensions {
+    write_extension(f, extension, Behavior::Require)?;
+  }
+
+  Ok(())
 }
 
-fn write_fun_def(f: &mut impl fmt::Write, handle: u16, fun: &ErasedFun) -> Result<(), fmt::Error> {
-  // just for aesthetics :')
-  f.write_str("\n")?;
+pub(crate) fn write_directive(
+  f: &mut impl fmt::Write,
+  directive: &Directive,
+) -> Result<(), fmt::Error> {
+  match directive {
+    Directive::Define(name, value) if value.is_empty() => writeln!(f, "#define {}", name),
+    Directive::Define(name, value) => writeln!(f, "#define {} {}", name, value),
+    Directive::Pragma(pragma) => writeln!(f, "#pragma {}", pragma),
+  }
+}
+
+pub(crate) fn write_extension(
+  f: &mut impl fmt::Write,
+  name: &str,
+  behavior: Behavior,
+) -> Result<(), fmt::Error> {
+  let behavior = match behavior {
+    Behavior::Require => "require",
+    Behavior::Enable => "enable",
+    Behavior::Warn => "warn",
+    Behavior::Disable => "disable",
+  };
+
+  writeln!(f, "#extension {} : {}", name, behavior)
+}
+
+pub(crate) fn write_stage_layout(
+  f: &mut impl fmt::Write,
+  layout: &StageLayout,
+) -> Result<(), fmt::Error> {
+  match layout {
+    StageLayout::Invocations(count) => writeln!(f, "layout(invocations = {}) in;", count),
+    StageLayout::Vertices(count) => writeln!(f, "layout(vertices = {}) out;", count),
+
+    StageLayout::Tessellation(primitive, spacing, winding) => {
+      let primitive = match primitive {
+        TessPrimitive::Triangles => "triangles",
+        TessPrimitive::Quads => "quads",
+        TessPrimitive::Isolines => "isolines",
+      };
+      let spacing = match spacing {
+        TessSpacing::Equal => "equal_spacing",
+        TessSpacing::FractionalEven => "fractional_even_spacing",
+        TessSpacing::FractionalOdd => "fractional_odd_spacing",
+      };
+      let winding = match winding {
+        TessWinding::Ccw => "ccw",
+        TessWinding::Cw => "cw",
+      };
+
+      writeln!(f, "layout({}, {}, {}) in;", primitive, spacing, winding)
+    }
+
+    StageLayout::EarlyFragmentTests => f.write_str("layout(early_fragment_tests) in;\n"),
+
+    StageLayout::LocalSize(sizes) => {
+      f.write_str("layout(")?;
 
+      for (i, (axis, size)) in ["x", "y", "z"].iter().zip(sizes).enumerate() {
+        if i > 0 {
+          f.write_str(", ")?;
+        }
+
+        match size {
+          LocalSize::Const(size) => write!(f, "local_size_{} = {}", axis, size)?,
+          LocalSize::Spec { id, default } => write!(
+            f,
+            "local_size_{0} = {1}, local_size_{0}_id = {2}",
+            axis, default, id
+          )?,
+        }
+      }
+
+      f.write_str(") in;\n")
+    }
+
+    StageLayout::InputPrimitive(primitive) => {
+      let primitive = match primitive {
+        GeometryInput::Points => "points",
+        GeometryInput::Lines => "lines",
+        GeometryInput::LinesAdjacency => "lines_adjacency",
+        GeometryInput::Triangles => "triangles",
+        GeometryInput::TrianglesAdjacency => "triangles_adjacency",
+      };
+
+      writeln!(f, "layout({}) in;", primitive)
+    }
+
+    StageLayout::ClipDistances(size) => writeln!(f, "out float gl_ClipDistance[{}];", size),
+    StageLayout::CullDistances(size) => writeln!(f, "out float gl_CullDistance[{}];", size),
+    StageLayout::XfbStride(buffer, stride) => writeln!(
+      f,
+      "layout(xfb_buffer = {}, xfb_stride = {}) out;",
+      buffer, stride
+    ),
+  }
+}
+
+fn write_main_fun(
+  f: &mut impl fmt::Write,
+  names: &Identifiers,
+  fun: &ErasedFun,
+  locations: bool,
+) -> Result<(), fmt::Error> {
+  f.write_str("void main() {\n")?;
+  write_scope(f, names, &fun.scope, 1, locations)?;
+  f.write_str("}")
+}
+
+fn write_fun_def(
+  f: &mut impl fmt::Write,
+  names: &Identifiers,
+  handle: u16,
+  fun: &ErasedFun,
+  locations: bool,
+) -> Result<(), fmt::Error> {
   let ret_expr = match &fun.ret {
     ErasedReturn::Void => {
       f.write_str("void")?;
@@ -56,7 +408,7 @@ fn write_fun_def(f: &mut impl fmt::Write, handle: u16, fun: &ErasedFun) -> Resul
   };
 
   f.write_str(" ")?;
-  write_user_fun_handle(f, handle)?;
+  write_user_fun_handle(f, names, handle)?;
 
   f.write_str("(")?;
   if !fun.args.is_empty() {
@@ -72,24 +424,31 @@ fn write_fun_def(f: &mut impl fmt::Write, handle: u16, fun: &ErasedFun) -> Resul
   }
   f.write_str(") {\n")?;
 
-  write_scope(f, &fun.scope, 1)?;
+  write_scope(f, names, &fun.scope, 1, locations)?;
 
-  if let Some(ref expr) = ret_expr {
+  if let Some(expr) = ret_expr {
     write_indent(f, 1)?;
     f.write_str("return ")?;
-    write_expr(f, expr)?;
-    f.write_str(";")?;
+    write_expr(f, names, expr)?;
+    f.write_str(";\n")?;
   }
 
-  f.write_str("\n}\n")
+  f.write_str("}\n")
 }
 
 fn write_scope(
   f: &mut impl fmt::Write,
+  names: &Identifiers,
   scope: &ErasedScope,
   indent_lvl: usize,
+  locations: bool,
 ) -> Result<(), fmt::Error> {
-  for instr in &scope.instructions {
+  for (i, instr) in scope.instructions.iter().enumerate() {
+    if let Some(location) = scope.location(i).filter(|_| locations) {
+      write_indent(f, indent_lvl)?;
+      writeln!(f, "// {}", location)?;
+    }
+
     write_indent(f, indent_lvl)?;
 
     match instr {
@@ -100,9 +459,16 @@ fn write_scope(
       } => {
         write_type(f, ty)?;
         f.write_str(" ")?;
-        write_scoped_handle(f, handle)?;
+        write_scoped_handle(f, names, handle)?;
         f.write_str(" = ")?;
-        write_expr(f, init_value)?;
+        write_expr(f, names, init_value)?;
+        f.write_str(";")?;
+      }
+
+      ScopeInstr::VarDeclUninit { ty, handle } => {
+        write_type(f, ty)?;
+        f.write_str(" ")?;
+        write_scoped_handle(f, names, handle)?;
         f.write_str(";")?;
       }
 
@@ -113,7 +479,7 @@ fn write_scope(
 
         ErasedReturn::Expr(_, expr) => {
           f.write_str("return ")?;
-          write_expr(f, expr)?;
+          write_expr(f, names, expr)?;
           f.write_str(";")?;
         }
       },
@@ -126,25 +492,36 @@ fn write_scope(
         f.write_str("break;")?;
       }
 
+      ScopeInstr::Comment(text) => {
+        for (i, line) in text.split('\n').enumerate() {
+          if i > 0 {
+            f.write_str("\n")?;
+            write_indent(f, indent_lvl)?;
+          }
+
+          f.write_str(format!("// {}", line).trim_end())?;
+        }
+      }
+
       ScopeInstr::If { condition, scope } => {
         f.write_str("if (")?;
-        write_expr(f, condition)?;
+        write_expr(f, names, condition)?;
         f.write_str(") {\n")?;
-        write_scope(f, scope, indent_lvl + 1)?;
+        write_scope(f, names, scope, indent_lvl + 1, locations)?;
         write_indented(f, indent_lvl, "}")?;
       }
 
       ScopeInstr::ElseIf { condition, scope } => {
         f.write_str(" else if (")?;
-        write_expr(f, condition)?;
+        write_expr(f, names, condition)?;
         f.write_str(") {\n")?;
-        write_scope(f, scope, indent_lvl + 1)?;
+        write_scope(f, names, scope, indent_lvl + 1, locations)?;
         write_indented(f, indent_lvl, "}")?;
       }
 
       ScopeInstr::Else { scope } => {
         f.write_str("else {\n")?;
-        write_scope(f, scope, indent_lvl + 1)?;
+        write_scope(f, names, scope, indent_lvl + 1, locations)?;
         write_indented(f, indent_lvl, "}")?;
       }
 
@@ -153,7 +530,7 @@ fn write_scope(
         init_handle,
         init_expr,
         condition,
-        // post_expr,
+        post_expr,
         scope,
       } => {
         f.write_str("for (")?;
@@ -161,41 +538,110 @@ fn write_scope(
         // initialization
         write_type(f, init_ty)?;
         f.write_str(" ")?;
-        write_scoped_handle(f, init_handle)?;
+        write_scoped_handle(f, names, init_handle)?;
         f.write_str(" = ")?;
-        write_expr(f, init_expr)?;
+        write_expr(f, names, init_expr)?;
         f.write_str("; ")?;
 
         // condition
-        write_expr(f, condition)?;
+        write_expr(f, names, condition)?;
         f.write_str("; ")?;
 
         // iteration; we basically write <init-expr> = <next-expr> in a fold-like way, so we need to re-use the
         // init_handle
-        write_scoped_handle(f, init_handle)?;
-        f.write_str("++")?;
-        // write_expr(f, post_expr)?;
+        write_scoped_handle(f, names, init_handle)?;
+        f.write_str(" = ")?;
+        write_expr(f, names, post_expr)?;
         f.write_str(") {\n")?;
 
         // scope
-        write_scope(f, scope, indent_lvl + 1)?;
-        f.write_str("}")?;
+        write_scope(f, names, scope, indent_lvl + 1, locations)?;
+        write_indented(f, indent_lvl, "}")?;
+      }
+
+      ScopeInstr::ForMulti {
+        condition,
+        post_exprs,
+        scope,
+      } => {
+        f.write_str("for (; ")?;
+        write_expr(f, names, condition)?;
+        f.write_str(";")?;
+
+        for (i, (var, expr)) in post_exprs.iter().enumerate() {
+          f.write_str(if i == 0 { " " } else { ", " })?;
+          write_expr(f, names, var)?;
+          f.write_str(" = ")?;
+          write_expr(f, names, expr)?;
+        }
+
+        f.write_str(") {\n")?;
+        write_scope(f, names, scope, indent_lvl + 1, locations)?;
+        write_indented(f, indent_lvl, "}")?;
       }
 
       ScopeInstr::While { condition, scope } => {
         f.write_str("while (")?;
-        write_expr(f, condition)?;
+        write_expr(f, names, condition)?;
         f.write_str(") {\n")?;
-        write_scope(f, scope, indent_lvl + 1)?;
+        write_scope(f, names, scope, indent_lvl + 1, locations)?;
+        write_indented(f, indent_lvl, "}")?;
+      }
+
+      ScopeInstr::Block { scope } => {
+        f.write_str("{\n")?;
+        write_scope(f, names, scope, indent_lvl + 1, locations)?;
+        write_indented(f, indent_lvl, "}")?;
+      }
+
+      ScopeInstr::Loop { scope } => {
+        f.write_str("while (true) {\n")?;
+        write_scope(f, names, scope, indent_lvl + 1, locations)?;
         write_indented(f, indent_lvl, "}")?;
       }
 
+      ScopeInstr::DoWhile { condition, scope } => {
+        f.write_str("do {\n")?;
+        write_scope(f, names, scope, indent_lvl + 1, locations)?;
+        write_indented(f, indent_lvl, "} while (")?;
+        write_expr(f, names, condition)?;
+        f.write_str(");")?;
+      }
+
       ScopeInstr::MutateVar { var, expr } => {
-        write_expr(f, var)?;
+        write_expr(f, names, var)?;
         f.write_str(" = ")?;
-        write_expr(f, expr)?;
+        write_expr(f, names, expr)?;
         f.write_str(";")?;
       }
+
+      ScopeInstr::Call(fun, args) => {
+        write_fun_call(f, names, fun, args)?;
+        f.write_str(";")?;
+      }
+
+      ScopeInstr::Switch { expr, cases } => {
+        f.write_str("switch (")?;
+        write_expr(f, names, expr)?;
+        f.write_str(") {\n")?;
+
+        for (label, scope) in cases {
+          write_indent(f, indent_lvl + 1)?;
+
+          if let Some(label) = label {
+            f.write_str("case ")?;
+            write_expr(f, names, label)?;
+            f.write_str(": {\n")?;
+          } else {
+            f.write_str("default: {\n")?;
+          }
+
+          write_scope(f, names, scope, indent_lvl + 2, locations)?;
+          write_indented(f, indent_lvl + 1, "}\n")?;
+        }
+
+        write_indented(f, indent_lvl, "}")?;
+      }
     }
 
     f.write_str("\n")?;
@@ -206,38 +652,121 @@ fn write_scope(
 
 fn write_constant(
   f: &mut impl fmt::Write,
+  names: &Identifiers,
   handle: u16,
   ty: &Type,
   constant: &ErasedExpr,
 ) -> Result<(), fmt::Error> {
-  f.write_str("const ")?;
+  let mut decl = String::from("const ");
+  write_type(&mut decl, ty)?;
+  decl.push(' ');
+  write_scoped_handle(&mut decl, names, &ScopedHandle::global(handle))?;
+  decl.push_str(" = ");
+
+  let mut value = String::new();
+  write_expr(&mut value, names, constant)?;
+
+  f.write_str(&decl)?;
+
+  match constant {
+    // long arrays, such as tables computed on the host, are written with as many items per line as possible
+    ErasedExpr::Array(ty, items) if decl.len() + value.len() + 1 > ARRAY_LINE_WIDTH => {
+      write_type(f, ty)?;
+      f.write_str("(\n")?;
+
+      let mut line = String::new();
+      for (i, item) in items.iter().enumerate() {
+        let mut item_str = String::new();
+        write_expr(&mut item_str, names, item)?;
+
+        if i + 1 < items.len() {
+          item_str.push(',');
+        }
+
+        if !line.is_empty() && INDENT_SPACES + line.len() + item_str.len() > ARRAY_LINE_WIDTH {
+          write_indent(f, 1)?;
+          writeln!(f, "{}", line)?;
+          line.clear();
+        }
+
+        line.push_str(&item_str);
+      }
+
+      write_indent(f, 1)?;
+      writeln!(f, "{}", line)?;
+      f.write_str(");\n")
+    }
+
+    _ => {
+      f.write_str(&value)?;
+      f.write_str(";\n")
+    }
+  }
+}
+
+fn write_shared(
+  f: &mut impl fmt::Write,
+  names: &Identifiers,
+  handle: u16,
+  ty: &Type,
+) -> Result<(), fmt::Error> {
+  f.write_str("shared ")?;
   write_type(f, ty)?;
   f.write_str(" ")?;
-  write_scoped_handle(f, &ScopedHandle::global(handle))?;
-  f.write_str(" = ")?;
-  write_expr(f, constant)?;
+  write_scoped_handle(f, names, &ScopedHandle::global(handle))?;
   f.write_str(";\n")
 }
 
 fn write_input(f: &mut impl fmt::Write, name: &str, ty: &Type) -> Result<(), fmt::Error> {
   f.write_str("in ")?;
   write_type(f, ty)?;
-  write!(f, " {};\n", name)
+  writeln!(f, " {};", name)
 }
 
 fn write_output(f: &mut impl fmt::Write, name: &str, ty: &Type) -> Result<(), fmt::Error> {
   f.write_str("out ")?;
   write_type(f, ty)?;
-  write!(f, " {};\n", name)
+  writeln!(f, " {};", name)
+}
+
+pub(crate) fn write_output_layout(
+  f: &mut impl fmt::Write,
+  layout: &OutputLayout,
+) -> Result<(), fmt::Error> {
+  let qualifiers = [
+    ("location", layout.location),
+    ("index", layout.index),
+    ("xfb_buffer", layout.xfb_buffer),
+    ("xfb_offset", layout.xfb_offset),
+  ];
+  let mut qualifiers = qualifiers
+    .iter()
+    .filter_map(|&(qualifier, value)| value.map(|value| (qualifier, value)));
+
+  if let Some((qualifier, value)) = qualifiers.next() {
+    write!(f, "layout({} = {}", qualifier, value)?;
+
+    for (qualifier, value) in qualifiers {
+      write!(f, ", {} = {}", qualifier, value)?;
+    }
+
+    f.write_str(") ")?;
+  }
+
+  Ok(())
 }
 
 fn write_uniform(f: &mut impl fmt::Write, name: &str, ty: &Type) -> Result<(), fmt::Error> {
   f.write_str("uniform ")?;
   write_type(f, ty)?;
-  write!(f, " {};\n", name)
+  writeln!(f, " {};", name)
 }
 
-fn write_expr(f: &mut impl fmt::Write, expr: &ErasedExpr) -> Result<(), fmt::Error> {
+pub(crate) fn write_expr(
+  f: &mut impl fmt::Write,
+  names: &Identifiers,
+  expr: &ErasedExpr,
+) -> Result<(), fmt::Error> {
   match expr {
     ErasedExpr::LitInt(x) => write!(f, "{}", x),
     ErasedExpr::LitUInt(x) => write!(f, "{}", x),
@@ -275,219 +804,226 @@ fn write_expr(f: &mut impl fmt::Write, expr: &ErasedExpr) -> Result<(), fmt::Err
     ErasedExpr::LitBool4([x, y, z, w]) => write!(f, "bvec4({}, {}, {}, {})", x, y, z, w),
 
     ErasedExpr::LitM22(m) => write_matrix(f, "mat2", &m.0),
+    ErasedExpr::LitM23(m) => write_matrix(f, "mat2x3", &m.0),
+    ErasedExpr::LitM24(m) => write_matrix(f, "mat2x4", &m.0),
+    ErasedExpr::LitM32(m) => write_matrix(f, "mat3x2", &m.0),
     ErasedExpr::LitM33(m) => write_matrix(f, "mat3", &m.0),
+    ErasedExpr::LitM34(m) => write_matrix(f, "mat3x4", &m.0),
+    ErasedExpr::LitM42(m) => write_matrix(f, "mat4x2", &m.0),
+    ErasedExpr::LitM43(m) => write_matrix(f, "mat4x3", &m.0),
     ErasedExpr::LitM44(m) => write_matrix(f, "mat4", &m.0),
 
     ErasedExpr::Array(ty, items) => {
       write_type(f, ty)?;
       f.write_str("(")?;
 
-      write_expr(f, &items[0])?;
-      for item in &items[1..] {
-        f.write_str(",")?;
-        write_expr(f, item)?;
+      if let Some((first, rest)) = items.split_first() {
+        write_expr(f, names, first)?;
+
+        for item in rest {
+          f.write_str(",")?;
+          write_expr(f, names, item)?;
+        }
       }
 
       f.write_str(")")
     }
 
-    ErasedExpr::Var(handle) => write_var(f, handle),
+    ErasedExpr::Var(handle) => write_var(f, names, handle),
 
     ErasedExpr::Not(e) => {
       f.write_str("!")?;
-      write_expr(f, e)
+      write_expr(f, names, e)
     }
 
     ErasedExpr::And(a, b) => {
       f.write_str("()")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str("&&")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Or(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" || ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Xor(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" ^^ ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::BitAnd(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" & ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::BitOr(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" | ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::BitXor(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" ^ ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Neg(e) => {
       f.write_str("-(")?;
-      write_expr(f, e)?;
+      write_expr(f, names, e)?;
       f.write_str(")")
     }
 
     ErasedExpr::Add(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" + ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Sub(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" - ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Mul(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" * ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Div(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" / ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Rem(a, b) => {
       f.write_str("mod(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(", ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Shl(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" << ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Shr(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" >> ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Eq(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" == ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Neq(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" != ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Lt(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" < ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Lte(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" <= ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Gt(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" > ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
     ErasedExpr::Gte(a, b) => {
       f.write_str("(")?;
-      write_expr(f, a)?;
+      write_expr(f, names, a)?;
       f.write_str(" >= ")?;
-      write_expr(f, b)?;
+      write_expr(f, names, b)?;
       f.write_str(")")
     }
 
-    ErasedExpr::FunCall(fun, args) => {
-      write_fun_handle(f, fun)?;
-      f.write_str("(")?;
-
-      if !args.is_empty() {
-        write_expr(f, &args[0])?;
-      }
+    ErasedExpr::FunCall(fun, args) => write_fun_call(f, names, fun, args),
 
-      for arg in &args[1..] {
-        f.write_str(", ")?;
-        write_expr(f, arg)?;
-      }
+    ErasedExpr::ArrayLength(e) => {
+      write_expr(f, names, e)?;
+      f.write_str(".length()")
+    }
 
+    ErasedExpr::Cast(ty, e) => {
+      write_type(f, ty)?;
+      f.write_str("(")?;
+      write_expr(f, names, e)?;
       f.write_str(")")
     }
 
     ErasedExpr::Swizzle(e, s) => {
-      write_expr(f, e)?;
+      write_expr(f, names, e)?;
       f.write_str(".")?;
       write_swizzle(f, s)
     }
 
     ErasedExpr::Field { object, field } => {
-      write_expr(f, object)?;
+      write_expr(f, names, object)?;
       f.write_str(".")?;
-      write_expr(f, field)
+      write_expr(f, names, field)
     }
 
     ErasedExpr::ArrayLookup { object, index } => {
-      write_expr(f, object)?;
+      write_expr(f, names, object)?;
       f.write_str("[")?;
-      write_expr(f, index)?;
+      write_expr(f, names, index)?;
       f.write_str("]")
     }
   }
@@ -543,7 +1079,32 @@ fn write_swizzle_sel(f: &mut impl fmt::Write, d: &SwizzleSelector) -> Result<(),
   }
 }
 
-fn write_fun_handle(f: &mut impl fmt::Write, fun: &ErasedFunHandle) -> Result<(), fmt::Error> {
+fn write_fun_call(
+  f: &mut impl fmt::Write,
+  names: &Identifiers,
+  fun: &ErasedFunHandle,
+  args: &[impl Borrow<ErasedExpr>],
+) -> Result<(), fmt::Error> {
+  write_fun_handle(f, names, fun)?;
+  f.write_str("(")?;
+
+  if let Some((first, rest)) = args.split_first() {
+    write_expr(f, names, first.borrow())?;
+
+    for arg in rest {
+      f.write_str(", ")?;
+      write_expr(f, names, arg.borrow())?;
+    }
+  }
+
+  f.write_str(")")
+}
+
+pub(crate) fn write_fun_handle(
+  f: &mut impl fmt::Write,
+  names: &Identifiers,
+  fun: &ErasedFunHandle,
+) -> Result<(), fmt::Error> {
   match fun {
     ErasedFunHandle::Vec2 => f.write_str("vec2"),
     ErasedFunHandle::Vec3 => f.write_str("vec3"),
@@ -577,6 +1138,8 @@ fn write_fun_handle(f: &mut impl fmt::Write, fun: &ErasedFunHandle) -> Result<()
     ErasedFunHandle::RoundEven => f.write_str("roundEven"),
     ErasedFunHandle::Ceil => f.write_str("ceil"),
     ErasedFunHandle::Fract => f.write_str("fract"),
+    ErasedFunHandle::Mod => f.write_str("mod"),
+    ErasedFunHandle::Modf => f.write_str("modf"),
     ErasedFunHandle::Min => f.write_str("min"),
     ErasedFunHandle::Max => f.write_str("max"),
     ErasedFunHandle::Clamp => f.write_str("clamp"),
@@ -588,7 +1151,7 @@ fn write_fun_handle(f: &mut impl fmt::Write, fun: &ErasedFunHandle) -> Result<()
     ErasedFunHandle::FloatBitsToInt => f.write_str("floatBitsToInt"),
     ErasedFunHandle::IntBitsToFloat => f.write_str("intBitsToFloat"),
     ErasedFunHandle::UIntBitsToFloat => f.write_str("uIntBitsToFloat"),
-    ErasedFunHandle::FMA => f.write_str("fma"),
+    ErasedFunHandle::Fma => f.write_str("fma"),
     ErasedFunHandle::Frexp => f.write_str("frexp"),
     ErasedFunHandle::Ldexp => f.write_str("ldexp"),
     ErasedFunHandle::PackUnorm2x16 => f.write_str("packUnorm2x16"),
@@ -632,12 +1195,12 @@ fn write_fun_handle(f: &mut impl fmt::Write, fun: &ErasedFunHandle) -> Result<()
     ErasedFunHandle::EndStreamPrimitive => f.write_str("EndStreamPrimitive"),
     ErasedFunHandle::EmitVertex => f.write_str("EmitVertex"),
     ErasedFunHandle::EndPrimitive => f.write_str("EndPrimitive"),
-    ErasedFunHandle::DFDX => f.write_str("dfdx"),
-    ErasedFunHandle::DFDY => f.write_str("dfdy"),
-    ErasedFunHandle::DFDXFine => f.write_str("dfdxFine"),
-    ErasedFunHandle::DFDYFine => f.write_str("dfdyFine"),
-    ErasedFunHandle::DFDXCoarse => f.write_str("dfdxCoarse"),
-    ErasedFunHandle::DFDYCoarse => f.write_str("dfdyCoarse"),
+    ErasedFunHandle::DfDx => f.write_str("dFdx"),
+    ErasedFunHandle::DfDy => f.write_str("dFdy"),
+    ErasedFunHandle::DfDxFine => f.write_str("dFdxFine"),
+    ErasedFunHandle::DfDyFine => f.write_str("dFdyFine"),
+    ErasedFunHandle::DfDxCoarse => f.write_str("dFdxCoarse"),
+    ErasedFunHandle::DfDyCoarse => f.write_str("dFdyCoarse"),
     ErasedFunHandle::FWidth => f.write_str("fwidth"),
     ErasedFunHandle::FWidthFine => f.write_str("fwidthFine"),
     ErasedFunHandle::FWidthCoarse => f.write_str("fwidthCoarse"),
@@ -646,7 +1209,7 @@ fn write_fun_handle(f: &mut impl fmt::Write, fun: &ErasedFunHandle) -> Result<()
     ErasedFunHandle::InterpolateAtOffset => f.write_str("interpolateAtOffset"),
     ErasedFunHandle::Barrier => f.write_str("barrier"),
     ErasedFunHandle::MemoryBarrier => f.write_str("memoryBarrier"),
-    ErasedFunHandle::MemoryBarrierAtomic => f.write_str("memoryBarrierAtomic"),
+    ErasedFunHandle::MemoryBarrierAtomic => f.write_str("memoryBarrierAtomicCounter"),
     ErasedFunHandle::MemoryBarrierBuffer => f.write_str("memoryBarrierBuffer"),
     ErasedFunHandle::MemoryBarrierShared => f.write_str("memoryBarrierShared"),
     ErasedFunHandle::MemoryBarrierImage => f.write_str("memoryBarrierImage"),
@@ -654,25 +1217,52 @@ fn write_fun_handle(f: &mut impl fmt::Write, fun: &ErasedFunHandle) -> Result<()
     ErasedFunHandle::AnyInvocation => f.write_str("anyInvocation"),
     ErasedFunHandle::AllInvocations => f.write_str("allInvocations"),
     ErasedFunHandle::AllInvocationsEqual => f.write_str("allInvocationsEqual"),
-    ErasedFunHandle::UserDefined(handle) => write_user_fun_handle(f, *handle),
+    ErasedFunHandle::SubgroupBarrier => f.write_str("subgroupBarrier"),
+    ErasedFunHandle::SubgroupElect => f.write_str("subgroupElect"),
+    ErasedFunHandle::SubgroupAll => f.write_str("subgroupAll"),
+    ErasedFunHandle::SubgroupAny => f.write_str("subgroupAny"),
+    ErasedFunHandle::SubgroupBallot => f.write_str("subgroupBallot"),
+    ErasedFunHandle::SubgroupBroadcastFirst => f.write_str("subgroupBroadcastFirst"),
+    ErasedFunHandle::SubgroupShuffle => f.write_str("subgroupShuffle"),
+    ErasedFunHandle::SubgroupAdd => f.write_str("subgroupAdd"),
+    ErasedFunHandle::SubgroupMul => f.write_str("subgroupMul"),
+    ErasedFunHandle::SubgroupMin => f.write_str("subgroupMin"),
+    ErasedFunHandle::SubgroupMax => f.write_str("subgroupMax"),
+    ErasedFunHandle::UserDefined(handle) => write_user_fun_handle(f, names, *handle),
   }
 }
 
-fn write_user_fun_handle(f: &mut impl fmt::Write, handle: u16) -> Result<(), fmt::Error> {
-  write!(f, "fun_{}", handle)
+fn write_user_fun_handle(
+  f: &mut impl fmt::Write,
+  names: &Identifiers,
+  handle: u16,
+) -> Result<(), fmt::Error> {
+  match names.funs.get(&handle) {
+    Some(name) => f.write_str(name),
+    None => write!(f, "fun_{}", handle),
+  }
 }
 
-fn write_var(f: &mut impl fmt::Write, handle: &ScopedHandle) -> Result<(), fmt::Error> {
-  write_scoped_handle(f, handle)
+fn write_var(
+  f: &mut impl fmt::Write,
+  names: &Identifiers,
+  handle: &ScopedHandle,
+) -> Result<(), fmt::Error> {
+  write_scoped_handle(f, names, handle)
 }
 
-fn write_scoped_handle(f: &mut impl fmt::Write, handle: &ScopedHandle) -> Result<(), fmt::Error> {
+pub(crate) fn write_scoped_handle(
+  f: &mut impl fmt::Write,
+  names: &Identifiers,
+  handle: &ScopedHandle,
+) -> Result<(), fmt::Error> {
   match handle {
     ScopedHandle::BuiltIn(builtin) => write_builtin(f, builtin),
 
-    ScopedHandle::Global(handle) => {
-      write!(f, "glob_{}", handle)
-    }
+    ScopedHandle::Global(handle) => match names.globals.get(handle) {
+      Some(name) => f.write_str(name),
+      None => write!(f, "glob_{}", handle),
+    },
 
     ScopedHandle::FunArg(handle) => {
       write!(f, "arg_{}", handle)
@@ -697,6 +1287,8 @@ fn write_builtin(f: &mut impl fmt::Write, builtin: &BuiltIn) -> Result<(), fmt::
     BuiltIn::TessEval(builtin) => write_tess_eval_builtin(f, builtin),
     BuiltIn::Geometry(builtin) => write_geo_builtin(f, builtin),
     BuiltIn::Fragment(builtin) => write_frag_builtin(f, builtin),
+    BuiltIn::Compute(builtin) => write_compute_builtin(f, builtin),
+    BuiltIn::Subgroup(builtin) => write_subgroup_builtin(f, builtin),
   }
 }
 
@@ -723,8 +1315,8 @@ fn write_tess_ctrl_builtin(
     TessCtrlBuiltIn::InvocationID => f.write_str("gl_InvocationID"),
     TessCtrlBuiltIn::TessellationLevelOuter => f.write_str("gl_TessellationLevelOuter"),
     TessCtrlBuiltIn::TessellationLevelInner => f.write_str("gl_TessellationLevelInner"),
-    TessCtrlBuiltIn::In => f.write_str("gl_In"),
-    TessCtrlBuiltIn::Out => f.write_str("gl_Out"),
+    TessCtrlBuiltIn::In => f.write_str("gl_in"),
+    TessCtrlBuiltIn::Out => f.write_str("gl_out"),
     TessCtrlBuiltIn::Position => f.write_str("gl_Position"),
     TessCtrlBuiltIn::PointSize => f.write_str("gl_PointSize"),
     TessCtrlBuiltIn::ClipDistance => f.write_str("gl_ClipDistance"),
@@ -743,8 +1335,8 @@ fn write_tess_eval_builtin(
     TessEvalBuiltIn::PrimitiveID => f.write_str("gl_PrimitiveID"),
     TessEvalBuiltIn::TessellationLevelOuter => f.write_str("gl_TessellationLevelOuter"),
     TessEvalBuiltIn::TessellationLevelInner => f.write_str("gl_TessellationLevelInner"),
-    TessEvalBuiltIn::In => f.write_str("gl_In"),
-    TessEvalBuiltIn::Out => f.write_str("gl_Out"),
+    TessEvalBuiltIn::In => f.write_str("gl_in"),
+    TessEvalBuiltIn::Out => f.write_str("gl_out"),
     TessEvalBuiltIn::Position => f.write_str("gl_Position"),
     TessEvalBuiltIn::PointSize => f.write_str("gl_PointSize"),
     TessEvalBuiltIn::ClipDistance => f.write_str("gl_ClipDistance"),
@@ -754,8 +1346,8 @@ fn write_tess_eval_builtin(
 
 fn write_geo_builtin(f: &mut impl fmt::Write, builtin: &GeometryBuiltIn) -> Result<(), fmt::Error> {
   match builtin {
-    GeometryBuiltIn::In => f.write_str("gl_In"),
-    GeometryBuiltIn::Out => f.write_str("gl_Out"),
+    GeometryBuiltIn::In => f.write_str("gl_in"),
+    GeometryBuiltIn::Out => f.write_str("gl_out"),
     GeometryBuiltIn::Position => f.write_str("gl_Position"),
     GeometryBuiltIn::PointSize => f.write_str("gl_PointSize"),
     GeometryBuiltIn::ClipDistance => f.write_str("gl_ClipDistance"),
@@ -790,6 +1382,29 @@ fn write_frag_builtin(
   }
 }
 
+fn write_compute_builtin(
+  f: &mut impl fmt::Write,
+  builtin: &ComputeBuiltIn,
+) -> Result<(), fmt::Error> {
+  match builtin {
+    ComputeBuiltIn::NumWorkGroups => f.write_str("gl_NumWorkGroups"),
+    ComputeBuiltIn::WorkGroupID => f.write_str("gl_WorkGroupID"),
+    ComputeBuiltIn::LocalInvocationID => f.write_str("gl_LocalInvocationID"),
+    ComputeBuiltIn::GlobalInvocationID => f.write_str("gl_GlobalInvocationID"),
+    ComputeBuiltIn::LocalInvocationIndex => f.write_str("gl_LocalInvocationIndex"),
+  }
+}
+
+fn write_subgroup_builtin(
+  f: &mut impl fmt::Write,
+  builtin: &SubgroupBuiltIn,
+) -> Result<(), fmt::Error> {
+  match builtin {
+    SubgroupBuiltIn::SubgroupSize => f.write_str("gl_SubgroupSize"),
+    SubgroupBuiltIn::SubgroupInvocationID => f.write_str("gl_SubgroupInvocationID"),
+  }
+}
+
 fn write_prim_type(f: &mut impl fmt::Write, prim_ty: &PrimType) -> Result<(), fmt::Error> {
   let ty_str = match prim_ty {
     // ints
@@ -818,20 +1433,20 @@ fn write_prim_type(f: &mut impl fmt::Write, prim_ty: &PrimType) -> Result<(), fm
 
     // matrices
     PrimType::Matrix(MatrixDim::D22) => "mat2",
-    PrimType::Matrix(MatrixDim::D23) => "mat23",
-    PrimType::Matrix(MatrixDim::D24) => "mat24",
-    PrimType::Matrix(MatrixDim::D32) => "mat32",
+    PrimType::Matrix(MatrixDim::D23) => "mat2x3",
+    PrimType::Matrix(MatrixDim::D24) => "mat2x4",
+    PrimType::Matrix(MatrixDim::D32) => "mat3x2",
     PrimType::Matrix(MatrixDim::D33) => "mat3",
-    PrimType::Matrix(MatrixDim::D34) => "mat34",
-    PrimType::Matrix(MatrixDim::D42) => "mat42",
-    PrimType::Matrix(MatrixDim::D43) => "mat43",
+    PrimType::Matrix(MatrixDim::D34) => "mat3x4",
+    PrimType::Matrix(MatrixDim::D42) => "mat4x2",
+    PrimType::Matrix(MatrixDim::D43) => "mat4x3",
     PrimType::Matrix(MatrixDim::D44) => "mat4",
   };
 
   f.write_str(ty_str)
 }
 
-fn write_type(f: &mut impl fmt::Write, ty: &Type) -> Result<(), fmt::Error> {
+pub(crate) fn write_type(f: &mut impl fmt::Write, ty: &Type) -> Result<(), fmt::Error> {
   write_prim_type(f, &ty.prim_ty)?;
 
   // array notation
@@ -872,20 +1487,255 @@ fn write_matrix<const M: usize, const N: usize>(
 
   // first dimension
   write!(f, "{}", write_f32(m[0][0]))?;
-  for i in 1..N {
-    write!(f, ", {}", write_f32(m[0][i]))?;
+  for x in &m[0][1..] {
+    write!(f, ", {}", write_f32(*x))?;
   }
 
   // general case
-  for y in 1..M {
-    for i in 0..N {
-      write!(f, ", {}", write_f32(m[y][i]))?;
+  for col in &m[1..] {
+    for x in col {
+      write!(f, ", {}", write_f32(*x))?;
     }
   }
 
   f.write_str(")")
 }
 
+/// Keywords of GLSL, including the ones reserved for future use, sorted.
+const KEYWORDS: &[&str] = &[
+  "active",
+  "asm",
+  "atomic_uint",
+  "attribute",
+  "bool",
+  "break",
+  "buffer",
+  "bvec2",
+  "bvec3",
+  "bvec4",
+  "case",
+  "cast",
+  "centroid",
+  "class",
+  "coherent",
+  "common",
+  "const",
+  "continue",
+  "default",
+  "discard",
+  "dmat2",
+  "dmat2x2",
+  "dmat2x3",
+  "dmat2x4",
+  "dmat3",
+  "dmat3x2",
+  "dmat3x3",
+  "dmat3x4",
+  "dmat4",
+  "dmat4x2",
+  "dmat4x3",
+  "dmat4x4",
+  "do",
+  "double",
+  "dvec2",
+  "dvec3",
+  "dvec4",
+  "else",
+  "enum",
+  "extern",
+  "external",
+  "false",
+  "filter",
+  "fixed",
+  "flat",
+  "float",
+  "for",
+  "fvec2",
+  "fvec3",
+  "fvec4",
+  "goto",
+  "half",
+  "highp",
+  "hvec2",
+  "hvec3",
+  "hvec4",
+  "if",
+  "iimage1D",
+  "iimage1DArray",
+  "iimage2D",
+  "iimage2DArray",
+  "iimage2DMS",
+  "iimage2DMSArray",
+  "iimage2DRect",
+  "iimage3D",
+  "iimageBuffer",
+  "iimageCube",
+  "iimageCubeArray",
+  "image1D",
+  "image1DArray",
+  "image2D",
+  "image2DArray",
+  "image2DMS",
+  "image2DMSArray",
+  "image2DRect",
+  "image3D",
+  "imageBuffer",
+  "imageCube",
+  "imageCubeArray",
+  "in",
+  "inline",
+  "inout",
+  "input",
+  "int",
+  "interface",
+  "invariant",
+  "isampler1D",
+  "isampler1DArray",
+  "isampler2D",
+  "isampler2DArray",
+  "isampler2DMS",
+  "isampler2DMSArray",
+  "isampler2DRect",
+  "isampler3D",
+  "isamplerBuffer",
+  "isamplerCube",
+  "isamplerCubeArray",
+  "ivec2",
+  "ivec3",
+  "ivec4",
+  "layout",
+  "long",
+  "lowp",
+  "mat2",
+  "mat2x2",
+  "mat2x3",
+  "mat2x4",
+  "mat3",
+  "mat3x2",
+  "mat3x3",
+  "mat3x4",
+  "mat4",
+  "mat4x2",
+  "mat4x3",
+  "mat4x4",
+  "mediump",
+  "namespace",
+  "noinline",
+  "noperspective",
+  "out",
+  "output",
+  "partition",
+  "patch",
+  "precise",
+  "precision",
+  "public",
+  "readonly",
+  "resource",
+  "restrict",
+  "return",
+  "sample",
+  "sampler1D",
+  "sampler1DArray",
+  "sampler1DArrayShadow",
+  "sampler1DShadow",
+  "sampler2D",
+  "sampler2DArray",
+  "sampler2DArrayShadow",
+  "sampler2DMS",
+  "sampler2DMSArray",
+  "sampler2DRect",
+  "sampler2DRectShadow",
+  "sampler2DShadow",
+  "sampler3D",
+  "sampler3DRect",
+  "samplerBuffer",
+  "samplerCube",
+  "samplerCubeArray",
+  "samplerCubeArrayShadow",
+  "samplerCubeShadow",
+  "shared",
+  "short",
+  "sizeof",
+  "smooth",
+  "static",
+  "struct",
+  "subroutine",
+  "superp",
+  "switch",
+  "template",
+  "this",
+  "true",
+  "typedef",
+  "uimage1D",
+  "uimage1DArray",
+  "uimage2D",
+  "uimage2DArray",
+  "uimage2DMS",
+  "uimage2DMSArray",
+  "uimage2DRect",
+  "uimage3D",
+  "uimageBuffer",
+  "uimageCube",
+  "uimageCubeArray",
+  "uint",
+  "uniform",
+  "union",
+  "unsigned",
+  "usampler1D",
+  "usampler1DArray",
+  "usampler2D",
+  "usampler2DArray",
+  "usampler2DMS",
+  "usampler2DMSArray",
+  "usampler2DRect",
+  "usampler3D",
+  "usamplerBuffer",
+  "usamplerCube",
+  "usamplerCubeArray",
+  "using",
+  "uvec2",
+  "uvec3",
+  "uvec4",
+  "varying",
+  "vec2",
+  "vec3",
+  "vec4",
+  "void",
+  "volatile",
+  "while",
+  "writeonly",
+];
+
+/// Why `name`, chosen by the user, cannot be used as an identifier, if it cannot.
+pub(crate) fn identifier_problem(name: &str) -> Option<&'static str> {
+  let mut chars = name.chars();
+  let valid = chars
+    .next()
+    .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+  // names generated by the writer, followed by a handle
+  let generated = ["var_", "arg_", "glob_", "fun_"].iter().any(|prefix| {
+    name
+      .strip_prefix(prefix)
+      .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+  });
+
+  if !valid {
+    Some("is not a valid identifier")
+  } else if KEYWORDS.binary_search(&name).is_ok() {
+    Some("is a reserved keyword")
+  } else if name.starts_with("gl_") {
+    Some("starts with the reserved prefix `gl_`")
+  } else if name.contains("__") {
+    Some("contains the reserved sequence `__`")
+  } else if generated || name == "main" {
+    Some("clashes with a generated name")
+  } else {
+    None
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -923,4 +1773,616 @@ mod tests {
       "mat4(1., 2., 3., 4., 5., 6., 7., 8., 9., 10., 11., 12., 13., 14., 15., 16.)"
     );
   }
+
+  #[test]
+  fn fun_calls() {
+    let mut output = String::new();
+
+    write_fun_call(
+      &mut output,
+      &Identifiers::default(),
+      &ErasedFunHandle::EmitVertex,
+      &[] as &[ErasedExpr],
+    )
+    .unwrap();
+    assert_eq!(output, "EmitVertex()");
+
+    output.clear();
+    write_fun_call(
+      &mut output,
+      &Identifiers::default(),
+      &ErasedFunHandle::EmitStreamVertex,
+      &[ErasedExpr::LitInt(1)],
+    )
+    .unwrap();
+    assert_eq!(output, "EmitStreamVertex(1)");
+  }
+
+  #[test]
+  fn subgroups() {
+    use crate::{Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, _| {
+      let subgroups = s.subgroups();
+      s.subgroups();
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let _ = s.var(subgroups.add(subgroups.subgroup_invocation_id.clone()));
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "#extension GL_KHR_shader_subgroup_basic : require
+#extension GL_KHR_shader_subgroup_vote : require
+#extension GL_KHR_shader_subgroup_ballot : require
+#extension GL_KHR_shader_subgroup_shuffle : require
+#extension GL_KHR_shader_subgroup_arithmetic : require
+
+void main() {
+  uint var_0_0 = subgroupAdd(gl_SubgroupInvocationID);
+}"
+    );
+  }
+
+  #[test]
+  fn comments() {
+    use crate::{Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      s.comment("time, in seconds");
+      let time = unsafe { s.uniform::<f32>("time") };
+      s.comment("pulse between 0 and 1\n");
+      s.comment("twice a second");
+      let pulse = s.fun(move |s: &mut Scope<Expr<f32>>| {
+        s.comment("a full period\nevery half second");
+        time.to_expr() * 2.
+      });
+
+      s.comment("main");
+      s.main_fun(move |s: &mut Scope<()>| {
+        s.loop_forever(|s| {
+          s.comment("pulse");
+          s.var(pulse.call());
+        });
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "// time, in seconds
+uniform float time;
+
+// pulse between 0 and 1
+//
+// twice a second
+float fun_0() {
+  // a full period
+  // every half second
+  return (time * 2.);
+}
+
+// main
+void main() {
+  while (true) {
+    // pulse
+    float var_1_0 = fun_0();
+  }
+}"
+    );
+  }
+
+  #[test]
+  fn names() {
+    use crate::{lit, Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      let scale = unsafe { s.uniform::<f32>("scale") };
+      let two = s.named_constant("two", 2.);
+      let double = s.named_fun("scale", move |_: &mut Scope<Expr<f32>>, a: Expr<f32>| {
+        a * two.clone() * scale.clone()
+      });
+      let triple = s.named_fun("scale", |_: &mut Scope<Expr<f32>>, a: Expr<f32>| a * 3.);
+      let half = s.named_fun("half value", |_: &mut Scope<Expr<f32>>, a: Expr<f32>| {
+        a / 2.
+      });
+
+      s.main_fun(move |s: &mut Scope<()>| {
+        s.var(double.call(lit!(1.)) + triple.call(lit!(1.)) + half.call(lit!(1.)));
+      })
+    });
+
+    // names clashing with the uniform, each other or invalid are fixed
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "uniform float scale;
+const float two = 2.;
+
+float scale0(float arg_0) {
+  return ((arg_0 * two) * scale);
+}
+
+float scale1(float arg_0) {
+  return (arg_0 * 3.);
+}
+
+float half_value(float arg_0) {
+  return (arg_0 / 2.);
+}
+
+void main() {
+  float var_0_0 = ((scale0(1.) + scale1(1.)) + half_value(1.));
+}"
+    );
+  }
+
+  #[test]
+  fn constant_arrays() {
+    use crate::{Scope, ShaderBuilder};
+
+    let table: Vec<f32> = (0..32).map(|i| i as f32 / 4.).collect();
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      let small = s.constant_array(&[1., 2., 3.]).unwrap();
+      let table = s.constant_array(&table).unwrap();
+
+      s.main_fun(|s: &mut Scope<()>| {
+        s.var(small.at(0) + table.at(31));
+        s.var(table.length());
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "const float[3] glob_0 = float[3](1.,2.,3.);
+const float[32] glob_1 = float[32](
+  0.,.25,.5,.75,1.,1.25,1.5,1.75,2.,2.25,2.5,2.75,3.,3.25,3.5,3.75,4.,4.25,4.5,4.75,5.,5.25,5.5,
+  5.75,6.,6.25,6.5,6.75,7.,7.25,7.5,7.75
+);
+
+void main() {
+  float var_0_0 = (glob_0[0] + glob_1[31]);
+  int var_0_1 = glob_1.length();
+}"
+    );
+    assert!(shader.check().is_ok());
+  }
+
+  #[test]
+  fn directives() {
+    use crate::{Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      s.define("SHADOWS", "1");
+      s.pragma("optimize(off)");
+      s.define("MAX_LIGHTS", "4");
+      s.define("SHADOWS", "");
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "#define SHADOWS\n#pragma optimize(off)\n#define MAX_LIGHTS 4\n\nvoid main() {\n}"
+    );
+  }
+
+  #[test]
+  fn extensions() {
+    use crate::{Behavior, Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, _| {
+      s.require_extension("GL_EXT_debug_printf", Behavior::Require);
+      s.require_extension("GL_KHR_shader_subgroup_shuffle", Behavior::Warn);
+      s.require_extension("GL_EXT_debug_printf", Behavior::Enable);
+      s.subgroups();
+
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "#extension GL_KHR_shader_subgroup_basic : require
+#extension GL_KHR_shader_subgroup_vote : require
+#extension GL_KHR_shader_subgroup_ballot : require
+#extension GL_KHR_shader_subgroup_shuffle : require
+#extension GL_KHR_shader_subgroup_arithmetic : require
+#extension GL_EXT_debug_printf : enable
+#extension GL_KHR_shader_subgroup_shuffle : warn
+
+void main() {
+}"
+    );
+  }
+
+  #[test]
+  fn invocations() {
+    use crate::{Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_geometry_shader(|mut s, _| {
+      s.invocations(2);
+      s.invocations(6);
+
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "layout(invocations = 6) in;\n\nvoid main() {\n}"
+    );
+  }
+
+  #[test]
+  fn vertices() {
+    use crate::{Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_tess_ctrl_shader(|mut s, patch| {
+      let output = s.vertices::<3>();
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let id = patch.invocation_id.clone();
+        s.set(
+          output.at(id.clone()).position(),
+          patch.input.at(id).position(),
+        );
+      })
+    });
+
+    let code = write_shader_to_str(&shader).unwrap();
+    assert!(code.starts_with("layout(vertices = 3) out;\n\nvoid main() {\n"));
+  }
+
+  #[test]
+  fn tessellation() {
+    use crate::{Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_tess_eval_shader(|mut s, patch| {
+      patch.tessellation(
+        &mut s,
+        TessPrimitive::Quads,
+        TessSpacing::Equal,
+        TessWinding::Ccw,
+      );
+      patch.tessellation(
+        &mut s,
+        TessPrimitive::Triangles,
+        TessSpacing::FractionalOdd,
+        TessWinding::Cw,
+      );
+
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "layout(triangles, fractional_odd_spacing, cw) in;\n\nvoid main() {\n}"
+    );
+  }
+
+  #[test]
+  fn dual_source_blending() {
+    use crate::{Scope, ShaderBuilder, V4};
+
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      let color = unsafe { s.output_at::<V4<f32>>("color", 0, 0) };
+      let blend = unsafe { s.output_at::<V4<f32>>("blend", 0, 1) };
+
+      s.main_fun(move |s: &mut Scope<()>| {
+        s.set(color, V4::from([1., 0., 0., 1.]));
+        s.set(blend, V4::from([0.5, 0.5, 0.5, 0.5]));
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "layout(location = 0, index = 0) out vec4 color;
+layout(location = 0, index = 1) out vec4 blend;
+
+void main() {
+  color = vec4(1., 0., 0., 1.);
+  blend = vec4(.5, .5, .5, .5);
+}"
+    );
+  }
+
+  #[test]
+  fn local_size() {
+    use crate::{Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_compute_shader(|mut s, _| {
+      s.local_size(LocalSize::Spec { id: 0, default: 64 }, 4, 1);
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "layout(local_size_x = 64, local_size_x_id = 0, local_size_y = 4, local_size_z = 1) in;\n\nvoid main() {\n}"
+    );
+    assert_eq!(
+      shader.local_size().map(|sizes| sizes.map(LocalSize::size)),
+      Some([64, 4, 1])
+    );
+  }
+
+  #[test]
+  fn input_primitive() {
+    use crate::{primitive::TrianglesAdjacency, LoopScope, Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_geometry_shader(|mut s, geometry| {
+      let input = s.input_primitive(TrianglesAdjacency);
+      s.main_fun(move |s: &mut Scope<()>| {
+        s.for_each(input, |s: &mut LoopScope<()>, v| {
+          s.set(&geometry.position, v.position());
+        });
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "layout(triangles_adjacency) in;\n\nvoid main() {\n  for (int var_1_0 = 0; (var_1_0 < 6); var_1_0 = (var_1_0 + 1)) {\n    gl_Position = gl_in[var_1_0].gl_Position;\n  }\n}"
+    );
+  }
+
+  #[test]
+  fn clip_distances() {
+    use crate::{lit, Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      s.clip_distances(1);
+      s.clip_distances(2);
+      s.main_fun(move |s: &mut Scope<()>| {
+        s.set(vertex.position, lit!(0., 0., 0., 1.));
+        s.set(vertex.clip_distance.at(1), lit!(1.));
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "out float gl_ClipDistance[2];\n\nvoid main() {\n  gl_Position = vec4(0., 0., 0., 1.);\n  gl_ClipDistance[1] = 1.;\n}"
+    );
+  }
+
+  #[test]
+  fn transform_feedback() {
+    use crate::{lit, Scope, ShaderBuilder, V3};
+
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, vertex| {
+      s.xfb_stride(0, 8);
+      s.xfb_stride(0, 16);
+      let position = unsafe { s.xfb_output::<V3<f32>>("position", 0, 0) };
+      let id = unsafe { s.xfb_output::<i32>("id", 1, 0) };
+
+      s.main_fun(move |s: &mut Scope<()>| {
+        s.set(vertex.position, lit!(0., 0., 0., 1.));
+        s.set(position, lit!(0., 0., 0.));
+        s.set(id, vertex.vertex_id);
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "layout(xfb_buffer = 0, xfb_stride = 16) out;\nlayout(xfb_buffer = 0, xfb_offset = 0) out vec3 position;\nlayout(xfb_buffer = 1, xfb_offset = 0) out int id;\n\nvoid main() {\n  gl_Position = vec4(0., 0., 0., 1.);\n  position = vec3(0., 0., 0.);\n  id = gl_VertexID;\n}"
+    );
+  }
+
+  #[test]
+  fn compute_builtins() {
+    use crate::{Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_compute_shader(|mut s, compute| {
+      s.local_size(8, 8, 1);
+      s.main_fun(|s: &mut Scope<()>| {
+        s.var(compute.global_invocation_id);
+        s.var(compute.local_invocation_index);
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;\n\nvoid main() {\n  uvec3 var_0_0 = gl_GlobalInvocationID;\n  uint var_0_1 = gl_LocalInvocationIndex;\n}"
+    );
+  }
+
+  #[test]
+  fn compute_barriers() {
+    use crate::{Barriers as _, Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_compute_shader(|mut s, compute| {
+      s.local_size(64, 1, 1);
+      s.main_fun(|s: &mut Scope<()>| {
+        compute.memory_barrier_shared(s);
+        compute.group_memory_barrier(s);
+        compute.barrier(s);
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;\n\nvoid main() {\n  memoryBarrierShared();\n  groupMemoryBarrier();\n  barrier();\n}"
+    );
+  }
+
+  #[test]
+  fn early_fragment_tests() {
+    use crate::{Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_fragment_shader(|mut s, _| {
+      s.early_fragment_tests();
+      s.early_fragment_tests();
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "layout(early_fragment_tests) in;\n\nvoid main() {\n}"
+    );
+  }
+
+  #[test]
+  fn patch() {
+    use crate::{Scope, ShaderBuilder};
+
+    let tess_ctrl = ShaderBuilder::new_tess_ctrl_shader(|mut s, _| {
+      let level = unsafe { s.patch_output::<f32>("level") };
+      s.main_fun(move |s: &mut Scope<()>| s.set(level, 1.))
+    });
+    let tess_eval = ShaderBuilder::new_tess_eval_shader(|mut s, _| {
+      let _ = unsafe { s.patch_input::<f32>("level") };
+      s.main_fun(|_: &mut Scope<()>| {})
+    });
+
+    assert_eq!(
+      write_shader_to_str(&tess_ctrl).unwrap(),
+      "patch out float level;\n\nvoid main() {\n  level = 1.;\n}"
+    );
+    assert_eq!(
+      write_shader_to_str(&tess_eval).unwrap(),
+      "patch in float level;\n\nvoid main() {\n}"
+    );
+  }
+
+  #[test]
+  fn switch() {
+    use crate::{Scope, ShaderBuilder};
+
+    let shader = ShaderBuilder::new_vertex_shader(|s, _| {
+      s.main_fun(|s: &mut Scope<()>| {
+        let x = s.var(1);
+
+        s.switch_on(&x)
+          .case_fallthrough(0, |_| {})
+          .case(1, |s| s.set(&x, 2))
+          .default(|s| s.abort());
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "
+void main() {
+  int var_0_0 = 1;
+  switch (var_0_0) {
+    case 0: {
+    }
+    case 1: {
+      var_0_0 = 2;
+      break;
+    }
+    default: {
+      return;
+    }
+  }
+}"
+    );
+  }
+
+  #[test]
+  fn standalone_expr() {
+    use crate::{lit, V2};
+
+    let e = !lit!(1., 2.).eq(lit!(3., 4.));
+    assert_eq!(write_expr_to_str(&e).unwrap(), "true");
+
+    let e = Expr::<V2<f32>>::new(ErasedExpr::Var(ScopedHandle::fun_var(0, 0))) * 3.;
+    assert_eq!(write_expr_to_str(&e).unwrap(), "(var_0_0 * 3.)");
+  }
+
+  #[test]
+  fn modules() {
+    use crate::{lit, module::ShaderModule, Expr, Scope, ShaderBuilder};
+
+    let base = ShaderModule::new(|m| {
+      let scale = m.constant(lit!(2.));
+      m.fun(move |_: &mut Scope<Expr<f32>>, a: Expr<f32>| a * scale.clone())
+    });
+    let lib = ShaderModule::new(|m| {
+      let double = m.import(&base);
+      m.fun(move |_: &mut Scope<Expr<f32>>, a: Expr<f32>| double.call(a) + 1.)
+    });
+
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, _| {
+      let id = s.fun(|_: &mut Scope<Expr<f32>>, a: Expr<f32>| a);
+      let inc = s.import(&lib);
+      let inc2 = s.import(&lib);
+      let double = s.import(&base);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let _x = s.var(inc.call(id.call(lit!(1.))) + inc2.call(double.call(lit!(2.))));
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "\nfloat fun_0(float arg_0) {\n  return arg_0;\n}\nconst float glob_0 = 2.;\n\nfloat fun_1(float arg_0) {\n  return (arg_0 * glob_0);\n}\n\nfloat fun_2(float arg_0) {\n  return (fun_1(arg_0) + 1.);\n}\n\nvoid main() {\n  float var_0_0 = (fun_2(fun_0(1.)) + fun_2(fun_1(2.)));\n}"
+    );
+  }
+
+  #[test]
+  fn diamond_modules() {
+    use crate::{lit, module::ShaderModule, Expr, Scope, ShaderBuilder};
+
+    let base = ShaderModule::new(|m| {
+      let scale = m.constant(lit!(2.));
+      m.fun(move |_: &mut Scope<Expr<f32>>, a: Expr<f32>| a * scale.clone())
+    });
+    let inc = ShaderModule::new(|m| {
+      let double = m.import(&base);
+      m.fun(move |_: &mut Scope<Expr<f32>>, a: Expr<f32>| double.call(a) + 1.)
+    });
+    let dec = ShaderModule::new(|m| {
+      let double = m.import(&base);
+      m.fun(move |_: &mut Scope<Expr<f32>>, a: Expr<f32>| double.call(a) - 1.)
+    });
+
+    let shader = ShaderBuilder::new_vertex_shader(|mut s, _| {
+      let inc = s.import(&inc);
+      let dec = s.import(&dec);
+
+      s.main_fun(|s: &mut Scope<()>| {
+        let _x = s.var(inc.call(lit!(1.)) + dec.call(lit!(2.)));
+      })
+    });
+
+    assert_eq!(
+      write_shader_to_str(&shader).unwrap(),
+      "const float glob_0 = 2.;\n\nfloat fun_0(float arg_0) {\n  return (arg_0 * glob_0);\n}\n\nfloat fun_1(float arg_0) {\n  return (fun_0(arg_0) + 1.);\n}\n\nfloat fun_3(float arg_0) {\n  return (fun_0(arg_0) - 1.);\n}\n\nvoid main() {\n  float var_0_0 = (fun_1(1.) + fun_3(2.));\n}"
+    );
+  }
+
+  #[test]
+  fn source_locations() {
+    use crate::{lit, CanEscape as _, Scope, ShaderBuilder};
+
+    let mut lines = Vec::new();
+    let shader = ShaderBuilder::new_vertex_shader(|s, vertex| {
+      s.main_fun(|s: &mut Scope<()>| {
+        lines.push(line!() + 1);
+        let x = s.var(lit!(1.));
+        lines.push(line!() + 1);
+        s.when(x.gt(0.), |s| {
+          lines.push(line!() + 1);
+          s.set(vertex.position, lit!(1., 2., 3., 4.) * x.clone());
+        });
+      })
+    });
+
+    let code = write_shader_with_locations_to_str(&shader).unwrap();
+    let comments: Vec<_> = code
+      .lines()
+      .filter_map(|line| line.trim_start().strip_prefix("// "))
+      .collect();
+
+    assert_eq!(comments.len(), 3);
+    for (comment, line) in comments.iter().zip(&lines) {
+      assert!(comment.starts_with(&format!("{}:{}:", file!(), line)));
+    }
+
+    // locations are not part of the code
+    assert_eq!(
+      code
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<_>>()
+        .join("\n"),
+      write_shader_to_str(&shader).unwrap()
+    );
+  }
 }